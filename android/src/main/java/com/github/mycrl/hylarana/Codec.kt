@@ -115,7 +115,7 @@ class Video {
                                     StreamType.VIDEO.flag,
                                     bufferInfo.flags,
                                     bufferInfo.presentationTimeUs,
-                                    buffer.sliceArray(bufferInfo.offset until bufferInfo.size),
+                                    buffer.sliceArray(0 until bufferInfo.size),
                                 )
                             }
 
@@ -425,7 +425,7 @@ class Audio {
                                     StreamType.AUDIO.flag,
                                     bufferInfo.flags,
                                     bufferInfo.presentationTimeUs,
-                                    buffer.sliceArray(bufferInfo.offset until bufferInfo.size),
+                                    buffer.sliceArray(0 until bufferInfo.size),
                                 )
                             }
 
@@ -1,5 +1,6 @@
 package com.github.mycrl.hylarana
 
+import android.view.Surface
 import kotlinx.serialization.SerialName
 import kotlinx.serialization.Serializable
 import kotlinx.serialization.encodeToString
@@ -67,6 +68,15 @@ data class TransportOptions(
      * Flow control window size
      */
     val fc: Int,
+    /**
+     * Session token, the receiver uses the token of the sender description, and the sender
+     * only accepts the receivers with the same token.
+     */
+    val session: String? = null,
+    /**
+     * Stream name in the stream id of the connection, the srt relays route the streams by it.
+     */
+    val resource: String? = null,
 )
 
 @Serializable
@@ -84,6 +94,10 @@ data class MediaVideoStreamDescription(
     val size: Size,
     val fps: Int,
     @SerialName("bit_rate") val bitRate: Int,
+    /**
+     * VideoCodec, HEVC is 0
+     */
+    val codec: Int = 0,
 )
 
 @Serializable
@@ -97,11 +111,14 @@ data class MediaAudioStreamDescription(
 data class MediaStreamDescription(
     val video: MediaVideoStreamDescription?,
     val audio: MediaAudioStreamDescription?,
+    val token: String? = null,
 )
 
 class HylaranaSenderAdapter(
     private val getPortHandle: () -> Int,
     private val sendHandle: (Int, Int, Long, ByteArray) -> Boolean,
+    private val writeVideoFrameHandle: (Int, Long, ByteArray) -> Boolean,
+    private val writeAudioFrameHandle: (Int, Long, ByteArray) -> Boolean,
     private val releaseHandle: () -> Unit,
 ) {
     fun getPort(): Int {
@@ -119,6 +136,22 @@ class HylaranaSenderAdapter(
         return sendHandle(kind, flags, timestamp, bytes)
     }
 
+    /**
+     * Send an encoded video frame to sender, `flags` is the flags of the MediaCodec buffer info,
+     * the buffer with the codec config flag must be written before the other frames.
+     */
+    fun writeVideoFrame(flags: Int, timestamp: Long, bytes: ByteArray): Boolean {
+        return writeVideoFrameHandle(flags, timestamp, bytes)
+    }
+
+    /**
+     * Send an encoded audio frame to sender, `flags` is the flags of the MediaCodec buffer info,
+     * the buffer with the codec config flag must be written before the other frames.
+     */
+    fun writeAudioFrame(flags: Int, timestamp: Long, bytes: ByteArray): Boolean {
+        return writeAudioFrameHandle(flags, timestamp, bytes)
+    }
+
     /**
      * Close and release this sender.
      */
@@ -165,6 +198,22 @@ internal class Hylarana {
                     bytes
                 ) else false
             },
+            { flags, timestamp, bytes ->
+                if (sender != 0L) senderWriteVideoFrame(
+                    sender,
+                    flags,
+                    timestamp,
+                    bytes
+                ) else false
+            },
+            { flags, timestamp, bytes ->
+                if (sender != 0L) senderWriteAudioFrame(
+                    sender,
+                    flags,
+                    timestamp,
+                    bytes
+                ) else false
+            },
             {
                 if (sender != 0L) {
                     val ptr = sender
@@ -195,6 +244,39 @@ internal class Hylarana {
         }
     }
 
+    /**
+     * Create a receiver that decodes the video stream on the native side and renders it directly
+     * to the surface, only the audio stream is passed to the observer.
+     */
+    fun createReceiverWithSurface(
+        addr: String,
+        options: TransportOptions,
+        surface: Surface,
+        video: MediaVideoStreamDescription,
+        observer: HylaranaReceiverAdapterObserver
+    ): HylaranaReceiverAdapter {
+        var receiver = receiverCreateWithSurface(
+            addr,
+            Json.encodeToString(options),
+            surface,
+            Json.encodeToString(video),
+            observer
+        )
+
+        if (receiver == 0L) {
+            throw Exception("failed to create transport receiver")
+        }
+
+        return HylaranaReceiverAdapter {
+            if (receiver != 0L) {
+                val ptr = receiver
+                receiver = 0L
+
+                receiverRelease(ptr)
+            }
+        }
+    }
+
     /**
      * Creates the sender, the return value indicates whether the creation
      * was successful or not.
@@ -229,6 +311,26 @@ internal class Hylarana {
         bytes: ByteArray,
     ): Boolean
 
+    /**
+     * Sends the encoded video frame to the sender instance.
+     */
+    private external fun senderWriteVideoFrame(
+        sender: Long,
+        flags: Int,
+        timestamp: Long,
+        bytes: ByteArray,
+    ): Boolean
+
+    /**
+     * Sends the encoded audio frame to the sender instance.
+     */
+    private external fun senderWriteAudioFrame(
+        sender: Long,
+        flags: Int,
+        timestamp: Long,
+        bytes: ByteArray,
+    ): Boolean
+
     /**
      * release transport sender.
      */
@@ -244,6 +346,18 @@ internal class Hylarana {
         observer: HylaranaReceiverAdapterObserver,
     ): Long
 
+    /**
+     * Creates the receiver that renders the video stream to the surface, the return value
+     * indicates whether the creation was successful or not.
+     */
+    private external fun receiverCreateWithSurface(
+        addr: String,
+        options: String,
+        surface: Surface,
+        description: String,
+        observer: HylaranaReceiverAdapterObserver,
+    ): Long
+
     /**
      * release transport receiver.
      */
@@ -16,6 +16,12 @@ data class HylaranaReceiverConfigure(
     val addr: String,
     val options: TransportOptions,
     val description: MediaStreamDescription,
+    /**
+     * Decode the video stream on the native side and render it directly to the surface of the
+     * observer, the video packets are not copied to the java side, so the observer does not
+     * receive them in `sink`.
+     */
+    val nativeRendering: Boolean = false,
 )
 
 abstract class HylaranaReceiverObserver {
@@ -95,82 +101,99 @@ class HylaranaService {
             configure: HylaranaReceiverConfigure,
             observer: HylaranaReceiverObserver
         ): HylaranaReceiver {
-            return HylaranaReceiver(
-                hylarana.createReceiver(
-                    configure.addr,
-                    configure.options,
-                    object : HylaranaReceiverAdapterObserver() {
-                        private var isReleased = false
-                        private val audioDecoder = observer.track?.let { Audio.AudioDecoder(it) }
-                        private val videoDecoder = Video.VideoDecoder(
-                            observer.surface,
-                            configure.description.video?.size?.width ?: 2560,
-                            configure.description.video?.size?.height ?: 1440
-                        )
+            val size = Size(
+                width = configure.description.video?.size?.width ?: 2560,
+                height = configure.description.video?.size?.height ?: 1440,
+            )
+
+            val adapter = object : HylaranaReceiverAdapterObserver() {
+                private var isReleased = false
+                private val audioDecoder = observer.track?.let { Audio.AudioDecoder(it) }
+                private val videoDecoder = if (configure.nativeRendering) {
+                    null
+                } else {
+                    Video.VideoDecoder(observer.surface, size.width, size.height)
+                }
 
-                        init {
-                            videoDecoder.start()
-                            audioDecoder?.start()
+                init {
+                    videoDecoder?.start()
+                    audioDecoder?.start()
+                }
+
+                override fun sink(
+                    kind: Int,
+                    flags: Int,
+                    timestamp: Long,
+                    bytes: ByteArray
+                ): Boolean {
+                    try {
+                        if (isReleased) {
+                            return false
                         }
 
-                        override fun sink(
-                            kind: Int,
-                            flags: Int,
-                            timestamp: Long,
-                            bytes: ByteArray
-                        ): Boolean {
-                            try {
-                                if (isReleased) {
-                                    return false
+                        when (kind) {
+                            StreamType.VIDEO.flag -> {
+                                if (videoDecoder != null && videoDecoder.isRunning) {
+                                    videoDecoder.sink(bytes, flags, timestamp)
+                                    observer.sink(bytes, StreamType.VIDEO)
                                 }
+                            }
 
-                                when (kind) {
-                                    StreamType.VIDEO.flag -> {
-                                        if (videoDecoder.isRunning) {
-                                            videoDecoder.sink(bytes, flags, timestamp)
-                                            observer.sink(bytes, StreamType.VIDEO)
-                                        }
-                                    }
-
-                                    StreamType.AUDIO.flag -> {
-                                        if (audioDecoder != null && audioDecoder.isRunning) {
-                                            audioDecoder.sink(bytes, flags, timestamp)
-                                            observer.sink(bytes, StreamType.AUDIO)
-                                        }
-                                    }
+                            StreamType.AUDIO.flag -> {
+                                if (audioDecoder != null && audioDecoder.isRunning) {
+                                    audioDecoder.sink(bytes, flags, timestamp)
+                                    observer.sink(bytes, StreamType.AUDIO)
                                 }
-
-                                return true
-                            } catch (e: Exception) {
-                                Log.e(
-                                    "com.github.mycrl.hylarana",
-                                    "Hylarana ReceiverAdapter sink exception",
-                                    e
-                                )
-
-                                return false
                             }
                         }
 
-                        override fun close() {
-                            try {
-                                if (!isReleased) {
-                                    isReleased = true
+                        return true
+                    } catch (e: Exception) {
+                        Log.e(
+                            "com.github.mycrl.hylarana",
+                            "Hylarana ReceiverAdapter sink exception",
+                            e
+                        )
+
+                        return false
+                    }
+                }
 
-                                    audioDecoder?.release()
-                                    videoDecoder.release()
-                                    observer.close()
-                                }
-                            } catch (e: Exception) {
-                                Log.e(
-                                    "com.github.mycrl.hylarana",
-                                    "Hylarana ReceiverAdapter close exception",
-                                    e
-                                )
-                            }
+                override fun close() {
+                    try {
+                        if (!isReleased) {
+                            isReleased = true
+
+                            audioDecoder?.release()
+                            videoDecoder?.release()
+                            observer.close()
                         }
+                    } catch (e: Exception) {
+                        Log.e(
+                            "com.github.mycrl.hylarana",
+                            "Hylarana ReceiverAdapter close exception",
+                            e
+                        )
                     }
-                )
+                }
+            }
+
+            val options = configure.description.token?.let { configure.options.copy(session = it) }
+                ?: configure.options
+
+            return HylaranaReceiver(
+                if (configure.nativeRendering) {
+                    hylarana.createReceiverWithSurface(
+                        configure.addr,
+                        options,
+                        observer.surface,
+                        configure.description.video
+                            ?: throw Exception("the stream has no video to render"),
+                        adapter
+                    )
+                } else {
+                    hylarana.createReceiver(configure.addr, options, adapter)
+                }
             )
         }
     }
@@ -205,7 +228,7 @@ class HylaranaSender(
                 buf: ByteArray
             ) {
                 if (!isClosed) {
-                    if (!sender.send(kind, flags, timestamp, buf)) {
+                    if (!sender.writeVideoFrame(flags, timestamp, buf)) {
                         isClosed = true
                         observer.close()
                     }
@@ -222,7 +245,7 @@ class HylaranaSender(
                 buf: ByteArray
             ) {
                 if (!isClosed) {
-                    if (!sender.send(kind, flags, timestamp, buf)) {
+                    if (!sender.writeAudioFrame(flags, timestamp, buf)) {
                         isClosed = true
                         observer.close()
                     }
@@ -261,6 +284,7 @@ class HylaranaSender(
                 channels = audio.channels,
                 bitRate = audio.bitRate
             ),
+            configure.options.session,
         )
     }
 
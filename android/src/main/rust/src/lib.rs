@@ -1,8 +1,9 @@
 mod discovery;
 mod receiver;
 mod sender;
+mod surface;
 
-use std::{cell::RefCell, ffi::c_void, ptr::null_mut, sync::Arc};
+use std::{cell::RefCell, ffi::c_void, net::SocketAddr, ptr::null_mut, sync::Arc};
 
 use anyhow::Result;
 use common::{logger, runtime::get_runtime_handle};
@@ -213,6 +214,44 @@ extern "system" fn sender_write(
     .unwrap_or(false)
 }
 
+/// Sends an encoded video frame to the sender instance, the flags are the
+/// flags of the buffer info of MediaCodec.
+#[unsafe(export_name = "Java_com_github_mycrl_hylarana_Hylarana_senderWriteVideoFrame")]
+extern "system" fn sender_write_video_frame(
+    mut env: JNIEnv,
+    _this: JClass,
+    sender: *const Sender,
+    flags: i32,
+    timestamp: i64,
+    buf: JByteArray,
+) -> bool {
+    assert!(!sender.is_null());
+
+    ok_or_check(&mut env, |env| {
+        unsafe { &*sender }.write_video_frame(env, flags, timestamp, buf)
+    })
+    .unwrap_or(false)
+}
+
+/// Sends an encoded audio frame to the sender instance, the flags are the
+/// flags of the buffer info of MediaCodec.
+#[unsafe(export_name = "Java_com_github_mycrl_hylarana_Hylarana_senderWriteAudioFrame")]
+extern "system" fn sender_write_audio_frame(
+    mut env: JNIEnv,
+    _this: JClass,
+    sender: *const Sender,
+    flags: i32,
+    timestamp: i64,
+    buf: JByteArray,
+) -> bool {
+    assert!(!sender.is_null());
+
+    ok_or_check(&mut env, |env| {
+        unsafe { &*sender }.write_audio_frame(env, flags, timestamp, buf)
+    })
+    .unwrap_or(false)
+}
+
 /// release transport sender.
 #[unsafe(export_name = "Java_com_github_mycrl_hylarana_Hylarana_senderRelease")]
 extern "system" fn sender_release(_env: JNIEnv, _this: JClass, sender: *mut Sender) {
@@ -239,6 +278,34 @@ extern "system" fn receiver_create(
     .unwrap_or_else(|| null_mut())
 }
 
+/// Creates the receiver that decodes and renders the video stream into the
+/// surface, the audio stream is still passed to the observer.
+#[unsafe(export_name = "Java_com_github_mycrl_hylarana_Hylarana_receiverCreateWithSurface")]
+#[allow(clippy::too_many_arguments)]
+extern "system" fn receiver_create_with_surface(
+    mut env: JNIEnv,
+    _this: JClass,
+    addr: JString,
+    options: JString,
+    surface: JObject,
+    description: JString,
+    observer: JObject,
+) -> *const Arc<Receiver> {
+    ok_or_check(&mut env, |env| {
+        let receiver = Arc::new(Receiver::with_surface(
+            env,
+            &addr,
+            &options,
+            &surface,
+            &description,
+            &observer,
+        )?);
+
+        Ok(Box::into_raw(Box::new(receiver)))
+    })
+    .unwrap_or_else(|| null_mut())
+}
+
 /// release transport receiver.
 #[unsafe(export_name = "Java_com_github_mycrl_hylarana_Hylarana_receiverRelease")]
 extern "system" fn receiver_release(_env: JNIEnv, _this: JClass, receiver: *mut Arc<Receiver>) {
@@ -263,7 +330,7 @@ extern "system" fn discovery_create(
         let observer = DiscoveryServiceObserver(env.new_global_ref(observer)?);
 
         Ok(Box::into_raw(Box::new(get_runtime_handle().block_on(
-            DiscoveryService::new(bind.parse()?, observer),
+            DiscoveryService::new(bind.parse::<SocketAddr>()?, observer),
         )?)))
     })
     .unwrap_or_else(|| null_mut())
@@ -1,49 +1,126 @@
 use anyhow::Result;
 use bytes::Bytes;
-use transport::{Buffer, TransportReceiver, TransportReceiverSink};
+use transport::{
+    Buffer, BufferType, CloseReason, StreamType, TransportReceiver, TransportReceiverSink,
+};
 
 use jni::{
     JNIEnv,
     objects::{GlobalRef, JObject, JString, JValue, JValueGen},
 };
 
-use super::get_current_env;
+use super::{get_current_env, surface::SurfaceDecoder};
+
+fn call_observer_sink(observer: &GlobalRef, buffer: &Buffer<Bytes>) -> bool {
+    let mut env = get_current_env();
+    let bytes = if let Ok(it) = env.byte_array_from_slice(&buffer.data) {
+        it.into()
+    } else {
+        return false;
+    };
+
+    let ret = env.call_method(
+        observer.as_obj(),
+        "sink",
+        "(IIJ[B)Z",
+        &[
+            JValue::Int(buffer.stream as i32),
+            JValue::Int(buffer.ty as i32),
+            JValue::Long(buffer.timestamp as i64),
+            JValue::Object(&bytes),
+        ],
+    );
+
+    let _ = env.delete_local_ref(bytes);
+    if let Ok(JValueGen::Bool(size)) = ret {
+        size > 0
+    } else {
+        false
+    }
+}
+
+fn call_observer_close(observer: &GlobalRef) {
+    let mut env = get_current_env();
+
+    let _ = env.call_method(observer.as_obj(), "close", "()V", &[]);
+}
+
+/// Keeps the notices of the stream away from the decoders, their types are
+/// flags of the java codec, the silence is the end of stream flag.
+///
+/// When the video track is enabled again, the packets before the next key
+/// frame reference the frames from before the track was disabled, so they are
+/// dropped.
+#[derive(Default)]
+struct KeyFrameGate {
+    waiting: bool,
+}
+
+impl KeyFrameGate {
+    /// Returns whether the buffer is passed to the decoder.
+    fn pass(&mut self, buffer: &Buffer<Bytes>) -> bool {
+        match buffer.ty {
+            BufferType::Silence | BufferType::TrackDisabled => false,
+            BufferType::TrackEnabled => {
+                if buffer.stream == StreamType::Video {
+                    self.waiting = true;
+                }
+
+                false
+            }
+            BufferType::Config | BufferType::KeyFrame if buffer.stream == StreamType::Video => {
+                self.waiting = false;
 
-struct ReceiverSink(GlobalRef);
+                true
+            }
+            _ => !(self.waiting && buffer.stream == StreamType::Video),
+        }
+    }
+}
+
+struct ReceiverSink {
+    observer: GlobalRef,
+    gate: KeyFrameGate,
+}
 
 impl TransportReceiverSink for ReceiverSink {
     fn sink(&mut self, buffer: Buffer<Bytes>) -> bool {
-        let mut env = get_current_env();
-        let bytes = if let Ok(it) = env.byte_array_from_slice(&buffer.data) {
-            it.into()
-        } else {
-            return false;
-        };
-
-        let ret = env.call_method(
-            self.0.as_obj(),
-            "sink",
-            "(IIJ[B)Z",
-            &[
-                JValue::Int(buffer.stream as i32),
-                JValue::Int(buffer.ty as i32),
-                JValue::Long(buffer.timestamp as i64),
-                JValue::Object(&bytes),
-            ],
-        );
-
-        let _ = env.delete_local_ref(bytes);
-        if let Ok(JValueGen::Bool(size)) = ret {
-            size > 0
-        } else {
-            false
+        if !self.gate.pass(&buffer) {
+            return true;
         }
+
+        call_observer_sink(&self.observer, &buffer)
     }
 
-    fn close(&mut self) {
-        let mut env = get_current_env();
+    fn close(&mut self, _: CloseReason) {
+        call_observer_close(&self.observer);
+    }
+}
 
-        let _ = env.call_method(self.0.as_obj(), "close", "()V", &[]);
+/// The video stream is decoded and rendered to the surface on the native side,
+/// only the audio stream is passed to the java observer.
+struct SurfaceReceiverSink {
+    observer: GlobalRef,
+    decoder: SurfaceDecoder,
+    gate: KeyFrameGate,
+}
+
+impl TransportReceiverSink for SurfaceReceiverSink {
+    fn sink(&mut self, buffer: Buffer<Bytes>) -> bool {
+        if !self.gate.pass(&buffer) {
+            return true;
+        }
+
+        match buffer.stream {
+            StreamType::Video => self
+                .decoder
+                .decode(buffer.ty, buffer.timestamp, &buffer.data),
+            StreamType::Audio => call_observer_sink(&self.observer, &buffer),
+        }
+    }
+
+    fn close(&mut self, _: CloseReason) {
+        call_observer_close(&self.observer);
     }
 }
 
@@ -63,7 +140,35 @@ impl Receiver {
         Ok(Self(TransportReceiver::new(
             addr.parse()?,
             serde_json::from_str(&options)?,
-            ReceiverSink(env.new_global_ref(observer)?),
+            ReceiverSink {
+                observer: env.new_global_ref(observer)?,
+                gate: KeyFrameGate::default(),
+            },
+        )?))
+    }
+
+    /// Create a receiver that decodes and renders the video stream into the
+    /// surface, the description is the video description of the sender.
+    pub fn with_surface(
+        env: &mut JNIEnv,
+        addr: &JString,
+        options: &JString,
+        surface: &JObject,
+        description: &JString,
+        observer: &JObject,
+    ) -> Result<Self> {
+        let addr: String = env.get_string(addr)?.into();
+        let options: String = env.get_string(options)?.into();
+        let description: String = env.get_string(description)?.into();
+
+        Ok(Self(TransportReceiver::new(
+            addr.parse()?,
+            serde_json::from_str(&options)?,
+            SurfaceReceiverSink {
+                decoder: SurfaceDecoder::new(env, surface, &serde_json::from_str(&description)?)?,
+                observer: env.new_global_ref(observer)?,
+                gate: KeyFrameGate::default(),
+            },
         )?))
     }
 }
@@ -1,4 +1,7 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use anyhow::Result;
+use bytes::BytesMut;
 use transport::{Buffer, BufferType, StreamType, TransportSender};
 
 use jni::{
@@ -6,17 +9,46 @@ use jni::{
     objects::{JByteArray, JString},
 };
 
-pub struct Sender(TransportSender);
+/// The flags of the buffer info of MediaCodec.
+///
+/// [MediaCodec.BufferInfo](https://developer.android.com/reference/android/media/MediaCodec.BufferInfo#flags)
+struct MediaCodecFlags(i32);
+
+impl MediaCodecFlags {
+    const BUFFER_FLAG_KEY_FRAME: i32 = 1;
+    const BUFFER_FLAG_CODEC_CONFIG: i32 = 2;
+
+    // The flags are a bit mask, a key frame can also carry the end of stream flag,
+    // so the flags cannot be converted to the buffer type directly.
+    fn buffer_type(&self) -> BufferType {
+        if self.0 & Self::BUFFER_FLAG_CODEC_CONFIG != 0 {
+            BufferType::Config
+        } else if self.0 & Self::BUFFER_FLAG_KEY_FRAME != 0 {
+            BufferType::KeyFrame
+        } else {
+            BufferType::Partial
+        }
+    }
+}
+
+pub struct Sender {
+    transport: TransportSender,
+    // Whether the codec configuration of the video and audio has been written,
+    // the receiver cannot create the decoder without it.
+    video_configured: AtomicBool,
+    audio_configured: AtomicBool,
+}
 
 impl Sender {
     pub fn new(env: &mut JNIEnv, bind: &JString, options: &JString) -> Result<Self> {
         let bind: String = env.get_string(bind)?.into();
         let options: String = env.get_string(options)?.into();
 
-        Ok(Self(TransportSender::new(
-            bind.parse()?,
-            serde_json::from_str(&options)?,
-        )?))
+        Ok(Self {
+            transport: TransportSender::new(bind.parse()?, serde_json::from_str(&options)?)?,
+            video_configured: AtomicBool::new(false),
+            audio_configured: AtomicBool::new(false),
+        })
     }
 
     pub fn sink(
@@ -28,31 +60,116 @@ impl Sender {
         array: JByteArray,
     ) -> Result<bool> {
         Ok(self
-            .0
+            .transport
             .send(Buffer {
-                data: {
-                    let size = env.get_array_length(&array)? as usize;
-                    let mut bytes = Buffer::<()>::with_capacity(size);
-                    let start = bytes.len() - size;
-
-                    env.get_byte_array_region(array, 0, unsafe {
-                        std::mem::transmute::<&mut [u8], &mut [i8]>(&mut bytes[start..])
-                    })?;
-
-                    bytes
-                },
+                data: Self::read_array(env, array)?,
                 stream: StreamType::try_from(ty as u8)?,
                 ty: BufferType::try_from(flags as u8)?,
                 timestamp: timestamp as u64,
+                rendition: 0,
+            })
+            .is_ok())
+    }
+
+    /// Write an encoded video frame, the flags are the flags of the buffer info
+    /// of MediaCodec, the buffer with the codec config flag carries the
+    /// parameter sets of the encoder.
+    pub fn write_video_frame(
+        &self,
+        env: &mut JNIEnv,
+        flags: i32,
+        timestamp: i64,
+        array: JByteArray,
+    ) -> Result<bool> {
+        self.write(
+            env,
+            StreamType::Video,
+            &self.video_configured,
+            MediaCodecFlags(flags),
+            timestamp,
+            array,
+        )
+    }
+
+    /// Write an encoded audio frame, the flags are the flags of the buffer info
+    /// of MediaCodec, the buffer with the codec config flag carries the
+    /// identification header of the encoder.
+    pub fn write_audio_frame(
+        &self,
+        env: &mut JNIEnv,
+        flags: i32,
+        timestamp: i64,
+        array: JByteArray,
+    ) -> Result<bool> {
+        self.write(
+            env,
+            StreamType::Audio,
+            &self.audio_configured,
+            MediaCodecFlags(flags),
+            timestamp,
+            array,
+        )
+    }
+
+    fn write(
+        &self,
+        env: &mut JNIEnv,
+        stream: StreamType,
+        configured: &AtomicBool,
+        flags: MediaCodecFlags,
+        timestamp: i64,
+        array: JByteArray,
+    ) -> Result<bool> {
+        // The last buffer of the encoder is usually empty and only carries the end of
+        // stream flag, there is nothing to send.
+        if env.get_array_length(&array)? == 0 {
+            return Ok(true);
+        }
+
+        let ty = flags.buffer_type();
+        if ty == BufferType::Config {
+            configured.store(true, Ordering::Relaxed);
+        } else if !configured.load(Ordering::Relaxed) {
+            // The encoder always outputs the codec config first, the frames before it
+            // cannot be decoded by the receiver, drop them instead of closing the
+            // sender.
+            log::warn!(
+                "sender drop the frame before the codec config, stream={:?}",
+                stream
+            );
+
+            return Ok(true);
+        }
+
+        Ok(self
+            .transport
+            .send(Buffer {
+                data: Self::read_array(env, array)?,
+                timestamp: timestamp as u64,
+                rendition: 0,
+                stream,
+                ty,
             })
             .is_ok())
     }
 
+    fn read_array(env: &mut JNIEnv, array: JByteArray) -> Result<BytesMut> {
+        let size = env.get_array_length(&array)? as usize;
+        let mut bytes = Buffer::<()>::with_capacity(size);
+        let start = bytes.len() - size;
+
+        env.get_byte_array_region(array, 0, unsafe {
+            std::mem::transmute::<&mut [u8], &mut [i8]>(&mut bytes[start..])
+        })?;
+
+        Ok(bytes)
+    }
+
     pub fn get_pkt_lose_rate(&self) -> f64 {
-        self.0.get_pkt_lose_rate()
+        self.transport.get_pkt_lose_rate()
     }
 
     pub fn get_port(&self) -> u16 {
-        self.0.local_addr().port()
+        self.transport.local_addr().port()
     }
 }
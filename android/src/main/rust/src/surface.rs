@@ -0,0 +1,305 @@
+use std::{
+    ffi::{CStr, c_char, c_void},
+    ptr::null_mut,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{Result, anyhow};
+use common::{MediaVideoStreamDescription, codec::VideoCodec};
+use jni::{JNIEnv, objects::JObject};
+use transport::BufferType;
+
+#[allow(non_camel_case_types)]
+type media_status_t = i32;
+
+const AMEDIA_OK: media_status_t = 0;
+const AMEDIACODEC_BUFFER_FLAG_KEY_FRAME: u32 = 1;
+const AMEDIACODEC_BUFFER_FLAG_CODEC_CONFIG: u32 = 2;
+const AMEDIACODEC_BUFFER_FLAG_END_OF_STREAM: u32 = 4;
+
+/// The timeout of waiting for the buffers of the codec in microseconds, the
+/// waiting is limited so that the threads can notice that the decoder is
+/// released.
+const TIMEOUT_US: i64 = 100_000;
+
+#[repr(C)]
+#[derive(Default)]
+struct AMediaCodecBufferInfo {
+    offset: i32,
+    size: i32,
+    presentation_time_us: i64,
+    flags: u32,
+}
+
+#[link(name = "mediandk")]
+unsafe extern "C" {
+    fn AMediaFormat_new() -> *mut c_void;
+    fn AMediaFormat_delete(format: *mut c_void) -> media_status_t;
+    fn AMediaFormat_setString(format: *mut c_void, name: *const c_char, value: *const c_char);
+    fn AMediaFormat_setInt32(format: *mut c_void, name: *const c_char, value: i32);
+    fn AMediaCodec_createDecoderByType(mime: *const c_char) -> *mut c_void;
+    fn AMediaCodec_getName(codec: *mut c_void, name: *mut *mut c_char) -> media_status_t;
+    fn AMediaCodec_releaseName(codec: *mut c_void, name: *mut c_char);
+    fn AMediaCodec_configure(
+        codec: *mut c_void,
+        format: *const c_void,
+        window: *mut c_void,
+        crypto: *mut c_void,
+        flags: u32,
+    ) -> media_status_t;
+    fn AMediaCodec_start(codec: *mut c_void) -> media_status_t;
+    fn AMediaCodec_stop(codec: *mut c_void) -> media_status_t;
+    fn AMediaCodec_delete(codec: *mut c_void) -> media_status_t;
+    fn AMediaCodec_dequeueInputBuffer(codec: *mut c_void, timeout_us: i64) -> isize;
+    fn AMediaCodec_getInputBuffer(codec: *mut c_void, idx: usize, out_size: *mut usize) -> *mut u8;
+    fn AMediaCodec_queueInputBuffer(
+        codec: *mut c_void,
+        idx: usize,
+        offset: i64,
+        size: usize,
+        time: u64,
+        flags: u32,
+    ) -> media_status_t;
+    fn AMediaCodec_dequeueOutputBuffer(
+        codec: *mut c_void,
+        info: *mut AMediaCodecBufferInfo,
+        timeout_us: i64,
+    ) -> isize;
+    fn AMediaCodec_releaseOutputBuffer(
+        codec: *mut c_void,
+        idx: usize,
+        render: bool,
+    ) -> media_status_t;
+}
+
+#[link(name = "android")]
+unsafe extern "C" {
+    fn ANativeWindow_fromSurface(
+        env: *mut jni::sys::JNIEnv,
+        surface: jni::sys::jobject,
+    ) -> *mut c_void;
+    fn ANativeWindow_release(window: *mut c_void);
+}
+
+struct MediaCodec(*mut c_void);
+
+unsafe impl Send for MediaCodec {}
+unsafe impl Sync for MediaCodec {}
+
+/// Hardware video decoder that renders the decoded frames directly into the
+/// window of the android surface.
+///
+/// The decoded frames never leave the codec, the output buffers are only
+/// released to the surface, so there is no copy of the frames between the
+/// native and the java side.
+pub struct SurfaceDecoder {
+    codec: Arc<MediaCodec>,
+    window: *mut c_void,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+unsafe impl Send for SurfaceDecoder {}
+
+impl SurfaceDecoder {
+    pub fn new(
+        env: &mut JNIEnv,
+        surface: &JObject,
+        description: &MediaVideoStreamDescription,
+    ) -> Result<Self> {
+        let window = unsafe { ANativeWindow_fromSurface(env.get_raw(), surface.as_raw()) };
+        if window.is_null() {
+            return Err(anyhow!("failed to get the native window from surface"));
+        }
+
+        match Self::create_codec(window, description) {
+            Ok(codec) => {
+                let codec = Arc::new(codec);
+                let running = Arc::new(AtomicBool::new(true));
+
+                let worker = {
+                    let codec = codec.clone();
+                    let running = running.clone();
+
+                    thread::Builder::new()
+                        .name("HylaranaSurfaceDecoderThread".to_string())
+                        .spawn(move || {
+                            let mut info = AMediaCodecBufferInfo::default();
+
+                            while running.load(Ordering::Relaxed) {
+                                let index = unsafe {
+                                    AMediaCodec_dequeueOutputBuffer(codec.0, &mut info, TIMEOUT_US)
+                                };
+
+                                // Negative indexes are the try again later, format changed and
+                                // buffers changed notifications, the surface handles the format
+                                // itself.
+                                if index >= 0 {
+                                    unsafe {
+                                        AMediaCodec_releaseOutputBuffer(
+                                            codec.0,
+                                            index as usize,
+                                            true,
+                                        );
+                                    }
+                                }
+                            }
+                        })?
+                };
+
+                Ok(Self {
+                    worker: Some(worker),
+                    running,
+                    window,
+                    codec,
+                })
+            }
+            Err(e) => {
+                unsafe {
+                    ANativeWindow_release(window);
+                }
+
+                Err(e)
+            }
+        }
+    }
+
+    fn mime(codec: VideoCodec) -> &'static CStr {
+        match codec {
+            VideoCodec::HEVC => c"video/hevc",
+        }
+    }
+
+    fn create_codec(
+        window: *mut c_void,
+        description: &MediaVideoStreamDescription,
+    ) -> Result<MediaCodec> {
+        let mime = Self::mime(description.codec);
+        let codec = unsafe { AMediaCodec_createDecoderByType(mime.as_ptr()) };
+        if codec.is_null() {
+            return Err(anyhow!(
+                "not found a decoder, codec={:?}",
+                description.codec
+            ));
+        }
+
+        let format = unsafe { AMediaFormat_new() };
+        unsafe {
+            AMediaFormat_setString(format, c"mime".as_ptr(), mime.as_ptr());
+            AMediaFormat_setInt32(format, c"width".as_ptr(), description.size.width as i32);
+            AMediaFormat_setInt32(format, c"height".as_ptr(), description.size.height as i32);
+        }
+
+        // Some rockchip and hisilicon decoders fail to configure with the low latency
+        // mode, keep the same exceptions as the java decoder.
+        let mut name = null_mut();
+        if unsafe { AMediaCodec_getName(codec, &mut name) } == AMEDIA_OK {
+            let low_latency = {
+                let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+
+                !name.contains(".rk.") && !name.contains(".hisi.")
+            };
+
+            unsafe {
+                AMediaCodec_releaseName(codec, name);
+            }
+
+            if low_latency {
+                unsafe {
+                    AMediaFormat_setInt32(format, c"low-latency".as_ptr(), 1);
+                }
+            }
+        }
+
+        let status = unsafe { AMediaCodec_configure(codec, format, window, null_mut(), 0) };
+        unsafe {
+            AMediaFormat_delete(format);
+        }
+
+        if status != AMEDIA_OK || unsafe { AMediaCodec_start(codec) } != AMEDIA_OK {
+            unsafe {
+                AMediaCodec_delete(codec);
+            }
+
+            return Err(anyhow!("failed to start the decoder, status={}", status));
+        }
+
+        Ok(MediaCodec(codec))
+    }
+
+    /// Push the packet of the video stream into the decoder, the return value
+    /// indicates whether the decoder is still working.
+    pub fn decode(&self, ty: BufferType, timestamp: u64, bytes: &[u8]) -> bool {
+        let index = unsafe { AMediaCodec_dequeueInputBuffer(self.codec.0, TIMEOUT_US) };
+        if index < 0 {
+            log::warn!("surface decoder has no free input buffer, drop the packet");
+
+            return true;
+        }
+
+        let mut capacity = 0;
+        let buffer =
+            unsafe { AMediaCodec_getInputBuffer(self.codec.0, index as usize, &mut capacity) };
+
+        if buffer.is_null() || capacity < bytes.len() {
+            log::error!(
+                "surface decoder input buffer is too small, capacity={}, size={}",
+                capacity,
+                bytes.len()
+            );
+
+            return false;
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer, bytes.len());
+        }
+
+        let flags = match ty {
+            BufferType::Config => AMEDIACODEC_BUFFER_FLAG_CODEC_CONFIG,
+            BufferType::KeyFrame => AMEDIACODEC_BUFFER_FLAG_KEY_FRAME,
+            BufferType::EndOfStream => AMEDIACODEC_BUFFER_FLAG_END_OF_STREAM,
+            // The silence, the track notices and the control messages never reach the
+            // decoder.
+            BufferType::Partial
+            | BufferType::Silence
+            | BufferType::TrackDisabled
+            | BufferType::TrackEnabled
+            | BufferType::Clock
+            | BufferType::Heartbeat => 0,
+        };
+
+        let status = unsafe {
+            AMediaCodec_queueInputBuffer(
+                self.codec.0,
+                index as usize,
+                0,
+                bytes.len(),
+                timestamp,
+                flags,
+            )
+        };
+
+        status == AMEDIA_OK
+    }
+}
+
+impl Drop for SurfaceDecoder {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+
+        unsafe {
+            AMediaCodec_stop(self.codec.0);
+            AMediaCodec_delete(self.codec.0);
+            ANativeWindow_release(self.window);
+        }
+    }
+}
@@ -2,10 +2,10 @@ use std::{net::SocketAddr, sync::Arc};
 
 use anyhow::{Result, anyhow};
 use hylarana::{
-    AVFrameStreamPlayer, AVFrameStreamPlayerOptions, Capture, HylaranaReceiver,
-    HylaranaReceiverOptions, HylaranaSender, HylaranaSenderOptions, MediaStreamDescription,
-    MediaStreamObserver, Size, Source, SourceType, VideoRenderOptionsBuilder,
-    VideoRenderSurfaceOptions, shutdown, startup,
+    AVFrameStreamPlayer, AVFrameStreamPlayerOptions, AudioRenderOptions, Capture, CloseReason,
+    HylaranaReceiver, HylaranaReceiverOptions, HylaranaSender, HylaranaSenderOptions,
+    MediaStreamDescription, MediaStreamObserver, Size, Source, SourceType,
+    VideoRenderOptionsBuilder, VideoRenderSurfaceOptions, shutdown, startup,
 };
 
 use parking_lot::Mutex;
@@ -114,6 +114,7 @@ impl CoreService {
             })
             .from_receiver(&description, &options)
             .build(),
+            AudioRenderOptions::default(),
         ))?;
 
         let callback = Arc::new(callback);
@@ -161,7 +162,7 @@ struct StreamObserver {
 }
 
 impl MediaStreamObserver for StreamObserver {
-    fn close(&self) {
+    fn close(&self, _: CloseReason) {
         *self.status.lock() = Status::Idle;
         (self.callback)();
     }
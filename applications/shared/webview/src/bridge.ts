@@ -26,6 +26,21 @@ export enum VideoEncoder {
      * hardware encoders and decoders.
      */
     VideoToolBox = "VideoToolBox",
+    /**
+     * [VA-API](https://www.intel.com/content/www/us/en/developer/articles/technical/linuxmedia-vaapi.html)
+     *
+     * Video Acceleration API is an open source library and api specification,
+     * which provides access to graphics hardware acceleration capabilities for
+     * video processing on Linux.
+     */
+    Vaapi = "Vaapi",
+    /**
+     * [NVENC](https://developer.nvidia.com/video-codec-sdk)
+     *
+     * NVENC is the dedicated hardware video encoder of NVIDIA GPUs, which
+     * offloads the encoding from the CPU.
+     */
+    Nvenc = "Nvenc",
 }
 
 /**
@@ -58,6 +73,21 @@ export enum VideoDecoder {
      * hardware encoders and decoders.
      */
     VideoToolBox = "VideoToolBox",
+    /**
+     * [VA-API](https://www.intel.com/content/www/us/en/developer/articles/technical/linuxmedia-vaapi.html)
+     *
+     * Video Acceleration API is an open source library and api specification,
+     * which provides access to graphics hardware acceleration capabilities for
+     * video processing on Linux.
+     */
+    Vaapi = "Vaapi",
+    /**
+     * [NVDEC](https://developer.nvidia.com/video-codec-sdk)
+     *
+     * NVDEC is the dedicated hardware video decoder of NVIDIA GPUs, accessed
+     * through CUDA.
+     */
+    Nvdec = "Nvdec",
 }
 
 /**
@@ -143,6 +173,8 @@ export interface Transport {
     timeout: number;
     fec: string;
     fc: number;
+    session?: string;
+    resource?: string;
 }
 
 /**
@@ -192,6 +224,7 @@ export interface MediaStreamDescription {
         channels: number;
         bit_rate: number;
     };
+    token?: string;
 }
 
 export interface SystemSettings {
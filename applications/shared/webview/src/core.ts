@@ -4,6 +4,8 @@ export const VideoEncoders = {
     X264: "X264",
     Qsv: "Intel QSV - Windows",
     VideoToolBox: "VideoToolbox - Apple",
+    Vaapi: "VA-API - Linux",
+    Nvenc: "NVIDIA NVENC - Windows/Linux",
 };
 
 export const VideoDecoders = {
@@ -11,6 +13,8 @@ export const VideoDecoders = {
     D3D11: "D3D11VA - Windows",
     Qsv: "Intel QSV - Windows",
     VideoToolBox: "VideoToolbox - Apple",
+    Vaapi: "VA-API - Linux",
+    Nvdec: "NVIDIA NVDEC - Windows/Linux",
 };
 
 export function onDevicesChange(listener: () => void) {
@@ -0,0 +1,15 @@
+use std::{env, path::Path};
+
+fn main() {
+    let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    cbindgen::Builder::new()
+        .with_crate(&dir)
+        .with_config(cbindgen::Config::from_file(Path::new(&dir).join("cbindgen.toml")).unwrap())
+        .generate()
+        .expect("failed to generate the c header")
+        .write_to_file(Path::new(&dir).join("include/hylarana.h"));
+}
@@ -0,0 +1,363 @@
+mod stream;
+
+use std::{
+    cell::Cell,
+    ffi::{CStr, CString, c_char},
+    net::SocketAddr,
+    ptr::null_mut,
+    slice::from_raw_parts,
+};
+
+use anyhow::{Result, anyhow};
+use common::logger;
+use hylarana::{
+    Capture, DiscoveryService, HylaranaError, HylaranaReceiver, HylaranaSender, SourceType,
+    get_runtime_handle,
+};
+
+use discovery::ffi::{DiscoveryServiceObserver, HylaranaDiscoveryCallbacks};
+
+use self::stream::{HylaranaStreamObserver, HylaranaStreamSink};
+
+thread_local! {
+    static LAST_ERROR: Cell<HylaranaErrorKind> = const { Cell::new(HylaranaErrorKind::None) };
+}
+
+fn ok_or_check<F, T>(func: F) -> Option<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    let (ret, kind) = match func() {
+        Ok(ret) => (Some(ret), HylaranaErrorKind::None),
+        Err(e) => {
+            log::error!("{:?}", e);
+
+            (None, HylaranaErrorKind::from(&e))
+        }
+    };
+
+    LAST_ERROR.set(kind);
+    ret
+}
+
+fn get_str<'a>(ptr: *const c_char) -> Result<&'a str> {
+    if ptr.is_null() {
+        return Err(anyhow!("string is null"));
+    }
+
+    Ok(unsafe { CStr::from_ptr(ptr) }.to_str()?)
+}
+
+fn into_raw_string(value: String) -> Result<*mut c_char> {
+    Ok(CString::new(value)?.into_raw())
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum HylaranaLogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// The category of the error of the last failed call, see
+/// `hylarana_get_last_error`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HylaranaErrorKind {
+    None,
+    Transport,
+    Codec,
+    Capture,
+    Render,
+    /// The arguments are invalid or the error is not from the sdk.
+    Other,
+}
+
+impl From<&anyhow::Error> for HylaranaErrorKind {
+    fn from(value: &anyhow::Error) -> Self {
+        match value.downcast_ref::<HylaranaError>() {
+            Some(HylaranaError::TransportError(_)) => Self::Transport,
+            Some(HylaranaError::CodecError(_)) => Self::Codec,
+            Some(HylaranaError::CaptureError(_)) => Self::Capture,
+            Some(HylaranaError::RenderError(_)) => Self::Render,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum HylaranaSourceType {
+    Camera,
+    Screen,
+    Audio,
+}
+
+/// Initialize the logger, the logs are written to the standard output, and to
+/// the directory if the path is not null.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_init_logger(level: HylaranaLogLevel, path: *const c_char) -> bool {
+    let path = if path.is_null() {
+        None
+    } else if let Ok(it) = get_str(path) {
+        Some(it)
+    } else {
+        return false;
+    };
+
+    let level = match level {
+        HylaranaLogLevel::Error => log::LevelFilter::Error,
+        HylaranaLogLevel::Warn => log::LevelFilter::Warn,
+        HylaranaLogLevel::Info => log::LevelFilter::Info,
+        HylaranaLogLevel::Debug => log::LevelFilter::Debug,
+        HylaranaLogLevel::Trace => log::LevelFilter::Trace,
+    };
+
+    if let Err(e) = logger::init_logger(level, path) {
+        eprintln!("failed to init logger, err={:?}", e);
+
+        return false;
+    }
+
+    logger::enable_panic_logger();
+    true
+}
+
+/// Initialize the environment, this must be called once before any other
+/// function except the logger.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_startup() -> bool {
+    ok_or_check(|| Ok(hylarana::startup()?)).is_some()
+}
+
+/// Clean up the environment, no other function can be called after this.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_shutdown() {
+    let _ = ok_or_check(|| Ok(hylarana::shutdown()?));
+}
+
+/// Get the kind of the error of the last call on the current thread, the
+/// functions that return null or false on failure set it.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_get_last_error() -> HylaranaErrorKind {
+    LAST_ERROR.get()
+}
+
+/// Release the strings returned by the library.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_string_release(value: *mut c_char) {
+    assert!(!value.is_null());
+
+    drop(unsafe { CString::from_raw(value) });
+}
+
+/// Get the capture sources of the type as a json array, returns null if it
+/// fails, the string is released with `hylarana_string_release`.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_get_sources(kind: HylaranaSourceType) -> *mut c_char {
+    ok_or_check(|| {
+        let sources = Capture::get_sources(match kind {
+            HylaranaSourceType::Camera => SourceType::Camera,
+            HylaranaSourceType::Screen => SourceType::Screen,
+            HylaranaSourceType::Audio => SourceType::Audio,
+        })?;
+
+        into_raw_string(serde_json::to_string(&sources)?)
+    })
+    .unwrap_or_else(null_mut)
+}
+
+/// Creates the sender, the options are the json of `HylaranaSenderOptions`,
+/// returns null if the creation fails.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_create_sender(
+    bind: *const c_char,
+    options: *const c_char,
+    sink: HylaranaStreamSink,
+    observer: HylaranaStreamObserver,
+) -> *mut HylaranaSender {
+    ok_or_check(|| {
+        Ok(Box::into_raw(Box::new(hylarana::create_sender(
+            get_str(bind)?.parse()?,
+            &serde_json::from_str(get_str(options)?)?,
+            sink,
+            observer,
+        )?)))
+    })
+    .unwrap_or_else(null_mut)
+}
+
+/// Get the json of the `MediaStreamDescription` of the sender, the receivers
+/// need it, the string is released with `hylarana_string_release`.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_sender_get_description(sender: *const HylaranaSender) -> *mut c_char {
+    assert!(!sender.is_null());
+
+    ok_or_check(|| {
+        into_raw_string(serde_json::to_string(
+            unsafe { &*sender }.get_description(),
+        )?)
+    })
+    .unwrap_or_else(null_mut)
+}
+
+/// Get the port the sender is bound to.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_sender_get_port(sender: *const HylaranaSender) -> u16 {
+    assert!(!sender.is_null());
+
+    unsafe { &*sender }.local_addr().port()
+}
+
+/// release the sender.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_sender_release(sender: *mut HylaranaSender) {
+    assert!(!sender.is_null());
+
+    drop(unsafe { Box::from_raw(sender) });
+}
+
+/// Creates the receiver, the options are the json of
+/// `HylaranaReceiverOptions`, the description is the json from
+/// `hylarana_sender_get_description`, returns null if the creation fails.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_create_receiver(
+    addr: *const c_char,
+    options: *const c_char,
+    description: *const c_char,
+    sink: HylaranaStreamSink,
+    observer: HylaranaStreamObserver,
+) -> *mut HylaranaReceiver {
+    ok_or_check(|| {
+        Ok(Box::into_raw(Box::new(hylarana::create_receiver(
+            get_str(addr)?.parse()?,
+            &serde_json::from_str(get_str(options)?)?,
+            &serde_json::from_str(get_str(description)?)?,
+            sink,
+            observer,
+        )?)))
+    })
+    .unwrap_or_else(null_mut)
+}
+
+/// Set the volume of the received audio, 1.0 is the original volume.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_set_volume(receiver: *const HylaranaReceiver, volume: f32) {
+    assert!(!receiver.is_null());
+
+    unsafe { &*receiver }.set_volume(volume);
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_set_muted(receiver: *const HylaranaReceiver, muted: bool) {
+    assert!(!receiver.is_null());
+
+    unsafe { &*receiver }.set_muted(muted);
+}
+
+/// Switch the receiver to the stream of another sender, the description is
+/// the json from `hylarana_sender_get_description`, the current stream is
+/// kept if this fails.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_switch_stream(
+    receiver: *mut HylaranaReceiver,
+    addr: *const c_char,
+    description: *const c_char,
+) -> bool {
+    assert!(!receiver.is_null());
+
+    ok_or_check(|| {
+        Ok(unsafe { &mut *receiver }.switch_stream(
+            get_str(addr)?.parse()?,
+            &serde_json::from_str(get_str(description)?)?,
+        )?)
+    })
+    .is_some()
+}
+
+/// Pause the playback, the time shift of the receiver must be enabled.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_pause(receiver: *const HylaranaReceiver) -> bool {
+    assert!(!receiver.is_null());
+
+    ok_or_check(|| Ok(unsafe { &*receiver }.pause()?)).is_some()
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_resume(receiver: *const HylaranaReceiver) -> bool {
+    assert!(!receiver.is_null());
+
+    ok_or_check(|| Ok(unsafe { &*receiver }.resume()?)).is_some()
+}
+
+/// Rewind the playback by the seconds, the time shift of the receiver must
+/// be enabled.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_seek_back(receiver: *const HylaranaReceiver, secs: f32) -> bool {
+    assert!(!receiver.is_null());
+
+    ok_or_check(|| Ok(unsafe { &*receiver }.seek_back(secs)?)).is_some()
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_go_live(receiver: *const HylaranaReceiver) -> bool {
+    assert!(!receiver.is_null());
+
+    ok_or_check(|| Ok(unsafe { &*receiver }.go_live()?)).is_some()
+}
+
+/// release the receiver.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_release(receiver: *mut HylaranaReceiver) {
+    assert!(!receiver.is_null());
+
+    drop(unsafe { Box::from_raw(receiver) });
+}
+
+/// Register the discovery service, the devices in the local network are
+/// reported to the callbacks, returns null if the creation fails.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_discovery_create(
+    bind: *const c_char,
+    callbacks: HylaranaDiscoveryCallbacks,
+) -> *mut DiscoveryService {
+    ok_or_check(|| {
+        Ok(Box::into_raw(Box::new(get_runtime_handle().block_on(
+            DiscoveryService::new(
+                get_str(bind)?.parse::<SocketAddr>()?,
+                DiscoveryServiceObserver(callbacks),
+            ),
+        )?)))
+    })
+    .unwrap_or_else(null_mut)
+}
+
+/// Publish the metadata of this device to the other devices.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_discovery_set_metadata(
+    discovery: *const DiscoveryService,
+    metadata: *const u8,
+    size: usize,
+) -> bool {
+    assert!(!discovery.is_null());
+
+    if metadata.is_null() {
+        return false;
+    }
+
+    let metadata = unsafe { from_raw_parts(metadata, size) }.to_vec();
+    get_runtime_handle().block_on(unsafe { &*discovery }.set_metadata(metadata));
+
+    true
+}
+
+/// release the discovery service.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_discovery_release(discovery: *mut DiscoveryService) {
+    assert!(!discovery.is_null());
+
+    drop(unsafe { Box::from_raw(discovery) });
+}
@@ -0,0 +1,105 @@
+use std::ffi::{CString, c_char, c_void};
+
+use hylarana::{AudioFrame, CloseReason, MediaStreamObserver, MediaStreamSink, Source, VideoFrame};
+
+/// The frames of the sender or the receiver, the frames are only valid during
+/// the callback, returning false closes the stream.
+#[repr(C)]
+pub struct HylaranaStreamSink {
+    pub ctx: *const c_void,
+    pub video: Option<extern "C" fn(ctx: *const c_void, frame: *const VideoFrame) -> bool>,
+    pub audio: Option<extern "C" fn(ctx: *const c_void, frame: *const AudioFrame) -> bool>,
+}
+
+unsafe impl Send for HylaranaStreamSink {}
+unsafe impl Sync for HylaranaStreamSink {}
+
+impl MediaStreamSink for HylaranaStreamSink {
+    fn video(&self, frame: &VideoFrame) -> bool {
+        self.video.map(|it| it(self.ctx, frame)).unwrap_or(true)
+    }
+
+    fn audio(&self, frame: &AudioFrame) -> bool {
+        self.audio.map(|it| it(self.ctx, frame)).unwrap_or(true)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum HylaranaCloseReason {
+    RemoteClosed,
+    NetworkTimeout,
+    Closed,
+    CaptureStopped,
+    TransportError,
+    EncoderError,
+    DecoderError,
+    SourceLost,
+}
+
+impl From<&CloseReason> for HylaranaCloseReason {
+    fn from(value: &CloseReason) -> Self {
+        match value {
+            CloseReason::RemoteClosed => Self::RemoteClosed,
+            CloseReason::NetworkTimeout => Self::NetworkTimeout,
+            CloseReason::Closed => Self::Closed,
+            CloseReason::CaptureStopped => Self::CaptureStopped,
+            CloseReason::TransportError(_) => Self::TransportError,
+            CloseReason::EncoderError(_) => Self::EncoderError,
+            CloseReason::DecoderError(_) => Self::DecoderError,
+            CloseReason::SourceLost => Self::SourceLost,
+        }
+    }
+}
+
+/// The events of the sender or the receiver.
+#[repr(C)]
+pub struct HylaranaStreamObserver {
+    pub ctx: *const c_void,
+    /// The stream is closed, the handle still needs to be released, the
+    /// message describes the error of the reason.
+    pub close: Option<
+        extern "C" fn(ctx: *const c_void, reason: HylaranaCloseReason, message: *const c_char),
+    >,
+    /// The default audio device of the sender is changed, the source is passed
+    /// as json.
+    pub audio_device_changed: Option<extern "C" fn(ctx: *const c_void, source: *const c_char)>,
+    pub signal_lost: Option<extern "C" fn(ctx: *const c_void)>,
+    pub signal_restored: Option<extern "C" fn(ctx: *const c_void)>,
+}
+
+unsafe impl Send for HylaranaStreamObserver {}
+unsafe impl Sync for HylaranaStreamObserver {}
+
+impl MediaStreamObserver for HylaranaStreamObserver {
+    fn close(&self, reason: CloseReason) {
+        if let Some(callback) = self.close {
+            let message = CString::new(reason.to_string()).unwrap_or_default();
+
+            callback(self.ctx, (&reason).into(), message.as_ptr());
+        }
+    }
+
+    fn audio_device_changed(&self, source: &Source) {
+        if let Some(callback) = self.audio_device_changed {
+            if let Some(source) = serde_json::to_string(source)
+                .ok()
+                .and_then(|it| CString::new(it).ok())
+            {
+                callback(self.ctx, source.as_ptr());
+            }
+        }
+    }
+
+    fn on_signal_lost(&self) {
+        if let Some(callback) = self.signal_lost {
+            callback(self.ctx);
+        }
+    }
+
+    fn on_signal_restored(&self) {
+        if let Some(callback) = self.signal_restored {
+            callback(self.ctx);
+        }
+    }
+}
@@ -0,0 +1,565 @@
+use crate::{
+    AudioCaptureSourceDescription, CaptureHandler, FrameConsumer, Source,
+    VideoCaptureSourceDescription,
+};
+
+use std::{
+    ffi::c_void,
+    marker::PhantomData,
+    mem::zeroed,
+    ptr::{null, null_mut},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{
+    Size, clock,
+    frame::{AudioFrame, VideoFormat, VideoFrame, VideoSubFormat},
+    strings::PSTR,
+};
+
+use ffmpeg::*;
+use parking_lot::Mutex;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MediaFileCaptureError {
+    #[error("failed to open the url={0}")]
+    OpenInputError(String),
+    #[error("not found a stream of the media type in the url")]
+    NotFoundStream,
+    #[error("failed to open the decoder of the stream")]
+    OpenDecoderError,
+    #[error("failed to allocate the frame of the converter")]
+    CreateConverterError,
+    #[error(transparent)]
+    CreateThreadError(#[from] std::io::Error),
+}
+
+// Called by the blocking io of ffmpeg, a stopped capture interrupts the
+// reading of a network stream that does not deliver any data.
+extern "C" fn interrupt(opaque: *mut c_void) -> i32 {
+    !unsafe { &*(opaque as *const AtomicBool) }.load(Ordering::Relaxed) as i32
+}
+
+/// An opened input with the decoder of one of its streams.
+struct MediaFile {
+    format: *mut AVFormatContext,
+    codec: *mut AVCodecContext,
+    packet: *mut AVPacket,
+    frame: *mut AVFrame,
+    stream: i32,
+    flushed: bool,
+    time_base: AVRational,
+    // The opaque of the interrupt callback, it must outlive the input.
+    running: Arc<AtomicBool>,
+}
+
+unsafe impl Send for MediaFile {}
+
+impl MediaFile {
+    fn open(
+        url: &str,
+        kind: AVMediaType,
+        running: Arc<AtomicBool>,
+    ) -> Result<Self, MediaFileCaptureError> {
+        // The resources are released by drop if any of the steps fails.
+        let mut this = Self {
+            format: unsafe { avformat_alloc_context() },
+            codec: null_mut(),
+            packet: unsafe { av_packet_alloc() },
+            frame: unsafe { av_frame_alloc() },
+            time_base: AVRational { num: 0, den: 1 },
+            flushed: false,
+            stream: -1,
+            running,
+        };
+
+        if this.format.is_null() || this.packet.is_null() || this.frame.is_null() {
+            return Err(MediaFileCaptureError::OpenInputError(url.to_string()));
+        }
+
+        unsafe {
+            (*this.format).interrupt_callback = AVIOInterruptCB {
+                callback: Some(interrupt),
+                opaque: Arc::as_ptr(&this.running) as *mut c_void,
+            };
+        }
+
+        // The context is freed by ffmpeg when the input can not be opened.
+        let path = PSTR::from(url);
+        if unsafe { avformat_open_input(&mut this.format, path.as_ptr(), null(), null_mut()) } != 0
+            || unsafe { avformat_find_stream_info(this.format, null_mut()) } < 0
+        {
+            return Err(MediaFileCaptureError::OpenInputError(url.to_string()));
+        }
+
+        let mut decoder = null();
+        this.stream = unsafe { av_find_best_stream(this.format, kind, -1, -1, &mut decoder, 0) };
+        if this.stream < 0 || decoder.is_null() {
+            return Err(MediaFileCaptureError::NotFoundStream);
+        }
+
+        let stream = unsafe { &**(*this.format).streams.add(this.stream as usize) };
+        this.time_base = stream.time_base;
+
+        this.codec = unsafe { avcodec_alloc_context3(decoder) };
+        if this.codec.is_null()
+            || unsafe { avcodec_parameters_to_context(this.codec, stream.codecpar) } < 0
+            || unsafe { avcodec_open2(this.codec, decoder, null_mut()) } != 0
+        {
+            return Err(MediaFileCaptureError::OpenDecoderError);
+        }
+
+        Ok(this)
+    }
+
+    /// Decode the next frame of the stream, returns `None` at the end of the
+    /// input or when the input fails.
+    fn read(&mut self) -> Option<&AVFrame> {
+        loop {
+            if unsafe { avcodec_receive_frame(self.codec, self.frame) } == 0 {
+                return Some(unsafe { &*self.frame });
+            }
+
+            if self.flushed {
+                return None;
+            }
+
+            // The packets of the other streams are skipped, the decoder is flushed at the
+            // end of the input to get the delayed frames.
+            loop {
+                if unsafe { av_read_frame(self.format, self.packet) } != 0 {
+                    self.flushed = true;
+
+                    unsafe {
+                        avcodec_send_packet(self.codec, null());
+                    }
+
+                    break;
+                }
+
+                let matched = unsafe { (*self.packet).stream_index } == self.stream;
+                let ret = if matched {
+                    unsafe { avcodec_send_packet(self.codec, self.packet) }
+                } else {
+                    0
+                };
+
+                unsafe {
+                    av_packet_unref(self.packet);
+                }
+
+                if ret != 0 {
+                    tracing::warn!("media file decode packet error={}", ret);
+                }
+
+                if matched {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for MediaFile {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.codec.is_null() {
+                avcodec_free_context(&mut self.codec);
+            }
+
+            if !self.format.is_null() {
+                avformat_close_input(&mut self.format);
+            }
+
+            if !self.packet.is_null() {
+                av_packet_free(&mut self.packet);
+            }
+
+            if !self.frame.is_null() {
+                av_frame_free(&mut self.frame);
+            }
+        }
+    }
+}
+
+/// Converts the decoded frames to the frames of the consumer.
+trait Converter: Send {
+    type Frame;
+
+    fn convert(&mut self, frame: &AVFrame) -> Option<&Self::Frame>;
+}
+
+/// Scales the decoded video to NV12 of the size of the capture, the frames
+/// are always in the system memory, whatever
+/// [VideoCaptureSourceDescription::hardware] says.
+struct VideoConverter {
+    context: *mut SwsContext,
+    output: *mut AVFrame,
+    frame: VideoFrame,
+}
+
+unsafe impl Send for VideoConverter {}
+
+impl VideoConverter {
+    fn new(size: Size) -> Result<Self, MediaFileCaptureError> {
+        let output = unsafe { av_frame_alloc() };
+        if output.is_null() {
+            return Err(MediaFileCaptureError::CreateConverterError);
+        }
+
+        let mut this = Self {
+            context: null_mut(),
+            frame: VideoFrame::default(),
+            output,
+        };
+
+        let output = unsafe { &mut *this.output };
+        output.width = size.width as i32;
+        output.height = size.height as i32;
+        output.format = AVPixelFormat::AV_PIX_FMT_NV12 as i32;
+
+        if unsafe { av_frame_get_buffer(this.output, 0) } != 0 {
+            return Err(MediaFileCaptureError::CreateConverterError);
+        }
+
+        this.frame = VideoFrame {
+            format: VideoFormat::NV12,
+            sub_format: VideoSubFormat::SW,
+            width: size.width,
+            height: size.height,
+            data: [output.data[0] as _, output.data[1] as _, null()],
+            linesize: [output.linesize[0] as u32, output.linesize[1] as u32, 0],
+            timestamp: 0,
+        };
+
+        Ok(this)
+    }
+}
+
+impl Converter for VideoConverter {
+    type Frame = VideoFrame;
+
+    fn convert(&mut self, frame: &AVFrame) -> Option<&Self::Frame> {
+        let output = unsafe { &*self.output };
+
+        // The cached context is only created again when the input changes, such as
+        // the resolution of an adaptive stream.
+        self.context = unsafe {
+            sws_getCachedContext(
+                self.context,
+                frame.width,
+                frame.height,
+                std::mem::transmute::<_, AVPixelFormat>(frame.format),
+                output.width,
+                output.height,
+                AVPixelFormat::AV_PIX_FMT_NV12,
+                SWS_BILINEAR as i32,
+                null_mut(),
+                null_mut(),
+                null(),
+            )
+        };
+
+        if self.context.is_null() {
+            return None;
+        }
+
+        if unsafe {
+            sws_scale(
+                self.context,
+                frame.data.as_ptr() as *const *const u8,
+                frame.linesize.as_ptr(),
+                0,
+                frame.height,
+                output.data.as_ptr(),
+                output.linesize.as_ptr(),
+            )
+        } <= 0
+        {
+            return None;
+        }
+
+        // The frames are paced by their position in the file, so the time of the
+        // conversion is the time of the frame.
+        self.frame.timestamp = clock::now();
+
+        Some(&self.frame)
+    }
+}
+
+impl Drop for VideoConverter {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.context.is_null() {
+                sws_freeContext(self.context);
+            }
+
+            av_frame_free(&mut self.output);
+        }
+    }
+}
+
+/// Resamples the decoded audio to the interleaved 16 bit PCM of the capture.
+struct AudioConverter {
+    context: *mut SwrContext,
+    buffer: Vec<i16>,
+    frame: AudioFrame,
+}
+
+unsafe impl Send for AudioConverter {}
+
+impl AudioConverter {
+    fn new(sample_rate: u32, channels: u8) -> Self {
+        Self {
+            context: null_mut(),
+            buffer: Vec::new(),
+            frame: AudioFrame {
+                frames: 0,
+                data: null(),
+                timestamp: 0,
+                sample_rate,
+                channels,
+            },
+        }
+    }
+}
+
+impl Converter for AudioConverter {
+    type Frame = AudioFrame;
+
+    fn convert(&mut self, frame: &AVFrame) -> Option<&Self::Frame> {
+        // The resampler is created with the layout of the first frame, the
+        // surround streams are downmixed.
+        if self.context.is_null() {
+            let layout = unsafe {
+                let mut layout = zeroed::<AVChannelLayout>();
+                av_channel_layout_default(&mut layout, self.frame.channels as i32);
+
+                layout
+            };
+
+            if unsafe {
+                swr_alloc_set_opts2(
+                    &mut self.context,
+                    &layout,
+                    AVSampleFormat::AV_SAMPLE_FMT_S16,
+                    self.frame.sample_rate as i32,
+                    &frame.ch_layout,
+                    std::mem::transmute::<_, AVSampleFormat>(frame.format),
+                    frame.sample_rate,
+                    0,
+                    null_mut(),
+                )
+            } != 0
+                || unsafe { swr_init(self.context) } != 0
+            {
+                return None;
+            }
+        }
+
+        let capacity = unsafe { swr_get_out_samples(self.context, frame.nb_samples) };
+        if capacity < 0 {
+            return None;
+        }
+
+        self.buffer
+            .resize(capacity as usize * self.frame.channels as usize, 0);
+
+        let frames = unsafe {
+            swr_convert(
+                self.context,
+                [self.buffer.as_mut_ptr() as *mut u8].as_ptr(),
+                capacity,
+                frame.extended_data as *const *const u8,
+                frame.nb_samples,
+            )
+        };
+
+        if frames < 0 {
+            return None;
+        }
+
+        self.frame.frames = frames as u32;
+        self.frame.data = self.buffer.as_ptr();
+        self.frame.timestamp = clock::now();
+
+        Some(&self.frame)
+    }
+}
+
+impl Drop for AudioConverter {
+    fn drop(&mut self) {
+        if !self.context.is_null() {
+            unsafe {
+                swr_free(&mut self.context);
+            }
+        }
+    }
+}
+
+/// Plays a media file or a network stream, such as rtsp or http, as a source,
+/// the url is the id of a [crate::SourceType::Url] source.
+///
+/// The video and the audio open the url separately, and both are paced by
+/// the timestamps of their stream. The capture closes the consumer at the end
+/// of the input.
+pub struct MediaFileCapture<T> {
+    running: Mutex<Option<Arc<AtomicBool>>>,
+    _p: PhantomData<T>,
+}
+
+impl<T> Default for MediaFileCapture<T> {
+    fn default() -> Self {
+        Self {
+            running: Mutex::new(None),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<T> MediaFileCapture<T>
+where
+    T: 'static,
+{
+    /// The stream is paced again from the current frame if it is further
+    /// ahead than this, such as after a discontinuity of a live stream.
+    const MAX_DELAY: Duration = Duration::from_secs(1);
+
+    fn run<C, S>(
+        &self,
+        url: &str,
+        kind: AVMediaType,
+        mut converter: C,
+        mut consumer: S,
+    ) -> Result<(), MediaFileCaptureError>
+    where
+        C: Converter<Frame = T> + 'static,
+        S: FrameConsumer<Frame = T> + 'static,
+    {
+        let running = Arc::new(AtomicBool::new(true));
+        let mut input = MediaFile::open(url, kind, running.clone())?;
+        let time_base = input.time_base.num as f64 / input.time_base.den.max(1) as f64;
+
+        let running_ = running.clone();
+        thread::Builder::new()
+            .name("MediaFileCaptureThread".to_string())
+            .spawn(move || {
+                let mut clock: Option<(Instant, f64)> = None;
+
+                while running_.load(Ordering::Relaxed) {
+                    let Some(frame) = input.read() else {
+                        tracing::info!("media file capture is at the end of the input");
+
+                        break;
+                    };
+
+                    // The frames without a timestamp are passed right away.
+                    if frame.best_effort_timestamp != i64::MIN {
+                        let position = frame.best_effort_timestamp as f64 * time_base;
+                        let (start, first) = *clock.get_or_insert((Instant::now(), position));
+                        let due = start + Duration::from_secs_f64((position - first).max(0.0));
+
+                        match due.checked_duration_since(Instant::now()) {
+                            Some(delay) if delay > Self::MAX_DELAY => {
+                                clock = Some((Instant::now(), position));
+                            }
+                            Some(delay) => thread::sleep(delay),
+                            None => (),
+                        }
+                    }
+
+                    let _span = tracing::trace_span!("file_capture").entered();
+
+                    let Some(frame) = converter.convert(frame) else {
+                        tracing::error!("media file capture convert frame failed");
+
+                        break;
+                    };
+
+                    if !consumer.sink(frame) {
+                        break;
+                    }
+                }
+
+                consumer.close();
+            })?;
+
+        // If there is a previous capture, end it first.
+        if let Some(previous) = self.running.lock().replace(running) {
+            previous.store(false, Ordering::Relaxed);
+        }
+
+        Ok(())
+    }
+
+    fn close(&self) {
+        if let Some(running) = self.running.lock().take() {
+            running.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl CaptureHandler for MediaFileCapture<VideoFrame> {
+    type Frame = VideoFrame;
+    type Error = MediaFileCaptureError;
+    type CaptureOptions = VideoCaptureSourceDescription;
+
+    // The urls can not be enumerated, the source is created with `Source::url`.
+    fn get_sources() -> Result<Vec<Source>, Self::Error> {
+        Ok(Vec::new())
+    }
+
+    fn stop(&self) -> Result<(), Self::Error> {
+        self.close();
+
+        Ok(())
+    }
+
+    fn start<S: FrameConsumer<Frame = Self::Frame> + 'static>(
+        &self,
+        options: Self::CaptureOptions,
+        consumer: S,
+    ) -> Result<(), Self::Error> {
+        self.run(
+            &options.source.id,
+            AVMediaType::AVMEDIA_TYPE_VIDEO,
+            VideoConverter::new(options.size)?,
+            consumer,
+        )
+    }
+}
+
+impl CaptureHandler for MediaFileCapture<AudioFrame> {
+    type Frame = AudioFrame;
+    type Error = MediaFileCaptureError;
+    type CaptureOptions = AudioCaptureSourceDescription;
+
+    fn get_sources() -> Result<Vec<Source>, Self::Error> {
+        Ok(Vec::new())
+    }
+
+    fn stop(&self) -> Result<(), Self::Error> {
+        self.close();
+
+        Ok(())
+    }
+
+    fn start<S: FrameConsumer<Frame = Self::Frame> + 'static>(
+        &self,
+        options: Self::CaptureOptions,
+        consumer: S,
+    ) -> Result<(), Self::Error> {
+        self.run(
+            &options.source.id,
+            AVMediaType::AVMEDIA_TYPE_AUDIO,
+            AudioConverter::new(options.sample_rate, options.channels),
+            consumer,
+        )
+    }
+}
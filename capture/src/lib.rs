@@ -1,7 +1,12 @@
+mod file;
+mod pattern;
+mod watcher;
+
 #[cfg(target_os = "windows")]
 mod win32 {
     pub mod audio;
     pub mod screen;
+    pub mod window;
 }
 
 #[cfg(target_os = "linux")]
@@ -14,8 +19,15 @@ mod linux {
 mod macos {
     pub mod audio;
     pub mod screen;
+    pub mod window;
 }
 
+pub use self::{
+    file::{MediaFileCapture, MediaFileCaptureError},
+    pattern::{TestPatternCapture, TestPatternCaptureError},
+    watcher::{SourceEvent, SourceWatcher},
+};
+
 #[cfg(target_os = "windows")]
 pub use self::win32::{
     audio::{AudioCapture, AudioCaptureError},
@@ -25,7 +37,7 @@ pub use self::win32::{
 #[cfg(target_os = "linux")]
 pub use self::linux::{
     audio::{AudioCapture, AudioCaptureError},
-    screen::{ScreenCapture, ScreenCaptureError},
+    screen::{ScreenCapture, ScreenCaptureError, SessionType},
 };
 
 #[cfg(target_os = "macos")]
@@ -53,6 +65,132 @@ pub enum CaptureError {
     AudioCaptureError(#[from] AudioCaptureError),
     #[error(transparent)]
     ScreenCaptureError(#[from] ScreenCaptureError),
+    #[error(transparent)]
+    MediaFileCaptureError(#[from] MediaFileCaptureError),
+    #[error(transparent)]
+    TestPatternCaptureError(#[from] TestPatternCaptureError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("this kind of source is not supported on this platform, kind={0:?}")]
+    UnsupportedSource(SourceType),
+}
+
+/// A rectangle of the captured picture, in the pixels of the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct DamageRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// What changed in the captured picture since the previous frame, see
+/// [FrameConsumer::damage].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum Damage {
+    /// The picture is the same as the previous frame, only the cursor may have
+    /// moved.
+    Unchanged,
+    /// Only these rectangles changed.
+    Regions(Vec<DamageRect>),
+    /// All of the picture may have changed.
+    #[default]
+    Full,
+}
+
+/// How a window on the screen is found, see [Capture::find_windows].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum WindowMatch {
+    /// The native id of the window, the `HWND` on Windows and the window
+    /// number on macOS.
+    Id(u64),
+    /// The process that owns the window, the file name of the executable on
+    /// Windows, such as `KeePassXC.exe`, and the name of the application on
+    /// macOS, case-insensitive.
+    Process(String),
+    /// The title of the window contains the text, case-insensitive. On macOS
+    /// the titles are only readable with the screen recording permission.
+    Title(String),
+}
+
+impl WindowMatch {
+    pub(crate) fn is_match(&self, id: u64, process: &str, title: &str) -> bool {
+        match self {
+            Self::Id(it) => *it == id,
+            Self::Process(it) => it.eq_ignore_ascii_case(process),
+            Self::Title(it) => title.to_lowercase().contains(&it.to_lowercase()),
+        }
+    }
+}
+
+/// The part of a window on a screen, in the coordinates of the screen from
+/// its top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct WindowRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowRect {
+    /// Clips the window to the screen, both are the left, top, right and
+    /// bottom edges in the coordinates of the desktop, `None` if the window is
+    /// not on the screen.
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) fn clip(window: [i64; 4], screen: [i64; 4]) -> Option<Self> {
+        let left = window[0].max(screen[0]);
+        let top = window[1].max(screen[1]);
+        let right = window[2].min(screen[2]);
+        let bottom = window[3].min(screen[3]);
+
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        Some(Self {
+            x: (left - screen[0]) as u32,
+            y: (top - screen[1]) as u32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+}
+
+/// The windows found on a screen, see [Capture::find_windows].
+#[derive(Debug, Clone, Default)]
+pub struct ScreenWindows {
+    /// The size of the screen in the coordinates of the rects, it is not the
+    /// size of the captured frames when the screen is scaled.
+    pub size: Size,
+    pub rects: Vec<WindowRect>,
+}
+
+/// Content that the system hides from the capture, see
+/// [FrameConsumer::protected].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum ProtectedContent {
+    /// Protected content, such as a DRM video, is blacked out in the captured
+    /// picture, the rest of the screen is still captured.
+    Masked,
+    /// The secure desktop is shown, such as the UAC prompt or the lock screen,
+    /// nothing of the screen can be captured and the last picture is repeated.
+    SecureDesktop,
+}
+
+impl Damage {
+    pub fn is_unchanged(&self) -> bool {
+        match self {
+            Self::Unchanged => true,
+            Self::Regions(regions) => regions.is_empty(),
+            Self::Full => false,
+        }
+    }
 }
 
 pub trait FrameConsumer: Sync + Send {
@@ -63,6 +201,25 @@ pub trait FrameConsumer: Sync + Send {
     /// returns false, the source stops capturing.
     fn sink(&mut self, frame: &Self::Frame) -> bool;
 
+    /// This method is called when the capture switches to another device, for
+    /// example, when the default device changes or the captured device is
+    /// removed.
+    #[allow(unused_variables)]
+    fn changed(&mut self, source: &Source) {}
+
+    /// This method is called before the frame with the changes of the frame,
+    /// only the screen captures of windows track the changes, the frames of
+    /// the other captures are always fully changed.
+    #[allow(unused_variables)]
+    fn damage(&mut self, damage: &Damage) {}
+
+    /// This method is called when the system starts or stops hiding content
+    /// from the capture, `None` when nothing is hidden anymore. Only the
+    /// desktop duplication and the gdi screen captures on windows detect it,
+    /// the other captures silently capture black.
+    #[allow(unused_variables)]
+    fn protected(&mut self, content: Option<ProtectedContent>) {}
+
     fn close(&mut self);
 }
 
@@ -79,6 +236,18 @@ pub trait CaptureHandler: Sync + Send {
     /// Get a list of sources, such as multiple screens in a display source.
     fn get_sources() -> Result<Vec<Source>, Self::Error>;
 
+    /// Check whether the process is allowed to capture this kind of source,
+    /// this never prompts the user.
+    fn check_permission() -> PermissionState {
+        PermissionState::Granted
+    }
+
+    /// Ask the user for the permission to capture this kind of source, this
+    /// may show a system dialog and blocks until the user has made a choice.
+    fn request_permission() -> Result<PermissionState, Self::Error> {
+        Ok(Self::check_permission())
+    }
+
     /// Stop capturing the current source.
     fn stop(&self) -> Result<(), Self::Error>;
 
@@ -103,6 +272,24 @@ pub enum SourceType {
     Screen,
     /// Audio input and output devices.
     Audio,
+    /// A media file or a network stream, such as rtsp or http, which is
+    /// decoded and played as the video source or the audio source, see
+    /// [Source::url].
+    Url,
+    /// The synthetic video and audio of [Source::test_pattern].
+    TestPattern,
+}
+
+/// Whether the process is allowed to capture a kind of source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum PermissionState {
+    Granted,
+    /// The user has refused, on macOS this can only be changed by the user in
+    /// the system settings.
+    Denied,
+    /// The user has not been asked yet, capturing will prompt the user.
+    NotDetermined,
 }
 
 /// Video source or Audio source.
@@ -123,10 +310,55 @@ pub struct Source {
     pub is_default: bool,
 }
 
+impl Source {
+    /// The source of a media file or a network stream, the path or the url is
+    /// opened by ffmpeg, so anything that ffmpeg can demux and decode is
+    /// supported.
+    pub fn url(url: &str) -> Self {
+        Self {
+            id: url.to_string(),
+            name: url.to_string(),
+            index: 0,
+            kind: SourceType::Url,
+            is_default: false,
+        }
+    }
+
+    /// Color bars with a moving ramp as the video source, or a 1 kHz tone as
+    /// the audio source, for development, soak tests, or checking a receiver
+    /// without a capture device.
+    pub fn test_pattern() -> Self {
+        Self {
+            id: "test-pattern".to_string(),
+            name: "Test Pattern".to_string(),
+            index: 0,
+            kind: SourceType::TestPattern,
+            is_default: false,
+        }
+    }
+}
+
+/// Screen capture implementations on windows.
+#[cfg(target_os = "windows")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum ScreenCaptureBackend {
+    /// Windows.Graphics.Capture, available since Windows 10 1803.
+    Wgc,
+    /// DXGI desktop duplication.
+    Dxgi,
+    /// GDI BitBlt, works everywhere but is slow.
+    Gdi,
+}
+
 #[derive(Debug, Clone)]
 pub struct VideoCaptureSourceDescription {
     #[cfg(target_os = "windows")]
     pub direct3d: Direct3DDevice,
+    /// Force a specific screen capture backend, by default the backends are
+    /// tried in the order of WGC, DXGI and GDI until one of them works.
+    #[cfg(target_os = "windows")]
+    pub backend: Option<ScreenCaptureBackend>,
     /// Indicates whether the capturer internally outputs hardware frames or
     /// not, it should be noted that internally it will just output hardware
     /// frames to the best of its ability and may also output software frames.
@@ -134,12 +366,23 @@ pub struct VideoCaptureSourceDescription {
     pub source: Source,
     pub size: Size,
     pub fps: u8,
+    /// The native ids of the windows that are left out of the captured
+    /// screen, such as the preview or the player of the application, which
+    /// would otherwise show the screen inside itself. The id is the `HWND` on
+    /// Windows and the window number on macOS, it is ignored on Linux.
+    ///
+    /// On Windows only the windows of this process can be excluded, and they
+    /// are hidden from all captures of the system while they are excluded.
+    pub exclude_windows: Vec<u64>,
 }
 
 #[derive(Debug, Clone)]
 pub struct AudioCaptureSourceDescription {
     pub source: Source,
     pub sample_rate: u32,
+    /// The channel count of the captured frames, the sources with more
+    /// channels, such as 5.1 or 7.1 devices, are downmixed.
+    pub channels: u8,
 }
 
 pub struct SourceCaptureOptions<T, P> {
@@ -172,6 +415,10 @@ where
 enum CaptureImplement {
     Screen(ScreenCapture),
     Audio(AudioCapture),
+    VideoFile(MediaFileCapture<VideoFrame>),
+    AudioFile(MediaFileCapture<AudioFrame>),
+    VideoPattern(TestPatternCapture<VideoFrame>),
+    AudioPattern(TestPatternCapture<AudioFrame>),
 }
 
 /// Capture implementations for audio devices and video devices.
@@ -181,17 +428,103 @@ pub struct Capture(Vec<CaptureImplement>);
 impl Capture {
     /// Get all sources that can be used for capture by specifying the type,
     /// which is usually an audio or video device.
-    #[allow(unreachable_patterns)]
     pub fn get_sources(kind: SourceType) -> Result<Vec<Source>, CaptureError> {
-        log::info!("capture get sources, kind={:?}", kind);
+        tracing::info!("capture get sources, kind={:?}", kind);
+
+        Self::enumerate(kind)
+    }
 
+    /// [Capture::get_sources] without the log, the source watcher calls this
+    /// every second.
+    #[allow(unreachable_patterns)]
+    pub(crate) fn enumerate(kind: SourceType) -> Result<Vec<Source>, CaptureError> {
         Ok(match kind {
             SourceType::Screen => ScreenCapture::get_sources()?,
             SourceType::Audio => AudioCapture::get_sources()?,
+            SourceType::TestPattern => vec![Source::test_pattern()],
             _ => Vec::new(),
         })
     }
 
+    /// Find the visible windows on a screen source, such as the windows whose
+    /// content should not be shared, the minimized windows are skipped. The
+    /// windows that are covered by other windows are still returned. Nothing
+    /// is found on Linux, where the positions of the windows are not known.
+    #[allow(unused_variables)]
+    pub fn find_windows(
+        source: &Source,
+        matches: &[WindowMatch],
+    ) -> Result<ScreenWindows, CaptureError> {
+        if source.kind != SourceType::Screen || matches.is_empty() {
+            return Ok(ScreenWindows::default());
+        }
+
+        #[cfg(target_os = "windows")]
+        return Ok(win32::window::find_windows(source, matches)?);
+
+        #[cfg(target_os = "macos")]
+        return Ok(macos::window::find_windows(source, matches)?);
+
+        #[cfg(target_os = "linux")]
+        return Ok(ScreenWindows::default());
+    }
+
+    /// Watch the sources of a kind, the watcher reports the monitors and the
+    /// devices that are plugged in or unplugged, so that the list of the
+    /// sources can be refreshed, or a sender can react when its source is
+    /// gone. The cameras and the urls can not be watched, nor the audio on
+    /// Linux.
+    pub fn watch_sources(kind: SourceType) -> Result<SourceWatcher, CaptureError> {
+        match kind {
+            SourceType::Screen | SourceType::TestPattern => (),
+            #[cfg(not(target_os = "linux"))]
+            SourceType::Audio => (),
+            _ => return Err(CaptureError::UnsupportedSource(kind)),
+        }
+
+        SourceWatcher::new(kind)
+    }
+
+    /// Check whether the process is allowed to capture this kind of source
+    /// without prompting the user, so that the application can guide the user
+    /// instead of capturing black frames.
+    #[allow(unreachable_patterns)]
+    pub fn check_permission(kind: SourceType) -> PermissionState {
+        let state = match kind {
+            SourceType::Screen => ScreenCapture::check_permission(),
+            SourceType::Audio => AudioCapture::check_permission(),
+            _ => PermissionState::Granted,
+        };
+
+        tracing::info!(
+            "capture check permission, kind={:?}, state={:?}",
+            kind,
+            state
+        );
+
+        state
+    }
+
+    /// Ask the user for the permission to capture this kind of source, on macOS
+    /// this shows the screen recording prompt, and on wayland this goes through
+    /// the dialog of the xdg-desktop-portal.
+    #[allow(unreachable_patterns)]
+    pub fn request_permission(kind: SourceType) -> Result<PermissionState, CaptureError> {
+        let state = match kind {
+            SourceType::Screen => ScreenCapture::request_permission()?,
+            SourceType::Audio => AudioCapture::request_permission()?,
+            _ => PermissionState::Granted,
+        };
+
+        tracing::info!(
+            "capture request permission, kind={:?}, state={:?}",
+            kind,
+            state
+        );
+
+        Ok(state)
+    }
+
     /// Create a capture and start capturing audio and video frames by
     /// specifying the source to be captured.
     pub fn start<V, A>(
@@ -208,9 +541,23 @@ impl Capture {
             consumer,
         }) = video
         {
-            let screen = ScreenCapture::default();
-            screen.start(description, consumer)?;
-            devices.push(CaptureImplement::Screen(screen));
+            devices.push(match description.source.kind {
+                SourceType::Url => {
+                    let file = MediaFileCapture::default();
+                    file.start(description, consumer)?;
+                    CaptureImplement::VideoFile(file)
+                }
+                SourceType::TestPattern => {
+                    let pattern = TestPatternCapture::default();
+                    pattern.start(description, consumer)?;
+                    CaptureImplement::VideoPattern(pattern)
+                }
+                _ => {
+                    let screen = ScreenCapture::default();
+                    screen.start(description, consumer)?;
+                    CaptureImplement::Screen(screen)
+                }
+            });
         }
 
         if let Some(SourceCaptureOptions {
@@ -218,9 +565,23 @@ impl Capture {
             consumer,
         }) = audio
         {
-            let audio = AudioCapture::default();
-            audio.start(description, consumer)?;
-            devices.push(CaptureImplement::Audio(audio));
+            devices.push(match description.source.kind {
+                SourceType::Url => {
+                    let file = MediaFileCapture::default();
+                    file.start(description, consumer)?;
+                    CaptureImplement::AudioFile(file)
+                }
+                SourceType::TestPattern => {
+                    let pattern = TestPatternCapture::default();
+                    pattern.start(description, consumer)?;
+                    CaptureImplement::AudioPattern(pattern)
+                }
+                _ => {
+                    let audio = AudioCapture::default();
+                    audio.start(description, consumer)?;
+                    CaptureImplement::Audio(audio)
+                }
+            });
         }
 
         Ok(Self(devices))
@@ -232,10 +593,14 @@ impl Capture {
             match item {
                 CaptureImplement::Screen(it) => it.stop()?,
                 CaptureImplement::Audio(it) => it.stop()?,
+                CaptureImplement::VideoFile(it) => it.stop()?,
+                CaptureImplement::AudioFile(it) => it.stop()?,
+                CaptureImplement::VideoPattern(it) => it.stop()?,
+                CaptureImplement::AudioPattern(it) => it.stop()?,
             };
         }
 
-        log::info!("close capture");
+        tracing::info!("close capture");
 
         Ok(())
     }
@@ -243,7 +608,7 @@ impl Capture {
 
 impl Drop for Capture {
     fn drop(&mut self) {
-        log::info!("capture drop");
+        tracing::info!("capture drop");
 
         drop(self.close());
     }
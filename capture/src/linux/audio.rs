@@ -4,7 +4,10 @@ use common::frame::AudioFrame;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-pub enum AudioCaptureError {}
+pub enum AudioCaptureError {
+    #[error("audio capture is not supported on linux")]
+    NotSupported,
+}
 
 #[derive(Default)]
 pub struct AudioCapture;
@@ -23,10 +26,10 @@ impl CaptureHandler for AudioCapture {
         _options: Self::CaptureOptions,
         _consumer: S,
     ) -> Result<(), Self::Error> {
-        unimplemented!("audio capture is not supported on linux")
+        Err(AudioCaptureError::NotSupported)
     }
 
     fn stop(&self) -> Result<(), Self::Error> {
-        unimplemented!("audio capture is not supported on linux")
+        Ok(())
     }
 }
@@ -1,13 +1,75 @@
-use crate::{CaptureHandler, FrameConsumer, Source, VideoCaptureSourceDescription};
+mod portal;
+mod stream;
+
+use crate::{
+    CaptureHandler, FrameConsumer, PermissionState, Source, SourceType,
+    VideoCaptureSourceDescription,
+};
+
+use std::env;
 
 use common::frame::VideoFrame;
+use parking_lot::Mutex;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-pub enum ScreenCaptureError {}
+pub enum ScreenCaptureError {
+    #[error(transparent)]
+    CreateThreadError(#[from] std::io::Error),
+    #[error(transparent)]
+    PortalError(#[from] ashpd::Error),
+    #[error(transparent)]
+    PipeWireError(#[from] pipewire::Error),
+    #[error(transparent)]
+    VideoResamplerError(#[from] resample::linux::VideoResamplerError),
+    #[error("not found a screen source")]
+    NotFoundScreenSource,
+    #[error("screen capture is not supported in the {0:?} session")]
+    NotSupportedSession(SessionType),
+    #[error("unsupported dma-buf modifier={0:#x}")]
+    NotSupportedModifier(u64),
+    #[error("failed to map the dma-buf")]
+    MapDmaBufError,
+    #[error("pipewire stream is closed")]
+    StreamClosed,
+}
+
+/// Type of the graphical session the process is running in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionType {
+    Wayland,
+    X11,
+    Unknown,
+}
+
+impl SessionType {
+    /// `XDG_SESSION_TYPE` is set by the login manager, if it is missing (for
+    /// example when started from a nested compositor), the display
+    /// environment variables are used instead.
+    pub fn current() -> Self {
+        match env::var("XDG_SESSION_TYPE").as_deref() {
+            Ok("wayland") => Self::Wayland,
+            Ok("x11") => Self::X11,
+            _ => {
+                if env::var_os("WAYLAND_DISPLAY").is_some() {
+                    Self::Wayland
+                } else if env::var_os("DISPLAY").is_some() {
+                    Self::X11
+                } else {
+                    Self::Unknown
+                }
+            }
+        }
+    }
+}
+
+/// The id of the source on wayland, the compositor does not allow the
+/// application to enumerate the screens, the screen is selected by the user in
+/// the dialog of the portal when the capture is started.
+const PORTAL_SOURCE_ID: &str = "xdg-desktop-portal";
 
 #[derive(Default)]
-pub struct ScreenCapture;
+pub struct ScreenCapture(Mutex<Option<(portal::Portal, stream::PipeWireCapture)>>);
 
 impl CaptureHandler for ScreenCapture {
     type Frame = VideoFrame;
@@ -15,18 +77,77 @@ impl CaptureHandler for ScreenCapture {
     type CaptureOptions = VideoCaptureSourceDescription;
 
     fn get_sources() -> Result<Vec<Source>, Self::Error> {
-        Ok(Vec::new())
+        Ok(match SessionType::current() {
+            SessionType::Wayland => vec![Source {
+                id: PORTAL_SOURCE_ID.to_string(),
+                name: "Screen".to_string(),
+                index: 0,
+                kind: SourceType::Screen,
+                is_default: true,
+            }],
+            _ => Vec::new(),
+        })
+    }
+
+    // Only wayland has a permission model for the screen capture, the portal
+    // cannot be queried without showing the dialog, so the permission is only
+    // known after the user has gone through the dialog once.
+    fn check_permission() -> PermissionState {
+        if SessionType::current() != SessionType::Wayland || portal::Portal::has_restore_token() {
+            PermissionState::Granted
+        } else {
+            PermissionState::NotDetermined
+        }
+    }
+
+    fn request_permission() -> Result<PermissionState, Self::Error> {
+        if SessionType::current() != SessionType::Wayland {
+            return Ok(PermissionState::Granted);
+        }
+
+        Ok(if portal::Portal::request()? {
+            PermissionState::Granted
+        } else {
+            PermissionState::Denied
+        })
     }
 
     fn start<S: FrameConsumer<Frame = Self::Frame> + 'static>(
         &self,
-        _options: Self::CaptureOptions,
-        _consumer: S,
+        options: Self::CaptureOptions,
+        consumer: S,
     ) -> Result<(), Self::Error> {
-        unimplemented!("screen capture is not supported on linux")
+        let session = SessionType::current();
+        if session != SessionType::Wayland {
+            return Err(ScreenCaptureError::NotSupportedSession(session));
+        }
+
+        if options.source.id != PORTAL_SOURCE_ID {
+            return Err(ScreenCaptureError::NotFoundScreenSource);
+        }
+
+        // The user is asked to select a screen and grant the permission here, this
+        // blocks until the dialog of the portal is closed.
+        let (portal, remote) = portal::Portal::open()?;
+        let capture = stream::PipeWireCapture::start(&options, remote, consumer)?;
+
+        tracing::info!("screen capture started, session={:?}", session);
+
+        // If there is a previous capture, end it first.
+        if let Some((portal, capture)) = self.0.lock().replace((portal, capture)) {
+            capture.stop();
+            portal.close();
+        }
+
+        Ok(())
     }
 
     fn stop(&self) -> Result<(), Self::Error> {
-        unimplemented!("screen capture is not supported on linux")
+        if let Some((portal, capture)) = self.0.lock().take() {
+            capture.stop();
+            portal.close();
+        }
+
+        Ok(())
     }
 }
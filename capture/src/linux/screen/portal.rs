@@ -0,0 +1,109 @@
+use super::ScreenCaptureError;
+
+use std::os::fd::OwnedFd;
+
+use ashpd::desktop::{
+    PersistMode, ResponseError, Session,
+    screencast::{CursorMode, Screencast, SourceType},
+};
+
+use parking_lot::Mutex;
+
+/// The restore token returned by the portal, with this token the portal will
+/// not ask the user to select the screen again when the capture is restarted
+/// in the same process.
+static RESTORE_TOKEN: Mutex<Option<String>> = Mutex::new(None);
+
+/// The pipewire remote opened by the portal, the stream of the selected screen
+/// is only visible through this remote.
+pub struct PipeWireRemote {
+    pub fd: OwnedFd,
+    pub node_id: u32,
+}
+
+/// Screen cast session of the xdg-desktop-portal.
+pub struct Portal(Session<'static, Screencast<'static>>);
+
+impl Portal {
+    /// Create a screen cast session, the user selects the screen and grants
+    /// the permission in the dialog of the portal.
+    pub fn open() -> Result<(Self, PipeWireRemote), ScreenCaptureError> {
+        pollster::block_on(async {
+            let proxy = Screencast::new().await?;
+            let session = proxy.create_session().await?;
+
+            // Not all portals support embedding the cursor in the frames.
+            let cursor_mode = if proxy
+                .available_cursor_modes()
+                .await?
+                .contains(CursorMode::Embedded)
+            {
+                CursorMode::Embedded
+            } else {
+                CursorMode::Hidden
+            };
+
+            let restore_token = RESTORE_TOKEN.lock().clone();
+            proxy
+                .select_sources(
+                    &session,
+                    cursor_mode,
+                    SourceType::Monitor.into(),
+                    false,
+                    restore_token.as_deref(),
+                    PersistMode::Application,
+                )
+                .await?;
+
+            // Rejecting the request in the dialog is reported as an error here.
+            let response = proxy.start(&session, None).await?.response()?;
+            if let Some(token) = response.restore_token() {
+                RESTORE_TOKEN.lock().replace(token.to_string());
+            }
+
+            let node_id = response
+                .streams()
+                .first()
+                .map(|it| it.pipe_wire_node_id())
+                .ok_or_else(|| ScreenCaptureError::NotFoundScreenSource)?;
+
+            let fd = proxy.open_pipe_wire_remote(&session).await?;
+
+            tracing::info!(
+                "xdg-desktop-portal screen cast started, node_id={}",
+                node_id
+            );
+
+            Ok::<_, ScreenCaptureError>((Self(session), PipeWireRemote { fd, node_id }))
+        })
+    }
+
+    /// The portal remembers the selection of the user with the restore token,
+    /// without the token the user is asked again.
+    pub fn has_restore_token() -> bool {
+        RESTORE_TOKEN.lock().is_some()
+    }
+
+    /// Go through the dialog of the portal without starting the capture, the
+    /// selection is remembered by the restore token, so the capture started
+    /// later does not prompt the user again.
+    pub fn request() -> Result<bool, ScreenCaptureError> {
+        match Self::open() {
+            Ok((portal, _)) => {
+                portal.close();
+
+                Ok(true)
+            }
+            Err(ScreenCaptureError::PortalError(ashpd::Error::Response(
+                ResponseError::Cancelled,
+            ))) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn close(self) {
+        if let Err(e) = pollster::block_on(self.0.close()) {
+            tracing::warn!("failed to close the screen cast session, err={:?}", e);
+        }
+    }
+}
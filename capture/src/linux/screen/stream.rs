@@ -0,0 +1,506 @@
+use super::{ScreenCaptureError, portal::PipeWireRemote};
+use crate::{FrameConsumer, VideoCaptureSourceDescription};
+
+use std::{
+    io::Cursor,
+    ptr::null_mut,
+    slice,
+    sync::mpsc::{Sender, channel},
+    thread,
+};
+
+use common::{
+    Size, clock,
+    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    linux::{
+        DRM_FORMAT_ABGR8888, DRM_FORMAT_ARGB8888, DRM_FORMAT_MOD_LINEAR, DRM_FORMAT_XBGR8888,
+        DRM_FORMAT_XRGB8888, DmaBufDescriptor, DmaBufPlane,
+    },
+};
+
+use pipewire::{
+    context::Context,
+    main_loop::MainLoop,
+    properties::properties,
+    spa::{
+        buffer::{Data, DataType},
+        param::{
+            ParamType,
+            format::{FormatProperties, MediaSubtype, MediaType},
+            format_utils,
+            video::{VideoFlags, VideoFormat as SpaVideoFormat, VideoInfoRaw},
+        },
+        pod::{self, Pod, Property, PropertyFlags, Value, serialize::PodSerializer},
+        sys::{SPA_DATA_DmaBuf, SPA_DATA_MemFd, SPA_DATA_MemPtr, SPA_PARAM_BUFFERS_dataType},
+        utils::{Fraction, Rectangle, SpaTypes},
+    },
+    stream::{Stream, StreamFlags, StreamState},
+};
+
+use resample::linux::VideoResampler;
+
+/// `_IOW('b', 0, struct dma_buf_sync)`, see `linux/dma-buf.h`.
+const DMA_BUF_IOCTL_SYNC: libc::c_ulong = 0x4008_6200;
+const DMA_BUF_SYNC_READ: u64 = 1 << 0;
+const DMA_BUF_SYNC_START: u64 = 0 << 2;
+const DMA_BUF_SYNC_END: u64 = 1 << 2;
+
+/// Read only mapping of a linear dma-buf, the cpu access is bracketed by the
+/// dma-buf sync ioctl, so that the caches are coherent with the gpu.
+struct DmaBufMapping<'a> {
+    descriptor: &'a DmaBufDescriptor,
+    ptr: *mut libc::c_void,
+}
+
+impl<'a> DmaBufMapping<'a> {
+    fn new(descriptor: &'a DmaBufDescriptor) -> Result<Self, ScreenCaptureError> {
+        if descriptor.modifier != DRM_FORMAT_MOD_LINEAR {
+            return Err(ScreenCaptureError::NotSupportedModifier(
+                descriptor.modifier,
+            ));
+        }
+
+        let ptr = unsafe {
+            libc::mmap(
+                null_mut(),
+                descriptor.size as usize,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                descriptor.fd,
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(ScreenCaptureError::MapDmaBufError);
+        }
+
+        let this = Self { descriptor, ptr };
+        this.sync(DMA_BUF_SYNC_START | DMA_BUF_SYNC_READ);
+
+        Ok(this)
+    }
+
+    fn sync(&self, flags: u64) {
+        unsafe {
+            libc::ioctl(self.descriptor.fd, DMA_BUF_IOCTL_SYNC, &flags);
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.descriptor.size as usize) }
+    }
+}
+
+impl Drop for DmaBufMapping<'_> {
+    fn drop(&mut self) {
+        self.sync(DMA_BUF_SYNC_END | DMA_BUF_SYNC_READ);
+
+        unsafe {
+            libc::munmap(self.ptr, self.descriptor.size as usize);
+        }
+    }
+}
+
+/// The format negotiated with the compositor.
+struct StreamFormat {
+    format: VideoFormat,
+    fourcc: u32,
+    modifier: u64,
+    size: Size,
+}
+
+impl StreamFormat {
+    fn new(info: &VideoInfoRaw) -> Option<Self> {
+        let (format, fourcc) = match info.format() {
+            SpaVideoFormat::BGRx => (VideoFormat::BGRA, DRM_FORMAT_XRGB8888),
+            SpaVideoFormat::BGRA => (VideoFormat::BGRA, DRM_FORMAT_ARGB8888),
+            SpaVideoFormat::RGBx => (VideoFormat::RGBA, DRM_FORMAT_XBGR8888),
+            SpaVideoFormat::RGBA => (VideoFormat::RGBA, DRM_FORMAT_ABGR8888),
+            _ => return None,
+        };
+
+        Some(Self {
+            size: Size {
+                width: info.size().width,
+                height: info.size().height,
+            },
+            modifier: info.modifier(),
+            fourcc,
+            format,
+        })
+    }
+}
+
+/// Converts the frames of the pipewire stream to NV12 and pushes the frames to
+/// the consumer.
+struct FrameOutput {
+    consumer: Box<dyn FrameConsumer<Frame = VideoFrame>>,
+    transform: Option<(StreamFormat, VideoResampler)>,
+    options: VideoCaptureSourceDescription,
+    frame: VideoFrame,
+}
+
+impl FrameOutput {
+    /// The format may be renegotiated at any time, for example when the
+    /// resolution of the screen changes.
+    fn update_format(&mut self, format: StreamFormat) -> Result<(), ScreenCaptureError> {
+        tracing::info!(
+            "pipewire screen capture format changed, format={:?}, size={:?}, modifier={:#x}",
+            format.format,
+            format.size,
+            format.modifier
+        );
+
+        let transform = VideoResampler::new(format.format, format.size, self.options.size)?;
+        self.transform = Some((format, transform));
+
+        Ok(())
+    }
+
+    /// Returns false if the consumer stops the capture.
+    fn push(&mut self, data: &mut Data) -> Result<bool, ScreenCaptureError> {
+        let Some((format, transform)) = self.transform.as_mut() else {
+            return Ok(true);
+        };
+
+        // The consumer runs in this span, so the conversion and the rest of the
+        // pipeline of the frame are in one span of the trace.
+        let _span = tracing::trace_span!("screen_capture").entered();
+
+        let chunk = data.chunk();
+        let offset = chunk.offset() as usize;
+        let stride = if chunk.stride() > 0 {
+            chunk.stride() as u32
+        } else {
+            format.size.width * 4
+        };
+
+        let planes = match data.type_() {
+            // The dma-buf is imported by mapping it into the system memory, the encoder
+            // only accepts software frames on linux.
+            DataType::DmaBuf => {
+                let raw = data.as_raw();
+
+                let mut descriptor = DmaBufDescriptor {
+                    format: format.fourcc,
+                    modifier: format.modifier,
+                    fd: raw.fd as i32,
+                    size: raw.maxsize as u64 + raw.mapoffset as u64,
+                    planes: [DmaBufPlane::default(); 4],
+                    planes_count: 1,
+                };
+
+                descriptor.planes[0] = DmaBufPlane {
+                    offset: offset as u32,
+                    pitch: stride,
+                };
+
+                let mapping = DmaBufMapping::new(&descriptor)?;
+                let buffer = &mapping.as_slice()[raw.mapoffset as usize + offset..];
+
+                transform.process(buffer, stride)?
+            }
+            _ => {
+                // Empty chunks are sent when only the cursor metadata is updated.
+                if chunk.size() == 0 {
+                    return Ok(true);
+                }
+
+                match data.data() {
+                    Some(buffer) => transform.process(&buffer[offset..], stride)?,
+                    None => return Ok(true),
+                }
+            }
+        };
+
+        self.frame.data[0] = planes[0].as_ptr() as *const _;
+        self.frame.data[1] = planes[1].as_ptr() as *const _;
+        self.frame.timestamp = clock::now();
+
+        Ok(self.consumer.sink(&self.frame))
+    }
+}
+
+/// The formats accepted by the stream, the formats are listed in order of
+/// preference. The linear dma-buf is preferred, which saves the compositor a
+/// copy to the shared memory, otherwise the frames are transferred in the
+/// shared memory.
+fn enum_formats(fps: u8) -> Result<Vec<Vec<u8>>, ScreenCaptureError> {
+    let mut formats = Vec::with_capacity(2);
+
+    for modifier in [Some(DRM_FORMAT_MOD_LINEAR), None] {
+        let mut object = pod::object!(
+            SpaTypes::ObjectParamFormat,
+            ParamType::EnumFormat,
+            pod::property!(FormatProperties::MediaType, Id, MediaType::Video),
+            pod::property!(FormatProperties::MediaSubtype, Id, MediaSubtype::Raw),
+            pod::property!(
+                FormatProperties::VideoFormat,
+                Choice,
+                Enum,
+                Id,
+                SpaVideoFormat::BGRx,
+                SpaVideoFormat::BGRx,
+                SpaVideoFormat::BGRA,
+                SpaVideoFormat::RGBx,
+                SpaVideoFormat::RGBA
+            ),
+            pod::property!(
+                FormatProperties::VideoSize,
+                Choice,
+                Range,
+                Rectangle,
+                Rectangle {
+                    width: 1920,
+                    height: 1080
+                },
+                Rectangle {
+                    width: 1,
+                    height: 1
+                },
+                Rectangle {
+                    width: 8192,
+                    height: 8192
+                }
+            ),
+            pod::property!(
+                FormatProperties::VideoFramerate,
+                Choice,
+                Range,
+                Fraction,
+                Fraction {
+                    num: fps as u32,
+                    denom: 1
+                },
+                Fraction { num: 0, denom: 1 },
+                Fraction {
+                    num: 1000,
+                    denom: 1
+                }
+            ),
+        );
+
+        if let Some(modifier) = modifier {
+            object.properties.push(Property {
+                key: FormatProperties::VideoModifier.as_raw(),
+                flags: PropertyFlags::MANDATORY,
+                value: Value::Long(modifier as i64),
+            });
+        }
+
+        formats.push(serialize(object)?);
+    }
+
+    Ok(formats)
+}
+
+/// The buffer types accepted by the stream, this depends on whether the
+/// negotiated format has a modifier.
+fn buffers(dma_buf: bool) -> Result<Vec<u8>, ScreenCaptureError> {
+    let data_type = if dma_buf {
+        1 << SPA_DATA_DmaBuf
+    } else {
+        (1 << SPA_DATA_MemPtr) | (1 << SPA_DATA_MemFd)
+    };
+
+    serialize(pod::object!(
+        SpaTypes::ObjectParamBuffers,
+        ParamType::Buffers,
+        Property::new(SPA_PARAM_BUFFERS_dataType, Value::Int(data_type as i32)),
+    ))
+}
+
+fn serialize(object: pod::Object) -> Result<Vec<u8>, ScreenCaptureError> {
+    Ok(
+        PodSerializer::serialize(Cursor::new(Vec::new()), &Value::Object(object))
+            .map_err(|_| pipewire::Error::CreationFailed)?
+            .0
+            .into_inner(),
+    )
+}
+
+/// Capture the screen cast stream of the portal, the stream runs on the main
+/// loop of pipewire in a separate thread.
+pub struct PipeWireCapture(pipewire::channel::Sender<()>);
+
+impl PipeWireCapture {
+    pub fn start<S: FrameConsumer<Frame = VideoFrame> + 'static>(
+        options: &VideoCaptureSourceDescription,
+        remote: PipeWireRemote,
+        consumer: S,
+    ) -> Result<Self, ScreenCaptureError> {
+        let mut frame = VideoFrame::default();
+        frame.width = options.size.width;
+        frame.height = options.size.height;
+        frame.format = VideoFormat::NV12;
+        frame.sub_format = VideoSubFormat::SW;
+        frame.linesize[0] = options.size.width;
+        frame.linesize[1] = options.size.width;
+
+        let output = FrameOutput {
+            consumer: Box::new(consumer),
+            options: options.clone(),
+            transform: None,
+            frame,
+        };
+
+        let fps = options.fps;
+        let (stop_tx, stop_rx) = pipewire::channel::channel::<()>();
+        let (ready_tx, ready_rx) = channel::<Result<(), ScreenCaptureError>>();
+        thread::Builder::new()
+            .name("LinuxPipeWireScreenCaptureThread".to_string())
+            .spawn(move || {
+                if let Err(e) = run(remote, fps, output, stop_rx, &ready_tx) {
+                    tracing::error!("LinuxPipeWireScreenCaptureThread stop, error={:?}", e);
+
+                    let _ = ready_tx.send(Err(e));
+                } else {
+                    tracing::info!("LinuxPipeWireScreenCaptureThread stop");
+                }
+            })?;
+
+        // Wait for the stream to connect, so that the errors of the setup are
+        // returned to the caller.
+        ready_rx
+            .recv()
+            .map_err(|_| ScreenCaptureError::StreamClosed)??;
+
+        Ok(Self(stop_tx))
+    }
+
+    pub fn stop(&self) {
+        let _ = self.0.send(());
+    }
+}
+
+fn run(
+    remote: PipeWireRemote,
+    fps: u8,
+    output: FrameOutput,
+    stop: pipewire::channel::Receiver<()>,
+    ready: &Sender<Result<(), ScreenCaptureError>>,
+) -> Result<(), ScreenCaptureError> {
+    pipewire::init();
+
+    let mainloop = MainLoop::new(None)?;
+    let context = Context::new(&mainloop)?;
+    let core = context.connect_fd(remote.fd, None)?;
+
+    let stream = Stream::new(
+        &core,
+        "hylarana-screen-capture",
+        properties! {
+            *pipewire::keys::MEDIA_TYPE => "Video",
+            *pipewire::keys::MEDIA_CATEGORY => "Capture",
+            *pipewire::keys::MEDIA_ROLE => "Screen",
+        },
+    )?;
+
+    let mainloop_ = mainloop.clone();
+    let mainloop__ = mainloop.clone();
+    let _listener = stream
+        .add_local_listener_with_user_data(output)
+        .state_changed(move |_, _, _, state| {
+            tracing::info!("pipewire screen capture stream state={:?}", state);
+
+            // The stream is closed when the user stops the screen cast from the
+            // compositor.
+            if matches!(state, StreamState::Error(_) | StreamState::Unconnected) {
+                mainloop_.quit();
+            }
+        })
+        .param_changed(|stream, output, id, param| {
+            let Some(param) = param else {
+                return;
+            };
+
+            if id != ParamType::Format.as_raw() {
+                return;
+            }
+
+            if !matches!(
+                format_utils::parse_format(param),
+                Ok((MediaType::Video, MediaSubtype::Raw))
+            ) {
+                return;
+            }
+
+            let mut info = VideoInfoRaw::default();
+            if info.parse(param).is_err() {
+                return;
+            }
+
+            let Some(format) = StreamFormat::new(&info) else {
+                tracing::error!(
+                    "pipewire screen capture unsupported format={:?}",
+                    info.format()
+                );
+
+                return;
+            };
+
+            if let Err(e) = output.update_format(format) {
+                tracing::error!("pipewire screen capture update format error={:?}", e);
+
+                return;
+            }
+
+            match buffers(info.flags().contains(VideoFlags::MODIFIER)) {
+                Ok(buffers) => {
+                    if let Some(pod) = Pod::from_bytes(&buffers) {
+                        if let Err(e) = stream.update_params(&mut [pod]) {
+                            tracing::error!("pipewire screen capture update params error={:?}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("pipewire screen capture update params error={:?}", e);
+                }
+            }
+        })
+        .process(move |stream, output| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+
+            let Some(data) = buffer.datas_mut().first_mut() else {
+                return;
+            };
+
+            match output.push(data) {
+                Ok(true) => (),
+                Ok(false) => mainloop__.quit(),
+                Err(e) => {
+                    tracing::error!("pipewire screen capture push frame error={:?}", e);
+
+                    mainloop__.quit();
+                }
+            }
+        })
+        .register()?;
+
+    let formats = enum_formats(fps)?;
+    let mut params = formats
+        .iter()
+        .filter_map(|it| Pod::from_bytes(it))
+        .collect::<Vec<_>>();
+
+    stream.connect(
+        pipewire::spa::utils::Direction::Input,
+        Some(remote.node_id),
+        StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS,
+        &mut params,
+    )?;
+
+    let mainloop_ = mainloop.clone();
+    let _receiver = stop.attach(mainloop.loop_(), move |_| mainloop_.quit());
+
+    let _ = ready.send(Ok(()));
+
+    mainloop.run();
+
+    let _ = stream.disconnect();
+
+    Ok(())
+}
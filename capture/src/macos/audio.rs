@@ -3,11 +3,15 @@ use std::{
     sync::atomic::{AtomicBool, Ordering},
 };
 
-use crate::{AudioCaptureSourceDescription, CaptureHandler, FrameConsumer, Source, SourceType};
+use super::screen::{check_screen_capture_access, request_screen_capture_access};
+use crate::{
+    AudioCaptureSourceDescription, CaptureHandler, FrameConsumer, PermissionState, Source,
+    SourceType,
+};
 
 use thiserror::Error;
 
-use common::frame::AudioFrame;
+use common::{clock, frame::AudioFrame};
 use core_foundation::error::CFError;
 use parking_lot::Mutex;
 use resample::{
@@ -58,6 +62,14 @@ impl CaptureHandler for AudioCapture {
         }])
     }
 
+    fn check_permission() -> PermissionState {
+        check_screen_capture_access()
+    }
+
+    fn request_permission() -> Result<PermissionState, Self::Error> {
+        Ok(request_screen_capture_access())
+    }
+
     fn start<S: FrameConsumer<Frame = Self::Frame> + 'static>(
         &self,
         options: Self::CaptureOptions,
@@ -85,13 +97,14 @@ impl CaptureHandler for AudioCapture {
                     AudioSampleDescription {
                         sample_rate: options.sample_rate,
                         sample_bits: AudioSampleFormat::I16,
-                        channels: 2,
+                        channels: options.channels,
                     },
                     Output {
                         consumer,
                         frame: {
                             let mut frame = AudioFrame::default();
                             frame.sample_rate = options.sample_rate;
+                            frame.channels = options.channels;
 
                             frame
                         },
@@ -124,7 +137,7 @@ struct Capture {
 impl SCStreamOutputTrait for Capture {
     fn did_output_sample_buffer(&self, buffer: CMSampleBuffer, _: SCStreamOutputType) {
         if !self.status.load(Ordering::Relaxed) {
-            log::warn!("macos screen audio capture stops because sink returns false");
+            tracing::warn!("macos screen audio capture stops because sink returns false");
 
             return;
         }
@@ -137,7 +150,7 @@ impl SCStreamOutputTrait for Capture {
                     if let Err(e) = resampler.resample(unsafe {
                         from_raw_parts(buffer.data().as_ptr() as _, buffer.data().len() / 4)
                     }) {
-                        log::error!("resample audio buffer error={:?}", e);
+                        tracing::error!("resample audio buffer error={:?}", e);
 
                         self.status.store(false, Ordering::Relaxed);
                         return;
@@ -158,8 +171,11 @@ where
     S: FrameConsumer<Frame = AudioFrame> + 'static,
 {
     fn output(&mut self, buffer: &[i16], frames: u32) -> bool {
+        let _span = tracing::trace_span!("audio_capture", frames).entered();
+
         self.frame.data = buffer.as_ptr();
         self.frame.frames = frames;
+        self.frame.timestamp = clock::now();
 
         if !self.consumer.sink(&self.frame) {
             self.consumer.close();
@@ -3,9 +3,15 @@ use std::{
     sync::atomic::{AtomicBool, Ordering},
 };
 
-use crate::{CaptureHandler, FrameConsumer, Source, SourceType, VideoCaptureSourceDescription};
+use crate::{
+    CaptureHandler, FrameConsumer, PermissionState, Source, SourceType,
+    VideoCaptureSourceDescription,
+};
 
-use common::frame::{VideoFormat, VideoFrame, VideoSubFormat};
+use common::{
+    clock,
+    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+};
 use core_foundation::{base::TCFType, error::CFError};
 use core_media::cm_time::CMTime;
 use parking_lot::Mutex;
@@ -37,6 +43,34 @@ impl From<CFError> for ScreenCaptureError {
     }
 }
 
+#[link(name = "CoreGraphics", kind = "framework")]
+unsafe extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+/// ScreenCaptureKit requires the screen recording permission for both the
+/// screen and the system audio. The system does not tell whether the user has
+/// refused, so a missing permission is reported as not determined.
+pub(crate) fn check_screen_capture_access() -> PermissionState {
+    if unsafe { CGPreflightScreenCaptureAccess() } {
+        PermissionState::Granted
+    } else {
+        PermissionState::NotDetermined
+    }
+}
+
+/// The prompt is only shown the first time, after that the user has to allow
+/// the application in the system settings, and the application usually needs
+/// to be restarted for the change to take effect.
+pub(crate) fn request_screen_capture_access() -> PermissionState {
+    if unsafe { CGRequestScreenCaptureAccess() } {
+        PermissionState::Granted
+    } else {
+        PermissionState::Denied
+    }
+}
+
 #[derive(Default)]
 pub struct ScreenCapture(Mutex<Option<SCStream>>);
 
@@ -66,19 +100,41 @@ impl CaptureHandler for ScreenCapture {
             .collect())
     }
 
+    fn check_permission() -> PermissionState {
+        check_screen_capture_access()
+    }
+
+    fn request_permission() -> Result<PermissionState, Self::Error> {
+        Ok(request_screen_capture_access())
+    }
+
     fn start<S: FrameConsumer<Frame = Self::Frame> + 'static>(
         &self,
         options: Self::CaptureOptions,
         consumer: S,
     ) -> Result<(), Self::Error> {
-        let display = SCShareableContent::with_options()
-            .on_screen_windows_only()
-            .get()?
+        // The windows that are not on the screen yet are also excluded, they may be
+        // shown while capturing.
+        let content = SCShareableContent::get()?;
+        let display = content
             .displays()
             .into_iter()
             .find(|it| it.display_id().to_string() == options.source.id)
             .ok_or_else(|| ScreenCaptureError::NotFoundDevice)?;
 
+        let excluded = content
+            .windows()
+            .into_iter()
+            .filter(|it| options.exclude_windows.contains(&(it.window_id() as u64)))
+            .collect::<Vec<_>>();
+
+        if excluded.len() != options.exclude_windows.len() {
+            tracing::warn!(
+                "screen capture not all excluded windows are found, windows={:?}",
+                options.exclude_windows
+            );
+        }
+
         let mut frame = VideoFrame::default();
         frame.sub_format = VideoSubFormat::CvPixelBufferRef;
         frame.format = VideoFormat::BGRA;
@@ -87,7 +143,8 @@ impl CaptureHandler for ScreenCapture {
         frame.linesize = [frame.width * 4, 0, 0];
 
         let mut stream = SCStream::new(
-            &SCContentFilter::new().with_display_excluding_windows(&display, &[]),
+            &SCContentFilter::new()
+                .with_display_excluding_windows(&display, &excluded.iter().collect::<Vec<_>>()),
             &SCStreamConfiguration::default()
                 .set_captures_audio(false)?
                 .set_width(frame.width)?
@@ -140,18 +197,21 @@ where
 {
     fn did_output_sample_buffer(&self, buffer: CMSampleBuffer, _: SCStreamOutputType) {
         if !self.status.load(Ordering::Relaxed) {
-            log::warn!("macos screen capture stops because sink returns false");
+            tracing::warn!("macos screen capture stops because sink returns false");
 
             return;
         }
 
         if buffer.make_data_ready().is_ok() {
             if let Ok(buffer) = buffer.get_pixel_buffer() {
+                let _span = tracing::trace_span!("screen_capture").entered();
+
                 let mut lock = self.ctx.lock();
                 let CaptureContext { consumer, frame } = lock.deref_mut();
 
                 let buffer_ref = buffer.as_concrete_TypeRef();
                 frame.data[0] = buffer_ref as _;
+                frame.timestamp = clock::now();
 
                 if !consumer.sink(&frame) {
                     self.status.store(false, Ordering::Relaxed);
@@ -0,0 +1,141 @@
+use crate::{ScreenWindows, Source, WindowMatch, WindowRect};
+
+use super::screen::ScreenCaptureError;
+
+use common::Size;
+use core_foundation::{
+    array::{CFArray, CFArrayRef},
+    base::{CFType, TCFType},
+    dictionary::{CFDictionary, CFDictionaryRef},
+    number::CFNumber,
+    string::{CFString, CFStringRef},
+};
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct CGPoint {
+    x: f64,
+    y: f64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct CGSize {
+    width: f64,
+    height: f64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct CGRect {
+    origin: CGPoint,
+    size: CGSize,
+}
+
+impl CGRect {
+    fn edges(&self) -> [i64; 4] {
+        [
+            self.origin.x.floor() as i64,
+            self.origin.y.floor() as i64,
+            (self.origin.x + self.size.width).ceil() as i64,
+            (self.origin.y + self.size.height).ceil() as i64,
+        ]
+    }
+}
+
+const WINDOW_LIST_ON_SCREEN_ONLY: u32 = 1 << 0;
+const WINDOW_LIST_EXCLUDE_DESKTOP_ELEMENTS: u32 = 1 << 4;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+unsafe extern "C" {
+    static kCGWindowNumber: CFStringRef;
+    static kCGWindowOwnerName: CFStringRef;
+    static kCGWindowName: CFStringRef;
+    static kCGWindowBounds: CFStringRef;
+
+    fn CGWindowListCopyWindowInfo(option: u32, relative_to_window: u32) -> CFArrayRef;
+    fn CGDisplayBounds(display: u32) -> CGRect;
+    fn CGRectMakeWithDictionaryRepresentation(dict: CFDictionaryRef, rect: *mut CGRect) -> bool;
+}
+
+fn get_string(info: &CFDictionary<CFString, CFType>, key: CFStringRef) -> String {
+    info.find(unsafe { CFString::wrap_under_get_rule(key) })
+        .and_then(|it| it.downcast::<CFString>())
+        .map(|it| it.to_string())
+        .unwrap_or_default()
+}
+
+/// The bounds of the displays and the windows are both in points of the
+/// global display coordinates, with the origin at the top left corner of the
+/// main display.
+pub fn find_windows(
+    source: &Source,
+    matches: &[WindowMatch],
+) -> Result<ScreenWindows, ScreenCaptureError> {
+    let display = source
+        .id
+        .parse::<u32>()
+        .map_err(|_| ScreenCaptureError::NotFoundDevice)?;
+
+    let bounds = unsafe { CGDisplayBounds(display) };
+    if bounds.size.width <= 0.0 || bounds.size.height <= 0.0 {
+        return Err(ScreenCaptureError::NotFoundDevice);
+    }
+
+    let list = unsafe {
+        CGWindowListCopyWindowInfo(
+            WINDOW_LIST_ON_SCREEN_ONLY | WINDOW_LIST_EXCLUDE_DESKTOP_ELEMENTS,
+            0,
+        )
+    };
+
+    if list.is_null() {
+        return Err(ScreenCaptureError::CoreFoundationError(
+            "failed to get the window list".to_string(),
+        ));
+    }
+
+    let windows: CFArray<CFDictionary<CFString, CFType>> =
+        unsafe { CFArray::wrap_under_create_rule(list) };
+
+    let mut rects = Vec::new();
+    for info in windows.iter() {
+        let id = info
+            .find(unsafe { CFString::wrap_under_get_rule(kCGWindowNumber) })
+            .and_then(|it| it.downcast::<CFNumber>())
+            .and_then(|it| it.to_i64())
+            .unwrap_or_default() as u64;
+
+        let process = get_string(&info, unsafe { kCGWindowOwnerName });
+        let title = get_string(&info, unsafe { kCGWindowName });
+        if !matches.iter().any(|it| it.is_match(id, &process, &title)) {
+            continue;
+        }
+
+        let Some(window) = info
+            .find(unsafe { CFString::wrap_under_get_rule(kCGWindowBounds) })
+            .and_then(|it| it.downcast::<CFDictionary>())
+        else {
+            continue;
+        };
+
+        let mut rect = CGRect::default();
+        if !unsafe {
+            CGRectMakeWithDictionaryRepresentation(window.as_concrete_TypeRef(), &mut rect)
+        } {
+            continue;
+        }
+
+        if let Some(rect) = WindowRect::clip(rect.edges(), bounds.edges()) {
+            rects.push(rect);
+        }
+    }
+
+    Ok(ScreenWindows {
+        size: Size {
+            width: bounds.size.width as u32,
+            height: bounds.size.height as u32,
+        },
+        rects,
+    })
+}
@@ -0,0 +1,319 @@
+use crate::{
+    AudioCaptureSourceDescription, CaptureHandler, FrameConsumer, Source,
+    VideoCaptureSourceDescription,
+};
+
+use std::{
+    f32::consts::PI,
+    marker::PhantomData,
+    ptr::null,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{
+    clock,
+    frame::{AudioFrame, VideoFormat, VideoFrame, VideoSubFormat},
+};
+use parking_lot::Mutex;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TestPatternCaptureError {
+    #[error(transparent)]
+    CreateThreadError(#[from] std::io::Error),
+}
+
+/// Produces the frames of the test pattern at a fixed interval.
+trait Generator: Send {
+    type Frame;
+
+    fn interval(&self) -> Duration;
+
+    fn next(&mut self) -> &Self::Frame;
+}
+
+/// The 75% color bars, white, yellow, cyan, green, magenta, red and blue, in
+/// limited range BT.709 YUV.
+const BARS: [[u8; 3]; 7] = [
+    [180, 128, 128],
+    [168, 44, 136],
+    [145, 147, 44],
+    [133, 63, 52],
+    [63, 193, 204],
+    [51, 109, 212],
+    [28, 212, 120],
+];
+
+/// The color bars in the upper two thirds, and a gray ramp in the lower third
+/// which moves with every frame, so that a frozen receiver can be told apart.
+/// The frames are NV12 in the system memory, whatever
+/// [VideoCaptureSourceDescription::hardware] says.
+struct VideoGenerator {
+    buffer: Vec<u8>,
+    interval: Duration,
+    count: usize,
+    frame: VideoFrame,
+}
+
+unsafe impl Send for VideoGenerator {}
+
+impl VideoGenerator {
+    fn new(width: u32, height: u32, fps: u8) -> Self {
+        // NV12 has a chroma sample for each 2x2 block, so the size is even.
+        let (width, height) = ((width.max(2) & !1) as usize, (height.max(2) & !1) as usize);
+        let mut buffer = vec![128u8; width * height * 3 / 2];
+
+        let (luma, chroma) = buffer.split_at_mut(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let [l, u, v] = BARS[x * BARS.len() / width];
+
+                luma[y * width + x] = l;
+                if y % 2 == 0 && x % 2 == 0 {
+                    let index = (y / 2) * width + x;
+
+                    chroma[index] = u;
+                    chroma[index + 1] = v;
+                }
+            }
+        }
+
+        // The ramp has no color.
+        chroma[((height * 2 / 3) & !1) / 2 * width..].fill(128);
+
+        let mut frame = VideoFrame {
+            format: VideoFormat::NV12,
+            sub_format: VideoSubFormat::SW,
+            width: width as u32,
+            height: height as u32,
+            data: [null(), null(), null()],
+            linesize: [width as u32, width as u32, 0],
+            timestamp: 0,
+        };
+
+        frame.data[0] = buffer.as_ptr() as _;
+        frame.data[1] = buffer[width * height..].as_ptr() as _;
+
+        Self {
+            interval: Duration::from_secs(1) / fps.max(1) as u32,
+            count: 0,
+            buffer,
+            frame,
+        }
+    }
+}
+
+impl Generator for VideoGenerator {
+    type Frame = VideoFrame;
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn next(&mut self) -> &Self::Frame {
+        let (width, height) = (self.frame.width as usize, self.frame.height as usize);
+        let top = (height * 2 / 3) & !1;
+
+        // The ramp moves one sixtieth of the width per frame.
+        let offset = self.count * width / 60;
+        for y in top..height {
+            for x in 0..width {
+                self.buffer[y * width + x] = 16 + (((x + offset) % width) * 219 / width) as u8;
+            }
+        }
+
+        self.count += 1;
+        self.frame.timestamp = clock::now();
+
+        &self.frame
+    }
+}
+
+/// A sine tone of 1 kHz at -20 dBFS, in chunks of 10 milliseconds.
+struct AudioGenerator {
+    buffer: Vec<i16>,
+    position: u64,
+    frame: AudioFrame,
+}
+
+unsafe impl Send for AudioGenerator {}
+
+impl AudioGenerator {
+    const FREQUENCY: f32 = 1000.0;
+
+    /// -20 dBFS.
+    const AMPLITUDE: f32 = 0.1;
+
+    fn new(sample_rate: u32, channels: u8) -> Self {
+        let frames = (sample_rate / 100).max(1);
+
+        Self {
+            buffer: vec![0; frames as usize * channels.max(1) as usize],
+            position: 0,
+            frame: AudioFrame {
+                data: null(),
+                sample_rate,
+                channels: channels.max(1),
+                timestamp: 0,
+                frames,
+            },
+        }
+    }
+}
+
+impl Generator for AudioGenerator {
+    type Frame = AudioFrame;
+
+    fn interval(&self) -> Duration {
+        Duration::from_millis(10)
+    }
+
+    fn next(&mut self) -> &Self::Frame {
+        let channels = self.frame.channels as usize;
+        let sample_rate = self.frame.sample_rate.max(1) as u64;
+
+        for samples in self.buffer.chunks_exact_mut(channels) {
+            // The phase is taken from the position within a second, so the precision of
+            // the phase does not decrease over time.
+            let phase = (self.position % sample_rate) as f32 / sample_rate as f32;
+            let sample = (2.0 * PI * Self::FREQUENCY * phase).sin() * Self::AMPLITUDE;
+
+            samples.fill((sample * i16::MAX as f32) as i16);
+            self.position += 1;
+        }
+
+        self.frame.data = self.buffer.as_ptr();
+        self.frame.timestamp = clock::now();
+
+        &self.frame
+    }
+}
+
+/// A synthetic source, color bars with a moving ramp for the video and a 1
+/// kHz tone for the audio, for example to check a receiver without a capture
+/// device, see [Source::test_pattern].
+pub struct TestPatternCapture<T> {
+    running: Mutex<Option<Arc<AtomicBool>>>,
+    _p: PhantomData<T>,
+}
+
+impl<T> Default for TestPatternCapture<T> {
+    fn default() -> Self {
+        Self {
+            running: Mutex::new(None),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<T> TestPatternCapture<T>
+where
+    T: 'static,
+{
+    fn run<G, S>(&self, mut generator: G, mut consumer: S) -> Result<(), TestPatternCaptureError>
+    where
+        G: Generator<Frame = T> + 'static,
+        S: FrameConsumer<Frame = T> + 'static,
+    {
+        let running = Arc::new(AtomicBool::new(true));
+
+        let running_ = running.clone();
+        thread::Builder::new()
+            .name("TestPatternCaptureThread".to_string())
+            .spawn(move || {
+                let interval = generator.interval();
+                let mut due = Instant::now();
+
+                while running_.load(Ordering::Relaxed) {
+                    let span = tracing::trace_span!("pattern_capture").entered();
+                    if !consumer.sink(generator.next()) {
+                        break;
+                    }
+
+                    drop(span);
+
+                    // The frames are due at a fixed rate, so a slow consumer does not make the
+                    // pattern drift.
+                    due += interval;
+                    match due.checked_duration_since(Instant::now()) {
+                        Some(delay) => thread::sleep(delay),
+                        None => due = Instant::now(),
+                    }
+                }
+
+                consumer.close();
+            })?;
+
+        // If there is a previous capture, end it first.
+        if let Some(previous) = self.running.lock().replace(running) {
+            previous.store(false, Ordering::Relaxed);
+        }
+
+        Ok(())
+    }
+
+    fn close(&self) {
+        if let Some(running) = self.running.lock().take() {
+            running.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl CaptureHandler for TestPatternCapture<VideoFrame> {
+    type Frame = VideoFrame;
+    type Error = TestPatternCaptureError;
+    type CaptureOptions = VideoCaptureSourceDescription;
+
+    fn get_sources() -> Result<Vec<Source>, Self::Error> {
+        Ok(vec![Source::test_pattern()])
+    }
+
+    fn stop(&self) -> Result<(), Self::Error> {
+        self.close();
+
+        Ok(())
+    }
+
+    fn start<S: FrameConsumer<Frame = Self::Frame> + 'static>(
+        &self,
+        options: Self::CaptureOptions,
+        consumer: S,
+    ) -> Result<(), Self::Error> {
+        self.run(
+            VideoGenerator::new(options.size.width, options.size.height, options.fps),
+            consumer,
+        )
+    }
+}
+
+impl CaptureHandler for TestPatternCapture<AudioFrame> {
+    type Frame = AudioFrame;
+    type Error = TestPatternCaptureError;
+    type CaptureOptions = AudioCaptureSourceDescription;
+
+    fn get_sources() -> Result<Vec<Source>, Self::Error> {
+        Ok(vec![Source::test_pattern()])
+    }
+
+    fn stop(&self) -> Result<(), Self::Error> {
+        self.close();
+
+        Ok(())
+    }
+
+    fn start<S: FrameConsumer<Frame = Self::Frame> + 'static>(
+        &self,
+        options: Self::CaptureOptions,
+        consumer: S,
+    ) -> Result<(), Self::Error> {
+        self.run(
+            AudioGenerator::new(options.sample_rate, options.channels),
+            consumer,
+        )
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender, channel},
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{Capture, CaptureError, Source, SourceType};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A change of the sources of a kind, see [Capture::watch_sources].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum SourceEvent {
+    /// A monitor or a device was plugged in.
+    Added(Source),
+    /// A monitor or a device was unplugged, the source is the last known
+    /// state of it.
+    Removed(Source),
+    /// The system changed the default device to this source.
+    DefaultChanged(Source),
+}
+
+/// Watches the sources of a kind and reports the changes, the sources are
+/// compared by their ids. The watching stops when this is dropped.
+pub struct SourceWatcher {
+    running: Arc<AtomicBool>,
+    events: Receiver<SourceEvent>,
+}
+
+impl SourceWatcher {
+    /// The sources are enumerated again in this interval, not all platforms
+    /// notify the device changes of every kind, so the watcher polls them.
+    pub const INTERVAL: Duration = Duration::from_secs(1);
+
+    pub(crate) fn new(kind: SourceType) -> Result<Self, CaptureError> {
+        // The first snapshot is taken here, so that a failure is returned to the
+        // caller instead of ending the thread silently.
+        let sources = Capture::get_sources(kind)?;
+        let running = Arc::new(AtomicBool::new(true));
+        let (tx, events) = channel();
+
+        let running_ = running.clone();
+        thread::Builder::new()
+            .name("HylaranaSourceWatcherThread".to_string())
+            .spawn(move || {
+                poll(kind, sources, &tx, &running_);
+
+                tracing::info!("source watcher is closed, kind={:?}", kind);
+            })?;
+
+        tracing::info!("source watcher is started, kind={:?}", kind);
+
+        Ok(Self { running, events })
+    }
+
+    /// Waits for the next change, returns `None` if the watcher was closed.
+    pub fn recv(&self) -> Option<SourceEvent> {
+        self.events.recv().ok()
+    }
+
+    /// Waits for the next change at most for the timeout, returns `None` if
+    /// there was no change.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<SourceEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Takes a change that already happened without waiting.
+    pub fn try_recv(&self) -> Option<SourceEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Iterator for SourceWatcher {
+    type Item = SourceEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+impl Drop for SourceWatcher {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn poll(
+    kind: SourceType,
+    mut sources: Vec<Source>,
+    tx: &Sender<SourceEvent>,
+    running: &AtomicBool,
+) {
+    while running.load(Ordering::Relaxed) {
+        thread::sleep(SourceWatcher::INTERVAL);
+
+        let current = match Capture::enumerate(kind) {
+            Ok(it) => it,
+            Err(e) => {
+                // A device that is being plugged in can fail the enumeration, the next
+                // round usually succeeds.
+                tracing::warn!("source watcher failed to get sources, err={:?}", e);
+
+                continue;
+            }
+        };
+
+        let mut events = Vec::new();
+        for source in &sources {
+            if !current.iter().any(|it| it.id == source.id) {
+                events.push(SourceEvent::Removed(source.clone()));
+            }
+        }
+
+        for source in &current {
+            match sources.iter().find(|it| it.id == source.id) {
+                None => events.push(SourceEvent::Added(source.clone())),
+                Some(previous) if source.is_default && !previous.is_default => {
+                    events.push(SourceEvent::DefaultChanged(source.clone()))
+                }
+                _ => (),
+            }
+        }
+
+        for event in events {
+            tracing::info!("source watcher event, kind={:?}, event={:?}", kind, event);
+
+            // The watcher is dropped.
+            if tx.send(event).is_err() {
+                return;
+            }
+        }
+
+        sources = current;
+    }
+}
@@ -1,13 +1,20 @@
 use crate::{AudioCaptureSourceDescription, CaptureHandler, FrameConsumer, Source, SourceType};
 
-use std::sync::LazyLock;
+use std::{
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
 
-use common::frame::AudioFrame;
-use cpal::{Host, Stream, StreamConfig, traits::*};
+use common::{clock, frame::AudioFrame};
+use cpal::{Device, Host, Stream, StreamConfig, traits::*};
 use parking_lot::Mutex;
 use resample::{
-    AudioResampler, AudioResamplerError, AudioResamplerOutput, AudioSampleDescription,
-    AudioSampleFormat,
+    AudioResamplerError, AudioResamplerOutput, AudioSampleDescription, AudioSampleFormat,
+    SyncAudioResampler,
 };
 
 use thiserror::Error;
@@ -33,6 +40,8 @@ pub enum AudioCaptureError {
     PauseStreamError(#[from] cpal::PauseStreamError),
     #[error(transparent)]
     AudioResamplerError(#[from] AudioResamplerError),
+    #[error("failed to create the device monitor thread")]
+    CreateThreadError,
 }
 
 enum DeviceKind {
@@ -40,12 +49,123 @@ enum DeviceKind {
     Output,
 }
 
+/// The stream of the capture, shared with the device monitor thread, which
+/// replaces the stream when the device changes.
 #[derive(Default)]
-pub struct AudioCapture(Mutex<Option<Stream>>);
+struct CaptureState {
+    stream: Mutex<Option<Stream>>,
+    /// Set by the stream error callback when the device is removed.
+    lost: AtomicBool,
+}
+
+unsafe impl Send for CaptureState {}
+unsafe impl Sync for CaptureState {}
+
+#[derive(Default)]
+pub struct AudioCapture(Mutex<Option<Arc<CaptureState>>>);
 
 unsafe impl Send for AudioCapture {}
 unsafe impl Sync for AudioCapture {}
 
+impl AudioCapture {
+    /// Interval at which the device monitor checks the default device.
+    const MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+
+    fn find_device(name: &str) -> Result<Option<(Device, DeviceKind)>, AudioCaptureError> {
+        Ok(HOST
+            .output_devices()?
+            .map(|it| (it, DeviceKind::Output))
+            .chain(HOST.input_devices()?.map(|it| (it, DeviceKind::Input)))
+            .find(|(it, _)| it.name().map(|it| it == name).unwrap_or(false)))
+    }
+
+    fn open<S: FrameConsumer<Frame = AudioFrame> + 'static>(
+        state: &Arc<CaptureState>,
+        device: &Device,
+        kind: &DeviceKind,
+        sample_rate: u32,
+        channels: u8,
+        consumer: &Arc<Mutex<S>>,
+    ) -> Result<Stream, AudioCaptureError> {
+        let config: StreamConfig = match kind {
+            DeviceKind::Input => device.default_input_config()?.into(),
+            DeviceKind::Output => device.default_output_config()?.into(),
+        };
+
+        // The shared mode of wasapi only accepts the channel count of the device, so
+        // the surround devices are captured as they are and downmixed by the
+        // resampler.
+        tracing::info!(
+            "audio capture device config, channels={}, sample_rate={}",
+            config.channels,
+            config.sample_rate.0
+        );
+
+        // The callback of cpal runs on a dedicated audio thread, so the samples are
+        // resampled in place without another thread.
+        let mut resampler = SyncAudioResampler::new(
+            AudioSampleDescription {
+                sample_bits: AudioSampleFormat::I16,
+                sample_rate: config.sample_rate.0,
+                channels: config.channels as u8,
+            },
+            AudioSampleDescription {
+                sample_bits: AudioSampleFormat::I16,
+                sample_rate,
+                channels,
+            },
+        )?;
+
+        let mut output = Output {
+            consumer: consumer.clone(),
+            frame: {
+                let mut frame = AudioFrame::default();
+                frame.sample_rate = sample_rate;
+                frame.channels = channels;
+
+                frame
+            },
+        };
+
+        let mut buffer = Vec::new();
+        let mut playing = true;
+        let state = Arc::downgrade(state);
+        let stream = device.build_input_stream(
+            &config,
+            move |data: &[i16], _| {
+                // When any problem occurs in the process, you should not continue processing.
+                // If the cpal bottom layer continues to push audio samples, it should be
+                // ignored here and the process should not continue.
+                if !playing {
+                    return;
+                }
+
+                playing = match resampler.resample_into(data, &mut buffer) {
+                    Ok(frames) => output.output(&buffer, frames),
+                    Err(_) => false,
+                };
+            },
+            move |e| {
+                // The device is gone, for the loopback captures the monitor thread opens the
+                // default device instead.
+                if let cpal::StreamError::DeviceNotAvailable = e {
+                    if let Some(state) = state.upgrade() {
+                        state.lost.store(true, Ordering::Relaxed);
+                    }
+                }
+
+                // An error has occurred, but there is nothing you can do at this moment except
+                // output the error log.
+                tracing::error!("audio capture callback error={:?}", e);
+            },
+            None,
+        )?;
+
+        stream.play()?;
+        Ok(stream)
+    }
+}
+
 impl CaptureHandler for AudioCapture {
     type Frame = AudioFrame;
     type Error = AudioCaptureError;
@@ -84,89 +204,121 @@ impl CaptureHandler for AudioCapture {
         consumer: S,
     ) -> Result<(), Self::Error> {
         // Find devices with matching names
-        let (device, kind) = HOST
-            .output_devices()?
-            .map(|it| (it, DeviceKind::Output))
-            .chain(HOST.input_devices()?.map(|it| (it, DeviceKind::Input)))
-            .find(|(it, _)| {
-                it.name()
-                    .map(|name| name == options.source.name)
-                    .unwrap_or(false)
-            })
+        let (device, kind) = Self::find_device(&options.source.name)?
             .ok_or_else(|| AudioCaptureError::NotFoundAudioSource)?;
 
-        let mut config: StreamConfig = match kind {
-            DeviceKind::Input => device.default_input_config()?.into(),
-            DeviceKind::Output => device.default_output_config()?.into(),
-        };
+        let state = Arc::new(CaptureState::default());
+        let consumer = Arc::new(Mutex::new(consumer));
+        state.stream.lock().replace(Self::open(
+            &state,
+            &device,
+            &kind,
+            options.sample_rate,
+            options.channels,
+            &consumer,
+        )?);
 
-        config.channels = 2;
+        // If there is a previous stream, end it first.
+        // Normally, a Capture instance is only used once, but here a defensive process
+        // is done to avoid multiple calls due to external errors.
+        if let Some(state) = self.0.lock().replace(state.clone()) {
+            if let Some(stream) = state.stream.lock().take() {
+                stream.pause()?;
+            }
+        }
 
-        let mut frame = AudioFrame::default();
-        frame.sample_rate = options.sample_rate;
+        // Only the output devices are captured by loopback, the default device that the
+        // monitor follows is an output device, so an input device is never replaced by
+        // it.
+        if !matches!(kind, DeviceKind::Output) {
+            return Ok(());
+        }
 
-        let mut resampler = AudioResampler::new(
-            // config.sample_rate.0 as f64,
-            AudioSampleDescription {
-                sample_bits: AudioSampleFormat::I16,
-                sample_rate: config.sample_rate.0,
-                channels: 2,
-            },
-            // options.sample_rate as f64,
-            AudioSampleDescription {
-                sample_bits: AudioSampleFormat::I16,
-                sample_rate: options.sample_rate,
-                channels: 2,
-            },
-            Output {
-                consumer,
-                frame: {
-                    let mut frame = AudioFrame::default();
-                    frame.sample_rate = options.sample_rate;
-
-                    frame
-                },
-            },
-        )?;
+        // When the default device is captured, the capture follows the default device,
+        // and when the captured device is removed, the capture falls back to the
+        // default device.
+        let following = options.source.is_default;
+        let mut current = options.source.name.clone();
+        let state = Arc::downgrade(&state);
+        thread::Builder::new()
+            .name("HylaranaAudioCaptureMonitorThread".to_string())
+            .spawn(move || {
+                loop {
+                    thread::sleep(Self::MONITOR_INTERVAL);
 
-        let mut playing = true;
-        let stream = device.build_input_stream(
-            &config,
-            move |data: &[i16], _| {
-                // When any problem occurs in the process, you should not continue processing.
-                // If the cpal bottom layer continues to push audio samples, it should be
-                // ignored here and the process should not continue.
-                if !playing {
-                    return;
-                }
+                    let Some(state) = state.upgrade() else {
+                        break;
+                    };
 
-                if resampler.resample(data).is_err() {
-                    playing = false;
-                }
-            },
-            |e| {
-                // An error has occurred, but there is nothing you can do at this moment except
-                // output the error log.
-                log::error!("audio capture callback error={:?}", e);
-            },
-            None,
-        )?;
+                    if state.stream.lock().is_none() {
+                        break;
+                    }
 
-        stream.play()?;
+                    let Some(default) = HOST.default_output_device() else {
+                        continue;
+                    };
 
-        // If there is a previous stream, end it first.
-        // Normally, a Capture instance is only used once, but here a defensive process
-        // is done to avoid multiple calls due to external errors.
-        if let Some(stream) = self.0.lock().replace(stream) {
-            stream.pause()?;
-        }
+                    let Ok(name) = default.name() else {
+                        continue;
+                    };
+
+                    let lost = state.lost.load(Ordering::Relaxed);
+                    if name == current || !(following || lost) {
+                        continue;
+                    }
+
+                    tracing::info!(
+                        "audio capture device changed, previous={}, current={}",
+                        current,
+                        name
+                    );
+
+                    match Self::open(
+                        &state,
+                        &default,
+                        &DeviceKind::Output,
+                        options.sample_rate,
+                        options.channels,
+                        &consumer,
+                    ) {
+                        Ok(stream) => {
+                            // The capture may be stopped while the device is reopened.
+                            let mut stream_ = state.stream.lock();
+                            if stream_.is_none() {
+                                break;
+                            }
+
+                            if let Some(previous) = stream_.replace(stream) {
+                                let _ = previous.pause();
+                            }
+
+                            state.lost.store(false, Ordering::Relaxed);
+                            current = name.clone();
+
+                            consumer.lock().changed(&Source {
+                                id: name.clone(),
+                                kind: SourceType::Audio,
+                                is_default: true,
+                                index: 0,
+                                name,
+                            });
+                        }
+                        Err(e) => {
+                            tracing::error!("failed to reopen audio capture device, err={:?}", e);
+                        }
+                    }
+                }
+            })
+            .map_err(|_| AudioCaptureError::CreateThreadError)?;
 
         Ok(())
     }
 
     fn stop(&self) -> Result<(), Self::Error> {
-        if let Some(stream) = self.0.lock().take() {
-            stream.pause()?;
+        if let Some(state) = self.0.lock().take() {
+            if let Some(stream) = state.stream.lock().take() {
+                stream.pause()?;
+            }
         }
 
         Ok(())
@@ -174,7 +326,7 @@ impl CaptureHandler for AudioCapture {
 }
 
 struct Output<S> {
-    consumer: S,
+    consumer: Arc<Mutex<S>>,
     frame: AudioFrame,
 }
 
@@ -183,9 +335,12 @@ where
     S: FrameConsumer<Frame = AudioFrame> + 'static,
 {
     fn output(&mut self, buffer: &[i16], frames: u32) -> bool {
+        let _span = tracing::trace_span!("audio_capture", frames).entered();
+
         self.frame.data = buffer.as_ptr();
         self.frame.frames = frames;
+        self.frame.timestamp = clock::now();
 
-        self.consumer.sink(&self.frame)
+        self.consumer.lock().sink(&self.frame)
     }
 }
@@ -1,38 +1,43 @@
-use crate::{CaptureHandler, FrameConsumer, Source, SourceType, VideoCaptureSourceDescription};
+mod dxgi;
+mod gdi;
+mod wgc;
+
+use crate::{
+    CaptureHandler, Damage, FrameConsumer, ProtectedContent, ScreenCaptureBackend, Source,
+    SourceType, VideoCaptureSourceDescription,
+};
 
 use std::{
-    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
     thread,
-    time::Duration,
 };
 
 use common::{
-    Size,
+    Size, clock,
     frame::{VideoFormat, VideoFrame, VideoSubFormat},
-    win32::{EasyTexture, MediaThreadClass},
 };
 
 use parking_lot::Mutex;
 use resample::win32::{Resource, VideoResampler, VideoResamplerOptions};
 use thiserror::Error;
 use windows::{
-    Win32::Graphics::{
-        Direct3D11::{
-            D3D11_RESOURCE_MISC_SHARED, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
-            ID3D11DeviceContext, ID3D11Texture2D,
+    Win32::{
+        Foundation::HWND,
+        Graphics::Direct3D11::ID3D11VideoProcessorInputView,
+        System::StationsAndDesktops::{
+            CloseDesktop, DESKTOP_CONTROL_FLAGS, DESKTOP_READOBJECTS, GetUserObjectInformationW,
+            OpenInputDesktop, UOI_NAME,
+        },
+        UI::WindowsAndMessaging::{
+            SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE, WDA_NONE, WINDOW_DISPLAY_AFFINITY,
         },
-        Dxgi::Common::{DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_SAMPLE_DESC},
     },
     core::Interface,
 };
-
-use windows_capture::{
-    capture::{CaptureControl, Context, GraphicsCaptureApiHandler},
-    frame::Frame,
-    graphics_capture_api::InternalCaptureControl,
-    monitor::Monitor,
-    settings::{ColorFormat, CursorCaptureSettings, DrawBorderSettings, Settings},
-};
+use windows_capture::monitor::Monitor;
 
 #[derive(Debug, Error)]
 pub enum ScreenCaptureError {
@@ -50,198 +55,312 @@ pub enum ScreenCaptureError {
     CaptureControlError(String),
     #[error("start capture error")]
     StartCaptureError(String),
+    #[error("windows graphics capture is not supported")]
+    NotSupportedGraphicsCapture,
+    #[error("failed to create gdi device context")]
+    CreateDeviceContextError,
 }
 
-struct Surface(ID3D11Texture2D);
-
-unsafe impl Sync for Surface {}
-unsafe impl Send for Surface {}
+/// Whether the input desktop is the secure desktop, such as the UAC prompt or
+/// the lock screen, which the process is not allowed to open or capture.
+pub(crate) fn is_secure_desktop() -> bool {
+    let Ok(desktop) =
+        (unsafe { OpenInputDesktop(DESKTOP_CONTROL_FLAGS(0), false, DESKTOP_READOBJECTS) })
+    else {
+        return true;
+    };
+
+    let mut name = [0u16; 64];
+    let mut size = 0;
+    let result = unsafe {
+        GetUserObjectInformationW(
+            desktop,
+            UOI_NAME,
+            Some(name.as_mut_ptr() as *mut _),
+            (name.len() * 2) as u32,
+            Some(&mut size),
+        )
+    };
+
+    unsafe {
+        let _ = CloseDesktop(desktop);
+    }
 
-struct WindowsCapture {
-    texture: ID3D11Texture2D,
-    device_context: ID3D11DeviceContext,
-    status: Arc<AtomicBool>,
+    result.is_ok()
+        && !String::from_utf16_lossy(&name)
+            .trim_end_matches('\0')
+            .eq_ignore_ascii_case("Default")
 }
 
-impl GraphicsCaptureApiHandler for WindowsCapture {
-    type Flags = CaptureContext;
-    type Error = ScreenCaptureError;
+/// The consumer is shared between the backends, so that it is still available
+/// to the next backend when a backend fails to start.
+type SharedConsumer = Arc<Mutex<Box<dyn FrameConsumer<Frame = VideoFrame>>>>;
+
+/// Converts the captured desktop to NV12 and pushes the frames to the
+/// consumer, this is the same for all backends.
+struct FrameOutput {
+    consumer: SharedConsumer,
+    transform: VideoResampler,
+    frame: VideoFrame,
+    protected: Option<ProtectedContent>,
+}
 
+impl FrameOutput {
     fn new(
-        Context {
-            mut flags,
-            device,
-            device_context,
-        }: Context<Self::Flags>,
-    ) -> Result<Self, Self::Error> {
-        let status: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
-
-        // Because windows-capture and this library implementation use different devices
-        // and contexts, the problem needs to be solved with an intermediate texture,
-        // for which a cross-device shared resource handle is created, then
-        // windows-capture writes the frame to the intermediate texture, and the
-        // following capture thread creates the texture view from this intermediate
-        // texture as well The following capture thread also creates the texture view
-        // from this intermediate texture.
-        let (texture, surface) = {
-            let desc = D3D11_TEXTURE2D_DESC {
-                Width: flags.source.width()?,
-                Height: flags.source.height()?,
-                MipLevels: 1,
-                ArraySize: 1,
-                Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-                SampleDesc: DXGI_SAMPLE_DESC {
-                    Count: 1,
-                    Quality: 0,
-                },
-                BindFlags: 0,
-                CPUAccessFlags: 0,
-                Usage: D3D11_USAGE_DEFAULT,
-                MiscFlags: D3D11_RESOURCE_MISC_SHARED.0 as u32,
-            };
-
-            let mut tex = None;
-            unsafe {
-                device.CreateTexture2D(&desc, None, Some(&mut tex))?;
-            }
-
-            let texture = tex.unwrap();
-
-            // Use as input to VideoResampler by sharing resources across devices.
-            let surface = flags
-                .options
-                .direct3d
-                .open_shared_texture(texture.get_shared()?)?;
-
-            (texture, Surface(surface))
-        };
-
+        options: &VideoCaptureSourceDescription,
+        format: VideoFormat,
+        size: Size,
+        consumer: SharedConsumer,
+    ) -> Result<Self, ScreenCaptureError> {
         let mut frame = VideoFrame::default();
-        frame.width = flags.options.size.width;
-        frame.height = flags.options.size.height;
+        frame.width = options.size.width;
+        frame.height = options.size.height;
         frame.format = VideoFormat::NV12;
-        frame.sub_format = if flags.options.hardware {
+        frame.sub_format = if options.hardware {
             VideoSubFormat::D3D11
         } else {
             VideoSubFormat::SW
         };
 
         // Convert texture formats and scale sizes.
-        let mut transform = VideoResampler::new(VideoResamplerOptions {
-            direct3d: flags.options.direct3d,
-            input: Resource::Default(
-                VideoFormat::RGBA,
-                Size {
-                    width: flags.source.width()?,
-                    height: flags.source.height()?,
-                },
-            ),
+        let transform = VideoResampler::new(VideoResamplerOptions {
+            direct3d: options.direct3d.clone(),
+            input: Resource::Default(format, size),
             output: Resource::Default(
                 VideoFormat::NV12,
                 Size {
-                    width: flags.options.size.width,
-                    height: flags.options.size.height,
+                    width: options.size.width,
+                    height: options.size.height,
                 },
             ),
         })?;
 
-        let status_ = Arc::downgrade(&status);
-        thread::Builder::new()
-            .name("WindowsScreenCaptureThread".to_string())
-            .spawn(move || {
-                let thread_class_guard = MediaThreadClass::Capture.join().ok();
-
-                let mut func = || {
-                    loop {
-                        let view = transform.create_input_view(&surface.0, 0)?;
-                        transform.process(Some(view))?;
-
-                        if frame.sub_format == VideoSubFormat::D3D11 {
-                            frame.data[0] = transform.get_output().as_raw();
-                            frame.data[1] = 0 as *const _;
-
-                            if !flags.consumer.sink(&frame) {
-                                break;
-                            }
-                        } else {
-                            let texture = transform.get_output_buffer()?;
-                            frame.data[0] = texture.buffer() as *const _;
-                            frame.data[1] = unsafe {
-                                texture
-                                    .buffer()
-                                    .add(frame.width as usize * frame.height as usize)
-                            } as *const _;
-
-                            frame.linesize[0] = texture.stride();
-                            frame.linesize[1] = texture.stride();
-
-                            if !flags.consumer.sink(&frame) {
-                                break;
-                            }
-                        }
-
-                        thread::sleep(Duration::from_millis(1000 / flags.options.fps as u64));
-                    }
-
-                    Ok::<_, ScreenCaptureError>(())
-                };
-
-                if let Err(e) = func() {
-                    log::error!("WindowsScreenCaptureThread stop, error={:?}", e);
-                } else {
-                    log::info!("WindowsScreenCaptureThread stop");
-                }
+        Ok(Self {
+            protected: None,
+            transform,
+            consumer,
+            frame,
+        })
+    }
 
-                if let Some(status) = status_.upgrade() {
-                    status.store(false, Ordering::Relaxed);
-                }
+    /// Reports the hidden content to the consumer when it changes.
+    fn protected(&mut self, content: Option<ProtectedContent>) {
+        if self.protected == content {
+            return;
+        }
 
-                if let Some(guard) = thread_class_guard {
-                    drop(guard)
-                }
-            })?;
+        tracing::info!(
+            "screen capture protected content changed, content={:?}",
+            content
+        );
 
-        Ok(Self {
-            device_context,
-            status,
-            texture,
-        })
+        self.protected = content;
+        self.consumer.lock().protected(content);
     }
 
-    fn on_frame_arrived(
+    /// Convert the input of the resampler, if the view is not set, the
+    /// internal input texture is used, returns false if the consumer stops
+    /// the capture.
+    fn push(
         &mut self,
-        frame: &mut Frame,
-        control: InternalCaptureControl,
-    ) -> Result<(), Self::Error> {
-        if self.status.load(Ordering::Relaxed) {
-            // Updates the texture in the frame to the middle texture.
-            unsafe {
-                self.device_context
-                    .CopyResource(&self.texture, frame.as_raw_texture());
-            }
+        view: Option<ID3D11VideoProcessorInputView>,
+        damage: &Damage,
+    ) -> Result<bool, ScreenCaptureError> {
+        // The consumer runs in this span, so the conversion and the rest of the
+        // pipeline of the frame are in one span of the trace.
+        let _span = tracing::trace_span!("screen_capture").entered();
+
+        self.frame.timestamp = clock::now();
+        self.transform.process(view)?;
+
+        if self.frame.sub_format == VideoSubFormat::D3D11 {
+            self.frame.data[0] = self.transform.get_output().as_raw();
+            self.frame.data[1] = 0 as *const _;
+
+            let mut consumer = self.consumer.lock();
+            consumer.damage(damage);
+
+            Ok(consumer.sink(&self.frame))
         } else {
-            log::info!("windows screen capture control stop");
+            let texture = self.transform.get_output_buffer()?;
+            self.frame.data[0] = texture.buffer() as *const _;
+            self.frame.data[1] = unsafe {
+                texture
+                    .buffer()
+                    .add(self.frame.width as usize * self.frame.height as usize)
+            } as *const _;
+
+            self.frame.linesize[0] = texture.stride();
+            self.frame.linesize[1] = texture.stride();
+
+            // The buffer is only mapped while the texture buffer is alive.
+            let mut consumer = self.consumer.lock();
+            consumer.damage(damage);
+
+            Ok(consumer.sink(&self.frame))
+        }
+    }
+}
+
+/// Moves the capture to the next backends when the running backend fails after
+/// it has started, the failures of WGC are only reported on its own threads
+/// after `start` has returned.
+#[derive(Clone)]
+struct Fallback {
+    backend: Weak<Mutex<Option<Backend>>>,
+    generation: Arc<AtomicU64>,
+    current: u64,
+    backends: Vec<ScreenCaptureBackend>,
+    options: VideoCaptureSourceDescription,
+    consumer: SharedConsumer,
+}
 
-            control.stop();
+impl Fallback {
+    fn fail(&self, e: &ScreenCaptureError) {
+        tracing::warn!("screen capture backend failed after start, err={:?}", e);
+
+        // The failure is reported on the threads of the backend, which are joined
+        // when the backend is stopped.
+        let this = self.clone();
+        if let Err(e) = thread::Builder::new()
+            .name("WindowsScreenCaptureFallbackThread".to_string())
+            .spawn(move || this.run())
+        {
+            tracing::error!(
+                "failed to create screen capture fallback thread, err={:?}",
+                e
+            );
         }
+    }
 
-        Ok(())
+    fn run(self) {
+        let Some(backend) = self.backend.upgrade() else {
+            return;
+        };
+
+        let mut backend = backend.lock();
+
+        // The capture has been stopped or restarted since the failure.
+        if self.generation.load(Ordering::Relaxed) != self.current
+            || !matches!(*backend, Some(Backend::Wgc(_)))
+        {
+            return;
+        }
+
+        if let Some(previous) = backend.take() {
+            let _ = previous.stop();
+        }
+
+        for it in &self.backends {
+            match Backend::start(*it, &self.options, &self.consumer, &self) {
+                Ok(it) => {
+                    tracing::info!("screen capture fell back, backend={:?}", it.kind());
+
+                    backend.replace(it);
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!("screen capture backend={:?} start failed, err={:?}", it, e);
+                }
+            }
+        }
+
+        tracing::error!("screen capture has no backend to fall back to");
     }
+}
 
-    fn on_closed(&mut self) -> Result<(), Self::Error> {
-        self.status.store(false, Ordering::Relaxed);
-        Ok(())
+enum Backend {
+    Wgc(wgc::WgcCapture),
+    Dxgi(dxgi::DxgiCapture),
+    Gdi(gdi::GdiCapture),
+}
+
+impl Backend {
+    fn start(
+        backend: ScreenCaptureBackend,
+        options: &VideoCaptureSourceDescription,
+        consumer: &SharedConsumer,
+        fallback: &Fallback,
+    ) -> Result<Self, ScreenCaptureError> {
+        Ok(match backend {
+            ScreenCaptureBackend::Wgc => {
+                Self::Wgc(wgc::WgcCapture::start(options, consumer, fallback)?)
+            }
+            ScreenCaptureBackend::Dxgi => Self::Dxgi(dxgi::DxgiCapture::start(options, consumer)?),
+            ScreenCaptureBackend::Gdi => Self::Gdi(gdi::GdiCapture::start(options, consumer)?),
+        })
+    }
+
+    fn kind(&self) -> ScreenCaptureBackend {
+        match self {
+            Self::Wgc(_) => ScreenCaptureBackend::Wgc,
+            Self::Dxgi(_) => ScreenCaptureBackend::Dxgi,
+            Self::Gdi(_) => ScreenCaptureBackend::Gdi,
+        }
+    }
+
+    fn stop(&self) -> Result<(), ScreenCaptureError> {
+        match self {
+            Self::Wgc(it) => it.stop(),
+            Self::Dxgi(it) => it.stop(),
+            Self::Gdi(it) => it.stop(),
+        }
     }
 }
 
-struct CaptureContext {
-    consumer: Box<dyn FrameConsumer<Frame = VideoFrame>>,
-    options: VideoCaptureSourceDescription,
-    source: Monitor,
+/// The windows that are excluded from the capture, the display affinity is
+/// a property of the window, so it applies to all backends, and it is reset
+/// when the capture stops.
+struct ExcludedWindows(Vec<u64>);
+
+impl ExcludedWindows {
+    fn new(windows: &[u64]) -> Self {
+        Self(
+            windows
+                .iter()
+                .copied()
+                .filter(|it| {
+                    // Excluding from the capture needs Windows 10 2004, and only works for the
+                    // windows of this process.
+                    Self::set_affinity(*it, WDA_EXCLUDEFROMCAPTURE)
+                        .inspect_err(|e| {
+                            tracing::warn!(
+                                "screen capture exclude window failed, window={}, err={:?}",
+                                it,
+                                e
+                            );
+                        })
+                        .is_ok()
+                })
+                .collect(),
+        )
+    }
+
+    fn set_affinity(
+        window: u64,
+        affinity: WINDOW_DISPLAY_AFFINITY,
+    ) -> Result<(), windows::core::Error> {
+        unsafe { SetWindowDisplayAffinity(HWND(window as usize as *mut _), affinity) }
+    }
+}
+
+impl Drop for ExcludedWindows {
+    fn drop(&mut self) {
+        for window in &self.0 {
+            // The window may have been closed.
+            let _ = Self::set_affinity(*window, WDA_NONE);
+        }
+    }
 }
 
 #[derive(Default)]
-pub struct ScreenCapture(Mutex<Option<CaptureControl<WindowsCapture, ScreenCaptureError>>>);
+pub struct ScreenCapture {
+    backend: Arc<Mutex<Option<Backend>>>,
+    /// Increased on every start and stop, so that a late failure of a previous
+    /// capture does not replace the current backend.
+    generation: Arc<AtomicU64>,
+    excluded: Mutex<Option<ExcludedWindows>>,
+}
 
 impl CaptureHandler for ScreenCapture {
     type Frame = VideoFrame;
@@ -265,46 +384,85 @@ impl CaptureHandler for ScreenCapture {
         Ok(displays)
     }
 
+    // Windows.Graphics.Capture is preferred, but it is unavailable on some systems
+    // (older builds, remote sessions, blocked by policy), then the desktop
+    // duplication is tried, and the gdi is the last resort, which works everywhere
+    // but is slow.
     fn start<S: FrameConsumer<Frame = Self::Frame> + 'static>(
         &self,
         options: Self::CaptureOptions,
         consumer: S,
     ) -> Result<(), Self::Error> {
-        let source = Monitor::enumerate()?
-            .into_iter()
-            .find(|it| it.device_name().ok() == Some(options.source.id.clone()))
-            .ok_or_else(|| ScreenCaptureError::NotFoundScreenSource)?;
-
-        // Start capturing the screen. This runs in a free thread. If it runs in the
-        // current thread, you will encounter problems with Winrt runtime
-        // initialization.
-        if let Some(control) = self.0.lock().replace(
-            WindowsCapture::start_free_threaded(Settings::new(
-                source,
-                CursorCaptureSettings::WithoutCursor,
-                DrawBorderSettings::Default,
-                ColorFormat::Rgba8,
-                CaptureContext {
-                    consumer: Box::new(consumer),
-                    options,
-                    source,
-                },
-            ))
-            .map_err(|e| ScreenCaptureError::StartCaptureError(e.to_string()))?,
-        ) {
-            control
-                .stop()
-                .map_err(|e| ScreenCaptureError::CaptureControlError(e.to_string()))?;
+        let backends: &[ScreenCaptureBackend] = match &options.backend {
+            Some(backend) => std::slice::from_ref(backend),
+            None => &[
+                ScreenCaptureBackend::Wgc,
+                ScreenCaptureBackend::Dxgi,
+                ScreenCaptureBackend::Gdi,
+            ],
+        };
+
+        let consumer: SharedConsumer = Arc::new(Mutex::new(Box::new(consumer)));
+
+        // The previous capture may exclude the same windows, its exclusion is reset
+        // before the windows are excluded again.
+        drop(self.excluded.lock().take());
+        let excluded = ExcludedWindows::new(&options.exclude_windows);
+
+        // The lock is held until the backend is stored, a backend that fails right
+        // after it has started falls back only after that.
+        let mut current = self.backend.lock();
+        let fallback = Fallback {
+            backend: Arc::downgrade(&self.backend),
+            current: self.generation.fetch_add(1, Ordering::Relaxed) + 1,
+            generation: self.generation.clone(),
+            backends: backends
+                .iter()
+                .copied()
+                .skip_while(|it| *it != ScreenCaptureBackend::Wgc)
+                .skip(1)
+                .collect(),
+            options: options.clone(),
+            consumer: consumer.clone(),
+        };
+
+        let mut result = Err(ScreenCaptureError::NotFoundScreenSource);
+        for backend in backends {
+            result = Backend::start(*backend, &options, &consumer, &fallback);
+
+            match &result {
+                Ok(_) => {
+                    tracing::info!("screen capture started, backend={:?}", backend);
+
+                    break;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "screen capture backend={:?} start failed, err={:?}",
+                        backend,
+                        e
+                    );
+                }
+            }
+        }
+
+        let backend = result?;
+        self.excluded.lock().replace(excluded);
+
+        // If there is a previous capture, end it first.
+        if let Some(backend) = current.replace(backend) {
+            backend.stop()?;
         }
 
         Ok(())
     }
 
     fn stop(&self) -> Result<(), Self::Error> {
-        if let Some(control) = self.0.lock().take() {
-            control
-                .stop()
-                .map_err(|e| ScreenCaptureError::CaptureControlError(e.to_string()))?;
+        drop(self.excluded.lock().take());
+
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        if let Some(backend) = self.backend.lock().take() {
+            backend.stop()?;
         }
 
         Ok(())
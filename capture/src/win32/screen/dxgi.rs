@@ -0,0 +1,265 @@
+use super::{FrameOutput, ScreenCaptureError, SharedConsumer, is_secure_desktop};
+use crate::{Damage, DamageRect, ProtectedContent, VideoCaptureSourceDescription};
+
+use std::{
+    mem::size_of,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use common::{Size, frame::VideoFormat, win32::MediaThreadClass};
+use windows::{
+    Win32::{
+        Foundation::RECT,
+        Graphics::{
+            Direct3D11::{ID3D11Device, ID3D11Texture2D},
+            Dxgi::{
+                DXGI_ERROR_ACCESS_LOST, DXGI_ERROR_WAIT_TIMEOUT, DXGI_OUTDUPL_FRAME_INFO,
+                DXGI_OUTDUPL_MOVE_RECT, IDXGIDevice, IDXGIOutput1, IDXGIOutputDuplication,
+                IDXGIResource,
+            },
+        },
+    },
+    core::Interface,
+};
+
+struct Duplication(IDXGIOutputDuplication);
+
+unsafe impl Sync for Duplication {}
+unsafe impl Send for Duplication {}
+
+impl Duplication {
+    /// Find the output of the source on the adapter of the device and create a
+    /// duplication of the output, the size of the output is also returned.
+    fn new(device: &ID3D11Device, id: &str) -> Result<(Self, Size), ScreenCaptureError> {
+        let adapter = unsafe { device.cast::<IDXGIDevice>()?.GetAdapter()? };
+
+        let mut index = 0;
+        while let Ok(output) = unsafe { adapter.EnumOutputs(index) } {
+            index += 1;
+
+            let desc = unsafe { output.GetDesc()? };
+            let name = String::from_utf16_lossy(
+                &desc.DeviceName[..desc
+                    .DeviceName
+                    .iter()
+                    .position(|it| *it == 0)
+                    .unwrap_or(desc.DeviceName.len())],
+            );
+
+            if name != id {
+                continue;
+            }
+
+            let rect = desc.DesktopCoordinates;
+            let duplication = unsafe { output.cast::<IDXGIOutput1>()?.DuplicateOutput(device)? };
+
+            return Ok((
+                Self(duplication),
+                Size {
+                    width: (rect.right - rect.left) as u32,
+                    height: (rect.bottom - rect.top) as u32,
+                },
+            ));
+        }
+
+        Err(ScreenCaptureError::NotFoundScreenSource)
+    }
+
+    /// The changes of the acquired frame, this must be called before the frame
+    /// is released.
+    fn damage(&self, info: &DXGI_OUTDUPL_FRAME_INFO) -> Result<Damage, ScreenCaptureError> {
+        // Only the pointer was updated.
+        if info.LastPresentTime == 0 {
+            return Ok(Damage::Unchanged);
+        }
+
+        let size = info.TotalMetadataBufferSize;
+        if size == 0 {
+            return Ok(Damage::Full);
+        }
+
+        // The moved rectangles are damaged at their destination, the source of the move
+        // is reported as dirty by the duplication.
+        let mut regions = Vec::new();
+        let mut required = 0;
+
+        let mut moves = vec![
+            DXGI_OUTDUPL_MOVE_RECT::default();
+            size as usize / size_of::<DXGI_OUTDUPL_MOVE_RECT>()
+        ];
+        unsafe {
+            self.0
+                .GetFrameMoveRects(size, moves.as_mut_ptr(), &mut required)?;
+        }
+
+        moves.truncate(required as usize / size_of::<DXGI_OUTDUPL_MOVE_RECT>());
+        regions.extend(moves.iter().map(|it| to_damage_rect(&it.DestinationRect)));
+
+        let mut dirty = vec![RECT::default(); size as usize / size_of::<RECT>()];
+        unsafe {
+            self.0
+                .GetFrameDirtyRects(size, dirty.as_mut_ptr(), &mut required)?;
+        }
+
+        dirty.truncate(required as usize / size_of::<RECT>());
+        regions.extend(dirty.iter().map(to_damage_rect));
+
+        Ok(Damage::Regions(regions))
+    }
+}
+
+fn to_damage_rect(rect: &RECT) -> DamageRect {
+    DamageRect {
+        x: rect.left.max(0) as u32,
+        y: rect.top.max(0) as u32,
+        width: (rect.right - rect.left).max(0) as u32,
+        height: (rect.bottom - rect.top).max(0) as u32,
+    }
+}
+
+/// Capture backend based on the DXGI desktop duplication.
+pub struct DxgiCapture(Arc<AtomicBool>);
+
+impl DxgiCapture {
+    pub fn start(
+        options: &VideoCaptureSourceDescription,
+        consumer: &SharedConsumer,
+    ) -> Result<Self, ScreenCaptureError> {
+        let device = options.direct3d.device.clone();
+        let (duplication, size) = Duplication::new(&device, &options.source.id)?;
+        let mut output = FrameOutput::new(options, VideoFormat::BGRA, size, consumer.clone())?;
+
+        let id = options.source.id.clone();
+        let fps = options.fps;
+        let status = Arc::new(AtomicBool::new(true));
+        let status_ = status.clone();
+        thread::Builder::new()
+            .name("WindowsDxgiScreenCaptureThread".to_string())
+            .spawn(move || {
+                let thread_class_guard = MediaThreadClass::Capture.join().ok();
+
+                // The duplication is missing while the secure desktop is shown.
+                let mut duplication = Some(duplication);
+
+                let mut func = || {
+                    while status_.load(Ordering::Relaxed) {
+                        let Some(current) = duplication.as_ref() else {
+                            match Duplication::new(&device, &id) {
+                                Ok((it, _)) => {
+                                    tracing::info!("dxgi desktop duplication recreated");
+
+                                    output.protected(None);
+                                    duplication = Some(it);
+                                }
+                                Err(_) if is_secure_desktop() => {
+                                    // The last picture is repeated until the desktop returns.
+                                    if !output.push(None, &Damage::Unchanged)? {
+                                        break;
+                                    }
+
+                                    thread::sleep(Duration::from_millis(1000 / fps as u64));
+                                }
+                                Err(e) => return Err(e),
+                            }
+
+                            continue;
+                        };
+
+                        let mut info = DXGI_OUTDUPL_FRAME_INFO::default();
+                        let mut resource: Option<IDXGIResource> = None;
+
+                        // The duplication only returns a frame when the desktop is updated, when
+                        // there is no update, the previous frame is pushed again.
+                        let damage = match unsafe {
+                            current.0.AcquireNextFrame(0, &mut info, &mut resource)
+                        } {
+                            Ok(_) => {
+                                let damage = current.damage(&info).unwrap_or_else(|e| {
+                                    tracing::warn!(
+                                        "dxgi failed to get the dirty rects, err={:?}",
+                                        e
+                                    );
+
+                                    Damage::Full
+                                });
+
+                                // The flag is only meaningful for the frames that update the
+                                // desktop image, the protected content is blacked out in them.
+                                if info.LastPresentTime != 0 {
+                                    output.protected(
+                                        info.ProtectedContentMaskedOut
+                                            .as_bool()
+                                            .then_some(ProtectedContent::Masked),
+                                    );
+                                }
+
+                                if let Some(resource) = resource {
+                                    output
+                                        .transform
+                                        .update_input(&resource.cast::<ID3D11Texture2D>()?);
+                                }
+
+                                unsafe {
+                                    current.0.ReleaseFrame()?;
+                                }
+
+                                damage
+                            }
+                            Err(e) if e.code() == DXGI_ERROR_WAIT_TIMEOUT => Damage::Unchanged,
+                            // The duplication is invalidated by mode changes, the secure desktop
+                            // and full screen applications, and needs to be recreated.
+                            Err(e) if e.code() == DXGI_ERROR_ACCESS_LOST => {
+                                tracing::warn!("dxgi desktop duplication access lost, recreate it");
+
+                                duplication = match Duplication::new(&device, &id) {
+                                    Ok((it, _)) => Some(it),
+                                    // The secure desktop can not be duplicated, the duplication
+                                    // is retried until the user returns to the normal desktop.
+                                    Err(_) if is_secure_desktop() => {
+                                        output.protected(Some(ProtectedContent::SecureDesktop));
+
+                                        None
+                                    }
+                                    Err(e) => return Err(e),
+                                };
+
+                                Damage::Full
+                            }
+                            Err(e) => return Err(e.into()),
+                        };
+
+                        if !output.push(None, &damage)? {
+                            break;
+                        }
+
+                        thread::sleep(Duration::from_millis(1000 / fps as u64));
+                    }
+
+                    Ok::<_, ScreenCaptureError>(())
+                };
+
+                if let Err(e) = func() {
+                    tracing::error!("WindowsDxgiScreenCaptureThread stop, error={:?}", e);
+                } else {
+                    tracing::info!("WindowsDxgiScreenCaptureThread stop");
+                }
+
+                if let Some(guard) = thread_class_guard {
+                    drop(guard)
+                }
+            })?;
+
+        Ok(Self(status))
+    }
+
+    pub fn stop(&self) -> Result<(), ScreenCaptureError> {
+        self.0.store(false, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use super::{FrameOutput, ScreenCaptureError, SharedConsumer, is_secure_desktop};
+use crate::{Damage, ProtectedContent, VideoCaptureSourceDescription};
+
+use std::{
+    mem::size_of,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use common::{Size, frame::VideoFormat, win32::MediaThreadClass};
+use windows::{
+    Win32::Graphics::Gdi::{
+        BI_RGB, BITMAPINFO, BITMAPINFOHEADER, BitBlt, CAPTUREBLT, CreateCompatibleBitmap,
+        CreateCompatibleDC, CreateDCW, DIB_RGB_COLORS, DeleteDC, DeleteObject, GetDIBits,
+        GetDeviceCaps, HBITMAP, HDC, HGDIOBJ, HORZRES, SRCCOPY, SelectObject, VERTRES,
+    },
+    core::{HSTRING, PCWSTR, w},
+};
+
+/// The device context of the display and a bitmap of the same size, the
+/// display is copied to the bitmap and then read back to the system memory.
+struct Surface {
+    display: HDC,
+    memory: HDC,
+    bitmap: HBITMAP,
+    previous: HGDIOBJ,
+    size: Size,
+    buffer: Vec<u8>,
+}
+
+unsafe impl Sync for Surface {}
+unsafe impl Send for Surface {}
+
+impl Surface {
+    fn new(id: &str) -> Result<Self, ScreenCaptureError> {
+        let display = unsafe { CreateDCW(w!("DISPLAY"), &HSTRING::from(id), PCWSTR::null(), None) };
+        if display.is_invalid() {
+            return Err(ScreenCaptureError::CreateDeviceContextError);
+        }
+
+        let size = unsafe {
+            Size {
+                width: GetDeviceCaps(display, HORZRES) as u32,
+                height: GetDeviceCaps(display, VERTRES) as u32,
+            }
+        };
+
+        let memory = unsafe { CreateCompatibleDC(display) };
+        if memory.is_invalid() {
+            unsafe {
+                let _ = DeleteDC(display);
+            }
+
+            return Err(ScreenCaptureError::CreateDeviceContextError);
+        }
+
+        let bitmap =
+            unsafe { CreateCompatibleBitmap(display, size.width as i32, size.height as i32) };
+        let previous = unsafe { SelectObject(memory, bitmap) };
+
+        Ok(Self {
+            buffer: vec![0u8; size.width as usize * size.height as usize * 4],
+            display,
+            memory,
+            bitmap,
+            previous,
+            size,
+        })
+    }
+
+    /// Copy the display to the buffer, the pixels are in BGRA.
+    fn capture(&mut self) -> Result<&[u8], ScreenCaptureError> {
+        unsafe {
+            BitBlt(
+                self.memory,
+                0,
+                0,
+                self.size.width as i32,
+                self.size.height as i32,
+                self.display,
+                0,
+                0,
+                SRCCOPY | CAPTUREBLT,
+            )?;
+        }
+
+        let mut info = BITMAPINFO::default();
+        info.bmiHeader.biSize = size_of::<BITMAPINFOHEADER>() as u32;
+        info.bmiHeader.biWidth = self.size.width as i32;
+        // A negative height means that the bitmap is top-down.
+        info.bmiHeader.biHeight = -(self.size.height as i32);
+        info.bmiHeader.biPlanes = 1;
+        info.bmiHeader.biBitCount = 32;
+        info.bmiHeader.biCompression = BI_RGB.0;
+
+        if unsafe {
+            GetDIBits(
+                self.memory,
+                self.bitmap,
+                0,
+                self.size.height,
+                Some(self.buffer.as_mut_ptr() as *mut _),
+                &mut info,
+                DIB_RGB_COLORS,
+            )
+        } == 0
+        {
+            return Err(windows::core::Error::from_win32().into());
+        }
+
+        Ok(&self.buffer)
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        unsafe {
+            SelectObject(self.memory, self.previous);
+
+            let _ = DeleteObject(self.bitmap);
+            let _ = DeleteDC(self.memory);
+            let _ = DeleteDC(self.display);
+        }
+    }
+}
+
+/// Capture backend based on the gdi, this is the slowest backend, but it works
+/// on all systems.
+pub struct GdiCapture(Arc<AtomicBool>);
+
+impl GdiCapture {
+    pub fn start(
+        options: &VideoCaptureSourceDescription,
+        consumer: &SharedConsumer,
+    ) -> Result<Self, ScreenCaptureError> {
+        let mut surface = Surface::new(&options.source.id)?;
+        let mut output =
+            FrameOutput::new(options, VideoFormat::BGRA, surface.size, consumer.clone())?;
+
+        let fps = options.fps;
+        let status = Arc::new(AtomicBool::new(true));
+        let status_ = status.clone();
+        thread::Builder::new()
+            .name("WindowsGdiScreenCaptureThread".to_string())
+            .spawn(move || {
+                let thread_class_guard = MediaThreadClass::Capture.join().ok();
+
+                let mut func = || {
+                    let linesize = [surface.size.width * 4];
+
+                    while status_.load(Ordering::Relaxed) {
+                        // The copy of the display fails while the secure desktop is shown, the
+                        // last picture is repeated until the desktop returns.
+                        let damage = match surface.capture() {
+                            Ok(buffer) => {
+                                output.transform.update_input_from_buffer(
+                                    VideoFormat::BGRA,
+                                    &[buffer],
+                                    &linesize,
+                                )?;
+
+                                output.protected(None);
+
+                                // The gdi copies the whole desktop without knowing what changed.
+                                Damage::Full
+                            }
+                            Err(_) if is_secure_desktop() => {
+                                output.protected(Some(ProtectedContent::SecureDesktop));
+
+                                Damage::Unchanged
+                            }
+                            Err(e) => return Err(e),
+                        };
+
+                        if !output.push(None, &damage)? {
+                            break;
+                        }
+
+                        thread::sleep(Duration::from_millis(1000 / fps as u64));
+                    }
+
+                    Ok::<_, ScreenCaptureError>(())
+                };
+
+                if let Err(e) = func() {
+                    tracing::error!("WindowsGdiScreenCaptureThread stop, error={:?}", e);
+                } else {
+                    tracing::info!("WindowsGdiScreenCaptureThread stop");
+                }
+
+                if let Some(guard) = thread_class_guard {
+                    drop(guard)
+                }
+            })?;
+
+        Ok(Self(status))
+    }
+
+    pub fn stop(&self) -> Result<(), ScreenCaptureError> {
+        self.0.store(false, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+use super::{Fallback, FrameOutput, ScreenCaptureError, SharedConsumer};
+use crate::{Damage, VideoCaptureSourceDescription};
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use common::{
+    Size,
+    frame::VideoFormat,
+    win32::{EasyTexture, MediaThreadClass},
+};
+
+use parking_lot::Mutex;
+use windows::Win32::Graphics::{
+    Direct3D11::{
+        D3D11_RESOURCE_MISC_SHARED, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT, ID3D11DeviceContext,
+        ID3D11Texture2D,
+    },
+    Dxgi::Common::{DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_SAMPLE_DESC},
+};
+
+use windows_capture::{
+    capture::{CaptureControl, Context, GraphicsCaptureApiHandler},
+    frame::Frame,
+    graphics_capture_api::{GraphicsCaptureApi, InternalCaptureControl},
+    monitor::Monitor,
+    settings::{ColorFormat, CursorCaptureSettings, DrawBorderSettings, Settings},
+};
+
+struct Surface(ID3D11Texture2D);
+
+unsafe impl Sync for Surface {}
+unsafe impl Send for Surface {}
+
+struct WindowsCapture {
+    texture: ID3D11Texture2D,
+    device_context: ID3D11DeviceContext,
+    status: Arc<AtomicBool>,
+    /// A frame arrived since the last push, the capture only delivers the
+    /// frames when the monitor is updated. The dirty regions of the frames
+    /// are not exposed by windows-capture, so an update is a full change.
+    updated: Arc<AtomicBool>,
+}
+
+impl WindowsCapture {
+    fn create(
+        Context {
+            flags,
+            device,
+            device_context,
+        }: Context<CaptureContext>,
+    ) -> Result<Self, ScreenCaptureError> {
+        let status: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
+
+        // Because windows-capture and this library implementation use different devices
+        // and contexts, the problem needs to be solved with an intermediate texture,
+        // for which a cross-device shared resource handle is created, then
+        // windows-capture writes the frame to the intermediate texture, and the
+        // following capture thread creates the texture view from this intermediate
+        // texture as well The following capture thread also creates the texture view
+        // from this intermediate texture.
+        let (texture, surface) = {
+            let desc = D3D11_TEXTURE2D_DESC {
+                Width: flags.source.width()?,
+                Height: flags.source.height()?,
+                MipLevels: 1,
+                ArraySize: 1,
+                Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                BindFlags: 0,
+                CPUAccessFlags: 0,
+                Usage: D3D11_USAGE_DEFAULT,
+                MiscFlags: D3D11_RESOURCE_MISC_SHARED.0 as u32,
+            };
+
+            let mut tex = None;
+            unsafe {
+                device.CreateTexture2D(&desc, None, Some(&mut tex))?;
+            }
+
+            let texture = tex.unwrap();
+
+            // Use as input to VideoResampler by sharing resources across devices.
+            let surface = flags
+                .options
+                .direct3d
+                .open_shared_texture(texture.get_shared()?)?;
+
+            (texture, Surface(surface))
+        };
+
+        let mut output = FrameOutput::new(
+            &flags.options,
+            VideoFormat::RGBA,
+            Size {
+                width: flags.source.width()?,
+                height: flags.source.height()?,
+            },
+            flags.consumer,
+        )?;
+
+        let updated = Arc::new(AtomicBool::new(true));
+        let updated_ = updated.clone();
+
+        let fps = flags.options.fps;
+        let fallback = flags.fallback;
+        let status_ = Arc::downgrade(&status);
+        thread::Builder::new()
+            .name("WindowsScreenCaptureThread".to_string())
+            .spawn(move || {
+                let thread_class_guard = MediaThreadClass::Capture.join().ok();
+
+                let mut func = || {
+                    loop {
+                        let damage = if updated_.swap(false, Ordering::Relaxed) {
+                            Damage::Full
+                        } else {
+                            Damage::Unchanged
+                        };
+
+                        let view = output.transform.create_input_view(&surface.0, 0)?;
+                        if !output.push(Some(view), &damage)? {
+                            break;
+                        }
+
+                        thread::sleep(Duration::from_millis(1000 / fps as u64));
+                    }
+
+                    Ok::<_, ScreenCaptureError>(())
+                };
+
+                if let Err(e) = func() {
+                    tracing::error!("WindowsScreenCaptureThread stop, error={:?}", e);
+
+                    fallback.fail(&e);
+                } else {
+                    tracing::info!("WindowsScreenCaptureThread stop");
+                }
+
+                if let Some(status) = status_.upgrade() {
+                    status.store(false, Ordering::Relaxed);
+                }
+
+                if let Some(guard) = thread_class_guard {
+                    drop(guard)
+                }
+            })?;
+
+        Ok(Self {
+            device_context,
+            updated,
+            status,
+            texture,
+        })
+    }
+}
+
+impl GraphicsCaptureApiHandler for WindowsCapture {
+    type Flags = CaptureContext;
+    type Error = ScreenCaptureError;
+
+    fn new(context: Context<Self::Flags>) -> Result<Self, Self::Error> {
+        // The handler is created on the thread of WGC after the capture has started,
+        // so its failure never reaches the caller of start.
+        let fallback = context.flags.fallback.clone();
+        Self::create(context).inspect_err(|e| fallback.fail(e))
+    }
+
+    fn on_frame_arrived(
+        &mut self,
+        frame: &mut Frame,
+        control: InternalCaptureControl,
+    ) -> Result<(), Self::Error> {
+        if self.status.load(Ordering::Relaxed) {
+            // Updates the texture in the frame to the middle texture.
+            unsafe {
+                self.device_context
+                    .CopyResource(&self.texture, frame.as_raw_texture());
+            }
+
+            self.updated.store(true, Ordering::Relaxed);
+        } else {
+            tracing::info!("windows screen capture control stop");
+
+            control.stop();
+        }
+
+        Ok(())
+    }
+
+    fn on_closed(&mut self) -> Result<(), Self::Error> {
+        self.status.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+struct CaptureContext {
+    consumer: SharedConsumer,
+    fallback: Fallback,
+    options: VideoCaptureSourceDescription,
+    source: Monitor,
+}
+
+/// Capture backend based on Windows.Graphics.Capture.
+pub struct WgcCapture(Mutex<Option<CaptureControl<WindowsCapture, ScreenCaptureError>>>);
+
+impl WgcCapture {
+    pub fn start(
+        options: &VideoCaptureSourceDescription,
+        consumer: &SharedConsumer,
+        fallback: &Fallback,
+    ) -> Result<Self, ScreenCaptureError> {
+        if !GraphicsCaptureApi::is_supported().unwrap_or(false) {
+            return Err(ScreenCaptureError::NotSupportedGraphicsCapture);
+        }
+
+        let source = Monitor::enumerate()?
+            .into_iter()
+            .find(|it| it.device_name().ok() == Some(options.source.id.clone()))
+            .ok_or_else(|| ScreenCaptureError::NotFoundScreenSource)?;
+
+        // Start capturing the screen. This runs in a free thread. If it runs in the
+        // current thread, you will encounter problems with Winrt runtime
+        // initialization.
+        Ok(Self(Mutex::new(Some(
+            WindowsCapture::start_free_threaded(Settings::new(
+                source,
+                CursorCaptureSettings::WithoutCursor,
+                DrawBorderSettings::Default,
+                ColorFormat::Rgba8,
+                CaptureContext {
+                    consumer: consumer.clone(),
+                    fallback: fallback.clone(),
+                    options: options.clone(),
+                    source,
+                },
+            ))
+            .map_err(|e| ScreenCaptureError::StartCaptureError(e.to_string()))?,
+        ))))
+    }
+
+    pub fn stop(&self) -> Result<(), ScreenCaptureError> {
+        if let Some(control) = self.0.lock().take() {
+            control
+                .stop()
+                .map_err(|e| ScreenCaptureError::CaptureControlError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
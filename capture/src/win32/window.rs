@@ -0,0 +1,188 @@
+use std::path::Path;
+
+use crate::{ScreenWindows, Source, WindowMatch, WindowRect};
+
+use super::screen::ScreenCaptureError;
+
+use common::Size;
+use windows::{
+    Win32::{
+        Foundation::{BOOL, CloseHandle, HWND, LPARAM, RECT},
+        Graphics::Gdi::{EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFOEXW},
+        System::Threading::{
+            OpenProcess, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+            QueryFullProcessImageNameW,
+        },
+        UI::WindowsAndMessaging::{
+            EnumWindows, GetWindowRect, GetWindowTextW, GetWindowThreadProcessId, IsIconic,
+            IsWindowVisible,
+        },
+    },
+    core::PWSTR,
+};
+
+unsafe extern "system" fn monitor_callback(
+    monitor: HMONITOR,
+    _: HDC,
+    _: *mut RECT,
+    data: LPARAM,
+) -> BOOL {
+    unsafe { &mut *(data.0 as *mut Vec<HMONITOR>) }.push(monitor);
+
+    true.into()
+}
+
+unsafe extern "system" fn window_callback(window: HWND, data: LPARAM) -> BOOL {
+    if unsafe { IsWindowVisible(window) }.as_bool() && !unsafe { IsIconic(window) }.as_bool() {
+        unsafe { &mut *(data.0 as *mut Vec<HWND>) }.push(window);
+    }
+
+    true.into()
+}
+
+/// The rect of the monitor in the coordinates of the desktop, the id of the
+/// screen source is the device name of the monitor.
+fn monitor_rect(source: &Source) -> Result<RECT, ScreenCaptureError> {
+    let mut monitors: Vec<HMONITOR> = Vec::with_capacity(4);
+    unsafe {
+        EnumDisplayMonitors(
+            HDC::default(),
+            None,
+            Some(monitor_callback),
+            LPARAM(&mut monitors as *mut _ as isize),
+        );
+    }
+
+    for monitor in monitors {
+        let mut info = MONITORINFOEXW::default();
+        info.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as u32;
+
+        if !unsafe { GetMonitorInfoW(monitor, &mut info.monitorInfo) }.as_bool() {
+            continue;
+        }
+
+        if String::from_utf16_lossy(&info.szDevice).trim_end_matches('\0') == source.id {
+            return Ok(info.monitorInfo.rcMonitor);
+        }
+    }
+
+    Err(ScreenCaptureError::NotFoundScreenSource)
+}
+
+fn window_title(window: HWND) -> String {
+    let mut title = [0u16; 256];
+    let size = unsafe { GetWindowTextW(window, &mut title) };
+
+    String::from_utf16_lossy(&title[..size.max(0) as usize])
+}
+
+/// The file name of the executable of the process that owns the window, empty
+/// if the process can not be opened, such as an elevated process.
+fn window_process(window: HWND) -> String {
+    let mut pid = 0;
+    unsafe {
+        GetWindowThreadProcessId(window, Some(&mut pid));
+    }
+
+    let Ok(process) = (unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) })
+    else {
+        return String::new();
+    };
+
+    let mut path = [0u16; 1024];
+    let mut size = path.len() as u32;
+    let result = unsafe {
+        QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(path.as_mut_ptr()),
+            &mut size,
+        )
+    };
+
+    unsafe {
+        let _ = CloseHandle(process);
+    }
+
+    if result.is_err() {
+        return String::new();
+    }
+
+    Path::new(&String::from_utf16_lossy(&path[..size as usize]))
+        .file_name()
+        .map(|it| it.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+pub fn find_windows(
+    source: &Source,
+    matches: &[WindowMatch],
+) -> Result<ScreenWindows, ScreenCaptureError> {
+    let monitor = monitor_rect(source)?;
+    let screen = [
+        monitor.left as i64,
+        monitor.top as i64,
+        monitor.right as i64,
+        monitor.bottom as i64,
+    ];
+
+    let mut windows: Vec<HWND> = Vec::with_capacity(256);
+    unsafe {
+        EnumWindows(
+            Some(window_callback),
+            LPARAM(&mut windows as *mut _ as isize),
+        )?;
+    }
+
+    // The title and the process are only looked up if they are matched, opening
+    // the process of every window is not free.
+    let titles = matches.iter().any(|it| matches!(it, WindowMatch::Title(_)));
+    let processes = matches
+        .iter()
+        .any(|it| matches!(it, WindowMatch::Process(_)));
+
+    let mut rects = Vec::new();
+    for window in windows {
+        let id = window.0 as usize as u64;
+        let title = if titles {
+            window_title(window)
+        } else {
+            String::new()
+        };
+
+        let process = if processes {
+            window_process(window)
+        } else {
+            String::new()
+        };
+
+        if !matches.iter().any(|it| it.is_match(id, &process, &title)) {
+            continue;
+        }
+
+        let mut rect = RECT::default();
+        if unsafe { GetWindowRect(window, &mut rect) }.is_err() {
+            continue;
+        }
+
+        if let Some(rect) = WindowRect::clip(
+            [
+                rect.left as i64,
+                rect.top as i64,
+                rect.right as i64,
+                rect.bottom as i64,
+            ],
+            screen,
+        ) {
+            rects.push(rect);
+        }
+    }
+
+    Ok(ScreenWindows {
+        size: Size {
+            width: (monitor.right - monitor.left) as u32,
+            height: (monitor.bottom - monitor.top) as u32,
+        },
+        rects,
+    })
+}
@@ -1,8 +1,8 @@
 use crate::{AudioEncoderSettings, set_option, set_str_option};
 
-use std::{ffi::c_int, ptr::null_mut};
+use std::{ffi::c_int, mem::zeroed, ptr::null_mut};
 
-use common::{frame::AudioFrame, strings::PSTR};
+use common::{clock, codec::AudioEncoderApplication, frame::AudioFrame, strings::PSTR};
 use ffmpeg::*;
 use thiserror::Error;
 
@@ -32,13 +32,25 @@ pub struct AudioDecoder {
     packet: *mut AVPacket,
     av_frame: *mut AVFrame,
     frame: AudioFrame,
+    toc: Option<u8>,
 }
 
 unsafe impl Sync for AudioDecoder {}
 unsafe impl Send for AudioDecoder {}
 
+/// The default layout of ffmpeg for the channel count, the stream is mono or
+/// stereo, opus only supports more channels with the multistream mapping.
+fn channel_layout(channels: u8) -> AVChannelLayout {
+    unsafe {
+        let mut layout = zeroed::<AVChannelLayout>();
+        av_channel_layout_default(&mut layout, channels.clamp(1, 2) as i32);
+
+        layout
+    }
+}
+
 impl AudioDecoder {
-    pub fn new() -> Result<Self, AudioDecoderError> {
+    pub fn new(channels: u8) -> Result<Self, AudioDecoderError> {
         let codec = unsafe { avcodec_find_decoder_by_name(PSTR::from("libopus").as_ptr()) };
         if codec.is_null() {
             return Err(AudioDecoderError::NotFoundAVCodec);
@@ -50,6 +62,7 @@ impl AudioDecoder {
             packet: null_mut(),
             av_frame: null_mut(),
             frame: AudioFrame::default(),
+            toc: None,
         };
 
         this.context = unsafe { avcodec_alloc_context3(codec) };
@@ -57,20 +70,11 @@ impl AudioDecoder {
             return Err(AudioDecoderError::AllocAVContextError);
         }
 
-        let ch_layout = AVChannelLayout {
-            order: AVChannelOrder::AV_CHANNEL_ORDER_NATIVE,
-            nb_channels: 2,
-            u: AVChannelLayout__bindgen_ty_1 {
-                mask: AV_CH_LAYOUT_STEREO,
-            },
-            opaque: null_mut(),
-        };
-
         let context_mut = unsafe { &mut *this.context };
         context_mut.thread_count = 4;
         context_mut.thread_type = FF_THREAD_SLICE as i32;
         context_mut.request_sample_fmt = AVSampleFormat::AV_SAMPLE_FMT_S16;
-        context_mut.ch_layout = ch_layout;
+        context_mut.ch_layout = channel_layout(channels);
         context_mut.flags |= AV_CODEC_FLAG_LOW_DELAY as i32 | AVFMT_FLAG_NOBUFFER as i32;
         context_mut.flags2 |= AV_CODEC_FLAG2_FAST as i32;
 
@@ -100,11 +104,21 @@ impl AudioDecoder {
         Ok(this)
     }
 
+    /// The maximum number of packets worth concealing for a single loss, after
+    /// that the concealment has faded out to silence anyway.
+    pub const MAX_CONCEALED_PACKETS: u32 = 10;
+
     pub fn decode(&mut self, mut buf: &[u8], pts: u64) -> Result<(), AudioDecoderError> {
         if buf.is_empty() {
             return Ok(());
         }
 
+        let _span = tracing::trace_span!("audio_decode", pts).entered();
+
+        // The configuration of the stream is in the first byte of each packet, it is
+        // needed to conceal the lost packets.
+        self.toc = Some(buf[0]);
+
         let mut size = buf.len();
         while size > 0 {
             let packet = unsafe { &mut *self.packet };
@@ -132,8 +146,11 @@ impl AudioDecoder {
             buf = &buf[len..];
             size -= len;
 
-            // One or more cells have been parsed.
+            // One or more cells have been parsed, the parser keeps the timestamp of the
+            // packet apart from the packet.
             if packet.size > 0 {
+                packet.pts = unsafe { (*self.parser).pts };
+
                 if unsafe { avcodec_send_packet(self.context, self.packet) } != 0 {
                     return Err(AudioDecoderError::SendPacketToAVCodecError);
                 }
@@ -143,6 +160,34 @@ impl AudioDecoder {
         Ok(())
     }
 
+    /// Conceal a lost packet, the frame generated by the concealment is read
+    /// in the same way as the decoded frames.
+    ///
+    /// A packet that only contains the first byte has no frame data, and the
+    /// opus decoder handles it as a missing packet, the decoder extrapolates
+    /// the previous frames to fill the gap.
+    pub fn conceal(&mut self) -> Result<(), AudioDecoderError> {
+        let Some(toc) = self.toc else {
+            return Ok(());
+        };
+
+        // Keep the mode, bandwidth and frame size, and set the frame count code to
+        // a single frame.
+        let buf = [toc & 0xFC];
+
+        // The concealed frame has no capture time.
+        let packet = unsafe { &mut *self.packet };
+        packet.data = buf.as_ptr() as *mut _;
+        packet.size = buf.len() as c_int;
+        packet.pts = 0;
+
+        if unsafe { avcodec_send_packet(self.context, self.packet) } != 0 {
+            return Err(AudioDecoderError::SendPacketToAVCodecError);
+        }
+
+        Ok(())
+    }
+
     pub fn read<'a>(&'a mut self) -> Option<&'a AudioFrame> {
         if !self.av_frame.is_null() {
             unsafe {
@@ -161,9 +206,13 @@ impl AudioDecoder {
 
         let frame = unsafe { &*self.av_frame };
         self.frame.sample_rate = frame.sample_rate as u32;
+        self.frame.channels = frame.ch_layout.nb_channels as u8;
         self.frame.frames = frame.nb_samples as u32;
         self.frame.data = frame.data[0] as *const _;
 
+        // The timestamps of the packets are the capture times of the sender.
+        self.frame.timestamp = frame.pts.max(0) as u64;
+
         Some(&self.frame)
     }
 }
@@ -233,7 +282,7 @@ impl AudioEncoder {
             context: null_mut(),
             packet: null_mut(),
             frame: null_mut(),
-            pts: 0,
+            pts: -1,
         };
 
         this.context = unsafe { avcodec_alloc_context3(codec) };
@@ -242,29 +291,47 @@ impl AudioEncoder {
         }
 
         let context_mut = unsafe { &mut *this.context };
-        let ch_layout = AVChannelLayout {
-            order: AVChannelOrder::AV_CHANNEL_ORDER_NATIVE,
-            nb_channels: 2,
-            u: AVChannelLayout__bindgen_ty_1 {
-                mask: AV_CH_LAYOUT_STEREO,
-            },
-            opaque: null_mut(),
-        };
-
         context_mut.thread_count = 4;
         context_mut.thread_type = FF_THREAD_SLICE as i32;
         context_mut.sample_fmt = AVSampleFormat::AV_SAMPLE_FMT_S16;
-        context_mut.ch_layout = ch_layout;
+        context_mut.ch_layout = channel_layout(options.channels);
         context_mut.flags |= AV_CODEC_FLAG_LOW_DELAY as i32;
         context_mut.flags2 |= AV_CODEC_FLAG2_FAST as i32;
 
         context_mut.bit_rate = options.bit_rate as i64;
         context_mut.sample_rate = options.sample_rate as i32;
-        context_mut.time_base = unsafe { av_make_q(1, options.sample_rate as i32) };
+        // The timestamps are in microseconds, the same as the video, so that the
+        // receiver can line them up.
+        context_mut.time_base = unsafe { av_make_q(1, 1_000_000) };
+
+        set_str_option(
+            context_mut,
+            "frame_duration",
+            &options.frame_duration.unwrap_or(20.0).to_string(),
+        );
+
+        set_str_option(
+            context_mut,
+            "application",
+            &options
+                .application
+                .unwrap_or(AudioEncoderApplication::LowDelay)
+                .to_string(),
+        );
+
+        if let Some(complexity) = options.complexity {
+            context_mut.compression_level = complexity.min(10) as i32;
+        }
 
-        // Forces opus to be encoded in units of 100 milliseconds.
-        set_str_option(context_mut, "frame_duration", "20");
-        set_option(context_mut, "application", 2051);
+        // The encoder only adds the redundant data if it expects packet loss.
+        if options.fec {
+            set_option(context_mut, "fec", 1);
+            set_option(context_mut, "packet_loss", 10);
+        }
+
+        if options.dtx {
+            set_option(context_mut, "dtx", 1);
+        }
 
         if unsafe { avcodec_open2(this.context, codec, null_mut()) } != 0 {
             return Err(AudioEncoderError::OpenAVCodecError);
@@ -287,9 +354,13 @@ impl AudioEncoder {
         Ok(this)
     }
 
+    /// The frame must have the channel count of the encoder.
     pub fn update(&mut self, frame: &AudioFrame) -> bool {
         let av_frame = unsafe { &mut *self.frame };
         let context_ref = unsafe { &*self.context };
+        if frame.channels as i32 != context_ref.ch_layout.nb_channels {
+            return false;
+        }
 
         av_frame.nb_samples = frame.frames as i32;
         av_frame.format = context_ref.sample_fmt as i32;
@@ -311,8 +382,16 @@ impl AudioEncoder {
             );
         }
 
+        // The capture time is the timestamp of the frame, the encoder rejects the
+        // timestamps that do not increase.
+        let timestamp = if frame.timestamp == 0 {
+            clock::now()
+        } else {
+            frame.timestamp
+        };
+
+        self.pts = (timestamp as i64).max(self.pts + 1);
         av_frame.pts = self.pts;
-        self.pts += context_ref.sample_rate as i64 / 10;
 
         true
     }
@@ -329,16 +408,20 @@ impl AudioEncoder {
         Ok(())
     }
 
+    /// Returns a packet with its flags and its timestamp in microseconds of
+    /// [clock].
     pub fn read<'a>(&'a mut self) -> Option<(&'a [u8], i32, u64)> {
         if unsafe { avcodec_receive_packet(self.context, self.packet) } != 0 {
             return None;
         }
 
+        // The first packet starts before the first frame by the padding of the encoder,
+        // its timestamp is unknown if that is before the clock started.
         let packet_ref = unsafe { &*self.packet };
         Some((
             unsafe { std::slice::from_raw_parts(packet_ref.data, packet_ref.size as usize) },
             packet_ref.flags,
-            packet_ref.pts as u64,
+            packet_ref.pts.max(0) as u64,
         ))
     }
 }
@@ -7,12 +7,22 @@ pub use self::{
         create_opus_identification_header,
     },
     video::{
-        CodecError, CodecType, VideoDecoder, VideoDecoderError, VideoEncoder, VideoEncoderError,
+        CodecError, CodecProbe, CodecType, VideoDecoder, VideoDecoderError, VideoEncoder,
+        VideoEncoderError, VideoFrameGuard, probe,
     },
 };
 
+use std::{
+    ffi::{CStr, c_char},
+    ptr::null_mut,
+};
+
 use common::{
-    codec::{VideoDecoderType, VideoEncoderType},
+    codec::{
+        AudioEncoderApplication, ContentHint, VideoDecoderType, VideoEncoderPreset,
+        VideoEncoderProfile, VideoEncoderRateControl, VideoEncoderThreading, VideoEncoderTune,
+        VideoEncoderType,
+    },
     strings::PSTR,
 };
 
@@ -38,6 +48,27 @@ pub struct VideoEncoderSettings {
     pub bit_rate: u64,
     /// the number of pictures in a group of pictures, or 0 for intra_only
     pub key_frame_interval: u32,
+    /// The following options are all optional, the encoder uses its built-in
+    /// low latency configuration for the options that are not set.
+    pub preset: Option<VideoEncoderPreset>,
+    pub tune: Option<VideoEncoderTune>,
+    pub profile: Option<VideoEncoderProfile>,
+    pub rate_control: Option<VideoEncoderRateControl>,
+    /// Number of the threads of the software encoder, the default is 4.
+    pub threads: Option<u32>,
+    /// How the software encoder uses the threads, the default is slice based.
+    pub threading: Option<VideoEncoderThreading>,
+    /// What the video shows, [ContentHint::Auto] is the same as not set, the
+    /// encoder is configured once, the caller detects the content and creates
+    /// the encoder again.
+    pub content_hint: Option<ContentHint>,
+    /// Private options of the encoder, they are applied last and take
+    /// precedence over everything else, for example `("x265-params",
+    /// "keyint=60")`.
+    pub extra_options: Vec<(String, String)>,
+    /// Index of the CUDA device used by the nvidia codecs, the default device
+    /// is used if it is not set.
+    pub cuda_device: Option<u32>,
     #[cfg(target_os = "windows")]
     pub direct3d: Option<Direct3DDevice>,
 }
@@ -50,6 +81,12 @@ pub struct VideoDecoderSettings {
     /// an encoder and a decoder can share the same name). This is
     /// the primary way to find a codec from the user perspective.
     pub codec: VideoDecoderType,
+    /// Index of the CUDA device used by the nvidia codecs, the default device
+    /// is used if it is not set.
+    pub cuda_device: Option<u32>,
+    /// Download the frames of the hardware decoders to the system memory, for
+    /// consumers that cannot access the textures of the gpu.
+    pub software: bool,
     #[cfg(target_os = "windows")]
     pub direct3d: Option<Direct3DDevice>,
 }
@@ -58,6 +95,23 @@ pub struct VideoDecoderSettings {
 pub struct AudioEncoderSettings {
     pub bit_rate: u64,
     pub sample_rate: u64,
+    /// 1 for mono or 2 for stereo, the surround sources are downmixed before
+    /// the encoder.
+    pub channels: u8,
+    /// Intended application of the encoder, the default is low delay.
+    pub application: Option<AudioEncoderApplication>,
+    /// Computational complexity, 0 to 10, a higher value gives better quality
+    /// at the same bitrate but uses more CPU, the default is 10.
+    pub complexity: Option<u8>,
+    /// In-band forward error correction, the next packet carries a low bitrate
+    /// copy of the previous packet, so that the decoder can recover a single
+    /// lost packet.
+    pub fec: bool,
+    /// Discontinuous transmission, almost nothing is sent during silence.
+    pub dtx: bool,
+    /// Duration of a single opus frame in milliseconds, one of 2.5, 5, 10, 20,
+    /// 40 and 60, the default is 20.
+    pub frame_duration: Option<f32>,
 }
 
 mod logger {
@@ -65,7 +119,7 @@ mod logger {
 
     use common::strings::PSTR;
     use ffmpeg::*;
-    use log::Level;
+    use log::{Level, LevelFilter};
 
     #[repr(C)]
     #[derive(Debug)]
@@ -124,6 +178,22 @@ mod logger {
         fn vsnprintf(s: *mut c_char, n: usize, format: *const c_char, args: va_list) -> c_int;
     }
 
+    /// Ffmpeg drops the logs above its own level before they reach the
+    /// callback, so the level follows the level of the `ffmpeg` target of the
+    /// logger.
+    pub fn set_level(level: LevelFilter) {
+        let level = match level {
+            LevelFilter::Off => LoggerLevel::Panic,
+            LevelFilter::Error => LoggerLevel::Error,
+            LevelFilter::Warn => LoggerLevel::Warn,
+            LevelFilter::Info => LoggerLevel::Info,
+            LevelFilter::Debug => LoggerLevel::Debug,
+            LevelFilter::Trace => LoggerLevel::Trace,
+        };
+
+        unsafe { av_log_set_level(level as c_int) }
+    }
+
     #[allow(improper_ctypes_definitions)]
     pub unsafe extern "C" fn logger_proc(
         _: *mut c_void,
@@ -147,6 +217,8 @@ mod logger {
 }
 
 pub fn startup() {
+    common::logger::watch_target_level("ffmpeg", logger::set_level);
+
     unsafe {
         av_log_set_callback(Some(logger::logger_proc));
     }
@@ -158,6 +230,13 @@ pub fn shutdown() {
     }
 }
 
+/// The version of the linked ffmpeg.
+pub fn version() -> String {
+    unsafe { CStr::from_ptr(av_version_info()) }
+        .to_string_lossy()
+        .to_string()
+}
+
 pub(crate) fn set_option(context: &mut AVCodecContext, key: &str, value: i64) {
     unsafe {
         av_opt_set_int(context.priv_data, PSTR::from(key).as_ptr(), value, 0);
@@ -174,3 +253,26 @@ pub(crate) fn set_str_option(context: &mut AVCodecContext, key: &str, value: &st
         );
     }
 }
+
+/// Setting a dictionary option replaces the whole dictionary, so the parameters
+/// are appended to the ones that are already set, such as `x265-params`.
+pub(crate) fn append_dict_option(context: &mut AVCodecContext, key: &str, value: &str) {
+    let mut current = null_mut();
+    let mut params = String::new();
+    if unsafe { av_opt_get(context.priv_data, PSTR::from(key).as_ptr(), 0, &mut current) } >= 0 {
+        if let Ok(it) = PSTR::from(current as *const c_char).to_string() {
+            params = it;
+        }
+
+        unsafe {
+            av_free(current as _);
+        }
+    }
+
+    if !params.is_empty() {
+        params.push(':');
+    }
+
+    params.push_str(value);
+    set_str_option(context, key, &params);
+}
@@ -1,25 +1,44 @@
-use crate::{VideoDecoderSettings, VideoEncoderSettings, set_option, set_str_option};
+use crate::{
+    VideoDecoderSettings, VideoEncoderSettings, append_dict_option, set_option, set_str_option,
+};
 
-use std::{ffi::c_int, ptr::null_mut};
+use std::{
+    ffi::c_int,
+    ops::Deref,
+    ptr::null_mut,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, SyncSender, channel, sync_channel},
+    },
+    thread::{self, JoinHandle},
+};
 
 use common::{
-    codec::{VideoDecoderType, VideoEncoderType},
+    clock,
+    codec::{
+        ContentHint, VideoDecoderType, VideoEncoderPreset, VideoEncoderProfile,
+        VideoEncoderRateControl, VideoEncoderThreading, VideoEncoderTune, VideoEncoderType,
+    },
     frame::{VideoFormat, VideoFrame, VideoSubFormat},
     strings::PSTR,
 };
 
 use ffmpeg::*;
+use parking_lot::Mutex;
 use thiserror::Error;
 
-#[cfg(any(target_os = "windows", target_os = "macos"))]
 use common::Size;
 
 #[cfg(target_os = "windows")]
 use common::win32::{Direct3DDevice, windows::core::Interface};
 
-#[cfg(target_os = "macos")]
+#[cfg(target_vendor = "apple")]
 use common::macos::get_pixel_buffer_format;
 
+#[cfg(target_os = "linux")]
+use common::linux::{DRM_FORMAT_NV12, DmaBufDescriptor, DmaBufPlane};
+
 #[derive(Error, Debug)]
 pub enum VideoDecoderError {
     #[error(transparent)]
@@ -42,12 +61,51 @@ pub enum VideoDecoderError {
     AllocAVFrameError,
 }
 
+/// A decoded frame that is kept alive after the decoder moved on, see
+/// `VideoDecoder::retain`, the memory of the frame is released to the decoder
+/// when the guard is dropped.
+pub struct VideoFrameGuard {
+    frames: Vec<*mut AVFrame>,
+    frame: VideoFrame,
+    #[cfg(target_os = "linux")]
+    dma_buf: Box<DmaBufDescriptor>,
+}
+
+unsafe impl Sync for VideoFrameGuard {}
+unsafe impl Send for VideoFrameGuard {}
+
+impl Deref for VideoFrameGuard {
+    type Target = VideoFrame;
+
+    fn deref(&self) -> &Self::Target {
+        &self.frame
+    }
+}
+
+impl Drop for VideoFrameGuard {
+    fn drop(&mut self) {
+        for frame in self.frames.iter_mut() {
+            unsafe {
+                av_frame_free(frame);
+            }
+        }
+    }
+}
+
 pub struct VideoDecoder {
     context: *mut AVCodecContext,
     parser: *mut AVCodecParserContext,
     packet: *mut AVPacket,
     av_frame: *mut AVFrame,
+    sw_frame: *mut AVFrame,
     frame: VideoFrame,
+    software: bool,
+    /// Whether the frame of the last read is valid.
+    ready: bool,
+    #[cfg(target_os = "linux")]
+    drm_frame: *mut AVFrame,
+    #[cfg(target_os = "linux")]
+    dma_buf: DmaBufDescriptor,
 }
 
 unsafe impl Sync for VideoDecoder {}
@@ -64,7 +122,21 @@ impl VideoDecoder {
             parser: null_mut(),
             packet: null_mut(),
             av_frame: null_mut(),
+            sw_frame: null_mut(),
             frame: VideoFrame::default(),
+            software: options.software,
+            ready: false,
+            #[cfg(target_os = "linux")]
+            drm_frame: null_mut(),
+            #[cfg(target_os = "linux")]
+            dma_buf: DmaBufDescriptor {
+                format: 0,
+                modifier: 0,
+                fd: -1,
+                size: 0,
+                planes: [DmaBufPlane::default(); 4],
+                planes_count: 0,
+            },
         };
 
         #[cfg(target_os = "windows")]
@@ -72,14 +144,17 @@ impl VideoDecoder {
             &mut this.context,
             CodecType::from(options.codec),
             None,
+            options.cuda_device,
             options.direct3d,
         )?;
 
-        #[cfg(target_os = "linux")]
-        let codec = create_video_context(&mut this.context, CodecType::from(options.codec))?;
-
-        #[cfg(target_os = "macos")]
-        let codec = create_video_context(&mut this.context, CodecType::from(options.codec), None)?;
+        #[cfg(not(target_os = "windows"))]
+        let codec = create_video_context(
+            &mut this.context,
+            CodecType::from(options.codec),
+            None,
+            options.cuda_device,
+        )?;
 
         let context_mut = unsafe { &mut *this.context };
         context_mut.delay = 0;
@@ -125,6 +200,8 @@ impl VideoDecoder {
             return Ok(());
         }
 
+        let _span = tracing::trace_span!("video_decode", pts).entered();
+
         let mut size = buf.len();
         while size > 0 {
             let packet = unsafe { &mut *self.packet };
@@ -152,8 +229,11 @@ impl VideoDecoder {
             buf = &buf[len..];
             size -= len;
 
-            // One or more cells have been parsed.
+            // One or more cells have been parsed, the parser keeps the timestamp of the
+            // packet apart from the packet.
             if packet.size > 0 {
+                packet.pts = unsafe { (*self.parser).pts };
+
                 if unsafe { avcodec_send_packet(self.context, self.packet) } != 0 {
                     return Err(VideoDecoderError::SendPacketToAVCodecError);
                 }
@@ -164,16 +244,36 @@ impl VideoDecoder {
     }
 
     pub fn read<'a>(&'a mut self) -> Option<&'a VideoFrame> {
-        // When decoding, each video frame uses a newly created one.
-        if !self.av_frame.is_null() {
+        // The frame is received and transferred to the system memory or mapped here.
+        let _span = tracing::trace_span!("video_receive").entered();
+
+        self.ready = false;
+
+        // The frames are allocated once and only unreferenced before each read, the
+        // memory of the frames goes back to the pool of the decoder, unless it is
+        // still held by a `VideoFrameGuard`.
+        if self.av_frame.is_null() {
+            self.av_frame = unsafe { av_frame_alloc() };
+            if self.av_frame.is_null() {
+                return None;
+            }
+        } else {
             unsafe {
-                av_frame_free(&mut self.av_frame);
+                av_frame_unref(self.av_frame);
             }
         }
 
-        self.av_frame = unsafe { av_frame_alloc() };
-        if self.av_frame.is_null() {
-            return None;
+        if !self.sw_frame.is_null() {
+            unsafe {
+                av_frame_unref(self.sw_frame);
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if !self.drm_frame.is_null() {
+            unsafe {
+                av_frame_unref(self.drm_frame);
+            }
         }
 
         if unsafe { avcodec_receive_frame(self.context, self.av_frame) } != 0 {
@@ -184,6 +284,15 @@ impl VideoDecoder {
         self.frame.width = frame.width as u32;
         self.frame.height = frame.height as u32;
 
+        // The timestamps of the packets are the capture times of the sender.
+        self.frame.timestamp = frame.pts.max(0) as u64;
+
+        if self.software && !frame.hw_frames_ctx.is_null() {
+            self.ready = self.transfer();
+
+            return if self.ready { Some(&self.frame) } else { None };
+        }
+
         let format = unsafe { std::mem::transmute::<_, AVPixelFormat>(frame.format) };
         match format {
             // mfxFrameSurface1.Data.MemId contains a pointer to the mfxHDLPair structure
@@ -228,18 +337,189 @@ impl VideoDecoder {
                 self.frame.sub_format = VideoSubFormat::SW;
                 self.frame.format = VideoFormat::I420;
             }
-            #[cfg(target_os = "macos")]
+            #[cfg(target_vendor = "apple")]
             AVPixelFormat::AV_PIX_FMT_VIDEOTOOLBOX => {
                 self.frame.data[0] = frame.data[3] as _;
 
                 self.frame.sub_format = VideoSubFormat::CvPixelBufferRef;
                 self.frame.format = get_pixel_buffer_format(frame.data[3] as _);
             }
+            // The vaapi surface is exported as a drm prime object, the renderer imports
+            // the dma-buf directly, so the frame never leaves the gpu.
+            #[cfg(target_os = "linux")]
+            AVPixelFormat::AV_PIX_FMT_VAAPI => {
+                if !self.map_dma_buf() {
+                    return None;
+                }
+
+                self.frame.data[0] = &self.dma_buf as *const _ as *const _;
+
+                self.frame.sub_format = VideoSubFormat::DmaBuf;
+                self.frame.format = VideoFormat::NV12;
+            }
+            // The renderer cannot import cuda memory, so the frame is downloaded to the
+            // system memory.
+            #[cfg(not(target_vendor = "apple"))]
+            AVPixelFormat::AV_PIX_FMT_CUDA => {
+                if !self.transfer() {
+                    return None;
+                }
+            }
             _ => unimplemented!("unsupported video frame format = {:?}", format),
         };
 
+        self.ready = true;
         Some(&self.frame)
     }
+
+    /// Keep the frame returned by the last `read` beyond the next `read`,
+    /// without copying the frame, such as to pass it to another thread.
+    ///
+    /// The decoder cannot reuse the memory of the frame until the guard is
+    /// dropped, and the hardware decoders only have a few surfaces, so drop
+    /// the guards as soon as possible.
+    pub fn retain(&self) -> Option<VideoFrameGuard> {
+        if !self.ready {
+            return None;
+        }
+
+        let frames = [
+            self.av_frame,
+            self.sw_frame,
+            #[cfg(target_os = "linux")]
+            self.drm_frame,
+        ];
+
+        let mut guard = VideoFrameGuard {
+            frames: Vec::with_capacity(frames.len()),
+            frame: VideoFrame {
+                format: self.frame.format,
+                sub_format: self.frame.sub_format,
+                width: self.frame.width,
+                height: self.frame.height,
+                data: self.frame.data,
+                linesize: self.frame.linesize,
+                timestamp: self.frame.timestamp,
+            },
+            #[cfg(target_os = "linux")]
+            dma_buf: Box::new(self.dma_buf),
+        };
+
+        for frame in frames {
+            if frame.is_null() || unsafe { &*frame }.buf[0].is_null() {
+                continue;
+            }
+
+            let frame = unsafe { av_frame_clone(frame) };
+            if frame.is_null() {
+                return None;
+            }
+
+            guard.frames.push(frame);
+        }
+
+        // The descriptor of the decoder is overwritten by the next read.
+        #[cfg(target_os = "linux")]
+        if guard.frame.sub_format == VideoSubFormat::DmaBuf {
+            guard.frame.data[0] = guard.dma_buf.as_ref() as *const _ as *const _;
+        }
+
+        Some(guard)
+    }
+
+    /// Download the hardware frame to the system memory, the layout of the
+    /// downloaded frame is chosen by the driver, which is NV12 for all the
+    /// supported decoders.
+    fn transfer(&mut self) -> bool {
+        if self.sw_frame.is_null() {
+            self.sw_frame = unsafe { av_frame_alloc() };
+            if self.sw_frame.is_null() {
+                return false;
+            }
+        }
+
+        if unsafe { av_hwframe_transfer_data(self.sw_frame, self.av_frame, 0) } != 0 {
+            return false;
+        }
+
+        let sw_frame = unsafe { &*self.sw_frame };
+        let format = unsafe { std::mem::transmute::<_, AVPixelFormat>(sw_frame.format) };
+        if format != AVPixelFormat::AV_PIX_FMT_NV12 {
+            tracing::error!("unsupported downloaded video frame format = {:?}", format);
+
+            return false;
+        }
+
+        for i in 0..2 {
+            self.frame.data[i] = sw_frame.data[i] as *const _;
+            self.frame.linesize[i] = sw_frame.linesize[i] as u32;
+        }
+
+        self.frame.sub_format = VideoSubFormat::SW;
+        self.frame.format = VideoFormat::NV12;
+
+        true
+    }
+
+    #[cfg(target_os = "linux")]
+    fn map_dma_buf(&mut self) -> bool {
+        if self.drm_frame.is_null() {
+            self.drm_frame = unsafe { av_frame_alloc() };
+            if self.drm_frame.is_null() {
+                return false;
+            }
+        }
+
+        unsafe { &mut *self.drm_frame }.format = AVPixelFormat::AV_PIX_FMT_DRM_PRIME as i32;
+        if unsafe { av_hwframe_map(self.drm_frame, self.av_frame, AV_HWFRAME_MAP_READ as i32) } != 0
+        {
+            tracing::error!("failed to map vaapi surface to drm prime frame");
+
+            return false;
+        }
+
+        let descriptor = unsafe { &*({ &*self.drm_frame }.data[0] as *const AVDRMFrameDescriptor) };
+
+        // Only the frames where all planes live in the same object can be described
+        // to the renderer.
+        if descriptor.nb_objects != 1 {
+            tracing::warn!(
+                "drm prime frame has multiple objects, count={}",
+                descriptor.nb_objects
+            );
+
+            return false;
+        }
+
+        let mut count = 0;
+        for layer in &descriptor.layers[..descriptor.nb_layers as usize] {
+            for plane in &layer.planes[..layer.nb_planes as usize] {
+                if count < self.dma_buf.planes.len() {
+                    self.dma_buf.planes[count] = DmaBufPlane {
+                        offset: plane.offset as u32,
+                        pitch: plane.pitch as u32,
+                    };
+
+                    count += 1;
+                }
+            }
+        }
+
+        // vaapi exports each plane as a separate layer (R8 and GR88 for NV12), the
+        // frame as a whole is still NV12.
+        self.dma_buf.format = if descriptor.nb_layers == 1 {
+            descriptor.layers[0].format
+        } else {
+            DRM_FORMAT_NV12
+        };
+
+        self.dma_buf.fd = descriptor.objects[0].fd;
+        self.dma_buf.size = descriptor.objects[0].size as u64;
+        self.dma_buf.modifier = descriptor.objects[0].format_modifier;
+        self.dma_buf.planes_count = count as u32;
+
+        true
+    }
 }
 
 impl Drop for VideoDecoder {
@@ -280,6 +560,19 @@ impl Drop for VideoDecoder {
                 av_frame_free(&mut self.av_frame);
             }
         }
+
+        if !self.sw_frame.is_null() {
+            unsafe {
+                av_frame_free(&mut self.sw_frame);
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if !self.drm_frame.is_null() {
+            unsafe {
+                av_frame_free(&mut self.drm_frame);
+            }
+        }
     }
 }
 
@@ -297,13 +590,23 @@ pub enum VideoEncoderError {
     AllocAVPacketError,
     #[error("send frame to codec failed")]
     EncodeFrameError,
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
 }
 
 pub struct VideoEncoder {
     context: *mut AVCodecContext,
     packet: *mut AVPacket,
     frame: *mut AVFrame,
+    upload_frame: *mut AVFrame,
     initialized: bool,
+    /// The timestamp of the updated frame.
+    timestamp: u64,
+    pts: i64,
+    /// The next frame is encoded as a key frame.
+    key_frame: bool,
+    /// Only for the software encoder with [VideoEncoderThreading::Frame].
+    pipeline: Option<Pipeline>,
 }
 
 unsafe impl Sync for VideoEncoder {}
@@ -319,7 +622,12 @@ impl VideoEncoder {
             context: null_mut(),
             packet: null_mut(),
             frame: null_mut(),
+            upload_frame: null_mut(),
             initialized: false,
+            timestamp: 0,
+            pts: -1,
+            key_frame: false,
+            pipeline: None,
         };
 
         #[cfg(target_os = "windows")]
@@ -330,13 +638,11 @@ impl VideoEncoder {
                 width: options.width,
                 height: options.height,
             }),
+            options.cuda_device,
             options.direct3d,
         )?;
 
-        #[cfg(target_os = "linux")]
-        let codec = create_video_context(&mut this.context, CodecType::from(options.codec))?;
-
-        #[cfg(target_os = "macos")]
+        #[cfg(not(target_os = "windows"))]
         let codec = create_video_context(
             &mut this.context,
             CodecType::from(options.codec),
@@ -344,6 +650,7 @@ impl VideoEncoder {
                 width: options.width,
                 height: options.height,
             }),
+            options.cuda_device,
         )?;
 
         let context_mut = unsafe { &mut *this.context };
@@ -363,9 +670,17 @@ impl VideoEncoder {
         // format.
         if options.codec == VideoEncoderType::Qsv {
             context_mut.pix_fmt = AVPixelFormat::AV_PIX_FMT_QSV;
+        } else if options.codec == VideoEncoderType::Vaapi {
+            context_mut.pix_fmt = AVPixelFormat::AV_PIX_FMT_VAAPI;
+        } else if options.codec == VideoEncoderType::Nvenc && cfg!(target_os = "windows") {
+            // On windows the captured textures are passed to nvenc as d3d11 frames.
+            context_mut.pix_fmt = AVPixelFormat::AV_PIX_FMT_D3D11;
         } else {
-            context_mut.thread_count = 4;
-            context_mut.thread_type = FF_THREAD_SLICE as i32;
+            context_mut.thread_count = options.threads.unwrap_or(4) as i32;
+            context_mut.thread_type = match options.threading {
+                Some(VideoEncoderThreading::Frame) => FF_THREAD_FRAME,
+                _ => FF_THREAD_SLICE,
+            } as i32;
             context_mut.pix_fmt = if options.codec == VideoEncoderType::VideoToolBox {
                 AVPixelFormat::AV_PIX_FMT_VIDEOTOOLBOX
             } else {
@@ -385,13 +700,19 @@ impl VideoEncoder {
         context_mut.rc_buffer_size = bit_rate as i32;
         context_mut.bit_rate_tolerance = (bit_rate / 10) as i32;
         context_mut.rc_initial_buffer_occupancy = (bit_rate * 3 / 4) as i32;
+        // The timestamps are in microseconds, the frame rate is only the nominal rate
+        // for the rate control, the frames may come at any interval.
         context_mut.framerate = unsafe { av_make_q(options.frame_rate as i32, 1) };
-        context_mut.time_base = unsafe { av_make_q(1, options.frame_rate as i32) };
-        context_mut.pkt_timebase = unsafe { av_make_q(1, options.frame_rate as i32) };
+        context_mut.time_base = unsafe { av_make_q(1, 1_000_000) };
+        context_mut.pkt_timebase = unsafe { av_make_q(1, 1_000_000) };
         context_mut.gop_size = options.frame_rate as i32 / 2;
         context_mut.height = options.height as i32;
         context_mut.width = options.width as i32;
 
+        // The requested key frames are IDR frames, so that a receiver can start from
+        // them, the encoders without the option ignore it.
+        set_option(context_mut, "forced-idr", 1);
+
         match options.codec {
             VideoEncoderType::X265 => {
                 set_str_option(context_mut, "preset", "superfast");
@@ -411,8 +732,83 @@ impl VideoEncoder {
                 set_option(context_mut, "realtime", 1);
                 set_option(context_mut, "coder", 1); // vlc
             }
+            VideoEncoderType::Vaapi => {
+                set_option(context_mut, "async_depth", 1);
+                set_str_option(context_mut, "rc_mode", "CBR");
+            }
+            VideoEncoderType::Nvenc => {
+                set_str_option(context_mut, "preset", "p1");
+                set_str_option(context_mut, "tune", "ull");
+                set_str_option(context_mut, "rc", "cbr");
+                set_option(context_mut, "zerolatency", 1);
+                set_option(context_mut, "delay", 0);
+
+                if let Some(device) = options.cuda_device {
+                    set_option(context_mut, "gpu", device as i64);
+                }
+            }
         };
 
+        if let Some(hint) = options.content_hint {
+            set_content_hint(context_mut, options.codec, options.frame_rate, hint);
+        }
+
+        // The user configuration overrides the built-in configuration above.
+        if let Some(preset) = options.preset {
+            if let Some(preset) = preset_name(options.codec, preset) {
+                set_str_option(context_mut, "preset", preset);
+            } else {
+                tracing::warn!("encoder not supports preset, codec={:?}", options.codec);
+            }
+        }
+
+        if let Some(tune) = options.tune {
+            if let Some(tune) = tune_name(options.codec, tune) {
+                set_str_option(context_mut, "tune", tune);
+            } else {
+                tracing::warn!("encoder not supports tune, codec={:?}", options.codec);
+            }
+        }
+
+        if let Some(profile) = options.profile {
+            context_mut.profile = match profile {
+                VideoEncoderProfile::Main => FF_PROFILE_HEVC_MAIN as i32,
+                VideoEncoderProfile::Main10 => FF_PROFILE_HEVC_MAIN_10 as i32,
+            };
+
+            // libx265 does not read the profile from the codec context.
+            if options.codec == VideoEncoderType::X265 {
+                set_str_option(context_mut, "profile", &profile.to_string());
+            }
+        }
+
+        if let Some(rate_control) = options.rate_control {
+            set_rate_control(context_mut, options.codec, rate_control);
+        }
+
+        // libx265 ignores the threading of the codec context and uses its own thread
+        // pool, so the threads are passed to it as private parameters.
+        if options.codec == VideoEncoderType::X265
+            && (options.threads.is_some() || options.threading.is_some())
+        {
+            let threads = options.threads.unwrap_or(4);
+            append_dict_option(
+                context_mut,
+                "x265-params",
+                &match options.threading {
+                    Some(VideoEncoderThreading::Frame) => {
+                        // x265 does not accept more than 16 frame threads.
+                        format!("pools={}:frame-threads={}", threads, threads.min(16))
+                    }
+                    _ => format!("pools={}:frame-threads=1:slices={}", threads, threads),
+                },
+            );
+        }
+
+        for (key, value) in &options.extra_options {
+            set_str_option(context_mut, key, value);
+        }
+
         if unsafe { avcodec_open2(this.context, codec, null_mut()) } != 0 {
             return Err(VideoEncoderError::OpenAVCodecError);
         }
@@ -430,10 +826,48 @@ impl VideoEncoder {
         // then reused by replacing the data inside the frame.
         create_video_frame(&mut this.frame, this.context)?;
 
+        // The vaapi surface and the pixel buffer of VideoToolbox cannot be written by
+        // the cpu, the software frames are written to the upload frame first and then
+        // transferred to the surface.
+        if options.codec == VideoEncoderType::Vaapi
+            || options.codec == VideoEncoderType::VideoToolBox
+        {
+            this.upload_frame = unsafe { av_frame_alloc() };
+            if this.upload_frame.is_null() {
+                return Err(VideoEncoderError::CreateVideoFrameError(
+                    CreateVideoFrameError::AllocAVFrameError,
+                ));
+            }
+
+            let frame_mut = unsafe { &mut *this.upload_frame };
+            frame_mut.width = options.width as i32;
+            frame_mut.height = options.height as i32;
+            frame_mut.format = AVPixelFormat::AV_PIX_FMT_NV12 as i32;
+
+            if unsafe { av_frame_get_buffer(this.upload_frame, 0) } != 0 {
+                return Err(VideoEncoderError::CreateVideoFrameError(
+                    CreateVideoFrameError::AllocAVFrameBufferError,
+                ));
+            }
+        }
+
+        if options.threading == Some(VideoEncoderThreading::Frame)
+            && !CodecType::from(options.codec).is_hardware()
+        {
+            this.pipeline = Some(Pipeline::new(this.context)?);
+        }
+
         Ok(this)
     }
 
     pub fn update(&mut self, frame: &VideoFrame) -> bool {
+        self.timestamp = frame.timestamp;
+
+        if let Some(pipeline) = self.pipeline.as_mut() {
+            return pipeline.update(frame);
+        }
+
+        #[allow(unused_variables)]
         let av_frame = unsafe { &mut *self.frame };
         match frame.sub_format {
             // mfxFrameSurface1.Data.MemId contains a pointer to the mfxHDLPair structure
@@ -457,35 +891,47 @@ impl VideoEncoder {
                     hdl.first = frame.data[0] as *mut _;
                     hdl.second = frame.data[1] as *mut _;
                 }
+
+                // The d3d11 frame is the texture and the array index of the texture, nvenc
+                // registers the texture with the texture pointer, so the captured texture
+                // can be passed directly.
+                if av_frame.format == AVPixelFormat::AV_PIX_FMT_D3D11 as i32 {
+                    av_frame.data[0] = frame.data[0] as *mut _;
+                    av_frame.data[1] = frame.data[1] as *mut _;
+                }
             }
-            #[cfg(target_os = "macos")]
+            #[cfg(target_vendor = "apple")]
             VideoSubFormat::CvPixelBufferRef => {
                 av_frame.data[3] = frame.data[0] as _;
             }
             VideoSubFormat::SW => {
-                // Anyway, the hardware encoder has no way to check whether the current frame is
-                // writable.
-                if unsafe { av_frame_make_writable(self.frame) } != 0 {
+                let target = if self.upload_frame.is_null() {
+                    self.frame
+                } else {
+                    self.upload_frame
+                };
+
+                if !copy_frame(target, frame) {
                     return false;
                 }
 
-                // Directly replacing the pointer may cause some problems with pointer access.
-                // Copying data to the frame is the safest way.
-                unsafe {
-                    av_image_copy(
-                        av_frame.data.as_mut_ptr(),
-                        av_frame.linesize.as_mut_ptr(),
-                        frame.data.as_ptr() as _,
-                        [
-                            frame.linesize[0] as i32,
-                            frame.linesize[1] as i32,
-                            frame.linesize[2] as i32,
-                        ]
-                        .as_ptr(),
-                        { &*self.context }.pix_fmt,
-                        av_frame.width,
-                        av_frame.height,
-                    );
+                // The previous surface may still be referenced by the encoder, so each frame
+                // is transferred to a new surface taken from the pool.
+                if !self.upload_frame.is_null() {
+                    unsafe {
+                        av_frame_unref(self.frame);
+                    }
+
+                    let context_ref = unsafe { &*self.context };
+                    if unsafe { av_hwframe_get_buffer(context_ref.hw_frames_ctx, self.frame, 0) }
+                        != 0
+                    {
+                        return false;
+                    }
+
+                    if unsafe { av_hwframe_transfer_data(self.frame, self.upload_frame, 0) } != 0 {
+                        return false;
+                    }
                 }
             }
             #[allow(unreachable_patterns)]
@@ -496,16 +942,38 @@ impl VideoEncoder {
     }
 
     pub fn encode(&mut self) -> Result<(), VideoEncoderError> {
-        let av_frame = unsafe { &mut *self.frame };
-        av_frame.pts = unsafe {
-            let context_ref = &*self.context;
-            av_rescale_q(
-                context_ref.frame_num,
-                context_ref.pkt_timebase,
-                context_ref.time_base,
-            )
+        // The frame that was dropped by the full queue is not encoded.
+        let frame = match self.pipeline.as_ref() {
+            Some(pipeline) => match pipeline.frame() {
+                Some(it) => it,
+                None => return pipeline.check(),
+            },
+            None => self.frame,
         };
 
+        // The capture time is the timestamp of the frame, so that the frames keep their
+        // timing when the frame rate varies, the encoders reject the timestamps that
+        // do not increase.
+        let timestamp = if self.timestamp == 0 {
+            clock::now()
+        } else {
+            self.timestamp
+        };
+
+        self.pts = (timestamp as i64).max(self.pts + 1);
+
+        let av_frame = unsafe { &mut *frame };
+        av_frame.pts = self.pts;
+        av_frame.pict_type = if std::mem::take(&mut self.key_frame) {
+            AVPictureType::AV_PICTURE_TYPE_I
+        } else {
+            AVPictureType::AV_PICTURE_TYPE_NONE
+        };
+
+        if let Some(pipeline) = self.pipeline.as_mut() {
+            return pipeline.push();
+        }
+
         if unsafe { avcodec_send_frame(self.context, self.frame) } != 0 {
             return Err(VideoEncoderError::EncodeFrameError);
         }
@@ -513,6 +981,14 @@ impl VideoEncoder {
         Ok(())
     }
 
+    /// The next encoded frame is a key frame, such as when a receiver starts
+    /// to play the video in the middle.
+    pub fn request_key_frame(&mut self) {
+        self.key_frame = true;
+    }
+
+    /// Returns a packet with its flags and its timestamp in microseconds of
+    /// [clock].
     pub fn read<'a>(&'a mut self) -> Option<(&'a [u8], i32, u64)> {
         let packet_ref = unsafe { &*self.packet };
         let context_ref = unsafe { &*self.context };
@@ -535,6 +1011,10 @@ impl VideoEncoder {
             ));
         }
 
+        if let Some(pipeline) = self.pipeline.as_mut() {
+            return pipeline.read();
+        }
+
         if unsafe { avcodec_receive_packet(self.context, self.packet) } != 0 {
             return None;
         }
@@ -547,14 +1027,20 @@ impl VideoEncoder {
     }
 
     pub fn frame_count(&mut self) -> u64 {
+        let _guard = self.pipeline.as_ref().map(|it| it.lock.lock());
+
         unsafe { &*self.context }.frame_num as u64
     }
 
     pub fn bit_rate(&mut self) -> u64 {
+        let _guard = self.pipeline.as_ref().map(|it| it.lock.lock());
+
         unsafe { &*self.context }.bit_rate as u64
     }
 
     pub fn set_bit_rate(&mut self, bit_rate: u64) {
+        let _guard = self.pipeline.as_ref().map(|it| it.lock.lock());
+
         let context_mut = unsafe { &mut *self.context };
 
         context_mut.bit_rate = bit_rate as i64;
@@ -566,6 +1052,9 @@ impl VideoEncoder {
 
 impl Drop for VideoEncoder {
     fn drop(&mut self) {
+        // The encoding thread uses the context until it is stopped.
+        drop(self.pipeline.take());
+
         if !self.packet.is_null() {
             unsafe {
                 av_packet_free(&mut self.packet);
@@ -596,6 +1085,409 @@ impl Drop for VideoEncoder {
                 av_frame_free(&mut self.frame);
             }
         }
+
+        if !self.upload_frame.is_null() {
+            unsafe {
+                av_frame_free(&mut self.upload_frame);
+            }
+        }
+    }
+}
+
+/// Copies a frame in the system memory to a frame of the encoder.
+fn copy_frame(target: *mut AVFrame, frame: &VideoFrame) -> bool {
+    // Anyway, the hardware encoder has no way to check whether the current frame is
+    // writable.
+    if unsafe { av_frame_make_writable(target) } != 0 {
+        return false;
+    }
+
+    // Directly replacing the pointer may cause some problems with pointer access.
+    // Copying data to the frame is the safest way.
+    let target_mut = unsafe { &mut *target };
+    unsafe {
+        av_image_copy(
+            target_mut.data.as_mut_ptr(),
+            target_mut.linesize.as_mut_ptr(),
+            frame.data.as_ptr() as _,
+            [
+                frame.linesize[0] as i32,
+                frame.linesize[1] as i32,
+                frame.linesize[2] as i32,
+            ]
+            .as_ptr(),
+            std::mem::transmute::<_, AVPixelFormat>(target_mut.format),
+            target_mut.width,
+            target_mut.height,
+        );
+    }
+
+    true
+}
+
+struct FramePtr(*mut AVFrame);
+
+unsafe impl Send for FramePtr {}
+
+struct ContextPtr(*mut AVCodecContext);
+
+unsafe impl Send for ContextPtr {}
+
+struct EncodedPacket {
+    data: Vec<u8>,
+    flags: i32,
+    pts: u64,
+}
+
+/// The frames of the ring, they are freed after the encoding thread is
+/// stopped.
+struct Ring(Vec<*mut AVFrame>);
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        for frame in self.0.iter_mut() {
+            unsafe {
+                av_frame_free(frame);
+            }
+        }
+    }
+}
+
+/// The captured frames are copied into a small ring of frames and encoded on
+/// a thread of its own, so the capture of the next frame overlaps with the
+/// encoding of the previous ones. The capture is never blocked, a frame is
+/// dropped when the whole ring is waiting for the encoder.
+struct Pipeline {
+    /// The encoding thread holds it while it uses the context.
+    lock: Arc<Mutex<()>>,
+    jobs: Option<SyncSender<FramePtr>>,
+    /// The frames that the encoder is done with.
+    free: Receiver<FramePtr>,
+    packets: Receiver<EncodedPacket>,
+    failed: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    /// The frame that is updated and not pushed yet.
+    current: Option<FramePtr>,
+    /// The packet that was read last, the reader borrows it.
+    packet: Option<EncodedPacket>,
+    dropped: u64,
+    // The ring is dropped after the thread is joined in the drop.
+    _ring: Ring,
+}
+
+impl Pipeline {
+    /// The number of the frames in the ring.
+    const DEPTH: usize = 3;
+
+    fn new(context: *mut AVCodecContext) -> Result<Self, VideoEncoderError> {
+        let mut ring = Ring(Vec::with_capacity(Self::DEPTH));
+        for _ in 0..Self::DEPTH {
+            let mut frame = null_mut();
+            let result = create_video_frame(&mut frame, context);
+            if !frame.is_null() {
+                ring.0.push(frame);
+            }
+
+            result?;
+        }
+
+        let (free_tx, free) = channel();
+        for frame in &ring.0 {
+            let _ = free_tx.send(FramePtr(*frame));
+        }
+
+        let (jobs, jobs_rx) = sync_channel::<FramePtr>(Self::DEPTH);
+        let (packets_tx, packets) = channel();
+        let failed = Arc::new(AtomicBool::new(false));
+        let lock = Arc::new(Mutex::new(()));
+
+        let failed_ = failed.clone();
+        let lock_ = lock.clone();
+        let context = ContextPtr(context);
+        let thread = thread::Builder::new()
+            .name("HylaranaVideoEncodeThread".to_string())
+            .spawn(move || {
+                let context = context;
+                let mut packet = unsafe { av_packet_alloc() };
+                if packet.is_null() {
+                    failed_.store(true, Ordering::Relaxed);
+
+                    return;
+                }
+
+                while let Ok(frame) = jobs_rx.recv() {
+                    {
+                        let _guard = lock_.lock();
+
+                        if unsafe { avcodec_send_frame(context.0, frame.0) } != 0 {
+                            failed_.store(true, Ordering::Relaxed);
+
+                            break;
+                        }
+
+                        while unsafe { avcodec_receive_packet(context.0, packet) } == 0 {
+                            let packet_ref = unsafe { &*packet };
+                            let _ = packets_tx.send(EncodedPacket {
+                                data: unsafe {
+                                    std::slice::from_raw_parts(
+                                        packet_ref.data,
+                                        packet_ref.size as usize,
+                                    )
+                                }
+                                .to_vec(),
+                                flags: packet_ref.flags,
+                                pts: packet_ref.pts as u64,
+                            });
+                        }
+                    }
+
+                    // The encoder keeps a reference to the frame if it still needs it, the
+                    // frame is made writable again before it is overwritten.
+                    if free_tx.send(frame).is_err() {
+                        break;
+                    }
+                }
+
+                unsafe {
+                    av_packet_free(&mut packet);
+                }
+
+                tracing::info!("video encoder pipeline thread is closed");
+            })?;
+
+        Ok(Self {
+            thread: Some(thread),
+            jobs: Some(jobs),
+            current: None,
+            packet: None,
+            dropped: 0,
+            _ring: ring,
+            packets,
+            failed,
+            free,
+            lock,
+        })
+    }
+
+    fn update(&mut self, frame: &VideoFrame) -> bool {
+        // The software encoder only takes the frames in the system memory.
+        if frame.sub_format != VideoSubFormat::SW {
+            return false;
+        }
+
+        // A frame that was updated and not pushed is overwritten.
+        let Some(target) = self.current.take().or_else(|| self.free.try_recv().ok()) else {
+            self.dropped += 1;
+
+            tracing::warn!(
+                "video encoder pipeline is full, the frame is dropped, dropped={}",
+                self.dropped
+            );
+
+            return true;
+        };
+
+        let updated = copy_frame(target.0, frame);
+        self.current = Some(target);
+
+        updated
+    }
+
+    fn frame(&self) -> Option<*mut AVFrame> {
+        self.current.as_ref().map(|it| it.0)
+    }
+
+    fn check(&self) -> Result<(), VideoEncoderError> {
+        if self.failed.load(Ordering::Relaxed) {
+            Err(VideoEncoderError::EncodeFrameError)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn push(&mut self) -> Result<(), VideoEncoderError> {
+        self.check()?;
+
+        let (Some(jobs), Some(frame)) = (self.jobs.as_ref(), self.current.take()) else {
+            return Ok(());
+        };
+
+        // The queue is as long as the ring, so this never blocks, it only fails if the
+        // encoding thread is gone.
+        jobs.send(frame)
+            .map_err(|_| VideoEncoderError::EncodeFrameError)
+    }
+
+    fn read<'a>(&'a mut self) -> Option<(&'a [u8], i32, u64)> {
+        let packet = self.packet.insert(self.packets.try_recv().ok()?);
+
+        Some((&packet.data, packet.flags, packet.pts))
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        // The thread ends once the queue is closed, it encodes the frames that are
+        // already queued first.
+        drop(self.jobs.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Maps the preset to the closest preset of the encoder, the hardware encoders
+/// have fewer presets than x265.
+fn preset_name(codec: VideoEncoderType, preset: VideoEncoderPreset) -> Option<&'static str> {
+    Some(match codec {
+        VideoEncoderType::X265 => match preset {
+            VideoEncoderPreset::UltraFast => "ultrafast",
+            VideoEncoderPreset::SuperFast => "superfast",
+            VideoEncoderPreset::VeryFast => "veryfast",
+            VideoEncoderPreset::Faster => "faster",
+            VideoEncoderPreset::Fast => "fast",
+            VideoEncoderPreset::Medium => "medium",
+            VideoEncoderPreset::Slow => "slow",
+            VideoEncoderPreset::Slower => "slower",
+            VideoEncoderPreset::VerySlow => "veryslow",
+            VideoEncoderPreset::Placebo => "placebo",
+        },
+        VideoEncoderType::Qsv => match preset {
+            VideoEncoderPreset::UltraFast
+            | VideoEncoderPreset::SuperFast
+            | VideoEncoderPreset::VeryFast => "veryfast",
+            VideoEncoderPreset::Faster => "faster",
+            VideoEncoderPreset::Fast => "fast",
+            VideoEncoderPreset::Medium => "medium",
+            VideoEncoderPreset::Slow => "slow",
+            VideoEncoderPreset::Slower => "slower",
+            VideoEncoderPreset::VerySlow | VideoEncoderPreset::Placebo => "veryslow",
+        },
+        VideoEncoderType::Nvenc => match preset {
+            VideoEncoderPreset::UltraFast | VideoEncoderPreset::SuperFast => "p1",
+            VideoEncoderPreset::VeryFast => "p2",
+            VideoEncoderPreset::Faster | VideoEncoderPreset::Fast => "p3",
+            VideoEncoderPreset::Medium => "p4",
+            VideoEncoderPreset::Slow => "p5",
+            VideoEncoderPreset::Slower => "p6",
+            VideoEncoderPreset::VerySlow | VideoEncoderPreset::Placebo => "p7",
+        },
+        VideoEncoderType::Vaapi | VideoEncoderType::VideoToolBox => return None,
+    })
+}
+
+fn tune_name(codec: VideoEncoderType, tune: VideoEncoderTune) -> Option<&'static str> {
+    Some(match codec {
+        VideoEncoderType::X265 => match tune {
+            VideoEncoderTune::ZeroLatency => "zerolatency",
+            VideoEncoderTune::FastDecode => "fastdecode",
+            VideoEncoderTune::Animation => "animation",
+            VideoEncoderTune::Grain => "grain",
+            VideoEncoderTune::Psnr => "psnr",
+            VideoEncoderTune::Ssim => "ssim",
+        },
+        VideoEncoderType::Nvenc => match tune {
+            VideoEncoderTune::ZeroLatency => "ull",
+            VideoEncoderTune::FastDecode => "ll",
+            _ => "hq",
+        },
+        VideoEncoderType::Qsv | VideoEncoderType::Vaapi | VideoEncoderType::VideoToolBox => {
+            return None;
+        }
+    })
+}
+
+fn set_content_hint(
+    context: &mut AVCodecContext,
+    codec: VideoEncoderType,
+    frame_rate: u8,
+    hint: ContentHint,
+) {
+    match hint {
+        // A static picture needs few key frames, and raising the lowest quantizer stops
+        // the encoder from spending the bits on refining the blocks that did not change.
+        ContentHint::Detail => {
+            context.gop_size = frame_rate as i32 * 10;
+            context.qmin = DETAIL_QP_MIN;
+            context.qmax = 51;
+
+            match codec {
+                VideoEncoderType::X265 => {
+                    append_dict_option(context, "x265-params", &format!("qpmin={}", DETAIL_QP_MIN))
+                }
+                VideoEncoderType::Nvenc => set_str_option(context, "tune", "ll"),
+                VideoEncoderType::Qsv => set_str_option(context, "scenario", "displayremoting"),
+                _ => (),
+            }
+        }
+        ContentHint::Motion => {
+            context.gop_size = frame_rate as i32 / 2;
+
+            match codec {
+                VideoEncoderType::Nvenc => set_str_option(context, "tune", "ull"),
+                VideoEncoderType::Qsv => set_str_option(context, "scenario", "remotegaming"),
+                _ => (),
+            }
+        }
+        // The content is detected by the caller.
+        ContentHint::Auto => (),
+    }
+}
+
+/// The lowest quantizer of the static content.
+const DETAIL_QP_MIN: i32 = 22;
+
+fn set_rate_control(
+    context: &mut AVCodecContext,
+    codec: VideoEncoderType,
+    rate_control: VideoEncoderRateControl,
+) {
+    match rate_control {
+        // The default configuration of the encoders is already constant bitrate, the
+        // peak bitrate is the same as the average bitrate.
+        VideoEncoderRateControl::Cbr => match codec {
+            VideoEncoderType::X265 => append_dict_option(context, "x265-params", "strict-cbr=1"),
+            VideoEncoderType::Nvenc => set_str_option(context, "rc", "cbr"),
+            VideoEncoderType::Vaapi => set_str_option(context, "rc_mode", "CBR"),
+            VideoEncoderType::VideoToolBox => set_option(context, "constant_bit_rate", 1),
+            VideoEncoderType::Qsv => (),
+        },
+        // The configured bitrate is used as the peak bitrate, and the average bitrate
+        // is lowered so that the encoder has room to follow the content.
+        VideoEncoderRateControl::Vbr => {
+            context.rc_max_rate = context.bit_rate;
+            context.bit_rate = context.bit_rate * 3 / 4;
+
+            match codec {
+                VideoEncoderType::Nvenc => set_str_option(context, "rc", "vbr"),
+                VideoEncoderType::Vaapi => set_str_option(context, "rc_mode", "VBR"),
+                _ => (),
+            }
+        }
+        VideoEncoderRateControl::Cqp(qp) => {
+            context.bit_rate = 0;
+            context.rc_max_rate = 0;
+            context.rc_buffer_size = 0;
+
+            match codec {
+                VideoEncoderType::X265 => {
+                    append_dict_option(context, "x265-params", &format!("qp={}", qp))
+                }
+                VideoEncoderType::Nvenc => {
+                    set_str_option(context, "rc", "constqp");
+                    set_option(context, "qp", qp as i64);
+                }
+                VideoEncoderType::Vaapi => {
+                    set_str_option(context, "rc_mode", "CQP");
+                    context.global_quality = qp as i32;
+                }
+                VideoEncoderType::Qsv | VideoEncoderType::VideoToolBox => {
+                    context.flags |= AV_CODEC_FLAG_QSCALE as i32;
+                    context.global_quality = qp as i32 * FF_QP2LAMBDA as i32;
+                }
+            }
+        }
     }
 }
 
@@ -651,18 +1543,27 @@ impl CodecType {
         match self {
             CodecType::Encoder(kind) => {
                 if cfg!(target_os = "windows") {
-                    *kind != VideoEncoderType::VideoToolBox
+                    *kind == VideoEncoderType::X265
+                        || *kind == VideoEncoderType::Qsv
+                        || *kind == VideoEncoderType::Nvenc
                 } else if cfg!(target_os = "linux") {
                     *kind == VideoEncoderType::X265
+                        || *kind == VideoEncoderType::Vaapi
+                        || *kind == VideoEncoderType::Nvenc
                 } else {
                     *kind == VideoEncoderType::X265 || *kind == VideoEncoderType::VideoToolBox
                 }
             }
             CodecType::Decoder(kind) => {
                 if cfg!(target_os = "windows") {
-                    *kind != VideoDecoderType::VideoToolBox
+                    *kind == VideoDecoderType::HEVC
+                        || *kind == VideoDecoderType::D3D11
+                        || *kind == VideoDecoderType::Qsv
+                        || *kind == VideoDecoderType::Nvdec
                 } else if cfg!(target_os = "linux") {
                     *kind == VideoDecoderType::HEVC
+                        || *kind == VideoDecoderType::Vaapi
+                        || *kind == VideoDecoderType::Nvdec
                 } else {
                     *kind == VideoDecoderType::HEVC || *kind == VideoDecoderType::VideoToolBox
                 }
@@ -698,7 +1599,11 @@ impl CodecType {
                 avcodec_find_encoder_by_name(PSTR::from(kind.to_string()).as_ptr())
             },
             Self::Decoder(kind) => {
-                if *kind == VideoDecoderType::D3D11 || *kind == VideoDecoderType::VideoToolBox {
+                if *kind == VideoDecoderType::D3D11
+                    || *kind == VideoDecoderType::VideoToolBox
+                    || *kind == VideoDecoderType::Vaapi
+                    || *kind == VideoDecoderType::Nvdec
+                {
                     unsafe { avcodec_find_decoder(AVCodecID::AV_CODEC_ID_HEVC) }
                 } else {
                     unsafe { avcodec_find_decoder_by_name(PSTR::from(kind.to_string()).as_ptr()) }
@@ -708,11 +1613,55 @@ impl CodecType {
     }
 }
 
+/// What [probe] found for a video codec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecProbe {
+    pub codec: CodecType,
+    /// The codec is supported on this platform, see [CodecType::is_supported].
+    pub supported: bool,
+    /// The codec is built into the linked ffmpeg, a supported codec that is not
+    /// available fails to open with codec not found.
+    pub available: bool,
+}
+
+/// Checks all the video encoders and decoders against this platform and the
+/// linked ffmpeg, the hardware devices are not opened.
+pub fn probe() -> Vec<CodecProbe> {
+    [
+        VideoEncoderType::X265,
+        VideoEncoderType::Qsv,
+        VideoEncoderType::VideoToolBox,
+        VideoEncoderType::Vaapi,
+        VideoEncoderType::Nvenc,
+    ]
+    .into_iter()
+    .map(CodecType::from)
+    .chain(
+        [
+            VideoDecoderType::HEVC,
+            VideoDecoderType::D3D11,
+            VideoDecoderType::Qsv,
+            VideoDecoderType::VideoToolBox,
+            VideoDecoderType::Vaapi,
+            VideoDecoderType::Nvdec,
+        ]
+        .into_iter()
+        .map(CodecType::from),
+    )
+    .map(|codec| CodecProbe {
+        supported: codec.is_supported(),
+        available: !unsafe { codec.find_av_codec() }.is_null(),
+        codec,
+    })
+    .collect()
+}
+
 #[cfg(target_os = "windows")]
 pub fn create_video_context(
     context: &mut *mut AVCodecContext,
     kind: CodecType,
     size: Option<Size>,
+    cuda_device: Option<u32>,
     direct3d: Option<Direct3DDevice>,
 ) -> Result<*const AVCodec, CreateVideoContextError> {
     // It is not possible to directly find the d3d11va decoder, so special
@@ -728,6 +1677,13 @@ pub fn create_video_context(
         return Err(CreateVideoContextError::AllocAVContextError);
     }
 
+    // nvdec does not use the direct3d device, it decodes through cuda.
+    if kind == CodecType::Decoder(VideoDecoderType::Nvdec) {
+        create_cuda_device(unsafe { &mut **context }, cuda_device)?;
+
+        return Ok(codec);
+    }
+
     // The hardware codec is used, and the hardware context is initialized here for
     // the hardware codec.
     if kind.is_hardware() {
@@ -816,16 +1772,45 @@ pub fn create_video_context(
             unsafe {
                 context_mut.hw_device_ctx = av_buffer_ref(hw_device_ctx);
             }
+
+            // nvenc takes the d3d11 textures directly, it needs the frame context to
+            // know the format and size of the textures.
+            if kind == CodecType::Encoder(VideoEncoderType::Nvenc) {
+                let hw_frames_ctx = unsafe { av_hwframe_ctx_alloc(context_mut.hw_device_ctx) };
+                if hw_frames_ctx.is_null() {
+                    return Err(CreateVideoContextError::AllocAVHardwareFrameContextError);
+                }
+
+                let size = size.expect("encoder needs init hardware frame for size");
+                unsafe {
+                    let frames_ctx = &mut *((&mut *hw_frames_ctx).data as *mut AVHWFramesContext);
+                    frames_ctx.sw_format = AVPixelFormat::AV_PIX_FMT_NV12;
+                    frames_ctx.format = AVPixelFormat::AV_PIX_FMT_D3D11;
+                    frames_ctx.width = size.width as i32;
+                    frames_ctx.height = size.height as i32;
+                    frames_ctx.initial_pool_size = 5;
+                }
+
+                if unsafe { av_hwframe_ctx_init(hw_frames_ctx) } != 0 {
+                    return Err(CreateVideoContextError::InitAVHardwareFrameContextError);
+                }
+
+                unsafe {
+                    context_mut.hw_frames_ctx = av_buffer_ref(hw_frames_ctx);
+                }
+            }
         }
     }
 
     Ok(codec)
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(not(target_os = "windows"))]
 pub fn create_video_context(
     context: &mut *mut AVCodecContext,
     kind: CodecType,
+    size: Option<Size>,
+    #[allow(unused_variables)] cuda_device: Option<u32>,
 ) -> Result<*const AVCodec, CreateVideoContextError> {
     let codec = unsafe { kind.find_av_codec() };
     if codec.is_null() {
@@ -837,31 +1822,41 @@ pub fn create_video_context(
         return Err(CreateVideoContextError::AllocAVContextError);
     }
 
-    Ok(codec)
-}
+    #[cfg(target_os = "linux")]
+    {
+        // nvenc takes the frames from the system memory and manages the cuda device
+        // itself, nvdec decodes through the cuda device.
+        if kind == CodecType::Encoder(VideoEncoderType::Nvenc) {
+            return Ok(codec);
+        }
 
-#[cfg(target_os = "macos")]
-pub fn create_video_context(
-    context: &mut *mut AVCodecContext,
-    kind: CodecType,
-    size: Option<Size>,
-) -> Result<*const AVCodec, CreateVideoContextError> {
-    let codec = unsafe { kind.find_av_codec() };
-    if codec.is_null() {
-        return Err(CreateVideoContextError::NotFoundAVCodec);
-    }
+        if kind == CodecType::Decoder(VideoDecoderType::Nvdec) {
+            create_cuda_device(unsafe { &mut **context }, cuda_device)?;
 
-    *context = unsafe { avcodec_alloc_context3(codec) };
-    if context.is_null() {
-        return Err(CreateVideoContextError::AllocAVContextError);
+            return Ok(codec);
+        }
     }
 
+    // VideoToolbox on apple platforms and vaapi on linux, both of them create their own
+    // default device.
+    let (device_type, format) = if cfg!(target_vendor = "apple") {
+        (
+            AVHWDeviceType::AV_HWDEVICE_TYPE_VIDEOTOOLBOX,
+            AVPixelFormat::AV_PIX_FMT_VIDEOTOOLBOX,
+        )
+    } else {
+        (
+            AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+            AVPixelFormat::AV_PIX_FMT_VAAPI,
+        )
+    };
+
     if kind.is_hardware() {
         let mut hw_device_ctx = std::ptr::null_mut();
         if unsafe {
             av_hwdevice_ctx_create(
                 &mut hw_device_ctx,
-                AVHWDeviceType::AV_HWDEVICE_TYPE_VIDEOTOOLBOX,
+                device_type,
                 std::ptr::null_mut(),
                 std::ptr::null_mut(),
                 0,
@@ -884,7 +1879,7 @@ pub fn create_video_context(
             unsafe {
                 let frames_ctx = &mut *((&mut *hw_frames_ctx).data as *mut AVHWFramesContext);
                 frames_ctx.sw_format = AVPixelFormat::AV_PIX_FMT_NV12;
-                frames_ctx.format = AVPixelFormat::AV_PIX_FMT_VIDEOTOOLBOX;
+                frames_ctx.format = format;
                 frames_ctx.width = size.width as i32;
                 frames_ctx.height = size.height as i32;
                 frames_ctx.initial_pool_size = 5;
@@ -903,6 +1898,37 @@ pub fn create_video_context(
     Ok(codec)
 }
 
+/// Create the cuda device used by nvdec, the device is selected by index, or the
+/// default device if there is no index.
+#[cfg(not(target_vendor = "apple"))]
+fn create_cuda_device(
+    context: &mut AVCodecContext,
+    device: Option<u32>,
+) -> Result<(), CreateVideoContextError> {
+    let device = device.map(|it| PSTR::from(it.to_string()));
+
+    let mut hw_device_ctx = std::ptr::null_mut();
+    if unsafe {
+        av_hwdevice_ctx_create(
+            &mut hw_device_ctx,
+            AVHWDeviceType::AV_HWDEVICE_TYPE_CUDA,
+            device
+                .as_ref()
+                .map(|it| it.as_ptr())
+                .unwrap_or(std::ptr::null()),
+            std::ptr::null_mut(),
+            0,
+        )
+    } != 0
+    {
+        return Err(CreateVideoContextError::InitAVHardwareDeviceContextError);
+    }
+
+    context.hw_device_ctx = unsafe { av_buffer_ref(hw_device_ctx) };
+
+    Ok(())
+}
+
 #[derive(Error, Debug)]
 pub enum CreateVideoFrameError {
     #[error("failed to alloc av frame")]
@@ -0,0 +1,19 @@
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+/// The clock starts when it is first read, the timestamps of the process are
+/// only comparable with each other.
+static EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// The monotonic clock of the media timestamps in microseconds, such as the
+/// capture time of [crate::frame::VideoFrame].
+pub fn now() -> u64 {
+    EPOCH.elapsed().as_micros() as u64
+}
+
+/// The instant of a timestamp of [now].
+pub fn instant(timestamp: u64) -> Instant {
+    *EPOCH + Duration::from_micros(timestamp)
+}
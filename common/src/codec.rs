@@ -6,6 +6,19 @@ use std::{
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "serde")]
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+/// The compression format of the video stream, the receivers pick the
+/// decoder by it. All encoders produce HEVC.
+#[repr(i32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize_repr, Serialize_repr))]
+pub enum VideoCodec {
+    #[default]
+    HEVC,
+}
+
 /// Video decoder type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
@@ -28,6 +41,17 @@ pub enum VideoDecoderType {
     /// VideoToolbox is a low-level framework that provides direct access to
     /// hardware encoders and decoders.
     VideoToolBox,
+    /// [VA-API](https://www.intel.com/content/www/us/en/developer/articles/technical/linuxmedia-vaapi.html)
+    ///
+    /// Video Acceleration API is an open source library and api specification,
+    /// which provides access to graphics hardware acceleration capabilities for
+    /// video processing on Linux.
+    Vaapi,
+    /// [NVDEC](https://developer.nvidia.com/video-codec-sdk)
+    ///
+    /// NVDEC is the dedicated hardware video decoder of NVIDIA GPUs, accessed
+    /// through CUDA.
+    Nvdec,
 }
 
 impl ToString for VideoDecoderType {
@@ -37,6 +61,8 @@ impl ToString for VideoDecoderType {
             Self::D3D11 => "d3d11va",
             Self::Qsv => "hevc_qsv",
             Self::VideoToolBox => "hevc_videotoolbox",
+            Self::Vaapi => "vaapi",
+            Self::Nvdec => "cuda",
         }
         .to_string()
     }
@@ -51,6 +77,8 @@ impl FromStr for VideoDecoderType {
             "d3d11va" => Self::D3D11,
             "hevc_qsv" => Self::Qsv,
             "hevc_videotoolbox" => Self::VideoToolBox,
+            "vaapi" => Self::Vaapi,
+            "cuda" => Self::Nvdec,
             _ => return Err(Error::new(ErrorKind::InvalidInput, value)),
         })
     }
@@ -76,6 +104,17 @@ pub enum VideoEncoderType {
     /// VideoToolbox is a low-level framework that provides direct access to
     /// hardware encoders and decoders.
     VideoToolBox,
+    /// [VA-API](https://www.intel.com/content/www/us/en/developer/articles/technical/linuxmedia-vaapi.html)
+    ///
+    /// Video Acceleration API is an open source library and api specification,
+    /// which provides access to graphics hardware acceleration capabilities for
+    /// video processing on Linux.
+    Vaapi,
+    /// [NVENC](https://developer.nvidia.com/video-codec-sdk)
+    ///
+    /// NVENC is the dedicated hardware video encoder of NVIDIA GPUs, which
+    /// offloads the encoding from the CPU.
+    Nvenc,
 }
 
 impl ToString for VideoEncoderType {
@@ -84,6 +123,8 @@ impl ToString for VideoEncoderType {
             Self::X265 => "libx265",
             Self::Qsv => "hevc_qsv",
             Self::VideoToolBox => "hevc_videotoolbox",
+            Self::Vaapi => "hevc_vaapi",
+            Self::Nvenc => "hevc_nvenc",
         }
         .to_string()
     }
@@ -97,6 +138,252 @@ impl FromStr for VideoEncoderType {
             "libx265" => Self::X265,
             "hevc_qsv" => Self::Qsv,
             "hevc_videotoolbox" => Self::VideoToolBox,
+            "hevc_vaapi" => Self::Vaapi,
+            "hevc_nvenc" => Self::Nvenc,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, value)),
+        })
+    }
+}
+
+/// Encoder speed preset.
+///
+/// A slower preset provides better compression, i.e. better quality at the
+/// same bitrate, at the cost of encoding latency. The presets follow the x265
+/// naming and are mapped to the closest preset of the hardware encoders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum VideoEncoderPreset {
+    UltraFast,
+    SuperFast,
+    VeryFast,
+    Faster,
+    Fast,
+    Medium,
+    Slow,
+    Slower,
+    VerySlow,
+    Placebo,
+}
+
+impl ToString for VideoEncoderPreset {
+    fn to_string(&self) -> String {
+        match self {
+            Self::UltraFast => "ultrafast",
+            Self::SuperFast => "superfast",
+            Self::VeryFast => "veryfast",
+            Self::Faster => "faster",
+            Self::Fast => "fast",
+            Self::Medium => "medium",
+            Self::Slow => "slow",
+            Self::Slower => "slower",
+            Self::VerySlow => "veryslow",
+            Self::Placebo => "placebo",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for VideoEncoderPreset {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "ultrafast" => Self::UltraFast,
+            "superfast" => Self::SuperFast,
+            "veryfast" => Self::VeryFast,
+            "faster" => Self::Faster,
+            "fast" => Self::Fast,
+            "medium" => Self::Medium,
+            "slow" => Self::Slow,
+            "slower" => Self::Slower,
+            "veryslow" => Self::VerySlow,
+            "placebo" => Self::Placebo,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, value)),
+        })
+    }
+}
+
+/// Encoder tuning, optimizes the encoder for a particular type of content or
+/// use case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum VideoEncoderTune {
+    /// Disables the frame lookahead and the frame level threading, every frame
+    /// is output immediately.
+    ZeroLatency,
+    /// Disables the features that are expensive to decode.
+    FastDecode,
+    Animation,
+    Grain,
+    Psnr,
+    Ssim,
+}
+
+impl ToString for VideoEncoderTune {
+    fn to_string(&self) -> String {
+        match self {
+            Self::ZeroLatency => "zerolatency",
+            Self::FastDecode => "fastdecode",
+            Self::Animation => "animation",
+            Self::Grain => "grain",
+            Self::Psnr => "psnr",
+            Self::Ssim => "ssim",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for VideoEncoderTune {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "zerolatency" => Self::ZeroLatency,
+            "fastdecode" => Self::FastDecode,
+            "animation" => Self::Animation,
+            "grain" => Self::Grain,
+            "psnr" => Self::Psnr,
+            "ssim" => Self::Ssim,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, value)),
+        })
+    }
+}
+
+/// HEVC profile of the encoded stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum VideoEncoderProfile {
+    /// 8 bit 4:2:0, supported by every decoder.
+    Main,
+    /// 10 bit 4:2:0, reduces banding, the decoder must support main 10 and the
+    /// encoder must accept 10 bit samples, which is usually only the case for
+    /// the hardware encoders.
+    Main10,
+}
+
+impl ToString for VideoEncoderProfile {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Main => "main",
+            Self::Main10 => "main10",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for VideoEncoderProfile {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "main" => Self::Main,
+            "main10" => Self::Main10,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, value)),
+        })
+    }
+}
+
+/// Encoder rate control mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum VideoEncoderRateControl {
+    /// Constant bitrate, the bitrate is kept close to the target, which is the
+    /// most predictable for the network.
+    Cbr,
+    /// Variable bitrate, the bitrate follows the complexity of the content and
+    /// never exceeds the target.
+    Vbr,
+    /// Constant quantization parameter, the bitrate is not controlled at all,
+    /// the lower the value, the better the quality.
+    Cqp(u8),
+}
+
+/// What the video mostly shows, screen content and video playback need
+/// different encoder settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum ContentHint {
+    /// Text and user interfaces, the picture is mostly static, so the key
+    /// frames are rare and the unchanged blocks are not refined.
+    Detail,
+    /// Video playback and games, the built-in low latency settings.
+    Motion,
+    /// Switches between the two from the differences between the frames.
+    Auto,
+}
+
+impl ToString for ContentHint {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Detail => "detail",
+            Self::Motion => "motion",
+            Self::Auto => "auto",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for ContentHint {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "detail" => Self::Detail,
+            "motion" => Self::Motion,
+            "auto" => Self::Auto,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, value)),
+        })
+    }
+}
+
+/// How the software encoder splits the work between the threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum VideoEncoderThreading {
+    /// Each frame is split into slices that are encoded in parallel, this adds
+    /// no delay but scales poorly beyond a few threads.
+    Slice,
+    /// Several frames are encoded in parallel, and the captured frames are
+    /// copied into a queue in front of the encoder, so that the capture does
+    /// not wait for the encoding. This saturates many-core machines at the cost
+    /// of one frame of delay per extra thread, the frames are dropped when the
+    /// queue is full.
+    Frame,
+}
+
+/// Intended application of the opus encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum AudioEncoderApplication {
+    /// Best for most VoIP/videoconference applications where listening
+    /// quality and intelligibility matter most.
+    Voip,
+    /// Best for broadcast/high-fidelity application where the decoded audio
+    /// should be as close as possible to the input, such as music.
+    Audio,
+    /// Only use when lowest-achievable latency is what matters most.
+    LowDelay,
+}
+
+impl ToString for AudioEncoderApplication {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Voip => "voip",
+            Self::Audio => "audio",
+            Self::LowDelay => "lowdelay",
+        }
+        .to_string()
+    }
+}
+
+impl FromStr for AudioEncoderApplication {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "voip" => Self::Voip,
+            "audio" => Self::Audio,
+            "lowdelay" => Self::LowDelay,
             _ => return Err(Error::new(ErrorKind::InvalidInput, value)),
         })
     }
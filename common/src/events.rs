@@ -0,0 +1,49 @@
+use std::sync::mpsc;
+
+use parking_lot::Mutex;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+enum Subscriber<T> {
+    Channel(mpsc::Sender<T>),
+    Stream(UnboundedSender<T>),
+}
+
+impl<T> Subscriber<T> {
+    fn send(&self, event: T) -> bool {
+        match self {
+            Self::Channel(tx) => tx.send(event).is_ok(),
+            Self::Stream(tx) => tx.send(event).is_ok(),
+        }
+    }
+}
+
+/// Delivers the events to all subscribers, a subscriber is a channel polled
+/// in a message loop or a stream of the runtime. The subscribers whose
+/// receiver is dropped are removed on the next event.
+pub struct Broadcaster<T>(Mutex<Vec<Subscriber<T>>>);
+
+impl<T> Default for Broadcaster<T> {
+    fn default() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+}
+
+impl<T: Clone> Broadcaster<T> {
+    pub fn subscribe(&self) -> mpsc::Receiver<T> {
+        let (tx, rx) = mpsc::channel();
+        self.0.lock().push(Subscriber::Channel(tx));
+
+        rx
+    }
+
+    pub fn subscribe_stream(&self) -> UnboundedReceiver<T> {
+        let (tx, rx) = unbounded_channel();
+        self.0.lock().push(Subscriber::Stream(tx));
+
+        rx
+    }
+
+    pub fn emit(&self, event: T) {
+        self.0.lock().retain(|it| it.send(event.clone()));
+    }
+}
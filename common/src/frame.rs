@@ -51,20 +51,38 @@
 //! JPEG: it has BT.601 matrix derived from System M primaries, yet the
 //! primaries of most images are BT.709.
 
-use std::{ffi::c_void, ptr::null};
+use std::{ffi::c_void, ptr::null, slice::from_raw_parts};
 
 #[cfg(feature = "serde")]
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+use windows::Win32::Graphics::Direct3D11::{D3D11_CPU_ACCESS_READ, D3D11_USAGE_STAGING};
+
+#[cfg(target_os = "windows")]
+use crate::win32::{EasyTexture, TextureBuffer, d3d_texture_borrowed_raw};
+
+#[cfg(target_vendor = "apple")]
+use crate::macos::{CVPixelBufferRef, PixelMomeryBuffer};
+
 /// A sample from the audio stream.
 #[repr(C)]
 #[derive(Debug)]
 pub struct AudioFrame {
     pub sample_rate: u32,
+    /// The number of interleaved channels, in the default channel order of
+    /// ffmpeg for the channel count.
+    pub channels: u8,
     /// The number of samples in the current audio frame.
     pub frames: u32,
     /// Pointer to the sample raw buffer.
     pub data: *const i16,
+    /// The time the first sample was captured in microseconds of
+    /// [crate::clock], 0 if it is unknown, the encoder then uses the time it
+    /// gets the frame. The decoded frames carry the timestamp of the sender.
+    pub timestamp: u64,
 }
 
 unsafe impl Sync for AudioFrame {}
@@ -76,6 +94,8 @@ impl Default for AudioFrame {
             frames: 0,
             data: null(),
             sample_rate: 0,
+            channels: 2,
+            timestamp: 0,
         }
     }
 }
@@ -104,6 +124,9 @@ pub enum VideoSubFormat {
     /// Video frames contain buffers that can be accessed directly through
     /// software.
     SW,
+    /// Inside this video frame is a pointer to the `DmaBufDescriptor`, a type
+    /// exclusive to the Linux platform.
+    DmaBuf,
 }
 
 /// A frame in a video stream.
@@ -117,8 +140,16 @@ pub struct VideoFrame {
     /// If the subformat is SW, the data layout is determined according to the
     /// format and the data corresponds to the plane of the corresponding
     /// format, All other sub formats use `data[0]`.
+    ///
+    /// Prefer [VideoFrame::planes] and [VideoFrame::to_software] over reading
+    /// the pointers directly.
     pub data: [*const c_void; 3],
     pub linesize: [u32; 3],
+    /// The time the frame was captured in microseconds of [crate::clock], 0
+    /// if it is unknown, the encoder then uses the time it gets the frame.
+    /// The decoded frames carry the timestamp of the sender, the receiver
+    /// moves it to its own clock once the clocks are synced.
+    pub timestamp: u64,
 }
 
 unsafe impl Sync for VideoFrame {}
@@ -133,6 +164,268 @@ impl Default for VideoFrame {
             data: [null(), null(), null()],
             format: VideoFormat::RGBA,
             sub_format: VideoSubFormat::SW,
+            timestamp: 0,
+        }
+    }
+}
+
+/// A plane of a frame in the system memory.
+#[derive(Debug, Clone, Copy)]
+pub struct VideoPlane<'a> {
+    /// All rows of the plane, including the padding at the end of each row.
+    pub data: &'a [u8],
+    /// Number of bytes between two consecutive rows, this may be larger than
+    /// `row_size`.
+    pub linesize: usize,
+    /// Number of bytes of the pixels in a row.
+    pub row_size: usize,
+    pub rows: usize,
+}
+
+impl<'a> VideoPlane<'a> {
+    /// The pixels of a row, without the padding.
+    pub fn row(&self, index: usize) -> &'a [u8] {
+        &self.data[index * self.linesize..][..self.row_size]
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SoftwareVideoFrameError {
+    #[error("the frame of this sub format cannot be downloaded, sub format={0:?}")]
+    NotSupportedSubFormat(VideoSubFormat),
+    #[error("the frame has no data")]
+    InvalidFrame,
+    #[cfg(target_os = "windows")]
+    #[error(transparent)]
+    WindowsError(#[from] windows::core::Error),
+}
+
+/// A frame copied to the system memory by [VideoFrame::to_software], the rows
+/// of the planes are tightly packed.
+#[derive(Debug)]
+pub struct SoftwareVideoFrame {
+    frame: VideoFrame,
+    data: Vec<u8>,
+}
+
+impl SoftwareVideoFrame {
+    /// The copied frame, it is always a software frame and is valid as long as
+    /// this is not dropped.
+    pub fn frame(&self) -> &VideoFrame {
+        &self.frame
+    }
+
+    /// All planes one after another.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Copy the planes of a software frame.
+    fn copy(frame: &VideoFrame) -> Self {
+        let planes = frame.planes().collect::<Vec<_>>();
+
+        let mut data = Vec::with_capacity(planes.iter().map(|it| it.row_size * it.rows).sum());
+        let mut offsets = [0; 3];
+        let mut linesize = [0; 3];
+        for (i, plane) in planes.iter().enumerate() {
+            offsets[i] = data.len();
+            linesize[i] = plane.row_size as u32;
+
+            for row in 0..plane.rows {
+                data.extend_from_slice(plane.row(row));
+            }
+        }
+
+        let mut pointers = [null(); 3];
+        for (pointer, offset) in pointers.iter_mut().zip(offsets).take(planes.len()) {
+            *pointer = unsafe { data.as_ptr().add(offset) } as *const c_void;
+        }
+
+        Self {
+            frame: VideoFrame {
+                format: frame.format,
+                sub_format: VideoSubFormat::SW,
+                width: frame.width,
+                height: frame.height,
+                data: pointers,
+                linesize,
+                timestamp: frame.timestamp,
+            },
+            data,
+        }
+    }
+}
+
+impl VideoFrame {
+    pub fn format(&self) -> VideoFormat {
+        self.format
+    }
+
+    /// The frame is a texture or a buffer of the gpu, its planes can only be
+    /// read after [VideoFrame::to_software].
+    pub fn is_hardware(&self) -> bool {
+        self.sub_format != VideoSubFormat::SW
+    }
+
+    /// The number of bytes of the pixels in a row and the number of rows of a
+    /// plane, the chroma planes of odd sizes are rounded up.
+    fn plane_size(&self, index: usize) -> (usize, usize) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+
+        match (self.format, index) {
+            (VideoFormat::BGRA | VideoFormat::RGBA, _) => (width * 4, height),
+            (VideoFormat::NV12 | VideoFormat::I420, 0) => (width, height),
+            (VideoFormat::NV12, _) => (chroma_width * 2, chroma_height),
+            (VideoFormat::I420, _) => (chroma_width, chroma_height),
         }
     }
+
+    fn planes_count(&self) -> usize {
+        if self.is_hardware() {
+            return 0;
+        }
+
+        match self.format {
+            VideoFormat::BGRA | VideoFormat::RGBA => 1,
+            VideoFormat::NV12 => 2,
+            VideoFormat::I420 => 3,
+        }
+    }
+
+    /// The line sizes of the planes of a software frame, empty for a hardware
+    /// frame.
+    pub fn linesize(&self) -> &[u32] {
+        &self.linesize[..self.planes_count()]
+    }
+
+    /// The planes of a software frame in the order of the format, such as Y
+    /// and UV for NV12, nothing for a hardware frame.
+    ///
+    /// The planes borrow the frame, so they cannot outlive the callback that
+    /// the frame is passed to, copy them or use [VideoFrame::to_software] to
+    /// keep them.
+    pub fn planes(&self) -> impl Iterator<Item = VideoPlane<'_>> {
+        (0..self.planes_count())
+            .filter(|i| !self.data[*i].is_null())
+            .map(|i| {
+                let (row_size, rows) = self.plane_size(i);
+                let linesize = (self.linesize[i] as usize).max(row_size);
+
+                // The last row may not have the padding.
+                let len = match rows {
+                    0 => 0,
+                    rows => linesize * (rows - 1) + row_size,
+                };
+
+                VideoPlane {
+                    data: unsafe { from_raw_parts(self.data[i] as *const u8, len) },
+                    linesize,
+                    row_size,
+                    rows,
+                }
+            })
+    }
+
+    /// Copy the frame to the system memory, a hardware frame is downloaded
+    /// from the gpu, which is slow, so this is meant for the sinks that need
+    /// the pixels, such as recording or text recognition.
+    ///
+    /// Direct3D 11 frames are copied with the immediate context of the device
+    /// of the texture, the device must be multithread protected if it is used
+    /// on other threads. Dma-buf frames are not supported.
+    pub fn to_software(&self) -> Result<SoftwareVideoFrame, SoftwareVideoFrameError> {
+        if self.data[0].is_null() {
+            return Err(SoftwareVideoFrameError::InvalidFrame);
+        }
+
+        match self.sub_format {
+            VideoSubFormat::SW => Ok(SoftwareVideoFrame::copy(self)),
+            #[cfg(target_os = "windows")]
+            VideoSubFormat::D3D11 => self.download_texture(),
+            #[cfg(target_vendor = "apple")]
+            VideoSubFormat::CvPixelBufferRef => self.download_pixel_buffer(),
+            sub_format => Err(SoftwareVideoFrameError::NotSupportedSubFormat(sub_format)),
+        }
+    }
+
+    /// The texture is copied to a staging texture and read back, `data[1]` is
+    /// the index of the texture in the texture array of the decoder.
+    #[cfg(target_os = "windows")]
+    fn download_texture(&self) -> Result<SoftwareVideoFrame, SoftwareVideoFrameError> {
+        let texture = d3d_texture_borrowed_raw(&(self.data[0] as *mut _))
+            .ok_or_else(|| SoftwareVideoFrameError::InvalidFrame)?;
+
+        let mut desc = texture.desc();
+        desc.MipLevels = 1;
+        desc.ArraySize = 1;
+        desc.Usage = D3D11_USAGE_STAGING;
+        desc.BindFlags = 0;
+        desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
+        desc.MiscFlags = 0;
+
+        let (device, context) = unsafe {
+            let device = texture.GetDevice()?;
+            let context = device.GetImmediateContext()?;
+
+            (device, context)
+        };
+
+        let mut staging = None;
+        unsafe {
+            device.CreateTexture2D(&desc, None, Some(&mut staging))?;
+        }
+
+        let staging = staging.ok_or_else(|| SoftwareVideoFrameError::InvalidFrame)?;
+        unsafe {
+            context.CopySubresourceRegion(&staging, 0, 0, 0, 0, texture, self.data[1] as u32, None);
+        }
+
+        let buffer = TextureBuffer::new(&context, &staging)?;
+
+        // The textures of the decoder may be larger than the frame, the chroma
+        // plane of NV12 follows the luma plane of the whole texture.
+        let (data, stride) = (buffer.buffer(), buffer.stride());
+        Ok(SoftwareVideoFrame::copy(&VideoFrame {
+            format: self.format,
+            sub_format: VideoSubFormat::SW,
+            width: self.width.min(desc.Width),
+            height: self.height.min(desc.Height),
+            data: [
+                data as *const c_void,
+                unsafe { data.add(stride as usize * desc.Height as usize) } as *const c_void,
+                null(),
+            ],
+            linesize: [stride, stride, 0],
+            timestamp: self.timestamp,
+        }))
+    }
+
+    /// The pixel buffer is locked while it is copied.
+    #[cfg(target_vendor = "apple")]
+    fn download_pixel_buffer(&self) -> Result<SoftwareVideoFrame, SoftwareVideoFrameError> {
+        let buffer = PixelMomeryBuffer::from(self.data[0] as CVPixelBufferRef);
+
+        let mut frame = VideoFrame {
+            format: buffer.format,
+            sub_format: VideoSubFormat::SW,
+            width: buffer.size.width,
+            height: buffer.size.height,
+            timestamp: self.timestamp,
+            ..Default::default()
+        };
+
+        for (i, data) in buffer.data.iter().enumerate() {
+            if !data.is_empty() {
+                frame.data[i] = data.as_ptr() as *const c_void;
+                frame.linesize[i] = buffer.linesize[i] as u32;
+            }
+        }
+
+        if frame.planes().count() != frame.planes_count() {
+            return Err(SoftwareVideoFrameError::InvalidFrame);
+        }
+
+        Ok(SoftwareVideoFrame::copy(&frame))
+    }
 }
@@ -1,15 +1,24 @@
+pub mod clock;
 pub mod codec;
+pub mod events;
 pub mod frame;
 pub mod logger;
+pub mod power;
 pub mod runtime;
 pub mod strings;
 
 #[cfg(target_os = "windows")]
 pub mod win32;
 
-#[cfg(target_os = "macos")]
+// CoreVideo and Metal are the same on macos, ios and tvos, so the module is
+// shared by all apple platforms.
+#[cfg(target_vendor = "apple")]
 pub mod macos;
 
+#[cfg(target_os = "linux")]
+pub mod linux;
+
+use codec::VideoCodec;
 use frame::VideoFormat;
 
 #[cfg(feature = "serde")]
@@ -29,6 +38,13 @@ pub struct MediaVideoStreamDescription {
     pub size: Size,
     pub fps: u8,
     pub bit_rate: u64,
+    /// The number of the lower renditions that are sent next to the video,
+    /// the receivers can switch to them, 0 if the sender has no simulcast.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub simulcast: u8,
+    /// The senders before the codec was described only sent HEVC.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub codec: VideoCodec,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -39,9 +55,58 @@ pub struct MediaAudioStreamDescription {
     pub bit_rate: u64,
 }
 
+/// The tracks of a session, the tracks outside of the mode are neither
+/// captured and encoded by the sender nor decoded and played by the
+/// receiver.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum SessionMode {
+    AudioOnly,
+    VideoOnly,
+    #[default]
+    Both,
+}
+
+impl SessionMode {
+    /// The mode of the given tracks, `None` if there is no track.
+    pub fn from_tracks(video: bool, audio: bool) -> Option<Self> {
+        match (video, audio) {
+            (true, true) => Some(Self::Both),
+            (true, false) => Some(Self::VideoOnly),
+            (false, true) => Some(Self::AudioOnly),
+            (false, false) => None,
+        }
+    }
+
+    pub fn has_video(&self) -> bool {
+        *self != Self::AudioOnly
+    }
+
+    pub fn has_audio(&self) -> bool {
+        *self != Self::VideoOnly
+    }
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct MediaStreamDescription {
     pub video: Option<MediaVideoStreamDescription>,
     pub audio: Option<MediaAudioStreamDescription>,
+    /// The tracks that the sender sends, the descriptions of the older
+    /// senders have both.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mode: SessionMode,
+    /// The session token of the sender, the receiver must send it when
+    /// connecting to the sender.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub token: Option<String>,
+    /// The passphrase of the encryption of the stream, only publish the
+    /// description to the paired devices, see `PairingSecret`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub passphrase: Option<String>,
+    /// The version of the packets of the sender, see
+    /// `transport::PROTOCOL_VERSION`, the descriptions of the older senders
+    /// have 0.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub protocol: u8,
 }
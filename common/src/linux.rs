@@ -0,0 +1,66 @@
+/// Build a DRM fourcc code, see `drm_fourcc.h`.
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    (code[0] as u32) | (code[1] as u32) << 8 | (code[2] as u32) << 16 | (code[3] as u32) << 24
+}
+
+/// 2 plane YCbCr 4:2:0, Y plane followed by interleaved CbCr plane.
+pub const DRM_FORMAT_NV12: u32 = fourcc(b"NV12");
+
+/// 32 bpp, `[31:0] A:R:G:B 8:8:8:8` little endian, which is BGRA in memory.
+pub const DRM_FORMAT_ARGB8888: u32 = fourcc(b"AR24");
+
+/// Same as `DRM_FORMAT_ARGB8888`, the alpha channel is ignored.
+pub const DRM_FORMAT_XRGB8888: u32 = fourcc(b"XR24");
+
+/// 32 bpp, `[31:0] A:B:G:R 8:8:8:8` little endian, which is RGBA in memory.
+pub const DRM_FORMAT_ABGR8888: u32 = fourcc(b"AB24");
+
+/// Same as `DRM_FORMAT_ABGR8888`, the alpha channel is ignored.
+pub const DRM_FORMAT_XBGR8888: u32 = fourcc(b"XB24");
+
+/// The buffer is laid out linearly, without any tiling or compression.
+pub const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+
+/// The modifier is unknown, the layout is implied by the driver.
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+/// Layout of a single plane inside of the dma-buf object.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DmaBufPlane {
+    /// Offset of the plane from the beginning of the object, in bytes.
+    pub offset: u32,
+    /// Number of bytes between two consecutive rows of the plane.
+    pub pitch: u32,
+}
+
+/// DRM PRIME buffer descriptor.
+///
+/// This is the Linux equivalent of the shared texture handle, the frame is
+/// exported from the hardware (for example by VAAPI) as a dma-buf file
+/// descriptor, and the consumer imports the file descriptor into its own
+/// graphics api without copying the frame back to system memory.
+///
+/// Only frames where all planes live in a single dma-buf object are
+/// described, the file descriptor is borrowed, it remains owned by the
+/// producer of the frame.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DmaBufDescriptor {
+    /// DRM fourcc of the frame, see the `DRM_FORMAT_*` constants.
+    pub format: u32,
+    /// DRM format modifier, describes tiling and compression of the object.
+    pub modifier: u64,
+    /// The dma-buf file descriptor.
+    pub fd: i32,
+    /// Total size of the dma-buf object, in bytes.
+    pub size: u64,
+    pub planes: [DmaBufPlane; 4],
+    pub planes_count: u32,
+}
+
+impl DmaBufDescriptor {
+    pub fn planes(&self) -> &[DmaBufPlane] {
+        &self.planes[..(self.planes_count as usize).min(self.planes.len())]
+    }
+}
@@ -1,7 +1,14 @@
-use std::fs::{create_dir, metadata};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{File, OpenOptions, create_dir_all, rename},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
 
-use fern::{DateBased, Dispatch};
-use log::LevelFilter;
+use fern::{Dispatch, Output};
+use log::{LevelFilter, Metadata};
+use parking_lot::{Mutex, RwLock};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -12,13 +19,232 @@ pub enum LoggerInitError {
     IoError(#[from] std::io::Error),
 }
 
+/// The levels of the logs, they can be changed at any time after the logger
+/// is initialized.
+struct Levels {
+    level: LevelFilter,
+    targets: HashMap<String, LevelFilter>,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            targets: [
+                ("wgpu", LevelFilter::Warn),
+                ("wgpu_core", LevelFilter::Warn),
+                ("wgpu_hal", LevelFilter::Warn),
+                ("wgpu_hal::auxil::dxgi::exception", LevelFilter::Error),
+            ]
+            .into_iter()
+            .map(|(target, level)| (target.to_string(), level))
+            .collect(),
+        }
+    }
+}
+
+impl Levels {
+    /// The level of the most specific target that the target of the log
+    /// belongs to, `srt` also covers `srt::core`.
+    fn get(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(it, _)| {
+                target == it.as_str()
+                    || (target.starts_with(it.as_str()) && target[it.len()..].starts_with("::"))
+            })
+            .max_by_key(|(it, _)| it.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level)
+    }
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.get(metadata.target())
+    }
+
+    /// The log crate skips the logs above the max level before they reach the
+    /// logger, so it is the highest of all levels.
+    fn apply(&self) {
+        log::set_max_level(
+            self.targets
+                .values()
+                .copied()
+                .fold(self.level, LevelFilter::max),
+        );
+    }
+}
+
+static LEVELS: LazyLock<RwLock<Levels>> = LazyLock::new(Default::default);
+
+/// The functions that pass the levels of the targets to the native libraries,
+/// see [watch_target_level].
+static WATCHERS: LazyLock<Mutex<Vec<(&'static str, fn(LevelFilter))>>> =
+    LazyLock::new(Default::default);
+
+/// Passes the current levels to the watchers, called with the lock of the
+/// levels held, so that the changes reach the watchers in order.
+fn notify_watchers(levels: &Levels) {
+    for (target, watcher) in WATCHERS.lock().iter() {
+        watcher(levels.get(target));
+    }
+}
+
+/// Calls the function with the level of the target now, and again whenever
+/// the level changes. The native libraries that filter their own logs before
+/// they reach the logger, such as `srt` and `ffmpeg`, follow the level of
+/// their target with this.
+pub fn watch_target_level(target: &'static str, watcher: fn(LevelFilter)) {
+    let levels = LEVELS.read();
+
+    WATCHERS.lock().push((target, watcher));
+    watcher(levels.get(target));
+}
+
+/// The last lines of the logs, kept for the diagnostics of the application.
+struct History {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+static HISTORY: LazyLock<Mutex<History>> = LazyLock::new(|| {
+    Mutex::new(History {
+        lines: VecDeque::new(),
+        capacity: LoggerOptions::default().history,
+    })
+});
+
+fn push_history(record: &log::Record) {
+    let mut history = HISTORY.lock();
+    if history.capacity == 0 {
+        return;
+    }
+
+    while history.lines.len() >= history.capacity {
+        history.lines.pop_front();
+    }
+
+    history.lines.push_back(format!(
+        "{} [{}] {} - {}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+        record.level(),
+        record.target(),
+        record.args()
+    ));
+}
+
+/// A log file that is rotated when it reaches the size limit, the rotated
+/// files are numbered, `hylarana.1.log` is the newest one, and the oldest one
+/// is deleted when there are too many.
+struct RotatingFile {
+    directory: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: u32,
+}
+
+impl RotatingFile {
+    fn new(directory: &Path, max_size: u64, max_files: u32) -> io::Result<Self> {
+        create_dir_all(directory)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::path(directory, 0))?;
+
+        Ok(Self {
+            size: file.metadata()?.len(),
+            directory: directory.to_path_buf(),
+            max_size: max_size.max(1),
+            max_files,
+            file,
+        })
+    }
+
+    fn path(directory: &Path, index: u32) -> PathBuf {
+        directory.join(if index == 0 {
+            "hylarana.log".to_string()
+        } else {
+            format!("hylarana.{}.log", index)
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // Renaming onto the oldest file replaces it, with no rotated files the current
+        // file is truncated.
+        for index in (0..self.max_files).rev() {
+            let from = Self::path(&self.directory, index);
+            if from.exists() {
+                rename(from, Self::path(&self.directory, index + 1))?;
+            }
+        }
+
+        self.file = File::create(Self::path(&self.directory, 0))?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let size = self.file.write(buf)?;
+        self.size += size as u64;
+
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Options of the logger, see [init_logger_with_options].
+#[derive(Debug, Clone)]
+pub struct LoggerOptions {
+    pub level: LevelFilter,
+    /// The logs are also written to the files in this directory, see
+    /// `max_file_size`.
+    pub path: Option<String>,
+    /// The size in bytes at which the log file is rotated.
+    pub max_file_size: u64,
+    /// How many rotated files are kept besides the current file.
+    pub max_files: u32,
+    /// How many of the last lines are kept in memory, see [recent_logs], `0`
+    /// keeps none.
+    pub history: usize,
+}
+
+impl Default for LoggerOptions {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            path: None,
+            max_file_size: 10 * 1024 * 1024,
+            max_files: 5,
+            history: 1000,
+        }
+    }
+}
+
 pub fn init_logger(level: LevelFilter, path: Option<&str>) -> Result<(), LoggerInitError> {
+    init_logger_with_options(LoggerOptions {
+        path: path.map(|it| it.to_string()),
+        level,
+        ..Default::default()
+    })
+}
+
+pub fn init_logger_with_options(options: LoggerOptions) -> Result<(), LoggerInitError> {
+    LEVELS.write().level = options.level;
+    HISTORY.lock().capacity = options.history;
+
     let mut logger = Dispatch::new()
-        .level(level)
-        .level_for("wgpu", LevelFilter::Warn)
-        .level_for("wgpu_core", LevelFilter::Warn)
-        .level_for("wgpu_hal", LevelFilter::Warn)
-        .level_for("wgpu_hal::auxil::dxgi::exception", LevelFilter::Error)
+        .filter(|metadata| LEVELS.read().enabled(metadata))
         .format(move |out, message, record| {
             out.finish(format_args!(
                 "[{}] - ({}) - {}",
@@ -27,23 +253,66 @@ pub fn init_logger(level: LevelFilter, path: Option<&str>) -> Result<(), LoggerI
                 message
             ))
         })
-        .chain(std::io::stdout());
+        .chain(std::io::stdout())
+        .chain(Output::call(push_history));
 
-    if let Some(path) = path {
-        if metadata(path).is_err() {
-            create_dir(path)?;
-        }
-
-        logger = logger.chain(DateBased::new(path, "%Y-%m-%d-hylarana.log"))
+    if let Some(path) = options.path.as_deref() {
+        logger = logger.chain(Box::new(RotatingFile::new(
+            Path::new(path),
+            options.max_file_size,
+            options.max_files,
+        )?) as Box<dyn Write + Send>);
     }
 
     logger.apply()?;
+
+    // The logger sets the max level by the level of the dispatch, which is not
+    // used here.
+    let levels = LEVELS.read();
+    levels.apply();
+    notify_watchers(&levels);
+
     Ok(())
 }
 
+/// Changes the level of the logs at runtime, the levels of the targets are
+/// kept.
+pub fn set_level(level: LevelFilter) {
+    let mut levels = LEVELS.write();
+
+    levels.level = level;
+    levels.apply();
+    notify_watchers(&levels);
+}
+
+/// Changes the level of the logs of a target at runtime, such as `srt` for
+/// the logs of the SRT library and `ffmpeg` for the logs of ffmpeg, `None`
+/// removes the level of the target, so it follows the level of the logger.
+pub fn set_target_level(target: &str, level: Option<LevelFilter>) {
+    let mut levels = LEVELS.write();
+
+    match level {
+        Some(level) => {
+            levels.targets.insert(target.to_string(), level);
+        }
+        None => {
+            levels.targets.remove(target);
+        }
+    }
+
+    levels.apply();
+    notify_watchers(&levels);
+}
+
+/// The last lines of the logs, the oldest first, for example to copy them
+/// into a bug report.
+pub fn recent_logs() -> Vec<String> {
+    HISTORY.lock().lines.iter().cloned().collect()
+}
+
 pub fn enable_panic_logger() {
     std::panic::set_hook(Box::new(|info| {
-        log::error!(
+        tracing::error!(
             "pnaic: location={:?}, message={:?}",
             info.location(),
             info.payload()
@@ -94,11 +363,17 @@ pub mod android {
     #[cfg(target_os = "android")]
     impl log::Log for AndroidLogger {
         fn flush(&self) {}
-        fn enabled(&self, _: &log::Metadata) -> bool {
-            true
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            super::LEVELS.read().enabled(metadata)
         }
 
         fn log(&self, record: &log::Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+
+            super::push_history(record);
+
             unsafe {
                 android_log_write(
                     AndroidLogLevel::from_level(record.level()) as c_int,
@@ -118,7 +393,14 @@ pub mod android {
     pub fn init_logger(package: &str, level: log::LevelFilter) {
         #[cfg(target_os = "android")]
         {
-            log::set_max_level(level);
+            {
+                let mut levels = super::LEVELS.write();
+
+                levels.level = level;
+                levels.apply();
+                super::notify_watchers(&levels);
+            }
+
             log::set_boxed_logger(Box::new(AndroidLogger {
                 package: package.to_string(),
             }))
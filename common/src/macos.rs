@@ -16,10 +16,12 @@ use objc2_core_media::{
 
 use objc2_core_video::{
     CVMetalTexture, CVMetalTextureCache, CVMetalTextureGetTexture, CVPixelBuffer,
-    CVPixelBufferGetBaseAddressOfPlane, CVPixelBufferGetBytesPerRowOfPlane, CVPixelBufferGetHeight,
-    CVPixelBufferGetPixelFormatType, CVPixelBufferGetWidth, CVPixelBufferLockBaseAddress,
-    CVPixelBufferLockFlags, CVPixelBufferUnlockBaseAddress, kCVPixelFormatType_32BGRA,
-    kCVPixelFormatType_32RGBA, kCVPixelFormatType_420YpCbCr8BiPlanarFullRange,
+    CVPixelBufferGetBaseAddress, CVPixelBufferGetBaseAddressOfPlane, CVPixelBufferGetBytesPerRow,
+    CVPixelBufferGetBytesPerRowOfPlane, CVPixelBufferGetHeight, CVPixelBufferGetHeightOfPlane,
+    CVPixelBufferGetPixelFormatType, CVPixelBufferGetWidth, CVPixelBufferGetWidthOfPlane,
+    CVPixelBufferIsPlanar, CVPixelBufferLockBaseAddress, CVPixelBufferLockFlags,
+    CVPixelBufferUnlockBaseAddress, kCVPixelFormatType_32BGRA, kCVPixelFormatType_32RGBA,
+    kCVPixelFormatType_420YpCbCr8BiPlanarFullRange,
     kCVPixelFormatType_420YpCbCr8BiPlanarVideoRange, kCVPixelFormatType_420YpCbCr8Planar,
     kCVReturnSuccess,
 };
@@ -78,6 +80,13 @@ pub fn get_pixel_buffer_size(buffer: CVPixelBufferRef) -> Size {
     }
 }
 
+pub fn get_pixel_buffer_plane_size(buffer: CVPixelBufferRef, plane: usize) -> Size {
+    Size {
+        width: unsafe { CVPixelBufferGetWidthOfPlane(&*buffer, plane) } as u32,
+        height: unsafe { CVPixelBufferGetHeightOfPlane(&*buffer, plane) } as u32,
+    }
+}
+
 pub fn get_format_description_info<'a>(
     descr: *const CMAudioFormatDescription,
 ) -> Option<&'a AudioStreamBasicDescription> {
@@ -118,6 +127,23 @@ impl<'a> From<(CVPixelBufferRef, VideoFormat, Size)> for PixelMomeryBuffer<'a> {
             linesize: [0; 3],
         };
 
+        // The packed formats such as BGRA have no planes.
+        if !unsafe { CVPixelBufferIsPlanar(&*buffer) } {
+            this.linesize[0] = unsafe { CVPixelBufferGetBytesPerRow(&*buffer) };
+
+            let data = unsafe { CVPixelBufferGetBaseAddress(&*buffer) };
+            if !data.is_null() {
+                this.data[0] = unsafe {
+                    std::slice::from_raw_parts(
+                        data as *const _,
+                        this.linesize[0] * size.height as usize,
+                    )
+                };
+            }
+
+            return this;
+        }
+
         for i in 0..3 {
             this.linesize[i] = unsafe { CVPixelBufferGetBytesPerRowOfPlane(&*buffer, i) };
 
@@ -195,6 +221,23 @@ impl From<(CVPixelBufferRef, VideoFormat, Size)> for PixelBuffer {
     }
 }
 
+/// A pixel buffer that is retained until this is dropped, so that it can be
+/// kept after the frame that it comes from is released.
+pub struct RetainedPixelBuffer(Retained<CVPixelBuffer>);
+
+unsafe impl Send for RetainedPixelBuffer {}
+unsafe impl Sync for RetainedPixelBuffer {}
+
+impl RetainedPixelBuffer {
+    pub fn new(buffer: CVPixelBufferRef) -> Option<Self> {
+        unsafe { Retained::retain(buffer) }.map(Self)
+    }
+
+    pub fn as_raw(&self) -> CVPixelBufferRef {
+        Retained::as_ptr(&self.0) as CVPixelBufferRef
+    }
+}
+
 pub struct MetalTextureCache(Retained<CVMetalTextureCache>);
 
 impl MetalTextureCache {
@@ -221,8 +264,40 @@ impl MetalTextureCache {
     }
 
     pub fn map(&self, buffer: PixelBuffer) -> Result<MetalTexture, Error> {
-        let Size { width, height } = buffer.size;
+        self.create_texture(
+            buffer,
+            match buffer.format {
+                VideoFormat::BGRA => Objc2MTLPixelFormat::BGRA8Unorm,
+                VideoFormat::RGBA => Objc2MTLPixelFormat::RGBA8Unorm,
+                _ => unimplemented!("unsupports format = {:?}", buffer.format),
+            },
+            buffer.size,
+            0,
+        )
+    }
+
+    /// Map a plane of a NV12 pixel buffer, the luma plane is a R8 texture and
+    /// the interleaved chroma plane is a RG8 texture of half the size.
+    pub fn map_plane(&self, buffer: PixelBuffer, plane: usize) -> Result<MetalTexture, Error> {
+        self.create_texture(
+            buffer,
+            if plane == 0 {
+                Objc2MTLPixelFormat::R8Unorm
+            } else {
+                Objc2MTLPixelFormat::RG8Unorm
+            },
+            get_pixel_buffer_plane_size(buffer.as_raw(), plane),
+            plane,
+        )
+    }
 
+    fn create_texture(
+        &self,
+        buffer: PixelBuffer,
+        format: Objc2MTLPixelFormat,
+        Size { width, height }: Size,
+        plane: usize,
+    ) -> Result<MetalTexture, Error> {
         let mut texture = null_mut();
         let code = unsafe {
             CVMetalTextureCache::create_texture_from_image(
@@ -230,14 +305,10 @@ impl MetalTextureCache {
                 &self.0,
                 buffer.as_ref(),
                 None,
-                match buffer.format {
-                    VideoFormat::BGRA => Objc2MTLPixelFormat::BGRA8Unorm,
-                    VideoFormat::RGBA => Objc2MTLPixelFormat::RGBA8Unorm,
-                    _ => unimplemented!("unsupports format = {:?}", buffer.format),
-                },
+                format,
                 width as usize,
                 height as usize,
-                0,
+                plane,
                 NonNull::new(&mut texture).unwrap(),
             )
         };
@@ -293,7 +364,7 @@ pub fn request_av_capture_permissions(ty: AVMediaType) {
             let callback_ = callback.clone();
             let fun = RcBlock::new(move |ret: Bool| {
                 if ret.is_false() {
-                    log::error!("failed to request permissions, type={:?}", ty);
+                    tracing::error!("failed to request permissions, type={:?}", ty);
                 }
 
                 let _ = callback_.lock().unwrap().take();
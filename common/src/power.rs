@@ -0,0 +1,252 @@
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+
+use self::inhibitor::Inhibitor;
+
+/// The guards of the process share one inhibition of the system, it is taken
+/// by the first guard and released with the last one.
+struct Guards {
+    count: usize,
+    inhibitor: Option<Inhibitor>,
+}
+
+static GUARDS: LazyLock<Mutex<Guards>> = LazyLock::new(|| {
+    Mutex::new(Guards {
+        count: 0,
+        inhibitor: None,
+    })
+});
+
+/// Keeps the display on and the system awake while it is held, such as
+/// during a long presentation. The guards can be nested, the system may sleep
+/// again once all of them are dropped.
+///
+/// Acquiring never fails, if the system refuses the inhibition the guard
+/// does nothing and the reason is logged.
+pub struct PowerGuard(());
+
+impl PowerGuard {
+    /// The reason is shown by some systems in the list of the applications
+    /// that keep the display on.
+    pub fn acquire(reason: &str) -> Self {
+        let mut guards = GUARDS.lock();
+        if guards.count == 0 {
+            match Inhibitor::new(reason) {
+                Ok(it) => {
+                    tracing::info!("power guard inhibits the display sleep, reason={}", reason);
+
+                    guards.inhibitor = Some(it);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "power guard failed to inhibit the display sleep, err={:?}",
+                        e
+                    );
+                }
+            }
+        }
+
+        guards.count += 1;
+
+        Self(())
+    }
+}
+
+impl Drop for PowerGuard {
+    fn drop(&mut self) {
+        let mut guards = GUARDS.lock();
+        guards.count -= 1;
+
+        if guards.count == 0 && guards.inhibitor.take().is_some() {
+            tracing::info!("power guard allows the display sleep");
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod inhibitor {
+    use std::{
+        io::Error,
+        sync::mpsc::{Sender, channel},
+        thread::{self, JoinHandle},
+    };
+
+    use windows::Win32::System::Power::{
+        ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED, SetThreadExecutionState,
+    };
+
+    /// The execution state belongs to the thread that sets it, so it is held by
+    /// a thread of its own until the inhibitor is dropped.
+    pub struct Inhibitor {
+        stop: Option<Sender<()>>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Inhibitor {
+        pub fn new(_reason: &str) -> Result<Self, Error> {
+            let (stop, rx) = channel::<()>();
+            let (tx, result) = channel();
+
+            let thread = thread::Builder::new()
+                .name("HylaranaPowerGuardThread".to_string())
+                .spawn(move || {
+                    let state = unsafe {
+                        SetThreadExecutionState(
+                            ES_CONTINUOUS | ES_DISPLAY_REQUIRED | ES_SYSTEM_REQUIRED,
+                        )
+                    };
+
+                    let _ = tx.send(state.0 != 0);
+                    if state.0 == 0 {
+                        return;
+                    }
+
+                    // Blocks until the inhibitor is dropped.
+                    let _ = rx.recv();
+
+                    unsafe {
+                        SetThreadExecutionState(ES_CONTINUOUS);
+                    }
+                })?;
+
+            if !result.recv().unwrap_or(false) {
+                return Err(Error::other("SetThreadExecutionState failed"));
+            }
+
+            Ok(Self {
+                stop: Some(stop),
+                thread: Some(thread),
+            })
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            drop(self.stop.take());
+
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod inhibitor {
+    use std::io::Error;
+
+    use objc2_core_foundation::CFString;
+
+    #[link(name = "IOKit", kind = "framework")]
+    unsafe extern "C" {
+        fn IOPMAssertionCreateWithName(
+            kind: &CFString,
+            level: u32,
+            name: &CFString,
+            id: *mut u32,
+        ) -> i32;
+
+        fn IOPMAssertionRelease(id: u32) -> i32;
+    }
+
+    const ASSERTION_LEVEL_ON: u32 = 255;
+
+    pub struct Inhibitor(u32);
+
+    impl Inhibitor {
+        pub fn new(reason: &str) -> Result<Self, Error> {
+            let mut id = 0;
+            let ret = unsafe {
+                IOPMAssertionCreateWithName(
+                    &CFString::from_str("PreventUserIdleDisplaySleep"),
+                    ASSERTION_LEVEL_ON,
+                    &CFString::from_str(reason),
+                    &mut id,
+                )
+            };
+
+            if ret != 0 {
+                return Err(Error::other(format!(
+                    "IOPMAssertionCreateWithName failed, ret={}",
+                    ret
+                )));
+            }
+
+            Ok(Self(id))
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            unsafe {
+                IOPMAssertionRelease(self.0);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inhibitor {
+    use std::io::Error;
+
+    use zbus::blocking::Connection;
+
+    const DESTINATION: &str = "org.freedesktop.ScreenSaver";
+    const PATH: &str = "/org/freedesktop/ScreenSaver";
+
+    /// Some desktops release the inhibition when the connection is closed, so
+    /// the connection is kept with the cookie.
+    pub struct Inhibitor {
+        connection: Connection,
+        cookie: u32,
+    }
+
+    impl Inhibitor {
+        pub fn new(reason: &str) -> Result<Self, Error> {
+            let connection = Connection::session().map_err(Error::other)?;
+            let cookie = connection
+                .call_method(
+                    Some(DESTINATION),
+                    PATH,
+                    Some(DESTINATION),
+                    "Inhibit",
+                    &("Hylarana", reason),
+                )
+                .map_err(Error::other)?
+                .body()
+                .deserialize::<u32>()
+                .map_err(Error::other)?;
+
+            Ok(Self { connection, cookie })
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            if let Err(e) = self.connection.call_method(
+                Some(DESTINATION),
+                PATH,
+                Some(DESTINATION),
+                "UnInhibit",
+                &(self.cookie,),
+            ) {
+                tracing::warn!("power guard failed to uninhibit, err={:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod inhibitor {
+    use std::io::Error;
+
+    /// Mobile apps keep the screen on through their own window flags.
+    pub struct Inhibitor;
+
+    impl Inhibitor {
+        pub fn new(_reason: &str) -> Result<Self, Error> {
+            Ok(Self)
+        }
+    }
+}
@@ -1,7 +1,10 @@
-use std::sync::LazyLock;
+use std::{panic::resume_unwind, sync::LazyLock, thread};
 
 use parking_lot::Mutex;
-use tokio::runtime::{Handle, Runtime};
+use tokio::{
+    runtime::{Handle, Runtime, RuntimeFlavor},
+    task::block_in_place,
+};
 
 // A runtime created with a delay that automatically creates a multithreaded
 // runtime internally if a runtime handle is not provided externally.
@@ -44,3 +47,29 @@ pub fn get_runtime_handle() -> Handle {
 
     handle
 }
+
+/// Run the future to completion on the calling thread. Unlike
+/// [Handle::block_on], this can also be called from inside the runtime, such
+/// as from the async functions of the library.
+///
+/// A current thread runtime can not be blocked in place, so the future is run
+/// on another thread, where it can not use the io and the timers of the
+/// runtime.
+pub fn block_on<F>(future: F) -> F::Output
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            block_in_place(|| handle.block_on(future))
+        }
+        Ok(handle) => thread::scope(|scope| {
+            scope
+                .spawn(|| handle.block_on(future))
+                .join()
+                .unwrap_or_else(|e| resume_unwind(e))
+        }),
+        Err(_) => get_runtime_handle().block_on(future),
+    }
+}
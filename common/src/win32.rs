@@ -6,18 +6,21 @@ pub use windows;
 
 use windows::{
     Win32::{
-        Foundation::{HANDLE, HWND, RECT},
+        Foundation::{HANDLE, HWND, LUID, RECT},
         Graphics::{
             Direct3D::{
-                D3D_DRIVER_TYPE_HARDWARE, D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_11_0,
-                D3D_FEATURE_LEVEL_11_1,
+                D3D_DRIVER_TYPE_HARDWARE, D3D_DRIVER_TYPE_UNKNOWN, D3D_FEATURE_LEVEL,
+                D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_11_1,
             },
             Direct3D11::{
-                D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC,
-                D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Multithread,
-                ID3D11Texture2D,
+                D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_MAP_READ, D3D11_MAPPED_SUBRESOURCE,
+                D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC, D3D11CreateDevice, ID3D11Device,
+                ID3D11DeviceContext, ID3D11Multithread, ID3D11Texture2D,
+            },
+            Dxgi::{
+                CreateDXGIFactory1, DXGI_ADAPTER_FLAG_SOFTWARE, DXGI_ERROR_NOT_FOUND, IDXGIAdapter,
+                IDXGIAdapter1, IDXGIDevice, IDXGIFactory1, IDXGIResource,
             },
-            Dxgi::IDXGIResource,
         },
         Media::MediaFoundation::{
             IMFActivate, IMFAttributes, IMFMediaType, MF_VERSION, MFShutdown, MFStartup,
@@ -33,7 +36,7 @@ use windows::{
         },
         UI::WindowsAndMessaging::GetClientRect,
     },
-    core::{GUID, HSTRING, Interface, PCSTR, PCWSTR, PWSTR, Result, s},
+    core::{Error, GUID, HSTRING, Interface, PCSTR, PCWSTR, PWSTR, Result, s},
 };
 
 pub fn get_hwnd_size(hwnd: HWND) -> Result<Size> {
@@ -203,7 +206,7 @@ impl Drop for MediaThreadClassGuard {
     fn drop(&mut self) {
         if let Some(handle) = THREAD_CLASS_HANDLE.get() {
             if let Err(e) = unsafe { AvRevertMmThreadCharacteristics(handle) } {
-                log::warn!("AvRevertMmThreadCharacteristics error={:?}", e)
+                tracing::warn!("AvRevertMmThreadCharacteristics error={:?}", e)
             }
         }
     }
@@ -216,7 +219,7 @@ impl MediaThreadClass {
         match unsafe { AvSetMmThreadCharacteristicsA(taskname, &mut taskindex) } {
             Ok(handle) => THREAD_CLASS_HANDLE.set(Some(handle)),
             Err(e) => {
-                log::warn!("AvSetMmThreadCharacteristics error={:?}", e);
+                tracing::warn!("AvSetMmThreadCharacteristics error={:?}", e);
 
                 return Err(e);
             }
@@ -226,6 +229,108 @@ impl MediaThreadClass {
     }
 }
 
+/// A hardware graphics adapter of the system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuAdapter {
+    /// The index of the adapter in the enumeration order of dxgi, the first
+    /// adapter is the one the system uses by default.
+    pub index: u32,
+    pub name: String,
+    /// The PCI vendor id, see [GpuAdapter::VENDOR_NVIDIA] and so on.
+    pub vendor_id: u32,
+    /// The PCI device id.
+    pub device_id: u32,
+    /// The locally unique identifier of the adapter, it identifies the same
+    /// adapter across the graphics apis until the system restarts.
+    pub luid: i64,
+    /// Dedicated video memory in bytes, integrated adapters have little or no
+    /// dedicated memory.
+    pub dedicated_video_memory: u64,
+}
+
+impl GpuAdapter {
+    pub const VENDOR_NVIDIA: u32 = 0x10DE;
+    pub const VENDOR_AMD: u32 = 0x1002;
+    pub const VENDOR_INTEL: u32 = 0x8086;
+
+    /// Enumerates the hardware adapters, the software rasterizer is skipped.
+    pub fn enumerate() -> Result<Vec<GpuAdapter>> {
+        Ok(enumerate_adapters()?
+            .into_iter()
+            .map(|(adapter, _)| adapter)
+            .collect())
+    }
+}
+
+/// Which adapter the direct3d device is created on, on laptops with an
+/// integrated and a discrete gpu, the capture, the codec and the renderer must
+/// use the same adapter to avoid copies between the adapters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuSelector {
+    /// The index of [GpuAdapter::index].
+    Index(u32),
+    /// The first adapter of the vendor.
+    Vendor(u32),
+    /// The adapter with the [GpuAdapter::luid].
+    Luid(i64),
+    /// The adapter with the most dedicated video memory, which is usually the
+    /// discrete gpu.
+    HighPerformance,
+}
+
+impl GpuSelector {
+    fn select<'a>(&self, adapters: &'a [(GpuAdapter, IDXGIAdapter1)]) -> Option<&'a IDXGIAdapter1> {
+        match *self {
+            Self::Index(index) => adapters.iter().find(|(it, _)| it.index == index),
+            Self::Vendor(vendor_id) => adapters.iter().find(|(it, _)| it.vendor_id == vendor_id),
+            Self::Luid(luid) => adapters.iter().find(|(it, _)| it.luid == luid),
+            Self::HighPerformance => adapters
+                .iter()
+                .max_by_key(|(it, _)| it.dedicated_video_memory),
+        }
+        .map(|(_, adapter)| adapter)
+    }
+}
+
+/// The [GpuAdapter::luid] of a dxgi adapter description.
+pub fn luid_to_i64(luid: LUID) -> i64 {
+    ((luid.HighPart as i64) << 32) | luid.LowPart as i64
+}
+
+fn enumerate_adapters() -> Result<Vec<(GpuAdapter, IDXGIAdapter1)>> {
+    let factory = unsafe { CreateDXGIFactory1::<IDXGIFactory1>()? };
+
+    let mut adapters = Vec::new();
+    for index in 0.. {
+        let adapter = match unsafe { factory.EnumAdapters1(index) } {
+            Ok(it) => it,
+            Err(e) if e.code() == DXGI_ERROR_NOT_FOUND => break,
+            Err(e) => return Err(e),
+        };
+
+        let desc = unsafe { adapter.GetDesc1()? };
+        if desc.Flags & DXGI_ADAPTER_FLAG_SOFTWARE.0 as u32 != 0 {
+            continue;
+        }
+
+        adapters.push((
+            GpuAdapter {
+                index,
+                name: String::from_utf16_lossy(&desc.Description)
+                    .trim_end_matches('\0')
+                    .to_string(),
+                vendor_id: desc.VendorId,
+                device_id: desc.DeviceId,
+                luid: luid_to_i64(desc.AdapterLuid),
+                dedicated_video_memory: desc.DedicatedVideoMemory as u64,
+            },
+            adapter,
+        ));
+    }
+
+    Ok(adapters)
+}
+
 #[derive(Debug, Clone)]
 pub struct Direct3DDevice {
     pub device: ID3D11Device,
@@ -233,14 +338,36 @@ pub struct Direct3DDevice {
 }
 
 impl Direct3DDevice {
+    /// Create the device on the default adapter of the system.
     pub fn new() -> Result<Direct3DDevice> {
+        Self::create(None)
+    }
+
+    /// Create the device on the adapter of the selector.
+    pub fn with_adapter(selector: GpuSelector) -> Result<Direct3DDevice> {
+        let adapters = enumerate_adapters()?;
+        let adapter = selector
+            .select(&adapters)
+            .ok_or_else(|| Error::from(DXGI_ERROR_NOT_FOUND))?;
+
+        Self::create(Some(adapter.cast()?))
+    }
+
+    fn create(adapter: Option<IDXGIAdapter>) -> Result<Direct3DDevice> {
         unsafe {
             let (mut d3d_device, mut d3d_context, mut feature_level) =
                 (None, None, D3D_FEATURE_LEVEL::default());
 
+            // The driver type must be unknown when the adapter is specified.
+            let driver_type = if adapter.is_some() {
+                D3D_DRIVER_TYPE_UNKNOWN
+            } else {
+                D3D_DRIVER_TYPE_HARDWARE
+            };
+
             D3D11CreateDevice(
-                None,
-                D3D_DRIVER_TYPE_HARDWARE,
+                adapter.as_ref(),
+                driver_type,
                 None,
                 D3D11_CREATE_DEVICE_BGRA_SUPPORT,
                 Some(&[D3D_FEATURE_LEVEL_11_1, D3D_FEATURE_LEVEL_11_0]),
@@ -257,6 +384,17 @@ impl Direct3DDevice {
         }
     }
 
+    /// The adapter that the device is created on.
+    pub fn adapter(&self) -> Result<GpuAdapter> {
+        let adapter = unsafe { self.device.cast::<IDXGIDevice>()?.GetAdapter()? };
+        let luid = luid_to_i64(unsafe { adapter.GetDesc()? }.AdapterLuid);
+
+        GpuAdapter::enumerate()?
+            .into_iter()
+            .find(|it| it.luid == luid)
+            .ok_or_else(|| Error::from(DXGI_ERROR_NOT_FOUND))
+    }
+
     pub fn set_multithread_protected(&self, value: bool) -> Result<()> {
         let multithread = self.device.cast::<ID3D11Multithread>()?;
         let _ = unsafe { multithread.SetMultithreadProtected(value) };
@@ -309,3 +447,49 @@ impl EasyTexture for ID3D11Texture2D {
         desc
     }
 }
+
+/// A staging texture mapped for reading, the texture is unmapped when this is
+/// dropped.
+pub struct TextureBuffer<'a> {
+    d3d_context: &'a ID3D11DeviceContext,
+    texture: &'a ID3D11Texture2D,
+    resource: D3D11_MAPPED_SUBRESOURCE,
+}
+
+unsafe impl Send for TextureBuffer<'_> {}
+unsafe impl Sync for TextureBuffer<'_> {}
+
+impl<'a> TextureBuffer<'a> {
+    pub fn new(d3d_context: &'a ID3D11DeviceContext, texture: &'a ID3D11Texture2D) -> Result<Self> {
+        let mut resource = D3D11_MAPPED_SUBRESOURCE::default();
+        unsafe {
+            d3d_context.Map(texture, 0, D3D11_MAP_READ, 0, Some(&mut resource))?;
+        }
+
+        Ok(Self {
+            d3d_context,
+            resource,
+            texture,
+        })
+    }
+
+    /// Represents a pointer to texture data. Internally, the texture is
+    /// copied to the CPU first, and then the internal data is
+    /// mapped.
+    pub fn buffer(&self) -> *const u8 {
+        self.resource.pData as *const _
+    }
+
+    /// The stride of the texture data
+    pub fn stride(&self) -> u32 {
+        self.resource.RowPitch
+    }
+}
+
+impl Drop for TextureBuffer<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            self.d3d_context.Unmap(self.texture, 0);
+        }
+    }
+}
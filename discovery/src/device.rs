@@ -0,0 +1,81 @@
+use common::MediaStreamDescription;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The type of the device, the devices of newer versions may report a kind
+/// that is unknown here, it is parsed as `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceKind {
+    Desktop,
+    Laptop,
+    Phone,
+    Tablet,
+    Tv,
+    #[default]
+    #[serde(other)]
+    Other,
+}
+
+/// What the device can do in a session.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeviceCapabilities {
+    pub sender: bool,
+    pub receiver: bool,
+    pub audio: bool,
+    pub video: bool,
+}
+
+/// The information of the device published by the discovery service, this is
+/// the typed alternative of the raw metadata, see
+/// `DiscoveryService::set_device_info`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub kind: DeviceKind,
+    pub capabilities: DeviceCapabilities,
+    /// The streams currently published by the device.
+    pub streams: Vec<MediaStreamDescription>,
+    /// Anything else of the application, it is passed through as is.
+    pub custom: Value,
+}
+
+/// The device information is wrapped with its version, the metadata that is
+/// not wrapped this way is left to the application.
+#[derive(Deserialize, Serialize)]
+struct Envelope<T> {
+    hylarana_device: u32,
+    info: T,
+}
+
+impl DeviceInfo {
+    /// The version of the encoding, the fields added later are optional, so
+    /// this only changes when the older versions cannot read it anymore.
+    pub const VERSION: u32 = 1;
+
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(&Envelope {
+            hylarana_device: Self::VERSION,
+            info: self,
+        })
+        .unwrap()
+    }
+
+    /// Returns `None` if the metadata is not a device information or is of an
+    /// unsupported version.
+    pub fn decode(metadata: &[u8]) -> Option<Self> {
+        let envelope = serde_json::from_slice::<Envelope<Value>>(metadata).ok()?;
+        if envelope.hylarana_device != Self::VERSION {
+            tracing::warn!(
+                "unsupported device info version={}",
+                envelope.hylarana_device
+            );
+
+            return None;
+        }
+
+        serde_json::from_value(envelope.info).ok()
+    }
+}
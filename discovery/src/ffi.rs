@@ -0,0 +1,111 @@
+//! The C callbacks of the discovery service, shared by the C interfaces of
+//! the desktop and the apple platforms.
+
+use std::{
+    ffi::{CString, c_char, c_void},
+    net::IpAddr,
+};
+
+use crate::DiscoveryObserver;
+
+/// The callbacks of the discovery service, the strings are only valid during
+/// the callback.
+#[repr(C)]
+pub struct HylaranaDiscoveryCallbacks {
+    pub ctx: *const c_void,
+    pub online: Option<
+        extern "C" fn(
+            ctx: *const c_void,
+            local_id: *const c_char,
+            id: *const c_char,
+            ip: *const c_char,
+        ),
+    >,
+    pub offline: Option<
+        extern "C" fn(
+            ctx: *const c_void,
+            local_id: *const c_char,
+            id: *const c_char,
+            ip: *const c_char,
+        ),
+    >,
+    pub metadata: Option<
+        extern "C" fn(
+            ctx: *const c_void,
+            local_id: *const c_char,
+            id: *const c_char,
+            ip: *const c_char,
+            metadata: *const u8,
+            size: usize,
+        ),
+    >,
+}
+
+unsafe impl Send for HylaranaDiscoveryCallbacks {}
+unsafe impl Sync for HylaranaDiscoveryCallbacks {}
+
+struct Strings {
+    local_id: CString,
+    id: CString,
+    ip: CString,
+}
+
+impl Strings {
+    fn new(local_id: &str, id: &str, ip: IpAddr) -> Option<Self> {
+        Some(Self {
+            local_id: CString::new(local_id).ok()?,
+            id: CString::new(id).ok()?,
+            ip: CString::new(ip.to_string()).ok()?,
+        })
+    }
+}
+
+pub struct DiscoveryServiceObserver(pub HylaranaDiscoveryCallbacks);
+
+impl DiscoveryObserver for DiscoveryServiceObserver {
+    async fn online(&self, local_id: &str, id: &str, ip: IpAddr) {
+        tracing::info!("devices manager device online, id={}, ip={}", id, ip);
+
+        if let (Some(callback), Some(it)) = (self.0.online, Strings::new(local_id, id, ip)) {
+            callback(
+                self.0.ctx,
+                it.local_id.as_ptr(),
+                it.id.as_ptr(),
+                it.ip.as_ptr(),
+            );
+        }
+    }
+
+    async fn offline(&self, local_id: &str, id: &str, ip: IpAddr) {
+        tracing::info!("devices manager device offline, id={}, ip={}", id, ip);
+
+        if let (Some(callback), Some(it)) = (self.0.offline, Strings::new(local_id, id, ip)) {
+            callback(
+                self.0.ctx,
+                it.local_id.as_ptr(),
+                it.id.as_ptr(),
+                it.ip.as_ptr(),
+            );
+        }
+    }
+
+    async fn on_metadata(&self, local_id: &str, id: &str, ip: IpAddr, metadata: Vec<u8>) {
+        tracing::info!(
+            "devices manager device on metadata, id={}, ip={} metadata={:?}",
+            id,
+            ip,
+            std::str::from_utf8(&metadata)
+        );
+
+        if let (Some(callback), Some(it)) = (self.0.metadata, Strings::new(local_id, id, ip)) {
+            callback(
+                self.0.ctx,
+                it.local_id.as_ptr(),
+                it.id.as_ptr(),
+                it.ip.as_ptr(),
+                metadata.as_ptr(),
+                metadata.len(),
+            );
+        }
+    }
+}
@@ -1,25 +1,59 @@
+mod device;
+mod options;
+mod pairing;
+mod peers;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+pub use self::{
+    device::{DeviceCapabilities, DeviceInfo, DeviceKind},
+    options::DiscoveryOptions,
+    pairing::{PairingError, PairingSecret},
+    peers::DiscoveryMode,
+};
+
+use self::{
+    pairing::{HEADER_NONCE, PairingServer, request_metadata, seal},
+    peers::{Peers, resolve_rendezvous},
+};
+
 use std::{
     collections::HashMap,
     io::Error,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
     sync::{
         Arc,
         atomic::{AtomicU32, Ordering},
+        mpsc,
     },
+    task::{Context, Poll},
     time::{Duration, Instant},
 };
 
-use axum::{Router, extract::State, http::StatusCode, response::IntoResponse, routing::get};
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use futures_core::Stream;
 use serde::{Deserialize, Serialize};
 use tokio::{
     net::{TcpListener, UdpSocket},
     sync::{
         Mutex, RwLock,
         broadcast::{Sender, channel},
+        mpsc::UnboundedReceiver,
+        watch,
     },
     time::sleep,
 };
 
+use common::events::Broadcaster;
 use uuid::Uuid;
 
 pub trait DiscoveryObserver {
@@ -43,25 +77,102 @@ pub trait DiscoveryObserver {
     ) -> impl Future<Output = ()> + Send {
         async {}
     }
+
+    /// Callback with the parsed device information when the metadata of the
+    /// service is a device information, the raw metadata is still passed to
+    /// `on_metadata`.
+    #[allow(unused_variables)]
+    fn on_device_info(
+        &self,
+        local_id: &str,
+        id: &str,
+        ip: IpAddr,
+        info: DeviceInfo,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Callback when the service requires the pairing and it is not paired,
+    /// or the pairing is rejected, for example the pin of the service has
+    /// changed, the metadata is not available until `DiscoveryService::pair`
+    /// succeeds.
+    #[allow(unused_variables)]
+    fn on_pairing_required(
+        &self,
+        local_id: &str,
+        id: &str,
+        ip: IpAddr,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
 }
 
+type Secrets = Arc<RwLock<HashMap<String, PairingSecret>>>;
+
+const RENDEZVOUS_INTERVAL: Duration = Duration::from_secs(10);
+
 pub struct DiscoveryService {
     _t: Sender<()>,
+    events: Arc<Broadcaster<DiscoveryEvent>>,
     metadata: Arc<RwLock<Option<Vec<u8>>>>,
     sequence: Arc<AtomicU32>,
+    pairing: Arc<PairingServer>,
+    services: Arc<Mutex<HashMap<String, Service>>>,
+    secrets: Secrets,
+    peers: Arc<RwLock<Peers>>,
+    watcher: Arc<watch::Sender<Vec<Peer>>>,
     local_id: String,
+    port: u16,
 }
 
 impl DiscoveryService {
-    pub async fn new<O>(bind: SocketAddr, observer: O) -> Result<Self, Error>
+    /// Create the service, the options can be only the address to listen on,
+    /// the other options are then the defaults.
+    pub async fn new<O>(options: impl Into<DiscoveryOptions>, observer: O) -> Result<Self, Error>
     where
         O: DiscoveryObserver + Send + Sync + 'static,
     {
-        let observer = Arc::new(observer);
+        Self::with_events(options.into(), observer, Default::default()).await
+    }
+
+    /// Creates the service with the events delivered as a stream instead of
+    /// an observer, the stream ends when the service is dropped.
+    pub async fn with_stream(
+        options: impl Into<DiscoveryOptions>,
+    ) -> Result<(Self, DiscoveryEventStream), Error> {
+        // Subscribed before the service starts, so that no event is missed.
+        let events: Arc<Broadcaster<DiscoveryEvent>> = Default::default();
+        let stream = DiscoveryEventStream(events.subscribe_stream());
+
+        Ok((
+            Self::with_events(options.into(), NoopObserver, events).await?,
+            stream,
+        ))
+    }
+
+    async fn with_events<O>(
+        options: DiscoveryOptions,
+        observer: O,
+        events: Arc<Broadcaster<DiscoveryEvent>>,
+    ) -> Result<Self, Error>
+    where
+        O: DiscoveryObserver + Send + Sync + 'static,
+    {
+        let bind = SocketAddr::new(options.bind, options.port);
+
+        let observer = Arc::new(EventObserver {
+            events: events.clone(),
+            observer,
+        });
+
         let local_id = Uuid::new_v4().to_string();
         let sequence: Arc<AtomicU32> = Default::default();
         let metadata: Arc<RwLock<Option<Vec<u8>>>> = Default::default();
         let service: Arc<Mutex<HashMap<String, Service>>> = Default::default();
+        let pairing: Arc<PairingServer> = Default::default();
+        let secrets: Secrets = Default::default();
+        let peers: Arc<RwLock<Peers>> = Default::default();
+        let watcher = Arc::new(watch::Sender::new(Vec::new()));
 
         let (tx, mut rx) = channel::<()>(2);
 
@@ -74,44 +185,37 @@ impl DiscoveryService {
 
         {
             let app = Router::new()
-                .route(
-                    "/metadata",
-                    get(
-                        |State(metadata): State<Arc<RwLock<Option<Vec<u8>>>>>| async move {
-                            if let Some(data) = metadata.read().await.as_ref() {
-                                data.clone().into_response()
-                            } else {
-                                StatusCode::NOT_FOUND.into_response()
-                            }
-                        },
-                    ),
-                )
-                .with_state(metadata.clone());
+                .route("/metadata", get(get_metadata))
+                .route("/pairing/challenge", post(post_challenge))
+                .with_state(Arc::new(HttpState {
+                    metadata: metadata.clone(),
+                    pairing: pairing.clone(),
+                }));
 
             let mut rx = tx.subscribe();
             tokio::spawn(async move {
-                axum::serve(listener, app)
-                    .with_graceful_shutdown(async move {
-                        let _ = rx.recv().await;
-                    })
-                    .await
-                    .unwrap();
+                axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .with_graceful_shutdown(async move {
+                    let _ = rx.recv().await;
+                })
+                .await
+                .unwrap();
             });
         }
 
-        let to_addr = {
-            let mut addr = bind.clone();
-            addr.set_ip(IpAddr::V4(Ipv4Addr::BROADCAST));
-
-            addr
-        };
-
         {
             let mut rx = tx.subscribe();
             let local_id = local_id.clone();
             let services = service.clone();
             let socket = socket.clone();
             let observer = observer.clone();
+            let secrets = secrets.clone();
+            let peers = peers.clone();
+            let watcher = watcher.clone();
+            let options = options.clone();
             tokio::spawn(async move {
                 let mut buffer = [0u8; 1024];
 
@@ -128,27 +232,37 @@ impl DiscoveryService {
                                     continue;
                                 }
 
+                                if !ping.unicast {
+                                    peers.write().await.broadcast_received();
+                                }
+
+                                let mut changed = false;
                                 let mut services = services.lock().await;
                                 if let Some(service) = services.get_mut(ping.id) {
-                                    if service.sequence != ping.sequence {
-                                        if let Some(metadata) = request_metadata(addr.ip(), to_addr.port()).await {
-                                            observer.on_metadata(&local_id, ping.id, addr.ip(), metadata).await;
+                                    if service.sequence != Some(ping.sequence) {
+                                        if let Some(metadata) = fetch_metadata(&*observer, &secrets, &local_id, &ping, addr.ip(), options.port).await {
+                                            service.metadata = Some(metadata);
+                                            changed = true;
                                         }
                                     }
 
                                     service.update_at = Instant::now();
-                                    service.sequence = ping.sequence;
+                                    service.sequence = Some(ping.sequence);
                                 } else {
+                                    observer.online(&local_id, ping.id, addr.ip()).await;
+
                                     services.insert(ping.id.to_string(), Service {
+                                        metadata: fetch_metadata(&*observer, &secrets, &local_id, &ping, addr.ip(), options.port).await,
                                         update_at: Instant::now(),
-                                        sequence: ping.sequence,
+                                        sequence: Some(ping.sequence),
                                         ip: addr.ip(),
                                     });
 
-                                    observer.online(&local_id, ping.id, addr.ip()).await;
-                                    if let Some(metadata) = request_metadata(addr.ip(), to_addr.port()).await {
-                                        observer.on_metadata(&local_id, ping.id, addr.ip(), metadata).await;
-                                    }
+                                    changed = true;
+                                }
+
+                                if changed {
+                                    watcher.send_replace(Peer::from_services(&services));
                                 }
                             }
                         }
@@ -167,15 +281,43 @@ impl DiscoveryService {
             let local_id = local_id.clone();
             let sequence = sequence.clone();
             let services = service.clone();
+            let pairing = pairing.clone();
+            let peers = peers.clone();
+            let watcher = watcher.clone();
+            let options = options.clone();
             tokio::spawn(async move {
                 loop {
                     tokio::select! {
-                        _ = sleep(Duration::from_secs(1)) => {
-                            if let Err(e) = socket.send_to(&serde_json::to_vec(&Ping {
+                        _ = sleep(options.ping_interval) => {
+                            let ping = |unicast| serde_json::to_vec(&Ping {
                                 sequence: sequence.load(Ordering::Relaxed),
+                                pairing: pairing.is_required(),
                                 id: &local_id,
-                            }).unwrap(), to_addr).await {
-                                log::error!("discovery service send ping failed, err={:?}", e);
+                                unicast,
+                            }).unwrap();
+
+                            let (broadcast, targets) = {
+                                let peers = peers.read().await;
+
+                                (peers.is_broadcast(), if peers.is_unicast() {
+                                    peers.targets(services.lock().await.values().map(|it| it.ip))
+                                } else {
+                                    Vec::new()
+                                })
+                            };
+
+                            if broadcast {
+                                for addr in options.broadcast_addrs() {
+                                    if let Err(e) = socket.send_to(&ping(false), addr).await {
+                                        tracing::error!("discovery service send ping failed, addr={}, err={:?}", addr, e);
+                                    }
+                                }
+                            }
+
+                            for ip in targets {
+                                if let Err(e) = socket.send_to(&ping(true), SocketAddr::new(ip, options.port)).await {
+                                    tracing::warn!("discovery service send ping failed, ip={}, err={:?}", ip, e);
+                                }
                             }
 
                             {
@@ -183,7 +325,7 @@ impl DiscoveryService {
                                 let mut services = services.lock().await;
 
                                 for (k, v) in services.iter() {
-                                    if v.update_at.elapsed().as_secs() >= 3 {
+                                    if v.update_at.elapsed() >= options.offline_timeout {
                                         offlines.push(k.clone());
 
                                         observer.offline(&local_id, k, v.ip).await;
@@ -193,6 +335,10 @@ impl DiscoveryService {
                                 for k in &offlines {
                                     services.remove(k);
                                 }
+
+                                if !offlines.is_empty() {
+                                    watcher.send_replace(Peer::from_services(&services));
+                                }
                             }
                         }
                         _ = rx.recv() => {
@@ -206,46 +352,427 @@ impl DiscoveryService {
             });
         }
 
+        {
+            let mut rx = tx.subscribe();
+            let peers = peers.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        _ = sleep(RENDEZVOUS_INTERVAL) => {
+                            let url = peers.read().await.rendezvous.clone();
+                            if let Some(url) = url {
+                                if let Some(resolved) = resolve_rendezvous(&url).await {
+                                    peers.write().await.resolved = resolved;
+                                } else {
+                                    tracing::warn!("discovery service resolve rendezvous failed, url={}", url);
+                                }
+                            }
+                        }
+                        _ = rx.recv() => {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+
         Ok(Self {
             _t: tx,
+            events,
             metadata,
             sequence,
+            pairing,
+            services: service,
+            secrets,
+            peers,
+            watcher,
             local_id,
+            port: options.port,
         })
     }
 
+    /// Subscribe to the events of the service, the events are also passed to
+    /// the observer, the channel can be polled in a message loop instead of
+    /// implementing the observer.
+    pub fn events(&self) -> mpsc::Receiver<DiscoveryEvent> {
+        self.events.subscribe()
+    }
+
     pub fn local_id(&self) -> &str {
         &self.local_id
     }
 
+    /// The services that are currently online.
+    pub async fn peers(&self) -> Vec<Peer> {
+        Peer::from_services(&*self.services.lock().await)
+    }
+
+    /// Watch the services that are online, the value changes when a service
+    /// goes online or offline or its metadata changes, the `last_seen` of
+    /// the value is as of the last change, use `peers` for the current one.
+    pub fn watch(&self) -> watch::Receiver<Vec<Peer>> {
+        self.watcher.subscribe()
+    }
+
     pub async fn set_metadata(&self, metadata: Vec<u8>) {
-        log::info!("discovery service set metadata");
+        tracing::info!("discovery service set metadata");
 
         self.sequence.fetch_add(1, Ordering::Relaxed);
         self.metadata.write().await.replace(metadata);
     }
+
+    /// Publish the information of the device, this replaces the metadata, the
+    /// other services receive it parsed in `on_device_info`.
+    pub async fn set_device_info(&self, info: &DeviceInfo) {
+        self.set_metadata(info.encode()).await;
+    }
+
+    /// Require the other services to pair with this service before they can
+    /// get the metadata, `None` removes the requirement. Changing the secret
+    /// also removes all previous pairings, because they no longer know it. A
+    /// pre-shared key shorter than `PairingSecret::MIN_KEY_LEN` is rejected.
+    pub async fn set_pairing(&self, secret: Option<PairingSecret>) -> Result<(), PairingError> {
+        tracing::info!("discovery service set pairing, secret={:?}", secret);
+
+        if let Some(secret) = &secret {
+            secret.check()?;
+        }
+
+        self.pairing.set_secret(secret);
+
+        // The other services get the metadata again, so they notice the change.
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Pair with the service, the secret is checked by the service, and it is
+    /// kept for the later requests of the metadata, which is then delivered
+    /// to the observer as usual.
+    pub async fn pair(&self, id: &str, secret: PairingSecret) -> Result<(), PairingError> {
+        secret.check()?;
+
+        let ip = self
+            .services
+            .lock()
+            .await
+            .get(id)
+            .map(|it| it.ip)
+            .ok_or(PairingError::NotFound)?;
+
+        request_metadata(ip, self.port, &self.local_id, Some(&secret)).await?;
+
+        tracing::info!("discovery service paired, id={}, ip={}", id, ip);
+
+        self.secrets.write().await.insert(id.to_string(), secret);
+
+        // Fetch the metadata again with the next ping.
+        if let Some(service) = self.services.lock().await.get_mut(id) {
+            service.sequence = None;
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_mode(&self, mode: DiscoveryMode) {
+        tracing::info!("discovery service set mode={:?}", mode);
+
+        self.peers.write().await.mode = mode;
+    }
+
+    /// The addresses of the peers that are pinged directly when the unicast
+    /// is used, see `DiscoveryMode`.
+    pub async fn set_peers(&self, peers: Vec<IpAddr>) {
+        tracing::info!("discovery service set peers={:?}", peers);
+
+        self.peers.write().await.known = peers;
+    }
+
+    /// Get the addresses of the peers from a rendezvous server, the url
+    /// returns a json array of the addresses and is polled periodically, the
+    /// addresses are pinged like the peers of `set_peers`.
+    pub async fn set_rendezvous(&self, url: Option<String>) {
+        tracing::info!("discovery service set rendezvous={:?}", url);
+
+        let resolved = match &url {
+            Some(url) => resolve_rendezvous(url).await.unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        let mut peers = self.peers.write().await;
+        peers.rendezvous = url;
+        peers.resolved = resolved;
+    }
+
+    /// Forget the secret of the service.
+    pub async fn unpair(&self, id: &str) {
+        self.secrets.write().await.remove(id);
+    }
+}
+
+/// The events of the discovery service, `local_id` is the id of the current
+/// service.
+#[derive(Debug, Clone)]
+pub enum DiscoveryEvent {
+    Online {
+        local_id: String,
+        id: String,
+        ip: IpAddr,
+    },
+    Offline {
+        local_id: String,
+        id: String,
+        ip: IpAddr,
+    },
+    Metadata {
+        local_id: String,
+        id: String,
+        ip: IpAddr,
+        metadata: Vec<u8>,
+    },
+    DeviceInfo {
+        local_id: String,
+        id: String,
+        ip: IpAddr,
+        info: DeviceInfo,
+    },
+    PairingRequired {
+        local_id: String,
+        id: String,
+        ip: IpAddr,
+    },
+}
+
+/// The observer of [DiscoveryService::with_stream], the events only go to the
+/// stream.
+struct NoopObserver;
+
+impl DiscoveryObserver for NoopObserver {}
+
+/// Forwards the callbacks to the observer of the user and emits them to the
+/// subscribed channels.
+struct EventObserver<O> {
+    events: Arc<Broadcaster<DiscoveryEvent>>,
+    observer: O,
+}
+
+impl<O> DiscoveryObserver for EventObserver<O>
+where
+    O: DiscoveryObserver + Send + Sync,
+{
+    async fn online(&self, local_id: &str, id: &str, ip: IpAddr) {
+        self.observer.online(local_id, id, ip).await;
+        self.events.emit(DiscoveryEvent::Online {
+            local_id: local_id.to_string(),
+            id: id.to_string(),
+            ip,
+        });
+    }
+
+    async fn offline(&self, local_id: &str, id: &str, ip: IpAddr) {
+        self.observer.offline(local_id, id, ip).await;
+        self.events.emit(DiscoveryEvent::Offline {
+            local_id: local_id.to_string(),
+            id: id.to_string(),
+            ip,
+        });
+    }
+
+    async fn on_metadata(&self, local_id: &str, id: &str, ip: IpAddr, metadata: Vec<u8>) {
+        self.observer
+            .on_metadata(local_id, id, ip, metadata.clone())
+            .await;
+
+        self.events.emit(DiscoveryEvent::Metadata {
+            local_id: local_id.to_string(),
+            id: id.to_string(),
+            ip,
+            metadata,
+        });
+    }
+
+    async fn on_device_info(&self, local_id: &str, id: &str, ip: IpAddr, info: DeviceInfo) {
+        self.observer
+            .on_device_info(local_id, id, ip, info.clone())
+            .await;
+
+        self.events.emit(DiscoveryEvent::DeviceInfo {
+            local_id: local_id.to_string(),
+            id: id.to_string(),
+            ip,
+            info,
+        });
+    }
+
+    async fn on_pairing_required(&self, local_id: &str, id: &str, ip: IpAddr) {
+        self.observer.on_pairing_required(local_id, id, ip).await;
+        self.events.emit(DiscoveryEvent::PairingRequired {
+            local_id: local_id.to_string(),
+            id: id.to_string(),
+            ip,
+        });
+    }
+}
+
+/// The stream of the discovery events, see `DiscoveryService::with_stream`.
+pub struct DiscoveryEventStream(UnboundedReceiver<DiscoveryEvent>);
+
+impl DiscoveryEventStream {
+    /// Receive the next event, returns `None` when the service is dropped.
+    pub async fn recv(&mut self) -> Option<DiscoveryEvent> {
+        self.0.recv().await
+    }
+}
+
+impl Stream for DiscoveryEventStream {
+    type Item = DiscoveryEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 struct Ping<'a> {
     id: &'a str,
     sequence: u32,
+    /// The service requires the pairing before the metadata is available.
+    #[serde(default)]
+    pairing: bool,
+    /// The ping is sent directly instead of broadcast.
+    #[serde(default)]
+    unicast: bool,
 }
 
 struct Service {
     ip: IpAddr,
-    sequence: u32,
+    /// `None` means that the metadata is requested again with the next ping.
+    sequence: Option<u32>,
+    metadata: Option<Vec<u8>>,
     update_at: Instant,
 }
 
-async fn request_metadata(ip: IpAddr, port: u16) -> Option<Vec<u8>> {
-    let res = reqwest::get(format!("http://{}:{}/metadata", ip, port))
-        .await
-        .ok()?;
+/// A service found by the discovery.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub id: String,
+    pub ip: IpAddr,
+    pub last_seen: Instant,
+    /// The last received metadata, `None` if the service has not published
+    /// any, or it is not paired.
+    pub metadata: Option<Vec<u8>>,
+}
+
+impl Peer {
+    /// Parse the metadata as the device information, see `DeviceInfo`.
+    pub fn device_info(&self) -> Option<DeviceInfo> {
+        DeviceInfo::decode(self.metadata.as_ref()?)
+    }
+
+    fn from_services(services: &HashMap<String, Service>) -> Vec<Self> {
+        services
+            .iter()
+            .map(|(id, it)| Self {
+                id: id.clone(),
+                ip: it.ip,
+                last_seen: it.update_at,
+                metadata: it.metadata.clone(),
+            })
+            .collect()
+    }
+}
+
+struct HttpState {
+    metadata: Arc<RwLock<Option<Vec<u8>>>>,
+    pairing: Arc<PairingServer>,
+}
+
+// The body is the key agreement message of the peer, the response carries the
+// nonce of the challenge in the header and the message of this side.
+async fn post_challenge(
+    State(state): State<Arc<HttpState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    body: Bytes,
+) -> Response {
+    match state.pairing.challenge(addr.ip(), &body) {
+        Ok((nonce, message)) => ([(HEADER_NONCE, nonce)], message).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+// The metadata of the paired services is encrypted, the others get it as is.
+async fn get_metadata(
+    State(state): State<Arc<HttpState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Response {
+    let cipher = match state.pairing.verify(addr.ip(), &headers) {
+        Ok(it) => it,
+        Err(status) => return status.into_response(),
+    };
+
+    match (state.metadata.read().await.as_ref(), cipher) {
+        (Some(data), Some(cipher)) => seal(&cipher, data).into_response(),
+        (Some(data), None) => data.clone().into_response(),
+        (None, _) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn fetch_metadata<O>(
+    observer: &O,
+    secrets: &Secrets,
+    local_id: &str,
+    ping: &Ping<'_>,
+    ip: IpAddr,
+    port: u16,
+) -> Option<Vec<u8>>
+where
+    O: DiscoveryObserver,
+{
+    let secret = secrets.read().await.get(ping.id).cloned();
+    if ping.pairing && secret.is_none() {
+        observer.on_pairing_required(local_id, ping.id, ip).await;
 
-    if res.status() != StatusCode::OK {
         return None;
     }
 
-    Some(res.bytes().await.ok()?.to_vec())
+    // The secret is kept if the service no longer requires the pairing, it may
+    // require it again later.
+    let secret = secret.filter(|_| ping.pairing);
+    match request_metadata(ip, port, local_id, secret.as_ref()).await {
+        Ok(Some(metadata)) => {
+            notify_metadata(observer, local_id, ping.id, ip, metadata.clone()).await;
+
+            return Some(metadata);
+        }
+        Ok(None) => (),
+        Err(PairingError::Rejected) => {
+            tracing::warn!("discovery pairing is rejected, id={}, ip={}", ping.id, ip);
+
+            secrets.write().await.remove(ping.id);
+            observer.on_pairing_required(local_id, ping.id, ip).await;
+        }
+        Err(e) => {
+            tracing::warn!(
+                "discovery request metadata failed, id={}, err={:?}",
+                ping.id,
+                e
+            );
+        }
+    }
+
+    None
+}
+
+// The raw metadata is always passed on, the device information is parsed from
+// it in addition.
+async fn notify_metadata<O>(observer: &O, local_id: &str, id: &str, ip: IpAddr, metadata: Vec<u8>)
+where
+    O: DiscoveryObserver,
+{
+    let info = DeviceInfo::decode(&metadata);
+
+    observer.on_metadata(local_id, id, ip, metadata).await;
+    if let Some(info) = info {
+        observer.on_device_info(local_id, id, ip, info).await;
+    }
 }
@@ -0,0 +1,77 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use if_addrs::IfAddr;
+
+/// Options of the discovery service, all services of a network have to use the
+/// same port.
+#[derive(Debug, Clone)]
+pub struct DiscoveryOptions {
+    /// The address to listen on, the unspecified address listens on all
+    /// interfaces.
+    pub bind: IpAddr,
+    /// The port of both the pings and the http endpoint.
+    pub port: u16,
+    pub ping_interval: Duration,
+    /// The service is offline when it has not pinged for this long, it should
+    /// be a few times the ping interval, so that a lost ping is tolerated.
+    pub offline_timeout: Duration,
+    /// Where the pings are broadcast, by default the pings are sent to the
+    /// broadcast address of every non-loopback IPv4 interface, so that all
+    /// networks of a device with several interfaces are reached.
+    pub broadcast_addr: Option<Ipv4Addr>,
+}
+
+impl DiscoveryOptions {
+    pub fn new(port: u16) -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            ping_interval: Duration::from_secs(1),
+            offline_timeout: Duration::from_secs(3),
+            broadcast_addr: None,
+            port,
+        }
+    }
+
+    /// The addresses the pings are broadcast to, the interfaces are listed on
+    /// every call, so that a network that is connected later is reached too.
+    pub(crate) fn broadcast_addrs(&self) -> Vec<SocketAddr> {
+        let mut addrs = match self.broadcast_addr {
+            Some(addr) => vec![addr],
+            None => if_addrs::get_if_addrs()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|it| !it.is_loopback())
+                .filter_map(|it| match it.addr {
+                    IfAddr::V4(addr) => Some(addr.broadcast.unwrap_or_else(|| {
+                        Ipv4Addr::from(u32::from(addr.ip) | !u32::from(addr.netmask))
+                    })),
+                    IfAddr::V6(_) => None,
+                })
+                .collect(),
+        };
+
+        // The limited broadcast is the fallback if no interface is found.
+        if addrs.is_empty() {
+            addrs.push(Ipv4Addr::BROADCAST);
+        }
+
+        addrs.sort();
+        addrs.dedup();
+        addrs
+            .into_iter()
+            .map(|it| SocketAddr::new(IpAddr::V4(it), self.port))
+            .collect()
+    }
+}
+
+impl From<SocketAddr> for DiscoveryOptions {
+    fn from(value: SocketAddr) -> Self {
+        Self {
+            bind: value.ip(),
+            ..Self::new(value.port())
+        }
+    }
+}
@@ -0,0 +1,583 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    net::IpAddr,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use axum::http::{HeaderMap, StatusCode};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce, aead::Aead};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+use thiserror::Error;
+use uuid::Uuid;
+
+const HEADER_ID: &str = "x-hylarana-id";
+pub(crate) const HEADER_NONCE: &str = "x-hylarana-nonce";
+const HEADER_PROOF: &str = "x-hylarana-proof";
+
+// Both sides of the key agreement use the same identity, the peers are
+// symmetric.
+const IDENTITY: &[u8] = b"hylarana-pairing";
+
+#[derive(Debug, Error)]
+pub enum PairingError {
+    #[error("the device is not found")]
+    NotFound,
+    #[error("the device does not require the pairing")]
+    NotRequired,
+    #[error("the secret is rejected by the device")]
+    Rejected,
+    #[error("too many failed attempts, try again later")]
+    LockedOut,
+    #[error("the pre-shared key is shorter than {min} characters", min = PairingSecret::MIN_KEY_LEN)]
+    WeakKey,
+    #[error(transparent)]
+    RequestError(#[from] reqwest::Error),
+}
+
+/// The secret shared by the paired devices.
+///
+/// The pin is shown by the sender and entered on the receiver, the pre-shared
+/// key is configured on both sides in advance. The secret itself is never
+/// sent, the peers agree on a session key with SPAKE2, so that the messages
+/// they exchange do not allow guessing the secret offline, each guess costs
+/// one request, and the failed attempts of each peer are limited. The
+/// pre-shared key must be long and random, it is rejected below
+/// `PairingSecret::MIN_KEY_LEN` characters.
+#[derive(Clone, PartialEq, Eq)]
+pub enum PairingSecret {
+    Pin(String),
+    PreSharedKey(String),
+}
+
+impl PairingSecret {
+    pub const MIN_KEY_LEN: usize = 16;
+
+    /// Generate a random pin of 6 digits.
+    pub fn random_pin() -> Self {
+        Self::Pin(format!("{:06}", Uuid::new_v4().as_u128() % 1_000_000))
+    }
+
+    /// Check that the pre-shared key is long enough, the pin is always
+    /// accepted.
+    pub fn check(&self) -> Result<(), PairingError> {
+        match self {
+            Self::PreSharedKey(it) if it.len() < Self::MIN_KEY_LEN => Err(PairingError::WeakKey),
+            _ => Ok(()),
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Pin(it) | Self::PreSharedKey(it) => it.as_bytes(),
+        }
+    }
+
+    /// Start the key agreement, returns the state and the message for the
+    /// peer, both sides start with the same identity.
+    fn start(&self) -> (Spake2<Ed25519Group>, Vec<u8>) {
+        Spake2::<Ed25519Group>::start_symmetric(
+            &Password::new(self.as_bytes()),
+            &Identity::new(IDENTITY),
+        )
+    }
+}
+
+// The secret is not printed in the logs.
+impl fmt::Debug for PairingSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pin(_) => write!(f, "Pin(..)"),
+            Self::PreSharedKey(_) => write!(f, "PreSharedKey(..)"),
+        }
+    }
+}
+
+fn mac(key: &[u8], label: &[u8], nonce: &str, id: &str) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+    mac.update(label);
+    mac.update(nonce.as_bytes());
+    mac.update(id.as_bytes());
+    mac
+}
+
+fn proof(key: &[u8], nonce: &str, id: &str) -> Vec<u8> {
+    mac(key, b"proof", nonce, id)
+        .finalize()
+        .into_bytes()
+        .to_vec()
+}
+
+// Each challenge has its own session key and is used once, so the key of the
+// cipher is never reused and the zero nonce of the cipher is fine.
+fn cipher(key: &[u8], nonce: &str, id: &str) -> ChaCha20Poly1305 {
+    let key = mac(key, b"key", nonce, id).finalize().into_bytes();
+
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|it| format!("{:02x}", it)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The failed attempts of a peer.
+struct Lockout {
+    failures: u32,
+    last: Instant,
+    until: Option<Instant>,
+}
+
+impl Lockout {
+    /// The failures are forgotten a while after the last one, so that the peers
+    /// that failed once are not kept forever.
+    fn is_expired(&self, timeout: Duration) -> bool {
+        self.until.unwrap_or(self.last + timeout) <= Instant::now()
+    }
+}
+
+/// A challenge issued to a peer, with the session key agreed with it.
+struct Challenge {
+    nonce: String,
+    key: Vec<u8>,
+    at: Instant,
+}
+
+/// The pairing of the published metadata, the challenges are issued by the
+/// http endpoint of the discovery and consumed by the metadata requests.
+#[derive(Default)]
+pub(crate) struct PairingServer {
+    secret: RwLock<Option<PairingSecret>>,
+    /// Keyed by the address of the peer, a peer only ever replaces its own
+    /// challenges.
+    challenges: Mutex<HashMap<IpAddr, VecDeque<Challenge>>>,
+    /// Keyed by the address of the peer, so that a peer with the wrong secret
+    /// does not lock out the others.
+    lockouts: Mutex<HashMap<IpAddr, Lockout>>,
+}
+
+impl PairingServer {
+    const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(30);
+    const CHALLENGE_INTERVAL: Duration = Duration::from_millis(200);
+    const MAX_CHALLENGES: usize = 4;
+    const MAX_PEERS: usize = 64;
+    const MAX_FAILURES: u32 = 5;
+    const LOCKOUT_DURATION: Duration = Duration::from_secs(30);
+
+    pub(crate) fn set_secret(&self, secret: Option<PairingSecret>) {
+        *self.secret.write().unwrap() = secret;
+        self.challenges.lock().unwrap().clear();
+    }
+
+    pub(crate) fn is_required(&self) -> bool {
+        self.secret.read().unwrap().is_some()
+    }
+
+    fn is_locked_out(&self, ip: IpAddr) -> bool {
+        let mut lockouts = self.lockouts.lock().unwrap();
+        lockouts.retain(|_, it| !it.is_expired(Self::LOCKOUT_DURATION));
+        lockouts.get(&ip).is_some_and(|it| it.until.is_some())
+    }
+
+    /// Issue a challenge to the peer, the message of the peer is the start of
+    /// its key agreement, returns the nonce of the challenge and the message
+    /// of this side.
+    ///
+    /// Each peer gets at most one challenge per `CHALLENGE_INTERVAL` and keeps
+    /// at most `MAX_CHALLENGES`, the new peers are refused once `MAX_PEERS`
+    /// peers have pending challenges.
+    pub(crate) fn challenge(
+        &self,
+        ip: IpAddr,
+        message: &[u8],
+    ) -> Result<(String, Vec<u8>), StatusCode> {
+        let Some(secret) = self.secret.read().unwrap().clone() else {
+            return Err(StatusCode::NOT_FOUND);
+        };
+
+        if self.is_locked_out(ip) {
+            return Err(StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        let mut challenges = self.challenges.lock().unwrap();
+        challenges.retain(|_, it| {
+            it.retain(|it| it.at.elapsed() < Self::CHALLENGE_TIMEOUT);
+            !it.is_empty()
+        });
+
+        if !challenges.contains_key(&ip) && challenges.len() >= Self::MAX_PEERS {
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        let pending = challenges.entry(ip).or_default();
+        if pending
+            .back()
+            .is_some_and(|it| it.at.elapsed() < Self::CHALLENGE_INTERVAL)
+        {
+            return Err(StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        let (state, reply) = secret.start();
+        let key = state.finish(message).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+        if pending.len() >= Self::MAX_CHALLENGES {
+            pending.pop_front();
+        }
+
+        let nonce = Uuid::new_v4().to_string();
+        pending.push_back(Challenge {
+            nonce: nonce.clone(),
+            at: Instant::now(),
+            key,
+        });
+
+        Ok((nonce, reply))
+    }
+
+    /// Check the proof of the request, returns the cipher of the response, or
+    /// `None` if the pairing is not required.
+    pub(crate) fn verify(
+        &self,
+        ip: IpAddr,
+        headers: &HeaderMap,
+    ) -> Result<Option<ChaCha20Poly1305>, StatusCode> {
+        if !self.is_required() {
+            return Ok(None);
+        }
+
+        if self.is_locked_out(ip) {
+            return Err(StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        let header = |key: &str| headers.get(key).and_then(|it| it.to_str().ok());
+        let (Some(id), Some(nonce), Some(proof)) = (
+            header(HEADER_ID),
+            header(HEADER_NONCE),
+            header(HEADER_PROOF).and_then(from_hex),
+        ) else {
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+
+        // The challenge is consumed whether the proof is right or not, and it
+        // is only valid for the peer it was issued to.
+        let key = {
+            let mut challenges = self.challenges.lock().unwrap();
+            let pending = challenges.get_mut(&ip).ok_or(StatusCode::UNAUTHORIZED)?;
+            let index = pending
+                .iter()
+                .position(|it| it.nonce == nonce && it.at.elapsed() < Self::CHALLENGE_TIMEOUT)
+                .ok_or(StatusCode::UNAUTHORIZED)?;
+
+            pending.remove(index).unwrap().key
+        };
+
+        if mac(&key, b"proof", nonce, id).verify_slice(&proof).is_err() {
+            let mut lockouts = self.lockouts.lock().unwrap();
+            let lockout = lockouts.entry(ip).or_insert_with(|| Lockout {
+                failures: 0,
+                last: Instant::now(),
+                until: None,
+            });
+
+            lockout.failures += 1;
+            lockout.last = Instant::now();
+            if lockout.failures >= Self::MAX_FAILURES {
+                tracing::warn!(
+                    "discovery pairing is locked out, too many failed attempts, ip={}",
+                    ip
+                );
+
+                lockout.until = Some(Instant::now() + Self::LOCKOUT_DURATION);
+            }
+
+            tracing::warn!("discovery pairing rejected, id={}, ip={}", id, ip);
+
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        self.lockouts.lock().unwrap().remove(&ip);
+
+        Ok(Some(cipher(&key, nonce, id)))
+    }
+}
+
+pub(crate) fn seal(cipher: &ChaCha20Poly1305, data: &[u8]) -> Vec<u8> {
+    cipher.encrypt(&Nonce::default(), data).unwrap()
+}
+
+fn open(cipher: &ChaCha20Poly1305, data: &[u8]) -> Result<Vec<u8>, PairingError> {
+    cipher
+        .decrypt(&Nonce::default(), data)
+        .map_err(|_| PairingError::Rejected)
+}
+
+/// Request the metadata of the service, `Ok(None)` means that the service has
+/// not published any metadata.
+pub(crate) async fn request_metadata(
+    ip: IpAddr,
+    port: u16,
+    local_id: &str,
+    secret: Option<&PairingSecret>,
+) -> Result<Option<Vec<u8>>, PairingError> {
+    let client = reqwest::Client::new();
+    let mut request = client.get(format!("http://{}:{}/metadata", ip, port));
+
+    let mut cipher = None;
+    if let Some(secret) = secret {
+        let (state, message) = secret.start();
+        let res = client
+            .post(format!("http://{}:{}/pairing/challenge", ip, port))
+            .body(message)
+            .send()
+            .await?;
+
+        match res.status() {
+            StatusCode::NOT_FOUND => return Err(PairingError::NotRequired),
+            StatusCode::TOO_MANY_REQUESTS => return Err(PairingError::LockedOut),
+            _ => (),
+        }
+
+        let res = res.error_for_status()?;
+        let nonce = res
+            .headers()
+            .get(HEADER_NONCE)
+            .and_then(|it| it.to_str().ok())
+            .map(|it| it.to_string())
+            .ok_or(PairingError::Rejected)?;
+
+        let key = state
+            .finish(&res.bytes().await?)
+            .map_err(|_| PairingError::Rejected)?;
+
+        request = request
+            .header(HEADER_ID, local_id)
+            .header(HEADER_NONCE, &nonce)
+            .header(HEADER_PROOF, to_hex(&proof(&key, &nonce, local_id)));
+
+        cipher = Some(self::cipher(&key, &nonce, local_id));
+    }
+
+    let res = request.send().await?;
+    match res.status() {
+        StatusCode::NOT_FOUND => return Ok(None),
+        StatusCode::UNAUTHORIZED => return Err(PairingError::Rejected),
+        StatusCode::TOO_MANY_REQUESTS => return Err(PairingError::LockedOut),
+        _ => (),
+    }
+
+    let data = res.error_for_status()?.bytes().await?;
+    Ok(Some(match cipher {
+        Some(cipher) => open(&cipher, &data)?,
+        None => data.to_vec(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    const ID: &str = "receiver";
+
+    fn server(secret: &str) -> PairingServer {
+        let server = PairingServer::default();
+        server.set_secret(Some(PairingSecret::Pin(secret.to_string())));
+        server
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(192, 168, 1, last))
+    }
+
+    /// Move the challenges of the server back in time.
+    fn backdate(server: &PairingServer, duration: Duration) {
+        for it in server.challenges.lock().unwrap().values_mut().flatten() {
+            it.at = it.at.checked_sub(duration).unwrap();
+        }
+    }
+
+    /// Runs the side of the peer, returns the headers of the metadata request
+    /// and the cipher of the response.
+    fn request(
+        server: &PairingServer,
+        ip: IpAddr,
+        secret: &str,
+    ) -> Result<(HeaderMap, ChaCha20Poly1305), StatusCode> {
+        let (state, message) = PairingSecret::Pin(secret.to_string()).start();
+        let (nonce, reply) = server.challenge(ip, &message)?;
+        let key = state.finish(&reply).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(HEADER_ID, HeaderValue::from_static(ID));
+        headers.insert(HEADER_NONCE, nonce.parse().unwrap());
+        headers.insert(
+            HEADER_PROOF,
+            to_hex(&proof(&key, &nonce, ID)).parse().unwrap(),
+        );
+
+        Ok((headers, cipher(&key, &nonce, ID)))
+    }
+
+    #[test]
+    fn accepts_correct_proof() {
+        let server = server("123456");
+        let (headers, _) = request(&server, ip(2), "123456").unwrap();
+
+        assert!(server.verify(ip(2), &headers).unwrap().is_some());
+    }
+
+    #[test]
+    fn rejects_wrong_proof_and_locks_out() {
+        let server = server("123456");
+
+        for _ in 0..PairingServer::MAX_FAILURES {
+            let (headers, _) = request(&server, ip(2), "654321").unwrap();
+            assert_eq!(
+                server.verify(ip(2), &headers).err(),
+                Some(StatusCode::UNAUTHORIZED)
+            );
+
+            backdate(&server, PairingServer::CHALLENGE_INTERVAL);
+        }
+
+        assert_eq!(
+            request(&server, ip(2), "123456").err(),
+            Some(StatusCode::TOO_MANY_REQUESTS)
+        );
+
+        // The other peers are not locked out.
+        let (headers, _) = request(&server, ip(3), "123456").unwrap();
+        assert!(server.verify(ip(3), &headers).unwrap().is_some());
+    }
+
+    #[test]
+    fn rejects_replayed_nonce() {
+        let server = server("123456");
+        let (headers, _) = request(&server, ip(2), "123456").unwrap();
+
+        assert!(server.verify(ip(2), &headers).is_ok());
+        assert_eq!(
+            server.verify(ip(2), &headers).err(),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[test]
+    fn rejects_challenge_of_other_peer() {
+        let server = server("123456");
+        let (headers, _) = request(&server, ip(2), "123456").unwrap();
+
+        assert_eq!(
+            server.verify(ip(3), &headers).err(),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[test]
+    fn expires_challenge() {
+        let server = server("123456");
+        let (headers, _) = request(&server, ip(2), "123456").unwrap();
+
+        backdate(&server, PairingServer::CHALLENGE_TIMEOUT);
+        assert_eq!(
+            server.verify(ip(2), &headers).err(),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[test]
+    fn limits_challenges_per_peer() {
+        let server = server("123456");
+        let (headers, _) = request(&server, ip(2), "123456").unwrap();
+
+        // Too soon after the previous challenge.
+        request(&server, ip(3), "123456").unwrap();
+        assert_eq!(
+            request(&server, ip(3), "123456").err(),
+            Some(StatusCode::TOO_MANY_REQUESTS)
+        );
+
+        // A peer only replaces its own challenges.
+        for _ in 0..PairingServer::MAX_CHALLENGES * 2 {
+            backdate(&server, PairingServer::CHALLENGE_INTERVAL);
+            request(&server, ip(3), "123456").unwrap();
+        }
+
+        assert_eq!(
+            server.challenges.lock().unwrap()[&ip(3)].len(),
+            PairingServer::MAX_CHALLENGES
+        );
+
+        assert!(server.verify(ip(2), &headers).unwrap().is_some());
+    }
+
+    #[test]
+    fn refuses_new_peers_when_full() {
+        let server = server("123456");
+        for i in 0..PairingServer::MAX_PEERS {
+            request(&server, ip(i as u8), "123456").unwrap();
+        }
+
+        assert_eq!(
+            request(&server, ip(255), "123456").err(),
+            Some(StatusCode::SERVICE_UNAVAILABLE)
+        );
+    }
+
+    #[test]
+    fn seals_and_opens() {
+        let server = server("123456");
+        let (headers, peer) = request(&server, ip(2), "123456").unwrap();
+        let cipher = server.verify(ip(2), &headers).unwrap().unwrap();
+
+        let sealed = seal(&cipher, b"metadata");
+        assert_ne!(sealed.as_slice(), b"metadata");
+        assert_eq!(open(&peer, &sealed).unwrap(), b"metadata");
+
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert!(open(&peer, &tampered).is_err());
+    }
+
+    #[test]
+    fn rejects_short_key() {
+        assert!(
+            PairingSecret::PreSharedKey("short".to_string())
+                .check()
+                .is_err()
+        );
+        assert!(
+            PairingSecret::PreSharedKey("0123456789abcdef".to_string())
+                .check()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn does_not_require_without_secret() {
+        let server = PairingServer::default();
+
+        assert!(server.verify(ip(2), &HeaderMap::new()).unwrap().is_none());
+        assert_eq!(
+            server.challenge(ip(2), &[]).err(),
+            Some(StatusCode::NOT_FOUND)
+        );
+    }
+}
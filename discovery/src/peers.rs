@@ -0,0 +1,87 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// How the service finds the other services.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiscoveryMode {
+    /// Broadcast the pings to the local network.
+    Broadcast,
+    /// Only send the pings directly to the known peers, for the networks that
+    /// block the broadcast, such as the isolated access points.
+    Unicast,
+    /// Broadcast the pings, and also send them directly to the known peers
+    /// when no broadcast ping has been received for a while.
+    #[default]
+    Auto,
+}
+
+/// The peers that are pinged directly, the services that have been found are
+/// pinged too, so that it is enough if only one side knows the other.
+pub(crate) struct Peers {
+    pub(crate) mode: DiscoveryMode,
+    pub(crate) known: Vec<IpAddr>,
+    pub(crate) rendezvous: Option<String>,
+    /// The addresses returned by the rendezvous server.
+    pub(crate) resolved: Vec<IpAddr>,
+    last_broadcast: Instant,
+}
+
+impl Default for Peers {
+    fn default() -> Self {
+        Self {
+            mode: DiscoveryMode::default(),
+            known: Vec::new(),
+            rendezvous: None,
+            resolved: Vec::new(),
+            last_broadcast: Instant::now(),
+        }
+    }
+}
+
+impl Peers {
+    /// In the auto mode, the pings are sent directly after the broadcast has
+    /// been silent for this long.
+    const FALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub(crate) fn broadcast_received(&mut self) {
+        self.last_broadcast = Instant::now();
+    }
+
+    pub(crate) fn is_broadcast(&self) -> bool {
+        self.mode != DiscoveryMode::Unicast
+    }
+
+    pub(crate) fn is_unicast(&self) -> bool {
+        match self.mode {
+            DiscoveryMode::Broadcast => false,
+            DiscoveryMode::Unicast => true,
+            DiscoveryMode::Auto => self.last_broadcast.elapsed() >= Self::FALLBACK_TIMEOUT,
+        }
+    }
+
+    /// The addresses to ping directly, the found services are given by the
+    /// caller.
+    pub(crate) fn targets(&self, services: impl Iterator<Item = IpAddr>) -> Vec<IpAddr> {
+        let mut targets = self
+            .known
+            .iter()
+            .chain(self.resolved.iter())
+            .copied()
+            .chain(services)
+            .collect::<Vec<_>>();
+
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+}
+
+/// The rendezvous server returns the addresses of the peers as a json array,
+/// such as `["192.168.1.2", "10.0.0.3"]`, it can be a static file.
+pub(crate) async fn resolve_rendezvous(url: &str) -> Option<Vec<IpAddr>> {
+    let res = reqwest::get(url).await.ok()?.error_for_status().ok()?;
+
+    serde_json::from_slice(&res.bytes().await.ok()?).ok()
+}
@@ -6,12 +6,14 @@ use std::{
 use anyhow::Result;
 use clap::Parser;
 use hylarana::{
-    AVFrameStreamPlayer, AVFrameStreamPlayerOptions, AudioOptions, Capture, DiscoveryObserver,
-    DiscoveryService, HylaranaReceiver, HylaranaReceiverOptions, HylaranaSender,
-    HylaranaSenderMediaOptions, HylaranaSenderOptions, HylaranaSenderTrackOptions,
-    MediaStreamDescription, Size, SourceType, TransportOptions, VideoDecoderType, VideoEncoderType,
-    VideoOptions, VideoRenderOptionsBuilder, VideoRenderSurfaceOptions, create_receiver,
-    create_sender, get_runtime_handle, shutdown, startup,
+    AVFrameStreamPlayer, AVFrameStreamPlayerOptions, AudioMonitor, AudioOptions,
+    AudioRenderOptions, Capture, DiscoveryObserver, DiscoveryService, HylaranaReceiver,
+    HylaranaReceiverOptions, HylaranaSender, HylaranaSenderMediaOptions, HylaranaSenderOptions,
+    HylaranaSenderTrackOptions, MediaStreamDescription, ProtectedContentPolicy, RenditionSelection,
+    SenderInput, Size, SourceFailover, SourceType, TransportOptions, VideoDecoderType,
+    VideoEncoderType, VideoOptions, VideoOutput, VideoRenderOptionsBuilder,
+    VideoRenderSurfaceOptions, WindowFit, create_receiver, create_sender, get_runtime_handle,
+    shutdown, startup,
 };
 
 use winit::{
@@ -89,6 +91,14 @@ impl Sender {
                 options: AudioOptions {
                     sample_rate: 48000,
                     bit_rate: 64000,
+                    channels: None,
+                    application: None,
+                    complexity: None,
+                    fec: false,
+                    dtx: false,
+                    frame_duration: None,
+                    silence: None,
+                    monitor: AudioMonitor::Captured,
                 },
             });
         }
@@ -96,6 +106,14 @@ impl Sender {
         let options = HylaranaSenderOptions {
             media: HylaranaSenderMediaOptions { video, audio },
             transport: TransportOptions::default(),
+            input: SenderInput::Capture,
+            mode: None,
+            overlay: None,
+            privacy: None,
+            allow_list: None,
+            failover: SourceFailover::Close,
+            allow_display_sleep: false,
+            bandwidth_cap: None,
         };
 
         let sender = create_sender(
@@ -124,8 +142,11 @@ impl Sender {
     }
 }
 
-#[allow(unused)]
-struct Receiver(HylaranaReceiver);
+struct Receiver {
+    player: Arc<AVFrameStreamPlayer<'static>>,
+    #[allow(unused)]
+    receiver: HylaranaReceiver,
+}
 
 impl Receiver {
     fn new(
@@ -136,24 +157,41 @@ impl Receiver {
         let options = HylaranaReceiverOptions {
             codec: CONFIG.decoder,
             transport: TransportOptions::default(),
+            video_output: VideoOutput::Native,
+            reconnect: None,
+            timeshift_seconds: 0,
+            sink_queue: None,
+            packet_sink: None,
+            sync_group: None,
+            rendition: RenditionSelection::Auto,
+            mode: None,
+            allow_display_sleep: false,
         };
 
-        let receiver = create_receiver(
-            addr,
-            &options,
-            &description,
-            AVFrameStreamPlayer::new(AVFrameStreamPlayerOptions::All(
+        let player = AVFrameStreamPlayer::new(
+            AVFrameStreamPlayerOptions::All(
                 VideoRenderOptionsBuilder::new(VideoRenderSurfaceOptions {
                     size: window.size(),
-                    window,
+                    window: window.clone(),
                 })
                 .from_receiver(&description, &options)
                 .build(),
-            ))?,
-            (),
+                AudioRenderOptions::default(),
+            )
+            .with_mode(description.mode),
         )?;
 
-        Ok(Self(receiver))
+        // The window follows the aspect ratio of the remote screen, the resize of the
+        // window comes back as a resized event.
+        player.on_source_size(WindowFit::AspectRatio, move |it| {
+            if let Some(size) = it.window {
+                let _ = window.request_inner_size(PhysicalSize::new(size.width, size.height));
+            }
+        });
+
+        let receiver = create_receiver(addr, &options, &description, player.clone(), ())?;
+
+        Ok(Self { player, receiver })
     }
 }
 
@@ -210,6 +248,14 @@ impl ApplicationHandler<Events> for App {
 
                 event_loop.exit();
             }
+            WindowEvent::Resized(size) => {
+                if let Some(receiver) = &self.receiver {
+                    receiver.player.resize(Size {
+                        width: size.width,
+                        height: size.height,
+                    });
+                }
+            }
             WindowEvent::KeyboardInput { event, .. } => {
                 if !event.repeat && event.state == ElementState::Released {
                     if let PhysicalKey::Code(key) = event.physical_key {
@@ -332,6 +378,18 @@ impl Configure {
             height: self.height,
             bit_rate: 10000000,
             key_frame_interval: 21,
+            preset: None,
+            tune: None,
+            profile: None,
+            rate_control: None,
+            threads: None,
+            threading: None,
+            extra_options: Vec::new(),
+            content_hint: None,
+            skip_unchanged_frames: false,
+            exclude_windows: Vec::new(),
+            protected_content: ProtectedContentPolicy::Stream,
+            simulcast: Vec::new(),
         }
     }
 }
@@ -0,0 +1,234 @@
+//! A snapshot of the environment and the state of the process, so that the
+//! apps can attach it to a bug report, for example when a codec is not found
+//! on the machine of a user.
+
+use std::{
+    net::SocketAddr,
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::{Arc, LazyLock, Weak},
+};
+
+use parking_lot::Mutex;
+use transport::{BufferPool, BufferPoolStats, ConnectionStats, PacingStats, TransportSender};
+
+#[cfg(feature = "metrics")]
+use crate::MetricsSnapshot;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The versions of this library and the native libraries linked into it.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Versions {
+    pub hylarana: String,
+    pub ffmpeg: String,
+    pub srt: String,
+    pub os: String,
+    pub arch: String,
+}
+
+/// A video codec and whether it can be used, see [codec::probe].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct CodecDiagnostics {
+    /// The name of the codec in ffmpeg, for example `hevc_nvenc`.
+    pub name: String,
+    pub encoder: bool,
+    pub hardware: bool,
+    /// The codec is supported on this platform.
+    pub supported: bool,
+    /// The codec is built into the linked ffmpeg.
+    pub available: bool,
+}
+
+/// A graphics adapter of the system, the dedicated video memory is only
+/// known on windows.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct AdapterDiagnostics {
+    pub name: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub dedicated_video_memory: u64,
+}
+
+/// A sender that was alive at the time of the collection.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct SenderDiagnostics {
+    pub local_addr: SocketAddr,
+    pub viewers: u32,
+    pub connections: Vec<ConnectionStats>,
+    /// Only when the bandwidth is capped.
+    pub pacing: Option<PacingStats>,
+}
+
+/// The state of the transport at the time of the collection.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct TransportDiagnostics {
+    pub buffer_pool: BufferPoolStats,
+    pub senders: Vec<SenderDiagnostics>,
+    /// The counters of the senders and the receivers, only with the `metrics`
+    /// feature.
+    #[cfg(feature = "metrics")]
+    pub metrics: MetricsSnapshot,
+}
+
+/// Everything [collect] gathered, a part that failed is left empty and the
+/// reason is in `errors`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct DiagnosticsBundle {
+    /// The time of the collection in RFC 3339.
+    pub time: String,
+    pub versions: Versions,
+    pub codecs: Vec<CodecDiagnostics>,
+    pub adapters: Vec<AdapterDiagnostics>,
+    /// The last lines of the logs, the oldest first.
+    pub logs: Vec<String>,
+    pub transport: TransportDiagnostics,
+    pub errors: Vec<String>,
+}
+
+/// The transports of the senders of the process, the dropped senders are
+/// removed when the next sender is registered.
+static SENDERS: LazyLock<Mutex<Vec<Weak<TransportSender>>>> = LazyLock::new(Default::default);
+
+/// Adds a sender to the transport part of [collect].
+pub(crate) fn register_sender(transport: &Arc<TransportSender>) {
+    let mut senders = SENDERS.lock();
+
+    senders.retain(|it| it.strong_count() > 0);
+    senders.push(Arc::downgrade(transport));
+}
+
+/// Runs a part of the collection, a panic is recorded as an error instead of
+/// unwinding, the bundle is usually collected after something went wrong.
+fn guard<T>(errors: &mut Vec<String>, part: &str, func: impl FnOnce() -> T) -> Option<T> {
+    match catch_unwind(AssertUnwindSafe(func)) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            let message = e
+                .downcast_ref::<&str>()
+                .map(|it| it.to_string())
+                .or_else(|| e.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+
+            errors.push(format!("{} panicked: {}", part, message));
+
+            None
+        }
+    }
+}
+
+/// Gathers the diagnostics of the process, this never fails and is cheap
+/// enough to call from a bug report button, the codecs are probed without
+/// opening the hardware devices.
+pub fn collect() -> DiagnosticsBundle {
+    let mut errors = Vec::new();
+
+    let versions = guard(&mut errors, "versions", || Versions {
+        hylarana: env!("CARGO_PKG_VERSION").to_string(),
+        ffmpeg: codec::version(),
+        srt: transport::version(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+    })
+    .unwrap_or_default();
+
+    let codecs = guard(&mut errors, "codecs", || {
+        codec::probe()
+            .into_iter()
+            .map(|it| CodecDiagnostics {
+                name: match it.codec {
+                    codec::CodecType::Encoder(kind) => kind.to_string(),
+                    codec::CodecType::Decoder(kind) => kind.to_string(),
+                },
+                encoder: it.codec.is_encoder(),
+                hardware: it.codec.is_hardware(),
+                supported: it.supported,
+                available: it.available,
+            })
+            .collect()
+    })
+    .unwrap_or_default();
+
+    let adapters = match guard(&mut errors, "adapters", adapters) {
+        Some(Ok(adapters)) => adapters,
+        Some(Err(e)) => {
+            errors.push(format!("failed to enumerate the adapters: {}", e));
+
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+
+    let logs = guard(&mut errors, "logs", common::logger::recent_logs).unwrap_or_default();
+
+    let transport = guard(&mut errors, "transport", || TransportDiagnostics {
+        buffer_pool: BufferPool::global().stats(),
+        senders: SENDERS
+            .lock()
+            .iter()
+            .filter_map(|it| it.upgrade())
+            .map(|it| SenderDiagnostics {
+                local_addr: it.local_addr(),
+                viewers: it.get_viewers(),
+                connections: it.connections(),
+                pacing: it.get_pacing_stats(),
+            })
+            .collect(),
+        #[cfg(feature = "metrics")]
+        metrics: crate::Metrics::global().snapshot(),
+    })
+    .unwrap_or_default();
+
+    DiagnosticsBundle {
+        time: chrono::Local::now().to_rfc3339(),
+        versions,
+        codecs,
+        adapters,
+        logs,
+        transport,
+        errors,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn adapters() -> Result<Vec<AdapterDiagnostics>, String> {
+    Ok(common::win32::GpuAdapter::enumerate()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|it| AdapterDiagnostics {
+            name: it.name,
+            vendor_id: it.vendor_id,
+            device_id: it.device_id,
+            dedicated_video_memory: it.dedicated_video_memory,
+        })
+        .collect())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn adapters() -> Result<Vec<AdapterDiagnostics>, String> {
+    use renderer::wgpu::{Backends, Instance, InstanceDescriptor};
+
+    let backends = Backends::all();
+    let instance = Instance::new(InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+
+    Ok(instance
+        .enumerate_adapters(backends)
+        .into_iter()
+        .map(|it| it.get_info())
+        .map(|it| AdapterDiagnostics {
+            name: format!("{} ({:?})", it.name, it.backend),
+            vendor_id: it.vendor,
+            device_id: it.device,
+            dedicated_video_memory: 0,
+        })
+        .collect())
+}
@@ -0,0 +1,226 @@
+use std::{error::Error as StdError, io};
+
+use common::codec::VideoEncoderType;
+use thiserror::Error;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// What went wrong in the transport layer, derived from the kind of the
+/// underlying io error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportErrorKind {
+    /// The address is already in use or not available on this host.
+    AddressUnavailable,
+    /// The remote side refused or reset the connection.
+    ConnectionFailed,
+    TimedOut,
+    InvalidInput,
+    Other,
+}
+
+impl From<io::ErrorKind> for TransportErrorKind {
+    fn from(value: io::ErrorKind) -> Self {
+        match value {
+            io::ErrorKind::AddrInUse | io::ErrorKind::AddrNotAvailable => Self::AddressUnavailable,
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected => Self::ConnectionFailed,
+            io::ErrorKind::TimedOut => Self::TimedOut,
+            io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => Self::InvalidInput,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("transport error, kind={kind:?}: {source}")]
+pub struct TransportError {
+    pub kind: TransportErrorKind,
+    #[source]
+    pub source: io::Error,
+}
+
+impl From<io::Error> for TransportError {
+    fn from(source: io::Error) -> Self {
+        Self {
+            kind: source.kind().into(),
+            source,
+        }
+    }
+}
+
+/// Which codec failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecErrorKind {
+    VideoEncoder,
+    VideoDecoder,
+    AudioEncoder,
+    AudioDecoder,
+}
+
+#[derive(Debug, Error)]
+#[error("codec error, kind={kind:?}: {source}")]
+pub struct CodecError {
+    pub kind: CodecErrorKind,
+    #[source]
+    pub source: BoxError,
+}
+
+/// Which capture failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureErrorKind {
+    Video,
+    Audio,
+    /// The media file or the network stream of a `SourceType::Url` source.
+    MediaFile,
+    TestPattern,
+    /// The thread of a capture or a source watcher could not be started.
+    Io,
+}
+
+#[derive(Debug, Error)]
+#[error("capture error, kind={kind:?}: {source}")]
+pub struct CaptureError {
+    pub kind: CaptureErrorKind,
+    #[source]
+    pub source: capture::CaptureError,
+}
+
+impl From<capture::CaptureError> for CaptureError {
+    fn from(source: capture::CaptureError) -> Self {
+        Self {
+            kind: match source {
+                capture::CaptureError::AudioCaptureError(_) => CaptureErrorKind::Audio,
+                capture::CaptureError::ScreenCaptureError(_) => CaptureErrorKind::Video,
+                capture::CaptureError::MediaFileCaptureError(_) => CaptureErrorKind::MediaFile,
+                capture::CaptureError::TestPatternCaptureError(_) => CaptureErrorKind::TestPattern,
+                capture::CaptureError::IoError(_) => CaptureErrorKind::Io,
+                capture::CaptureError::UnsupportedSource(capture::SourceType::Audio) => {
+                    CaptureErrorKind::Audio
+                }
+                capture::CaptureError::UnsupportedSource(_) => CaptureErrorKind::Video,
+            },
+            source,
+        }
+    }
+}
+
+/// Which part of the rendering failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderErrorKind {
+    Video,
+    Audio,
+    Overlay,
+}
+
+#[derive(Debug, Error)]
+#[error("render error, kind={kind:?}: {source}")]
+pub struct RenderError {
+    pub kind: RenderErrorKind,
+    #[source]
+    pub source: BoxError,
+}
+
+/// The error of the sdk, the category tells which layer failed, and each
+/// category has a kind for the programmatic handling, the original error is
+/// kept as the source.
+#[derive(Debug, Error)]
+pub enum HylaranaError {
+    #[error(transparent)]
+    TransportError(#[from] TransportError),
+    #[error(transparent)]
+    CodecError(#[from] CodecError),
+    #[error(transparent)]
+    CaptureError(#[from] CaptureError),
+    #[error(transparent)]
+    RenderError(#[from] RenderError),
+    #[error(transparent)]
+    #[cfg(target_os = "windows")]
+    Win32Error(#[from] common::win32::windows::core::Error),
+    #[error("the stream has no video")]
+    NotFoundVideo,
+    #[error("the stream has no audio")]
+    NotFoundAudio,
+    #[error("the time shift of the receiver is not enabled")]
+    TimeshiftDisabled,
+    #[error("the frames of the sender are captured, they can not be pushed")]
+    PushDisabled,
+    #[error("the stream is closed")]
+    StreamClosed,
+    #[error("the bandwidth cap is too low for the audio of the stream, cap={0}")]
+    BandwidthCapTooLow(u64),
+    #[error("too many simulcast renditions of the video, renditions={0}")]
+    TooManyRenditions(usize),
+    #[error("the runtime was shut down before the stream was created")]
+    RuntimeShutdown,
+    #[error("the frames in the system memory can not be encoded by the encoder, codec={0:?}")]
+    SoftwareFramesNotSupported(VideoEncoderType),
+    #[error("the windows can not be hidden on this platform")]
+    PrivacyNotSupported,
+    #[error("the protected content is not reported on this platform")]
+    ProtectedContentNotSupported,
+    #[error("the sender sends the packets of another version, protocol={0}")]
+    ProtocolMismatch(u8),
+}
+
+impl From<capture::CaptureError> for HylaranaError {
+    fn from(value: capture::CaptureError) -> Self {
+        Self::CaptureError(value.into())
+    }
+}
+
+macro_rules! impl_from_error {
+    ($category:ident, $kind:expr, $error:ty) => {
+        impl From<$error> for HylaranaError {
+            fn from(value: $error) -> Self {
+                Self::$category($category {
+                    kind: $kind,
+                    source: Box::new(value),
+                })
+            }
+        }
+    };
+}
+
+impl_from_error!(
+    CodecError,
+    CodecErrorKind::VideoEncoder,
+    codec::VideoEncoderError
+);
+
+impl_from_error!(
+    CodecError,
+    CodecErrorKind::VideoDecoder,
+    codec::VideoDecoderError
+);
+
+impl_from_error!(
+    CodecError,
+    CodecErrorKind::AudioEncoder,
+    codec::AudioEncoderError
+);
+
+impl_from_error!(
+    CodecError,
+    CodecErrorKind::AudioDecoder,
+    codec::AudioDecoderError
+);
+
+impl_from_error!(
+    RenderError,
+    RenderErrorKind::Video,
+    super::player::VideoRenderError
+);
+
+impl_from_error!(
+    RenderError,
+    RenderErrorKind::Audio,
+    super::player::AudioRenderError
+);
+
+impl_from_error!(
+    RenderError,
+    RenderErrorKind::Overlay,
+    super::overlay::OverlayError
+);
@@ -0,0 +1,147 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use capture::{ProtectedContent, Source};
+use common::events::Broadcaster;
+use transport::StreamType;
+
+use super::{AudioLevel, CloseReason, MediaStreamObserver};
+
+/// The events of the sender or the receiver, this is the channel based
+/// alternative of the `MediaStreamObserver`, see `HylaranaSender::events` and
+/// `HylaranaReceiver::events`.
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// A receiver is connected to the sender, only the sender has this event.
+    Connected(SocketAddr),
+    /// A receiver joined the stream, with the number of the receivers, only
+    /// the sender has this event.
+    ViewerJoined(u32),
+    /// A receiver left the stream or stopped sending its heartbeats, with the
+    /// number of the receivers, only the sender has this event.
+    ViewerLeft(u32),
+    /// The stream is closed, no more events follow this.
+    Closed(CloseReason),
+    AudioDeviceChanged(Source),
+    /// The captured video source is gone, only the sender has this event.
+    SourceLost(Source),
+    VideoSourceChanged(Source),
+    SignalLost,
+    SignalRestored,
+    /// The system started or stopped hiding content from the captured screen,
+    /// `None` when nothing is hidden anymore, only the sender has this event.
+    ProtectedContent(Option<ProtectedContent>),
+    /// The receiver lost the connection and is reconnecting, attempt starts
+    /// from 1, only the receiver has this event.
+    Reconnecting {
+        attempt: u32,
+    },
+    Reconnected,
+    /// Packets of the stream were lost, count is the number of the lost
+    /// packets, only the receiver has this event.
+    PacketLoss {
+        stream: StreamType,
+        count: u32,
+    },
+    /// Packets of the stream were dropped because the sink could not keep
+    /// up, see `SinkAction` and `SinkQueueOptions`, or the frames were too
+    /// late for the sync group, see `SyncGroupOptions`, only the receiver has
+    /// this event.
+    FramesDropped {
+        stream: StreamType,
+        count: u32,
+    },
+    /// The receiver switched to another rendition of the simulcast video, 0
+    /// is the main video, see `RenditionSelection`, only the receiver has
+    /// this event.
+    RenditionChanged(u8),
+    AudioLevel(AudioLevel),
+    AudioPaused,
+    AudioResumed,
+    /// The sender enabled or disabled a track.
+    TrackChanged {
+        stream: StreamType,
+        enabled: bool,
+    },
+}
+
+pub(crate) type StreamEvents = Broadcaster<StreamEvent>;
+
+/// Forwards the callbacks to the observer of the user and emits them as
+/// events.
+pub(crate) struct EventObserver<O> {
+    pub(crate) events: Arc<StreamEvents>,
+    pub(crate) observer: O,
+}
+
+impl<O> MediaStreamObserver for EventObserver<O>
+where
+    O: MediaStreamObserver,
+{
+    fn close(&self, reason: CloseReason) {
+        self.observer.close(reason);
+        self.events.emit(StreamEvent::Closed(reason));
+    }
+
+    fn audio_device_changed(&self, source: &Source) {
+        self.observer.audio_device_changed(source);
+        self.events
+            .emit(StreamEvent::AudioDeviceChanged(source.clone()));
+    }
+
+    fn on_source_lost(&self, source: &Source) {
+        self.observer.on_source_lost(source);
+        self.events.emit(StreamEvent::SourceLost(source.clone()));
+    }
+
+    fn video_source_changed(&self, source: &Source) {
+        self.observer.video_source_changed(source);
+        self.events
+            .emit(StreamEvent::VideoSourceChanged(source.clone()));
+    }
+
+    fn on_signal_lost(&self) {
+        self.observer.on_signal_lost();
+        self.events.emit(StreamEvent::SignalLost);
+    }
+
+    fn on_signal_restored(&self) {
+        self.observer.on_signal_restored();
+        self.events.emit(StreamEvent::SignalRestored);
+    }
+
+    fn on_protected_content(&self, content: Option<ProtectedContent>) {
+        self.observer.on_protected_content(content);
+        self.events.emit(StreamEvent::ProtectedContent(content));
+    }
+
+    fn on_reconnecting(&self, attempt: u32) {
+        self.observer.on_reconnecting(attempt);
+        self.events.emit(StreamEvent::Reconnecting { attempt });
+    }
+
+    fn on_reconnected(&self) {
+        self.observer.on_reconnected();
+        self.events.emit(StreamEvent::Reconnected);
+    }
+
+    fn on_audio_level(&self, level: AudioLevel) {
+        self.observer.on_audio_level(level);
+        self.events.emit(StreamEvent::AudioLevel(level));
+    }
+
+    fn on_audio_paused(&self) {
+        self.observer.on_audio_paused();
+        self.events.emit(StreamEvent::AudioPaused);
+    }
+
+    fn on_audio_resumed(&self) {
+        self.observer.on_audio_resumed();
+        self.events.emit(StreamEvent::AudioResumed);
+    }
+
+    fn on_track_changed(&self, stream: StreamType, enabled: bool) {
+        self.observer.on_track_changed(stream, enabled);
+        self.events
+            .emit(StreamEvent::TrackChanged { stream, enabled });
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    ptr::null,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use capture::{
+    Capture, CaptureError, CaptureOptions, Damage, FrameConsumer, ProtectedContent, Source,
+    SourceCaptureOptions, SourceEvent, SourceType, SourceWatcher, VideoCaptureSourceDescription,
+};
+
+use common::{
+    Size,
+    frame::{AudioFrame, VideoFormat, VideoFrame, VideoSubFormat},
+};
+use parking_lot::Mutex;
+
+use super::{CloseReason, HylaranaError, MediaStreamObserver};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// What the sender does when the captured video source is gone, such as the
+/// captured monitor is unplugged or the captured window is closed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum SourceFailover {
+    /// The source is not watched, the sender is closed with
+    /// [CloseReason::CaptureStopped] when the capture stops.
+    #[default]
+    Close,
+    /// Capture another source of the same kind, the default source is
+    /// preferred, the sender is closed with [CloseReason::SourceLost] if there
+    /// is no other source.
+    Switch,
+    /// Keep the stream and send black frames until the source is plugged in
+    /// again, the capture then continues with it. The black frames are in the
+    /// system memory, so only the software encoders and vaapi can be used.
+    Placeholder,
+}
+
+impl SourceFailover {
+    /// Only the monitors disappear, the files and the test pattern end by
+    /// themselves, and the cameras can not be watched. The capture is not
+    /// wrapped for [SourceFailover::Close], which is what the capture does
+    /// anyway.
+    pub(crate) fn applies_to(&self, kind: SourceType) -> bool {
+        *self != Self::Close && kind == SourceType::Screen
+    }
+}
+
+/// The audio is captured by the sender, the captures of the failover only have
+/// the video.
+struct NoAudio;
+
+impl FrameConsumer for NoAudio {
+    type Frame = AudioFrame;
+
+    fn sink(&mut self, _frame: &Self::Frame) -> bool {
+        false
+    }
+
+    fn close(&mut self) {}
+}
+
+/// Passes the frames of a capture to the consumer of the sender, the consumer
+/// outlives the capture, so the encoder continues when the capture is
+/// replaced.
+struct FailoverConsumer<C> {
+    consumer: Arc<Mutex<C>>,
+    running: Arc<AtomicBool>,
+    /// The capture was replaced or stopped by the failover.
+    detached: Arc<AtomicBool>,
+    /// The consumer stopped the capture, the stream is closed.
+    stopped: bool,
+    lost: Sender<()>,
+}
+
+impl<C> FrameConsumer for FailoverConsumer<C>
+where
+    C: FrameConsumer<Frame = VideoFrame>,
+{
+    type Frame = VideoFrame;
+
+    fn sink(&mut self, frame: &Self::Frame) -> bool {
+        if self.detached.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        if !self.consumer.lock().sink(frame) {
+            self.stopped = true;
+
+            return false;
+        }
+
+        true
+    }
+
+    fn changed(&mut self, source: &Source) {
+        self.consumer.lock().changed(source);
+    }
+
+    fn damage(&mut self, damage: &Damage) {
+        if !self.detached.load(Ordering::Relaxed) {
+            self.consumer.lock().damage(damage);
+        }
+    }
+
+    fn protected(&mut self, content: Option<ProtectedContent>) {
+        if !self.detached.load(Ordering::Relaxed) {
+            self.consumer.lock().protected(content);
+        }
+    }
+
+    fn close(&mut self) {
+        if self.detached.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if self.stopped {
+            self.running.store(false, Ordering::Relaxed);
+            self.consumer.lock().close();
+        } else {
+            // The capture stopped by itself, the source is most likely gone.
+            let _ = self.lost.send(());
+        }
+    }
+}
+
+/// Black frames in the size of the video options, they are also sent in
+/// place of the protected content, see `ProtectedContentPolicy`.
+pub(crate) struct Placeholder {
+    #[allow(unused)]
+    buffer: Vec<u8>,
+    interval: Duration,
+    frame: VideoFrame,
+}
+
+unsafe impl Send for Placeholder {}
+
+impl Placeholder {
+    pub(crate) fn new(size: Size, fps: u8) -> Self {
+        let width = size.width as usize;
+        let height = size.height as usize;
+
+        // Limited range black.
+        let mut buffer = vec![16u8; width * height * 3 / 2];
+        buffer[width * height..].fill(128);
+
+        let mut frame = VideoFrame {
+            format: VideoFormat::NV12,
+            sub_format: VideoSubFormat::SW,
+            width: width as u32,
+            height: height as u32,
+            data: [null(), null(), null()],
+            linesize: [width as u32, width as u32, 0],
+            timestamp: 0,
+        };
+
+        frame.data[0] = buffer.as_ptr() as _;
+        frame.data[1] = buffer[width * height..].as_ptr() as _;
+
+        Self {
+            interval: Duration::from_secs(1) / fps.max(1) as u32,
+            buffer,
+            frame,
+        }
+    }
+
+    pub(crate) fn frame(&self) -> &VideoFrame {
+        &self.frame
+    }
+}
+
+type CurrentCapture = Mutex<Option<(Capture, Arc<AtomicBool>)>>;
+
+struct FailoverState<C> {
+    policy: SourceFailover,
+    description: VideoCaptureSourceDescription,
+    consumer: Arc<Mutex<C>>,
+    capture: Arc<CurrentCapture>,
+    running: Arc<AtomicBool>,
+    lost: Sender<()>,
+    observer: Arc<dyn MediaStreamObserver>,
+    callback: Arc<dyn Fn(CloseReason) + Send + Sync + 'static>,
+}
+
+impl<C> FailoverState<C>
+where
+    C: FrameConsumer<Frame = VideoFrame> + 'static,
+{
+    /// How often the lost capture is checked when no placeholder is sent.
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    fn start(&self, source: Source) -> Result<(), HylaranaError> {
+        detach(&self.capture);
+
+        let detached = Arc::new(AtomicBool::new(false));
+        let capture = Capture::start(CaptureOptions::<_, NoAudio> {
+            video: Some(SourceCaptureOptions {
+                description: VideoCaptureSourceDescription {
+                    source,
+                    ..self.description.clone()
+                },
+                consumer: FailoverConsumer {
+                    consumer: self.consumer.clone(),
+                    running: self.running.clone(),
+                    detached: detached.clone(),
+                    lost: self.lost.clone(),
+                    stopped: false,
+                },
+            }),
+            audio: None,
+        })?;
+
+        self.capture.lock().replace((capture, detached));
+
+        Ok(())
+    }
+
+    /// Another source of the same kind, the default source first.
+    fn next_source(&self, lost: &Source) -> Option<Source> {
+        let mut sources = Capture::get_sources(lost.kind)
+            .inspect_err(|e| tracing::warn!("sender failover failed to get sources, err={:?}", e))
+            .ok()?
+            .into_iter()
+            .filter(|it| it.id != lost.id)
+            .collect::<Vec<_>>();
+
+        sources.sort_by_key(|it| !it.is_default);
+        sources.into_iter().next()
+    }
+
+    fn close(&self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        (self.callback)(CloseReason::SourceLost);
+    }
+
+    fn run(&self, lost: Receiver<()>, watcher: SourceWatcher) {
+        let mut source = self.description.source.clone();
+        let mut placeholder: Option<Placeholder> = None;
+
+        while self.running.load(Ordering::Relaxed) {
+            let mut is_lost = match lost.recv_timeout(
+                placeholder
+                    .as_ref()
+                    .map(|it| it.interval)
+                    .unwrap_or(Self::POLL_INTERVAL),
+            ) {
+                Ok(_) => true,
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            // Some captures keep running without frames when the monitor is unplugged, so
+            // the removal is also taken from the watcher.
+            while let Some(event) = watcher.try_recv() {
+                match event {
+                    SourceEvent::Removed(it) if placeholder.is_none() && it.id == source.id => {
+                        is_lost = true;
+                    }
+                    SourceEvent::Added(it) if placeholder.is_some() && it.id == source.id => {
+                        tracing::info!("sender failover source is back, source={:?}", it);
+
+                        if let Err(e) = self.start(it.clone()) {
+                            tracing::warn!("sender failover failed to restart, err={:?}", e);
+
+                            return self.close();
+                        }
+
+                        source = it;
+                        placeholder = None;
+                        self.consumer.lock().changed(&source);
+                        self.observer.video_source_changed(&source);
+                    }
+                    _ => (),
+                }
+            }
+
+            if is_lost && self.running.load(Ordering::Relaxed) {
+                tracing::warn!(
+                    "sender failover source is lost, source={:?}, policy={:?}",
+                    source,
+                    self.policy
+                );
+
+                detach(&self.capture);
+                while lost.try_recv().is_ok() {}
+
+                self.observer.on_source_lost(&source);
+
+                match self.policy {
+                    SourceFailover::Close => return self.close(),
+                    SourceFailover::Switch => {
+                        let Some(next) = self.next_source(&source) else {
+                            tracing::warn!("sender failover has no other source");
+
+                            return self.close();
+                        };
+
+                        if let Err(e) = self.start(next.clone()) {
+                            tracing::warn!("sender failover failed to switch, err={:?}", e);
+
+                            return self.close();
+                        }
+
+                        tracing::info!("sender failover switched, source={:?}", next);
+
+                        source = next;
+                        self.consumer.lock().changed(&source);
+                        self.observer.video_source_changed(&source);
+                    }
+                    SourceFailover::Placeholder => {
+                        placeholder = Some(Placeholder::new(
+                            self.description.size,
+                            self.description.fps,
+                        ));
+                    }
+                }
+            }
+
+            if let Some(it) = placeholder.as_ref() {
+                let mut consumer = self.consumer.lock();
+                if !consumer.sink(&it.frame) {
+                    self.running.store(false, Ordering::Relaxed);
+                    consumer.close();
+                }
+            }
+        }
+    }
+}
+
+fn detach(capture: &CurrentCapture) {
+    // The capture is dropped outside of the lock, stopping it closes the consumer.
+    let current = capture.lock().take();
+    if let Some((capture, detached)) = current {
+        detached.store(true, Ordering::Relaxed);
+
+        drop(capture);
+    }
+}
+
+/// Captures the video source of the sender and applies the [SourceFailover]
+/// when the source is gone, the capture is stopped when this is dropped.
+pub(crate) struct VideoFailover {
+    capture: Arc<CurrentCapture>,
+    running: Arc<AtomicBool>,
+}
+
+impl VideoFailover {
+    pub(crate) fn start<C>(
+        policy: SourceFailover,
+        description: VideoCaptureSourceDescription,
+        consumer: C,
+        observer: Arc<dyn MediaStreamObserver>,
+        callback: Arc<dyn Fn(CloseReason) + Send + Sync + 'static>,
+    ) -> Result<Self, HylaranaError>
+    where
+        C: FrameConsumer<Frame = VideoFrame> + 'static,
+    {
+        let watcher = Capture::watch_sources(description.source.kind)?;
+        let running = Arc::new(AtomicBool::new(true));
+        let capture = Arc::new(Mutex::new(None));
+        let (tx, rx) = mpsc::channel();
+
+        let state = FailoverState {
+            consumer: Arc::new(Mutex::new(consumer)),
+            capture: capture.clone(),
+            running: running.clone(),
+            lost: tx,
+            description,
+            observer,
+            callback,
+            policy,
+        };
+
+        state.start(state.description.source.clone())?;
+
+        thread::Builder::new()
+            .name("HylaranaSenderFailoverThread".to_string())
+            .spawn(move || {
+                state.run(rx, watcher);
+
+                tracing::info!("sender failover thread is closed");
+            })
+            .map_err(CaptureError::IoError)?;
+
+        Ok(Self { capture, running })
+    }
+}
+
+impl Drop for VideoFailover {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        detach(&self.capture);
+    }
+}
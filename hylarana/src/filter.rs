@@ -0,0 +1,93 @@
+use std::fmt::Debug;
+
+/// A filter of the played audio, applied in order to the samples of each frame
+/// before the frame is queued to the output device.
+///
+/// The filters are shared with the player, so a filter that keeps a state
+/// between the frames, such as an equalizer, keeps it behind a lock.
+pub trait AudioFilter: Debug + Send + Sync {
+    /// The samples are interleaved and in the range of -1.0 to 1.0, the
+    /// samples that are still outside the range after the last filter are
+    /// clipped.
+    fn process(&self, samples: &mut [f32], channels: u8, sample_rate: u32);
+}
+
+/// Runs the filters on the samples of a frame. The filters work on the float
+/// samples, so the gain of a filter is not clipped before the next filter.
+pub(crate) fn filter_samples(
+    filters: &[&dyn AudioFilter],
+    samples: &mut [i16],
+    channels: u8,
+    sample_rate: u32,
+) {
+    if filters.is_empty() {
+        return;
+    }
+
+    let mut buffer = samples
+        .iter()
+        .map(|it| *it as f32 / 32768.0)
+        .collect::<Vec<_>>();
+
+    for filter in filters {
+        filter.process(&mut buffer, channels, sample_rate);
+    }
+
+    for (sample, it) in samples.iter_mut().zip(buffer) {
+        *sample = (it * 32768.0).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    }
+}
+
+/// Scales the audio by the factor, 1.0 keeps the audio and 0.0 is silence.
+/// This is the volume of the receiver and of the player, which is applied
+/// after the other filters.
+#[derive(Debug, Clone, Copy)]
+pub struct Volume(pub f32);
+
+impl AudioFilter for Volume {
+    fn process(&self, samples: &mut [f32], _: u8, _: u32) {
+        for sample in samples {
+            *sample *= self.0;
+        }
+    }
+}
+
+/// Amplifies or attenuates the audio by the decibels, 0.0 keeps the audio.
+#[derive(Debug, Clone, Copy)]
+pub struct Gain(pub f32);
+
+impl AudioFilter for Gain {
+    fn process(&self, samples: &mut [f32], channels: u8, sample_rate: u32) {
+        Volume(10f32.powf(self.0 / 20.0)).process(samples, channels, sample_rate);
+    }
+}
+
+/// Bends the samples above the threshold smoothly towards the full scale,
+/// instead of the hard clipping of a sender that runs hot, the samples below
+/// the threshold are not changed.
+#[derive(Debug, Clone, Copy)]
+pub struct SoftLimiter {
+    /// The linear level where the limiting starts, between 0.0 and 1.0.
+    pub threshold: f32,
+}
+
+impl Default for SoftLimiter {
+    fn default() -> Self {
+        Self { threshold: 0.8 }
+    }
+}
+
+impl AudioFilter for SoftLimiter {
+    fn process(&self, samples: &mut [f32], _: u8, _: u32) {
+        let threshold = self.threshold.clamp(0.0, 0.99);
+        let knee = 1.0 - threshold;
+
+        for sample in samples {
+            let level = sample.abs();
+            if level > threshold {
+                *sample =
+                    sample.signum() * (threshold + knee * ((level - threshold) / knee).tanh());
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Several receivers of one sender present each video frame at the same time,
+/// such as the screens of a video wall. The clock of every receiver is synced
+/// with the clock of the sender, and a frame is presented when the shared
+/// delay has passed since it was captured, so the receivers agree on the time
+/// without talking to each other. The audio is held back by the same delay,
+/// so that it stays in sync with the video. Each receiver connects to the
+/// sender on its own, the sender sends the stream to all of them.
+///
+/// The packets are held back on the thread that decodes them, so the receiver
+/// always has a sink queue with a sync group, the default queue is used if
+/// `HylaranaReceiverOptions::sink_queue` is `None`. The capacity of the queue
+/// must hold the packets of the delay.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SyncGroupOptions {
+    /// The time from the capture to the presentation in milliseconds, all
+    /// receivers of the group must use the same delay, and it must be longer
+    /// than the latency of the slowest receiver, see
+    /// `HylaranaReceiver::get_latency`.
+    pub presentation_delay: u32,
+    /// A frame that is later than its presentation time by more than this in
+    /// milliseconds is dropped instead of presented, so that a receiver that
+    /// fell behind catches up with the group.
+    pub max_lateness: u32,
+}
+
+impl Default for SyncGroupOptions {
+    fn default() -> Self {
+        Self {
+            presentation_delay: 200,
+            max_lateness: 20,
+        }
+    }
+}
+
+/// Holds the frames of a receiver until their presentation time.
+pub(crate) struct SyncGroup {
+    delay: Duration,
+    max_lateness: Duration,
+}
+
+impl SyncGroup {
+    pub(crate) fn new(options: &SyncGroupOptions) -> Self {
+        Self {
+            delay: Duration::from_millis(options.presentation_delay as u64),
+            max_lateness: Duration::from_millis(options.max_lateness as u64),
+        }
+    }
+
+    /// The time until the packet captured at the timestamp on the clock of
+    /// the receiver is due.
+    pub(crate) fn wait(&self, timestamp: u64) -> Duration {
+        let due = Duration::from_micros(timestamp) + self.delay;
+        let now = Duration::from_micros(common::clock::now());
+
+        // A packet is never held longer than the delay, even if its timestamp is
+        // ahead of the clock, such as right after the clock was synced.
+        due.saturating_sub(now).min(self.delay)
+    }
+
+    /// Returns true if the frame captured at the timestamp on the clock of the
+    /// receiver is too late to be presented.
+    pub(crate) fn is_late(&self, timestamp: u64) -> bool {
+        let due = Duration::from_micros(timestamp) + self.delay;
+
+        Duration::from_micros(common::clock::now()) > due + self.max_lateness
+    }
+}
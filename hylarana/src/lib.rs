@@ -1,94 +1,285 @@
+pub mod diagnostics;
+mod error;
+mod events;
+mod failover;
+mod filter;
+mod group;
+mod luma;
+mod meter;
+mod overlay;
 mod player;
+mod privacy;
+mod profile;
+mod queue;
 mod receiver;
 mod sender;
+mod simulcast;
+mod snapshot;
+mod surface;
+pub mod testing;
+mod timeshift;
 mod util;
 
-use std::net::SocketAddr;
+#[cfg(feature = "metrics")]
+mod metrics;
+
+#[cfg(feature = "chrome-trace")]
+mod trace;
+
+use std::{error::Error, fmt::Debug, net::SocketAddr, panic::resume_unwind, sync::Arc};
 
 use thiserror::Error;
 
-pub use self::{player::*, receiver::*, sender::*};
+pub use self::{
+    error::*,
+    events::StreamEvent,
+    failover::SourceFailover,
+    filter::*,
+    group::SyncGroupOptions,
+    meter::AudioLevel,
+    overlay::*,
+    player::*,
+    privacy::{PrivacyMaskStyle, PrivacyOptions},
+    profile::*,
+    queue::*,
+    receiver::*,
+    sender::*,
+    simulcast::{RenditionSelection, VideoRendition},
+    snapshot::*,
+    surface::*,
+};
+
+#[cfg(feature = "metrics")]
+pub use self::metrics::*;
+
+#[cfg(feature = "chrome-trace")]
+pub use self::trace::*;
 
-pub use capture::{Capture, Source, SourceType};
+pub use bytes::Bytes;
+pub use capture::{
+    Capture, PermissionState, ProtectedContent, Source, SourceEvent, SourceType, SourceWatcher,
+    WindowMatch,
+};
 pub use common::{
-    MediaAudioStreamDescription, MediaStreamDescription, MediaVideoStreamDescription, Size,
-    codec::*, frame::*, runtime::*,
+    MediaAudioStreamDescription, MediaStreamDescription, MediaVideoStreamDescription, SessionMode,
+    Size, clock, codec::*, frame::*, power::PowerGuard, runtime::*,
+};
+
+pub use discovery::{
+    DeviceCapabilities, DeviceInfo, DeviceKind, DiscoveryEvent, DiscoveryEventStream,
+    DiscoveryMode, DiscoveryObserver, DiscoveryOptions, DiscoveryService, PairingError,
+    PairingSecret, Peer,
+};
+pub use renderer::{
+    Brightness, Contrast, MultiRendererLayout, PresentMode, RendererBackend, RendererEvent,
+    ScalingMode, ScalingQuality, SurfaceTarget, VideoFilter, raw_window_handle, wgpu,
+};
+pub use transport::{
+    Buffer, BufferPool, BufferPoolStats, BufferType, ClockEstimate, ConnectionStats, FecArq,
+    FecLayout, FecOptions, MAX_RENDITIONS, PacingStats, ProbeReport, ProbeServer, ReconnectOptions,
+    StreamId, StreamMode, StreamType, TransportOptions, TransportOptionsBuilder,
+    TransportOptionsError, TransportStrategy, bandwidth_probe,
 };
 
-pub use discovery::{DiscoveryObserver, DiscoveryService};
-pub use renderer::{SurfaceTarget, raw_window_handle};
-pub use transport::TransportOptions;
+#[cfg(target_os = "windows")]
+pub use common::win32::{GpuAdapter, GpuSelector};
 
 #[cfg(target_os = "windows")]
 use common::win32::{
     ProcessPriority, set_process_priority, shutdown as win32_shutdown, startup as win32_startup,
 };
 
-#[derive(Debug, Error)]
-pub enum HylaranaError {
-    #[error(transparent)]
-    #[cfg(target_os = "windows")]
-    Win32Error(#[from] common::win32::windows::core::Error),
-    #[error(transparent)]
-    TransportError(#[from] std::io::Error),
-}
-
 /// Initialize the environment, which must be initialized before using the sdk.
 pub fn startup() -> Result<(), HylaranaError> {
-    log::info!("hylarana startup");
+    tracing::info!("hylarana startup");
 
     #[cfg(target_os = "windows")]
     if let Err(e) = win32_startup() {
-        log::warn!("{:?}", e);
+        tracing::warn!("{:?}", e);
     }
 
     // In order to prevent other programs from affecting the delay performance of
     // the current program, set the priority of the current process to high.
     #[cfg(target_os = "windows")]
     if set_process_priority(ProcessPriority::High).is_err() {
-        log::error!(
+        tracing::error!(
             "failed to set current process priority, Maybe it's \
             because you didn't run it with administrator privileges."
         );
     }
 
     codec::startup();
-    log::info!("codec initialized");
+    tracing::info!("codec initialized");
 
     transport::startup();
-    log::info!("transport initialized");
+    tracing::info!("transport initialized");
 
-    log::info!("all initialized");
+    tracing::info!("all initialized");
     Ok(())
 }
 
 /// Cleans up the environment when the sdk exits, and is recommended to be
 /// called when the application exits.
 pub fn shutdown() -> Result<(), HylaranaError> {
-    log::info!("hylarana shutdown");
+    tracing::info!("hylarana shutdown");
 
     codec::shutdown();
     transport::shutdown();
 
     #[cfg(target_os = "windows")]
     if let Err(e) = win32_shutdown() {
-        log::warn!("{:?}", e);
+        tracing::warn!("{:?}", e);
     }
 
     Ok(())
 }
 
+/// Get the hardware graphics adapters of the system.
+#[cfg(target_os = "windows")]
+pub fn get_gpu_adapters() -> Result<Vec<GpuAdapter>, HylaranaError> {
+    Ok(GpuAdapter::enumerate()?)
+}
+
+/// Select the adapter that the capture, the codecs and the renderer share, the
+/// system default adapter is used if this is not called. Call this after
+/// [startup] and before creating the senders, receivers and players.
+#[cfg(target_os = "windows")]
+pub fn select_gpu(selector: GpuSelector) -> Result<GpuAdapter, HylaranaError> {
+    let adapter = util::set_direct3d(selector)?;
+    tracing::info!("hylarana select gpu, adapter={:?}", adapter);
+
+    Ok(adapter)
+}
+
+/// Why the sender or the receiver is closed.
+#[derive(Debug, Clone, Error)]
+pub enum CloseReason {
+    /// The sender ended the stream intentionally, only the receiver is closed
+    /// with this.
+    #[error("the sender ended the stream")]
+    RemoteClosed,
+    /// The connection to the sender was lost, and the reconnection failed if it
+    /// is enabled, only the receiver is closed with this.
+    #[error("the connection timed out")]
+    NetworkTimeout,
+    /// Closed locally, by dropping the handle or by the sink returning false.
+    #[error("the stream is closed")]
+    Closed,
+    /// The capture stopped by itself, such as the captured window was closed,
+    /// only the sender is closed with this.
+    #[error("the capture stopped")]
+    CaptureStopped,
+    /// The captured monitor or camera is gone and the failover of the sender
+    /// found no other source or could not capture it again, see
+    /// `HylaranaSenderOptions::failover`, only the sender is closed with this.
+    #[error("the captured source is gone")]
+    SourceLost,
+    #[error("transport error: {0}")]
+    TransportError(Arc<std::io::Error>),
+    #[error("encoder error: {0}")]
+    EncoderError(Arc<dyn Error + Send + Sync>),
+    #[error("decoder error: {0}")]
+    DecoderError(Arc<dyn Error + Send + Sync>),
+}
+
+impl From<transport::CloseReason> for CloseReason {
+    fn from(value: transport::CloseReason) -> Self {
+        match value {
+            transport::CloseReason::RemoteClosed => Self::RemoteClosed,
+            transport::CloseReason::NetworkTimeout => Self::NetworkTimeout,
+            transport::CloseReason::Closed => Self::Closed,
+        }
+    }
+}
+
 /// Audio and video streaming events observer.
 pub trait MediaStreamObserver: Sync + Send {
     /// Callback when the sender is closed. This may be because the external
     /// side actively calls the close, or the audio and video packets cannot be
-    /// sent (the network is disconnected), etc.
-    fn close(&self) {}
+    /// sent (the network is disconnected), etc. The reason carries the error
+    /// that closed the stream, if any.
+    #[allow(unused_variables)]
+    fn close(&self, reason: CloseReason) {}
+
+    /// Callback when the audio device switches to another device, the capture
+    /// of the sender follows the default device and falls back to the default
+    /// device when the captured device is removed.
+    #[allow(unused_variables)]
+    fn audio_device_changed(&self, source: &Source) {}
+
+    /// Callback when the captured video source of the sender is gone, such as
+    /// the monitor is unplugged, what follows depends on
+    /// `HylaranaSenderOptions::failover`.
+    #[allow(unused_variables)]
+    fn on_source_lost(&self, source: &Source) {}
+
+    /// Callback when the sender captures another video source after the
+    /// source was lost, or the lost source is captured again.
+    #[allow(unused_variables)]
+    fn video_source_changed(&self, source: &Source) {}
+
+    /// Callback when the captured video has been black for a while, for
+    /// example the captured window shows protected content or the permission
+    /// to capture the screen is missing, the stream is not interrupted.
+    fn on_signal_lost(&self) {}
+
+    /// Callback when the captured video is no longer black after the signal
+    /// was lost.
+    fn on_signal_restored(&self) {}
+
+    /// Callback when the system starts or stops hiding content from the
+    /// captured screen, `None` when nothing is hidden anymore, what is sent in
+    /// the meantime depends on `VideoOptions::protected_content`. Only the
+    /// desktop duplication on windows detects it, which is used for the
+    /// screens unless the policy is `ProtectedContentPolicy::Stream`,
+    /// otherwise the signal is lost.
+    #[allow(unused_variables)]
+    fn on_protected_content(&self, content: Option<ProtectedContent>) {}
+
+    /// Callback before each attempt of the receiver to reconnect to the
+    /// sender, only when the reconnection is enabled in the options.
+    #[allow(unused_variables)]
+    fn on_reconnecting(&self, attempt: u32) {}
+
+    /// Callback when the receiver is reconnected to the sender.
+    fn on_reconnected(&self) {}
+
+    /// Callback with the level of the audio about every 100 milliseconds, the
+    /// captured audio for the sender and the decoded audio for the receiver,
+    /// the volume of the receiver is not applied.
+    #[allow(unused_variables)]
+    fn on_audio_level(&self, level: AudioLevel) {}
+
+    /// Callback when the audio is paused because the captured audio is
+    /// silent, see `AudioOptions::silence`, no audio frames arrive at the
+    /// receiver until the audio is resumed.
+    fn on_audio_paused(&self) {}
+
+    /// Callback when the audio is resumed after it was paused.
+    fn on_audio_resumed(&self) {}
+
+    /// Callback when a track is enabled or disabled by the sender, see
+    /// `HylaranaSender::set_track_enabled`, the disabled track is not sent
+    /// but the session is kept.
+    #[allow(unused_variables)]
+    fn on_track_changed(&self, stream: StreamType, enabled: bool) {}
 }
 
 // impl empty type for default
 impl MediaStreamObserver for () {}
 
+/// What the receiver does after a video frame is passed to the sink, see
+/// [MediaStreamSink::video_action].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkAction {
+    Continue,
+    /// The video is not decoded until the next key frame, which sheds the load
+    /// of a sink that can not keep up, the audio is not affected.
+    DropUntilKeyframe,
+    /// Close the stream.
+    Close,
+}
+
 /// Streaming sink for audio and video frames.
 pub trait MediaStreamSink: Sync + Send {
     /// Callback occurs when the video frame is updated. The video frame format
@@ -96,12 +287,27 @@ pub trait MediaStreamSink: Sync + Send {
     /// callback, which will seriously slow down the encoding and decoding
     /// pipeline.
     ///
+    /// The frame may be a hardware frame, see [VideoFrame::is_hardware], the
+    /// pixels of any frame can be downloaded with [VideoFrame::to_software].
+    ///
     /// Returning `false` causes the stream to close.
     #[allow(unused_variables)]
     fn video(&self, frame: &VideoFrame) -> bool {
         true
     }
 
+    /// The same as [MediaStreamSink::video], and the receiver calls this
+    /// instead, a slow sink can return [SinkAction::DropUntilKeyframe] to skip
+    /// the frames instead of closing the stream. The default calls
+    /// [MediaStreamSink::video].
+    fn video_action(&self, frame: &VideoFrame) -> SinkAction {
+        if self.video(frame) {
+            SinkAction::Continue
+        } else {
+            SinkAction::Close
+        }
+    }
+
     /// Callback is called when the audio frame is updated. The audio frame
     /// format is fixed to PCM. Be careful not to call blocking methods inside
     /// the callback, which will seriously slow down the encoding and decoding
@@ -112,11 +318,38 @@ pub trait MediaStreamSink: Sync + Send {
     fn audio(&self, frame: &AudioFrame) -> bool {
         true
     }
+
+    /// Called when the receiver switches to the stream of another sender,
+    /// before the first frame of the new stream, the size and the format of
+    /// the video may differ from the previous stream, such as for the back
+    /// buffer of a renderer. The audio frames carry their own format.
+    #[allow(unused_variables)]
+    fn stream_changed(
+        &self,
+        description: &MediaStreamDescription,
+        options: &HylaranaReceiverOptions,
+    ) {
+    }
 }
 
 // impl empty type for default
 impl MediaStreamSink for () {}
 
+/// Sink of the encoded packets of a receiver, see
+/// `HylaranaReceiverOptions::packet_sink`, for example to record or forward
+/// the stream without decoding and encoding it again.
+pub trait MediaPacketSink: Debug + Sync + Send {
+    /// Called with each packet as it is received, before it is decoded. The
+    /// video packets are the access units of the codec in the annex b format,
+    /// the audio packets are opus packets. The type of the packet tells the
+    /// configurations in front of the key frames and the silent audio, the
+    /// timestamp is the capture time on the clock of the sender in
+    /// microseconds, 0 if it is unknown.
+    ///
+    /// Returning `false` causes the stream to close.
+    fn packet(&self, packet: &Buffer<Bytes>) -> bool;
+}
+
 /// Creates a sender that can specify the audio source or video source to be
 /// captured.
 pub fn create_sender<S, O>(
@@ -124,12 +357,12 @@ pub fn create_sender<S, O>(
     options: &HylaranaSenderOptions,
     sink: S,
     observer: O,
-) -> Result<HylaranaSender, HylaranaSenderError>
+) -> Result<HylaranaSender, HylaranaError>
 where
     S: MediaStreamSink + 'static,
     O: MediaStreamObserver + 'static,
 {
-    log::info!("create sender: options={:?}", options);
+    tracing::info!("create sender: options={:?}", options);
 
     HylaranaSender::new(bind, options, sink, observer)
 }
@@ -142,12 +375,12 @@ pub fn create_receiver<S, O>(
     description: &MediaStreamDescription,
     sink: S,
     observer: O,
-) -> Result<HylaranaReceiver, HylaranaReceiverError>
+) -> Result<HylaranaReceiver, HylaranaError>
 where
     S: MediaStreamSink + 'static,
     O: MediaStreamObserver + 'static,
 {
-    log::info!(
+    tracing::info!(
         "create receiver: description={:?}, options={:?}",
         description,
         options
@@ -155,3 +388,49 @@ where
 
     HylaranaReceiver::new(addr, options, description, sink, observer)
 }
+
+/// The asynchronous version of `create_sender`, the capture and the encoders
+/// are initialized on the blocking threads of the runtime, so it does not
+/// block the asynchronous tasks.
+pub async fn create_sender_async<S, O>(
+    bind: SocketAddr,
+    options: HylaranaSenderOptions,
+    sink: S,
+    observer: O,
+) -> Result<HylaranaSender, HylaranaError>
+where
+    S: MediaStreamSink + 'static,
+    O: MediaStreamObserver + 'static,
+{
+    match get_runtime_handle()
+        .spawn_blocking(move || create_sender(bind, &options, sink, observer))
+        .await
+    {
+        Ok(ret) => ret,
+        Err(e) if e.is_panic() => resume_unwind(e.into_panic()),
+        Err(_) => Err(HylaranaError::RuntimeShutdown),
+    }
+}
+
+/// The asynchronous version of `create_receiver`, connecting to the sender and
+/// initializing the decoders are done on the blocking threads of the runtime.
+pub async fn create_receiver_async<S, O>(
+    addr: SocketAddr,
+    options: HylaranaReceiverOptions,
+    description: MediaStreamDescription,
+    sink: S,
+    observer: O,
+) -> Result<HylaranaReceiver, HylaranaError>
+where
+    S: MediaStreamSink + 'static,
+    O: MediaStreamObserver + 'static,
+{
+    match get_runtime_handle()
+        .spawn_blocking(move || create_receiver(addr, &options, &description, sink, observer))
+        .await
+    {
+        Ok(ret) => ret,
+        Err(e) if e.is_panic() => resume_unwind(e.into_panic()),
+        Err(_) => Err(HylaranaError::RuntimeShutdown),
+    }
+}
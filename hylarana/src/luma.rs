@@ -0,0 +1,220 @@
+use std::slice::from_raw_parts;
+
+use common::frame::{VideoFormat, VideoFrame, VideoSubFormat};
+
+#[cfg(target_os = "windows")]
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "windows")]
+use common::{
+    Size,
+    win32::{Direct3DDevice, d3d_texture_borrowed_raw},
+};
+
+#[cfg(target_os = "windows")]
+use resample::win32::{Resource, VideoResampler, VideoResamplerOptions};
+
+#[cfg(target_os = "macos")]
+use common::{
+    Size,
+    macos::{CVPixelBufferRef, PixelMomeryBuffer},
+};
+
+/// The luma of a frame sampled on a sparse grid, for the detectors that only
+/// need a rough picture of the video, such as a black or a still screen.
+///
+/// The software frames and the pixel buffers are sampled in place. The
+/// textures are scaled down on the gpu to one pixel per cell of the grid and
+/// read back, which stalls the pipeline, so this happens at most every
+/// [LumaSampler::READBACK_INTERVAL]. The dma-buf frames are not sampled.
+pub(crate) struct LumaSampler {
+    samples: Vec<u8>,
+    #[cfg(target_os = "windows")]
+    direct3d: Direct3DDevice,
+    /// Created for the first texture, and again when the textures change.
+    #[cfg(target_os = "windows")]
+    readback: Option<((VideoFormat, u32, u32), VideoResampler)>,
+    #[cfg(target_os = "windows")]
+    read: Option<Instant>,
+}
+
+impl LumaSampler {
+    /// Distance between the sampled pixels, in both directions.
+    pub const STEP: usize = 16;
+
+    #[cfg(target_os = "windows")]
+    const READBACK_INTERVAL: Duration = Duration::from_millis(200);
+
+    pub fn new(#[cfg(target_os = "windows")] direct3d: Direct3DDevice) -> Self {
+        Self {
+            samples: Vec::new(),
+            #[cfg(target_os = "windows")]
+            direct3d,
+            #[cfg(target_os = "windows")]
+            readback: None,
+            #[cfg(target_os = "windows")]
+            read: None,
+        }
+    }
+
+    /// Returns the samples of the frame, `None` if the frame is not sampled.
+    /// The number of the samples changes with the size of the frames, but not
+    /// between the frames of the same size.
+    pub fn sample(&mut self, frame: &VideoFrame) -> Option<&[u8]> {
+        if frame.data[0].is_null() {
+            return None;
+        }
+
+        match frame.sub_format {
+            VideoSubFormat::SW => self.sample_plane(
+                frame.data[0] as *const u8,
+                frame.linesize[0] as usize,
+                frame.width,
+                frame.height,
+                frame.format,
+                Self::STEP,
+            ),
+            #[cfg(target_os = "windows")]
+            VideoSubFormat::D3D11 => self.read_texture(frame)?,
+            #[cfg(target_os = "macos")]
+            VideoSubFormat::CvPixelBufferRef => {
+                let buffer = PixelMomeryBuffer::from((
+                    frame.data[0] as CVPixelBufferRef,
+                    frame.format,
+                    Size {
+                        width: frame.width,
+                        height: frame.height,
+                    },
+                ));
+
+                if buffer.data[0].is_empty() {
+                    return None;
+                }
+
+                self.sample_plane(
+                    buffer.data[0].as_ptr(),
+                    buffer.linesize[0],
+                    frame.width,
+                    frame.height,
+                    frame.format,
+                    Self::STEP,
+                )
+            }
+            _ => return None,
+        }
+
+        Some(&self.samples)
+    }
+
+    /// The packed formats are converted with the BT.709 weights, the planar
+    /// formats start with the luma plane.
+    fn sample_plane(
+        &mut self,
+        data: *const u8,
+        stride: usize,
+        width: u32,
+        height: u32,
+        format: VideoFormat,
+        step: usize,
+    ) {
+        let (width, height) = (width as usize, height as usize);
+
+        self.samples.clear();
+        for y in (0..height).step_by(step) {
+            let row = unsafe {
+                from_raw_parts(
+                    data.add(y * stride),
+                    match format {
+                        VideoFormat::NV12 | VideoFormat::I420 => width,
+                        VideoFormat::BGRA | VideoFormat::RGBA => width * 4,
+                    },
+                )
+            };
+
+            for x in (0..width).step_by(step) {
+                self.samples.push(match format {
+                    VideoFormat::NV12 | VideoFormat::I420 => row[x],
+                    VideoFormat::BGRA | VideoFormat::RGBA => {
+                        let pixel = &row[x * 4..x * 4 + 3];
+                        let (r, g, b) = if format == VideoFormat::BGRA {
+                            (pixel[2], pixel[1], pixel[0])
+                        } else {
+                            (pixel[0], pixel[1], pixel[2])
+                        };
+
+                        ((r as u32 * 54 + g as u32 * 183 + b as u32 * 19) >> 8) as u8
+                    }
+                });
+            }
+        }
+    }
+
+    /// Returns `None` between the readbacks, and when the texture cannot be
+    /// read back.
+    #[cfg(target_os = "windows")]
+    fn read_texture(&mut self, frame: &VideoFrame) -> Option<()> {
+        if self
+            .read
+            .map(|it| it.elapsed() < Self::READBACK_INTERVAL)
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        self.read = Some(Instant::now());
+
+        let texture = d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))?;
+
+        // NV12 needs an even size.
+        let size = Size {
+            width: (frame.width.div_ceil(Self::STEP as u32) + 1) & !1,
+            height: (frame.height.div_ceil(Self::STEP as u32) + 1) & !1,
+        };
+
+        let input = (frame.format, frame.width, frame.height);
+        if self.readback.as_ref().map(|(it, _)| *it != input) != Some(false) {
+            self.readback = match VideoResampler::new(VideoResamplerOptions {
+                direct3d: self.direct3d.clone(),
+                input: Resource::Default(
+                    frame.format,
+                    Size {
+                        width: frame.width,
+                        height: frame.height,
+                    },
+                ),
+                output: Resource::Default(VideoFormat::NV12, size),
+            }) {
+                Ok(it) => Some((input, it)),
+                Err(e) => {
+                    tracing::warn!("failed to create the luma readback, err={:?}", e);
+
+                    None
+                }
+            };
+        }
+
+        let (_, resampler) = self.readback.as_mut()?;
+        let result = resampler
+            .create_input_view(texture, frame.data[1] as u32)
+            .and_then(|view| resampler.process(Some(view)))
+            .and_then(|_| resampler.get_output_buffer());
+
+        let buffer = match result {
+            Ok(it) => it,
+            Err(e) => {
+                tracing::warn!("failed to read back the luma, err={:?}", e);
+
+                return None;
+            }
+        };
+
+        let (data, stride) = (buffer.buffer(), buffer.stride() as usize);
+        self.samples.clear();
+        for y in 0..size.height as usize {
+            let row = unsafe { from_raw_parts(data.add(y * stride), size.width as usize) };
+            self.samples.extend_from_slice(row);
+        }
+
+        Some(())
+    }
+}
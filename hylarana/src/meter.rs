@@ -0,0 +1,159 @@
+use std::slice::from_raw_parts;
+
+use common::frame::AudioFrame;
+
+use crate::SilenceOptions;
+
+/// The level of the audio over the last metering interval, the values are
+/// linear, 0.0 is silence and 1.0 is full scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioLevel {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+impl AudioLevel {
+    /// Below this level the audio is considered silent, a working microphone
+    /// in a quiet room is usually well above it.
+    pub const SILENCE_DB: f32 = -60.0;
+
+    /// The rms level in decibels relative to full scale.
+    pub fn rms_db(&self) -> f32 {
+        Self::to_db(self.rms)
+    }
+
+    /// The peak level in decibels relative to full scale.
+    pub fn peak_db(&self) -> f32 {
+        Self::to_db(self.peak)
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.peak_db() < Self::SILENCE_DB
+    }
+
+    fn to_db(value: f32) -> f32 {
+        if value > 0.0 {
+            20.0 * value.log10()
+        } else {
+            f32::NEG_INFINITY
+        }
+    }
+}
+
+fn samples(frame: &AudioFrame) -> &[i16] {
+    if frame.data.is_null() {
+        return &[];
+    }
+
+    unsafe { from_raw_parts(frame.data, frame.frames as usize * frame.channels as usize) }
+}
+
+/// Accumulates the rms and the peak of the frames, and reports the level once
+/// per interval of the audio, so the cost is a single pass over the samples.
+pub(crate) struct AudioMeter {
+    sum_squares: f64,
+    peak: i32,
+    samples: u64,
+    /// The frames per channel since the last report.
+    frames: u32,
+}
+
+impl AudioMeter {
+    /// The interval of the reports in milliseconds.
+    const INTERVAL: u32 = 100;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            sum_squares: 0.0,
+            samples: 0,
+            frames: 0,
+            peak: 0,
+        }
+    }
+
+    /// Measure the frame, returns the level when an interval is complete.
+    pub(crate) fn process(&mut self, frame: &AudioFrame) -> Option<AudioLevel> {
+        let samples = samples(frame);
+        if samples.is_empty() {
+            return None;
+        }
+
+        for it in samples {
+            let sample = *it as i32;
+
+            self.sum_squares += (sample * sample) as f64;
+            self.peak = self.peak.max(sample.abs());
+        }
+
+        self.samples += samples.len() as u64;
+        self.frames += frame.frames;
+
+        if self.frames < frame.sample_rate * Self::INTERVAL / 1000 {
+            return None;
+        }
+
+        let full_scale = i16::MAX as f64 + 1.0;
+        let level = AudioLevel {
+            rms: ((self.sum_squares / self.samples as f64).sqrt() / full_scale) as f32,
+            peak: (self.peak as f64 / full_scale) as f32,
+        };
+
+        *self = Self::new();
+        Some(level)
+    }
+}
+
+/// Detects the silence of the captured audio from the peak of each frame, the
+/// audio is paused after it has been silent for the timeout, and resumed with
+/// the first frame above the threshold.
+pub(crate) struct SilenceDetector {
+    threshold: i32,
+    timeout: u64,
+    /// The silent frames per channel since the last sound.
+    silent_frames: u64,
+    paused: bool,
+}
+
+impl SilenceDetector {
+    pub(crate) fn new(options: &SilenceOptions) -> Self {
+        Self {
+            threshold: (10f32.powf(options.threshold / 20.0) * (i16::MAX as f32 + 1.0)) as i32,
+            timeout: options.timeout,
+            silent_frames: 0,
+            paused: false,
+        }
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns the new state when the audio is paused or resumed.
+    pub(crate) fn process(&mut self, frame: &AudioFrame) -> Option<bool> {
+        let peak = samples(frame)
+            .iter()
+            .map(|it| (*it as i32).abs())
+            .max()
+            .unwrap_or(0);
+
+        if peak > self.threshold {
+            self.silent_frames = 0;
+
+            return if self.paused {
+                self.paused = false;
+                Some(false)
+            } else {
+                None
+            };
+        }
+
+        self.silent_frames += frame.frames as u64;
+        if !self.paused && self.silent_frames * 1000 >= self.timeout * frame.sample_rate as u64 {
+            self.paused = true;
+
+            return Some(true);
+        }
+
+        None
+    }
+}
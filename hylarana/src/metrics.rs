@@ -0,0 +1,379 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use transport::{StreamType, TransportSender};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+static GLOBAL: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Default)]
+struct TrackCounters {
+    frames: AtomicU64,
+    bytes: AtomicU64,
+    micros: AtomicU64,
+    lost: AtomicU64,
+}
+
+impl TrackCounters {
+    fn load(&self) -> TrackMetrics {
+        TrackMetrics {
+            frames: self.frames.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            codec_seconds: self.micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            lost: self.lost.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tracks {
+    video: TrackCounters,
+    audio: TrackCounters,
+}
+
+impl Tracks {
+    fn get(&self, stream: StreamType) -> &TrackCounters {
+        match stream {
+            StreamType::Video => &self.video,
+            StreamType::Audio => &self.audio,
+        }
+    }
+}
+
+/// The counters of a track, the frame rate and the bit rate are the rates of
+/// the frames and the bytes.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct TrackMetrics {
+    /// The frames encoded by the senders, or the packets decoded by the
+    /// receivers.
+    pub frames: u64,
+    /// The bytes of the encoded packets.
+    pub bytes: u64,
+    /// The total time of the encoding or the decoding, divided by the frames
+    /// it is the average time of a frame.
+    pub codec_seconds: f64,
+    /// The packets lost by the transport, only for the receivers.
+    pub lost: u64,
+}
+
+/// The values of the metrics at a time, see [Metrics::snapshot].
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct MetricsSnapshot {
+    pub sent_video: TrackMetrics,
+    pub sent_audio: TrackMetrics,
+    pub received_video: TrackMetrics,
+    pub received_audio: TrackMetrics,
+    /// The packets in the sink queues of all the receivers, see
+    /// `HylaranaReceiverOptions::sink_queue`, the Prometheus endpoint has the
+    /// depth of each receiver.
+    pub sink_queue_depth: u64,
+    /// The round trip time to the receiver in milliseconds.
+    pub rtt: f64,
+    /// The share of the packets dropped by the transport of the sender,
+    /// between 0.0 and 1.0.
+    pub loss_rate: f64,
+    /// The time from the capture on the sender to the decoding on the
+    /// receiver in milliseconds, measured on the synced clocks.
+    pub latency: f64,
+}
+
+/// The metrics of all the senders and receivers of the process, the counters
+/// only increase, the gauges hold the last value of any sender, a device
+/// usually runs one sender or one receiver at a time. The depth of the sink
+/// queue is kept for each receiver.
+#[derive(Default)]
+pub struct Metrics {
+    sent: Tracks,
+    received: Tracks,
+    /// The depth of the sink queue of each receiver, by the id of the queue.
+    sink_queues: Mutex<BTreeMap<u64, u64>>,
+    sink_queue_id: AtomicU64,
+    /// The bits of the f64 values.
+    rtt: AtomicU64,
+    loss_rate: AtomicU64,
+    latency: AtomicU64,
+    transport_updated: Mutex<Option<Instant>>,
+}
+
+impl Metrics {
+    /// The transport is sampled at most once in this interval.
+    const TRANSPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn global() -> &'static Self {
+        &GLOBAL
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            sent_video: self.sent.video.load(),
+            sent_audio: self.sent.audio.load(),
+            received_video: self.received.video.load(),
+            received_audio: self.received.audio.load(),
+            sink_queue_depth: self.sink_queues.lock().values().sum(),
+            rtt: f64::from_bits(self.rtt.load(Ordering::Relaxed)),
+            loss_rate: f64::from_bits(self.loss_rate.load(Ordering::Relaxed)),
+            latency: f64::from_bits(self.latency.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// The metrics in the text format of Prometheus.
+    pub fn render(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut text = String::new();
+
+        let mut counter = |name: &str, help: &str, values: [(&str, f64); 4]| {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} counter", name);
+
+            for (labels, value) in values {
+                let _ = writeln!(text, "{}{{{}}} {}", name, labels, value);
+            }
+        };
+
+        let tracks = [
+            ("direction=\"sent\",stream=\"video\"", &snapshot.sent_video),
+            ("direction=\"sent\",stream=\"audio\"", &snapshot.sent_audio),
+            (
+                "direction=\"received\",stream=\"video\"",
+                &snapshot.received_video,
+            ),
+            (
+                "direction=\"received\",stream=\"audio\"",
+                &snapshot.received_audio,
+            ),
+        ];
+
+        counter(
+            "hylarana_frames_total",
+            "Frames encoded by the senders or decoded by the receivers.",
+            tracks.map(|(labels, it)| (labels, it.frames as f64)),
+        );
+
+        counter(
+            "hylarana_bytes_total",
+            "Bytes of the encoded packets.",
+            tracks.map(|(labels, it)| (labels, it.bytes as f64)),
+        );
+
+        counter(
+            "hylarana_codec_seconds_total",
+            "Time spent encoding or decoding.",
+            tracks.map(|(labels, it)| (labels, it.codec_seconds)),
+        );
+
+        counter(
+            "hylarana_packets_lost_total",
+            "Packets lost by the transport.",
+            tracks.map(|(labels, it)| (labels, it.lost as f64)),
+        );
+
+        let _ = writeln!(
+            text,
+            "# HELP hylarana_sink_queue_depth Packets in the sink queue of a receiver."
+        );
+        let _ = writeln!(text, "# TYPE hylarana_sink_queue_depth gauge");
+        for (id, depth) in self.sink_queues.lock().iter() {
+            let _ = writeln!(
+                text,
+                "hylarana_sink_queue_depth{{receiver=\"{}\"}} {}",
+                id, depth
+            );
+        }
+
+        for (name, help, value) in [
+            (
+                "hylarana_rtt_milliseconds",
+                "Round trip time to the receiver.",
+                snapshot.rtt,
+            ),
+            (
+                "hylarana_loss_rate",
+                "Share of the packets dropped by the transport of the sender.",
+                snapshot.loss_rate,
+            ),
+            (
+                "hylarana_latency_milliseconds",
+                "Time from the capture on the sender to the decoding on the receiver.",
+                snapshot.latency,
+            ),
+        ] {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} gauge", name);
+            let _ = writeln!(text, "{} {}", name, value);
+        }
+
+        text
+    }
+
+    pub(crate) fn encoded(&self, stream: StreamType, elapsed: Duration) {
+        let track = self.sent.get(stream);
+
+        track.frames.fetch_add(1, Ordering::Relaxed);
+        track
+            .micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sent(&self, stream: StreamType, bytes: usize) {
+        self.sent
+            .get(stream)
+            .bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn decoded(&self, stream: StreamType, bytes: usize, elapsed: Duration) {
+        let track = self.received.get(stream);
+
+        track.frames.fetch_add(1, Ordering::Relaxed);
+        track.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        track
+            .micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn lost(&self, stream: StreamType, count: u32) {
+        self.received
+            .get(stream)
+            .lost
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// Adds the gauge of the sink queue of a receiver, returns the id of the
+    /// queue, which is the `receiver` label of the gauge.
+    pub(crate) fn add_sink_queue(&self) -> u64 {
+        let id = self.sink_queue_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.sink_queues.lock().insert(id, 0);
+
+        id
+    }
+
+    pub(crate) fn set_sink_queue_depth(&self, id: u64, depth: usize) {
+        if let Some(it) = self.sink_queues.lock().get_mut(&id) {
+            *it = depth as u64;
+        }
+    }
+
+    pub(crate) fn remove_sink_queue(&self, id: u64) {
+        self.sink_queues.lock().remove(&id);
+    }
+
+    pub(crate) fn set_latency(&self, latency: Duration) {
+        self.latency.store(
+            (latency.as_secs_f64() * 1000.0).to_bits(),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Samples the round trip time and the loss rate of the transport, this is
+    /// called for every frame and skipped until the interval has passed.
+    pub(crate) fn update_transport(&self, transport: &TransportSender) {
+        {
+            let mut updated = self.transport_updated.lock();
+            if updated
+                .map(|it| it.elapsed() < Self::TRANSPORT_INTERVAL)
+                .unwrap_or(false)
+            {
+                return;
+            }
+
+            updated.replace(Instant::now());
+        }
+
+        if let Some(rtt) = transport.get_rtt() {
+            self.rtt.store(rtt.to_bits(), Ordering::Relaxed);
+        }
+
+        self.loss_rate
+            .store(transport.get_pkt_lose_rate().to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// A small http server for the scraping of Prometheus, any request is
+/// answered with the metrics of the process, see [Metrics::render].
+pub struct MetricsServer {
+    running: Arc<AtomicBool>,
+    address: SocketAddr,
+}
+
+impl MetricsServer {
+    pub fn bind(addr: SocketAddr) -> Result<Self, io::Error> {
+        let listener = TcpListener::bind(addr)?;
+        let address = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+
+        let running_ = running.clone();
+        thread::Builder::new()
+            .name("HylaranaMetricsThread".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if !running_.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    if let Err(e) = stream.and_then(Self::respond) {
+                        tracing::warn!("metrics server failed to respond, err={:?}", e);
+                    }
+                }
+
+                tracing::info!("metrics server is closed, addr={}", address);
+            })?;
+
+        tracing::info!("metrics server is listening, addr={}", address);
+
+        Ok(Self { running, address })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    fn respond(mut stream: TcpStream) -> Result<(), io::Error> {
+        // Only the scrapers connect, a client that does not send the request in time
+        // does not hold up the server.
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        let mut request = [0u8; 4096];
+        let _ = stream.read(&mut request)?;
+
+        let body = Metrics::global().render();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        // Wake up the accepting thread.
+        let mut address = self.address;
+        if address.ip().is_unspecified() {
+            address.set_ip(if address.is_ipv4() {
+                Ipv4Addr::LOCALHOST.into()
+            } else {
+                Ipv6Addr::LOCALHOST.into()
+            });
+        }
+
+        let _ = TcpStream::connect_timeout(&address, Duration::from_secs(1));
+    }
+}
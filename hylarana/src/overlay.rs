@@ -0,0 +1,700 @@
+use std::{fs, path::PathBuf};
+
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont, point};
+use common::frame::{VideoFormat, VideoFrame, VideoSubFormat};
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+use common::win32::{
+    Direct3DDevice, d3d_texture_borrowed_raw,
+    windows::Win32::Graphics::{
+        Direct3D11::{
+            D3D11_BOX, D3D11_CPU_ACCESS_READ, D3D11_CPU_ACCESS_WRITE, D3D11_MAP_READ_WRITE,
+            D3D11_MAPPED_SUBRESOURCE, D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING, ID3D11Texture2D,
+        },
+        Dxgi::Common::{DXGI_FORMAT_NV12, DXGI_SAMPLE_DESC},
+    },
+};
+
+#[cfg(target_os = "macos")]
+use std::ffi::c_void;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Error)]
+pub enum OverlayError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("invalid font file")]
+    InvalidFont,
+    #[error("the size of the image does not match the pixels")]
+    InvalidImage,
+    #[error("failed to lock the pixel buffer")]
+    LockPixelBufferError,
+    #[error(transparent)]
+    #[cfg(target_os = "windows")]
+    Win32Error(#[from] common::win32::windows::core::Error),
+}
+
+/// Where the overlay is placed in the video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum OverlayContent {
+    /// RGBA pixels with straight alpha, such as a logo.
+    Image {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    /// A single line of text, such as the device name. `{time}` in the text is
+    /// replaced with the current local time. The font is a TrueType or
+    /// OpenType font file.
+    Text {
+        text: String,
+        font: PathBuf,
+        size: f32,
+        /// RGBA color of the text.
+        color: [u8; 4],
+    },
+}
+
+/// An image or a text stamped onto the video.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct OverlayLayer {
+    pub content: OverlayContent,
+    pub position: OverlayPosition,
+    /// Distance to the edges of the video in pixels, ignored in the center.
+    pub margin: u32,
+    /// 0.0 is fully transparent, 1.0 is opaque.
+    pub opacity: f32,
+}
+
+/// Overlays composited onto the outgoing video before encoding, the layers
+/// are drawn in order.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct OverlayOptions {
+    pub layers: Vec<OverlayLayer>,
+}
+
+impl OverlayLayer {
+    /// Rasterize the layer for the renderer of the receiver, the opacity is
+    /// applied to the alpha. The text is rendered only once here, so `{time}`
+    /// is the time the layer is set.
+    pub(crate) fn rasterize(&self) -> Result<renderer::OverlayLayer, OverlayError> {
+        let mut layer = Layer::new(self.clone())?;
+        layer.update();
+
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        let mut pixels = layer.bitmap.pixels;
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel[3] = (pixel[3] as f32 * opacity) as u8;
+        }
+
+        Ok(renderer::OverlayLayer {
+            width: layer.bitmap.width,
+            height: layer.bitmap.height,
+            margin: self.margin,
+            position: match self.position {
+                OverlayPosition::TopLeft => renderer::OverlayPosition::TopLeft,
+                OverlayPosition::TopRight => renderer::OverlayPosition::TopRight,
+                OverlayPosition::BottomLeft => renderer::OverlayPosition::BottomLeft,
+                OverlayPosition::BottomRight => renderer::OverlayPosition::BottomRight,
+                OverlayPosition::Center => renderer::OverlayPosition::Center,
+            },
+            pixels,
+        })
+    }
+}
+
+/// RGBA pixels with straight alpha.
+#[derive(Default)]
+struct Bitmap {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Bitmap {
+    fn from_text(font: &FontVec, text: &str, size: f32, color: [u8; 4]) -> Self {
+        let scale = PxScale::from(size);
+        let scaled = font.as_scaled(scale);
+
+        let mut glyphs = Vec::with_capacity(text.len());
+        let mut caret = 0.0f32;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += scaled.kern(previous, id);
+            }
+
+            glyphs.push(id.with_scale_and_position(scale, point(caret, scaled.ascent())));
+            caret += scaled.h_advance(id);
+            previous = Some(id);
+        }
+
+        let width = caret.ceil().max(1.0) as u32;
+        let height = scaled.height().ceil().max(1.0) as u32;
+
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+        for chunk in pixels.chunks_exact_mut(4) {
+            chunk[..3].copy_from_slice(&color[..3]);
+        }
+
+        for glyph in glyphs {
+            if let Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, coverage| {
+                    let x = bounds.min.x as i64 + x as i64;
+                    let y = bounds.min.y as i64 + y as i64;
+                    if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                        return;
+                    }
+
+                    let alpha = &mut pixels[(y as usize * width as usize + x as usize) * 4 + 3];
+                    *alpha = (*alpha).max((coverage * color[3] as f32) as u8);
+                });
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Returns the color and the alpha of the pixel, the alpha is scaled by
+    /// the opacity.
+    #[inline]
+    fn pixel(&self, x: u32, y: u32, opacity: f32) -> ([u8; 3], u32) {
+        let index = (y as usize * self.width as usize + x as usize) * 4;
+        let pixel = &self.pixels[index..index + 4];
+
+        (
+            [pixel[0], pixel[1], pixel[2]],
+            (pixel[3] as f32 * opacity.clamp(0.0, 1.0)) as u32,
+        )
+    }
+}
+
+/// BT.709 limited range, the same as the color space tagged by the encoder.
+#[inline]
+fn rgb_to_yuv([r, g, b]: [u8; 3]) -> (u8, u8, u8) {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+
+    (
+        (((47 * r + 157 * g + 16 * b + 128) >> 8) + 16) as u8,
+        (((-26 * r - 87 * g + 112 * b + 128) >> 8) + 128) as u8,
+        (((112 * r - 102 * g - 10 * b + 128) >> 8) + 128) as u8,
+    )
+}
+
+#[inline]
+fn blend(dst: &mut u8, src: u8, alpha: u32) {
+    *dst = ((src as u32 * alpha + *dst as u32 * (255 - alpha)) / 255) as u8;
+}
+
+/// Rectangle of the layer inside of the frame, clipped to the frame.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The planes of a NV12 image that can be written.
+pub(crate) struct Nv12Planes {
+    pub y: *mut u8,
+    pub y_stride: usize,
+    pub uv: *mut u8,
+    pub uv_stride: usize,
+}
+
+impl Nv12Planes {
+    /// The planes of the rect of a software frame, the origin of the planes
+    /// is the origin of the rect.
+    pub fn from_frame(frame: &VideoFrame, rect: Rect) -> Self {
+        let (x, y) = (rect.x as usize, rect.y as usize);
+        let (y_stride, uv_stride) = (frame.linesize[0] as usize, frame.linesize[1] as usize);
+
+        Self {
+            y: unsafe { (frame.data[0] as *mut u8).add(y * y_stride + x) },
+            uv: unsafe { (frame.data[1] as *mut u8).add(y / 2 * uv_stride + x) },
+            y_stride,
+            uv_stride,
+        }
+    }
+
+    /// Blend the bitmap at the origin of the planes, the rect is the size of
+    /// the area to blend, the chroma is blended per 2x2 block.
+    fn blend(&mut self, bitmap: &Bitmap, rect: Rect, opacity: f32) {
+        for y in 0..rect.height {
+            let row = unsafe { self.y.add(y as usize * self.y_stride) };
+
+            for x in 0..rect.width {
+                let (rgb, alpha) = bitmap.pixel(x, y, opacity);
+                if alpha > 0 {
+                    blend(
+                        unsafe { &mut *row.add(x as usize) },
+                        rgb_to_yuv(rgb).0,
+                        alpha,
+                    );
+                }
+            }
+        }
+
+        for y in (0..rect.height & !1).step_by(2) {
+            let row = unsafe { self.uv.add((y / 2) as usize * self.uv_stride) };
+
+            for x in (0..rect.width & !1).step_by(2) {
+                let (mut rgb, mut alpha) = ([0u32; 3], 0u32);
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let (pixel, a) = bitmap.pixel(x + dx, y + dy, opacity);
+                    for i in 0..3 {
+                        rgb[i] += pixel[i] as u32;
+                    }
+
+                    alpha += a;
+                }
+
+                let alpha = alpha / 4;
+                if alpha > 0 {
+                    let (_, u, v) =
+                        rgb_to_yuv([(rgb[0] / 4) as u8, (rgb[1] / 4) as u8, (rgb[2] / 4) as u8]);
+
+                    blend(unsafe { &mut *row.add(x as usize) }, u, alpha);
+                    blend(unsafe { &mut *row.add(x as usize + 1) }, v, alpha);
+                }
+            }
+        }
+    }
+}
+
+/// A staging texture of the size of a region of a NV12 texture, only the
+/// region is read back, edited on the cpu and written back, the rest of the
+/// texture is not touched.
+#[cfg(target_os = "windows")]
+#[derive(Default)]
+pub(crate) struct StagingRegion(Option<(ID3D11Texture2D, Rect)>);
+
+#[cfg(target_os = "windows")]
+impl StagingRegion {
+    pub fn edit<F>(
+        &mut self,
+        direct3d: &Direct3DDevice,
+        texture: &ID3D11Texture2D,
+        index: u32,
+        rect: Rect,
+        func: F,
+    ) -> Result<(), OverlayError>
+    where
+        F: FnOnce(&mut Nv12Planes),
+    {
+        if self
+            .0
+            .as_ref()
+            .map(|(_, it)| it.width != rect.width || it.height != rect.height)
+            .unwrap_or(true)
+        {
+            let desc = D3D11_TEXTURE2D_DESC {
+                Width: rect.width,
+                Height: rect.height,
+                MipLevels: 1,
+                ArraySize: 1,
+                Format: DXGI_FORMAT_NV12,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Usage: D3D11_USAGE_STAGING,
+                BindFlags: 0,
+                CPUAccessFlags: (D3D11_CPU_ACCESS_READ.0 | D3D11_CPU_ACCESS_WRITE.0) as u32,
+                MiscFlags: 0,
+            };
+
+            let mut staging = None;
+            unsafe {
+                direct3d
+                    .device
+                    .CreateTexture2D(&desc, None, Some(&mut staging))?;
+            }
+
+            self.0 = staging.map(|it| (it, rect));
+        }
+
+        let Some((staging, _)) = &self.0 else {
+            return Ok(());
+        };
+
+        let region = D3D11_BOX {
+            left: rect.x,
+            top: rect.y,
+            front: 0,
+            right: rect.x + rect.width,
+            bottom: rect.y + rect.height,
+            back: 1,
+        };
+
+        unsafe {
+            direct3d.context.CopySubresourceRegion(
+                staging,
+                0,
+                0,
+                0,
+                0,
+                texture,
+                index,
+                Some(&region),
+            );
+        }
+
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        unsafe {
+            direct3d
+                .context
+                .Map(staging, 0, D3D11_MAP_READ_WRITE, 0, Some(&mut mapped))?;
+        }
+
+        let stride = mapped.RowPitch as usize;
+        func(&mut Nv12Planes {
+            y: mapped.pData as *mut u8,
+            uv: unsafe { (mapped.pData as *mut u8).add(stride * rect.height as usize) },
+            y_stride: stride,
+            uv_stride: stride,
+        });
+
+        unsafe {
+            direct3d.context.Unmap(staging, 0);
+            direct3d
+                .context
+                .CopySubresourceRegion(texture, index, rect.x, rect.y, 0, staging, 0, None);
+        }
+
+        Ok(())
+    }
+}
+
+/// A copy of a software NV12 frame that can be written, the buffers of the
+/// software frames belong to the capture and may be mapped read-only.
+#[derive(Default)]
+pub(crate) struct FrameCopy {
+    buffer: Vec<u8>,
+    frame: VideoFrame,
+}
+
+impl FrameCopy {
+    pub fn copy(&mut self, frame: &VideoFrame) -> &VideoFrame {
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let uv_height = height.div_ceil(2);
+
+        self.buffer.resize(width * (height + uv_height), 0);
+
+        let (y, uv) = self.buffer.split_at_mut(width * height);
+        for (plane, stride, rows, src) in [
+            (y, frame.linesize[0] as usize, height, frame.data[0]),
+            (uv, frame.linesize[1] as usize, uv_height, frame.data[1]),
+        ] {
+            for row in 0..rows {
+                plane[row * width..(row + 1) * width].copy_from_slice(unsafe {
+                    std::slice::from_raw_parts((src as *const u8).add(row * stride), width)
+                });
+            }
+        }
+
+        self.frame.format = frame.format;
+        self.frame.sub_format = frame.sub_format;
+        self.frame.width = frame.width;
+        self.frame.height = frame.height;
+        self.frame.timestamp = frame.timestamp;
+        self.frame.data[0] = self.buffer.as_mut_ptr() as *const _;
+        self.frame.data[1] = unsafe { self.buffer.as_mut_ptr().add(width * height) } as *const _;
+        self.frame.linesize = [frame.width, frame.width, 0];
+
+        &self.frame
+    }
+}
+
+/// Locks the BGRA pixel buffer of the frame for writing, the base address and
+/// the stride are passed to the function.
+#[cfg(target_os = "macos")]
+pub(crate) fn lock_pixel_buffer<F>(frame: &VideoFrame, func: F) -> Result<(), OverlayError>
+where
+    F: FnOnce(*mut u8, usize),
+{
+    let buffer = frame.data[0];
+    if unsafe { CVPixelBufferLockBaseAddress(buffer, 0) } != 0 {
+        return Err(OverlayError::LockPixelBufferError);
+    }
+
+    let base = unsafe { CVPixelBufferGetBaseAddress(buffer) } as *mut u8;
+    let stride = unsafe { CVPixelBufferGetBytesPerRow(buffer) };
+    if !base.is_null() {
+        func(base, stride);
+    }
+
+    unsafe {
+        CVPixelBufferUnlockBaseAddress(buffer, 0);
+    }
+
+    Ok(())
+}
+
+struct Layer {
+    options: OverlayLayer,
+    font: Option<FontVec>,
+    text: String,
+    bitmap: Bitmap,
+    #[cfg(target_os = "windows")]
+    staging: StagingRegion,
+}
+
+impl Layer {
+    fn new(options: OverlayLayer) -> Result<Self, OverlayError> {
+        let (font, bitmap) = match &options.content {
+            OverlayContent::Image {
+                width,
+                height,
+                pixels,
+            } => {
+                if pixels.len() != *width as usize * *height as usize * 4 {
+                    return Err(OverlayError::InvalidImage);
+                }
+
+                (
+                    None,
+                    Bitmap {
+                        width: *width,
+                        height: *height,
+                        pixels: pixels.clone(),
+                    },
+                )
+            }
+            OverlayContent::Text { font, .. } => (
+                Some(
+                    FontVec::try_from_vec(fs::read(font)?)
+                        .map_err(|_| OverlayError::InvalidFont)?,
+                ),
+                Bitmap::default(),
+            ),
+        };
+
+        Ok(Self {
+            text: String::new(),
+            options,
+            bitmap,
+            font,
+            #[cfg(target_os = "windows")]
+            staging: StagingRegion::default(),
+        })
+    }
+
+    /// The text is rendered again only when it changes, which is at most once
+    /// per second for the time.
+    fn update(&mut self) {
+        if let (
+            OverlayContent::Text {
+                text, size, color, ..
+            },
+            Some(font),
+        ) = (&self.options.content, &self.font)
+        {
+            let text = if text.contains("{time}") {
+                text.replace(
+                    "{time}",
+                    &chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                )
+            } else {
+                text.clone()
+            };
+
+            if text != self.text {
+                self.bitmap = Bitmap::from_text(font, &text, *size, *color);
+                self.text = text;
+            }
+        }
+    }
+
+    /// The origin is aligned to even pixels, so that the chroma of the layer
+    /// does not straddle two 2x2 blocks of the NV12 frame.
+    fn rect(&self, width: u32, height: u32) -> Option<Rect> {
+        let margin = self.options.margin;
+        let (w, h) = (self.bitmap.width, self.bitmap.height);
+
+        let x = match self.options.position {
+            OverlayPosition::TopLeft | OverlayPosition::BottomLeft => margin,
+            OverlayPosition::TopRight | OverlayPosition::BottomRight => {
+                width.saturating_sub(w + margin)
+            }
+            OverlayPosition::Center => width.saturating_sub(w) / 2,
+        } & !1;
+
+        let y = match self.options.position {
+            OverlayPosition::TopLeft | OverlayPosition::TopRight => margin,
+            OverlayPosition::BottomLeft | OverlayPosition::BottomRight => {
+                height.saturating_sub(h + margin)
+            }
+            OverlayPosition::Center => height.saturating_sub(h) / 2,
+        } & !1;
+
+        let rect = Rect {
+            width: w.min(width.saturating_sub(x)) & !1,
+            height: h.min(height.saturating_sub(y)) & !1,
+            x,
+            y,
+        };
+
+        if rect.width == 0 || rect.height == 0 {
+            None
+        } else {
+            Some(rect)
+        }
+    }
+
+    fn blend_nv12(&self, frame: &VideoFrame) {
+        if let Some(rect) = self.rect(frame.width, frame.height) {
+            Nv12Planes::from_frame(frame, rect).blend(&self.bitmap, rect, self.options.opacity);
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    fn blend_texture(
+        &mut self,
+        direct3d: &Direct3DDevice,
+        texture: &ID3D11Texture2D,
+        index: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), OverlayError> {
+        let Some(rect) = self.rect(width, height) else {
+            return Ok(());
+        };
+
+        let (bitmap, opacity) = (&self.bitmap, self.options.opacity);
+        self.staging.edit(direct3d, texture, index, rect, |planes| {
+            planes.blend(bitmap, rect, opacity)
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    fn blend_bgra(&self, buffer: *mut u8, stride: usize, width: u32, height: u32) {
+        if let Some(rect) = self.rect(width, height) {
+            for y in 0..rect.height {
+                let row =
+                    unsafe { buffer.add((rect.y + y) as usize * stride + rect.x as usize * 4) };
+
+                for x in 0..rect.width {
+                    let ([r, g, b], alpha) = self.bitmap.pixel(x, y, self.options.opacity);
+                    if alpha > 0 {
+                        let pixel =
+                            unsafe { std::slice::from_raw_parts_mut(row.add(x as usize * 4), 4) };
+                        blend(&mut pixel[0], b, alpha);
+                        blend(&mut pixel[1], g, alpha);
+                        blend(&mut pixel[2], r, alpha);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreVideo", kind = "framework")]
+unsafe extern "C" {
+    fn CVPixelBufferLockBaseAddress(buffer: *const c_void, flags: u64) -> i32;
+    fn CVPixelBufferUnlockBaseAddress(buffer: *const c_void, flags: u64) -> i32;
+    fn CVPixelBufferGetBaseAddress(buffer: *const c_void) -> *mut c_void;
+    fn CVPixelBufferGetBytesPerRow(buffer: *const c_void) -> usize;
+}
+
+/// Composites the overlay layers onto the outgoing video frames.
+///
+/// Hardware frames are blended in place, the software frames are copied
+/// first, because the buffers of the software frames belong to the capture and
+/// may be mapped read-only.
+pub(crate) struct Overlay {
+    layers: Vec<Layer>,
+    copy: FrameCopy,
+    #[cfg(target_os = "windows")]
+    direct3d: Direct3DDevice,
+}
+
+impl Overlay {
+    pub fn new(
+        options: &OverlayOptions,
+        #[cfg(target_os = "windows")] direct3d: Direct3DDevice,
+    ) -> Result<Self, OverlayError> {
+        let mut layers = Vec::with_capacity(options.layers.len());
+        for layer in &options.layers {
+            layers.push(Layer::new(layer.clone())?);
+        }
+
+        Ok(Self {
+            copy: FrameCopy::default(),
+            layers,
+            #[cfg(target_os = "windows")]
+            direct3d,
+        })
+    }
+
+    /// Returns the frame with the overlays, frames in formats that are not
+    /// supported are returned untouched.
+    pub fn apply<'a>(&'a mut self, frame: &'a VideoFrame) -> Result<&'a VideoFrame, OverlayError> {
+        for layer in self.layers.iter_mut() {
+            layer.update();
+        }
+
+        match (frame.sub_format, frame.format) {
+            (VideoSubFormat::SW, VideoFormat::NV12) => {
+                let frame = self.copy.copy(frame);
+                for layer in &self.layers {
+                    layer.blend_nv12(frame);
+                }
+
+                Ok(frame)
+            }
+            #[cfg(target_os = "windows")]
+            (VideoSubFormat::D3D11, VideoFormat::NV12) => {
+                let raw = frame.data[0] as *mut _;
+                if let Some(texture) = d3d_texture_borrowed_raw(&raw) {
+                    for layer in self.layers.iter_mut() {
+                        layer.blend_texture(
+                            &self.direct3d,
+                            texture,
+                            frame.data[1] as u32,
+                            frame.width,
+                            frame.height,
+                        )?;
+                    }
+                }
+
+                Ok(frame)
+            }
+            #[cfg(target_os = "macos")]
+            (VideoSubFormat::CvPixelBufferRef, VideoFormat::BGRA) => {
+                lock_pixel_buffer(frame, |base, stride| {
+                    for layer in &self.layers {
+                        layer.blend_bgra(base, stride, frame.width, frame.height);
+                    }
+                })?;
+
+                Ok(frame)
+            }
+            _ => Ok(frame),
+        }
+    }
+}
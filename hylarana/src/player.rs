@@ -1,34 +1,49 @@
-use std::{slice::from_raw_parts, sync::Arc};
+use std::{
+    collections::VecDeque,
+    slice::from_raw_parts,
+    sync::{Arc, mpsc::Receiver},
+    thread,
+    time::{Duration, Instant},
+};
 
 use super::{
-    HylaranaReceiverOptions, MediaStreamDescription, MediaStreamSink, sender::HylaranaSenderOptions,
+    HylaranaError, HylaranaReceiverOptions, MediaStreamDescription, MediaStreamSink, VideoOutput,
+    filter::{AudioFilter, Volume, filter_samples},
+    overlay::{OverlayError, OverlayLayer},
+    sender::HylaranaSenderOptions,
 };
 
 #[cfg(target_os = "windows")]
 use super::util::get_direct3d;
 
 #[cfg(target_os = "windows")]
-use common::win32::d3d_texture_borrowed_raw;
+use common::win32::{Direct3DDevice, d3d_texture_borrowed_raw};
 
 #[cfg(target_os = "macos")]
 use common::macos::{CVPixelBufferRef, PixelMomeryBuffer};
 
-#[cfg(not(target_os = "linux"))]
+#[cfg(target_os = "linux")]
+use common::linux::DmaBufDescriptor;
+
 use renderer::Texture2DRaw;
 
 use common::{
-    Size,
+    SessionMode, Size,
     codec::{VideoDecoderType, VideoEncoderType},
+    events::Broadcaster,
     frame::{AudioFrame, VideoFormat, VideoFrame, VideoSubFormat},
 };
 
 use renderer::{
-    Renderer, RendererOptions, RendererSourceOptions, RendererSurfaceOptions, SurfaceTarget,
-    Texture, Texture2DBuffer, Texture2DResource,
+    MultiRenderer, MultiRendererLayout, MultiRendererOptions, PresentMode, Renderer,
+    RendererBackend, RendererEvent, RendererOptions, RendererPresentOptions, RendererSourceOptions,
+    RendererSurfaceOptions, RendererTextureOptions, ScalingMode, ScalingQuality, SurfaceTarget,
+    Texture, Texture2DBuffer, Texture2DResource, VideoFilter, wgpu,
 };
 
+use capture::{Source, SourceType};
 use parking_lot::Mutex;
-use rodio::{OutputStream, OutputStreamHandle, Sink};
+use rodio::{Device, DeviceTrait, OutputStream, OutputStreamHandle, Sink, cpal::traits::HostTrait};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -38,6 +53,8 @@ pub enum VideoRenderError {
     #[error("invalid d3d11texture2d texture")]
     #[cfg(target_os = "windows")]
     InvalidD3D11Texture,
+    #[error("failed to create thread")]
+    CreateThreadError,
 }
 
 #[derive(Debug, Error)]
@@ -48,16 +65,14 @@ pub enum AudioRenderError {
     StreamError(#[from] rodio::StreamError),
     #[error(transparent)]
     PlayError(#[from] rodio::PlayError),
-    #[error("send audio queue error")]
-    SendQueueError,
-}
-
-#[derive(Debug, Error)]
-pub enum AVFrameStreamPlayerError {
     #[error(transparent)]
-    VideoRenderError(#[from] VideoRenderError),
+    DevicesError(#[from] rodio::DevicesError),
     #[error(transparent)]
-    AudioRenderError(#[from] AudioRenderError),
+    DeviceNameError(#[from] rodio::cpal::DeviceNameError),
+    #[error("send audio queue error")]
+    SendQueueError,
+    #[error("failed to create the device monitor thread")]
+    CreateThreadError,
 }
 
 /// Configuration of the audio and video streaming player.
@@ -65,13 +80,42 @@ pub enum AVFrameStreamPlayerOptions<T> {
     /// Play video only.
     OnlyVideo(VideoRenderOptions<T>),
     /// Both audio and video will play.
-    All(VideoRenderOptions<T>),
+    All(VideoRenderOptions<T>, AudioRenderOptions),
     /// Play audio only.
-    OnlyAudio,
+    OnlyAudio(AudioRenderOptions),
     /// Nothing plays.
     Quiet,
 }
 
+impl<T> AVFrameStreamPlayerOptions<T> {
+    /// Only the tracks of the mode are played, the renderers of the other
+    /// tracks are not created, see `HylaranaReceiver::get_mode`.
+    pub fn with_mode(self, mode: SessionMode) -> Self {
+        match (self, mode.has_video(), mode.has_audio()) {
+            (Self::All(video, audio), true, true) => Self::All(video, audio),
+            (Self::All(video, _) | Self::OnlyVideo(video), true, _) => Self::OnlyVideo(video),
+            (Self::All(_, audio) | Self::OnlyAudio(audio), _, true) => Self::OnlyAudio(audio),
+            _ => Self::Quiet,
+        }
+    }
+}
+
+/// Audio renderer configuration.
+#[derive(Debug, Default, Clone)]
+pub struct AudioRenderOptions {
+    /// The output device, see [AudioRender::get_devices], the default output
+    /// device is used if it is not set.
+    pub device: Option<Source>,
+    /// Applied to the audio in order before it is played, such as [crate::Gain]
+    /// and [crate::SoftLimiter].
+    pub filters: Vec<Arc<dyn AudioFilter>>,
+    /// The audio that is queued before the playback starts, and starts again
+    /// after an underrun, a larger buffer survives longer stalls at the cost
+    /// of latency. Zero plays the audio as soon as it arrives.
+    pub buffer: Duration,
+    pub underrun: AudioUnderrunPolicy,
+}
+
 #[derive(Debug)]
 pub struct VideoRenderSurfaceOptions<T> {
     pub window: T,
@@ -85,10 +129,68 @@ pub struct VideoRenderSourceOptions {
     pub sub_format: VideoSubFormat,
 }
 
+impl VideoRenderSourceOptions {
+    /// The frames that the receiver passes to the sink, `None` if the stream
+    /// has no video.
+    pub fn from_receiver(
+        description: &MediaStreamDescription,
+        options: &HylaranaReceiverOptions,
+    ) -> Option<Self> {
+        let it = description.video?;
+
+        Some(Self {
+            size: it.size,
+            format: match options.video_output {
+                VideoOutput::Rgba => VideoFormat::RGBA,
+                _ => it.format,
+            },
+            sub_format: match options.codec {
+                _ if options.video_output != VideoOutput::Native => VideoSubFormat::SW,
+                VideoDecoderType::HEVC | VideoDecoderType::Nvdec => VideoSubFormat::SW,
+                VideoDecoderType::Qsv | VideoDecoderType::D3D11 => {
+                    if it.format == VideoFormat::I420 {
+                        VideoSubFormat::SW
+                    } else {
+                        VideoSubFormat::D3D11
+                    }
+                }
+                VideoDecoderType::VideoToolBox => {
+                    if it.format == VideoFormat::BGRA || it.format == VideoFormat::RGBA {
+                        VideoSubFormat::CvPixelBufferRef
+                    } else {
+                        VideoSubFormat::SW
+                    }
+                }
+                VideoDecoderType::Vaapi => {
+                    if it.format == VideoFormat::I420 {
+                        VideoSubFormat::SW
+                    } else {
+                        VideoSubFormat::DmaBuf
+                    }
+                }
+            },
+        })
+    }
+}
+
 /// Video renderer configuration.
 pub struct VideoRenderOptions<T> {
     pub surface: VideoRenderSurfaceOptions<T>,
     pub source: VideoRenderSourceOptions,
+    /// See [RendererPresentOptions], the default of the platform is used if
+    /// these are not set.
+    pub present_mode: Option<PresentMode>,
+    pub max_frame_latency: Option<u32>,
+    /// The native backend of the platform is used if it is not set, the other
+    /// backends only accept software frames.
+    pub backend: Option<RendererBackend>,
+    pub scaling_quality: ScalingQuality,
+    pub scaling_mode: ScalingMode,
+    /// Applied to the video in order before it is drawn.
+    pub filters: Vec<Box<dyn VideoFilter>>,
+    /// See [VideoRenderOptionsBuilder::pacing], the frames are presented as
+    /// soon as they arrive if it is not set.
+    pub pacing: Option<Duration>,
 }
 
 pub struct VideoRenderOptionsBuilder<T>(VideoRenderOptions<T>);
@@ -105,16 +207,76 @@ impl<T> VideoRenderOptionsBuilder<T> {
                     VideoFormat::NV12
                 },
             },
+            present_mode: None,
+            max_frame_latency: None,
+            scaling_quality: ScalingQuality::default(),
+            scaling_mode: ScalingMode::default(),
+            filters: Vec::new(),
+            backend: None,
+            pacing: None,
             surface,
         })
     }
 
+    /// `Immediate` turns the vsync off for the lowest latency, `Fifo` avoids
+    /// the tearing.
+    pub fn present_mode(mut self, mode: PresentMode) -> Self {
+        self.0.present_mode = Some(mode);
+        self
+    }
+
+    pub fn max_frame_latency(mut self, max_frame_latency: u32) -> Self {
+        self.0.max_frame_latency = Some(max_frame_latency);
+        self
+    }
+
+    pub fn backend(mut self, backend: RendererBackend) -> Self {
+        self.0.backend = Some(backend);
+        self
+    }
+
+    /// How the video is sampled when it is scaled to the window, bilinear by
+    /// default.
+    pub fn scaling_quality(mut self, quality: ScalingQuality) -> Self {
+        self.0.scaling_quality = quality;
+        self
+    }
+
+    /// How the video is placed in the window, fit with black bars by default.
+    pub fn scaling_mode(mut self, mode: ScalingMode) -> Self {
+        self.0.scaling_mode = mode;
+        self
+    }
+
+    /// Present the frames at the intervals of their timestamps instead of as
+    /// soon as they arrive, so that a variable frame rate plays with its own
+    /// timing and the jitter of the network is smoothed out. A frame is held
+    /// back at most for the max delay, which is added to the latency.
+    pub fn pacing(mut self, max_delay: Duration) -> Self {
+        self.0.pacing = Some(max_delay);
+        self
+    }
+
+    /// Append a filter to the video, such as [renderer::Brightness].
+    pub fn filter<F: VideoFilter + 'static>(mut self, filter: F) -> Self {
+        self.0.filters.push(Box::new(filter));
+        self
+    }
+
     pub fn from_sender(mut self, options: &HylaranaSenderOptions) -> Self {
         if let Some(it) = &options.media.video {
             self.0.source.sub_format = match it.options.codec {
                 VideoEncoderType::X265 => VideoSubFormat::SW,
                 VideoEncoderType::Qsv => VideoSubFormat::D3D11,
                 VideoEncoderType::VideoToolBox => VideoSubFormat::CvPixelBufferRef,
+                VideoEncoderType::Vaapi => VideoSubFormat::SW,
+                VideoEncoderType::Nvenc => {
+                    if cfg!(target_os = "windows") {
+                        VideoSubFormat::D3D11
+                    } else {
+                        VideoSubFormat::SW
+                    }
+                }
             };
 
             self.0.source.size = Size {
@@ -131,26 +293,8 @@ impl<T> VideoRenderOptionsBuilder<T> {
         description: &MediaStreamDescription,
         options: &HylaranaReceiverOptions,
     ) -> Self {
-        if let Some(it) = description.video {
-            self.0.source.format = it.format;
-            self.0.source.size = it.size;
-            self.0.source.sub_format = match options.codec {
-                VideoDecoderType::HEVC => VideoSubFormat::SW,
-                VideoDecoderType::Qsv | VideoDecoderType::D3D11 => {
-                    if it.format == VideoFormat::I420 {
-                        VideoSubFormat::SW
-                    } else {
-                        VideoSubFormat::D3D11
-                    }
-                }
-                VideoDecoderType::VideoToolBox => {
-                    if it.format == VideoFormat::BGRA || it.format == VideoFormat::RGBA {
-                        VideoSubFormat::CvPixelBufferRef
-                    } else {
-                        VideoSubFormat::SW
-                    }
-                }
-            };
+        if let Some(source) = VideoRenderSourceOptions::from_receiver(description, options) {
+            self.0.source = source;
         }
 
         self
@@ -161,6 +305,177 @@ impl<T> VideoRenderOptionsBuilder<T> {
     }
 }
 
+/// How the player keeps the window alive when the stream stalls, see
+/// [AVFrameStreamPlayer::watch_stalls].
+#[derive(Debug, Clone)]
+pub struct StallOptions {
+    /// The stream is stalled when no frame arrives within this time.
+    pub timeout: Duration,
+    /// The last frame is presented again at this rate during the stall.
+    pub frame_rate: u8,
+    /// Darken the video during the stall, 0.0 disables the dimming.
+    pub dim: f32,
+    /// Drawn on top of the other overlays during the stall, such as a
+    /// "connection unstable" banner.
+    pub overlay: Option<OverlayLayer>,
+}
+
+impl Default for StallOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(500),
+            frame_rate: 30,
+            dim: 0.4,
+            overlay: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallEvent {
+    /// No frame has arrived within [StallOptions::timeout].
+    Started,
+    /// A frame has arrived again, with the duration of the stall.
+    Ended(Duration),
+}
+
+#[derive(Default)]
+struct StallState {
+    options: Option<(StallOptions, Option<renderer::OverlayLayer>)>,
+    /// The stream cannot stall before the first frame.
+    last_frame: Option<Instant>,
+    since: Option<Instant>,
+    /// The overlays of the user, the overlay of the stall is drawn on top of
+    /// them.
+    layers: Vec<renderer::OverlayLayer>,
+    events: Broadcaster<StallEvent>,
+}
+
+impl StallState {
+    fn apply(&self, render: &mut VideoRender) {
+        match (&self.options, self.since) {
+            (Some((options, overlay)), Some(_)) => {
+                let mut layers = self.layers.clone();
+                layers.extend(overlay.clone());
+
+                render.set_dim(options.dim);
+                render.set_overlay(&layers);
+            }
+            _ => {
+                render.set_dim(0.0);
+                render.set_overlay(&self.layers);
+            }
+        }
+    }
+}
+
+/// What the window does when the size of the source video is known, see
+/// [AVFrameStreamPlayer::on_source_size].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFit {
+    /// The window is kept, the video is placed in it by the scaling mode,
+    /// with black bars if the aspect ratios differ.
+    #[default]
+    Letterbox,
+    /// The window is resized to the aspect ratio of the video, it is fitted
+    /// within the size of the window before it was fitted, the initial size
+    /// or the size that the user resized it to, so that it does not grow past
+    /// the screen and does not shrink with each change of the video.
+    AspectRatio,
+}
+
+impl WindowFit {
+    /// Returns the largest size within the bounds with the aspect ratio of
+    /// the video.
+    fn fit(self, video: Size, bounds: Size) -> Option<Size> {
+        if self == Self::Letterbox
+            || video.width == 0
+            || video.height == 0
+            || bounds.width == 0
+            || bounds.height == 0
+        {
+            return None;
+        }
+
+        let (video_width, video_height) = (video.width as u64, video.height as u64);
+        let (bounds_width, bounds_height) = (bounds.width as u64, bounds.height as u64);
+
+        // The bounds are wider than the video if the width is kept, so the height is
+        // kept, and the other way round.
+        Some(
+            if bounds_width * video_height > bounds_height * video_width {
+                Size {
+                    width: (bounds_height * video_width / video_height).max(1) as u32,
+                    height: bounds.height,
+                }
+            } else {
+                Size {
+                    width: bounds.width,
+                    height: (bounds_width * video_height / video_width).max(1) as u32,
+                }
+            },
+        )
+    }
+}
+
+/// The size of the source video, see [AVFrameStreamPlayer::on_source_size].
+#[derive(Debug, Clone, Copy)]
+pub struct SourceSize {
+    /// The size of the decoded video.
+    pub video: Size,
+    /// The size to resize the window to, such as with
+    /// `winit::window::Window::request_inner_size`, `None` if the window is
+    /// kept or already fits the video.
+    pub window: Option<Size>,
+}
+
+type SourceSizeCallback = Arc<dyn Fn(SourceSize) + Send + Sync>;
+
+#[derive(Default)]
+struct SourceSizeState {
+    video: Option<Size>,
+    /// The size of the surface, `None` if there is no video.
+    window: Option<Size>,
+    /// The size that the window is fitted within, it is not changed by the
+    /// resizes to the suggested size.
+    bounds: Option<Size>,
+    /// The last suggested size of the window.
+    suggested: Option<Size>,
+    callback: Option<(WindowFit, SourceSizeCallback)>,
+}
+
+impl SourceSizeState {
+    fn resize(&mut self, size: Size) {
+        let is_suggested = self
+            .suggested
+            .is_some_and(|it| it.width == size.width && it.height == size.height);
+
+        if !is_suggested {
+            self.bounds = Some(size);
+        }
+
+        self.window = Some(size);
+    }
+
+    /// The callback and what is passed to it, the callback is called without
+    /// the lock held.
+    fn report(&mut self) -> Option<(SourceSizeCallback, SourceSize)> {
+        let (fit, callback) = self.callback.as_ref()?;
+        let video = self.video?;
+
+        let window = self.bounds.and_then(|it| fit.fit(video, it)).filter(|it| {
+            self.window
+                .is_none_or(|window| window.width != it.width || window.height != it.height)
+        });
+
+        if window.is_some() {
+            self.suggested = window;
+        }
+
+        Some((callback.clone(), SourceSize { window, video }))
+    }
+}
+
 /// Player for audio and video streaming.
 ///
 /// This player is used to quickly and easily create a player that implements
@@ -169,175 +484,971 @@ impl<T> VideoRenderOptionsBuilder<T> {
 pub struct AVFrameStreamPlayer<'a> {
     video: Option<Mutex<VideoRender<'a>>>,
     audio: Option<AudioRender>,
+    stall: Mutex<StallState>,
+    source: Mutex<SourceSizeState>,
 }
 
 impl<'a> AVFrameStreamPlayer<'a> {
-    pub fn new<T>(
-        options: AVFrameStreamPlayerOptions<T>,
-    ) -> Result<Arc<Self>, AVFrameStreamPlayerError>
+    pub fn new<T>(options: AVFrameStreamPlayerOptions<T>) -> Result<Arc<Self>, HylaranaError>
     where
         T: Into<SurfaceTarget<'a>>,
     {
+        let window = match &options {
+            AVFrameStreamPlayerOptions::All(options, _)
+            | AVFrameStreamPlayerOptions::OnlyVideo(options) => Some(options.surface.size),
+            _ => None,
+        };
+
+        let source = SourceSizeState {
+            bounds: window,
+            window,
+            ..Default::default()
+        };
+
         Ok(Arc::new(Self {
-            audio: match options {
-                AVFrameStreamPlayerOptions::All(_) | AVFrameStreamPlayerOptions::OnlyAudio => {
-                    Some(AudioRender::new()?)
+            source: Mutex::new(source),
+            audio: match &options {
+                AVFrameStreamPlayerOptions::All(_, options)
+                | AVFrameStreamPlayerOptions::OnlyAudio(options) => {
+                    Some(AudioRender::new(options)?)
                 }
                 _ => None,
             },
             video: match options {
-                AVFrameStreamPlayerOptions::All(options)
+                AVFrameStreamPlayerOptions::All(options, _)
                 | AVFrameStreamPlayerOptions::OnlyVideo(options) => {
                     Some(Mutex::new(VideoRender::new(options)?))
                 }
                 _ => None,
             },
+            stall: Mutex::new(StallState::default()),
+        }))
+    }
+
+    /// The audio monitor of a sender, it plays the audio that is sent at the
+    /// volume, so that the presenter can confirm that the right source is
+    /// shared, pass it as the sink of the sender, see
+    /// [crate::AudioOptions::monitor].
+    ///
+    /// Keep the volume low or use headphones when the system audio is
+    /// captured, otherwise the monitor is captured again and echoes.
+    pub fn monitor(options: &AudioRenderOptions, volume: f32) -> Result<Arc<Self>, HylaranaError> {
+        let audio = AudioRender::new(options)?;
+        audio.set_volume(volume);
+
+        Ok(Arc::new(Self {
+            audio: Some(audio),
+            video: None,
+            stall: Mutex::new(StallState::default()),
+            source: Mutex::new(SourceSizeState::default()),
         }))
     }
 
     pub fn resize(&self, size: Size) {
         if let Some(player) = &self.video {
             player.lock().resize(size);
+
+            self.source.lock().resize(size);
         }
     }
-}
 
-impl<'a> MediaStreamSink for Arc<AVFrameStreamPlayer<'a>> {
-    fn audio(&self, frame: &AudioFrame) -> bool {
-        if let Some(player) = &self.audio {
-            if let Err(e) = player.send(frame) {
-                log::error!("AVFrameStreamPlayer sink audio error={:?}", e);
+    /// Calls the function with the size of the source video when the first
+    /// frame is decoded, and again when the size changes, such as when the
+    /// sender changes the resolution or the stream is switched. The function
+    /// is called on the thread of the decoder, and right away if the size is
+    /// already known. Calling this again replaces the function.
+    ///
+    /// Resize the window in the function to the suggested size to fit it to
+    /// the video, the new size of the window is then passed to
+    /// [AVFrameStreamPlayer::resize] as usual.
+    pub fn on_source_size<F>(&self, fit: WindowFit, func: F)
+    where
+        F: Fn(SourceSize) + Send + Sync + 'static,
+    {
+        let report = {
+            let mut state = self.source.lock();
+            state.callback = Some((fit, Arc::new(func)));
+            state.report()
+        };
 
-                return false;
-            }
+        if let Some((callback, size)) = report {
+            callback(size);
         }
+    }
 
-        true
+    /// The size of the source video, `None` before the first frame.
+    pub fn source_size(&self) -> Option<Size> {
+        self.source.lock().video
     }
 
-    fn video(&self, frame: &VideoFrame) -> bool {
+    /// Change how the video is sampled when it is scaled to the window,
+    /// ignored if there is no video.
+    pub fn set_scaling_quality(&self, quality: ScalingQuality) -> Result<(), VideoRenderError> {
         if let Some(player) = &self.video {
-            if let Some(mut player) = player.try_lock() {
-                if let Err(e) = player.send(frame) {
-                    log::error!("AVFrameStreamPlayer sink video error={:?}", e);
-
-                    return false;
-                }
-            }
+            player.lock().set_scaling_quality(quality)?;
         }
 
-        true
+        Ok(())
     }
-}
 
-struct AudioSamples {
-    sample_rate: u32,
-    buffer: Vec<i16>,
-    index: usize,
-    frames: usize,
-}
+    /// Change how the video is placed in the window, such as filling the
+    /// window instead of showing black bars, ignored if there is no video.
+    pub fn set_scaling_mode(&self, mode: ScalingMode) {
+        if let Some(player) = &self.video {
+            player.lock().set_scaling_mode(mode);
+        }
+    }
 
-impl rodio::Source for AudioSamples {
-    fn current_frame_len(&self) -> Option<usize> {
-        Some(self.frames)
+    /// The counters of the audio playback, such as the underruns, `None` if
+    /// there is no audio.
+    pub fn audio_stats(&self) -> Option<AudioRenderStats> {
+        self.audio.as_ref().map(|it| it.stats())
     }
 
-    fn channels(&self) -> u16 {
-        2
+    /// Replace the filters of the audio, such as gain and limiter, an empty
+    /// list removes all filters. Ignored if there is no audio.
+    pub fn set_audio_filters(&self, filters: Vec<Arc<dyn AudioFilter>>) {
+        if let Some(player) = &self.audio {
+            player.set_filters(filters);
+        }
     }
 
-    fn sample_rate(&self) -> u32 {
-        self.sample_rate
+    /// Replace the filters of the video, such as brightness and contrast, an
+    /// empty list removes all filters. Ignored if there is no video.
+    pub fn set_filters(&self, filters: &[Box<dyn VideoFilter>]) -> Result<(), VideoRenderError> {
+        if let Some(player) = &self.video {
+            player.lock().set_filters(filters)?;
+        }
+
+        Ok(())
     }
 
-    fn total_duration(&self) -> Option<std::time::Duration> {
-        None
+    /// The recovery events of the video, see [VideoRender::events], `None` if
+    /// there is no video.
+    pub fn video_events(&self) -> Option<Receiver<RendererEvent>> {
+        self.video.as_ref().map(|it| it.lock().events())
     }
-}
 
-impl Iterator for AudioSamples {
-    type Item = i16;
+    /// Replace the overlays drawn on top of the video, such as connection
+    /// statistics or a reconnecting banner, an empty list removes all
+    /// overlays. Ignored if there is no video.
+    pub fn set_overlay(&self, layers: &[OverlayLayer]) -> Result<(), OverlayError> {
+        if let Some(player) = &self.video {
+            let layers = layers
+                .iter()
+                .map(|it| it.rasterize())
+                .collect::<Result<Vec<_>, _>>()?;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let item = self.buffer.get(self.index).map(|it| *it);
-        self.index += 1;
-        item
-    }
-}
+            let mut player = player.lock();
+            let mut stall = self.stall.lock();
 
-impl From<&AudioFrame> for AudioSamples {
-    fn from(frame: &AudioFrame) -> Self {
-        Self {
-            index: 0,
-            frames: frame.frames as usize,
-            sample_rate: frame.sample_rate,
-            buffer: unsafe { from_raw_parts(frame.data as *const i16, frame.frames as usize * 2) }
-                .to_vec(),
+            stall.layers = layers;
+            stall.apply(&mut player);
         }
+
+        Ok(())
     }
-}
 
-/// Audio player that plays the original audio frames directly.
-pub struct AudioRender {
-    #[allow(dead_code)]
-    stream: OutputStream,
-    #[allow(dead_code)]
-    stream_handle: OutputStreamHandle,
-    sink: Sink,
-}
+    /// Set the playback volume, 1.0 is the original volume.
+    pub fn set_volume(&self, volume: f32) {
+        if let Some(player) = &self.audio {
+            player.set_volume(volume);
+        }
+    }
 
-unsafe impl Send for AudioRender {}
-unsafe impl Sync for AudioRender {}
+    /// Mute or unmute the playback, the volume is kept.
+    pub fn set_muted(&self, muted: bool) {
+        if let Some(player) = &self.audio {
+            player.set_muted(muted);
+        }
+    }
 
-impl AudioRender {
-    /// Create a audio player.
-    pub fn new() -> Result<Self, AudioRenderError> {
-        let (stream, stream_handle) = OutputStream::try_default()?;
-        let sink = Sink::try_new(&stream_handle)?;
+    /// Switch the audio output device while playing, the default output device
+    /// is used if the device is not set.
+    pub fn set_audio_device(&self, device: Option<&Source>) -> Result<(), AudioRenderError> {
+        if let Some(player) = &self.audio {
+            player.set_device(device)?;
+        }
 
-        sink.play();
-        Ok(Self {
-            stream_handle,
-            stream,
-            sink,
-        })
+        Ok(())
     }
 
-    /// Push an audio clip to the queue.
-    pub fn send(&self, frame: &AudioFrame) -> Result<(), AudioRenderError> {
-        self.sink.append(AudioSamples::from(frame));
-        Ok(())
+    /// Reports the size of the source video if it is new.
+    fn update_source_size(&self, frame: &VideoFrame) {
+        let report = {
+            let mut state = self.source.lock();
+            if state
+                .video
+                .is_some_and(|it| it.width == frame.width && it.height == frame.height)
+            {
+                return;
+            }
+
+            tracing::info!(
+                "AVFrameStreamPlayer source size changed, width={}, height={}",
+                frame.width,
+                frame.height
+            );
+
+            state.video = Some(Size {
+                width: frame.width,
+                height: frame.height,
+            });
+
+            state.report()
+        };
+
+        if let Some((callback, size)) = report {
+            callback(size);
+        }
     }
-}
 
-impl Drop for AudioRender {
-    fn drop(&mut self) {
-        self.sink.pause();
+    /// A frame has arrived, ends the stall if the stream is stalled.
+    fn frame_arrived(&self, render: &mut VideoRender) {
+        let mut stall = self.stall.lock();
+        stall.last_frame = Some(Instant::now());
+
+        if let Some(since) = stall.since.take() {
+            tracing::info!(
+                "AVFrameStreamPlayer stall ended, duration={:?}",
+                since.elapsed()
+            );
+
+            stall.apply(render);
+            stall.events.emit(StallEvent::Ended(since.elapsed()));
+        }
     }
-}
 
-/// Video player that can render video frames to window.
-pub struct VideoRender<'a>(Renderer<'a>);
+    /// Start the stall if no frame has arrived within the timeout, and present
+    /// the last frame again during the stall. Returns the interval of the next
+    /// check.
+    fn check_stall(&self) -> Duration {
+        let Some(video) = &self.video else {
+            return Duration::from_secs(1);
+        };
 
-impl<'a> VideoRender<'a> {
-    /// Create a video player.
-    pub fn new<T>(
-        VideoRenderOptions { surface, source }: VideoRenderOptions<T>,
-    ) -> Result<Self, VideoRenderError>
-    where
-        T: Into<SurfaceTarget<'a>>,
-    {
-        log::info!("create video render, size={:?}", surface.size);
+        let Some((timeout, interval)) = self.stall.lock().options.as_ref().map(|(it, _)| {
+            (
+                it.timeout,
+                Duration::from_secs(1) / it.frame_rate.max(1) as u32,
+            )
+        }) else {
+            return Duration::from_secs(1);
+        };
 
-        #[cfg(target_os = "windows")]
-        let direct3d = get_direct3d();
+        // The sink drops the frames while the video is locked, so the video is only
+        // locked during the stall.
+        let is_stalled = |stall: &StallState| {
+            stall.since.is_some() || stall.last_frame.is_some_and(|it| it.elapsed() >= timeout)
+        };
 
-        let options = RendererOptions {
-            #[cfg(target_os = "windows")]
+        if !is_stalled(&self.stall.lock()) {
+            return interval;
+        }
+
+        let mut render = video.lock();
+        let mut stall = self.stall.lock();
+
+        // A frame may have arrived before the video is locked.
+        if !is_stalled(&stall) {
+            return interval;
+        }
+
+        if stall.since.is_none() {
+            tracing::info!("AVFrameStreamPlayer stall started");
+
+            stall.since = Some(Instant::now());
+            stall.apply(&mut render);
+            stall.events.emit(StallEvent::Started);
+        }
+
+        if let Err(e) = render.redraw() {
+            tracing::error!("AVFrameStreamPlayer redraw video error={:?}", e);
+        }
+
+        interval
+    }
+}
+
+impl AVFrameStreamPlayer<'static> {
+    /// Keep presenting the last frame while the stream stalls, with the
+    /// dimming and the overlay of the options, and subscribe to the start and
+    /// the end of the stalls. Calling this again replaces the options.
+    ///
+    /// The frames are presented from a thread, so the player must not borrow
+    /// the window.
+    pub fn watch_stalls(
+        self: &Arc<Self>,
+        options: StallOptions,
+    ) -> Result<Receiver<StallEvent>, HylaranaError> {
+        let Some(video) = &self.video else {
+            return Err(HylaranaError::NotFoundVideo);
+        };
+
+        let overlay = options
+            .overlay
+            .as_ref()
+            .map(|it| it.rasterize())
+            .transpose()?;
+
+        let mut render = video.lock();
+        let mut stall = self.stall.lock();
+
+        let rx = stall.events.subscribe();
+
+        if stall.options.replace((options, overlay)).is_none() {
+            let player = Arc::downgrade(self);
+            thread::Builder::new()
+                .name("HylaranaStallWatcherThread".to_string())
+                .spawn(move || {
+                    loop {
+                        let Some(player) = player.upgrade() else {
+                            break;
+                        };
+
+                        let interval = player.check_stall();
+
+                        drop(player);
+                        thread::sleep(interval);
+                    }
+                })
+                .map_err(|_| VideoRenderError::CreateThreadError)?;
+        } else {
+            stall.apply(&mut render);
+        }
+
+        Ok(rx)
+    }
+}
+
+impl<'a> MediaStreamSink for Arc<AVFrameStreamPlayer<'a>> {
+    fn stream_changed(
+        &self,
+        description: &MediaStreamDescription,
+        options: &HylaranaReceiverOptions,
+    ) {
+        let (Some(player), Some(source)) = (
+            &self.video,
+            VideoRenderSourceOptions::from_receiver(description, options),
+        ) else {
+            return;
+        };
+
+        tracing::info!("AVFrameStreamPlayer stream changed, source={:?}", source);
+
+        if let Err(e) = player.lock().set_source(source) {
+            tracing::error!("AVFrameStreamPlayer set source error={:?}", e);
+        }
+    }
+
+    fn audio(&self, frame: &AudioFrame) -> bool {
+        if let Some(player) = &self.audio {
+            if let Err(e) = player.send(frame) {
+                tracing::error!("AVFrameStreamPlayer sink audio error={:?}", e);
+
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn video(&self, frame: &VideoFrame) -> bool {
+        self.update_source_size(frame);
+
+        if let Some(player) = &self.video {
+            if let Some(mut player) = player.try_lock() {
+                self.frame_arrived(&mut player);
+
+                if let Err(e) = player.send(frame) {
+                    tracing::error!("AVFrameStreamPlayer sink video error={:?}", e);
+
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Clone)]
+struct AudioSamples {
+    sample_rate: u32,
+    channels: u8,
+    buffer: Vec<i16>,
+    index: usize,
+}
+
+impl Iterator for AudioSamples {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.buffer.get(self.index).map(|it| *it);
+        self.index += 1;
+        item
+    }
+}
+
+impl AudioSamples {
+    /// Silence of the format, the gaps of the queue are filled with pieces of
+    /// this length.
+    fn silence(sample_rate: u32, channels: u8) -> Self {
+        Self {
+            buffer: vec![0; (sample_rate as usize / 100).max(1) * channels.max(1) as usize],
+            index: 0,
+            sample_rate,
+            channels,
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.buffer.len().saturating_sub(self.index)
+    }
+
+    fn duration(&self) -> Duration {
+        Duration::from_secs_f64(
+            self.buffer.len() as f64 / self.channels.max(1) as f64 / self.sample_rate.max(1) as f64,
+        )
+    }
+
+    fn filter(&mut self, filters: &[&dyn AudioFilter]) {
+        filter_samples(filters, &mut self.buffer, self.channels, self.sample_rate);
+    }
+}
+
+impl From<&AudioFrame> for AudioSamples {
+    fn from(frame: &AudioFrame) -> Self {
+        Self {
+            index: 0,
+            sample_rate: frame.sample_rate,
+            channels: frame.channels,
+            buffer: unsafe {
+                from_raw_parts(
+                    frame.data as *const i16,
+                    frame.frames as usize * frame.channels as usize,
+                )
+            }
+            .to_vec(),
+        }
+    }
+}
+
+/// How the audio render fills the gap when the queue of the audio runs out,
+/// such as when the network stalls.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AudioUnderrunPolicy {
+    /// Play silence until the audio arrives again.
+    #[default]
+    Silence,
+    /// Repeat the last audio and fade it out, which hides short gaps better
+    /// than silence.
+    Stretch,
+    /// Play silence, and when the audio arrives again, drop the oldest audio
+    /// that exceeds the buffer, so that the gap does not add to the latency.
+    SkipAhead,
+}
+
+/// The counters of the audio render.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AudioRenderStats {
+    /// The number of the times the queue ran out while playing.
+    pub underruns: u64,
+    /// The number of the frames dropped by [AudioUnderrunPolicy::SkipAhead].
+    pub skipped_frames: u64,
+    /// The audio currently waiting to be played.
+    pub buffered: Duration,
+}
+
+#[derive(Default)]
+struct AudioQueueState {
+    frames: VecDeque<AudioSamples>,
+    buffered: Duration,
+    /// Nothing is played until the queue holds the target, which is the case
+    /// at the start and after an underrun.
+    filling: bool,
+    /// The queue ran out while playing, so the next time the playback starts
+    /// it recovers from an underrun.
+    underrun: bool,
+    underruns: u64,
+    skipped_frames: u64,
+}
+
+/// The audio frames waiting to be played, shared by the audio render and the
+/// source of the output device, so the queue survives a switch of the device.
+struct AudioQueue {
+    state: Mutex<AudioQueueState>,
+    target: Duration,
+    policy: AudioUnderrunPolicy,
+}
+
+impl AudioQueue {
+    fn new(target: Duration, policy: AudioUnderrunPolicy) -> Self {
+        Self {
+            state: Mutex::new(AudioQueueState {
+                filling: true,
+                ..Default::default()
+            }),
+            target,
+            policy,
+        }
+    }
+
+    fn push(&self, samples: AudioSamples) {
+        let mut state = self.state.lock();
+        state.buffered += samples.duration();
+        state.frames.push_back(samples);
+    }
+
+    /// Takes the next frame to play, `None` if the queue is empty or is
+    /// filling up to the target.
+    fn pop(&self) -> Option<AudioSamples> {
+        let mut state = self.state.lock();
+
+        if state.filling {
+            if state.frames.is_empty() || state.buffered < self.target {
+                return None;
+            }
+
+            state.filling = false;
+
+            if state.underrun && self.policy == AudioUnderrunPolicy::SkipAhead {
+                while state.frames.len() > 1 {
+                    let duration = state.frames[0].duration();
+                    if state.buffered.saturating_sub(duration) < self.target {
+                        break;
+                    }
+
+                    state.frames.pop_front();
+                    state.buffered = state.buffered.saturating_sub(duration);
+                    state.skipped_frames += 1;
+                }
+            }
+
+            state.underrun = false;
+        }
+
+        match state.frames.pop_front() {
+            Some(samples) => {
+                state.buffered = state.buffered.saturating_sub(samples.duration());
+
+                Some(samples)
+            }
+            None => {
+                state.filling = true;
+                state.underrun = true;
+                state.underruns += 1;
+
+                None
+            }
+        }
+    }
+
+    fn stats(&self) -> AudioRenderStats {
+        let state = self.state.lock();
+
+        AudioRenderStats {
+            underruns: state.underruns,
+            skipped_frames: state.skipped_frames,
+            buffered: state.buffered,
+        }
+    }
+}
+
+/// The source of the output device, it plays the queue without an end, and
+/// fills the gaps of the queue according to the underrun policy.
+struct AudioQueueSource {
+    queue: Arc<AudioQueue>,
+    current: AudioSamples,
+    /// The last frame of the audio, repeated by [AudioUnderrunPolicy::Stretch].
+    last: Option<AudioSamples>,
+    repeats: u32,
+    gain: f32,
+}
+
+impl AudioQueueSource {
+    /// The last frame is repeated this many times, each time at half of the
+    /// previous volume, before the gap is filled with silence.
+    const MAX_REPEATS: u32 = 4;
+
+    fn new(queue: Arc<AudioQueue>) -> Self {
+        Self {
+            current: AudioSamples::silence(48000, 2),
+            last: None,
+            repeats: 0,
+            gain: 1.0,
+            queue,
+        }
+    }
+
+    fn advance(&mut self) {
+        if let Some(samples) = self.queue.pop() {
+            self.last = Some(samples.clone());
+            self.current = samples;
+            self.repeats = 0;
+            self.gain = 1.0;
+
+            return;
+        }
+
+        let (sample_rate, channels) = (self.current.sample_rate, self.current.channels);
+        self.current = match &self.last {
+            Some(last)
+                if self.queue.policy == AudioUnderrunPolicy::Stretch
+                    && self.repeats < Self::MAX_REPEATS =>
+            {
+                self.repeats += 1;
+                self.gain = 0.5f32.powi(self.repeats as i32);
+
+                AudioSamples {
+                    index: 0,
+                    ..last.clone()
+                }
+            }
+            _ => {
+                self.gain = 1.0;
+
+                AudioSamples::silence(sample_rate, channels)
+            }
+        };
+    }
+}
+
+impl rodio::Source for AudioQueueSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.current.remaining())
+    }
+
+    fn channels(&self) -> u16 {
+        self.current.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.current.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Iterator for AudioQueueSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.current.next().unwrap_or_default();
+        let sample = if self.gain == 1.0 {
+            sample
+        } else {
+            (sample as f32 * self.gain) as i16
+        };
+
+        // The next frame is taken as soon as the current one is finished, so
+        // that the length of the current frame is never zero, which would end
+        // the source.
+        if self.current.remaining() == 0 {
+            self.advance();
+        }
+
+        Some(sample)
+    }
+}
+
+struct AudioOutput {
+    #[allow(dead_code)]
+    stream: OutputStream,
+    #[allow(dead_code)]
+    stream_handle: OutputStreamHandle,
+    sink: Sink,
+    /// Name of the opened device.
+    name: String,
+}
+
+impl AudioOutput {
+    /// Find the output device by name, or the default output device if the
+    /// name is not set.
+    fn find_device(name: Option<&str>) -> Result<Option<Device>, AudioRenderError> {
+        let host = rodio::cpal::default_host();
+
+        Ok(if let Some(name) = name {
+            host.output_devices()?
+                .find(|it| it.name().map(|it| it == name).unwrap_or(false))
+        } else {
+            host.default_output_device()
+        })
+    }
+
+    fn new(device: &Device, queue: &Arc<AudioQueue>) -> Result<Self, AudioRenderError> {
+        let (stream, stream_handle) = OutputStream::try_from_device(device)?;
+        let sink = Sink::try_new(&stream_handle)?;
+
+        sink.append(AudioQueueSource::new(queue.clone()));
+        sink.play();
+
+        Ok(Self {
+            name: device.name()?,
+            stream_handle,
+            stream,
+            sink,
+        })
+    }
+}
+
+impl Drop for AudioOutput {
+    fn drop(&mut self) {
+        self.sink.pause();
+    }
+}
+
+struct AudioRenderState {
+    output: Mutex<AudioOutput>,
+    /// Applied with the [Volume] filter after the other filters.
+    volume: Mutex<(f32, bool)>,
+    /// The device selected by the user, the default device is followed if it
+    /// is not set.
+    device: Mutex<Option<Source>>,
+    filters: Mutex<Vec<Arc<dyn AudioFilter>>>,
+    queue: Arc<AudioQueue>,
+}
+
+unsafe impl Send for AudioRenderState {}
+unsafe impl Sync for AudioRenderState {}
+
+impl AudioRenderState {
+    fn volume(&self) -> f32 {
+        let (volume, muted) = *self.volume.lock();
+        if muted { 0.0 } else { volume }
+    }
+
+    /// Open the selected device, or the default device if the selected device
+    /// is not set or has been removed, nothing is done if the device is
+    /// already opened.
+    fn reopen(&self) -> Result<(), AudioRenderError> {
+        let device = self.device.lock().as_ref().map(|it| it.id.clone());
+        let device = match AudioOutput::find_device(device.as_deref())? {
+            Some(it) => it,
+            None => AudioOutput::find_device(None)?
+                .ok_or_else(|| AudioRenderError::NotFoundOutputDevice)?,
+        };
+
+        let name = device.name()?;
+        if self.output.lock().name == name {
+            return Ok(());
+        }
+
+        tracing::info!("audio render switch output device, name={}", name);
+
+        *self.output.lock() = AudioOutput::new(&device, &self.queue)?;
+        Ok(())
+    }
+}
+
+/// Audio player that plays the original audio frames directly.
+///
+/// The player follows the default output device, when the selected device is
+/// removed, the player falls back to the default output device, and switches
+/// back once the selected device is available again.
+pub struct AudioRender(Arc<AudioRenderState>);
+
+impl AudioRender {
+    /// Interval at which the device monitor checks the output devices.
+    const MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Get all the output devices that can be used for playback, the id of
+    /// the device is the name of the device.
+    pub fn get_devices() -> Result<Vec<Source>, AudioRenderError> {
+        let host = rodio::cpal::default_host();
+        let default_name = host.default_output_device().and_then(|it| it.name().ok());
+
+        let mut devices = Vec::with_capacity(10);
+        for (index, device) in host.output_devices()?.enumerate() {
+            let name = device.name()?;
+
+            devices.push(Source {
+                is_default: Some(&name) == default_name.as_ref(),
+                kind: SourceType::Audio,
+                id: name.clone(),
+                index,
+                name,
+            });
+        }
+
+        Ok(devices)
+    }
+
+    /// Create a audio player.
+    pub fn new(options: &AudioRenderOptions) -> Result<Self, AudioRenderError> {
+        let device = AudioOutput::find_device(options.device.as_ref().map(|it| it.id.as_str()))?
+            .ok_or_else(|| AudioRenderError::NotFoundOutputDevice)?;
+
+        let queue = Arc::new(AudioQueue::new(options.buffer, options.underrun));
+        let state = Arc::new(AudioRenderState {
+            output: Mutex::new(AudioOutput::new(&device, &queue)?),
+            device: Mutex::new(options.device.clone()),
+            filters: Mutex::new(options.filters.clone()),
+            volume: Mutex::new((1.0, false)),
+            queue,
+        });
+
+        let state_ = Arc::downgrade(&state);
+        thread::Builder::new()
+            .name("HylaranaAudioRenderMonitorThread".to_string())
+            .spawn(move || {
+                loop {
+                    thread::sleep(Self::MONITOR_INTERVAL);
+
+                    let Some(state) = state_.upgrade() else {
+                        break;
+                    };
+
+                    if let Err(e) = state.reopen() {
+                        tracing::error!("audio render reopen output device error={:?}", e);
+                    }
+                }
+            })
+            .map_err(|_| AudioRenderError::CreateThreadError)?;
+
+        Ok(Self(state))
+    }
+
+    /// Push an audio clip to the queue.
+    pub fn send(&self, frame: &AudioFrame) -> Result<(), AudioRenderError> {
+        let _span = tracing::trace_span!("render", stream = "audio").entered();
+
+        let volume = Volume(self.0.volume());
+        let filters = self.0.filters.lock();
+
+        let mut chain = filters.iter().map(|it| it.as_ref()).collect::<Vec<_>>();
+        if volume.0 != 1.0 {
+            chain.push(&volume);
+        }
+
+        let mut samples = AudioSamples::from(frame);
+        samples.filter(&chain);
+
+        self.0.queue.push(samples);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> AudioRenderStats {
+        self.0.queue.stats()
+    }
+
+    /// Replace the filters of the audio, an empty list removes all filters,
+    /// the queued audio clips are not filtered again.
+    pub fn set_filters(&self, filters: Vec<Arc<dyn AudioFilter>>) {
+        tracing::info!("audio render set filters={:?}", filters);
+
+        *self.0.filters.lock() = filters;
+    }
+
+    /// Switch the output device, the queued audio continues on the new
+    /// device.
+    pub fn set_device(&self, device: Option<&Source>) -> Result<(), AudioRenderError> {
+        let output = AudioOutput::new(
+            &AudioOutput::find_device(device.map(|it| it.id.as_str()))?
+                .ok_or_else(|| AudioRenderError::NotFoundOutputDevice)?,
+            &self.0.queue,
+        )?;
+
+        *self.0.device.lock() = device.cloned();
+        *self.0.output.lock() = output;
+        Ok(())
+    }
+
+    /// Set the playback volume, 1.0 is the original volume. The volume is a
+    /// filter, so it takes effect with the next pushed audio clip.
+    pub fn set_volume(&self, volume: f32) {
+        self.0.volume.lock().0 = volume.max(0.0);
+    }
+
+    /// Mute or unmute the playback, the volume is kept.
+    pub fn set_muted(&self, muted: bool) {
+        self.0.volume.lock().1 = muted;
+    }
+}
+
+/// Video player that can render video frames to window.
+/// Holds the frames until they are due, the first frame sets the clock, and
+/// the clock is set again when a frame is late or too far ahead.
+struct FramePacer {
+    max_delay: Duration,
+    clock: Option<(Instant, u64)>,
+}
+
+impl FramePacer {
+    fn new(max_delay: Duration) -> Self {
+        Self {
+            clock: None,
+            max_delay,
+        }
+    }
+
+    fn wait(&mut self, timestamp: u64) {
+        // The frames without a timestamp are not paced.
+        if timestamp == 0 {
+            return;
+        }
+
+        let (start, first) = *self.clock.get_or_insert((Instant::now(), timestamp));
+        let due = start + Duration::from_micros(timestamp.saturating_sub(first));
+
+        match due.checked_duration_since(Instant::now()) {
+            Some(delay) if delay <= self.max_delay => thread::sleep(delay),
+            // The timestamps jumped, such as the sender was restarted, or the frame is
+            // late, the following frames are paced from this one.
+            _ => self.clock = Some((Instant::now(), timestamp)),
+        }
+    }
+}
+
+pub struct VideoRender<'a>(Renderer<'a>, Option<FramePacer>);
+
+impl<'a> VideoRender<'a> {
+    /// Create a video player.
+    pub fn new<T>(options: VideoRenderOptions<T>) -> Result<Self, VideoRenderError>
+    where
+        T: Into<SurfaceTarget<'a>>,
+    {
+        Self::with_device(
+            options,
+            #[cfg(target_os = "windows")]
+            get_direct3d(),
+        )
+    }
+
+    /// Create a video player on the device of a pipeline, the device of the
+    /// process may have been replaced after the pipeline was created.
+    pub(crate) fn with_device<T>(
+        VideoRenderOptions {
+            surface,
+            source,
+            present_mode,
+            max_frame_latency,
+            backend,
+            scaling_quality,
+            scaling_mode,
+            filters,
+            pacing,
+        }: VideoRenderOptions<T>,
+        #[cfg(target_os = "windows")] direct3d: Direct3DDevice,
+    ) -> Result<Self, VideoRenderError>
+    where
+        T: Into<SurfaceTarget<'a>>,
+    {
+        tracing::info!("create video render, size={:?}", surface.size);
+
+        let options = RendererOptions {
+            #[cfg(target_os = "windows")]
             direct3d,
             surface: RendererSurfaceOptions {
                 window: surface.window,
                 size: surface.size,
+                present: RendererPresentOptions {
+                    mode: present_mode,
+                    max_frame_latency,
+                },
+                backend,
             },
             source: RendererSourceOptions {
                 size: source.size,
@@ -346,169 +1457,405 @@ impl<'a> VideoRender<'a> {
             },
         };
 
-        Ok(Self(Renderer::new(options)?))
+        let mut renderer = Renderer::new(options)?;
+        renderer.set_scaling_quality(scaling_quality)?;
+        renderer.set_scaling_mode(scaling_mode);
+
+        if !filters.is_empty() {
+            renderer.set_filters(&filters)?;
+        }
+
+        Ok(Self(renderer, pacing.map(FramePacer::new)))
+    }
+
+    /// Create a video player that renders into a texture of the caller instead
+    /// of a window, such as the texture of a game engine, the texture must
+    /// belong to the device.
+    pub fn from_texture(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        texture: wgpu::Texture,
+        source: VideoRenderSourceOptions,
+    ) -> Result<Self, VideoRenderError> {
+        tracing::info!("create texture video render, size={:?}", source.size);
+
+        Ok(Self(
+            Renderer::from_texture(RendererTextureOptions {
+                #[cfg(target_os = "windows")]
+                direct3d: get_direct3d(),
+                source: RendererSourceOptions {
+                    size: source.size,
+                    format: source.format,
+                    sub_format: source.sub_format,
+                },
+                device,
+                queue,
+                texture,
+            })?,
+            None,
+        ))
+    }
+
+    /// Render the frames of another source, see [Renderer::set_source].
+    pub fn set_source(&mut self, source: VideoRenderSourceOptions) -> Result<(), VideoRenderError> {
+        Ok(self.0.set_source(RendererSourceOptions {
+            size: source.size,
+            format: source.format,
+            sub_format: source.sub_format,
+        })?)
+    }
+
+    /// Replace the texture of a player created with [VideoRender::from_texture].
+    pub fn set_texture(&mut self, texture: wgpu::Texture) -> Result<(), VideoRenderError> {
+        Ok(self.0.set_texture(texture)?)
     }
 
     pub fn resize(&mut self, size: Size) {
         self.0.resize(size);
     }
 
+    /// See [Renderer::set_scaling_quality].
+    pub fn set_scaling_quality(&mut self, quality: ScalingQuality) -> Result<(), VideoRenderError> {
+        Ok(self.0.set_scaling_quality(quality)?)
+    }
+
+    /// See [Renderer::set_scaling_mode].
+    pub fn set_scaling_mode(&mut self, mode: ScalingMode) {
+        self.0.set_scaling_mode(mode);
+    }
+
+    /// See [Renderer::set_filters].
+    pub fn set_filters(
+        &mut self,
+        filters: &[Box<dyn VideoFilter>],
+    ) -> Result<(), VideoRenderError> {
+        Ok(self.0.set_filters(filters)?)
+    }
+
+    /// The recovery events of the player, the player keeps rendering after
+    /// the gpu driver is reset or the system resumes from sleep, and only
+    /// stops after [RendererEvent::RecoveryFailed].
+    pub fn events(&mut self) -> Receiver<RendererEvent> {
+        self.0.events()
+    }
+
+    /// Move the player to another window, see [Renderer::recreate_surface].
+    pub fn recreate_surface<T>(&mut self, window: T, size: Size) -> Result<(), VideoRenderError>
+    where
+        T: Into<SurfaceTarget<'a>>,
+    {
+        Ok(self.0.recreate_surface(window, size)?)
+    }
+
+    /// The overlays are drawn in the pixels of the window, on top of the video.
+    pub fn set_overlay(&mut self, layers: &[renderer::OverlayLayer]) {
+        self.0.set_overlay(layers);
+    }
+
+    /// Darken the video below the overlays, 0.0 disables the dimming.
+    pub fn set_dim(&mut self, dim: f32) {
+        self.0.set_dim(dim);
+    }
+
+    /// Present the last frame again, see [Renderer::redraw].
+    pub fn redraw(&mut self) -> Result<(), VideoRenderError> {
+        Ok(self.0.redraw()?)
+    }
+
     /// Push video frames to the queue and the player will render them as
-    /// quickly as possible, basically in real time.
+    /// quickly as possible, basically in real time, or at their timestamps
+    /// with [VideoRenderOptionsBuilder::pacing].
     pub fn send(&mut self, frame: &VideoFrame) -> Result<(), VideoRenderError> {
-        match frame.sub_format {
-            #[cfg(target_os = "windows")]
-            VideoSubFormat::D3D11 => {
-                let texture = Texture2DResource::Texture(Texture2DRaw::ID3D11Texture2D(
-                    d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
-                        .ok_or_else(|| VideoRenderError::InvalidD3D11Texture)?
-                        .clone(),
-                    frame.data[1] as u32,
+        if let Some(pacer) = self.1.as_mut() {
+            pacer.wait(frame.timestamp);
+        }
+
+        let _span = tracing::trace_span!("render", stream = "video").entered();
+
+        submit_frame(frame, |texture| self.0.submit(texture))
+    }
+}
+
+/// Describe the frame as a texture of the renderer and submit it, the frame
+/// is only borrowed, so the texture is passed to the callback.
+fn submit_frame<F>(frame: &VideoFrame, mut submit: F) -> Result<(), VideoRenderError>
+where
+    F: FnMut(Texture) -> Result<(), renderer::GraphicsError>,
+{
+    match frame.sub_format {
+        #[cfg(target_os = "windows")]
+        VideoSubFormat::D3D11 => {
+            let texture = Texture2DResource::Texture(Texture2DRaw::ID3D11Texture2D(
+                d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
+                    .ok_or_else(|| VideoRenderError::InvalidD3D11Texture)?
+                    .clone(),
+                frame.data[1] as u32,
+            ));
+
+            let texture = match frame.format {
+                VideoFormat::BGRA => Texture::Bgra(texture),
+                VideoFormat::RGBA => Texture::Rgba(texture),
+                VideoFormat::NV12 => Texture::Nv12(texture),
+                VideoFormat::I420 => unimplemented!("no hardware texture for I420"),
+            };
+
+            submit(texture)?;
+        }
+        #[cfg(target_os = "macos")]
+        VideoSubFormat::CvPixelBufferRef => match frame.format {
+            VideoFormat::BGRA => {
+                submit(Texture::Bgra(Texture2DResource::Texture(
+                    Texture2DRaw::CVPixelBufferRef(frame.data[0] as CVPixelBufferRef),
+                )))?;
+            }
+            VideoFormat::RGBA => {
+                submit(Texture::Rgba(Texture2DResource::Texture(
+                    Texture2DRaw::CVPixelBufferRef(frame.data[0] as CVPixelBufferRef),
+                )))?;
+            }
+            _ => {
+                let pixel_buffer = PixelMomeryBuffer::from((
+                    frame.data[0] as CVPixelBufferRef,
+                    frame.format,
+                    Size {
+                        width: frame.width,
+                        height: frame.height,
+                    },
                 ));
 
-                let texture = match frame.format {
-                    VideoFormat::BGRA => Texture::Bgra(texture),
-                    VideoFormat::RGBA => Texture::Rgba(texture),
-                    VideoFormat::NV12 => Texture::Nv12(texture),
-                    VideoFormat::I420 => unimplemented!("no hardware texture for I420"),
+                let buffer = Texture2DBuffer {
+                    buffers: &pixel_buffer.data,
+                    linesize: &frame.linesize,
                 };
 
-                self.0.submit(texture)?;
+                submit(match frame.format {
+                    VideoFormat::NV12 => Texture::Nv12(Texture2DResource::Buffer(buffer)),
+                    VideoFormat::I420 => Texture::I420(buffer),
+                    _ => unreachable!(),
+                })?;
             }
-            #[cfg(target_os = "macos")]
-            VideoSubFormat::CvPixelBufferRef => match frame.format {
-                VideoFormat::BGRA => {
-                    self.0.submit(Texture::Bgra(Texture2DResource::Texture(
-                        Texture2DRaw::CVPixelBufferRef(frame.data[0] as CVPixelBufferRef),
-                    )))?;
-                }
-                VideoFormat::RGBA => {
-                    self.0.submit(Texture::Rgba(Texture2DResource::Texture(
-                        Texture2DRaw::CVPixelBufferRef(frame.data[0] as CVPixelBufferRef),
-                    )))?;
-                }
-                _ => {
-                    let pixel_buffer = PixelMomeryBuffer::from((
-                        frame.data[0] as CVPixelBufferRef,
-                        frame.format,
-                        Size {
-                            width: frame.width,
-                            height: frame.height,
-                        },
-                    ));
-
-                    let buffer = Texture2DBuffer {
-                        buffers: &pixel_buffer.data,
-                        linesize: &frame.linesize,
-                    };
+        },
+        #[cfg(target_os = "linux")]
+        VideoSubFormat::DmaBuf => {
+            let texture = Texture2DResource::Texture(Texture2DRaw::DmaBuf(unsafe {
+                *(frame.data[0] as *const DmaBufDescriptor)
+            }));
 
-                    self.0.submit(match frame.format {
-                        VideoFormat::NV12 => Texture::Nv12(Texture2DResource::Buffer(buffer)),
-                        VideoFormat::I420 => Texture::I420(buffer),
-                        _ => unreachable!(),
-                    })?;
-                }
+            let texture = match frame.format {
+                VideoFormat::BGRA => Texture::Bgra(texture),
+                VideoFormat::RGBA => Texture::Rgba(texture),
+                VideoFormat::NV12 => Texture::Nv12(texture),
+                VideoFormat::I420 => unimplemented!("no hardware texture for I420"),
+            };
+
+            submit(texture)?;
+        }
+        VideoSubFormat::SW => {
+            let buffers = match frame.format {
+                // RGBA stands for red green blue alpha. While it is sometimes described as a
+                // color space, it is actually a three-channel RGB color model supplemented
+                // with a fourth alpha channel. Alpha indicates how opaque each pixel is and
+                // allows an image to be combined over others using alpha compositing, with
+                // transparent areas and anti-aliasing of the edges of opaque regions. Each
+                // pixel is a 4D vector.
+                //
+                // The term does not define what RGB color space is being used. It also does
+                // not state whether or not the colors are premultiplied by the alpha value,
+                // and if they are it does not state what color space that premultiplication
+                // was done in. This means more information than just "RGBA" is needed to
+                // determine how to handle an image.
+                //
+                // In some contexts the abbreviation "RGBA" means a specific memory layout
+                // (called RGBA8888 below), with other terms such as "BGRA" used for
+                // alternatives. In other contexts "RGBA" means any layout.
+                VideoFormat::BGRA | VideoFormat::RGBA => [
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[0] as *const _,
+                            frame.linesize[0] as usize * frame.height as usize,
+                        )
+                    },
+                    &[],
+                    &[],
+                ],
+                // YCbCr, Y′CbCr, or Y Pb/Cb Pr/Cr, also written as YCBCR or Y′CBCR, is a
+                // family of color spaces used as a part of the color image pipeline in video
+                // and digital photography systems. Y′ is the luma component and CB and CR are
+                // the blue-difference and red-difference chroma components. Y′ (with prime) is
+                // distinguished from Y, which is luminance, meaning that light intensity is
+                // nonlinearly encoded based on gamma corrected RGB primaries.
+                //
+                // Y′CbCr color spaces are defined by a mathematical coordinate transformation
+                // from an associated RGB primaries and white point. If the underlying RGB
+                // color space is absolute, the Y′CbCr color space is an absolute color space
+                // as well; conversely, if the RGB space is ill-defined, so is Y′CbCr. The
+                // transformation is defined in equations 32, 33 in ITU-T H.273. Nevertheless
+                // that rule does not apply to P3-D65 primaries used by Netflix with
+                // BT.2020-NCL matrix, so that means matrix was not derived from primaries, but
+                // now Netflix allows BT.2020 primaries (since 2021).[1] The same happens with
+                // JPEG: it has BT.601 matrix derived from System M primaries, yet the
+                // primaries of most images are BT.709.
+                VideoFormat::NV12 => [
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[0] as *const _,
+                            frame.linesize[0] as usize * frame.height as usize,
+                        )
+                    },
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[1] as *const _,
+                            frame.linesize[1] as usize * frame.height as usize,
+                        )
+                    },
+                    &[],
+                ],
+                VideoFormat::I420 => [
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[0] as *const _,
+                            frame.linesize[0] as usize * frame.height as usize,
+                        )
+                    },
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[1] as *const _,
+                            frame.linesize[1] as usize * (frame.height as usize / 2),
+                        )
+                    },
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[2] as *const _,
+                            frame.linesize[2] as usize * (frame.height as usize / 2),
+                        )
+                    },
+                ],
+            };
+
+            let texture = Texture2DBuffer {
+                buffers: &buffers,
+                linesize: &frame.linesize,
+            };
+
+            let texture = match frame.format {
+                VideoFormat::BGRA => Texture::Bgra(Texture2DResource::Buffer(texture)),
+                VideoFormat::RGBA => Texture::Rgba(Texture2DResource::Buffer(texture)),
+                VideoFormat::NV12 => Texture::Nv12(Texture2DResource::Buffer(texture)),
+                VideoFormat::I420 => Texture::I420(texture),
+            };
+
+            submit(texture)?;
+        }
+        #[allow(unreachable_patterns)]
+        _ => unimplemented!("not suppports the frame format = {:?}", frame.sub_format),
+    }
+
+    Ok(())
+}
+
+/// Video player that renders the videos of several receivers into one
+/// window, see [MultiRenderer].
+pub struct MultiVideoRender<'a>(Mutex<MultiRenderer<'a>>);
+
+impl<'a> MultiVideoRender<'a> {
+    pub fn new<T>(
+        surface: VideoRenderSurfaceOptions<T>,
+        layout: MultiRendererLayout,
+        sub_format: VideoSubFormat,
+    ) -> Result<Arc<Self>, VideoRenderError>
+    where
+        T: Into<SurfaceTarget<'a>>,
+    {
+        tracing::info!("create multi video render, size={:?}", surface.size);
+
+        Ok(Arc::new(Self(Mutex::new(MultiRenderer::new(
+            MultiRendererOptions {
+                #[cfg(target_os = "windows")]
+                direct3d: get_direct3d(),
+                surface: RendererSurfaceOptions {
+                    window: surface.window,
+                    size: surface.size,
+                    present: RendererPresentOptions::default(),
+                    backend: None,
+                },
+                sub_format,
+                layout,
             },
-            VideoSubFormat::SW => {
-                let buffers = match frame.format {
-                    // RGBA stands for red green blue alpha. While it is sometimes described as a
-                    // color space, it is actually a three-channel RGB color model supplemented
-                    // with a fourth alpha channel. Alpha indicates how opaque each pixel is and
-                    // allows an image to be combined over others using alpha compositing, with
-                    // transparent areas and anti-aliasing of the edges of opaque regions. Each
-                    // pixel is a 4D vector.
-                    //
-                    // The term does not define what RGB color space is being used. It also does
-                    // not state whether or not the colors are premultiplied by the alpha value,
-                    // and if they are it does not state what color space that premultiplication
-                    // was done in. This means more information than just "RGBA" is needed to
-                    // determine how to handle an image.
-                    //
-                    // In some contexts the abbreviation "RGBA" means a specific memory layout
-                    // (called RGBA8888 below), with other terms such as "BGRA" used for
-                    // alternatives. In other contexts "RGBA" means any layout.
-                    VideoFormat::BGRA | VideoFormat::RGBA => [
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[0] as *const _,
-                                frame.linesize[0] as usize * frame.height as usize,
-                            )
-                        },
-                        &[],
-                        &[],
-                    ],
-                    // YCbCr, Y′CbCr, or Y Pb/Cb Pr/Cr, also written as YCBCR or Y′CBCR, is a
-                    // family of color spaces used as a part of the color image pipeline in video
-                    // and digital photography systems. Y′ is the luma component and CB and CR are
-                    // the blue-difference and red-difference chroma components. Y′ (with prime) is
-                    // distinguished from Y, which is luminance, meaning that light intensity is
-                    // nonlinearly encoded based on gamma corrected RGB primaries.
-                    //
-                    // Y′CbCr color spaces are defined by a mathematical coordinate transformation
-                    // from an associated RGB primaries and white point. If the underlying RGB
-                    // color space is absolute, the Y′CbCr color space is an absolute color space
-                    // as well; conversely, if the RGB space is ill-defined, so is Y′CbCr. The
-                    // transformation is defined in equations 32, 33 in ITU-T H.273. Nevertheless
-                    // that rule does not apply to P3-D65 primaries used by Netflix with
-                    // BT.2020-NCL matrix, so that means matrix was not derived from primaries, but
-                    // now Netflix allows BT.2020 primaries (since 2021).[1] The same happens with
-                    // JPEG: it has BT.601 matrix derived from System M primaries, yet the
-                    // primaries of most images are BT.709.
-                    VideoFormat::NV12 => [
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[0] as *const _,
-                                frame.linesize[0] as usize * frame.height as usize,
-                            )
-                        },
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[1] as *const _,
-                                frame.linesize[1] as usize * frame.height as usize,
-                            )
-                        },
-                        &[],
-                    ],
-                    VideoFormat::I420 => [
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[0] as *const _,
-                                frame.linesize[0] as usize * frame.height as usize,
-                            )
-                        },
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[1] as *const _,
-                                frame.linesize[1] as usize * (frame.height as usize / 2),
-                            )
-                        },
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[2] as *const _,
-                                frame.linesize[2] as usize * (frame.height as usize / 2),
-                            )
-                        },
-                    ],
-                };
+        )?))))
+    }
 
-                let texture = Texture2DBuffer {
-                    buffers: &buffers,
-                    linesize: &frame.linesize,
-                };
+    /// Add a view to the window, the view is a sink that can be passed to a
+    /// receiver, and it is removed from the window when the view is dropped.
+    pub fn add_view(
+        self: &Arc<Self>,
+        source: VideoRenderSourceOptions,
+    ) -> Result<MultiVideoRenderView<'a>, VideoRenderError> {
+        let id = self.0.lock().add_view(RendererSourceOptions {
+            size: source.size,
+            format: source.format,
+            sub_format: source.sub_format,
+        })?;
 
-                let texture = match frame.format {
-                    VideoFormat::BGRA => Texture::Bgra(Texture2DResource::Buffer(texture)),
-                    VideoFormat::RGBA => Texture::Rgba(Texture2DResource::Buffer(texture)),
-                    VideoFormat::NV12 => Texture::Nv12(Texture2DResource::Buffer(texture)),
-                    VideoFormat::I420 => Texture::I420(texture),
-                };
+        Ok(MultiVideoRenderView {
+            render: self.clone(),
+            id,
+        })
+    }
+
+    pub fn set_layout(&self, layout: MultiRendererLayout) {
+        self.0.lock().set_layout(layout);
+    }
+
+    pub fn resize(&self, size: Size) {
+        self.0.lock().resize(size);
+    }
+}
+
+/// A view of [MultiVideoRender], only the video of the stream is rendered.
+pub struct MultiVideoRenderView<'a> {
+    render: Arc<MultiVideoRender<'a>>,
+    id: u32,
+}
+
+impl MediaStreamSink for MultiVideoRenderView<'_> {
+    fn video(&self, frame: &VideoFrame) -> bool {
+        // The views share one surface, a view that cannot get the lock skips the
+        // frame instead of waiting for the other views.
+        if let Some(mut render) = self.render.0.try_lock() {
+            if let Err(e) = submit_frame(frame, |texture| render.submit(self.id, texture)) {
+                tracing::error!("MultiVideoRenderView sink video error={:?}", e);
 
-                self.0.submit(texture)?;
+                return false;
             }
-            #[allow(unreachable_patterns)]
-            _ => unimplemented!("not suppports the frame format = {:?}", frame.sub_format),
         }
 
-        Ok(())
+        true
+    }
+
+    fn stream_changed(
+        &self,
+        description: &MediaStreamDescription,
+        options: &HylaranaReceiverOptions,
+    ) {
+        let Some(source) = VideoRenderSourceOptions::from_receiver(description, options) else {
+            return;
+        };
+
+        if let Err(e) = self.render.0.lock().set_view_source(
+            self.id,
+            RendererSourceOptions {
+                size: source.size,
+                format: source.format,
+                sub_format: source.sub_format,
+            },
+        ) {
+            tracing::error!("MultiVideoRenderView set source error={:?}", e);
+        }
+    }
+}
+
+impl Drop for MultiVideoRenderView<'_> {
+    fn drop(&mut self) {
+        self.render.0.lock().remove_view(self.id);
     }
 }
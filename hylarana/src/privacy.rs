@@ -0,0 +1,446 @@
+use std::{
+    io,
+    sync::{Arc, Weak},
+    thread,
+    time::Duration,
+};
+
+use capture::{Capture, ScreenWindows, Source, WindowMatch, WindowRect};
+use common::frame::{VideoFormat, VideoFrame, VideoSubFormat};
+use parking_lot::Mutex;
+
+use super::overlay::{FrameCopy, Nv12Planes, OverlayError, Rect};
+
+#[cfg(target_os = "windows")]
+use common::win32::{Direct3DDevice, d3d_texture_borrowed_raw, windows::Win32::Foundation::RECT};
+
+#[cfg(target_os = "windows")]
+use resample::win32::{VideoMask, VideoMaskStyle};
+
+#[cfg(target_os = "macos")]
+use super::overlay::lock_pixel_buffer;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How the windows are hidden, see [PrivacyOptions::style].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum PrivacyMaskStyle {
+    /// The windows are filled with black.
+    #[default]
+    Blank,
+    /// The windows are averaged over coarse blocks, the layout of the window
+    /// is still visible but the text can not be read.
+    Blur,
+}
+
+/// The windows that are hidden in the outgoing video, such as password
+/// managers or chat applications.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct PrivacyOptions {
+    pub windows: Vec<WindowMatch>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub style: PrivacyMaskStyle,
+}
+
+/// The privacy options of a sender, they are replaced while the sender is
+/// running, see `HylaranaSender::set_privacy`.
+pub(crate) type PrivacySlot = Mutex<Option<PrivacyOptions>>;
+
+/// The positions of the windows are only known on Windows and macOS, the
+/// windows can not be hidden anywhere else.
+pub(crate) fn is_supported() -> bool {
+    cfg!(any(target_os = "windows", target_os = "macos"))
+}
+
+/// The windows found for a screen and the matches, `None` if they could not
+/// be looked up.
+struct FoundWindows {
+    source: String,
+    matches: Vec<WindowMatch>,
+    windows: Option<ScreenWindows>,
+}
+
+struct LookupState {
+    source: Mutex<Source>,
+    options: Arc<PrivacySlot>,
+    found: Mutex<Option<FoundWindows>>,
+}
+
+impl LookupState {
+    fn refresh(&self) {
+        let matches = self
+            .options
+            .lock()
+            .as_ref()
+            .map(|it| it.windows.clone())
+            .unwrap_or_default();
+
+        let source = self.source.lock().clone();
+        let windows = match Capture::find_windows(&source, &matches) {
+            Ok(it) => Some(it),
+            Err(e) => {
+                tracing::warn!("video sender privacy failed to find windows, err={:?}", e);
+
+                None
+            }
+        };
+
+        self.found.lock().replace(FoundWindows {
+            source: source.id,
+            matches,
+            windows,
+        });
+    }
+}
+
+/// Looks up the windows on a thread of its own every
+/// [WindowLookup::INTERVAL], so that the capture thread does not enumerate
+/// the windows for each frame. The windows are looked up right away when the
+/// screen or the matches change.
+struct WindowLookup(Arc<LookupState>);
+
+impl WindowLookup {
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn new(source: Source, options: Arc<PrivacySlot>) -> io::Result<Self> {
+        let state = Arc::new(LookupState {
+            source: Mutex::new(source),
+            found: Mutex::new(None),
+            options,
+        });
+
+        let weak = Arc::downgrade(&state);
+        thread::Builder::new()
+            .name("HylaranaPrivacyThread".to_string())
+            .spawn(move || Self::run(weak))?;
+
+        Ok(Self(state))
+    }
+
+    /// Stops when the privacy mask is dropped.
+    fn run(state: Weak<LookupState>) {
+        while let Some(state) = state.upgrade() {
+            if state.options.lock().is_some() {
+                state.refresh();
+            }
+
+            drop(state);
+            thread::sleep(Self::INTERVAL);
+        }
+    }
+
+    fn set_source(&self, source: &Source) {
+        *self.0.source.lock() = source.clone();
+    }
+
+    /// Returns the windows found for the current screen and the matches,
+    /// `None` if they could not be looked up.
+    fn get(&self, matches: &[WindowMatch]) -> Option<ScreenWindows> {
+        let find = || {
+            let source = self.0.source.lock();
+            let found = self.0.found.lock();
+
+            found
+                .as_ref()
+                .filter(|it| it.source == source.id && it.matches == matches)
+                .map(|it| it.windows.clone())
+        };
+
+        if let Some(windows) = find() {
+            return windows;
+        }
+
+        self.0.refresh();
+        find().flatten()
+    }
+}
+
+/// A plane of an image that can be written, the origin is the origin of the
+/// masked rect.
+struct Plane {
+    data: *mut u8,
+    stride: usize,
+}
+
+impl Plane {
+    fn row(&mut self, y: u32, width: usize) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.data.add(y as usize * self.stride), width) }
+    }
+
+    /// Fills the rows with the value of a pixel, the width is in bytes.
+    fn fill(&mut self, width: usize, height: u32, pixel: &[u8]) {
+        for y in 0..height {
+            for it in self.row(y, width).chunks_exact_mut(pixel.len()) {
+                it.copy_from_slice(pixel);
+            }
+        }
+    }
+
+    /// Replaces the blocks with their average, each channel of the
+    /// interleaved pixels is averaged on its own, the width and the block
+    /// width are in bytes.
+    fn mosaic(&mut self, width: usize, height: u32, block: (usize, u32), channels: usize) {
+        for by in (0..height).step_by(block.1 as usize) {
+            let rows = block.1.min(height - by);
+
+            for bx in (0..width).step_by(block.0) {
+                let columns = block.0.min(width - bx);
+                let count = (rows as usize * columns / channels).max(1) as u32;
+
+                let mut sums = [0u32; 4];
+                for y in by..by + rows {
+                    for (i, it) in self.row(y, width)[bx..bx + columns].iter().enumerate() {
+                        sums[i % channels] += *it as u32;
+                    }
+                }
+
+                for y in by..by + rows {
+                    for (i, it) in self.row(y, width)[bx..bx + columns].iter_mut().enumerate() {
+                        *it = (sums[i % channels] / count) as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Hides the windows in the captured screen before the frames are encoded,
+/// the textures are masked on the gpu. The mask follows the windows when they
+/// are moved, but lags behind a moving window by up to
+/// [WindowLookup::INTERVAL].
+///
+/// A frame in which the windows can not be hidden is not sent at all, the
+/// content of the windows must never reach the receivers.
+pub(crate) struct PrivacyMask {
+    lookup: WindowLookup,
+    options: Arc<PrivacySlot>,
+    copy: FrameCopy,
+    #[cfg(target_os = "windows")]
+    direct3d: Direct3DDevice,
+    /// Created for the first texture.
+    #[cfg(target_os = "windows")]
+    mask: Option<VideoMask>,
+}
+
+impl PrivacyMask {
+    /// The size of the blocks of the blur in pixels, even so that the
+    /// chroma blocks of the NV12 frames line up with the luma blocks.
+    const BLOCK: u32 = 16;
+
+    pub fn new(
+        source: Source,
+        options: Arc<PrivacySlot>,
+        #[cfg(target_os = "windows")] direct3d: Direct3DDevice,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            lookup: WindowLookup::new(source, options.clone())?,
+            copy: FrameCopy::default(),
+            #[cfg(target_os = "windows")]
+            mask: None,
+            #[cfg(target_os = "windows")]
+            direct3d,
+            options,
+        })
+    }
+
+    /// The windows are looked up on the new screen.
+    pub fn set_source(&mut self, source: &Source) {
+        self.lookup.set_source(source);
+    }
+
+    /// Returns the frame with the windows hidden, `None` if the windows can
+    /// not be hidden and the frame must be dropped.
+    pub fn apply<'a>(&'a mut self, frame: &'a VideoFrame) -> Option<&'a VideoFrame> {
+        let Some(options) = self.options.lock().clone() else {
+            return Some(frame);
+        };
+
+        let windows = self.lookup.get(&options.windows)?;
+
+        let rects = windows
+            .rects
+            .iter()
+            .filter_map(|it| Self::scale(it, &windows, frame))
+            .collect::<Vec<_>>();
+
+        if rects.is_empty() {
+            return Some(frame);
+        }
+
+        match self.mask(frame, &rects, options.style) {
+            Ok(Some(it)) => Some(it),
+            Ok(None) => {
+                tracing::warn!(
+                    "video sender privacy is not supported, format={:?}, sub_format={:?}",
+                    frame.format,
+                    frame.sub_format
+                );
+
+                None
+            }
+            Err(e) => {
+                tracing::warn!("video sender privacy failed to mask, err={:?}", e);
+
+                None
+            }
+        }
+    }
+
+    /// Scales the rect of the window from the screen to the frame, the rect
+    /// is rounded outwards to even pixels, so that the edges of the window
+    /// are always covered.
+    fn scale(rect: &WindowRect, windows: &ScreenWindows, frame: &VideoFrame) -> Option<Rect> {
+        let (width, height) = (frame.width & !1, frame.height & !1);
+        if windows.size.width == 0 || windows.size.height == 0 {
+            return None;
+        }
+
+        let scale = |value: u32, from: u32, to: u32, up: bool| {
+            let value = value as u64 * to as u64;
+            let value = if up {
+                value.div_ceil(from as u64)
+            } else {
+                value / from as u64
+            };
+
+            value.min(to as u64) as u32
+        };
+
+        let left = scale(rect.x, windows.size.width, width, false) & !1;
+        let top = scale(rect.y, windows.size.height, height, false) & !1;
+        let right = (scale(rect.x + rect.width, windows.size.width, width, true) + 1) & !1;
+        let bottom = (scale(rect.y + rect.height, windows.size.height, height, true) + 1) & !1;
+
+        let (right, bottom) = (right.min(width), bottom.min(height));
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        Some(Rect {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+
+    fn mask_nv12(planes: &mut Nv12Planes, rect: Rect, style: PrivacyMaskStyle) {
+        let mut y = Plane {
+            data: planes.y,
+            stride: planes.y_stride,
+        };
+
+        let mut uv = Plane {
+            data: planes.uv,
+            stride: planes.uv_stride,
+        };
+
+        // The chroma plane has half of the rows, and a pair of bytes for every two
+        // pixels of a row.
+        let width = rect.width as usize;
+        match style {
+            PrivacyMaskStyle::Blank => {
+                y.fill(width, rect.height, &[16]);
+                uv.fill(width, rect.height / 2, &[128, 128]);
+            }
+            PrivacyMaskStyle::Blur => {
+                y.mosaic(width, rect.height, (Self::BLOCK as usize, Self::BLOCK), 1);
+                uv.mosaic(
+                    width,
+                    rect.height / 2,
+                    (Self::BLOCK as usize, Self::BLOCK / 2),
+                    2,
+                );
+            }
+        }
+    }
+
+    /// Software frames are masked in a copy, hardware frames in place, the
+    /// textures on the gpu and the pixel buffers on the cpu.
+    fn mask<'a>(
+        &'a mut self,
+        frame: &'a VideoFrame,
+        rects: &[Rect],
+        style: PrivacyMaskStyle,
+    ) -> Result<Option<&'a VideoFrame>, OverlayError> {
+        match (frame.sub_format, frame.format) {
+            (VideoSubFormat::SW, VideoFormat::NV12) => {
+                let frame = self.copy.copy(frame);
+                for rect in rects {
+                    Self::mask_nv12(&mut Nv12Planes::from_frame(frame, *rect), *rect, style);
+                }
+
+                Ok(Some(frame))
+            }
+            #[cfg(target_os = "windows")]
+            (VideoSubFormat::D3D11, VideoFormat::NV12) => {
+                let raw = frame.data[0] as *mut _;
+                let Some(texture) = d3d_texture_borrowed_raw(&raw) else {
+                    return Ok(None);
+                };
+
+                if self.mask.is_none() {
+                    self.mask = Some(VideoMask::new(self.direct3d.clone())?);
+                }
+
+                let rects = rects
+                    .iter()
+                    .map(|it| RECT {
+                        left: it.x as i32,
+                        top: it.y as i32,
+                        right: (it.x + it.width) as i32,
+                        bottom: (it.y + it.height) as i32,
+                    })
+                    .collect::<Vec<_>>();
+
+                if let Some(mask) = self.mask.as_mut() {
+                    mask.mask(
+                        texture,
+                        frame.data[1] as u32,
+                        &rects,
+                        match style {
+                            PrivacyMaskStyle::Blank => VideoMaskStyle::Blank,
+                            PrivacyMaskStyle::Blur => VideoMaskStyle::Blur(Self::BLOCK),
+                        },
+                    )?;
+                }
+
+                Ok(Some(frame))
+            }
+            #[cfg(target_os = "macos")]
+            (VideoSubFormat::CvPixelBufferRef, VideoFormat::BGRA) => {
+                lock_pixel_buffer(frame, |base, stride| {
+                    for rect in rects {
+                        let mut plane = Plane {
+                            data: unsafe {
+                                base.add(rect.y as usize * stride + rect.x as usize * 4)
+                            },
+                            stride,
+                        };
+
+                        let width = rect.width as usize * 4;
+                        match style {
+                            PrivacyMaskStyle::Blank => {
+                                plane.fill(width, rect.height, &[0, 0, 0, 255]);
+                            }
+                            PrivacyMaskStyle::Blur => {
+                                plane.mosaic(
+                                    width,
+                                    rect.height,
+                                    (Self::BLOCK as usize * 4, Self::BLOCK),
+                                    4,
+                                );
+                            }
+                        }
+                    }
+                })?;
+
+                Ok(Some(frame))
+            }
+            _ => Ok(None),
+        }
+    }
+}
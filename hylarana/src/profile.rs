@@ -0,0 +1,342 @@
+use super::{
+    AudioMonitor, AudioOptions, HylaranaReceiverOptions, HylaranaSenderMediaOptions,
+    HylaranaSenderOptions, HylaranaSenderTrackOptions, ProtectedContentPolicy, RenditionSelection,
+    SenderInput, SourceFailover, VideoOptions, VideoOutput,
+};
+
+use capture::Source;
+use common::codec::{VideoDecoderType, VideoEncoderType};
+use transport::{FecArq, FecLayout, FecOptions, TransportOptions, TransportStrategy};
+
+/// A known-good end-to-end configuration.
+///
+/// The video, audio and transport parameters interact with each other (a high
+/// bitrate with a short latency window on a lossy network will stutter, a long
+/// GOP on a lossy network will take a long time to recover), so instead of
+/// guessing every parameter, you can start from one of the reference profiles
+/// and only adjust what you need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: u8,
+    pub bit_rate: u64,
+    pub key_frame_interval: u32,
+    pub audio_sample_rate: u64,
+    pub audio_bit_rate: u64,
+    /// Opus in-band forward error correction
+    pub audio_fec: bool,
+    /// Latency in milliseconds
+    pub latency: u32,
+    /// Connection timeout in milliseconds
+    pub timeout: u32,
+    /// Forward Error Correction configuration
+    pub fec: FecOptions,
+    /// Flow control window size
+    pub fc: u32,
+    /// Maximum Transmission Unit size
+    pub mtu: u32,
+}
+
+impl Profile {
+    /// Wired LAN, 1920x1080 at 60 fps, with the smallest latency window.
+    pub const LAN_1080P60_LOW_LATENCY: Self = Self {
+        name: "LAN-1080p60-lowlatency",
+        width: 1920,
+        height: 1080,
+        frame_rate: 60,
+        bit_rate: 10 * 1024 * 1024,
+        key_frame_interval: 60,
+        audio_sample_rate: 48000,
+        audio_bit_rate: 64000,
+        audio_fec: false,
+        latency: 20,
+        timeout: 2000,
+        fec: FecOptions {
+            layout: FecLayout::Staircase,
+            rows: 2,
+            cols: 10,
+            arq: FecArq::OnRequest,
+        },
+        fc: 32,
+        mtu: 1500,
+    };
+
+    /// Wireless networks, 1280x720 at 30 fps, trading latency for robustness
+    /// against packet loss.
+    pub const WIFI_720P30_RESILIENT: Self = Self {
+        name: "WiFi-720p30-resilient",
+        width: 1280,
+        height: 720,
+        frame_rate: 30,
+        bit_rate: 4 * 1024 * 1024,
+        key_frame_interval: 30,
+        audio_sample_rate: 48000,
+        audio_bit_rate: 64000,
+        audio_fec: true,
+        latency: 120,
+        timeout: 5000,
+        fec: FecOptions {
+            layout: FecLayout::Staircase,
+            rows: 4,
+            cols: 5,
+            arq: FecArq::Always,
+        },
+        fc: 64,
+        mtu: 1400,
+    };
+
+    /// All reference profiles shipped in the crate.
+    pub const ALL: &'static [Self] = &[Self::LAN_1080P60_LOW_LATENCY, Self::WIFI_720P30_RESILIENT];
+
+    /// Find the reference profile by name, the name is case-insensitive.
+    pub fn find(name: &str) -> Option<&'static Self> {
+        Self::ALL
+            .iter()
+            .find(|it| it.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn video_options(&self, codec: VideoEncoderType) -> VideoOptions {
+        VideoOptions {
+            codec,
+            frame_rate: self.frame_rate,
+            width: self.width,
+            height: self.height,
+            bit_rate: self.bit_rate,
+            key_frame_interval: self.key_frame_interval,
+            preset: None,
+            tune: None,
+            profile: None,
+            rate_control: None,
+            threads: None,
+            threading: None,
+            extra_options: Vec::new(),
+            content_hint: None,
+            skip_unchanged_frames: false,
+            exclude_windows: Vec::new(),
+            protected_content: ProtectedContentPolicy::Stream,
+            simulcast: Vec::new(),
+        }
+    }
+
+    pub fn audio_options(&self) -> AudioOptions {
+        AudioOptions {
+            sample_rate: self.audio_sample_rate,
+            bit_rate: self.audio_bit_rate,
+            channels: None,
+            application: None,
+            complexity: None,
+            fec: self.audio_fec,
+            dtx: false,
+            frame_duration: None,
+            silence: None,
+            monitor: AudioMonitor::Captured,
+        }
+    }
+
+    pub fn transport_options(&self) -> TransportOptions {
+        TransportOptions {
+            fec: self.fec.to_string(),
+            max_bandwidth: -1,
+            timeout: self.timeout,
+            latency: self.latency,
+            mtu: self.mtu,
+            fc: self.fc,
+            session: None,
+            resource: None,
+            passphrase: None,
+            strategy: TransportStrategy::Srt,
+        }
+    }
+
+    /// Create the sender configuration, both video source and audio source can
+    /// be empty, which means that the track is not captured.
+    pub fn sender_options(
+        &self,
+        codec: VideoEncoderType,
+        video: Option<Source>,
+        audio: Option<Source>,
+    ) -> HylaranaSenderOptions {
+        HylaranaSenderOptions {
+            media: HylaranaSenderMediaOptions {
+                video: video.map(|source| HylaranaSenderTrackOptions {
+                    options: self.video_options(codec),
+                    source,
+                }),
+                audio: audio.map(|source| HylaranaSenderTrackOptions {
+                    options: self.audio_options(),
+                    source,
+                }),
+            },
+            transport: self.transport_options(),
+            input: SenderInput::Capture,
+            mode: None,
+            overlay: None,
+            privacy: None,
+            allow_list: None,
+            failover: SourceFailover::Close,
+            allow_display_sleep: false,
+            bandwidth_cap: None,
+        }
+    }
+
+    /// Create the receiver configuration, the transport options on both sides
+    /// must be consistent.
+    pub fn receiver_options(&self, codec: VideoDecoderType) -> HylaranaReceiverOptions {
+        HylaranaReceiverOptions {
+            transport: self.transport_options(),
+            video_output: VideoOutput::Native,
+            reconnect: None,
+            timeshift_seconds: 0,
+            sink_queue: None,
+            packet_sink: None,
+            sync_group: None,
+            rendition: RenditionSelection::Auto,
+            mode: None,
+            allow_display_sleep: false,
+            codec,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::SessionMode;
+    use capture::SourceType;
+
+    #[cfg(target_os = "windows")]
+    const ENCODERS: &[VideoEncoderType] = &[
+        VideoEncoderType::X265,
+        VideoEncoderType::Qsv,
+        VideoEncoderType::Nvenc,
+    ];
+
+    #[cfg(target_os = "windows")]
+    const DECODERS: &[VideoDecoderType] = &[
+        VideoDecoderType::HEVC,
+        VideoDecoderType::D3D11,
+        VideoDecoderType::Qsv,
+        VideoDecoderType::Nvdec,
+    ];
+
+    #[cfg(target_os = "macos")]
+    const ENCODERS: &[VideoEncoderType] = &[VideoEncoderType::X265, VideoEncoderType::VideoToolBox];
+
+    #[cfg(target_os = "macos")]
+    const DECODERS: &[VideoDecoderType] = &[VideoDecoderType::HEVC, VideoDecoderType::VideoToolBox];
+
+    #[cfg(target_os = "linux")]
+    const ENCODERS: &[VideoEncoderType] = &[
+        VideoEncoderType::X265,
+        VideoEncoderType::Vaapi,
+        VideoEncoderType::Nvenc,
+    ];
+
+    #[cfg(target_os = "linux")]
+    const DECODERS: &[VideoDecoderType] = &[
+        VideoDecoderType::HEVC,
+        VideoDecoderType::Vaapi,
+        VideoDecoderType::Nvdec,
+    ];
+
+    /// The sample rates that the opus encoder accepts.
+    const OPUS_SAMPLE_RATES: &[u64] = &[8000, 12000, 16000, 24000, 48000];
+
+    fn source(kind: SourceType) -> Source {
+        Source {
+            id: "test".to_string(),
+            name: "test".to_string(),
+            index: 0,
+            is_default: true,
+            kind,
+        }
+    }
+
+    #[test]
+    fn profiles_are_found_by_name() {
+        for profile in Profile::ALL {
+            assert_eq!(Profile::find(profile.name), Some(profile));
+            assert_eq!(
+                Profile::find(&profile.name.to_ascii_uppercase()),
+                Some(profile)
+            );
+        }
+    }
+
+    #[test]
+    fn profile_fec_round_trips() {
+        for profile in Profile::ALL {
+            assert_eq!(
+                FecOptions::try_from(profile.fec.to_string().as_str()).unwrap(),
+                profile.fec,
+                "profile={}",
+                profile.name
+            );
+        }
+    }
+
+    #[test]
+    fn sender_options_of_every_profile_are_valid() {
+        for profile in Profile::ALL {
+            for codec in ENCODERS {
+                let mut options = profile.sender_options(
+                    *codec,
+                    Some(source(SourceType::Screen)),
+                    Some(source(SourceType::Audio)),
+                );
+
+                let name = format!("profile={}, codec={:?}", profile.name, codec);
+
+                options
+                    .transport
+                    .validate()
+                    .unwrap_or_else(|e| panic!("{}, err={:?}", name, e));
+
+                assert_eq!(options.apply_mode().unwrap(), SessionMode::Both, "{}", name);
+
+                let options = options
+                    .capped()
+                    .unwrap_or_else(|e| panic!("{}, err={:?}", name, e));
+
+                let video = &options.media.video.as_ref().unwrap().options;
+                assert_eq!(video.codec, *codec, "{}", name);
+                assert!(video.width > 0 && video.width % 2 == 0, "{}", name);
+                assert!(video.height > 0 && video.height % 2 == 0, "{}", name);
+                assert!(video.frame_rate > 0, "{}", name);
+                assert!(video.bit_rate > 0, "{}", name);
+                assert!(video.key_frame_interval > 0, "{}", name);
+
+                let audio = &options.media.audio.as_ref().unwrap().options;
+                assert!(OPUS_SAMPLE_RATES.contains(&audio.sample_rate), "{}", name);
+                assert!(audio.bit_rate > 0, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn receiver_options_of_every_profile_are_valid() {
+        for profile in Profile::ALL {
+            let sender = profile.transport_options();
+
+            for codec in DECODERS {
+                let options = profile.receiver_options(*codec);
+                let name = format!("profile={}, codec={:?}", profile.name, codec);
+
+                options
+                    .transport
+                    .validate()
+                    .unwrap_or_else(|e| panic!("{}, err={:?}", name, e));
+
+                assert_eq!(options.codec, *codec, "{}", name);
+
+                // Both sides of the connection need the same srt options.
+                assert_eq!(options.transport.fec, sender.fec, "{}", name);
+                assert_eq!(options.transport.latency, sender.latency, "{}", name);
+                assert_eq!(options.transport.mtu, sender.mtu, "{}", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+};
+
+use bytes::Bytes;
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use transport::{
+    Buffer, BufferType, CloseReason as TransportCloseReason, StreamType, TransportReceiverSink,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::events::{StreamEvent, StreamEvents};
+
+#[cfg(feature = "metrics")]
+use super::metrics::Metrics;
+
+/// What the receiver drops when the queue of the sink is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum SinkDropPolicy {
+    /// The queued video is dropped, and the video after it until the next key
+    /// frame, the audio is kept unless the queue holds only audio, then the
+    /// oldest audio is dropped.
+    #[default]
+    DropUntilKeyframe,
+    /// The transport waits until the sink catches up, the packets are held
+    /// back by the transport instead, which loses them when its own buffer
+    /// is full.
+    Block,
+}
+
+/// A bounded queue between the transport and the sink, the packets are
+/// decoded and passed to the sink on a thread of their own, so that a slow
+/// sink does not hold up the transport.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct SinkQueueOptions {
+    /// The maximum number of queued packets, a packet is a video frame or
+    /// about 20ms of audio.
+    pub capacity: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub policy: SinkDropPolicy,
+}
+
+impl Default for SinkQueueOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 120,
+            policy: SinkDropPolicy::default(),
+        }
+    }
+}
+
+enum Message {
+    Packet(Buffer<Bytes>),
+    Lost(StreamType, u32),
+    Reconnecting(u32),
+    Reconnected,
+    Close(TransportCloseReason),
+}
+
+#[derive(Default)]
+struct QueueState {
+    messages: VecDeque<Message>,
+    /// The number of packets in the messages, the other messages are not
+    /// limited by the capacity.
+    packets: usize,
+    /// The video packets are dropped until the next key frame.
+    skipping: bool,
+    /// The video packets dropped since the skipping started.
+    skipped: u32,
+    closed: bool,
+}
+
+struct QueueShared {
+    state: Mutex<QueueState>,
+    condvar: Condvar,
+    /// The sink stopped the stream, the remaining packets are not decoded.
+    stopped: AtomicBool,
+    options: SinkQueueOptions,
+    events: Arc<StreamEvents>,
+    /// The id of the gauge of the queue, see [Metrics::add_sink_queue].
+    #[cfg(feature = "metrics")]
+    metrics: u64,
+}
+
+impl QueueShared {
+    fn stop(&self) {
+        let _state = self.state.lock();
+
+        self.stopped.store(true, Ordering::Relaxed);
+        self.condvar.notify_all();
+    }
+
+    fn push(&self, message: Message) {
+        let mut state = self.state.lock();
+
+        state.messages.push_back(message);
+        self.condvar.notify_all();
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for QueueShared {
+    fn drop(&mut self) {
+        Metrics::global().remove_sink_queue(self.metrics);
+    }
+}
+
+/// The notices of the tracks carry no media, and are never dropped.
+fn is_video(buffer: &Buffer<Bytes>) -> bool {
+    buffer.stream == StreamType::Video
+        && !matches!(
+            buffer.ty,
+            BufferType::TrackEnabled | BufferType::TrackDisabled
+        )
+}
+
+/// Passes the callbacks of the transport through the queue to the sinker on
+/// the thread of the queue.
+pub(crate) struct QueuedSinker {
+    shared: Arc<QueueShared>,
+}
+
+impl QueuedSinker {
+    pub(crate) fn new<T>(
+        mut sinker: T,
+        options: SinkQueueOptions,
+        events: Arc<StreamEvents>,
+    ) -> Result<Self, io::Error>
+    where
+        T: TransportReceiverSink + 'static,
+    {
+        let shared = Arc::new(QueueShared {
+            state: Mutex::new(QueueState::default()),
+            condvar: Condvar::new(),
+            stopped: AtomicBool::new(false),
+            options: SinkQueueOptions {
+                capacity: options.capacity.max(1),
+                ..options
+            },
+            events,
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::global().add_sink_queue(),
+        });
+
+        let shared_ = shared.clone();
+        thread::Builder::new()
+            .name("HylaranaReceiverSinkThread".to_string())
+            .spawn(move || {
+                loop {
+                    let message = {
+                        let mut state = shared_.state.lock();
+                        while state.messages.is_empty() && !state.closed {
+                            shared_.condvar.wait(&mut state);
+                        }
+
+                        let Some(message) = state.messages.pop_front() else {
+                            break;
+                        };
+
+                        if let Message::Packet(_) = message {
+                            state.packets -= 1;
+                            shared_.condvar.notify_all();
+
+                            #[cfg(feature = "metrics")]
+                            Metrics::global().set_sink_queue_depth(shared_.metrics, state.packets);
+                        }
+
+                        message
+                    };
+
+                    let stopped = shared_.stopped.load(Ordering::Relaxed);
+                    match message {
+                        Message::Packet(buffer) => {
+                            if !stopped && !sinker.sink(buffer) {
+                                shared_.stop();
+                            }
+                        }
+                        Message::Lost(stream, count) => {
+                            if !stopped && !sinker.lost(stream, count) {
+                                shared_.stop();
+                            }
+                        }
+                        Message::Reconnecting(attempt) => {
+                            if !sinker.reconnecting(attempt) {
+                                shared_.stop();
+                            }
+                        }
+                        Message::Reconnected => sinker.reconnected(),
+                        Message::Close(reason) => {
+                            sinker.close(reason);
+
+                            break;
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Self { shared })
+    }
+
+    /// Makes room for a packet according to the policy, returns false if the
+    /// packet itself is dropped.
+    fn make_room(&self, state: &mut MutexGuard<QueueState>, buffer: &Buffer<Bytes>) -> bool {
+        while state.packets >= self.shared.options.capacity {
+            if self.shared.options.policy == SinkDropPolicy::Block {
+                if self.shared.stopped.load(Ordering::Relaxed) {
+                    return false;
+                }
+
+                self.shared.condvar.wait(state);
+                continue;
+            }
+
+            let packets = state.packets;
+            state
+                .messages
+                .retain(|it| !matches!(it, Message::Packet(it) if is_video(it)));
+
+            state.packets = state
+                .messages
+                .iter()
+                .filter(|it| matches!(it, Message::Packet(_)))
+                .count();
+
+            if state.packets < packets {
+                state.skipping = true;
+                state.skipped += (packets - state.packets) as u32;
+            } else if let Some(index) = state
+                .messages
+                .iter()
+                .position(|it| matches!(it, Message::Packet(_)))
+            {
+                state.messages.remove(index);
+                state.packets -= 1;
+
+                self.shared.events.emit(StreamEvent::FramesDropped {
+                    stream: StreamType::Audio,
+                    count: 1,
+                });
+            }
+
+            tracing::warn!("receiver sink queue is full, packets are dropped");
+        }
+
+        !(state.skipping && is_video(buffer) && buffer.ty != BufferType::Config)
+    }
+}
+
+impl TransportReceiverSink for QueuedSinker {
+    fn sink(&mut self, buffer: Buffer<Bytes>) -> bool {
+        if self.shared.stopped.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let mut state = self.shared.state.lock();
+        if !self.make_room(&mut state, &buffer) {
+            if is_video(&buffer) {
+                state.skipped += 1;
+            }
+
+            return !self.shared.stopped.load(Ordering::Relaxed);
+        }
+
+        // The decoding of the video starts again from the key frame.
+        if state.skipping && is_video(&buffer) {
+            self.shared.events.emit(StreamEvent::FramesDropped {
+                stream: StreamType::Video,
+                count: state.skipped,
+            });
+
+            state.skipping = false;
+            state.skipped = 0;
+        }
+
+        state.packets += 1;
+        state.messages.push_back(Message::Packet(buffer));
+        self.shared.condvar.notify_all();
+
+        #[cfg(feature = "metrics")]
+        Metrics::global().set_sink_queue_depth(self.shared.metrics, state.packets);
+
+        true
+    }
+
+    fn lost(&mut self, stream: StreamType, count: u32) -> bool {
+        self.shared.push(Message::Lost(stream, count));
+
+        !self.shared.stopped.load(Ordering::Relaxed)
+    }
+
+    fn reconnecting(&mut self, attempt: u32) -> bool {
+        self.shared.push(Message::Reconnecting(attempt));
+
+        !self.shared.stopped.load(Ordering::Relaxed)
+    }
+
+    fn reconnected(&mut self) {
+        self.shared.push(Message::Reconnected);
+    }
+
+    fn close(&mut self, reason: TransportCloseReason) {
+        self.shared.push(Message::Close(reason));
+    }
+}
+
+impl Drop for QueuedSinker {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+
+        state.closed = true;
+        self.shared.condvar.notify_all();
+    }
+}
@@ -1,12 +1,42 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    slice::from_raw_parts,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        mpsc::Receiver,
+    },
+    thread,
+    time::Duration,
+};
 
-use super::{MediaStreamDescription, MediaStreamObserver, MediaStreamSink};
+use super::{
+    CloseReason, HylaranaError, MediaPacketSink, MediaStreamDescription, MediaStreamObserver,
+    MediaStreamSink, SinkAction, TransportError,
+    events::{EventObserver, StreamEvent, StreamEvents},
+    filter::{Volume, filter_samples},
+    group::{SyncGroup, SyncGroupOptions},
+    meter::AudioMeter,
+    queue::{QueuedSinker, SinkQueueOptions},
+    simulcast::{RenditionSelection, RenditionSelector},
+    snapshot::{RgbaConverter, RgbaImage, Snapshot, SnapshotError},
+    timeshift::Timeshift,
+};
 
 use bytes::Bytes;
 use codec::{AudioDecoder, VideoDecoder, VideoDecoderSettings};
-use common::codec::VideoDecoderType;
-use thiserror::Error;
-use transport::{Buffer, StreamType, TransportOptions, TransportReceiver, TransportReceiverSink};
+use common::{
+    SessionMode,
+    codec::VideoDecoderType,
+    frame::{AudioFrame, VideoFrame},
+    power::PowerGuard,
+};
+use parking_lot::Mutex;
+use transport::{
+    Buffer, BufferType, ClockEstimate, ClockSync, CloseReason as TransportCloseReason,
+    PROTOCOL_VERSION, ReconnectOptions, RenditionSubscription, StreamType, TransportOptions,
+    TransportReceiver, TransportReceiverSink,
+};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -14,14 +44,27 @@ use serde::{Deserialize, Serialize};
 #[cfg(target_os = "windows")]
 use super::util::get_direct3d;
 
-#[derive(Debug, Error)]
-pub enum HylaranaReceiverError {
-    #[error(transparent)]
-    CreateThreadError(#[from] std::io::Error),
-    #[error(transparent)]
-    VideoDecoderError(#[from] codec::VideoDecoderError),
-    #[error(transparent)]
-    AudioDecoderError(#[from] codec::AudioDecoderError),
+#[cfg(feature = "metrics")]
+use super::metrics::Metrics;
+
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+/// Where the decoded video frames are passed to the sink.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum VideoOutput {
+    /// The frames as they are produced by the decoder, the hardware decoders
+    /// produce textures of the gpu, which the renderer draws without a copy.
+    #[default]
+    Native,
+    /// The frames are downloaded to the system memory, NV12 or I420.
+    Software,
+    /// The frames are downloaded to the system memory and converted to RGBA.
+    Rgba,
+    /// The video is not decoded, for a receiver that only takes the encoded
+    /// packets, see `HylaranaReceiverOptions::packet_sink`.
+    Disabled,
 }
 
 /// Receiver configuration.
@@ -30,50 +73,619 @@ pub enum HylaranaReceiverError {
 pub struct HylaranaReceiverOptions {
     pub codec: VideoDecoderType,
     pub transport: TransportOptions,
+    /// Use `Software` or `Rgba` for a headless receiver, such as recording or
+    /// analysis on a server, the sink can read the pixels of the frames
+    /// directly and no window is needed.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub video_output: VideoOutput,
+    /// Reconnect to the sender when the connection is lost, the decoders and
+    /// the sink are kept, so the renderer keeps the last frame until the
+    /// stream is restored. The receiver is closed right away if it is `None`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub reconnect: Option<ReconnectOptions>,
+    /// Keep the encoded packets of the last seconds, so that the playback can
+    /// be paused and rewound, the memory used is about the bit rate of the
+    /// stream times the seconds. `0` disables the time shift.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub timeshift_seconds: u32,
+    /// Decode and sink the packets on a thread of their own behind a bounded
+    /// queue, the packets are dropped by the policy of the queue when the
+    /// sink can not keep up. The sink is called on the transport thread if
+    /// it is `None`, unless there is a sync group, which uses the default
+    /// queue.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sink_queue: Option<SinkQueueOptions>,
+    /// Receives the encoded packets before they are decoded, the packets of
+    /// the time shift are passed as they arrive, not as they are played.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub packet_sink: Option<Arc<dyn MediaPacketSink>>,
+    /// The display is kept on while the receiver is open, set this to let the
+    /// system sleep as usual, such as for a headless receiver.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub allow_display_sleep: bool,
+    /// Present the video at the same time as the other receivers of the
+    /// sender with the same options, such as the screens of a video wall,
+    /// see [SyncGroupOptions].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sync_group: Option<SyncGroupOptions>,
+    /// Which rendition of the video is played when the sender has simulcast,
+    /// see `VideoOptions::simulcast`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rendition: RenditionSelection,
+    /// The tracks that are played, the stream must have the tracks of the
+    /// mode, the decoders of the other tracks are not created. All tracks of
+    /// the stream are played if it is `None`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mode: Option<SessionMode>,
+}
+
+/// The packets of a sender of another version can not be read.
+fn check_protocol(description: &MediaStreamDescription) -> Result<(), HylaranaError> {
+    if description.protocol != PROTOCOL_VERSION {
+        return Err(HylaranaError::ProtocolMismatch(description.protocol));
+    }
+
+    Ok(())
+}
+
+/// The tracks of the stream that the receiver plays, see
+/// [HylaranaReceiverOptions::mode].
+fn session_mode(
+    requested: Option<SessionMode>,
+    description: &MediaStreamDescription,
+) -> Result<SessionMode, HylaranaError> {
+    let video = description.video.is_some() && description.mode.has_video();
+    let audio = description.audio.is_some() && description.mode.has_audio();
+
+    let Some(mode) = requested else {
+        return Ok(SessionMode::from_tracks(video, audio).unwrap_or_default());
+    };
+
+    if mode.has_video() && !video {
+        return Err(HylaranaError::NotFoundVideo);
+    }
+
+    if mode.has_audio() && !audio {
+        return Err(HylaranaError::NotFoundAudio);
+    }
+
+    Ok(mode)
+}
+
+/// Volume of the received audio, shared between the receiver handle and the
+/// transport thread.
+struct AudioGain {
+    /// The bits of the f32 volume.
+    volume: AtomicU32,
+    muted: AtomicBool,
+}
+
+impl Default for AudioGain {
+    fn default() -> Self {
+        Self {
+            volume: AtomicU32::new(1.0f32.to_bits()),
+            muted: AtomicBool::new(false),
+        }
+    }
+}
+
+impl AudioGain {
+    /// Scale the samples of the frame with the [Volume] filter, the frame is
+    /// returned as it is when the volume is not changed, otherwise the scaled
+    /// samples are written to the buffer and the returned frame points to the
+    /// buffer.
+    fn apply(&self, frame: &AudioFrame, buffer: &mut Vec<i16>) -> AudioFrame {
+        let volume = if self.muted.load(Ordering::Relaxed) {
+            0.0
+        } else {
+            f32::from_bits(self.volume.load(Ordering::Relaxed))
+        };
+
+        let mut output = AudioFrame {
+            sample_rate: frame.sample_rate,
+            channels: frame.channels,
+            frames: frame.frames,
+            data: frame.data,
+            timestamp: frame.timestamp,
+        };
+
+        if volume != 1.0 {
+            buffer.clear();
+            buffer.extend_from_slice(unsafe {
+                from_raw_parts(frame.data, frame.frames as usize * frame.channels as usize)
+            });
+
+            filter_samples(
+                &[&Volume(volume)],
+                buffer,
+                frame.channels,
+                frame.sample_rate,
+            );
+
+            output.data = buffer.as_ptr();
+        }
+
+        output
+    }
 }
 
 struct ReceiverSinker<S, O> {
-    audio_decoder: AudioDecoder,
-    video_decoder: VideoDecoder,
-    observer: O,
+    audio_gain: Arc<AudioGain>,
+    snapshot: Option<Arc<Snapshot>>,
+    rgba: Option<RgbaConverter>,
+    audio_buffer: Vec<i16>,
+    /// Only when the audio is played.
+    audio_decoder: Option<AudioDecoder>,
+    audio_meter: AudioMeter,
+    audio_paused: bool,
+    audio_enabled: bool,
+    video_enabled: bool,
+    /// The video packets skipped for the sink, the video is skipped until the
+    /// next key frame while it is `Some`.
+    video_skipped: Option<u32>,
+    /// Only when the video is played and decoded.
+    video_decoder: Option<VideoDecoder>,
+    timeshift: Option<Arc<Mutex<Timeshift>>>,
+    packet_sink: Option<Arc<dyn MediaPacketSink>>,
+    /// The clock sync of the current connection.
+    clock: ClockSync,
+    /// Follows the rendition subscription of the current connection.
+    rendition: RenditionSelector,
+    sync_group: Option<SyncGroup>,
+    /// The latency of the last video frame in microseconds, 0 until the clock
+    /// is synced.
+    latency: Arc<AtomicU64>,
+    reason: Option<CloseReason>,
+    observer: EventObserver<O>,
     sink: S,
 }
 
-impl<S, O> TransportReceiverSink for ReceiverSinker<S, O>
+impl<S, O> ReceiverSinker<S, O> {
+    // Stops the transport, the reason is passed to the observer when the
+    // transport closes the sink.
+    fn stop(&mut self, reason: CloseReason) -> bool {
+        self.reason = Some(reason);
+
+        false
+    }
+}
+
+impl<S, O> ReceiverSinker<S, O>
 where
-    S: MediaStreamSink + 'static,
-    O: MediaStreamObserver + 'static,
+    S: MediaStreamSink,
+    O: MediaStreamObserver,
 {
-    fn sink(&mut self, buffer: Buffer<Bytes>) -> bool {
+    fn video_decoder(
+        options: &HylaranaReceiverOptions,
+        mode: SessionMode,
+    ) -> Result<Option<VideoDecoder>, HylaranaError> {
+        if !mode.has_video() || options.video_output == VideoOutput::Disabled {
+            return Ok(None);
+        }
+
+        Ok(Some(VideoDecoder::new(VideoDecoderSettings {
+            codec: options.codec,
+            cuda_device: None,
+            software: options.video_output != VideoOutput::Native,
+            #[cfg(target_os = "windows")]
+            direct3d: Some(get_direct3d()),
+        })?))
+    }
+
+    fn audio_decoder(
+        description: &MediaStreamDescription,
+        mode: SessionMode,
+    ) -> Result<Option<AudioDecoder>, HylaranaError> {
+        if !mode.has_audio() {
+            return Ok(None);
+        }
+
+        Ok(Some(AudioDecoder::new(
+            description.audio.map(|it| it.channels).unwrap_or(2),
+        )?))
+    }
+
+    /// Prepares the decoders for a new stream, the new stream starts with the
+    /// configurations of the codecs, which the old decoders can not take.
+    fn reset(
+        &mut self,
+        options: &HylaranaReceiverOptions,
+        description: &MediaStreamDescription,
+    ) -> Result<(), HylaranaError> {
+        let mode = session_mode(options.mode, description)?;
+
+        self.video_decoder = Self::video_decoder(options, mode)?;
+        self.audio_decoder = Self::audio_decoder(description, mode)?;
+        self.audio_meter = AudioMeter::new();
+        self.video_skipped = None;
+        self.latency.store(0, Ordering::Relaxed);
+        self.reason = None;
+
+        if let Some(timeshift) = &self.timeshift {
+            *timeshift.lock() =
+                Timeshift::new(Duration::from_secs(options.timeshift_seconds as u64));
+        }
+
+        if self.audio_paused {
+            self.audio_paused = false;
+            self.observer.on_audio_resumed();
+        }
+
+        self.set_track_enabled(StreamType::Video, true);
+        self.set_track_enabled(StreamType::Audio, true);
+        self.sink.stream_changed(description, options);
+
+        Ok(())
+    }
+
+    fn decode(&mut self, buffer: Buffer<Bytes>) -> bool {
         match buffer.stream {
             StreamType::Video => {
-                if let Err(e) = self.video_decoder.decode(&buffer.data, buffer.timestamp) {
-                    log::error!("video decode error={:?}", e);
+                // The video is not played or not decoded.
+                let Some(video_decoder) = self.video_decoder.as_mut() else {
+                    return true;
+                };
+
+                if let Some(count) = self.video_skipped.as_mut() {
+                    if buffer.ty != BufferType::Config {
+                        *count += 1;
 
-                    return false;
+                        return true;
+                    }
+
+                    self.observer.events.emit(StreamEvent::FramesDropped {
+                        stream: StreamType::Video,
+                        count: *count,
+                    });
+
+                    self.video_skipped = None;
+                }
+
+                #[cfg(feature = "metrics")]
+                let started = Instant::now();
+
+                if let Err(e) =
+                    tracing::trace_span!("decode", stream = "video", timestamp = buffer.timestamp)
+                        .in_scope(|| video_decoder.decode(&buffer.data, buffer.timestamp))
+                {
+                    tracing::error!("video decode error={:?}", e);
+
+                    return self.stop(CloseReason::DecoderError(Arc::new(e)));
                 } else {
-                    while let Some(frame) = self.video_decoder.read() {
-                        if !self.sink.video(frame) {
-                            log::warn!("video sink return false!");
+                    #[cfg(feature = "metrics")]
+                    Metrics::global().decoded(
+                        StreamType::Video,
+                        buffer.data.len(),
+                        started.elapsed(),
+                    );
 
-                            return false;
+                    let mut late = 0;
+                    while let Some(frame) = video_decoder.read() {
+                        // The frames left in the decoder belong to the skipped video.
+                        if self.video_skipped.is_some() {
+                            continue;
+                        }
+
+                        let frame = match self.rgba.as_mut() {
+                            Some(rgba) => match rgba.convert(frame) {
+                                Some(it) => it,
+                                None => {
+                                    tracing::warn!("video frame is not in the system memory");
+
+                                    return false;
+                                }
+                            },
+                            None => frame,
+                        };
+
+                        // The timestamps of the sender are moved to the clock of the receiver,
+                        // so that the frames can be compared with the local playback.
+                        let frame = &Self::synced(&self.clock, &self.latency, frame);
+
+                        // The packets were held until the presentation time of the group, the
+                        // frames that are still too late are dropped, the timestamps are only
+                        // comparable once the clock is synced.
+                        if Self::is_late_in(&self.sync_group, &self.clock, frame.timestamp) {
+                            late += 1;
+                            continue;
+                        }
+
+                        // The decoded frame is retained instead of copied, unless it was
+                        // converted to RGBA.
+                        if let Some(snapshot) = &self.snapshot {
+                            match self.rgba.is_none().then(|| video_decoder.retain()) {
+                                Some(Some(guard)) => snapshot.retain(guard),
+                                _ => snapshot.capture(frame),
+                            }
+                        }
+
+                        match self.sink.video_action(frame) {
+                            SinkAction::Continue => (),
+                            SinkAction::DropUntilKeyframe => {
+                                tracing::warn!(
+                                    "video sink drops the frames until the next key frame"
+                                );
+
+                                self.video_skipped = Some(0);
+                            }
+                            SinkAction::Close => {
+                                tracing::warn!("video sink return false!");
+
+                                return false;
+                            }
                         }
                     }
+
+                    if late > 0 {
+                        self.observer.events.emit(StreamEvent::FramesDropped {
+                            stream: StreamType::Video,
+                            count: late,
+                        });
+                    }
                 }
             }
             StreamType::Audio => {
-                if let Err(e) = self.audio_decoder.decode(&buffer.data, buffer.timestamp) {
-                    log::error!("audio decode error={:?}", e);
+                let Some(audio_decoder) = self.audio_decoder.as_mut() else {
+                    return true;
+                };
+
+                // The sender stops the audio while it is silent, and resumes it with the
+                // next packet.
+                if buffer.ty == BufferType::Silence {
+                    if !self.audio_paused {
+                        self.audio_paused = true;
+                        self.observer.on_audio_paused();
+                    }
+
+                    return true;
+                }
 
-                    return false;
+                if self.audio_paused {
+                    self.audio_paused = false;
+                    self.observer.on_audio_resumed();
+                }
+
+                #[cfg(feature = "metrics")]
+                let started = Instant::now();
+
+                if let Err(e) =
+                    tracing::trace_span!("decode", stream = "audio", timestamp = buffer.timestamp)
+                        .in_scope(|| audio_decoder.decode(&buffer.data, buffer.timestamp))
+                {
+                    tracing::error!("audio decode error={:?}", e);
+
+                    return self.stop(CloseReason::DecoderError(Arc::new(e)));
                 } else {
-                    while let Some(frame) = self.audio_decoder.read() {
-                        if !self.sink.audio(frame) {
-                            log::warn!("audio sink return false!");
+                    #[cfg(feature = "metrics")]
+                    Metrics::global().decoded(
+                        StreamType::Audio,
+                        buffer.data.len(),
+                        started.elapsed(),
+                    );
+
+                    let mut late = 0;
+                    while let Some(frame) = audio_decoder.read() {
+                        if let Some(level) = self.audio_meter.process(frame) {
+                            self.observer.on_audio_level(level);
+                        }
+
+                        // The timestamps of the sender are moved to the clock of the receiver,
+                        // the same as the video.
+                        let mut frame = self.audio_gain.apply(frame, &mut self.audio_buffer);
+                        if frame.timestamp > 0 {
+                            frame.timestamp = self.clock.to_local(frame.timestamp);
+                        }
+
+                        if Self::is_late_in(&self.sync_group, &self.clock, frame.timestamp) {
+                            late += 1;
+                            continue;
+                        }
+
+                        if !self.sink.audio(&frame) {
+                            tracing::warn!("audio sink return false!");
 
                             return false;
                         }
                     }
+
+                    if late > 0 {
+                        self.observer.events.emit(StreamEvent::FramesDropped {
+                            stream: StreamType::Audio,
+                            count: late,
+                        });
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    /// The time until the packet is due with the sync group, `None` without a
+    /// sync group or before the clock is synced.
+    fn presentation_wait(&self, buffer: &Buffer<Bytes>) -> Option<Duration> {
+        let group = self.sync_group.as_ref()?;
+        if buffer.timestamp == 0 || self.clock.estimate().is_none() {
+            return None;
+        }
+
+        Some(group.wait(self.clock.to_local(buffer.timestamp)))
+    }
+
+    // The frame is too late for the sync group. This does not borrow the sinker,
+    // the frames are borrowed from the decoders.
+    fn is_late_in(group: &Option<SyncGroup>, clock: &ClockSync, timestamp: u64) -> bool {
+        match group {
+            Some(group) => timestamp > 0 && clock.estimate().is_some() && group.is_late(timestamp),
+            None => false,
+        }
+    }
+
+    /// The frame with its timestamp on the clock of the receiver, the latency is
+    /// measured from it once the clock is synced.
+    fn synced(clock: &ClockSync, latency: &AtomicU64, frame: &VideoFrame) -> VideoFrame {
+        let timestamp = match clock.estimate() {
+            Some(estimate) if frame.timestamp > 0 => {
+                let timestamp = estimate.to_local(frame.timestamp);
+                let elapsed = common::clock::now().saturating_sub(timestamp).max(1);
+
+                latency.store(elapsed, Ordering::Relaxed);
+
+                #[cfg(feature = "metrics")]
+                Metrics::global().set_latency(Duration::from_micros(elapsed));
+
+                timestamp
+            }
+            _ => frame.timestamp,
+        };
+
+        VideoFrame {
+            timestamp,
+            ..*frame
+        }
+    }
+
+    /// The rendition of a new connection starts again from the selection, the
+    /// stream may have another number of renditions.
+    fn attach_rendition(&mut self, connection: &Connection, description: &MediaStreamDescription) {
+        let renditions = description
+            .video
+            .map(|it| it.simulcast.saturating_add(1))
+            .unwrap_or(1);
+        let rendition = self.rendition.attach(connection.rendition(), renditions);
+        if rendition != 0 {
+            self.observer
+                .events
+                .emit(StreamEvent::RenditionChanged(rendition));
+        }
+    }
+
+    fn set_track_enabled(&mut self, stream: StreamType, enabled: bool) {
+        let state = match stream {
+            StreamType::Video => &mut self.video_enabled,
+            StreamType::Audio => &mut self.audio_enabled,
+        };
+
+        if *state != enabled {
+            *state = enabled;
+
+            tracing::info!(
+                "receiver track changed, stream={:?}, enabled={}",
+                stream,
+                enabled
+            );
+
+            self.observer.on_track_changed(stream, enabled);
+        }
+    }
+}
+
+impl<S, O> TransportReceiverSink for ReceiverSinker<S, O>
+where
+    S: MediaStreamSink + 'static,
+    O: MediaStreamObserver + 'static,
+{
+    fn sink(&mut self, buffer: Buffer<Bytes>) -> bool {
+        let notice = match buffer.ty {
+            BufferType::TrackDisabled => Some(false),
+            BufferType::TrackEnabled => Some(true),
+            _ => None,
+        };
+
+        // Any packet of a disabled track enables it again, in case the notice was
+        // lost, the notices themselves carry no media.
+        self.set_track_enabled(buffer.stream, notice.unwrap_or(true));
+        if notice.is_some() {
+            return true;
+        }
+
+        if buffer.stream == StreamType::Video {
+            if let Some(rendition) = self.rendition.update() {
+                self.observer
+                    .events
+                    .emit(StreamEvent::RenditionChanged(rendition));
+            }
+        }
+
+        let forwarded = self
+            .packet_sink
+            .as_ref()
+            .map(|it| it.packet(&buffer))
+            .unwrap_or(true);
+
+        if !forwarded {
+            tracing::warn!("packet sink return false!");
+
+            return false;
+        }
+
+        // The packets are decoded from the time shift buffer, which holds them back
+        // while the playback is behind the live stream.
+        let Some(timeshift) = self.timeshift.clone() else {
+            return self.decode(buffer);
+        };
+
+        timeshift.lock().push(buffer);
+
+        let next = || timeshift.lock().next();
+        while let Some(buffer) = next() {
+            if !self.decode(buffer) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn lost(&mut self, stream: StreamType, count: u32) -> bool {
+        #[cfg(feature = "metrics")]
+        Metrics::global().lost(stream, count);
+
+        self.observer
+            .events
+            .emit(StreamEvent::PacketLoss { stream, count });
+
+        // The lower rendition is played from its next key frame, the video decoder
+        // waits for it like after any other loss.
+        if stream == StreamType::Video {
+            if let Some(rendition) = self.rendition.lost() {
+                self.observer
+                    .events
+                    .emit(StreamEvent::RenditionChanged(rendition));
+            }
+        }
+
+        // The video decoder waits for the next key frame on its own, only the audio
+        // gap has to be filled, and only when the audio is played live, otherwise the
+        // gap is not where the audio is played.
+        let live = self
+            .timeshift
+            .as_ref()
+            .map(|it| it.lock().is_live())
+            .unwrap_or(true);
+
+        let audio_decoder = self.audio_decoder.as_mut().filter(|_| live);
+        if let (StreamType::Audio, Some(audio_decoder)) = (stream, audio_decoder) {
+            for _ in 0..count.min(AudioDecoder::MAX_CONCEALED_PACKETS) {
+                // The concealment only fills the gap, the next packets are still decoded
+                // without it.
+                if let Err(e) = audio_decoder.conceal() {
+                    tracing::warn!("audio conceal error={:?}", e);
+
+                    break;
+                }
+
+                while let Some(frame) = audio_decoder.read() {
+                    if let Some(level) = self.audio_meter.process(frame) {
+                        self.observer.on_audio_level(level);
+                    }
+
+                    let frame = self.audio_gain.apply(frame, &mut self.audio_buffer);
+                    if !self.sink.audio(&frame) {
+                        tracing::warn!("audio sink return false!");
+
+                        return false;
+                    }
                 }
             }
         }
@@ -81,18 +693,283 @@ where
         true
     }
 
-    fn close(&mut self) {
-        log::info!("receiver is closed");
+    fn reconnecting(&mut self, attempt: u32) -> bool {
+        self.observer.on_reconnecting(attempt);
+
+        true
+    }
+
+    fn reconnected(&mut self) {
+        self.observer.on_reconnected();
+    }
+
+    fn close(&mut self, reason: TransportCloseReason) {
+        // The sinker knows why it closed the stream locally, such as a decoder
+        // error.
+        let reason = match reason {
+            TransportCloseReason::Closed => self.reason.take().unwrap_or(CloseReason::Closed),
+            it => it.into(),
+        };
+
+        tracing::info!("receiver is closed, reason={}", reason);
+
+        self.observer.close(reason);
+    }
+}
+
+/// The transport of a receiver shares the sinker with the transports of the
+/// previous streams, the sinker is detached from a transport that is replaced,
+/// so that the old transport neither feeds nor closes it.
+struct SharedSinker<S, O> {
+    sinker: Arc<Mutex<ReceiverSinker<S, O>>>,
+    detached: Arc<AtomicBool>,
+}
+
+impl<S, O> TransportReceiverSink for SharedSinker<S, O>
+where
+    S: MediaStreamSink + 'static,
+    O: MediaStreamObserver + 'static,
+{
+    // The flag is checked with the lock held, the transport may have been waiting
+    // for the lock while the sinker was switched to another stream.
+    //
+    // The packets of a sync group are held until their presentation time without
+    // the lock, so that the receiver can still be switched and controlled.
+    fn sink(&mut self, buffer: Buffer<Bytes>) -> bool {
+        let wait = {
+            let sinker = self.sinker.lock();
+            if self.detached.load(Ordering::Relaxed) {
+                return false;
+            }
+
+            sinker.presentation_wait(&buffer)
+        };
+
+        if let Some(wait) = wait {
+            thread::sleep(wait);
+        }
+
+        let mut sinker = self.sinker.lock();
+        if self.detached.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        sinker.sink(buffer)
+    }
+
+    fn lost(&mut self, stream: StreamType, count: u32) -> bool {
+        let mut sinker = self.sinker.lock();
+        if self.detached.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        sinker.lost(stream, count)
+    }
+
+    fn reconnecting(&mut self, attempt: u32) -> bool {
+        let mut sinker = self.sinker.lock();
+        if self.detached.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        sinker.reconnecting(attempt)
+    }
+
+    fn reconnected(&mut self) {
+        let mut sinker = self.sinker.lock();
+        if !self.detached.load(Ordering::Relaxed) {
+            sinker.reconnected();
+        }
+    }
+
+    fn close(&mut self, reason: TransportCloseReason) {
+        let mut sinker = self.sinker.lock();
+        if !self.detached.load(Ordering::Relaxed) {
+            sinker.close(reason);
+        }
+    }
+}
+
+struct Connection {
+    detached: Arc<AtomicBool>,
+    transport: TransportReceiver,
+}
+
+impl Connection {
+    fn new<S, O>(
+        addr: SocketAddr,
+        options: &HylaranaReceiverOptions,
+        description: &MediaStreamDescription,
+        sinker: Arc<Mutex<ReceiverSinker<S, O>>>,
+        events: &Arc<StreamEvents>,
+    ) -> Result<Self, HylaranaError>
+    where
+        S: MediaStreamSink + 'static,
+        O: MediaStreamObserver + 'static,
+    {
+        let detached = Arc::new(AtomicBool::new(false));
+        let sinker = SharedSinker {
+            detached: detached.clone(),
+            sinker,
+        };
+
+        let transport_options = TransportOptions {
+            session: description
+                .token
+                .clone()
+                .or_else(|| options.transport.session.clone()),
+            passphrase: description
+                .passphrase
+                .clone()
+                .or_else(|| options.transport.passphrase.clone()),
+            ..options.transport.clone()
+        };
+
+        // The sync group holds the packets back, which must not hold up the
+        // transport.
+        let sink_queue = options
+            .sink_queue
+            .or_else(|| options.sync_group.map(|_| SinkQueueOptions::default()));
+
+        Ok(Self {
+            transport: match sink_queue {
+                Some(queue) => TransportReceiver::with_reconnect(
+                    addr,
+                    transport_options,
+                    options.reconnect,
+                    QueuedSinker::new(sinker, queue, events.clone())
+                        .map_err(TransportError::from)?,
+                ),
+                None => TransportReceiver::with_reconnect(
+                    addr,
+                    transport_options,
+                    options.reconnect,
+                    sinker,
+                ),
+            }
+            .map_err(TransportError::from)?,
+            detached,
+        })
+    }
+
+    fn detach(&self) {
+        self.detached.store(true, Ordering::Relaxed);
+    }
+
+    fn clock(&self) -> ClockSync {
+        self.transport.clock()
+    }
 
-        self.observer.close();
+    fn rendition(&self) -> RenditionSubscription {
+        self.transport.rendition()
+    }
+}
+
+/// Connects the sinker of a receiver to the streams, this hides the types of
+/// the sink and the observer from the receiver.
+trait Connector: Send + Sync {
+    fn connect(
+        &self,
+        addr: SocketAddr,
+        description: &MediaStreamDescription,
+    ) -> Result<Connection, HylaranaError>;
+
+    /// Connects to the new stream and detaches the current connection, the
+    /// decoders are reset for the new stream.
+    fn switch(
+        &self,
+        addr: SocketAddr,
+        description: &MediaStreamDescription,
+        current: &Connection,
+    ) -> Result<Connection, HylaranaError>;
+}
+
+struct SinkerConnector<S, O> {
+    sinker: Arc<Mutex<ReceiverSinker<S, O>>>,
+    events: Arc<StreamEvents>,
+    options: HylaranaReceiverOptions,
+}
+
+impl<S, O> SinkerConnector<S, O>
+where
+    S: MediaStreamSink + 'static,
+    O: MediaStreamObserver + 'static,
+{
+    fn open(
+        &self,
+        addr: SocketAddr,
+        description: &MediaStreamDescription,
+    ) -> Result<Connection, HylaranaError> {
+        Connection::new(
+            addr,
+            &self.options,
+            description,
+            self.sinker.clone(),
+            &self.events,
+        )
+    }
+}
+
+impl<S, O> Connector for SinkerConnector<S, O>
+where
+    S: MediaStreamSink + 'static,
+    O: MediaStreamObserver + 'static,
+{
+    fn connect(
+        &self,
+        addr: SocketAddr,
+        description: &MediaStreamDescription,
+    ) -> Result<Connection, HylaranaError> {
+        let connection = self.open(addr, description)?;
+
+        let mut sinker = self.sinker.lock();
+        sinker.clock = connection.clock();
+        sinker.attach_rendition(&connection, description);
+
+        Ok(connection)
+    }
+
+    fn switch(
+        &self,
+        addr: SocketAddr,
+        description: &MediaStreamDescription,
+        current: &Connection,
+    ) -> Result<Connection, HylaranaError> {
+        // Both transports wait for the lock while the new stream is connected, so
+        // none of the old packets reaches the new decoders, and none of the new
+        // packets reaches the old ones.
+        let mut sinker = self.sinker.lock();
+
+        let connection = self.open(addr, description)?;
+        if let Err(e) = sinker.reset(&self.options, description) {
+            connection.detach();
+
+            return Err(e);
+        }
+
+        sinker.clock = connection.clock();
+        sinker.attach_rendition(&connection, description);
+        current.detach();
+
+        Ok(connection)
     }
 }
 
 /// Screen casting receiver.
 pub struct HylaranaReceiver {
     description: MediaStreamDescription,
+    /// The mode of the options, the mode of the session follows the stream.
+    requested: Option<SessionMode>,
+    mode: SessionMode,
+    events: Arc<StreamEvents>,
+    audio_gain: Arc<AudioGain>,
+    snapshot: Option<Arc<Snapshot>>,
+    timeshift: Option<Arc<Mutex<Timeshift>>>,
+    latency: Arc<AtomicU64>,
+    connector: Box<dyn Connector>,
+    connection: Connection,
     #[allow(unused)]
-    transport: TransportReceiver,
+    power: Option<PowerGuard>,
 }
 
 impl HylaranaReceiver {
@@ -105,34 +982,218 @@ impl HylaranaReceiver {
         description: &MediaStreamDescription,
         sink: S,
         observer: O,
-    ) -> Result<Self, HylaranaReceiverError>
+    ) -> Result<Self, HylaranaError>
     where
         S: MediaStreamSink + 'static,
         O: MediaStreamObserver + 'static,
     {
-        log::info!("create receiver");
+        tracing::info!("create receiver");
 
-        Ok(Self {
-            description: description.clone(),
-            transport: TransportReceiver::new(
-                addr,
-                options.transport.clone(),
-                ReceiverSinker {
-                    video_decoder: VideoDecoder::new(VideoDecoderSettings {
-                        codec: options.codec,
-                        #[cfg(target_os = "windows")]
-                        direct3d: Some(get_direct3d()),
-                    })?,
-                    audio_decoder: AudioDecoder::new()?,
+        check_protocol(description)?;
+
+        let mode = session_mode(options.mode, description)?;
+        let audio_gain = Arc::new(AudioGain::default());
+        let snapshot = description
+            .video
+            .as_ref()
+            .filter(|_| options.video_output != VideoOutput::Disabled && mode.has_video())
+            .map(|_| {
+                Arc::new(Snapshot::new(
+                    #[cfg(target_os = "windows")]
+                    get_direct3d(),
+                ))
+            });
+
+        let timeshift = if options.timeshift_seconds > 0 {
+            Some(Arc::new(Mutex::new(Timeshift::new(Duration::from_secs(
+                options.timeshift_seconds as u64,
+            )))))
+        } else {
+            None
+        };
+
+        let events = Arc::new(StreamEvents::default());
+        let latency = Arc::new(AtomicU64::new(0));
+        let connector = SinkerConnector {
+            events: events.clone(),
+            options: options.clone(),
+            sinker: Arc::new(Mutex::new(ReceiverSinker {
+                video_decoder: ReceiverSinker::<S, O>::video_decoder(options, mode)?,
+                audio_decoder: ReceiverSinker::<S, O>::audio_decoder(description, mode)?,
+                audio_buffer: Vec::new(),
+                audio_meter: AudioMeter::new(),
+                audio_paused: false,
+                audio_enabled: true,
+                video_enabled: true,
+                video_skipped: None,
+                reason: None,
+                timeshift: timeshift.clone(),
+                packet_sink: options.packet_sink.clone(),
+                clock: ClockSync::default(),
+                rendition: RenditionSelector::new(options.rendition),
+                sync_group: options.sync_group.as_ref().map(SyncGroup::new),
+                latency: latency.clone(),
+                audio_gain: audio_gain.clone(),
+                snapshot: snapshot.clone(),
+                rgba: if options.video_output == VideoOutput::Rgba && mode.has_video() {
+                    Some(RgbaConverter::new())
+                } else {
+                    None
+                },
+                observer: EventObserver {
+                    events: events.clone(),
                     observer,
-                    sink,
                 },
-            )?,
+                sink,
+            })),
+        };
+
+        Ok(Self {
+            connection: connector.connect(addr, description)?,
+            connector: Box::new(connector),
+            description: description.clone(),
+            requested: options.mode,
+            mode,
+            power: (!options.allow_display_sleep).then(|| PowerGuard::acquire("Hylarana receiver")),
+            audio_gain,
+            snapshot,
+            timeshift,
+            latency,
+            events,
         })
     }
 
+    /// Switch to the stream of another sender, the sink, and so the window,
+    /// the renderer and the audio device, are kept. The last frame of the
+    /// current stream is kept until the first key frame of the new stream,
+    /// and the current stream is kept if the new sender can not be
+    /// connected.
+    pub fn switch_stream(
+        &mut self,
+        addr: SocketAddr,
+        description: &MediaStreamDescription,
+    ) -> Result<(), HylaranaError> {
+        tracing::info!("receiver switch stream, addr={}", addr);
+
+        // The new stream must have the tracks of the options too.
+        check_protocol(description)?;
+        let mode = session_mode(self.requested, description)?;
+        let connection = self.connector.switch(addr, description, &self.connection)?;
+
+        // The replaced transport is closed here, its sinker is already detached.
+        drop(std::mem::replace(&mut self.connection, connection));
+
+        self.description = description.clone();
+        self.mode = mode;
+
+        Ok(())
+    }
+
     /// Get the media description information of the current receiver.
     pub fn get_description(&self) -> &MediaStreamDescription {
         &self.description
     }
+
+    /// The tracks that the receiver plays, see
+    /// [HylaranaReceiverOptions::mode].
+    pub fn get_mode(&self) -> SessionMode {
+        self.mode
+    }
+
+    /// The clock of the sender relative to the clock of the receiver, it is
+    /// estimated by exchanging the times with the sender over the connection,
+    /// `None` until the first exchange is completed.
+    pub fn get_clock(&self) -> Option<ClockEstimate> {
+        self.connection.clock().estimate()
+    }
+
+    /// The time from the capture on the sender to the decoding of the last
+    /// video frame on the receiver, `None` until the clock is synced.
+    pub fn get_latency(&self) -> Option<Duration> {
+        match self.latency.load(Ordering::Relaxed) {
+            0 => None,
+            it => Some(Duration::from_micros(it)),
+        }
+    }
+
+    /// Subscribe to the events of the receiver, the events are also passed to
+    /// the observer, the channel can be polled in a message loop instead of
+    /// implementing the observer.
+    pub fn events(&self) -> Receiver<StreamEvent> {
+        self.events.subscribe()
+    }
+
+    /// Set the volume of the received audio, 1.0 is the original volume, the
+    /// samples are scaled before they are passed to the sink.
+    pub fn set_volume(&self, volume: f32) {
+        self.audio_gain
+            .volume
+            .store(volume.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Mute or unmute the received audio, the sink receives silent frames
+    /// while muted, the volume is kept.
+    pub fn set_muted(&self, muted: bool) {
+        self.audio_gain.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Take a snapshot of the received video, the latest decoded frame is
+    /// converted to RGBA, for example to save a screenshot of the remote
+    /// screen.
+    pub fn snapshot(&self) -> Result<RgbaImage, SnapshotError> {
+        self.snapshot
+            .as_ref()
+            .ok_or_else(|| SnapshotError::NotFoundVideo)?
+            .take()
+    }
+
+    fn timeshift(&self) -> Result<&Mutex<Timeshift>, HylaranaError> {
+        self.timeshift
+            .as_deref()
+            .ok_or(HylaranaError::TimeshiftDisabled)
+    }
+
+    /// Pause the playback, the received packets are kept in the time shift
+    /// buffer, the packets older than the buffer are dropped.
+    pub fn pause(&self) -> Result<(), HylaranaError> {
+        self.timeshift()?.lock().pause();
+
+        Ok(())
+    }
+
+    /// Resume the playback from where it was paused, the playback stays
+    /// behind the live stream.
+    pub fn resume(&self) -> Result<(), HylaranaError> {
+        self.timeshift()?.lock().resume();
+
+        Ok(())
+    }
+
+    /// Rewind the playback, the playback starts from the key frame before
+    /// the position, and goes back at most to the start of the time shift
+    /// buffer, negative or NaN seconds do not move it.
+    pub fn seek_back(&self, secs: f32) -> Result<(), HylaranaError> {
+        // Anything too large for a duration is beyond the buffer anyway.
+        let offset = Duration::try_from_secs_f32(secs.max(0.0)).unwrap_or(Duration::MAX);
+        self.timeshift()?.lock().seek_back(offset);
+
+        Ok(())
+    }
+
+    /// Return to the live stream, the playback starts again from the next key
+    /// frame.
+    pub fn go_live(&self) -> Result<(), HylaranaError> {
+        self.timeshift()?.lock().go_live();
+
+        Ok(())
+    }
+
+    /// Whether the playback is at the live stream, which is always the case
+    /// when the time shift is disabled.
+    pub fn is_live(&self) -> bool {
+        self.timeshift
+            .as_ref()
+            .map(|it| it.lock().is_live())
+            .unwrap_or(true)
+    }
 }
@@ -1,53 +1,73 @@
 use super::{
-    MediaAudioStreamDescription, MediaStreamDescription, MediaStreamObserver, MediaStreamSink,
-    MediaVideoStreamDescription,
+    CloseReason, HylaranaError, MediaAudioStreamDescription, MediaStreamDescription,
+    MediaStreamObserver, MediaStreamSink, MediaVideoStreamDescription, RenderError,
+    RenderErrorKind, TransportError, diagnostics,
+    events::{EventObserver, StreamEvent, StreamEvents},
+    failover::{Placeholder, SourceFailover, VideoFailover},
+    luma::LumaSampler,
+    meter::{AudioLevel, AudioMeter, SilenceDetector},
+    overlay::{Overlay, OverlayOptions},
+    player::{VideoRender, VideoRenderOptionsBuilder, VideoRenderSurfaceOptions},
+    privacy::{self, PrivacyMask, PrivacyOptions, PrivacySlot},
+    simulcast::{Rendition, VideoRendition},
+    snapshot::{KeptFrame, RgbaImage, Snapshot, SnapshotError},
 };
 
 #[cfg(target_os = "windows")]
 use super::util::get_direct3d;
 
+#[cfg(target_os = "windows")]
+use common::win32::Direct3DDevice;
+
+#[cfg(feature = "metrics")]
+use super::metrics::Metrics;
+
 use std::{
-    net::SocketAddr,
+    io,
+    net::{IpAddr, SocketAddr},
     sync::{
         Arc, Weak,
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc::Receiver,
     },
+    thread,
+    time::{Duration, Instant},
 };
 
 use capture::{
-    AudioCaptureSourceDescription, Capture, CaptureOptions, FrameConsumer, Source,
-    SourceCaptureOptions, VideoCaptureSourceDescription,
+    AudioCaptureSourceDescription, Capture, CaptureOptions, Damage, FrameConsumer,
+    ProtectedContent, Source, SourceCaptureOptions, SourceType, VideoCaptureSourceDescription,
 };
 
+#[cfg(target_os = "windows")]
+use capture::ScreenCaptureBackend;
+
 use common::{
-    Size,
-    codec::VideoEncoderType,
-    frame::{AudioFrame, VideoFormat, VideoFrame},
+    SessionMode, Size,
+    codec::{
+        AudioEncoderApplication, ContentHint, VideoCodec, VideoEncoderPreset, VideoEncoderProfile,
+        VideoEncoderRateControl, VideoEncoderThreading, VideoEncoderTune, VideoEncoderType,
+    },
+    frame::{AudioFrame, VideoFormat, VideoFrame, VideoSubFormat},
+    power::PowerGuard,
 };
 
 use codec::{
-    AudioEncoder, AudioEncoderSettings, CodecType, VideoEncoder, VideoEncoderSettings,
-    create_opus_identification_header,
+    AudioDecoder, AudioEncoder, AudioEncoderSettings, CodecType, VideoEncoder, VideoEncoderError,
+    VideoEncoderSettings, create_opus_identification_header,
 };
 
-use thiserror::Error;
-use transport::{Buffer, BufferType, StreamType, TransportOptions, TransportSender};
+use parking_lot::{Condvar, Mutex};
+use renderer::SurfaceTarget;
+use transport::{
+    Buffer, BufferType, ConnectionStats, MAX_RENDITIONS, PROTOCOL_VERSION, PacingStats, StreamType,
+    TransportOptions, TransportSender,
+};
+use uuid::Uuid;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Error)]
-pub enum HylaranaSenderError {
-    #[error(transparent)]
-    TransportError(#[from] std::io::Error),
-    #[error(transparent)]
-    CaptureError(#[from] capture::CaptureError),
-    #[error(transparent)]
-    VideoEncoderError(#[from] codec::VideoEncoderError),
-    #[error(transparent)]
-    AudioEncoderError(#[from] codec::AudioEncoderError),
-}
-
 /// Description of video coding.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
@@ -58,6 +78,66 @@ pub struct VideoOptions {
     pub height: u32,
     pub bit_rate: u64,
     pub key_frame_interval: u32,
+    /// Advanced encoder options, keep them empty to use the built-in low
+    /// latency configuration of the encoder.
+    pub preset: Option<VideoEncoderPreset>,
+    pub tune: Option<VideoEncoderTune>,
+    pub profile: Option<VideoEncoderProfile>,
+    pub rate_control: Option<VideoEncoderRateControl>,
+    /// Threads of the software encoder, raise them on many-core machines to
+    /// encode high resolutions at high frame rates.
+    pub threads: Option<u32>,
+    pub threading: Option<VideoEncoderThreading>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub extra_options: Vec<(String, String)>,
+    /// What the video shows, such as text or video playback, the encoder uses
+    /// its built-in low latency configuration if it is not set.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub content_hint: Option<ContentHint>,
+    /// Do not encode the frames in which the captured screen did not change,
+    /// a frame is still encoded a few times per second so that the receiver
+    /// does not see the stream as stalled. Only the screen captures of windows
+    /// report the changes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub skip_unchanged_frames: bool,
+    /// The native ids of the windows that are left out of the captured
+    /// screen, such as the preview or the player of the application, see
+    /// [VideoCaptureSourceDescription::exclude_windows].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub exclude_windows: Vec<u64>,
+    /// What is sent while the system hides content from the capture, such as
+    /// a DRM video or the secure desktop, see [ProtectedContent]. Only the
+    /// desktop duplication of windows reports the protected content, so the
+    /// screens are captured with it unless this is
+    /// [ProtectedContentPolicy::Stream], and the other policies are rejected
+    /// on the other platforms.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub protected_content: ProtectedContentPolicy,
+    /// Lower renditions that are encoded next to the main video, from the
+    /// largest to the smallest, the receiver plays one of them by its network,
+    /// see [RenditionSelection]. Only the renditions that the receivers play
+    /// are encoded. At most `MAX_RENDITIONS - 1`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub simulcast: Vec<VideoRendition>,
+}
+
+/// What the sender does while the system hides content from the capture, the
+/// observer is told in every case, see
+/// [MediaStreamObserver::on_protected_content].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum ProtectedContentPolicy {
+    /// The video is sent as it is captured, with the protected content
+    /// blacked out or the last picture repeated.
+    #[default]
+    Stream,
+    /// No video is encoded, the receivers keep the last picture from before
+    /// the protected content.
+    Pause,
+    /// A black picture is sent instead of the captured video, it is drawn in
+    /// the system memory, which only the software encoders, vaapi and
+    /// VideoToolbox can encode.
+    Placeholder,
 }
 
 /// Description of the audio encoding.
@@ -66,6 +146,75 @@ pub struct VideoOptions {
 pub struct AudioOptions {
     pub sample_rate: u64,
     pub bit_rate: u64,
+    /// 1 for mono or 2 for stereo, the default is stereo. The surround sources
+    /// are downmixed to this channel count.
+    pub channels: Option<u8>,
+    /// Use `Audio` when mirroring music, and `Voip` for voice only content, the
+    /// default is low delay.
+    pub application: Option<AudioEncoderApplication>,
+    /// Computational complexity of the encoder, 0 to 10.
+    pub complexity: Option<u8>,
+    /// In-band forward error correction, makes the stream more robust against
+    /// packet loss at the cost of some bitrate, the receiver always conceals
+    /// the lost packets.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fec: bool,
+    /// Discontinuous transmission, almost nothing is sent during silence.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dtx: bool,
+    /// Duration of a single opus frame in milliseconds.
+    pub frame_duration: Option<f32>,
+    /// Stop sending the audio while it is silent to save the bandwidth, the
+    /// receiver is notified, this is disabled if it is not set.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub silence: Option<SilenceOptions>,
+    /// Which audio is passed to the sink of the sender, such as a monitor
+    /// player, see [crate::AVFrameStreamPlayer::monitor].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub monitor: AudioMonitor,
+}
+
+/// The audio that the presenter hears on the sender, see
+/// [AudioOptions::monitor].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum AudioMonitor {
+    /// The captured audio, as it is before the encoder, with no delay.
+    #[default]
+    Captured,
+    /// The encoded audio decoded again on the sender, as the receivers hear
+    /// it apart from the network, the bitrate and the encoder options can be
+    /// checked with it. It costs a decoder and the delay of the encoder. The
+    /// sink does not receive the audio while it is disabled or silent. If the
+    /// decoder fails, the sink gets the captured audio from then on.
+    Decoded,
+}
+
+/// When the audio is considered silent, see [AudioOptions::silence].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct SilenceOptions {
+    /// The peak level in decibels relative to full scale below which the
+    /// audio is silent.
+    pub threshold: f32,
+    /// How long the audio must be silent before the stream is paused, in
+    /// milliseconds.
+    pub timeout: u64,
+}
+
+impl Default for SilenceOptions {
+    fn default() -> Self {
+        Self {
+            threshold: AudioLevel::SILENCE_DB,
+            timeout: 2000,
+        }
+    }
+}
+
+impl AudioOptions {
+    pub(crate) fn channels(&self) -> u8 {
+        self.channels.unwrap_or(2).clamp(1, 2)
+    }
 }
 
 /// Options of the media track.
@@ -84,14 +233,536 @@ pub struct HylaranaSenderMediaOptions {
     pub audio: Option<HylaranaSenderTrackOptions<AudioOptions>>,
 }
 
+/// Where the frames of the sender come from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum SenderInput {
+    /// The sources of the tracks are captured.
+    #[default]
+    Capture,
+    /// Nothing is captured and the sources of the tracks are ignored, the
+    /// application pushes the frames with `HylaranaSender::push_video_frame`
+    /// and `HylaranaSender::push_audio_frame`, such as the frames rendered by
+    /// a game engine.
+    Push,
+}
+
+/// The hardware encoders take the textures of their device, only vaapi and
+/// VideoToolbox upload the frames in the system memory to their surfaces.
+fn accepts_software_frames(codec: VideoEncoderType) -> bool {
+    matches!(
+        codec,
+        VideoEncoderType::Vaapi | VideoEncoderType::VideoToolBox
+    ) || !CodecType::from(codec).is_hardware()
+}
+
+/// The windows must not be sent unmasked where they can not be found.
+fn check_privacy(options: Option<&PrivacyOptions>) -> Result<(), HylaranaError> {
+    if options.map(|it| !it.windows.is_empty()).unwrap_or(false) && !privacy::is_supported() {
+        return Err(HylaranaError::PrivacyNotSupported);
+    }
+
+    Ok(())
+}
+
 /// Sender configuration.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct HylaranaSenderOptions {
     pub media: HylaranaSenderMediaOptions,
     pub transport: TransportOptions,
+    /// The tracks that are sent, the tracks of the mode must be given, and
+    /// the other tracks are ignored. The mode follows the given tracks if it
+    /// is `None`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mode: Option<SessionMode>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub input: SenderInput,
+    /// Images or texts stamped onto the outgoing video, such as a logo or the
+    /// device name.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub overlay: Option<OverlayOptions>,
+    /// Windows whose content is hidden in the outgoing video, such as password
+    /// managers or chat applications, see also `HylaranaSender::set_privacy`.
+    /// Only the screens on Windows and macOS are masked, the sender is not
+    /// created elsewhere, see [HylaranaError::PrivacyNotSupported].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub privacy: Option<PrivacyOptions>,
+    /// Only the receivers of these addresses may connect, all receivers may
+    /// connect if this is `None`, see also `HylaranaSender::set_access_control`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub allow_list: Option<Vec<IpAddr>>,
+    /// What the sender does when the captured monitor or camera is gone, the
+    /// sender is closed by default.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub failover: SourceFailover,
+    /// The display is kept on while the sender is open, set this to let the
+    /// system sleep as usual.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub allow_display_sleep: bool,
+    /// A hard cap of the uplink in bits per second, the cap overrides the
+    /// `max_bandwidth` of the transport if it is lower, the bit rate of the
+    /// video is lowered to fit the cap, and the packets are paced so that
+    /// the key frames do not burst over it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bandwidth_cap: Option<u64>,
+}
+
+impl HylaranaSenderOptions {
+    /// The share of the payload bandwidth given to the encoders, the rest
+    /// absorbs the encoders that overshoot their bit rate.
+    const ENCODER_HEADROOM: f64 = 0.9;
+
+    /// Drops the tracks outside of the mode, so that nothing is captured or
+    /// encoded for them, and returns the mode of the session.
+    pub(crate) fn apply_mode(&mut self) -> Result<SessionMode, HylaranaError> {
+        let media = &mut self.media;
+        let Some(mode) = self.mode else {
+            return Ok(
+                SessionMode::from_tracks(media.video.is_some(), media.audio.is_some())
+                    .unwrap_or_default(),
+            );
+        };
+
+        if !mode.has_video() {
+            media.video = None;
+        } else if media.video.is_none() {
+            return Err(HylaranaError::NotFoundVideo);
+        }
+
+        if !mode.has_audio() {
+            media.audio = None;
+        } else if media.audio.is_none() {
+            return Err(HylaranaError::NotFoundAudio);
+        }
+
+        Ok(mode)
+    }
+
+    /// Applies the bandwidth cap to the transport and the encoders, the
+    /// options are returned unchanged if there is no cap.
+    pub(crate) fn capped(&self) -> Result<Self, HylaranaError> {
+        let Some(cap) = self.bandwidth_cap else {
+            return Ok(self.clone());
+        };
+
+        let mut options = self.clone();
+
+        // The transport counts the bandwidth in bytes per second.
+        let cap_bytes = (cap / 8) as i64;
+        if options.transport.max_bandwidth <= 0 || options.transport.max_bandwidth > cap_bytes {
+            options.transport.max_bandwidth = cap_bytes;
+        }
+
+        let payload = options
+            .transport
+            .payload_bandwidth()
+            .map(|it| (it as f64 * 8.0 * Self::ENCODER_HEADROOM) as u64)
+            .unwrap_or_default();
+
+        let audio = options
+            .media
+            .audio
+            .as_ref()
+            .map(|it| it.options.bit_rate)
+            .unwrap_or_default();
+
+        if audio >= payload {
+            return Err(HylaranaError::BandwidthCapTooLow(cap));
+        }
+
+        if let Some(video) = options.media.video.as_mut() {
+            let bit_rate = video.options.bit_rate.min(payload - audio);
+            if bit_rate != video.options.bit_rate {
+                tracing::info!(
+                    "sender lowers the video bit rate to the bandwidth cap, cap={}, bit_rate={}",
+                    cap,
+                    bit_rate
+                );
+
+                video.options.bit_rate = bit_rate;
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// The usage of the bandwidth of a sender, see
+/// `HylaranaSender::get_bandwidth_stats`.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct BandwidthStats {
+    /// The bandwidth cap of the options in bits per second.
+    pub cap: Option<u64>,
+    /// The rate that is actually sent in bits per second, including the fec,
+    /// the retransmissions and the headers, `None` if there is no receiver.
+    pub send_rate: Option<f64>,
+    /// The bit rate of the video encoder after the cap is applied.
+    pub video_bit_rate: Option<u64>,
+    /// The counters of the pacing, `None` if the bandwidth is not capped.
+    pub pacing: Option<PacingStats>,
+}
+
+/// Detects that the captured video is black, this happens when the capture
+/// silently fails, for example protected content or a missing permission.
+///
+/// The luma is sampled by the [LumaSampler], the frames that are not sampled
+/// keep the current state.
+struct SignalDetector {
+    black_since: Option<Instant>,
+    lost: bool,
+}
+
+impl SignalDetector {
+    /// Luma below this value is considered black, the limited range black is 16.
+    const BLACK_LUMA: u8 = 32;
+
+    /// The video has to be black for this long before the signal is lost, so
+    /// that fades and scene cuts are not reported.
+    const LOST_DURATION: Duration = Duration::from_secs(2);
+
+    fn new() -> Self {
+        Self {
+            black_since: None,
+            lost: false,
+        }
+    }
+
+    fn is_black(samples: &[u8]) -> bool {
+        let bright = samples.iter().filter(|it| **it > Self::BLACK_LUMA).count();
+
+        // A few bright pixels are tolerated, such as the cursor.
+        bright * 200 <= samples.len()
+    }
+
+    /// Returns the new state of the signal if it has changed, true means that
+    /// the signal is lost.
+    fn update(&mut self, samples: &[u8]) -> Option<bool> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        if Self::is_black(samples) {
+            let since = *self.black_since.get_or_insert_with(Instant::now);
+            if !self.lost && since.elapsed() >= Self::LOST_DURATION {
+                self.lost = true;
+
+                return Some(true);
+            }
+        } else {
+            self.black_since = None;
+
+            if self.lost {
+                self.lost = false;
+
+                return Some(false);
+            }
+        }
+
+        None
+    }
+}
+
+/// Tells the static content from the motion for [ContentHint::Auto], the
+/// samples of the [LumaSampler] are compared with the previous ones. The
+/// textures are only read back at the interval of the sampler, so the motion
+/// settles slower for them, and the frames that are not sampled keep the
+/// current tuning.
+struct ContentDetector {
+    samples: Vec<u8>,
+    /// The share of the changed samples, smoothed over the frames.
+    motion: f32,
+    current: ContentHint,
+    changed: Instant,
+}
+
+impl ContentDetector {
+    /// A sample changed if its luma moved more than this, so that the noise of
+    /// the capture is not counted.
+    const CHANGED_LUMA: u8 = 12;
+
+    /// The motion goes above this share to switch to the motion tuning, and
+    /// below the lower one to switch back, so that a blinking cursor or a
+    /// scrolling page does not flip the tuning.
+    const MOTION_SHARE: f32 = 0.05;
+    const DETAIL_SHARE: f32 = 0.01;
+
+    /// The weight of the current frame in the smoothed motion.
+    const SMOOTHING: f32 = 0.05;
+
+    /// The encoder is created again on every switch, so the switches are at
+    /// least this far apart.
+    const HOLD_DURATION: Duration = Duration::from_secs(3);
+
+    fn new() -> Self {
+        Self {
+            samples: Vec::new(),
+            motion: 0.0,
+            current: ContentHint::Motion,
+            changed: Instant::now(),
+        }
+    }
+
+    /// Returns the share of the samples that changed since the previous ones.
+    fn compare(&mut self, samples: &[u8]) -> Option<f32> {
+        // The first frame and a resized frame have nothing to compare with.
+        let is_comparable = !samples.is_empty() && self.samples.len() == samples.len();
+        let changed = if is_comparable {
+            self.samples
+                .iter()
+                .zip(samples)
+                .filter(|(a, b)| a.abs_diff(**b) > Self::CHANGED_LUMA)
+                .count()
+        } else {
+            0
+        };
+
+        self.samples.clear();
+        self.samples.extend_from_slice(samples);
+
+        if !is_comparable {
+            return None;
+        }
+
+        Some(changed as f32 / samples.len() as f32)
+    }
+
+    /// Returns the new content if it has changed.
+    fn update(&mut self, samples: &[u8]) -> Option<ContentHint> {
+        let share = self.compare(samples)?;
+        self.motion += (share - self.motion) * Self::SMOOTHING;
+
+        if self.changed.elapsed() < Self::HOLD_DURATION {
+            return None;
+        }
+
+        let content = match self.current {
+            ContentHint::Motion if self.motion < Self::DETAIL_SHARE => ContentHint::Detail,
+            ContentHint::Detail if self.motion > Self::MOTION_SHARE => ContentHint::Motion,
+            _ => return None,
+        };
+
+        self.current = content;
+        self.changed = Instant::now();
+
+        Some(content)
+    }
+}
+
+/// The enabled state of the tracks, shared by the sender and the consumers of
+/// the capture.
+struct TrackSwitches {
+    video: AtomicBool,
+    audio: AtomicBool,
+}
+
+impl Default for TrackSwitches {
+    fn default() -> Self {
+        Self {
+            video: AtomicBool::new(true),
+            audio: AtomicBool::new(true),
+        }
+    }
+}
+
+impl TrackSwitches {
+    fn get(&self, stream: StreamType) -> &AtomicBool {
+        match stream {
+            StreamType::Video => &self.video,
+            StreamType::Audio => &self.audio,
+        }
+    }
+}
+
+fn send_track_notice(
+    transport: &TransportSender,
+    stream: StreamType,
+    enabled: bool,
+) -> io::Result<()> {
+    transport.send(Buffer {
+        data: Buffer::<()>::with_capacity(0),
+        ty: if enabled {
+            BufferType::TrackEnabled
+        } else {
+            BufferType::TrackDisabled
+        },
+        timestamp: 0,
+        rendition: 0,
+        stream,
+    })
+}
+
+/// Gates a track of the sender, the disabled track is still captured and
+/// passed to the local sink and the preview, only the encoding is skipped, so
+/// the encoder continues where it stopped when the track is enabled again.
+struct TrackGate {
+    stream: StreamType,
+    switches: Arc<TrackSwitches>,
+    notified: Option<Instant>,
 }
 
+impl TrackGate {
+    /// The disabled track is announced again at this interval, so that a
+    /// receiver connecting later knows about it too.
+    const NOTICE_INTERVAL: Duration = Duration::from_secs(1);
+
+    fn new(stream: StreamType, switches: Arc<TrackSwitches>) -> Self {
+        Self {
+            notified: None,
+            switches,
+            stream,
+        }
+    }
+
+    /// Returns whether the frame is sent.
+    fn update(&mut self, transport: &TransportSender) -> io::Result<bool> {
+        if self.switches.get(self.stream).load(Ordering::Relaxed) {
+            self.notified = None;
+
+            return Ok(true);
+        }
+
+        if self
+            .notified
+            .map(|it| it.elapsed() >= Self::NOTICE_INTERVAL)
+            .unwrap_or(true)
+        {
+            self.notified = Some(Instant::now());
+
+            send_track_notice(transport, self.stream, false)?;
+        }
+
+        Ok(false)
+    }
+}
+
+/// The preview of the sender. The capture thread only copies the frames, the
+/// frames are rendered on the thread of the preview, so that the presentation,
+/// which may wait for the display, does not hold up the encoder. A frame that
+/// arrives while the previous one is rendered is skipped.
+///
+/// The id tells the previews apart, so that a replaced preview does not
+/// remove the new one when it is dropped.
+struct PreviewSlot {
+    render: Mutex<Option<(u32, VideoRender<'static>)>>,
+    /// The latest frame, and whether it is not rendered yet.
+    frame: Mutex<(KeptFrame, bool)>,
+    condvar: Condvar,
+    active: AtomicBool,
+    /// The device of the capture, the preview renders the hardware frames on
+    /// the same device.
+    #[cfg(target_os = "windows")]
+    direct3d: Direct3DDevice,
+}
+
+impl PreviewSlot {
+    /// The thread of the preview checks that the preview is still alive at
+    /// this interval when there are no frames.
+    const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+    fn new(#[cfg(target_os = "windows")] direct3d: Direct3DDevice) -> Self {
+        Self {
+            render: Mutex::new(None),
+            frame: Mutex::new((KeptFrame::default(), false)),
+            condvar: Condvar::new(),
+            active: AtomicBool::new(false),
+            #[cfg(target_os = "windows")]
+            direct3d,
+        }
+    }
+
+    /// Called on the capture thread.
+    fn push(&self, frame: &VideoFrame) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let Some(mut kept) = self.frame.try_lock() else {
+            return;
+        };
+
+        match kept.0.keep(
+            frame,
+            #[cfg(target_os = "windows")]
+            &self.direct3d,
+        ) {
+            Ok(_) => {
+                kept.1 = true;
+
+                self.condvar.notify_all();
+            }
+            Err(e) => {
+                tracing::warn!("video sender preview failed, err={:?}", e);
+            }
+        }
+    }
+
+    /// The loop of the preview thread, ends when the preview is dropped or
+    /// replaced.
+    fn run(&self, id: u32) {
+        loop {
+            let mut frame = self.frame.lock();
+            if !frame.1 {
+                self.condvar.wait_for(&mut frame, Self::IDLE_INTERVAL);
+            }
+
+            let mut render = self.render.lock();
+            let Some((current, render)) = render.as_mut() else {
+                break;
+            };
+
+            if *current != id {
+                break;
+            }
+
+            if std::mem::take(&mut frame.1) {
+                if let Err(e) = render.send(frame.0.frame()) {
+                    tracing::warn!("video sender preview failed, err={:?}", e);
+                }
+            }
+        }
+
+        tracing::info!("sender preview thread stop, id={}", id);
+    }
+}
+
+/// A self view of the sender, the video is removed from the window when the
+/// preview is dropped.
+pub struct HylaranaSenderPreview {
+    slot: Arc<PreviewSlot>,
+    id: u32,
+}
+
+impl HylaranaSenderPreview {
+    pub fn resize(&self, size: Size) {
+        if let Some((id, render)) = self.slot.render.lock().as_mut() {
+            if *id == self.id {
+                render.resize(size);
+            }
+        }
+    }
+}
+
+impl Drop for HylaranaSenderPreview {
+    fn drop(&mut self) {
+        let mut render = self.slot.render.lock();
+        if render
+            .as_ref()
+            .map(|(id, _)| *id == self.id)
+            .unwrap_or(false)
+        {
+            self.slot.active.store(false, Ordering::Relaxed);
+
+            render.take();
+        }
+    }
+}
+
+/// The encoder and its settings created with another content, see
+/// [VideoSender::set_content_hint].
+type Retuning = thread::JoinHandle<Result<(VideoEncoder, VideoEncoderSettings), VideoEncoderError>>;
+
 // Encoding is a relatively complex task. If you add encoding tasks to the
 // pipeline that pushes frames, it will slow down the entire pipeline.
 //
@@ -99,37 +770,215 @@ pub struct HylaranaSenderOptions {
 // independent threads. The encoding thread is notified of task updates through
 // the optional lock.
 struct VideoSender<S> {
-    callback: Arc<dyn Fn() + Send + Sync + 'static>,
+    callback: Arc<dyn Fn(CloseReason) + Send + Sync + 'static>,
+    reason: Option<CloseReason>,
+    observer: Arc<dyn MediaStreamObserver>,
     transport: Weak<TransportSender>,
     encoder: VideoEncoder,
+    settings: VideoEncoderSettings,
+    /// Only with [ContentHint::Auto].
+    content: Option<ContentDetector>,
+    /// The encoder with the detected content, it is created on a thread of its
+    /// own and replaces the current encoder once it is ready.
+    retuning: Option<Retuning>,
+    skip_unchanged_frames: bool,
+    /// The capture reported that the next frame did not change.
+    unchanged: bool,
+    encoded: Option<Instant>,
+    gate: TrackGate,
+    luma: LumaSampler,
+    signal: SignalDetector,
+    /// The content that the capture reported as hidden.
+    protected: Option<ProtectedContent>,
+    protected_content: ProtectedContentPolicy,
+    /// Only with [ProtectedContentPolicy::Placeholder], created when it is
+    /// first needed.
+    placeholder: Option<Placeholder>,
+    /// Only for the captured screens.
+    privacy: Option<PrivacyMask>,
+    overlay: Option<Overlay>,
+    /// The lower renditions of [VideoOptions::simulcast].
+    renditions: Vec<Rendition>,
+    snapshot: Arc<Snapshot>,
+    preview: Arc<PreviewSlot>,
     sink: Arc<S>,
+    /// The number of captured frames, the sequence of the frame in the trace.
+    frames: u64,
 }
 
 impl<S> VideoSender<S> {
     fn new(
         options: &VideoOptions,
         transport: &Arc<TransportSender>,
+        privacy: Option<PrivacyMask>,
+        overlay: Option<&OverlayOptions>,
+        snapshot: Arc<Snapshot>,
+        preview: Arc<PreviewSlot>,
+        switches: Arc<TrackSwitches>,
         sink: Arc<S>,
-        callback: Arc<dyn Fn() + Send + Sync + 'static>,
-    ) -> Result<Self, HylaranaSenderError> {
+        observer: Arc<dyn MediaStreamObserver>,
+        callback: Arc<dyn Fn(CloseReason) + Send + Sync + 'static>,
+    ) -> Result<Self, HylaranaError> {
+        let settings = VideoEncoderSettings {
+            codec: options.codec,
+            key_frame_interval: options.key_frame_interval,
+            frame_rate: options.frame_rate,
+            width: options.width,
+            height: options.height,
+            bit_rate: options.bit_rate,
+            preset: options.preset,
+            tune: options.tune,
+            profile: options.profile,
+            rate_control: options.rate_control,
+            threads: options.threads,
+            threading: options.threading,
+            extra_options: options.extra_options.clone(),
+            // The detector starts from the motion, the same as the built-in
+            // configuration.
+            content_hint: match options.content_hint {
+                Some(ContentHint::Auto) => Some(ContentHint::Motion),
+                hint => hint,
+            },
+            cuda_device: None,
+            #[cfg(target_os = "windows")]
+            direct3d: Some(get_direct3d()),
+        };
+
+        let renditions = options
+            .simulcast
+            .iter()
+            .enumerate()
+            .map(|(i, it)| Rendition::new(i as u8 + 1, it, &settings))
+            .collect::<Result<Vec<_>, _>>()?;
+
         Ok(VideoSender {
-            encoder: VideoEncoder::new(VideoEncoderSettings {
-                codec: options.codec,
-                key_frame_interval: options.key_frame_interval,
-                frame_rate: options.frame_rate,
-                width: options.width,
-                height: options.height,
-                bit_rate: options.bit_rate,
-                #[cfg(target_os = "windows")]
-                direct3d: Some(get_direct3d()),
-            })?,
+            overlay: match overlay {
+                Some(options) if !options.layers.is_empty() => Some(Overlay::new(
+                    options,
+                    #[cfg(target_os = "windows")]
+                    get_direct3d(),
+                )?),
+                _ => None,
+            },
+            encoder: VideoEncoder::new(settings.clone())?,
+            content: (options.content_hint == Some(ContentHint::Auto)).then(ContentDetector::new),
+            retuning: None,
+            skip_unchanged_frames: options.skip_unchanged_frames,
+            unchanged: false,
+            encoded: None,
             transport: Arc::downgrade(&transport),
+            gate: TrackGate::new(StreamType::Video, switches),
+            luma: LumaSampler::new(
+                #[cfg(target_os = "windows")]
+                get_direct3d(),
+            ),
+            signal: SignalDetector::new(),
+            protected: None,
+            protected_content: options.protected_content,
+            placeholder: None,
+            reason: None,
+            frames: 0,
+            renditions,
+            privacy,
+            settings,
+            snapshot,
+            preview,
+            observer,
             callback,
             sink,
         })
     }
 }
 
+impl<S> VideoSender<S> {
+    /// The unchanged frames are still encoded at this interval, it is shorter
+    /// than the stall timeout of the receiver, the overlays such as the time
+    /// are also refreshed at this interval.
+    const REPEAT_INTERVAL: Duration = Duration::from_millis(250);
+
+    // Only the frames reported as unchanged right before them are skipped, the
+    // frames without a report are always encoded.
+    fn is_skipped(&mut self) -> bool {
+        let unchanged = std::mem::take(&mut self.unchanged);
+
+        self.skip_unchanged_frames
+            && unchanged
+            && self
+                .encoded
+                .map(|it| it.elapsed() < Self::REPEAT_INTERVAL)
+                .unwrap_or(false)
+    }
+
+    // The encoders are configured once, so the encoder is created again with the
+    // detected content. Creating a hardware encoder takes long enough to stall the
+    // capture, so this happens on a thread of its own, and the current encoder
+    // keeps going until the new one is ready.
+    fn set_content_hint(&mut self, hint: ContentHint) {
+        // The detector holds the content for a few seconds, a retuning that is
+        // still running is not started again.
+        if self.retuning.is_some() {
+            return;
+        }
+
+        let settings = VideoEncoderSettings {
+            content_hint: Some(hint),
+            ..self.settings.clone()
+        };
+
+        match thread::Builder::new()
+            .name("HylaranaVideoRetuneThread".to_string())
+            .spawn(move || Ok((VideoEncoder::new(settings.clone())?, settings)))
+        {
+            Ok(it) => self.retuning = Some(it),
+            Err(e) => {
+                tracing::warn!("video sender failed to start the retuning, err={:?}", e);
+            }
+        }
+    }
+
+    // Replaces the encoder once the retuned one is ready, the old encoder is kept
+    // if that failed. The new encoder starts with the configuration and a key
+    // frame.
+    //
+    // This does not borrow the sender, the frame that is being encoded may be
+    // borrowed from the overlay or the placeholder.
+    fn retuned(
+        retuning: &mut Option<Retuning>,
+        encoder: &mut VideoEncoder,
+        settings: &mut VideoEncoderSettings,
+    ) {
+        if !retuning.as_ref().is_some_and(|it| it.is_finished()) {
+            return;
+        }
+
+        match retuning.take().map(|it| it.join()) {
+            Some(Ok(Ok((it, retuned)))) => {
+                tracing::info!(
+                    "video sender content changed, content={:?}",
+                    retuned.content_hint
+                );
+
+                *encoder = it;
+                *settings = retuned;
+            }
+            Some(Ok(Err(e))) => {
+                tracing::warn!("video sender failed to retune the encoder, err={:?}", e);
+            }
+            _ => {
+                tracing::warn!("video sender retuning thread panicked");
+            }
+        }
+    }
+
+    // Stops the capture, the reason is passed to the observer when the capture
+    // closes the consumer.
+    fn stop(&mut self, reason: CloseReason) -> bool {
+        self.reason = Some(reason);
+
+        false
+    }
+}
+
 impl<S> FrameConsumer for VideoSender<S>
 where
     S: MediaStreamSink + 'static,
@@ -137,54 +986,221 @@ where
     type Frame = VideoFrame;
 
     fn sink(&mut self, frame: &Self::Frame) -> bool {
+        // The stages of the frame are the children of this span, so that the time of
+        // each stage can be told apart in a trace.
+        self.frames += 1;
+        let _span =
+            tracing::trace_span!("capture", stream = "video", sequence = self.frames).entered();
+
+        let samples = self.luma.sample(frame);
+        let hint = samples.and_then(|it| self.content.as_mut()?.update(it));
+
+        match samples.and_then(|it| self.signal.update(it)) {
+            Some(true) => {
+                tracing::warn!("video sender signal lost, the captured video is black");
+
+                self.observer.on_signal_lost();
+            }
+            Some(false) => {
+                tracing::info!("video sender signal restored");
+
+                self.observer.on_signal_restored();
+            }
+            None => (),
+        }
+
+        if let Some(hint) = hint {
+            self.set_content_hint(hint);
+        }
+
+        // The report of the capture belongs to this frame, it is taken before the
+        // frame is replaced.
+        let skipped = self.is_skipped();
+        let paused =
+            self.protected.is_some() && self.protected_content == ProtectedContentPolicy::Pause;
+
+        let frame = match self.protected {
+            Some(_) if self.protected_content == ProtectedContentPolicy::Placeholder => self
+                .placeholder
+                .get_or_insert_with(|| {
+                    Placeholder::new(
+                        Size {
+                            width: self.settings.width,
+                            height: self.settings.height,
+                        },
+                        self.settings.frame_rate,
+                    )
+                })
+                .frame(),
+            _ => frame,
+        };
+
+        // The windows are hidden before anything else sees the frame, including the
+        // snapshot and the preview.
+        let frame = match self.privacy.as_mut() {
+            Some(privacy) => match privacy.apply(frame) {
+                Some(it) => it,
+                None => return true,
+            },
+            None => frame,
+        };
+
+        let frame = match self.overlay.as_mut() {
+            Some(overlay) => match overlay.apply(frame) {
+                Ok(it) => it,
+                Err(e) => {
+                    tracing::warn!("video sender overlay failed, err={:?}", e);
+
+                    frame
+                }
+            },
+            None => frame,
+        };
+
+        // The snapshot is the video as it is sent, including the overlays.
+        self.snapshot.capture(frame);
+
+        self.preview.push(frame);
+
         if let Some(transport) = self.transport.upgrade() {
-            // Push the audio and video frames into the encoder.
-            if self.encoder.update(frame) {
-                // Try to get the encoded data packets. The audio and video frames do not
-                // correspond to the data packets one by one, so you need to try to get
-                // multiple packets until they are empty.
-                if let Err(e) = self.encoder.encode() {
-                    log::error!("video encode error={:?}", e);
+            #[cfg(feature = "metrics")]
+            Metrics::global().update_transport(&transport);
+
+            let enabled = match self.gate.update(&transport) {
+                Ok(it) => it,
+                Err(e) => {
+                    tracing::warn!("video send track notice to transport failed, err={:?}", e);
 
-                    return false;
+                    return self.stop(CloseReason::TransportError(Arc::new(e)));
+                }
+            };
+
+            // The disabled video, the unchanged frames and the paused protected content
+            // are not encoded.
+            if enabled && !skipped && !paused {
+                self.encoded = Some(Instant::now());
+
+                Self::retuned(&mut self.retuning, &mut self.encoder, &mut self.settings);
+
+                // Push the audio and video frames into the encoder.
+                let encode = tracing::trace_span!("encode", stream = "video").entered();
+                #[cfg(feature = "metrics")]
+                let started = Instant::now();
+
+                if self.encoder.update(frame) {
+                    // Try to get the encoded data packets. The audio and video frames do not
+                    // correspond to the data packets one by one, so you need to try to get
+                    // multiple packets until they are empty.
+                    if let Err(e) = self.encoder.encode() {
+                        tracing::error!("video encode error={:?}", e);
+
+                        return self.stop(CloseReason::EncoderError(Arc::new(e)));
+                    } else {
+                        drop(encode);
+
+                        #[cfg(feature = "metrics")]
+                        Metrics::global().encoded(StreamType::Video, started.elapsed());
+
+                        while let Some((buffer, flags, timestamp)) = self.encoder.read() {
+                            #[cfg(feature = "metrics")]
+                            Metrics::global().sent(StreamType::Video, buffer.len());
+
+                            if let Err(e) = transport.send(Buffer {
+                                data: Buffer::<()>::copy_from_slice(buffer),
+                                ty: BufferType::try_from(flags as u8).unwrap(),
+                                stream: StreamType::Video,
+                                timestamp,
+                                rendition: 0,
+                            }) {
+                                tracing::warn!(
+                                    "video send packet to transport failed, err={:?}",
+                                    e
+                                );
+
+                                return self.stop(CloseReason::TransportError(Arc::new(e)));
+                            }
+                        }
+                    }
                 } else {
-                    while let Some((buffer, flags, timestamp)) = self.encoder.read() {
+                    tracing::warn!("video encoder update frame failed");
+
+                    return self.stop(CloseReason::Closed);
+                }
+
+                // The lower renditions are encoded from the same frame after the main video,
+                // only the renditions that the receivers play are encoded and sent.
+                for rendition in self.renditions.iter_mut() {
+                    rendition.set_wanted(transport.is_rendition_wanted(rendition.id()));
+
+                    if let Err(e) = rendition.encode(frame) {
+                        tracing::error!("video rendition encode error={:?}", e);
+
+                        return self.stop(CloseReason::EncoderError(Arc::new(e)));
+                    }
+
+                    while let Some((buffer, flags, timestamp)) = rendition.read() {
+                        #[cfg(feature = "metrics")]
+                        Metrics::global().sent(StreamType::Video, buffer.len());
+
                         if let Err(e) = transport.send(Buffer {
                             data: Buffer::<()>::copy_from_slice(buffer),
                             ty: BufferType::try_from(flags as u8).unwrap(),
                             stream: StreamType::Video,
+                            rendition: rendition.id(),
                             timestamp,
                         }) {
-                            log::warn!("video send packet to transport failed, err={:?}", e);
+                            tracing::warn!("video send packet to transport failed, err={:?}", e);
 
-                            return false;
+                            return self.stop(CloseReason::TransportError(Arc::new(e)));
                         }
                     }
                 }
-            } else {
-                log::warn!("video encoder update frame failed");
-
-                return false;
             }
 
             if self.sink.video(frame) {
                 true
             } else {
-                log::warn!("video sink on frame return false");
+                tracing::warn!("video sink on frame return false");
 
-                false
+                self.stop(CloseReason::Closed)
             }
         } else {
-            log::warn!("transport weak upgrade failed, maybe is drop");
+            tracing::warn!("transport weak upgrade failed, maybe is drop");
 
-            false
+            self.stop(CloseReason::Closed)
+        }
+    }
+
+    fn damage(&mut self, damage: &Damage) {
+        self.unchanged = damage.is_unchanged();
+    }
+
+    fn protected(&mut self, content: Option<ProtectedContent>) {
+        if self.protected == content {
+            return;
+        }
+
+        tracing::info!(
+            "video sender protected content changed, content={:?}, policy={:?}",
+            content,
+            self.protected_content
+        );
+
+        self.protected = content;
+        self.observer.on_protected_content(content);
+    }
+
+    fn changed(&mut self, source: &Source) {
+        if let Some(privacy) = self.privacy.as_mut() {
+            privacy.set_source(source);
         }
     }
 
     fn close(&mut self) {
-        log::info!("video sender is closed");
+        tracing::info!("video sender is closed");
 
-        (self.callback)();
+        // The capture stopped by itself if the consumer did not stop it.
+        (self.callback)(self.reason.take().unwrap_or(CloseReason::CaptureStopped));
     }
 }
 
@@ -195,44 +1211,92 @@ where
 // independent threads. The encoding thread is notified of task updates through
 // the optional lock.
 struct AudioSender<S> {
-    callback: Arc<dyn Fn() + Send + Sync + 'static>,
+    callback: Arc<dyn Fn(CloseReason) + Send + Sync + 'static>,
+    reason: Option<CloseReason>,
+    meter: AudioMeter,
+    gate: TrackGate,
+    silence: Option<SilenceDetector>,
+    observer: Arc<dyn MediaStreamObserver>,
     transport: Weak<TransportSender>,
     encoder: AudioEncoder,
+    /// Decodes the encoded audio for the sink, see [AudioMonitor::Decoded].
+    monitor: Option<AudioDecoder>,
     sink: Arc<S>,
+    /// The number of captured frames, the sequence of the frame in the trace.
+    frames: u64,
 }
 
 impl<S> AudioSender<S> {
     fn new(
         options: &AudioOptions,
         transport: &Arc<TransportSender>,
+        switches: Arc<TrackSwitches>,
         sink: Arc<S>,
-        callback: Arc<dyn Fn() + Send + Sync + 'static>,
-    ) -> Result<Self, HylaranaSenderError> {
+        observer: Arc<dyn MediaStreamObserver>,
+        callback: Arc<dyn Fn(CloseReason) + Send + Sync + 'static>,
+    ) -> Result<Self, HylaranaError> {
         // Create an opus header data. The opus decoder needs this data to obtain audio
         // information. Here, actively add an opus header information to the queue, and
         // the adapter layer will automatically cache it.
-        transport.send(Buffer {
-            stream: StreamType::Audio,
-            ty: BufferType::Config,
-            timestamp: 0,
-            data: Buffer::<()>::copy_from_slice(&create_opus_identification_header(
-                2,
-                options.sample_rate as u32,
-            )),
-        })?;
+        let header =
+            create_opus_identification_header(options.channels(), options.sample_rate as u32);
+
+        transport
+            .send(Buffer {
+                stream: StreamType::Audio,
+                ty: BufferType::Config,
+                timestamp: 0,
+                rendition: 0,
+                data: Buffer::<()>::copy_from_slice(&header),
+            })
+            .map_err(TransportError::from)?;
+
+        // The monitor decoder takes the same header as the decoders of the receivers.
+        let monitor = match options.monitor {
+            AudioMonitor::Captured => None,
+            AudioMonitor::Decoded => {
+                let mut decoder = AudioDecoder::new(options.channels())?;
+                decoder.decode(&header, 0)?;
+
+                Some(decoder)
+            }
+        };
 
         Ok(Self {
             encoder: AudioEncoder::new(AudioEncoderSettings {
                 sample_rate: options.sample_rate,
+                channels: options.channels(),
                 bit_rate: options.bit_rate,
+                application: options.application,
+                complexity: options.complexity,
+                fec: options.fec,
+                dtx: options.dtx,
+                frame_duration: options.frame_duration,
             })?,
             transport: Arc::downgrade(&transport),
+            meter: AudioMeter::new(),
+            gate: TrackGate::new(StreamType::Audio, switches),
+            silence: options.silence.as_ref().map(SilenceDetector::new),
+            reason: None,
+            frames: 0,
+            monitor,
+            observer,
             callback,
             sink,
         })
     }
 }
 
+impl<S> AudioSender<S> {
+    // Stops the capture, the reason is passed to the observer when the capture
+    // closes the consumer.
+    fn stop(&mut self, reason: CloseReason) -> bool {
+        self.reason = Some(reason);
+
+        false
+    }
+}
+
 impl<S> FrameConsumer for AudioSender<S>
 where
     S: MediaStreamSink + 'static,
@@ -240,65 +1304,198 @@ where
     type Frame = AudioFrame;
 
     fn sink(&mut self, frame: &Self::Frame) -> bool {
-        if self.encoder.update(&frame) {
-            // Push the audio and video frames into the encoder.
-            if let Err(e) = self.encoder.encode() {
-                log::error!("audio encode error={:?}", e);
+        self.frames += 1;
+        let _span =
+            tracing::trace_span!("capture", stream = "audio", sequence = self.frames).entered();
 
-                return false;
-            } else {
-                // Try to get the encoded data packets. The audio and video frames
-                // do not correspond to the data
-                // packets one by one, so you need to try to get
-                // multiple packets until they are empty.
-                while let Some((buffer, _, timestamp)) = self.encoder.read() {
-                    if let Some(transport) = self.transport.upgrade() {
-                        if let Err(e) = transport.send(Buffer {
-                            data: Buffer::<()>::copy_from_slice(buffer),
-                            ty: BufferType::Partial,
-                            stream: StreamType::Audio,
-                            timestamp,
-                        }) {
-                            log::warn!("audio send packet to transport failed, err={:?}", e);
+        if let Some(level) = self.meter.process(frame) {
+            self.observer.on_audio_level(level);
+        }
 
-                            return false;
-                        }
-                    } else {
-                        log::warn!("transport weak upgrade failed, maybe is drop");
+        // The disabled audio is still metered, so that the application can tell the
+        // user that they are talking while muted.
+        let enabled = match self.transport.upgrade() {
+            Some(transport) => match self.gate.update(&transport) {
+                Ok(it) => it,
+                Err(e) => {
+                    tracing::warn!("audio send track notice to transport failed, err={:?}", e);
 
-                        return false;
+                    return self.stop(CloseReason::TransportError(Arc::new(e)));
+                }
+            },
+            None => {
+                tracing::warn!("transport weak upgrade failed, maybe is drop");
+
+                return self.stop(CloseReason::Closed);
+            }
+        };
+
+        match self
+            .silence
+            .as_mut()
+            .filter(|_| enabled)
+            .and_then(|it| it.process(frame))
+        {
+            Some(true) => {
+                tracing::info!("audio sender is silent, pause the audio");
+
+                // The receiver is notified, otherwise it cannot tell the silence from the
+                // packet loss.
+                if let Some(transport) = self.transport.upgrade() {
+                    if let Err(e) = transport.send(Buffer {
+                        data: Buffer::<()>::with_capacity(0),
+                        ty: BufferType::Silence,
+                        stream: StreamType::Audio,
+                        timestamp: 0,
+                        rendition: 0,
+                    }) {
+                        tracing::warn!("audio send silence to transport failed, err={:?}", e);
+
+                        return self.stop(CloseReason::TransportError(Arc::new(e)));
                     }
                 }
+
+                self.observer.on_audio_paused();
             }
-        } else {
-            log::warn!("audio encoder update frame failed");
+            Some(false) => {
+                tracing::info!("audio sender is no longer silent, resume the audio");
 
-            return false;
+                self.observer.on_audio_resumed();
+            }
+            None => (),
         }
 
-        if self.sink.audio(frame) {
-            true
-        } else {
-            log::warn!("audio sink on frame return false");
+        if enabled && !self.silence.as_ref().is_some_and(|it| it.is_paused()) {
+            let encode = tracing::trace_span!("encode", stream = "audio").entered();
+            #[cfg(feature = "metrics")]
+            let started = Instant::now();
+
+            if self.encoder.update(&frame) {
+                // Push the audio and video frames into the encoder.
+                if let Err(e) = self.encoder.encode() {
+                    tracing::error!("audio encode error={:?}", e);
+
+                    return self.stop(CloseReason::EncoderError(Arc::new(e)));
+                } else {
+                    drop(encode);
+
+                    #[cfg(feature = "metrics")]
+                    Metrics::global().encoded(StreamType::Audio, started.elapsed());
+
+                    // Try to get the encoded data packets. The audio and video frames
+                    // do not correspond to the data
+                    // packets one by one, so you need to try to get
+                    // multiple packets until they are empty.
+                    while let Some((buffer, _, timestamp)) = self.encoder.read() {
+                        #[cfg(feature = "metrics")]
+                        Metrics::global().sent(StreamType::Audio, buffer.len());
+
+                        if let Some(transport) = self.transport.upgrade() {
+                            if let Err(e) = transport.send(Buffer {
+                                data: Buffer::<()>::copy_from_slice(buffer),
+                                ty: BufferType::Partial,
+                                stream: StreamType::Audio,
+                                timestamp,
+                                rendition: 0,
+                            }) {
+                                tracing::warn!(
+                                    "audio send packet to transport failed, err={:?}",
+                                    e
+                                );
+
+                                return self.stop(CloseReason::TransportError(Arc::new(e)));
+                            }
+                        } else {
+                            tracing::warn!("transport weak upgrade failed, maybe is drop");
+
+                            return self.stop(CloseReason::Closed);
+                        }
+
+                        // The monitor is local, so it does not end the stream, the sink gets
+                        // the captured audio instead.
+                        if let Some(decoder) = self.monitor.as_mut() {
+                            if let Err(e) = decoder.decode(buffer, timestamp) {
+                                tracing::warn!(
+                                    "audio monitor decode error, the monitor is disabled, err={:?}",
+                                    e
+                                );
+
+                                self.monitor = None;
+                            }
+                        }
+                    }
+                }
+            } else {
+                tracing::warn!("audio encoder update frame failed");
+
+                return self.stop(CloseReason::Closed);
+            }
+        }
+
+        let Some(decoder) = self.monitor.as_mut() else {
+            return if self.sink.audio(frame) {
+                true
+            } else {
+                tracing::warn!("audio sink on frame return false");
+
+                self.stop(CloseReason::Closed)
+            };
+        };
+
+        while let Some(frame) = decoder.read() {
+            if !self.sink.audio(frame) {
+                tracing::warn!("audio sink on frame return false");
 
-            false
+                return self.stop(CloseReason::Closed);
+            }
         }
+
+        true
+    }
+
+    fn changed(&mut self, source: &Source) {
+        tracing::info!("audio sender device changed, source={:?}", source);
+
+        self.observer.audio_device_changed(source);
     }
 
     fn close(&mut self) {
-        log::info!("audio sender is closed");
+        tracing::info!("audio sender is closed");
 
-        (self.callback)();
+        // The capture stopped by itself if the consumer did not stop it.
+        (self.callback)(self.reason.take().unwrap_or(CloseReason::CaptureStopped));
     }
 }
 
+/// The consumers of the tracks whose frames are pushed by the application
+/// instead of the capture, see [SenderInput::Push].
+#[derive(Default)]
+struct PushedTracks {
+    video: Mutex<Option<Box<dyn FrameConsumer<Frame = VideoFrame>>>>,
+    audio: Mutex<Option<Box<dyn FrameConsumer<Frame = AudioFrame>>>>,
+}
+
 /// Screen casting sender.
 pub struct HylaranaSender {
-    callback: Arc<dyn Fn() + Send + Sync + 'static>,
+    callback: Arc<dyn Fn(CloseReason) + Send + Sync + 'static>,
+    events: Arc<StreamEvents>,
     description: MediaStreamDescription,
     transport: Arc<TransportSender>,
+    snapshot: Option<Arc<Snapshot>>,
+    options: HylaranaSenderOptions,
+    preview: Arc<PreviewSlot>,
+    previews: AtomicU32,
+    privacy: Arc<PrivacySlot>,
+    switches: Arc<TrackSwitches>,
+    observer: Arc<dyn MediaStreamObserver>,
+    pushed: PushedTracks,
     #[allow(unused)]
     capture: Capture,
+    /// The capture of the video source if the failover applies to it.
+    #[allow(unused)]
+    video_capture: Option<VideoFailover>,
+    #[allow(unused)]
+    power: Option<PowerGuard>,
 }
 
 impl HylaranaSender {
@@ -310,67 +1507,237 @@ impl HylaranaSender {
         options: &HylaranaSenderOptions,
         sink: S,
         observer: O,
-    ) -> Result<Self, HylaranaSenderError>
+    ) -> Result<Self, HylaranaError>
     where
         S: MediaStreamSink + 'static,
         O: MediaStreamObserver + 'static,
     {
-        log::info!("create sender");
+        tracing::info!("create sender");
+
+        let mut options = options.clone();
+        let mode = options.apply_mode()?;
+        let options = &options.capped()?;
+
+        // The media files, the test pattern and the black frames of the failover
+        // are decoded or drawn into the system memory, which only the software
+        // encoders, vaapi and VideoToolbox upload.
+        if let Some(video) = &options.media.video {
+            if options.input == SenderInput::Capture
+                && (matches!(video.source.kind, SourceType::Url | SourceType::TestPattern)
+                    || (options.failover == SourceFailover::Placeholder
+                        && options.failover.applies_to(video.source.kind))
+                    || (video.options.protected_content == ProtectedContentPolicy::Placeholder
+                        && video.source.kind == SourceType::Screen))
+                && !accepts_software_frames(video.options.codec)
+            {
+                return Err(HylaranaError::SoftwareFramesNotSupported(
+                    video.options.codec,
+                ));
+            }
+
+            // Only the screen captures of windows report the protected content.
+            if cfg!(not(target_os = "windows"))
+                && options.input == SenderInput::Capture
+                && video.source.kind == SourceType::Screen
+                && video.options.protected_content != ProtectedContentPolicy::Stream
+            {
+                return Err(HylaranaError::ProtectedContentNotSupported);
+            }
+        }
+
+        check_privacy(options.privacy.as_ref())?;
+
+        // The main video takes the rendition 0.
+        if let Some(video) = &options.media.video {
+            if video.options.simulcast.len() >= MAX_RENDITIONS as usize {
+                return Err(HylaranaError::TooManyRenditions(
+                    video.options.simulcast.len(),
+                ));
+            }
+        }
+
+        // Every sender has a session token unless one is given, the token is
+        // published in the description, so only the receivers that got the
+        // description can connect to the sender.
+        let session = options
+            .transport
+            .session
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+
+        // The stream is always encrypted, the passphrase is published together
+        // with the token.
+        let passphrase = options
+            .transport
+            .passphrase
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
 
-        let transport = Arc::new(TransportSender::new(bind, options.transport.clone())?);
+        let transport = Arc::new(
+            TransportSender::new(
+                bind,
+                TransportOptions {
+                    session: Some(session.clone()),
+                    passphrase: Some(passphrase.clone()),
+                    ..options.transport.clone()
+                },
+            )
+            .map_err(TransportError::from)?,
+        );
 
+        diagnostics::register_sender(&transport);
+
+        let events = Arc::new(StreamEvents::default());
+        transport.set_connected_callback({
+            let events = events.clone();
+
+            move |addr| events.emit(StreamEvent::Connected(addr))
+        });
+
+        transport.set_viewers_callback({
+            let events = events.clone();
+            let viewers = AtomicU32::new(0);
+
+            move |count| {
+                if count > viewers.swap(count, Ordering::Relaxed) {
+                    events.emit(StreamEvent::ViewerJoined(count));
+                } else {
+                    events.emit(StreamEvent::ViewerLeft(count));
+                }
+            }
+        });
+
+        if let Some(allow_list) = options.allow_list.clone() {
+            transport
+                .set_access_control(move |addr| allow_list.contains(&addr.ip().to_canonical()));
+        }
+
+        let observer = Arc::new(EventObserver {
+            events: events.clone(),
+            observer,
+        });
         let callback = {
             let working = AtomicBool::new(true);
+            let observer = observer.clone();
 
-            Arc::new(move || {
+            Arc::new(move |reason: CloseReason| {
                 if working.load(Ordering::Relaxed) {
                     working.store(false, Ordering::Relaxed);
-                    observer.close();
 
-                    log::info!("sender is closed");
+                    tracing::info!("sender is closed, reason={}", reason);
+
+                    observer.close(reason);
                 }
             })
         };
 
+        let snapshot = options.media.video.as_ref().map(|_| {
+            Arc::new(Snapshot::new(
+                #[cfg(target_os = "windows")]
+                get_direct3d(),
+            ))
+        });
+
+        let preview = Arc::new(PreviewSlot::new(
+            #[cfg(target_os = "windows")]
+            get_direct3d(),
+        ));
+        let privacy = Arc::new(Mutex::new(options.privacy.clone()));
+        let switches = Arc::new(TrackSwitches::default());
+        let pushed = PushedTracks::default();
+        let mut video_capture = None;
         let capture_options = {
             let sink = Arc::new(sink);
+            let overlay = options.overlay.as_ref();
+            let push = options.input == SenderInput::Push;
+            let failover = options.failover;
             let mut opt = CaptureOptions::default();
 
             if let Some(HylaranaSenderTrackOptions { source, options }) = &options.media.audio {
-                opt.audio = Some(SourceCaptureOptions {
-                    consumer: AudioSender::new(
-                        &options,
-                        &transport,
-                        sink.clone(),
-                        callback.clone(),
-                    )?,
-                    description: AudioCaptureSourceDescription {
-                        sample_rate: options.sample_rate as u32,
-                        source: source.clone(),
-                    },
-                });
-            }
+                let consumer = AudioSender::new(
+                    &options,
+                    &transport,
+                    switches.clone(),
+                    sink.clone(),
+                    observer.clone(),
+                    callback.clone(),
+                )?;
 
-            if let Some(HylaranaSenderTrackOptions { source, options }) = &options.media.video {
-                opt.video = Some(SourceCaptureOptions {
-                    consumer: VideoSender::new(
-                        options,
-                        &transport,
-                        sink.clone(),
-                        callback.clone(),
-                    )?,
-                    description: VideoCaptureSourceDescription {
-                        hardware: CodecType::from(options.codec).is_hardware(),
-                        fps: options.frame_rate,
-                        size: Size {
-                            width: options.width,
-                            height: options.height,
+                if push {
+                    pushed.audio.lock().replace(Box::new(consumer));
+                } else {
+                    opt.audio = Some(SourceCaptureOptions {
+                        consumer,
+                        description: AudioCaptureSourceDescription {
+                            sample_rate: options.sample_rate as u32,
+                            channels: options.channels(),
+                            source: source.clone(),
                         },
-                        source: source.clone(),
-                        #[cfg(target_os = "windows")]
-                        direct3d: get_direct3d(),
+                    });
+                }
+            }
+
+            if let (Some(HylaranaSenderTrackOptions { source, options }), Some(snapshot)) =
+                (&options.media.video, &snapshot)
+            {
+                let consumer = VideoSender::new(
+                    options,
+                    &transport,
+                    (!push && source.kind == SourceType::Screen)
+                        .then(|| {
+                            PrivacyMask::new(
+                                source.clone(),
+                                privacy.clone(),
+                                #[cfg(target_os = "windows")]
+                                get_direct3d(),
+                            )
+                        })
+                        .transpose()
+                        .map_err(capture::CaptureError::from)?,
+                    overlay,
+                    snapshot.clone(),
+                    preview.clone(),
+                    switches.clone(),
+                    sink.clone(),
+                    observer.clone(),
+                    callback.clone(),
+                )?;
+
+                let description = VideoCaptureSourceDescription {
+                    hardware: CodecType::from(options.codec).is_hardware(),
+                    fps: options.frame_rate,
+                    size: Size {
+                        width: options.width,
+                        height: options.height,
                     },
-                });
+                    source: source.clone(),
+                    exclude_windows: options.exclude_windows.clone(),
+                    #[cfg(target_os = "windows")]
+                    direct3d: get_direct3d(),
+                    // WGC does not report the protected content, the desktop duplication
+                    // does.
+                    #[cfg(target_os = "windows")]
+                    backend: (source.kind == SourceType::Screen
+                        && options.protected_content != ProtectedContentPolicy::Stream)
+                        .then_some(ScreenCaptureBackend::Dxgi),
+                };
+
+                if push {
+                    pushed.video.lock().replace(Box::new(consumer));
+                } else if failover.applies_to(source.kind) {
+                    video_capture = Some(VideoFailover::start(
+                        failover,
+                        description,
+                        consumer,
+                        observer.clone(),
+                        callback.clone(),
+                    )?);
+                } else {
+                    opt.video = Some(SourceCaptureOptions {
+                        consumer,
+                        description,
+                    });
+                }
             }
 
             opt
@@ -385,6 +1752,8 @@ impl HylaranaSender {
                     format: VideoFormat::NV12,
                     fps: it.options.frame_rate,
                     bit_rate: it.options.bit_rate,
+                    simulcast: it.options.simulcast.len() as u8,
+                    codec: VideoCodec::HEVC,
                     size: Size {
                         width: it.options.width,
                         height: it.options.height,
@@ -397,17 +1766,32 @@ impl HylaranaSender {
                 .map(|it| MediaAudioStreamDescription {
                     sample_rate: it.options.sample_rate,
                     bit_rate: it.options.bit_rate,
-                    channels: 2,
+                    channels: it.options.channels(),
                 }),
+            protocol: PROTOCOL_VERSION,
+            token: Some(session),
+            passphrase: Some(passphrase),
+            mode,
         };
 
-        log::info!("sender description={:?}", description);
+        tracing::info!("sender description={:?}", description);
 
         Ok(Self {
             capture: Capture::start(capture_options)?,
+            video_capture,
+            power: (!options.allow_display_sleep).then(|| PowerGuard::acquire("Hylarana sender")),
             description,
+            snapshot,
+            options: options.clone(),
+            previews: AtomicU32::new(0),
+            preview,
+            privacy,
+            switches,
+            observer,
             transport,
             callback,
+            pushed,
+            events,
         })
     }
 
@@ -420,10 +1804,226 @@ impl HylaranaSender {
     pub fn local_addr(&self) -> SocketAddr {
         self.transport.local_addr()
     }
+
+    /// Subscribe to the events of the sender, the events are also passed to
+    /// the observer, the channel can be polled in a message loop instead of
+    /// implementing the observer.
+    pub fn events(&self) -> Receiver<StreamEvent> {
+        self.events.subscribe()
+    }
+
+    /// Render the outgoing video to a window before it is encoded, such as a
+    /// small self view, this replaces the previous preview. The preview is
+    /// rendered on its own thread on the device of the capture, the hardware
+    /// frames are only copied on the gpu.
+    pub fn create_preview<T>(
+        &self,
+        window: T,
+        size: Size,
+    ) -> Result<HylaranaSenderPreview, HylaranaError>
+    where
+        T: Into<SurfaceTarget<'static>>,
+    {
+        if self.options.media.video.is_none() {
+            return Err(HylaranaError::NotFoundVideo);
+        }
+
+        let render = VideoRender::with_device(
+            VideoRenderOptionsBuilder::new(VideoRenderSurfaceOptions { window, size })
+                .from_sender(&self.options)
+                .build(),
+            #[cfg(target_os = "windows")]
+            self.preview.direct3d.clone(),
+        )?;
+
+        // The frames are copied on the capture thread and rendered on the thread of
+        // the preview with the same immediate context.
+        #[cfg(target_os = "windows")]
+        self.preview.direct3d.set_multithread_protected(true)?;
+
+        let id = self.previews.fetch_add(1, Ordering::Relaxed);
+        self.preview.render.lock().replace((id, render));
+        self.preview.active.store(true, Ordering::Relaxed);
+
+        let slot = self.preview.clone();
+        thread::Builder::new()
+            .name("HylaranaSenderPreviewThread".to_string())
+            .spawn(move || slot.run(id))
+            .map_err(|e| {
+                HylaranaError::RenderError(RenderError {
+                    kind: RenderErrorKind::Video,
+                    source: Box::new(e),
+                })
+            })?;
+
+        tracing::info!("sender create preview, size={:?}", size);
+
+        Ok(HylaranaSenderPreview {
+            slot: self.preview.clone(),
+            id,
+        })
+    }
+
+    /// Replace the windows that are hidden in the outgoing video, `None` stops
+    /// hiding the windows, see [HylaranaSenderOptions::privacy]. This takes
+    /// effect with the next frame.
+    ///
+    /// The windows can only be hidden on Windows and macOS, elsewhere this
+    /// fails with [HylaranaError::PrivacyNotSupported] rather than sending
+    /// the windows.
+    pub fn set_privacy(&self, options: Option<PrivacyOptions>) -> Result<(), HylaranaError> {
+        tracing::info!("sender set privacy, options={:?}", options);
+
+        check_privacy(options.as_ref())?;
+        *self.privacy.lock() = options;
+
+        Ok(())
+    }
+
+    /// Returns the actual usage of the bandwidth, compare the send rate with
+    /// the cap to see how much of it is used.
+    pub fn get_bandwidth_stats(&self) -> BandwidthStats {
+        BandwidthStats {
+            cap: self.options.bandwidth_cap,
+            send_rate: self.transport.get_send_rate(),
+            video_bit_rate: self
+                .options
+                .media
+                .video
+                .as_ref()
+                .map(|it| it.options.bit_rate),
+            pacing: self.transport.get_pacing_stats(),
+        }
+    }
+
+    /// The statistics of the connections to the receivers, such as the loss
+    /// and the round trip time of their links, see also
+    /// [HylaranaSender::get_bandwidth_stats]. There is one entry for each
+    /// connection, and none with the loopback strategy.
+    pub fn connections(&self) -> Vec<ConnectionStats> {
+        self.transport.connections()
+    }
+
+    /// The number of the receivers of the stream, see
+    /// [StreamEvent::ViewerJoined].
+    pub fn get_viewers(&self) -> u32 {
+        self.transport.get_viewers()
+    }
+
+    /// Decide whether a receiver may connect, the callback is called with the
+    /// address of the receiver before it is accepted, this replaces the
+    /// `allow_list` of the options.
+    pub fn set_access_control<F>(&self, callback: F)
+    where
+        F: Fn(SocketAddr) -> bool + Send + Sync + 'static,
+    {
+        self.transport.set_access_control(callback);
+    }
+
+    /// Enable or disable a track without renegotiating the session, the
+    /// disabled track is still captured and passed to the sink and the
+    /// preview, but it is not sent, the receiver is notified of the change.
+    pub fn set_track_enabled(
+        &self,
+        stream: StreamType,
+        enabled: bool,
+    ) -> Result<(), HylaranaError> {
+        match stream {
+            StreamType::Video if self.options.media.video.is_none() => {
+                return Err(HylaranaError::NotFoundVideo);
+            }
+            StreamType::Audio if self.options.media.audio.is_none() => {
+                return Err(HylaranaError::NotFoundAudio);
+            }
+            _ => (),
+        }
+
+        if self.switches.get(stream).swap(enabled, Ordering::Relaxed) == enabled {
+            return Ok(());
+        }
+
+        tracing::info!(
+            "sender set track enabled, stream={:?}, enabled={}",
+            stream,
+            enabled
+        );
+
+        send_track_notice(&self.transport, stream, enabled).map_err(TransportError::from)?;
+        self.observer.on_track_changed(stream, enabled);
+
+        Ok(())
+    }
+
+    /// Push a video frame to the sender whose input is [SenderInput::Push],
+    /// the frame goes through the overlays, the preview and the encoder like a
+    /// captured frame. The frame must have the size of the video options, the
+    /// hardware frames must come from the device of the encoder. The frames in
+    /// the system memory are NV12 or I420, and only the software encoders,
+    /// vaapi and VideoToolbox take them, the other hardware encoders return
+    /// [HylaranaError::SoftwareFramesNotSupported]. The timestamp of the frame
+    /// is in microseconds of [crate::clock::now], the time of the push is used
+    /// if it is 0.
+    pub fn push_video_frame(&self, frame: &VideoFrame) -> Result<(), HylaranaError> {
+        let Some(video) = &self.options.media.video else {
+            return Err(HylaranaError::NotFoundVideo);
+        };
+
+        if frame.sub_format == VideoSubFormat::SW && !accepts_software_frames(video.options.codec) {
+            return Err(HylaranaError::SoftwareFramesNotSupported(
+                video.options.codec,
+            ));
+        }
+
+        self.push(&self.pushed.video, frame)
+    }
+
+    /// Push an audio frame to the sender whose input is [SenderInput::Push],
+    /// the frame is interleaved PCM with the sample rate and the channels of
+    /// the audio options.
+    pub fn push_audio_frame(&self, frame: &AudioFrame) -> Result<(), HylaranaError> {
+        if self.options.media.audio.is_none() {
+            return Err(HylaranaError::NotFoundAudio);
+        }
+
+        self.push(&self.pushed.audio, frame)
+    }
+
+    // The consumer is closed and removed once it stops the stream, the same as
+    // the capture does, the observer is told the reason.
+    fn push<T>(
+        &self,
+        consumer: &Mutex<Option<Box<dyn FrameConsumer<Frame = T>>>>,
+        frame: &T,
+    ) -> Result<(), HylaranaError> {
+        if self.options.input != SenderInput::Push {
+            return Err(HylaranaError::PushDisabled);
+        }
+
+        let mut consumer = consumer.lock();
+        let Some(it) = consumer.as_mut() else {
+            return Err(HylaranaError::StreamClosed);
+        };
+
+        if !it.sink(frame) {
+            it.close();
+            consumer.take();
+        }
+
+        Ok(())
+    }
+
+    /// Take a snapshot of the outgoing video, the latest captured frame is
+    /// converted to RGBA, the overlays are included.
+    pub fn snapshot(&self) -> Result<RgbaImage, SnapshotError> {
+        self.snapshot
+            .as_ref()
+            .ok_or_else(|| SnapshotError::NotFoundVideo)?
+            .take()
+    }
 }
 
 impl Drop for HylaranaSender {
     fn drop(&mut self) {
-        (self.callback)();
+        (self.callback)(CloseReason::Closed);
     }
 }
@@ -0,0 +1,485 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use codec::{VideoEncoder, VideoEncoderError, VideoEncoderSettings};
+use common::{
+    Size,
+    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+};
+
+use resample::{VideoScaler, VideoScalerError};
+use thiserror::Error;
+use transport::{MAX_RENDITIONS, RenditionSubscription};
+
+#[cfg(target_os = "windows")]
+use common::win32::{Direct3DDevice, d3d_texture_borrowed_raw, windows::core::Interface};
+
+#[cfg(target_os = "windows")]
+use resample::win32::{Resource, VideoResampler, VideoResamplerOptions};
+
+#[cfg(target_os = "macos")]
+use std::ptr::null;
+
+#[cfg(target_os = "macos")]
+use super::overlay::{OverlayError, lock_pixel_buffer};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A lower rendition of the video that is encoded next to the main video,
+/// so that the receiver on a poor network can play a smaller stream, see
+/// `VideoOptions::simulcast`. The other encoder options are the same as the
+/// main video. Each receiver plays the rendition of its own network, the
+/// renditions that no receiver plays are not encoded.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct VideoRendition {
+    pub width: u32,
+    pub height: u32,
+    pub bit_rate: u64,
+}
+
+/// Which rendition of the simulcast video the receiver plays, see
+/// `HylaranaReceiverOptions::rendition`. The rendition 0 is the main video,
+/// the higher ids are the renditions of `VideoOptions::simulcast` in order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum RenditionSelection {
+    /// Starts with the main video, steps down to a lower rendition when the
+    /// video packets are lost, and back up after the loss is gone.
+    #[default]
+    Auto,
+    /// Always plays the rendition, the last rendition of the stream is played
+    /// if the stream has fewer renditions.
+    Fixed(u8),
+}
+
+#[derive(Debug, Error)]
+enum ScaleError {
+    #[error("the frame can not be scaled, format={0:?}, sub_format={1:?}")]
+    UnsupportedFrame(VideoFormat, VideoSubFormat),
+    #[error(transparent)]
+    VideoScalerError(#[from] VideoScalerError),
+    #[cfg(target_os = "macos")]
+    #[error(transparent)]
+    OverlayError(#[from] OverlayError),
+    #[cfg(target_os = "windows")]
+    #[error(transparent)]
+    Win32Error(#[from] common::win32::windows::core::Error),
+}
+
+enum ScalerKind {
+    Software(VideoScaler),
+    /// The output frame points to the output texture of the resampler.
+    #[cfg(target_os = "windows")]
+    Direct3D(VideoResampler, VideoFrame),
+}
+
+/// Scales the captured frames to the size of a rendition, the output is
+/// NV12 in the same kind of memory as the frames where it is supported.
+struct Scaler {
+    /// The frames of another kind, format or size need another scaler.
+    input: (VideoSubFormat, VideoFormat, u32, u32),
+    kind: ScalerKind,
+}
+
+impl Scaler {
+    fn new(
+        frame: &VideoFrame,
+        size: Size,
+        #[cfg(target_os = "windows")] direct3d: &Direct3DDevice,
+    ) -> Result<Self, ScaleError> {
+        let input = Size {
+            width: frame.width,
+            height: frame.height,
+        };
+
+        let kind = match (frame.sub_format, frame.format) {
+            (VideoSubFormat::SW, format) => {
+                ScalerKind::Software(VideoScaler::new(format, input, size)?)
+            }
+            // The pixel buffer is scaled from the system memory while it is locked.
+            #[cfg(target_os = "macos")]
+            (VideoSubFormat::CvPixelBufferRef, VideoFormat::BGRA) => {
+                ScalerKind::Software(VideoScaler::new(VideoFormat::BGRA, input, size)?)
+            }
+            #[cfg(target_os = "windows")]
+            (VideoSubFormat::D3D11, format) => ScalerKind::Direct3D(
+                VideoResampler::new(VideoResamplerOptions {
+                    direct3d: direct3d.clone(),
+                    input: Resource::Default(format, input),
+                    output: Resource::Default(VideoFormat::NV12, size),
+                })?,
+                VideoFrame {
+                    format: VideoFormat::NV12,
+                    sub_format: VideoSubFormat::D3D11,
+                    width: size.width,
+                    height: size.height,
+                    data: [std::ptr::null(); 3],
+                    linesize: [0; 3],
+                    timestamp: 0,
+                },
+            ),
+            (sub_format, format) => return Err(ScaleError::UnsupportedFrame(format, sub_format)),
+        };
+
+        Ok(Self {
+            input: (frame.sub_format, frame.format, frame.width, frame.height),
+            kind,
+        })
+    }
+
+    fn is_input(&self, frame: &VideoFrame) -> bool {
+        self.input == (frame.sub_format, frame.format, frame.width, frame.height)
+    }
+
+    fn process(&mut self, frame: &VideoFrame) -> Result<&VideoFrame, ScaleError> {
+        match &mut self.kind {
+            ScalerKind::Software(scaler) => {
+                #[cfg(target_os = "macos")]
+                if frame.sub_format == VideoSubFormat::CvPixelBufferRef {
+                    let mut result = Ok(());
+                    lock_pixel_buffer(frame, |base, stride| {
+                        result = scaler
+                            .process(&VideoFrame {
+                                format: VideoFormat::BGRA,
+                                sub_format: VideoSubFormat::SW,
+                                width: frame.width,
+                                height: frame.height,
+                                data: [base as *const _, null(), null()],
+                                linesize: [stride as u32, 0, 0],
+                                timestamp: frame.timestamp,
+                            })
+                            .map(|_| ());
+                    })?;
+
+                    result?;
+
+                    return Ok(scaler.frame());
+                }
+
+                Ok(scaler.process(frame)?)
+            }
+            #[cfg(target_os = "windows")]
+            ScalerKind::Direct3D(resampler, output) => {
+                let raw = frame.data[0] as *mut _;
+                let Some(texture) = d3d_texture_borrowed_raw(&raw) else {
+                    return Err(ScaleError::UnsupportedFrame(frame.format, frame.sub_format));
+                };
+
+                let view = resampler.create_input_view(texture, frame.data[1] as u32)?;
+                resampler.process(Some(view))?;
+
+                output.data[0] = resampler.get_output().as_raw();
+                output.timestamp = frame.timestamp;
+
+                Ok(output)
+            }
+        }
+    }
+}
+
+/// A lower rendition of the video, the frames are scaled and encoded on the
+/// capture thread after the main video. A frame that can not be scaled is
+/// skipped for the rendition, the main video is still sent.
+pub(crate) struct Rendition {
+    id: u8,
+    size: Size,
+    encoder: VideoEncoder,
+    /// Nothing is scaled or encoded while no receiver plays the rendition.
+    wanted: bool,
+    /// Created for the first frame, and again when the frames change.
+    scaler: Option<Scaler>,
+    #[cfg(target_os = "windows")]
+    direct3d: Direct3DDevice,
+}
+
+impl Rendition {
+    /// The ids of the lower renditions start from 1, 0 is the main video.
+    pub fn new(
+        id: u8,
+        options: &VideoRendition,
+        settings: &VideoEncoderSettings,
+    ) -> Result<Self, VideoEncoderError> {
+        Ok(Self {
+            encoder: VideoEncoder::new(VideoEncoderSettings {
+                width: options.width,
+                height: options.height,
+                bit_rate: options.bit_rate,
+                ..settings.clone()
+            })?,
+            size: Size {
+                width: options.width,
+                height: options.height,
+            },
+            scaler: None,
+            wanted: true,
+            #[cfg(target_os = "windows")]
+            direct3d: super::util::get_direct3d(),
+            id,
+        })
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// The rendition starts again from a key frame when it is wanted again,
+    /// the receiver switches to it there.
+    pub fn set_wanted(&mut self, wanted: bool) {
+        if wanted && !self.wanted {
+            self.encoder.request_key_frame();
+        }
+
+        self.wanted = wanted;
+    }
+
+    /// Scales the frame and pushes it into the encoder, the encoded packets
+    /// are then taken with [Rendition::read]. Nothing is done while the
+    /// rendition is not wanted, see [Rendition::set_wanted].
+    pub fn encode(&mut self, frame: &VideoFrame) -> Result<(), VideoEncoderError> {
+        if !self.wanted {
+            return Ok(());
+        }
+
+        if !self
+            .scaler
+            .as_ref()
+            .map(|it| it.is_input(frame))
+            .unwrap_or(false)
+        {
+            self.scaler = match Scaler::new(
+                frame,
+                self.size,
+                #[cfg(target_os = "windows")]
+                &self.direct3d,
+            ) {
+                Ok(it) => Some(it),
+                Err(e) => {
+                    tracing::warn!(
+                        "video sender failed to create the scaler of the rendition, id={}, err={:?}",
+                        self.id,
+                        e
+                    );
+
+                    return Ok(());
+                }
+            };
+        }
+
+        let Some(scaler) = self.scaler.as_mut() else {
+            return Ok(());
+        };
+
+        let frame = match scaler.process(frame) {
+            Ok(it) => it,
+            Err(e) => {
+                tracing::warn!(
+                    "video sender failed to scale the rendition, id={}, err={:?}",
+                    self.id,
+                    e
+                );
+
+                return Ok(());
+            }
+        };
+
+        if !self.encoder.update(frame) {
+            tracing::warn!(
+                "video rendition encoder update frame failed, id={}",
+                self.id
+            );
+
+            return Ok(());
+        }
+
+        self.encoder.encode()
+    }
+
+    pub fn read(&mut self) -> Option<(&[u8], i32, u64)> {
+        self.encoder.read()
+    }
+}
+
+/// Picks the rendition of the receiver from its own loss of the video
+/// packets, the sender is told the rendition with the heartbeats, see
+/// [RenditionSubscription].
+pub(crate) struct RenditionSelector {
+    selection: RenditionSelection,
+    subscription: RenditionSubscription,
+    /// The number of the renditions of the stream, including the main video.
+    renditions: u8,
+    /// The times of the recent losses of the video.
+    losses: VecDeque<Instant>,
+    /// The last loss or the last switch, the rendition is raised after a
+    /// while without losses.
+    stable: Instant,
+}
+
+impl RenditionSelector {
+    /// The rendition is lowered when the video is lost this many times within
+    /// the window, a single loss is not worth a switch.
+    const LOSSES: usize = 2;
+    const LOSS_WINDOW: Duration = Duration::from_secs(5);
+
+    /// Raising the rendition is tried late, the higher rendition may bring
+    /// the loss back.
+    const STEP_UP_AFTER: Duration = Duration::from_secs(15);
+
+    pub fn new(selection: RenditionSelection) -> Self {
+        Self {
+            subscription: RenditionSubscription::default(),
+            losses: VecDeque::with_capacity(Self::LOSSES),
+            stable: Instant::now(),
+            renditions: 1,
+            selection,
+        }
+    }
+
+    /// Follows the transport of a new connection, the stream may have another
+    /// number of renditions. Returns the rendition of the connection.
+    pub fn attach(&mut self, subscription: RenditionSubscription, renditions: u8) -> u8 {
+        self.renditions = renditions.clamp(1, MAX_RENDITIONS);
+        self.subscription = subscription;
+        self.losses.clear();
+        self.stable = Instant::now();
+
+        let rendition = match self.selection {
+            RenditionSelection::Auto => 0,
+            RenditionSelection::Fixed(it) => it.min(self.renditions - 1),
+        };
+
+        self.subscription.set(rendition);
+
+        rendition
+    }
+
+    /// Returns the new rendition if the loss lowered it.
+    pub fn lost(&mut self) -> Option<u8> {
+        if self.selection != RenditionSelection::Auto {
+            return None;
+        }
+
+        let now = Instant::now();
+        self.stable = now;
+
+        self.losses.push_back(now);
+        while self.losses.len() > Self::LOSSES
+            || self
+                .losses
+                .front()
+                .map(|it| now.duration_since(*it) > Self::LOSS_WINDOW)
+                .unwrap_or(false)
+        {
+            self.losses.pop_front();
+        }
+
+        let rendition = self.subscription.get();
+        if self.losses.len() < Self::LOSSES || rendition + 1 >= self.renditions {
+            return None;
+        }
+
+        self.losses.clear();
+        self.switch(rendition + 1)
+    }
+
+    /// Called for the received video, returns the new rendition if it was
+    /// raised.
+    pub fn update(&mut self) -> Option<u8> {
+        if self.selection != RenditionSelection::Auto || self.stable.elapsed() < Self::STEP_UP_AFTER
+        {
+            return None;
+        }
+
+        self.stable = Instant::now();
+
+        match self.subscription.get() {
+            0 => None,
+            it => self.switch(it - 1),
+        }
+    }
+
+    fn switch(&mut self, rendition: u8) -> Option<u8> {
+        tracing::info!(
+            "receiver rendition changed, from={}, to={}",
+            self.subscription.get(),
+            rendition
+        );
+
+        self.subscription.set(rendition);
+
+        Some(rendition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lose(selector: &mut RenditionSelector, times: usize) -> Option<u8> {
+        (0..times).fold(None, |_, _| selector.lost())
+    }
+
+    #[test]
+    fn fixed_selection_is_clamped_and_never_changes() {
+        let mut selector = RenditionSelector::new(RenditionSelection::Fixed(5));
+        let subscription = RenditionSubscription::default();
+
+        assert_eq!(selector.attach(subscription.clone(), 2), 1);
+        assert_eq!(subscription.get(), 1);
+        assert_eq!(lose(&mut selector, RenditionSelector::LOSSES * 2), None);
+        assert_eq!(selector.update(), None);
+    }
+
+    #[test]
+    fn auto_selection_steps_down_on_repeated_losses() {
+        let mut selector = RenditionSelector::new(RenditionSelection::Auto);
+        let subscription = RenditionSubscription::default();
+
+        assert_eq!(selector.attach(subscription.clone(), 3), 0);
+
+        // A single loss is not worth a switch.
+        assert_eq!(lose(&mut selector, RenditionSelector::LOSSES - 1), None);
+        assert_eq!(selector.lost(), Some(1));
+        assert_eq!(lose(&mut selector, RenditionSelector::LOSSES), Some(2));
+        assert_eq!(subscription.get(), 2);
+
+        // There is no rendition below the last one.
+        assert_eq!(lose(&mut selector, RenditionSelector::LOSSES), None);
+        assert_eq!(subscription.get(), 2);
+    }
+
+    #[test]
+    fn auto_selection_steps_up_after_a_while_without_losses() {
+        let mut selector = RenditionSelector::new(RenditionSelection::Auto);
+        let subscription = RenditionSubscription::default();
+
+        selector.attach(subscription.clone(), 3);
+        lose(&mut selector, RenditionSelector::LOSSES);
+        assert_eq!(subscription.get(), 1);
+
+        assert_eq!(selector.update(), None);
+
+        selector.stable = Instant::now() - RenditionSelector::STEP_UP_AFTER;
+        assert_eq!(selector.update(), Some(0));
+        assert_eq!(subscription.get(), 0);
+
+        selector.stable = Instant::now() - RenditionSelector::STEP_UP_AFTER;
+        assert_eq!(selector.update(), None);
+    }
+
+    #[test]
+    fn attach_starts_over_on_a_new_connection() {
+        let mut selector = RenditionSelector::new(RenditionSelection::Auto);
+
+        selector.attach(RenditionSubscription::default(), 3);
+        lose(&mut selector, RenditionSelector::LOSSES);
+
+        let subscription = RenditionSubscription::default();
+        assert_eq!(selector.attach(subscription.clone(), 1), 0);
+        assert_eq!(lose(&mut selector, RenditionSelector::LOSSES), None);
+        assert_eq!(subscription.get(), 0);
+    }
+}
@@ -0,0 +1,609 @@
+use std::{
+    slice::from_raw_parts,
+    time::{Duration, Instant},
+};
+
+use codec::VideoFrameGuard;
+use common::frame::{VideoFormat, VideoFrame, VideoSubFormat};
+use parking_lot::{Condvar, Mutex};
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+use std::ptr::null;
+
+#[cfg(target_os = "windows")]
+use common::win32::{
+    Direct3DDevice, EasyTexture, d3d_texture_borrowed_raw,
+    windows::{
+        Win32::Graphics::Direct3D11::{
+            D3D11_CPU_ACCESS_READ, D3D11_MAP_READ, D3D11_MAPPED_SUBRESOURCE, D3D11_USAGE_STAGING,
+            ID3D11Texture2D,
+        },
+        core::Interface,
+    },
+};
+
+#[cfg(target_os = "macos")]
+use std::ffi::c_void;
+
+#[cfg(target_os = "macos")]
+use common::macos::{CVPixelBufferRef, RetainedPixelBuffer};
+
+#[derive(Debug, Error, Clone)]
+pub enum SnapshotError {
+    #[error("there is no video in the stream")]
+    NotFoundVideo,
+    #[error("no video frame was received in time")]
+    Timeout,
+    #[error("unsupported video frame, format={0:?}, sub_format={1:?}")]
+    NotSupportedFormat(VideoFormat, VideoSubFormat),
+    #[error("failed to lock the pixel buffer")]
+    #[cfg(target_os = "macos")]
+    LockPixelBufferError,
+    #[error("invalid d3d11texture2d texture")]
+    #[cfg(target_os = "windows")]
+    InvalidD3D11Texture,
+    #[error(transparent)]
+    #[cfg(target_os = "windows")]
+    Win32Error(#[from] common::win32::windows::core::Error),
+}
+
+/// 8-bit RGBA image, the rows are tightly packed.
+#[derive(Debug, Clone)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    fn new(width: u32, height: u32, convert: impl FnOnce(&mut Vec<u8>)) -> Self {
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        convert(&mut pixels);
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+fn copy_rgba(
+    data: *const u8,
+    stride: usize,
+    width: u32,
+    height: u32,
+    bgra: bool,
+    pixels: &mut Vec<u8>,
+) {
+    pixels.clear();
+
+    for y in 0..height as usize {
+        let row = unsafe { from_raw_parts(data.add(y * stride), width as usize * 4) };
+        if bgra {
+            for pixel in row.chunks_exact(4) {
+                pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+            }
+        } else {
+            pixels.extend_from_slice(row);
+        }
+    }
+}
+
+/// Convert a software frame to RGBA.
+fn software_to_rgba(frame: &VideoFrame, pixels: &mut Vec<u8>) {
+    match frame.format {
+        VideoFormat::NV12 => Yuv420::nv12(
+            frame.data[0] as *const u8,
+            frame.data[1] as *const u8,
+            frame.linesize[0] as usize,
+            frame.linesize[1] as usize,
+        )
+        .to_rgba(frame.width, frame.height, pixels),
+        VideoFormat::I420 => Yuv420 {
+            y: frame.data[0] as *const u8,
+            u: frame.data[1] as *const u8,
+            v: frame.data[2] as *const u8,
+            y_stride: frame.linesize[0] as usize,
+            uv_stride: frame.linesize[1] as usize,
+            uv_step: 1,
+        }
+        .to_rgba(frame.width, frame.height, pixels),
+        VideoFormat::BGRA | VideoFormat::RGBA => copy_rgba(
+            frame.data[0] as *const u8,
+            frame.linesize[0] as usize,
+            frame.width,
+            frame.height,
+            frame.format == VideoFormat::BGRA,
+            pixels,
+        ),
+    }
+}
+
+/// The planes of a 4:2:0 frame, NV12 interleaves the chroma, so the distance
+/// between two chroma samples is 2, I420 has separate chroma planes.
+struct Yuv420 {
+    y: *const u8,
+    u: *const u8,
+    v: *const u8,
+    y_stride: usize,
+    uv_stride: usize,
+    uv_step: usize,
+}
+
+impl Yuv420 {
+    fn nv12(y: *const u8, uv: *const u8, y_stride: usize, uv_stride: usize) -> Self {
+        Self {
+            u: uv,
+            v: unsafe { uv.add(1) },
+            uv_step: 2,
+            y_stride,
+            uv_stride,
+            y,
+        }
+    }
+
+    /// BT.709 limited range, the same as the color space tagged by the encoder.
+    #[inline]
+    fn to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+        let c = (y as i32 - 16) * 298;
+        let (d, e) = (u as i32 - 128, v as i32 - 128);
+
+        [
+            ((c + 459 * e + 128) >> 8).clamp(0, 255) as u8,
+            ((c - 55 * d - 136 * e + 128) >> 8).clamp(0, 255) as u8,
+            ((c + 541 * d + 128) >> 8).clamp(0, 255) as u8,
+        ]
+    }
+
+    fn to_rgba(&self, width: u32, height: u32, pixels: &mut Vec<u8>) {
+        let (width, height) = (width as usize, height as usize);
+        // The slices end at the last chroma sample, V of NV12 starts one byte later,
+        // so it would otherwise read past the row.
+        let chroma = width.div_ceil(2) * self.uv_step + 1 - self.uv_step;
+
+        pixels.clear();
+
+        for y in 0..height {
+            let (luma, u, v) = unsafe {
+                (
+                    from_raw_parts(self.y.add(y * self.y_stride), width),
+                    from_raw_parts(self.u.add(y / 2 * self.uv_stride), chroma),
+                    from_raw_parts(self.v.add(y / 2 * self.uv_stride), chroma),
+                )
+            };
+
+            for (x, luma) in luma.iter().enumerate() {
+                let index = x / 2 * self.uv_step;
+                let [r, g, b] = Self::to_rgb(*luma, u[index], v[index]);
+                pixels.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreVideo", kind = "framework")]
+unsafe extern "C" {
+    fn CVPixelBufferLockBaseAddress(buffer: *const c_void, flags: u64) -> i32;
+    fn CVPixelBufferUnlockBaseAddress(buffer: *const c_void, flags: u64) -> i32;
+    fn CVPixelBufferGetBaseAddress(buffer: *const c_void) -> *mut c_void;
+    fn CVPixelBufferGetBytesPerRow(buffer: *const c_void) -> usize;
+    fn CVPixelBufferGetBaseAddressOfPlane(buffer: *const c_void, plane: usize) -> *mut c_void;
+    fn CVPixelBufferGetBytesPerRowOfPlane(buffer: *const c_void, plane: usize) -> usize;
+}
+
+/// A frame of the pipeline in memory that is owned by the holder, so that it
+/// can be used after the pipeline has moved on, the frame points to the
+/// planes, the texture or the pixel buffer below, or to the frame retained
+/// from the decoder.
+#[derive(Default)]
+pub(crate) struct KeptFrame {
+    frame: VideoFrame,
+    guard: Option<VideoFrameGuard>,
+    planes: [Vec<u8>; 3],
+    #[cfg(target_os = "windows")]
+    texture: Option<ID3D11Texture2D>,
+    #[cfg(target_os = "macos")]
+    buffer: Option<RetainedPixelBuffer>,
+}
+
+unsafe impl Send for KeptFrame {}
+
+impl KeptFrame {
+    /// The rows of each plane, the chroma planes of 4:2:0 have half the rows.
+    fn rows(frame: &VideoFrame) -> [usize; 3] {
+        let (height, half) = (frame.height as usize, frame.height.div_ceil(2) as usize);
+
+        match frame.format {
+            VideoFormat::NV12 => [height, half, 0],
+            VideoFormat::I420 => [height, half, half],
+            VideoFormat::BGRA | VideoFormat::RGBA => [height, 0, 0],
+        }
+    }
+
+    pub fn frame(&self) -> &VideoFrame {
+        &self.frame
+    }
+
+    /// Replace the kept frame with a copy of the frame, the buffers are
+    /// reused if they fit.
+    pub fn keep(
+        &mut self,
+        frame: &VideoFrame,
+        #[cfg(target_os = "windows")] direct3d: &Direct3DDevice,
+    ) -> Result<(), SnapshotError> {
+        self.guard = None;
+        self.frame = VideoFrame {
+            format: frame.format,
+            sub_format: frame.sub_format,
+            width: frame.width,
+            height: frame.height,
+            data: frame.data,
+            linesize: frame.linesize,
+            timestamp: frame.timestamp,
+        };
+
+        match frame.sub_format {
+            VideoSubFormat::SW => {
+                for (i, rows) in Self::rows(frame).into_iter().enumerate() {
+                    let plane = &mut self.planes[i];
+                    plane.clear();
+
+                    if rows > 0 && !frame.data[i].is_null() {
+                        plane.extend_from_slice(unsafe {
+                            from_raw_parts(
+                                frame.data[i] as *const u8,
+                                frame.linesize[i] as usize * rows,
+                            )
+                        });
+                    }
+
+                    self.frame.data[i] = plane.as_ptr() as *const _;
+                }
+            }
+            // The texture of the frame is reused by the pipeline, the array slice of the
+            // frame is copied to a texture of the snapshot.
+            #[cfg(target_os = "windows")]
+            VideoSubFormat::D3D11 => {
+                let source = d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
+                    .ok_or_else(|| SnapshotError::InvalidD3D11Texture)?;
+
+                let mut desc = source.desc();
+                desc.MipLevels = 1;
+                desc.ArraySize = 1;
+                desc.BindFlags = 0;
+                desc.CPUAccessFlags = 0;
+                desc.MiscFlags = 0;
+
+                let is_reusable = self.texture.as_ref().map(|it| {
+                    let current = it.desc();
+
+                    (current.Width, current.Height, current.Format)
+                        == (desc.Width, desc.Height, desc.Format)
+                });
+
+                if is_reusable != Some(true) {
+                    let mut texture = None;
+                    unsafe {
+                        direct3d
+                            .device
+                            .CreateTexture2D(&desc, None, Some(&mut texture))?;
+                    }
+
+                    self.texture = Some(texture.ok_or_else(|| SnapshotError::InvalidD3D11Texture)?);
+                }
+
+                let texture = self.texture.as_ref().unwrap();
+                unsafe {
+                    direct3d.context.CopySubresourceRegion(
+                        texture,
+                        0,
+                        0,
+                        0,
+                        0,
+                        source,
+                        frame.data[1] as u32,
+                        None,
+                    );
+                }
+
+                self.frame.data[0] = texture.as_raw() as *const _;
+                self.frame.data[1] = null();
+            }
+            // The decoders and the capture allocate a new pixel buffer for each frame, so
+            // retaining the buffer keeps the frame.
+            #[cfg(target_os = "macos")]
+            VideoSubFormat::CvPixelBufferRef => {
+                let buffer = RetainedPixelBuffer::new(frame.data[0] as CVPixelBufferRef)
+                    .ok_or_else(|| SnapshotError::LockPixelBufferError)?;
+
+                self.frame.data[0] = buffer.as_raw() as *const _;
+                self.buffer = Some(buffer);
+            }
+            _ => {
+                return Err(SnapshotError::NotSupportedFormat(
+                    frame.format,
+                    frame.sub_format,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the latest frame passing through the pipeline, and converts it when
+/// a snapshot is requested.
+///
+/// The frames are only borrowed by the pipeline, so every frame is copied
+/// into the memory of the snapshot, the textures are copied on the gpu and
+/// the pixel buffers are retained. A still screen does not produce frames,
+/// the snapshot is the last frame that it produced.
+pub(crate) struct Snapshot {
+    /// The latest frame could not be kept, the error is returned instead of an
+    /// older frame.
+    latest: Mutex<Option<Result<KeptFrame, SnapshotError>>>,
+    condvar: Condvar,
+    #[cfg(target_os = "windows")]
+    direct3d: Direct3DDevice,
+}
+
+impl Snapshot {
+    /// The pipeline may not have produced the first frame yet.
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub fn new(#[cfg(target_os = "windows")] direct3d: Direct3DDevice) -> Self {
+        // The kept texture is read back on the thread of the caller, while the
+        // pipeline uses the same immediate context.
+        #[cfg(target_os = "windows")]
+        if let Err(e) = direct3d.set_multithread_protected(true) {
+            tracing::warn!("failed to set the d3d multithread protected, err={:?}", e);
+        }
+
+        Self {
+            latest: Mutex::new(None),
+            condvar: Condvar::new(),
+            #[cfg(target_os = "windows")]
+            direct3d,
+        }
+    }
+
+    /// Called for each frame of the pipeline, the frame replaces the kept
+    /// frame.
+    pub fn capture(&self, frame: &VideoFrame) {
+        let mut latest = self.latest.lock();
+
+        // The buffers of the kept frame are reused.
+        let (mut kept, failed) = match latest.take() {
+            Some(Ok(it)) => (it, false),
+            Some(Err(_)) => (KeptFrame::default(), true),
+            None => (KeptFrame::default(), false),
+        };
+
+        let result = kept
+            .keep(
+                frame,
+                #[cfg(target_os = "windows")]
+                &self.direct3d,
+            )
+            .map(|_| kept);
+
+        // Only the first failure in a row is logged, the pipeline keeps producing the
+        // same kind of frames.
+        if let Err(e) = &result {
+            if !failed {
+                tracing::warn!("failed to keep the video snapshot frame, err={:?}", e);
+            }
+        }
+
+        latest.replace(result);
+        self.condvar.notify_all();
+    }
+
+    /// Called for each decoded frame instead of `capture`, the frame is held
+    /// in the memory of the decoder instead of being copied, which holds one
+    /// surface of the hardware decoders until the next frame.
+    pub fn retain(&self, guard: VideoFrameGuard) {
+        let kept = KeptFrame {
+            frame: VideoFrame { ..*guard },
+            guard: Some(guard),
+            ..Default::default()
+        };
+
+        self.latest.lock().replace(Ok(kept));
+        self.condvar.notify_all();
+    }
+
+    /// Convert the latest frame, waits for the first frame if the pipeline
+    /// has not produced one yet.
+    pub fn take(&self) -> Result<RgbaImage, SnapshotError> {
+        let deadline = Instant::now() + Self::TIMEOUT;
+
+        let mut latest = self.latest.lock();
+        loop {
+            match latest.as_ref() {
+                Some(Ok(kept)) => return self.convert(kept.frame()),
+                Some(Err(e)) => return Err(e.clone()),
+                None => (),
+            }
+
+            if self.condvar.wait_until(&mut latest, deadline).timed_out() && latest.is_none() {
+                return Err(SnapshotError::Timeout);
+            }
+        }
+    }
+
+    fn convert(&self, frame: &VideoFrame) -> Result<RgbaImage, SnapshotError> {
+        match (frame.sub_format, frame.format) {
+            (VideoSubFormat::SW, _) => Ok(RgbaImage::new(frame.width, frame.height, |pixels| {
+                software_to_rgba(frame, pixels)
+            })),
+            #[cfg(target_os = "windows")]
+            (VideoSubFormat::D3D11, VideoFormat::NV12 | VideoFormat::BGRA | VideoFormat::RGBA) => {
+                self.read_texture(frame)
+            }
+            #[cfg(target_os = "macos")]
+            (VideoSubFormat::CvPixelBufferRef, VideoFormat::NV12 | VideoFormat::BGRA) => {
+                Self::read_pixel_buffer(frame)
+            }
+            (sub_format, format) => Err(SnapshotError::NotSupportedFormat(format, sub_format)),
+        }
+    }
+
+    /// The texture is copied to a staging texture and read back, the textures
+    /// of the decoder may be larger than the frame, the padding is cut off.
+    #[cfg(target_os = "windows")]
+    fn read_texture(&self, frame: &VideoFrame) -> Result<RgbaImage, SnapshotError> {
+        let texture = d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
+            .ok_or_else(|| SnapshotError::InvalidD3D11Texture)?;
+
+        let mut desc = texture.desc();
+        desc.MipLevels = 1;
+        desc.ArraySize = 1;
+        desc.Usage = D3D11_USAGE_STAGING;
+        desc.BindFlags = 0;
+        desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
+        desc.MiscFlags = 0;
+
+        let mut staging = None;
+        unsafe {
+            self.direct3d
+                .device
+                .CreateTexture2D(&desc, None, Some(&mut staging))?;
+        }
+
+        let staging = staging.ok_or_else(|| SnapshotError::InvalidD3D11Texture)?;
+        unsafe {
+            self.direct3d.context.CopySubresourceRegion(
+                &staging,
+                0,
+                0,
+                0,
+                0,
+                texture,
+                frame.data[1] as u32,
+                None,
+            );
+        }
+
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        unsafe {
+            self.direct3d
+                .context
+                .Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))?;
+        }
+
+        let (width, height) = (frame.width.min(desc.Width), frame.height.min(desc.Height));
+        let (data, stride) = (mapped.pData as *const u8, mapped.RowPitch as usize);
+        let image = RgbaImage::new(width, height, |pixels| match frame.format {
+            VideoFormat::NV12 => Yuv420::nv12(
+                data,
+                unsafe { data.add(stride * desc.Height as usize) },
+                stride,
+                stride,
+            )
+            .to_rgba(width, height, pixels),
+            format => copy_rgba(
+                data,
+                stride,
+                width,
+                height,
+                format == VideoFormat::BGRA,
+                pixels,
+            ),
+        });
+
+        unsafe {
+            self.direct3d.context.Unmap(&staging, 0);
+        }
+
+        Ok(image)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn read_pixel_buffer(frame: &VideoFrame) -> Result<RgbaImage, SnapshotError> {
+        // kCVPixelBufferLock_ReadOnly
+        const READ_ONLY: u64 = 1;
+
+        let buffer = frame.data[0];
+        if unsafe { CVPixelBufferLockBaseAddress(buffer, READ_ONLY) } != 0 {
+            return Err(SnapshotError::LockPixelBufferError);
+        }
+
+        let image = unsafe {
+            if frame.format == VideoFormat::NV12 {
+                let (y, uv) = (
+                    CVPixelBufferGetBaseAddressOfPlane(buffer, 0) as *const u8,
+                    CVPixelBufferGetBaseAddressOfPlane(buffer, 1) as *const u8,
+                );
+
+                if y.is_null() || uv.is_null() {
+                    None
+                } else {
+                    Some(RgbaImage::new(frame.width, frame.height, |pixels| {
+                        Yuv420::nv12(
+                            y,
+                            uv,
+                            CVPixelBufferGetBytesPerRowOfPlane(buffer, 0),
+                            CVPixelBufferGetBytesPerRowOfPlane(buffer, 1),
+                        )
+                        .to_rgba(frame.width, frame.height, pixels)
+                    }))
+                }
+            } else {
+                let base = CVPixelBufferGetBaseAddress(buffer) as *const u8;
+                if base.is_null() {
+                    None
+                } else {
+                    let stride = CVPixelBufferGetBytesPerRow(buffer);
+                    Some(RgbaImage::new(frame.width, frame.height, |pixels| {
+                        copy_rgba(base, stride, frame.width, frame.height, true, pixels)
+                    }))
+                }
+            }
+        };
+
+        unsafe {
+            CVPixelBufferUnlockBaseAddress(buffer, READ_ONLY);
+        }
+
+        image.ok_or_else(|| SnapshotError::LockPixelBufferError)
+    }
+}
+
+/// Converts the software frames to RGBA for the sink of a headless receiver,
+/// the buffer is reused between the frames.
+pub(crate) struct RgbaConverter {
+    buffer: Vec<u8>,
+    frame: VideoFrame,
+}
+
+impl RgbaConverter {
+    pub fn new() -> Self {
+        Self {
+            frame: VideoFrame::default(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Returns the converted frame, the frames that are not in the system
+    /// memory cannot be converted.
+    pub fn convert<'a>(&'a mut self, frame: &VideoFrame) -> Option<&'a VideoFrame> {
+        if frame.sub_format != VideoSubFormat::SW {
+            return None;
+        }
+
+        software_to_rgba(frame, &mut self.buffer);
+
+        self.frame.width = frame.width;
+        self.frame.height = frame.height;
+        self.frame.format = VideoFormat::RGBA;
+        self.frame.sub_format = VideoSubFormat::SW;
+        self.frame.data[0] = self.buffer.as_ptr() as *const _;
+        self.frame.linesize[0] = frame.width * 4;
+
+        Some(&self.frame)
+    }
+}
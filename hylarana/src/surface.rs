@@ -0,0 +1,466 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use common::frame::{AudioFrame, VideoFormat, VideoFrame, VideoSubFormat};
+use thiserror::Error;
+
+use super::MediaStreamSink;
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+use std::sync::Arc;
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+use common::Size;
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+use renderer::{
+    RendererSourceOptions, Texture2DRaw, TextureImporter, TextureImporterOptions, wgpu,
+};
+
+#[cfg(target_os = "windows")]
+use parking_lot::Mutex;
+
+#[cfg(target_os = "windows")]
+use super::util::get_direct3d;
+
+#[cfg(target_os = "windows")]
+use common::win32::{
+    Direct3DDevice, EasyTexture, d3d_texture_borrowed_raw,
+    windows::{
+        Win32::{
+            Foundation::{HANDLE, S_OK},
+            Graphics::{
+                Direct3D11::{
+                    D3D11_BIND_SHADER_RESOURCE, D3D11_RESOURCE_MISC_SHARED_KEYEDMUTEX,
+                    D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT, ID3D11Texture2D,
+                },
+                Dxgi::{
+                    Common::{
+                        DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_NV12, DXGI_FORMAT_R8G8B8A8_UNORM,
+                    },
+                    IDXGIKeyedMutex,
+                },
+            },
+        },
+        core::Interface,
+    },
+};
+
+#[cfg(target_os = "macos")]
+use common::macos::{CVPixelBufferRef, RetainedPixelBuffer};
+
+#[derive(Debug, Error)]
+pub enum HardwareSurfaceError {
+    #[error("the frame is not a hardware surface, format={0:?}, sub_format={1:?}")]
+    NotSupportedFormat(VideoFormat, VideoSubFormat),
+    #[error("the surface was not released by the other device in time")]
+    #[cfg(target_os = "windows")]
+    Timeout,
+    #[error("invalid d3d11texture2d texture")]
+    #[cfg(target_os = "windows")]
+    InvalidD3D11Texture,
+    #[error("invalid pixel buffer")]
+    #[cfg(target_os = "macos")]
+    InvalidPixelBuffer,
+    #[error(transparent)]
+    #[cfg(target_os = "windows")]
+    Win32Error(#[from] common::win32::windows::core::Error),
+    #[error(transparent)]
+    GraphicsError(#[from] renderer::GraphicsError),
+}
+
+/// A decoded video frame that is handed to the application as a texture of
+/// the native graphics api, see [HardwareFrameSink].
+///
+/// On windows the surface is a copy of the decoded frame, a texture with a
+/// keyed mutex that is shared with the other devices. The frame is written
+/// with the key [HardwareSurface::KEY_READY] released, the application
+/// acquires the key before reading the texture and releases
+/// [HardwareSurface::KEY_FREE] after that, so that the texture can be written
+/// again. A surface that is never acquired is written with a newer frame.
+///
+/// On macos the surface is the pixel buffer of the decoder, it is retained
+/// until the surface is dropped and is complete when it is handed out.
+pub struct HardwareSurface {
+    pub format: VideoFormat,
+    pub width: u32,
+    pub height: u32,
+    pub timestamp: u64,
+    #[cfg(target_os = "windows")]
+    texture: ID3D11Texture2D,
+    #[cfg(target_os = "windows")]
+    mutex: IDXGIKeyedMutex,
+    #[cfg(target_os = "macos")]
+    buffer: RetainedPixelBuffer,
+}
+
+#[cfg(target_os = "windows")]
+unsafe impl Send for HardwareSurface {}
+
+#[cfg(target_os = "windows")]
+unsafe impl Sync for HardwareSurface {}
+
+#[cfg(target_os = "windows")]
+impl HardwareSurface {
+    /// The key that is acquired by the application before reading the texture.
+    pub const KEY_READY: u64 = 1;
+
+    /// The key that is released by the application after reading the texture.
+    pub const KEY_FREE: u64 = 0;
+
+    /// The texture belongs to the d3d11 device of the receiver, another device
+    /// opens the texture with [HardwareSurface::shared_handle].
+    pub fn texture(&self) -> &ID3D11Texture2D {
+        &self.texture
+    }
+
+    /// The shared handle of the texture, see `ID3D11Device::OpenSharedResource`.
+    pub fn shared_handle(&self) -> Result<HANDLE, HardwareSurfaceError> {
+        Ok(self.texture.get_shared()?)
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl HardwareSurface {
+    pub fn pixel_buffer(&self) -> CVPixelBufferRef {
+        self.buffer.as_raw()
+    }
+}
+
+/// Acquire the key without waiting longer than the timeout, the wrapper of
+/// the windows crate reports the timeout as a success.
+#[cfg(target_os = "windows")]
+fn acquire_key(mutex: &IDXGIKeyedMutex, key: u64, timeout: u32) -> bool {
+    unsafe {
+        (Interface::vtable(mutex).AcquireSync)(Interface::as_raw(mutex), key, timeout) == S_OK
+    }
+}
+
+/// The shared textures that the decoded frames are copied into, the textures
+/// are used in turn, so that the application can still read the previous
+/// frames while a new one is written.
+#[cfg(target_os = "windows")]
+struct SurfaceRing {
+    direct3d: Direct3DDevice,
+    /// The frames of another format or size need another ring.
+    input: (VideoFormat, u32, u32),
+    textures: Vec<(ID3D11Texture2D, IDXGIKeyedMutex)>,
+    next: usize,
+}
+
+#[cfg(target_os = "windows")]
+unsafe impl Send for SurfaceRing {}
+
+#[cfg(target_os = "windows")]
+impl SurfaceRing {
+    const CAPACITY: usize = 3;
+
+    fn new(direct3d: Direct3DDevice, frame: &VideoFrame) -> Result<Self, HardwareSurfaceError> {
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        desc.Width = frame.width;
+        desc.Height = frame.height;
+        desc.MipLevels = 1;
+        desc.ArraySize = 1;
+        desc.SampleDesc.Count = 1;
+        desc.SampleDesc.Quality = 0;
+        desc.BindFlags = D3D11_BIND_SHADER_RESOURCE.0 as u32;
+        desc.CPUAccessFlags = 0;
+        desc.Usage = D3D11_USAGE_DEFAULT;
+        desc.MiscFlags = D3D11_RESOURCE_MISC_SHARED_KEYEDMUTEX.0 as u32;
+        desc.Format = match frame.format {
+            VideoFormat::NV12 => DXGI_FORMAT_NV12,
+            VideoFormat::BGRA => DXGI_FORMAT_B8G8R8A8_UNORM,
+            VideoFormat::RGBA => DXGI_FORMAT_R8G8B8A8_UNORM,
+            format => {
+                return Err(HardwareSurfaceError::NotSupportedFormat(
+                    format,
+                    frame.sub_format,
+                ));
+            }
+        };
+
+        let mut textures = Vec::with_capacity(Self::CAPACITY);
+        for _ in 0..Self::CAPACITY {
+            let mut texture = None;
+            unsafe {
+                direct3d
+                    .device
+                    .CreateTexture2D(&desc, None, Some(&mut texture))?;
+            }
+
+            let texture = texture.unwrap();
+            let mutex = texture.cast::<IDXGIKeyedMutex>()?;
+            textures.push((texture, mutex));
+        }
+
+        Ok(Self {
+            input: (frame.format, frame.width, frame.height),
+            next: 0,
+            direct3d,
+            textures,
+        })
+    }
+
+    fn is_input(&self, frame: &VideoFrame) -> bool {
+        self.input == (frame.format, frame.width, frame.height)
+    }
+
+    /// Copies the frame into the oldest texture that the application is not
+    /// reading, `None` if the application holds all of them.
+    fn write(
+        &mut self,
+        frame: &VideoFrame,
+    ) -> Result<Option<(ID3D11Texture2D, IDXGIKeyedMutex)>, HardwareSurfaceError> {
+        let raw = frame.data[0] as *mut _;
+        let texture = d3d_texture_borrowed_raw(&raw)
+            .ok_or_else(|| HardwareSurfaceError::InvalidD3D11Texture)?;
+
+        for i in 0..self.textures.len() {
+            let index = (self.next + i) % self.textures.len();
+            let (target, mutex) = &self.textures[index];
+
+            // A frame that was never read is overwritten with the newer one.
+            if !acquire_key(mutex, HardwareSurface::KEY_FREE, 0)
+                && !acquire_key(mutex, HardwareSurface::KEY_READY, 0)
+            {
+                continue;
+            }
+
+            unsafe {
+                self.direct3d.context.CopySubresourceRegion(
+                    target,
+                    0,
+                    0,
+                    0,
+                    0,
+                    texture,
+                    frame.data[1] as u32,
+                    None,
+                );
+
+                mutex.ReleaseSync(HardwareSurface::KEY_READY)?;
+            }
+
+            self.next = index + 1;
+
+            return Ok(Some((target.clone(), mutex.clone())));
+        }
+
+        Ok(None)
+    }
+}
+
+/// A sink that hands the decoded frames to the application as hardware
+/// surfaces, so that an application with its own compositor, such as a
+/// browser or a game overlay, can skip the renderer entirely.
+///
+/// The receiver must decode to hardware frames, see [crate::VideoOutput::Native].
+/// The frames are skipped with a warning if they are not hardware frames, or
+/// on linux, where the dma-buf objects of the decoder can not outlive the
+/// frames.
+pub struct HardwareFrameSink<F, A = ()> {
+    func: F,
+    audio: A,
+    warned: AtomicBool,
+    #[cfg(target_os = "windows")]
+    ring: Mutex<Option<SurfaceRing>>,
+}
+
+impl<F> HardwareFrameSink<F>
+where
+    F: Fn(HardwareSurface) -> bool + Send + Sync,
+{
+    /// The function is called with the surface of every frame on the thread
+    /// of the decoder, returning `false` closes the stream.
+    pub fn new(func: F) -> Self {
+        Self {
+            audio: (),
+            warned: AtomicBool::new(false),
+            #[cfg(target_os = "windows")]
+            ring: Mutex::new(None),
+            func,
+        }
+    }
+}
+
+impl<F, A> HardwareFrameSink<F, A> {
+    /// The audio of the stream is passed to the sink, such as a player
+    /// that plays the audio only.
+    pub fn with_audio<T: MediaStreamSink>(self, audio: T) -> HardwareFrameSink<F, T> {
+        HardwareFrameSink {
+            func: self.func,
+            warned: self.warned,
+            #[cfg(target_os = "windows")]
+            ring: self.ring,
+            audio,
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    fn surface(&self, frame: &VideoFrame) -> Result<Option<HardwareSurface>, HardwareSurfaceError> {
+        if frame.sub_format != VideoSubFormat::D3D11 {
+            return Err(HardwareSurfaceError::NotSupportedFormat(
+                frame.format,
+                frame.sub_format,
+            ));
+        }
+
+        let mut ring = self.ring.lock();
+        if !ring.as_ref().map(|it| it.is_input(frame)).unwrap_or(false) {
+            ring.replace(SurfaceRing::new(get_direct3d(), frame)?);
+        }
+
+        Ok(ring
+            .as_mut()
+            .unwrap()
+            .write(frame)?
+            .map(|(texture, mutex)| HardwareSurface {
+                format: frame.format,
+                width: frame.width,
+                height: frame.height,
+                timestamp: frame.timestamp,
+                texture,
+                mutex,
+            }))
+    }
+
+    #[cfg(target_os = "macos")]
+    fn surface(&self, frame: &VideoFrame) -> Result<Option<HardwareSurface>, HardwareSurfaceError> {
+        if frame.sub_format != VideoSubFormat::CvPixelBufferRef {
+            return Err(HardwareSurfaceError::NotSupportedFormat(
+                frame.format,
+                frame.sub_format,
+            ));
+        }
+
+        Ok(Some(HardwareSurface {
+            format: frame.format,
+            width: frame.width,
+            height: frame.height,
+            timestamp: frame.timestamp,
+            buffer: RetainedPixelBuffer::new(frame.data[0] as CVPixelBufferRef)
+                .ok_or_else(|| HardwareSurfaceError::InvalidPixelBuffer)?,
+        }))
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    fn surface(&self, frame: &VideoFrame) -> Result<Option<HardwareSurface>, HardwareSurfaceError> {
+        Err(HardwareSurfaceError::NotSupportedFormat(
+            frame.format,
+            frame.sub_format,
+        ))
+    }
+}
+
+impl<F, A> MediaStreamSink for HardwareFrameSink<F, A>
+where
+    F: Fn(HardwareSurface) -> bool + Send + Sync,
+    A: MediaStreamSink,
+{
+    fn video(&self, frame: &VideoFrame) -> bool {
+        match self.surface(frame) {
+            Ok(Some(surface)) => (self.func)(surface),
+            // The application still reads all of the surfaces.
+            Ok(None) => true,
+            Err(e) => {
+                if !self.warned.swap(true, Ordering::Relaxed) {
+                    tracing::warn!("HardwareFrameSink failed to get the surface, err={:?}", e);
+                }
+
+                true
+            }
+        }
+    }
+
+    fn audio(&self, frame: &AudioFrame) -> bool {
+        self.audio.audio(frame)
+    }
+}
+
+/// Imports the surfaces into a texture of the application's wgpu device, see
+/// [TextureImporter]. The texture can be sampled by the work that is submitted
+/// to the queue after the import and before the next import. The device must
+/// use DX12 on windows and Metal on macos, the NV12 pixel buffers on macos are
+/// imported as a `Rgba8Unorm` texture.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+pub struct SurfaceImporter {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    /// Created for the first surface, and again when the surfaces change.
+    importer: Option<((VideoFormat, u32, u32), TextureImporter)>,
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+impl SurfaceImporter {
+    /// The time that the import waits for the other devices to release the
+    /// surface, in milliseconds.
+    #[cfg(target_os = "windows")]
+    const ACQUIRE_TIMEOUT: u32 = 100;
+
+    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self {
+        Self {
+            importer: None,
+            device,
+            queue,
+        }
+    }
+
+    /// On windows the surface is acquired while it is copied, and released
+    /// for the receiver to write again after that.
+    pub fn import(
+        &mut self,
+        surface: &HardwareSurface,
+    ) -> Result<&wgpu::Texture, HardwareSurfaceError> {
+        let input = (surface.format, surface.width, surface.height);
+        if self.importer.as_ref().map(|(it, _)| *it) != Some(input) {
+            // The previous texture is released before the new one is created.
+            self.importer = None;
+            self.importer = Some((
+                input,
+                TextureImporter::new(TextureImporterOptions {
+                    #[cfg(target_os = "windows")]
+                    direct3d: get_direct3d(),
+                    device: self.device.clone(),
+                    queue: self.queue.clone(),
+                    source: RendererSourceOptions {
+                        format: surface.format,
+                        #[cfg(target_os = "windows")]
+                        sub_format: VideoSubFormat::D3D11,
+                        #[cfg(target_os = "macos")]
+                        sub_format: VideoSubFormat::CvPixelBufferRef,
+                        size: Size {
+                            width: surface.width,
+                            height: surface.height,
+                        },
+                    },
+                })?,
+            ));
+        }
+
+        let importer = &mut self.importer.as_mut().unwrap().1;
+
+        #[cfg(target_os = "windows")]
+        {
+            if !acquire_key(
+                &surface.mutex,
+                HardwareSurface::KEY_READY,
+                Self::ACQUIRE_TIMEOUT,
+            ) {
+                return Err(HardwareSurfaceError::Timeout);
+            }
+
+            let texture =
+                importer.import(Texture2DRaw::ID3D11Texture2D(surface.texture.clone(), 0));
+
+            unsafe {
+                surface.mutex.ReleaseSync(HardwareSurface::KEY_FREE)?;
+            }
+
+            Ok(texture?)
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            Ok(importer.import(Texture2DRaw::CVPixelBufferRef(surface.pixel_buffer()))?)
+        }
+    }
+}
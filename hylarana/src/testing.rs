@@ -0,0 +1,554 @@
+//! End to end harness of the pipeline, a sender with the test pattern source
+//! and a headless receiver are connected within this process, so that the
+//! codecs, the transport and the sinks can be checked by regression tests
+//! without a capture device or a window.
+
+use std::{
+    fs,
+    io::{self, Write},
+    net::SocketAddr,
+    path::Path,
+    slice::from_raw_parts,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use capture::Source;
+use common::{
+    codec::{VideoDecoderType, VideoEncoderType},
+    frame::{AudioFrame, VideoFormat, VideoFrame, VideoSubFormat},
+};
+
+use parking_lot::Mutex;
+use thiserror::Error;
+use transport::{TransportOptions, TransportStrategy};
+
+use crate::{
+    AudioMonitor, AudioOptions, CloseReason, HylaranaError, HylaranaReceiverOptions,
+    HylaranaSenderMediaOptions, HylaranaSenderOptions, HylaranaSenderTrackOptions,
+    MediaStreamObserver, MediaStreamSink, ProtectedContentPolicy, RenditionSelection, RgbaImage,
+    SenderInput, SourceFailover, SyncGroupOptions, VideoOptions, VideoOutput, create_receiver,
+    create_sender,
+};
+
+#[derive(Debug, Error)]
+pub enum TestHarnessError {
+    #[error(transparent)]
+    HylaranaError(#[from] HylaranaError),
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("no video frame was received")]
+    NoVideoFrame,
+    #[error("too few frames were received, video={video}, audio={audio}")]
+    TooFewFrames { video: u32, audio: u32 },
+    #[error("the size does not match the golden image, size={0}x{1}, golden={2}x{3}")]
+    SizeMismatch(u32, u32, u32, u32),
+    #[error("the frame does not match the golden image, psnr={psnr:.2}dB, min={min:.2}dB")]
+    GoldenMismatch { psnr: f64, min: f64 },
+    #[error("the golden image is not a RGB_ALPHA pam image")]
+    InvalidGoldenImage,
+}
+
+/// Options of [TestHarness], the default is a short stream of both tracks
+/// over the loopback transport.
+#[derive(Debug, Clone)]
+pub struct TestHarnessOptions {
+    /// The video track is not sent if it is `None`.
+    pub video: Option<VideoOptions>,
+    /// The audio track is not sent if it is `None`.
+    pub audio: Option<AudioOptions>,
+    pub decoder: VideoDecoderType,
+    /// Use [TransportStrategy::Loopback] on the machines without network, or
+    /// [TransportStrategy::Srt] to check the stream over localhost.
+    pub transport: TransportOptions,
+    /// How long the stream runs before the receivers and the sender are
+    /// closed.
+    pub duration: Duration,
+    /// The number of the receivers of the sender, each has its own
+    /// connection, see [TestHarness::run_all].
+    pub receivers: usize,
+    /// The sync group of all receivers, see
+    /// `HylaranaReceiverOptions::sync_group`.
+    pub sync_group: Option<SyncGroupOptions>,
+}
+
+impl Default for TestHarnessOptions {
+    fn default() -> Self {
+        Self {
+            video: Some(VideoOptions {
+                codec: VideoEncoderType::X265,
+                frame_rate: 30,
+                width: 640,
+                height: 360,
+                bit_rate: 2_000_000,
+                key_frame_interval: 30,
+                preset: None,
+                tune: None,
+                profile: None,
+                rate_control: None,
+                threads: None,
+                threading: None,
+                extra_options: Vec::new(),
+                content_hint: None,
+                skip_unchanged_frames: false,
+                exclude_windows: Vec::new(),
+                protected_content: ProtectedContentPolicy::Stream,
+                simulcast: Vec::new(),
+            }),
+            audio: Some(AudioOptions {
+                sample_rate: 48000,
+                bit_rate: 64000,
+                channels: None,
+                application: None,
+                complexity: None,
+                fec: false,
+                dtx: false,
+                frame_duration: None,
+                silence: None,
+                monitor: AudioMonitor::Captured,
+            }),
+            decoder: VideoDecoderType::HEVC,
+            transport: TransportOptions {
+                strategy: TransportStrategy::Loopback,
+                ..Default::default()
+            },
+            duration: Duration::from_secs(3),
+            receivers: 1,
+            sync_group: None,
+        }
+    }
+}
+
+/// What the receiver of [TestHarness] got.
+#[derive(Debug, Clone, Default)]
+pub struct TestReport {
+    pub video_frames: u32,
+    pub audio_frames: u32,
+    /// The last decoded video frame.
+    pub last_frame: Option<RgbaImage>,
+    /// Why the receiver was closed, `None` if it was still running at the end
+    /// of the stream.
+    pub close_reason: Option<CloseReason>,
+}
+
+impl TestReport {
+    /// Checks that at least the given numbers of frames were received.
+    pub fn expect_frames(&self, video: u32, audio: u32) -> Result<(), TestHarnessError> {
+        if self.video_frames < video || self.audio_frames < audio {
+            return Err(TestHarnessError::TooFewFrames {
+                video: self.video_frames,
+                audio: self.audio_frames,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The PSNR of the last frame against the golden image, the moving ramp in
+    /// the lower third of the test pattern is not compared.
+    pub fn compare(&self, golden: &RgbaImage) -> Result<f64, TestHarnessError> {
+        let frame = self
+            .last_frame
+            .as_ref()
+            .ok_or(TestHarnessError::NoVideoFrame)?;
+
+        psnr(frame, golden, (frame.height * 2 / 3) & !1)
+    }
+
+    /// Checks the last frame against the golden image, see
+    /// [TestReport::compare], the lossy codec never reproduces the golden
+    /// image exactly, about 30dB is a good match.
+    pub fn expect_golden(&self, golden: &RgbaImage, min: f64) -> Result<f64, TestHarnessError> {
+        let psnr = self.compare(golden)?;
+        if psnr < min {
+            return Err(TestHarnessError::GoldenMismatch { psnr, min });
+        }
+
+        Ok(psnr)
+    }
+}
+
+/// The peak signal to noise ratio of the first rows of two images in
+/// decibels, the alpha is ignored. Identical images are infinite.
+pub fn psnr(image: &RgbaImage, golden: &RgbaImage, rows: u32) -> Result<f64, TestHarnessError> {
+    if image.width != golden.width || image.height != golden.height {
+        return Err(TestHarnessError::SizeMismatch(
+            image.width,
+            image.height,
+            golden.width,
+            golden.height,
+        ));
+    }
+
+    let size = image.width as usize * rows.min(image.height) as usize * 4;
+    let (mut sum, mut count) = (0u64, 0u64);
+    for (a, b) in image.pixels[..size]
+        .chunks_exact(4)
+        .zip(golden.pixels[..size].chunks_exact(4))
+    {
+        for (a, b) in a[..3].iter().zip(&b[..3]) {
+            let diff = a.abs_diff(*b) as u64;
+
+            sum += diff * diff;
+            count += 1;
+        }
+    }
+
+    if sum == 0 {
+        return Ok(f64::INFINITY);
+    }
+
+    let mse = sum as f64 / count as f64;
+    Ok(10.0 * (255.0 * 255.0 / mse).log10())
+}
+
+/// Reads a golden image, the golden images are stored as RGB_ALPHA pam
+/// images, which most image viewers and tools can open.
+pub fn load_golden<P: AsRef<Path>>(path: P) -> Result<RgbaImage, TestHarnessError> {
+    let bytes = fs::read(path)?;
+
+    let end = b"ENDHDR\n";
+    let offset = bytes
+        .windows(end.len())
+        .position(|it| it == end)
+        .ok_or(TestHarnessError::InvalidGoldenImage)?;
+
+    let (mut width, mut height, mut depth) = (0, 0, 0);
+    let header =
+        std::str::from_utf8(&bytes[..offset]).map_err(|_| TestHarnessError::InvalidGoldenImage)?;
+    for (i, line) in header.lines().enumerate() {
+        if i == 0 {
+            if line != "P7" {
+                return Err(TestHarnessError::InvalidGoldenImage);
+            }
+
+            continue;
+        }
+
+        let mut items = line.split_whitespace();
+        let key = items.next();
+        let value = items.next().and_then(|it| it.parse::<u32>().ok());
+        match key {
+            Some("WIDTH") => width = value.unwrap_or(0),
+            Some("HEIGHT") => height = value.unwrap_or(0),
+            Some("DEPTH") => depth = value.unwrap_or(0),
+            Some("MAXVAL") if value != Some(255) => {
+                return Err(TestHarnessError::InvalidGoldenImage);
+            }
+            _ => (),
+        }
+    }
+
+    let pixels = &bytes[offset + end.len()..];
+    if depth != 4 || width == 0 || pixels.len() != width as usize * height as usize * 4 {
+        return Err(TestHarnessError::InvalidGoldenImage);
+    }
+
+    Ok(RgbaImage {
+        pixels: pixels.to_vec(),
+        width,
+        height,
+    })
+}
+
+/// Writes a golden image, for example the last frame of a run that was
+/// checked by eye, see [load_golden].
+pub fn save_golden<P: AsRef<Path>>(path: P, image: &RgbaImage) -> Result<(), TestHarnessError> {
+    let mut file = fs::File::create(path)?;
+    write!(
+        file,
+        "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+        image.width, image.height
+    )?;
+
+    file.write_all(&image.pixels)?;
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct Collected {
+    report: Mutex<TestReport>,
+}
+
+/// Counts the frames and keeps the last video frame, the receiver outputs
+/// RGBA frames in the system memory.
+struct HeadlessSink(Arc<Collected>);
+
+impl MediaStreamSink for HeadlessSink {
+    fn video(&self, frame: &VideoFrame) -> bool {
+        let mut report = self.0.report.lock();
+        report.video_frames += 1;
+
+        if frame.format == VideoFormat::RGBA && frame.sub_format == VideoSubFormat::SW {
+            let image = report.last_frame.get_or_insert_with(|| RgbaImage {
+                width: 0,
+                height: 0,
+                pixels: Vec::new(),
+            });
+
+            image.width = frame.width;
+            image.height = frame.height;
+            image.pixels.clear();
+
+            let data = frame.data[0] as *const u8;
+            for y in 0..frame.height as usize {
+                image.pixels.extend_from_slice(unsafe {
+                    from_raw_parts(
+                        data.add(y * frame.linesize[0] as usize),
+                        frame.width as usize * 4,
+                    )
+                });
+            }
+        }
+
+        true
+    }
+
+    fn audio(&self, _frame: &AudioFrame) -> bool {
+        self.0.report.lock().audio_frames += 1;
+
+        true
+    }
+}
+
+struct HeadlessObserver(Arc<Collected>);
+
+impl MediaStreamObserver for HeadlessObserver {
+    fn close(&self, reason: CloseReason) {
+        self.0.report.lock().close_reason.replace(reason);
+    }
+}
+
+/// Runs a sender and its receivers for the duration of the options, and
+/// reports what the receivers got. [startup](crate::startup) must be called
+/// before.
+pub struct TestHarness;
+
+impl TestHarness {
+    /// Reports what the first receiver got.
+    pub fn run(options: &TestHarnessOptions) -> Result<TestReport, TestHarnessError> {
+        Ok(Self::run_all(options)?.remove(0))
+    }
+
+    /// Reports what each receiver got, in the order of the receivers, there
+    /// is always at least one receiver.
+    pub fn run_all(options: &TestHarnessOptions) -> Result<Vec<TestReport>, TestHarnessError> {
+        let sender = create_sender(
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            &HylaranaSenderOptions {
+                media: HylaranaSenderMediaOptions {
+                    video: options
+                        .video
+                        .clone()
+                        .map(|options| HylaranaSenderTrackOptions {
+                            source: Source::test_pattern(),
+                            options,
+                        }),
+                    audio: options.audio.map(|options| HylaranaSenderTrackOptions {
+                        source: Source::test_pattern(),
+                        options,
+                    }),
+                },
+                transport: options.transport.clone(),
+                input: SenderInput::Capture,
+                mode: None,
+                overlay: None,
+                privacy: None,
+                allow_list: None,
+                failover: SourceFailover::Close,
+                allow_display_sleep: true,
+                bandwidth_cap: None,
+            },
+            (),
+            (),
+        )?;
+
+        let mut receivers = Vec::new();
+        for _ in 0..options.receivers.max(1) {
+            let collected = Arc::new(Collected::default());
+            let receiver = create_receiver(
+                sender.local_addr(),
+                &HylaranaReceiverOptions {
+                    codec: options.decoder,
+                    transport: options.transport.clone(),
+                    video_output: VideoOutput::Rgba,
+                    reconnect: None,
+                    timeshift_seconds: 0,
+                    sink_queue: None,
+                    packet_sink: None,
+                    sync_group: options.sync_group,
+                    rendition: RenditionSelection::Auto,
+                    mode: None,
+                    allow_display_sleep: true,
+                },
+                sender.get_description(),
+                HeadlessSink(collected.clone()),
+                HeadlessObserver(collected.clone()),
+            )?;
+
+            receivers.push((receiver, collected));
+        }
+
+        thread::sleep(options.duration);
+
+        // The reports are taken before the sender is closed, so that the end of
+        // the stream is not reported as the close reason.
+        let reports = receivers
+            .iter()
+            .map(|(_, collected)| collected.report.lock().clone())
+            .collect::<Vec<_>>();
+
+        drop(receivers);
+        drop(sender);
+
+        for report in &reports {
+            tracing::info!(
+                "test harness is done, video frames={}, audio frames={}",
+                report.video_frames,
+                report.audio_frames
+            );
+        }
+
+        Ok(reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{path::PathBuf, sync::Once};
+
+    /// The golden image of the test pattern in the size of [options], the
+    /// lossless conversion of the generated frame.
+    const PATTERN_GOLDEN: &str = "pattern_320x180.pam";
+
+    fn startup() {
+        static STARTUP: Once = Once::new();
+
+        STARTUP.call_once(|| crate::startup().unwrap());
+    }
+
+    fn golden(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("golden")
+            .join(name)
+    }
+
+    fn options() -> TestHarnessOptions {
+        let mut options = TestHarnessOptions::default();
+        if let Some(video) = options.video.as_mut() {
+            video.width = 320;
+            video.height = 180;
+        }
+
+        options
+    }
+
+    #[test]
+    fn golden_image_round_trips() {
+        let image = load_golden(golden(PATTERN_GOLDEN)).unwrap();
+        assert_eq!((image.width, image.height), (320, 180));
+
+        let path = std::env::temp_dir().join(format!("hylarana-{}.pam", std::process::id()));
+        save_golden(&path, &image).unwrap();
+
+        let saved = load_golden(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(psnr(&saved, &image, image.height).unwrap(), f64::INFINITY);
+    }
+
+    #[test]
+    fn psnr_only_compares_the_first_rows() {
+        let image = load_golden(golden(PATTERN_GOLDEN)).unwrap();
+
+        let mut changed = image.clone();
+        let rows = image.height / 2;
+        for it in &mut changed.pixels[(image.width * rows * 4) as usize..] {
+            *it = !*it;
+        }
+
+        assert_eq!(psnr(&changed, &image, rows).unwrap(), f64::INFINITY);
+        assert!(psnr(&changed, &image, image.height).unwrap() < 10.0);
+        assert!(matches!(
+            psnr(
+                &image,
+                &RgbaImage {
+                    width: 2,
+                    height: 2,
+                    pixels: vec![0; 16],
+                },
+                2
+            ),
+            Err(TestHarnessError::SizeMismatch(320, 180, 2, 2))
+        ));
+    }
+
+    #[test]
+    fn pattern_matches_the_golden_image() {
+        startup();
+
+        let report = TestHarness::run(&options()).unwrap();
+        assert!(report.close_reason.is_none(), "{:?}", report.close_reason);
+
+        report.expect_frames(30, 50).unwrap();
+        report
+            .expect_golden(&load_golden(golden(PATTERN_GOLDEN)).unwrap(), 30.0)
+            .unwrap();
+    }
+
+    #[test]
+    fn pattern_video_only() {
+        startup();
+
+        let report = TestHarness::run(&TestHarnessOptions {
+            audio: None,
+            ..options()
+        })
+        .unwrap();
+
+        report.expect_frames(30, 0).unwrap();
+        assert_eq!(report.audio_frames, 0);
+        report
+            .expect_golden(&load_golden(golden(PATTERN_GOLDEN)).unwrap(), 30.0)
+            .unwrap();
+    }
+
+    #[test]
+    fn pattern_audio_only() {
+        startup();
+
+        let report = TestHarness::run(&TestHarnessOptions {
+            video: None,
+            ..options()
+        })
+        .unwrap();
+
+        report.expect_frames(0, 50).unwrap();
+        assert_eq!(report.video_frames, 0);
+        assert!(report.last_frame.is_none());
+    }
+
+    #[test]
+    fn sync_group_plays_on_every_receiver() {
+        startup();
+
+        let reports = TestHarness::run_all(&TestHarnessOptions {
+            receivers: 2,
+            sync_group: Some(SyncGroupOptions::default()),
+            ..options()
+        })
+        .unwrap();
+
+        assert_eq!(reports.len(), 2);
+        for report in reports {
+            assert!(report.close_reason.is_none(), "{:?}", report.close_reason);
+
+            report.expect_frames(30, 50).unwrap();
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use transport::{Buffer, BufferType, StreamType};
+
+/// The encoded packets of the last seconds of the stream, the receiver decodes
+/// the packets from here, so that the playback can fall behind the live
+/// stream when it is paused or rewound.
+///
+/// The playback is driven by the received packets, a packet is decoded when
+/// it is as old as the delay of the playback, so the playback does not move
+/// while the sender is not sending anything.
+pub(crate) struct Timeshift {
+    capacity: Duration,
+    packets: VecDeque<(Instant, Buffer<Bytes>)>,
+    /// The index of the next packet to decode, the packets before it are kept
+    /// for rewinding.
+    cursor: usize,
+    /// How far the playback is behind the live stream.
+    delay: Duration,
+    paused: Option<Instant>,
+    /// The packets are skipped until the next seek point, the decoder can not
+    /// continue from the middle of a group of pictures.
+    resync: bool,
+    video: bool,
+}
+
+impl Timeshift {
+    pub(crate) fn new(capacity: Duration) -> Self {
+        Self {
+            packets: VecDeque::new(),
+            delay: Duration::ZERO,
+            paused: None,
+            resync: false,
+            video: false,
+            cursor: 0,
+            capacity,
+        }
+    }
+
+    /// The decoding can only start from the configuration in front of a key
+    /// frame, or from any packet if there is no video.
+    fn is_seek_point(&self, buffer: &Buffer<Bytes>) -> bool {
+        if self.video {
+            buffer.stream == StreamType::Video && buffer.ty == BufferType::Config
+        } else {
+            true
+        }
+    }
+
+    /// How far the playback is behind the live stream, including the time it
+    /// has been paused.
+    fn behind(&self, now: Instant) -> Duration {
+        match self.paused {
+            Some(paused) => self.delay + now.duration_since(paused),
+            None => self.delay,
+        }
+    }
+
+    pub(crate) fn is_live(&self) -> bool {
+        self.paused.is_none() && self.delay.is_zero()
+    }
+
+    pub(crate) fn push(&mut self, buffer: Buffer<Bytes>) {
+        let now = Instant::now();
+
+        if buffer.stream == StreamType::Video {
+            self.video = true;
+        }
+
+        self.packets.push_back((now, buffer));
+
+        while let Some((received, _)) = self.packets.front() {
+            if now.duration_since(*received) <= self.capacity {
+                break;
+            }
+
+            self.packets.pop_front();
+
+            // A packet that is not played yet is dropped, this happens when the
+            // playback is paused for longer than the buffer.
+            if self.cursor > 0 {
+                self.cursor -= 1;
+            } else {
+                self.resync = true;
+            }
+        }
+    }
+
+    /// Takes the next packet that is due for decoding.
+    pub(crate) fn next(&mut self) -> Option<Buffer<Bytes>> {
+        if self.paused.is_some() {
+            return None;
+        }
+
+        let now = Instant::now();
+        while let Some((received, buffer)) = self.packets.get(self.cursor) {
+            if *received + self.delay > now {
+                break;
+            }
+
+            let seek_point = self.is_seek_point(buffer);
+            let buffer = buffer.clone();
+
+            self.cursor += 1;
+
+            if self.resync {
+                if !seek_point {
+                    continue;
+                }
+
+                self.resync = false;
+            }
+
+            return Some(buffer);
+        }
+
+        None
+    }
+
+    pub(crate) fn pause(&mut self) {
+        if self.paused.is_none() {
+            self.paused = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn resume(&mut self) {
+        let now = Instant::now();
+
+        if let Some(paused) = self.paused.take() {
+            self.delay += now.duration_since(paused);
+
+            // The packets that were dropped while paused can not be played, the
+            // playback continues from the oldest packet.
+            if let Some((received, _)) = self.packets.get(self.cursor) {
+                self.delay = self.delay.min(now.duration_since(*received));
+            }
+        }
+    }
+
+    /// Rewinds the playback to the last seek point that is at least `offset`
+    /// before the current position, or to the oldest seek point, the offset
+    /// never goes further than the capacity.
+    pub(crate) fn seek_back(&mut self, offset: Duration) {
+        let offset = offset.min(self.capacity);
+        let now = Instant::now();
+        let target = now.checked_sub(self.behind(now) + offset);
+
+        let mut index = None;
+        for (i, (received, buffer)) in self.packets.iter().enumerate() {
+            if !self.is_seek_point(buffer) {
+                continue;
+            }
+
+            if index.is_some() && target.is_none_or(|it| *received > it) {
+                break;
+            }
+
+            index = Some(i);
+        }
+
+        if let Some(index) = index {
+            self.cursor = index;
+            self.resync = false;
+            self.delay = now.duration_since(self.packets[index].0);
+
+            if self.paused.is_some() {
+                self.paused = Some(now);
+            }
+        }
+    }
+
+    /// Returns to the live stream, the playback starts again from the next
+    /// seek point that is received.
+    pub(crate) fn go_live(&mut self) {
+        self.cursor = self.packets.len();
+        self.delay = Duration::ZERO;
+        self.paused = None;
+        self.resync = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(ty: BufferType, timestamp: u64) -> Buffer<Bytes> {
+        Buffer {
+            stream: StreamType::Video,
+            rendition: 0,
+            data: Bytes::new(),
+            timestamp,
+            ty,
+        }
+    }
+
+    /// Moves the clock of the buffer forward by making everything in it older.
+    fn elapse(timeshift: &mut Timeshift, duration: Duration) {
+        for (received, _) in timeshift.packets.iter_mut() {
+            *received -= duration;
+        }
+
+        if let Some(paused) = timeshift.paused.as_mut() {
+            *paused -= duration;
+        }
+    }
+
+    fn next(timeshift: &mut Timeshift) -> Option<u64> {
+        timeshift.next().map(|it| it.timestamp)
+    }
+
+    #[test]
+    fn plays_live() {
+        let mut timeshift = Timeshift::new(Duration::from_secs(10));
+        timeshift.push(packet(BufferType::Config, 0));
+        timeshift.push(packet(BufferType::Partial, 1));
+
+        assert!(timeshift.is_live());
+        assert_eq!(next(&mut timeshift), Some(0));
+        assert_eq!(next(&mut timeshift), Some(1));
+        assert_eq!(next(&mut timeshift), None);
+    }
+
+    #[test]
+    fn resumes_behind_the_live_stream() {
+        let mut timeshift = Timeshift::new(Duration::from_secs(10));
+        timeshift.push(packet(BufferType::Config, 0));
+        assert_eq!(next(&mut timeshift), Some(0));
+
+        timeshift.pause();
+        timeshift.push(packet(BufferType::Partial, 1));
+        assert_eq!(next(&mut timeshift), None);
+
+        elapse(&mut timeshift, Duration::from_secs(2));
+        timeshift.push(packet(BufferType::Partial, 2));
+        assert_eq!(next(&mut timeshift), None);
+
+        timeshift.resume();
+        assert!(!timeshift.is_live());
+        assert!(timeshift.delay >= Duration::from_secs(2));
+
+        // The packet received at the pause is due, the newest one is not.
+        assert_eq!(next(&mut timeshift), Some(1));
+        assert_eq!(next(&mut timeshift), None);
+    }
+
+    #[test]
+    fn seeks_back_to_the_seek_point() {
+        let mut timeshift = Timeshift::new(Duration::from_secs(10));
+        for timestamp in 0..3 {
+            timeshift.push(packet(BufferType::Config, timestamp));
+            timeshift.push(packet(BufferType::Partial, timestamp));
+            elapse(&mut timeshift, Duration::from_secs(1));
+        }
+
+        while timeshift.next().is_some() {}
+
+        // The seek points are 3, 2 and 1 seconds old.
+        timeshift.seek_back(Duration::from_millis(1500));
+        assert_eq!(timeshift.cursor, 2);
+        assert_eq!(next(&mut timeshift), Some(1));
+        assert_eq!(next(&mut timeshift), Some(1));
+        assert_eq!(next(&mut timeshift), None);
+
+        // Too far back, the playback goes to the oldest seek point.
+        timeshift.seek_back(Duration::MAX);
+        assert_eq!(timeshift.cursor, 0);
+        assert_eq!(next(&mut timeshift), Some(0));
+    }
+
+    #[test]
+    fn seeks_back_while_paused() {
+        let mut timeshift = Timeshift::new(Duration::from_secs(10));
+        timeshift.push(packet(BufferType::Config, 0));
+        elapse(&mut timeshift, Duration::from_secs(1));
+        timeshift.push(packet(BufferType::Config, 1));
+        while timeshift.next().is_some() {}
+
+        timeshift.pause();
+        timeshift.seek_back(Duration::from_millis(500));
+        assert_eq!(next(&mut timeshift), None);
+
+        timeshift.resume();
+        assert_eq!(next(&mut timeshift), Some(0));
+    }
+
+    #[test]
+    fn goes_live_from_the_next_seek_point() {
+        let mut timeshift = Timeshift::new(Duration::from_secs(10));
+        timeshift.push(packet(BufferType::Config, 0));
+        elapse(&mut timeshift, Duration::from_secs(1));
+        timeshift.seek_back(Duration::from_secs(1));
+        timeshift.pause();
+
+        timeshift.go_live();
+        assert!(timeshift.is_live());
+        assert_eq!(next(&mut timeshift), None);
+
+        timeshift.push(packet(BufferType::Partial, 1));
+        timeshift.push(packet(BufferType::Config, 2));
+        assert_eq!(next(&mut timeshift), Some(2));
+    }
+
+    #[test]
+    fn drops_the_packets_while_paused_for_too_long() {
+        let mut timeshift = Timeshift::new(Duration::from_secs(2));
+        timeshift.push(packet(BufferType::Config, 0));
+        assert_eq!(next(&mut timeshift), Some(0));
+
+        timeshift.pause();
+        timeshift.push(packet(BufferType::Partial, 1));
+        elapse(&mut timeshift, Duration::from_secs(3));
+
+        // Both the played and the unplayed packets are dropped.
+        timeshift.push(packet(BufferType::Partial, 2));
+        timeshift.push(packet(BufferType::Config, 3));
+        assert_eq!(timeshift.packets.len(), 2);
+        assert_eq!(timeshift.cursor, 0);
+        assert!(timeshift.resync);
+
+        // The playback continues from the oldest packet, from its seek point.
+        timeshift.resume();
+        assert_eq!(next(&mut timeshift), Some(3));
+        assert_eq!(next(&mut timeshift), None);
+    }
+}
@@ -0,0 +1,39 @@
+use std::{fs::File, io, io::BufWriter, path::Path};
+
+use thiserror::Error;
+use tracing::subscriber::SetGlobalDefaultError;
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::prelude::*;
+
+#[derive(Debug, Error)]
+pub enum ChromeTraceError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    SetGlobalDefaultError(#[from] SetGlobalDefaultError),
+}
+
+/// The running trace, the trace file is completed when it is dropped.
+pub struct ChromeTrace(#[allow(unused)] FlushGuard);
+
+/// Records the spans of the pipeline to a file in the chrome trace format,
+/// which can be opened by perfetto or `chrome://tracing`. Each frame is a
+/// span of the capture with the sequence of the frame, the encoding and the
+/// sending are its children, and each received packet is a span with the
+/// timestamp of the encoder, the decoding and the rendering are its children,
+/// so that a spike of the latency can be attributed to the stage.
+///
+/// This sets the global subscriber of tracing, so it can only be started once
+/// in the process. The crates of the workspace log with tracing, with the
+/// `log-always` feature the events are passed to the `log` crate too, so the
+/// logger of [common::logger] still sees all the logs of the library.
+pub fn start_chrome_trace<P: AsRef<Path>>(path: P) -> Result<ChromeTrace, ChromeTraceError> {
+    let (layer, guard) = ChromeLayerBuilder::new()
+        .writer(BufWriter::new(File::create(path)?))
+        .include_args(true)
+        .build();
+
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))?;
+
+    Ok(ChromeTrace(guard))
+}
@@ -1,5 +1,5 @@
 #[cfg(target_os = "windows")]
-use common::win32::Direct3DDevice;
+use common::win32::{Direct3DDevice, GpuAdapter, GpuSelector};
 
 #[cfg(target_os = "windows")]
 use parking_lot::RwLock;
@@ -18,3 +18,16 @@ pub(crate) fn get_direct3d() -> Direct3DDevice {
 
     DIRECT_3D_DEVICE.read().as_ref().unwrap().clone()
 }
+
+/// Replace the global D3D device with one on the selected adapter, the
+/// senders, receivers and players that already exist keep the previous device.
+#[cfg(target_os = "windows")]
+pub(crate) fn set_direct3d(
+    selector: GpuSelector,
+) -> common::win32::windows::core::Result<GpuAdapter> {
+    let direct3d = Direct3DDevice::with_adapter(selector)?;
+    let adapter = direct3d.adapter()?;
+
+    DIRECT_3D_DEVICE.write().replace(direct3d);
+    Ok(adapter)
+}
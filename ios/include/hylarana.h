@@ -0,0 +1,80 @@
+#ifndef HYLARANA_H
+#define HYLARANA_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct HylaranaReceiver HylaranaReceiver;
+typedef struct HylaranaDiscovery HylaranaDiscovery;
+
+typedef struct HylaranaReceiverCallbacks {
+    const void* ctx;
+    /// Decoded audio, 16 bit interleaved stereo pcm, `frames` is the number of
+    /// samples of a single channel.
+    void (*audio)(const void* ctx, uint32_t sample_rate, uint32_t frames, const int16_t* data);
+    /// The sender is closed or the network is disconnected.
+    void (*close)(const void* ctx);
+} HylaranaReceiverCallbacks;
+
+/// The strings are only valid during the callback.
+typedef struct HylaranaDiscoveryCallbacks {
+    const void* ctx;
+    void (*online)(const void* ctx, const char* local_id, const char* id, const char* ip);
+    void (*offline)(const void* ctx, const char* local_id, const char* id, const char* ip);
+    void (*metadata)(const void* ctx,
+                     const char* local_id,
+                     const char* id,
+                     const char* ip,
+                     const uint8_t* metadata,
+                     size_t size);
+} HylaranaDiscoveryCallbacks;
+
+/// Initialize the environment, this must be called once before any other
+/// function.
+bool hylarana_startup(void);
+
+/// Clean up the environment, no other function can be called after this.
+void hylarana_shutdown(void);
+
+/// Creates the receiver, the video is rendered to the UIView and the audio is
+/// passed to the callbacks, returns null if the creation fails.
+///
+/// `options` is the transport options as json, the size is the size of the
+/// view in pixels. The renderer adds a metal layer to the view, so this must
+/// be called on the main thread.
+HylaranaReceiver* hylarana_receiver_create(const char* addr,
+                                           const char* options,
+                                           void* view,
+                                           uint32_t width,
+                                           uint32_t height,
+                                           HylaranaReceiverCallbacks callbacks);
+
+/// The view has been resized, the size is in pixels.
+void hylarana_receiver_resize(const HylaranaReceiver* receiver, uint32_t width, uint32_t height);
+
+/// release the receiver.
+void hylarana_receiver_release(HylaranaReceiver* receiver);
+
+/// Register the discovery service, the senders in the local network are
+/// reported to the callbacks, returns null if the creation fails.
+HylaranaDiscovery* hylarana_discovery_create(const char* bind,
+                                             HylaranaDiscoveryCallbacks callbacks);
+
+/// Publish the metadata of this device to the other devices.
+bool hylarana_discovery_set_metadata(const HylaranaDiscovery* discovery,
+                                     const uint8_t* metadata,
+                                     size_t size);
+
+/// release the discovery service.
+void hylarana_discovery_release(HylaranaDiscovery* discovery);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif // HYLARANA_H
@@ -0,0 +1,147 @@
+#![cfg(any(target_os = "ios", target_os = "tvos"))]
+
+mod receiver;
+
+use std::{
+    ffi::{CStr, c_char, c_void},
+    net::SocketAddr,
+    ptr::null_mut,
+    slice::from_raw_parts,
+};
+
+use anyhow::{Result, anyhow};
+use common::{Size, logger, runtime::get_runtime_handle};
+use discovery::{
+    DiscoveryService,
+    ffi::{DiscoveryServiceObserver, HylaranaDiscoveryCallbacks},
+};
+
+use self::receiver::{HylaranaReceiverCallbacks, Receiver};
+
+fn ok_or_check<F, T>(func: F) -> Option<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    match func() {
+        Ok(ret) => Some(ret),
+        Err(e) => {
+            log::error!("{:?}", e);
+            None
+        }
+    }
+}
+
+fn get_str<'a>(ptr: *const c_char) -> Result<&'a str> {
+    if ptr.is_null() {
+        return Err(anyhow!("string is null"));
+    }
+
+    Ok(unsafe { CStr::from_ptr(ptr) }.to_str()?)
+}
+
+/// Initialize the environment, this must be called once before any other
+/// function.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_startup() -> bool {
+    if let Err(e) = logger::init_logger(log::LevelFilter::Info, None) {
+        eprintln!("failed to init logger, err={:?}", e);
+    }
+
+    logger::enable_panic_logger();
+    transport::startup();
+
+    true
+}
+
+/// Clean up the environment, no other function can be called after this.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_shutdown() {
+    transport::shutdown();
+}
+
+/// Creates the receiver, the video is rendered to the UIView and the audio is
+/// passed to the callbacks, returns null if the creation fails.
+///
+/// The renderer adds a metal layer to the view, so this must be called on the
+/// main thread.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_create(
+    addr: *const c_char,
+    options: *const c_char,
+    view: *mut c_void,
+    width: u32,
+    height: u32,
+    callbacks: HylaranaReceiverCallbacks,
+) -> *mut Receiver {
+    ok_or_check(|| {
+        Ok(Box::into_raw(Box::new(Receiver::new(
+            get_str(addr)?.parse()?,
+            serde_json::from_str(get_str(options)?)?,
+            view,
+            Size { width, height },
+            callbacks,
+        )?)))
+    })
+    .unwrap_or_else(null_mut)
+}
+
+/// The view has been resized, the size is in pixels.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_resize(receiver: *const Receiver, width: u32, height: u32) {
+    assert!(!receiver.is_null());
+
+    unsafe { &*receiver }.resize(Size { width, height });
+}
+
+/// release the receiver.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_receiver_release(receiver: *mut Receiver) {
+    assert!(!receiver.is_null());
+
+    drop(unsafe { Box::from_raw(receiver) });
+}
+
+/// Register the discovery service, the senders in the local network are
+/// reported to the callbacks, returns null if the creation fails.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_discovery_create(
+    bind: *const c_char,
+    callbacks: HylaranaDiscoveryCallbacks,
+) -> *mut DiscoveryService {
+    ok_or_check(|| {
+        Ok(Box::into_raw(Box::new(get_runtime_handle().block_on(
+            DiscoveryService::new(
+                get_str(bind)?.parse::<SocketAddr>()?,
+                DiscoveryServiceObserver(callbacks),
+            ),
+        )?)))
+    })
+    .unwrap_or_else(null_mut)
+}
+
+/// Publish the metadata of this device to the other devices.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_discovery_set_metadata(
+    discovery: *const DiscoveryService,
+    metadata: *const u8,
+    size: usize,
+) -> bool {
+    assert!(!discovery.is_null());
+
+    if metadata.is_null() {
+        return false;
+    }
+
+    let metadata = unsafe { from_raw_parts(metadata, size) }.to_vec();
+    get_runtime_handle().block_on(unsafe { &*discovery }.set_metadata(metadata));
+
+    true
+}
+
+/// release the discovery service.
+#[unsafe(no_mangle)]
+extern "C" fn hylarana_discovery_release(discovery: *mut DiscoveryService) {
+    assert!(!discovery.is_null());
+
+    drop(unsafe { Box::from_raw(discovery) });
+}
@@ -0,0 +1,256 @@
+use std::{ffi::c_void, net::SocketAddr, ptr::NonNull, slice::from_raw_parts, sync::Arc};
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use codec::{AudioDecoder, VideoDecoder, VideoDecoderSettings};
+use common::{
+    Size,
+    codec::VideoDecoderType,
+    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    macos::{CVPixelBufferRef, PixelMomeryBuffer},
+};
+
+use parking_lot::Mutex;
+use renderer::{
+    Renderer, RendererOptions, RendererPresentOptions, RendererSourceOptions,
+    RendererSurfaceOptions, Texture, Texture2DBuffer, Texture2DResource,
+    raw_window_handle::{
+        DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
+        RawWindowHandle, UiKitDisplayHandle, UiKitWindowHandle, WindowHandle,
+    },
+};
+
+use transport::{
+    Buffer, CloseReason, StreamType, TransportOptions, TransportReceiver, TransportReceiverSink,
+};
+
+/// The callbacks of the receiver, `ctx` is passed back to every callback
+/// as it is.
+#[repr(C)]
+pub struct HylaranaReceiverCallbacks {
+    pub ctx: *const c_void,
+    /// Decoded audio, 16 bit interleaved stereo pcm, `frames` is the number of
+    /// samples of a single channel.
+    pub audio:
+        Option<extern "C" fn(ctx: *const c_void, sample_rate: u32, frames: u32, data: *const i16)>,
+    /// The sender is closed or the network is disconnected.
+    pub close: Option<extern "C" fn(ctx: *const c_void)>,
+}
+
+unsafe impl Send for HylaranaReceiverCallbacks {}
+unsafe impl Sync for HylaranaReceiverCallbacks {}
+
+/// The UIView the video is rendered to, wgpu adds a metal layer to the view.
+struct UiKitView(NonNull<c_void>);
+
+unsafe impl Send for UiKitView {}
+unsafe impl Sync for UiKitView {}
+
+impl HasWindowHandle for UiKitView {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        Ok(unsafe {
+            WindowHandle::borrow_raw(RawWindowHandle::UiKit(UiKitWindowHandle::new(self.0)))
+        })
+    }
+}
+
+impl HasDisplayHandle for UiKitView {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        let handle = RawDisplayHandle::UiKit(UiKitDisplayHandle::new());
+
+        Ok(unsafe { DisplayHandle::borrow_raw(handle) })
+    }
+}
+
+fn render(renderer: &mut Renderer, frame: &VideoFrame) -> Result<()> {
+    match frame.sub_format {
+        // VideoToolbox outputs NV12 pixel buffers, metal cannot sample the two planes of
+        // the pixel buffer as a single texture, so the planes are uploaded from the
+        // locked memory of the pixel buffer.
+        VideoSubFormat::CvPixelBufferRef => {
+            let pixel_buffer = PixelMomeryBuffer::from(frame.data[0] as CVPixelBufferRef);
+            let linesize = pixel_buffer.linesize.map(|it| it as u32);
+            let buffer = Texture2DBuffer {
+                buffers: &pixel_buffer.data,
+                linesize: &linesize,
+            };
+
+            renderer.submit(match pixel_buffer.format {
+                VideoFormat::NV12 => Texture::Nv12(Texture2DResource::Buffer(buffer)),
+                VideoFormat::I420 => Texture::I420(buffer),
+                format => return Err(anyhow!("unsupported pixel buffer format={:?}", format)),
+            })?;
+        }
+        VideoSubFormat::SW if frame.format == VideoFormat::NV12 => {
+            let buffers = [
+                unsafe {
+                    from_raw_parts(
+                        frame.data[0] as *const u8,
+                        frame.linesize[0] as usize * frame.height as usize,
+                    )
+                },
+                unsafe {
+                    from_raw_parts(
+                        frame.data[1] as *const u8,
+                        frame.linesize[1] as usize * (frame.height as usize / 2),
+                    )
+                },
+            ];
+
+            renderer.submit(Texture::Nv12(Texture2DResource::Buffer(Texture2DBuffer {
+                buffers: &buffers,
+                linesize: &frame.linesize,
+            })))?;
+        }
+        _ => {
+            return Err(anyhow!(
+                "unsupported video frame, format={:?}, sub_format={:?}",
+                frame.format,
+                frame.sub_format
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+struct ReceiverSinker {
+    callbacks: HylaranaReceiverCallbacks,
+    renderer: Arc<Mutex<Renderer<'static>>>,
+    video_decoder: VideoDecoder,
+    audio_decoder: AudioDecoder,
+}
+
+impl ReceiverSinker {
+    fn audio(&mut self) {
+        while let Some(frame) = self.audio_decoder.read() {
+            if let Some(callback) = self.callbacks.audio {
+                callback(
+                    self.callbacks.ctx,
+                    frame.sample_rate,
+                    frame.frames,
+                    frame.data,
+                );
+            }
+        }
+    }
+}
+
+impl TransportReceiverSink for ReceiverSinker {
+    fn sink(&mut self, buffer: Buffer<Bytes>) -> bool {
+        match buffer.stream {
+            StreamType::Video => {
+                if let Err(e) = self.video_decoder.decode(&buffer.data, buffer.timestamp) {
+                    log::error!("video decode error={:?}", e);
+
+                    return false;
+                } else {
+                    while let Some(frame) = self.video_decoder.read() {
+                        if let Err(e) = render(&mut self.renderer.lock(), frame) {
+                            log::error!("video render error={:?}", e);
+
+                            return false;
+                        }
+                    }
+                }
+            }
+            StreamType::Audio => {
+                if let Err(e) = self.audio_decoder.decode(&buffer.data, buffer.timestamp) {
+                    log::error!("audio decode error={:?}", e);
+
+                    return false;
+                } else {
+                    self.audio();
+                }
+            }
+        }
+
+        true
+    }
+
+    fn lost(&mut self, stream: StreamType, count: u32) -> bool {
+        // The video decoder waits for the next key frame on its own, only the audio
+        // gap has to be filled.
+        if stream == StreamType::Audio {
+            for _ in 0..count.min(AudioDecoder::MAX_CONCEALED_PACKETS) {
+                if let Err(e) = self.audio_decoder.conceal() {
+                    log::error!("audio conceal error={:?}", e);
+
+                    return false;
+                }
+
+                self.audio();
+            }
+        }
+
+        true
+    }
+
+    fn close(&mut self, reason: CloseReason) {
+        log::info!("receiver is closed, reason={:?}", reason);
+
+        if let Some(callback) = self.callbacks.close {
+            callback(self.callbacks.ctx);
+        }
+    }
+}
+
+/// Receiver that decodes the video with VideoToolbox and renders it to a
+/// UIView, the audio is decoded and passed to the callbacks.
+pub struct Receiver {
+    renderer: Arc<Mutex<Renderer<'static>>>,
+    #[allow(unused)]
+    transport: TransportReceiver,
+}
+
+impl Receiver {
+    /// The renderer is created here, and the metal layer is added to the view,
+    /// so this needs to be called on the main thread.
+    pub fn new(
+        addr: SocketAddr,
+        options: TransportOptions,
+        view: *mut c_void,
+        size: Size,
+        callbacks: HylaranaReceiverCallbacks,
+    ) -> Result<Self> {
+        let view = NonNull::new(view).ok_or_else(|| anyhow!("view is null"))?;
+        let renderer = Arc::new(Mutex::new(Renderer::new(RendererOptions {
+            surface: RendererSurfaceOptions {
+                window: UiKitView(view),
+                size,
+                present: RendererPresentOptions::default(),
+                backend: None,
+            },
+            source: RendererSourceOptions {
+                format: VideoFormat::NV12,
+                sub_format: VideoSubFormat::SW,
+                size,
+            },
+        })?));
+
+        Ok(Self {
+            renderer: renderer.clone(),
+            transport: TransportReceiver::new(
+                addr,
+                options,
+                ReceiverSinker {
+                    video_decoder: VideoDecoder::new(VideoDecoderSettings {
+                        codec: VideoDecoderType::VideoToolBox,
+                        cuda_device: None,
+                        software: false,
+                    })?,
+                    // The audio callback only takes stereo samples, opus decodes the mono
+                    // streams as stereo.
+                    audio_decoder: AudioDecoder::new(2)?,
+                    callbacks,
+                    renderer,
+                },
+            )?,
+        })
+    }
+
+    /// The size of the view in pixels, the video keeps its aspect ratio.
+    pub fn resize(&self, size: Size) {
+        self.renderer.lock().resize(size);
+    }
+}
@@ -0,0 +1,90 @@
+use std::net::IpAddr;
+
+use hylarana::DiscoveryObserver;
+use napi::{
+    bindgen_prelude::Buffer,
+    threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
+
+use napi_derive::napi;
+
+#[napi(object)]
+pub struct DiscoveryDevice {
+    pub local_id: String,
+    pub id: String,
+    pub ip: String,
+}
+
+#[napi(object)]
+pub struct DiscoveryMetadata {
+    pub local_id: String,
+    pub id: String,
+    pub ip: String,
+    pub metadata: Buffer,
+}
+
+/// The callbacks of the discovery service, all callbacks are optional.
+#[napi(object, object_to_js = false)]
+pub struct DiscoveryCallbacks {
+    #[napi(ts_type = "(device: DiscoveryDevice) => void")]
+    pub online: Option<ThreadsafeFunction<DiscoveryDevice, ErrorStrategy::Fatal>>,
+    #[napi(ts_type = "(device: DiscoveryDevice) => void")]
+    pub offline: Option<ThreadsafeFunction<DiscoveryDevice, ErrorStrategy::Fatal>>,
+    #[napi(ts_type = "(device: DiscoveryMetadata) => void")]
+    pub metadata: Option<ThreadsafeFunction<DiscoveryMetadata, ErrorStrategy::Fatal>>,
+}
+
+pub struct DiscoveryServiceObserver(pub DiscoveryCallbacks);
+
+impl DiscoveryObserver for DiscoveryServiceObserver {
+    async fn online(&self, local_id: &str, id: &str, ip: IpAddr) {
+        log::info!("devices manager device online, id={}, ip={}", id, ip);
+
+        if let Some(callback) = &self.0.online {
+            callback.call(
+                DiscoveryDevice {
+                    local_id: local_id.to_string(),
+                    id: id.to_string(),
+                    ip: ip.to_string(),
+                },
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+    }
+
+    async fn offline(&self, local_id: &str, id: &str, ip: IpAddr) {
+        log::info!("devices manager device offline, id={}, ip={}", id, ip);
+
+        if let Some(callback) = &self.0.offline {
+            callback.call(
+                DiscoveryDevice {
+                    local_id: local_id.to_string(),
+                    id: id.to_string(),
+                    ip: ip.to_string(),
+                },
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+    }
+
+    async fn on_metadata(&self, local_id: &str, id: &str, ip: IpAddr, metadata: Vec<u8>) {
+        log::info!(
+            "devices manager device on metadata, id={}, ip={} metadata={:?}",
+            id,
+            ip,
+            std::str::from_utf8(&metadata)
+        );
+
+        if let Some(callback) = &self.0.metadata {
+            callback.call(
+                DiscoveryMetadata {
+                    local_id: local_id.to_string(),
+                    id: id.to_string(),
+                    ip: ip.to_string(),
+                    metadata: metadata.into(),
+                },
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+    }
+}
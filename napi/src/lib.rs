@@ -0,0 +1,330 @@
+mod discovery;
+mod stream;
+
+use std::{fmt::Display, net::SocketAddr};
+
+use common::logger;
+use napi::{
+    Error, Result,
+    bindgen_prelude::{Buffer, Either},
+};
+
+use napi_derive::napi;
+use parking_lot::Mutex;
+
+use self::{
+    discovery::{DiscoveryCallbacks, DiscoveryServiceObserver},
+    stream::HylaranaStreamCallbacks,
+};
+
+fn to_error<E: Display>(e: E) -> Error {
+    Error::from_reason(e.to_string())
+}
+
+#[napi]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Error => Self::Error,
+            LogLevel::Warn => Self::Warn,
+            LogLevel::Info => Self::Info,
+            LogLevel::Debug => Self::Debug,
+            LogLevel::Trace => Self::Trace,
+        }
+    }
+}
+
+#[napi]
+pub enum SourceType {
+    Camera,
+    Screen,
+    Audio,
+}
+
+/// Initialize the logger, the logs are written to the standard output, and to
+/// the directory if the path is passed.
+#[napi]
+pub fn init_logger(level: LogLevel, path: Option<String>) -> Result<()> {
+    logger::init_logger(level.into(), path.as_deref()).map_err(to_error)?;
+
+    logger::enable_panic_logger();
+    Ok(())
+}
+
+/// Change the level of the logs at runtime, the target is the module of the
+/// logs, such as "srt" or "ffmpeg", the level of the logger is changed if it
+/// is not passed.
+#[napi]
+pub fn set_log_level(level: LogLevel, target: Option<String>) {
+    match target {
+        Some(target) => logger::set_target_level(&target, Some(level.into())),
+        None => logger::set_level(level.into()),
+    }
+}
+
+/// The last lines of the logs, the oldest first, for example to attach them
+/// to a bug report.
+#[napi]
+pub fn get_recent_logs() -> Vec<String> {
+    logger::recent_logs()
+}
+
+/// The versions, the codecs, the adapters, the last logs and the transport
+/// stats of the process, for a bug report.
+#[napi(ts_return_type = "any")]
+pub fn collect_diagnostics() -> Result<serde_json::Value> {
+    serde_json::to_value(hylarana::diagnostics::collect()).map_err(to_error)
+}
+
+/// Initialize the environment, this must be called once before any other
+/// function except the logger.
+#[napi]
+pub fn startup() -> Result<()> {
+    hylarana::startup().map_err(to_error)
+}
+
+/// Clean up the environment, no other function can be called after this.
+#[napi]
+pub fn shutdown() -> Result<()> {
+    hylarana::shutdown().map_err(to_error)
+}
+
+/// Get the capture sources of the type.
+#[napi(ts_return_type = "any[]")]
+pub fn get_sources(kind: SourceType) -> Result<serde_json::Value> {
+    let sources = hylarana::Capture::get_sources(match kind {
+        SourceType::Camera => hylarana::SourceType::Camera,
+        SourceType::Screen => hylarana::SourceType::Screen,
+        SourceType::Audio => hylarana::SourceType::Audio,
+    })
+    .map_err(to_error)?;
+
+    serde_json::to_value(sources).map_err(to_error)
+}
+
+#[napi]
+pub struct HylaranaSender(Mutex<Option<hylarana::HylaranaSender>>);
+
+#[napi]
+impl HylaranaSender {
+    /// The description of the stream, the receivers need it.
+    #[napi(ts_return_type = "any")]
+    pub fn get_description(&self) -> Result<serde_json::Value> {
+        let sender = self.0.lock();
+        let sender = sender
+            .as_ref()
+            .ok_or_else(|| to_error("sender is closed"))?;
+
+        serde_json::to_value(sender.get_description()).map_err(to_error)
+    }
+
+    /// The usage of the bandwidth, see `HylaranaSender::get_bandwidth_stats`.
+    #[napi(ts_return_type = "any")]
+    pub fn get_bandwidth_stats(&self) -> Result<serde_json::Value> {
+        let sender = self.0.lock();
+        let sender = sender
+            .as_ref()
+            .ok_or_else(|| to_error("sender is closed"))?;
+
+        serde_json::to_value(sender.get_bandwidth_stats()).map_err(to_error)
+    }
+
+    /// The port the sender is bound to.
+    #[napi]
+    pub fn get_port(&self) -> Result<u16> {
+        let sender = self.0.lock();
+        let sender = sender
+            .as_ref()
+            .ok_or_else(|| to_error("sender is closed"))?;
+
+        Ok(sender.local_addr().port())
+    }
+
+    /// Close the sender, the sender is also closed when it is garbage
+    /// collected.
+    #[napi]
+    pub fn close(&self) {
+        drop(self.0.lock().take());
+    }
+}
+
+/// Creates a sender, the options are the same as `HylaranaSenderOptions`.
+#[napi]
+pub fn create_sender(
+    bind: String,
+    #[napi(ts_arg_type = "any")] options: serde_json::Value,
+    callbacks: HylaranaStreamCallbacks,
+) -> Result<HylaranaSender> {
+    let (sink, observer) = callbacks.split();
+
+    Ok(HylaranaSender(Mutex::new(Some(
+        hylarana::create_sender(
+            bind.parse().map_err(to_error)?,
+            &serde_json::from_value(options).map_err(to_error)?,
+            sink,
+            observer,
+        )
+        .map_err(to_error)?,
+    ))))
+}
+
+#[napi]
+pub struct HylaranaReceiver(Mutex<Option<hylarana::HylaranaReceiver>>);
+
+#[napi]
+impl HylaranaReceiver {
+    /// Set the volume of the received audio, 1.0 is the original volume.
+    #[napi]
+    pub fn set_volume(&self, volume: f64) {
+        if let Some(receiver) = self.0.lock().as_ref() {
+            receiver.set_volume(volume as f32);
+        }
+    }
+
+    #[napi]
+    pub fn set_muted(&self, muted: bool) {
+        if let Some(receiver) = self.0.lock().as_ref() {
+            receiver.set_muted(muted);
+        }
+    }
+
+    /// Switch the receiver to the stream of another sender, the current
+    /// stream is kept if this fails.
+    #[napi]
+    pub fn switch_stream(
+        &self,
+        addr: String,
+        #[napi(ts_arg_type = "any")] description: serde_json::Value,
+    ) -> Result<()> {
+        if let Some(receiver) = self.0.lock().as_mut() {
+            receiver
+                .switch_stream(
+                    addr.parse().map_err(to_error)?,
+                    &serde_json::from_value(description).map_err(to_error)?,
+                )
+                .map_err(to_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pause the playback, the time shift of the receiver must be enabled.
+    #[napi]
+    pub fn pause(&self) -> Result<()> {
+        if let Some(receiver) = self.0.lock().as_ref() {
+            receiver.pause().map_err(to_error)?;
+        }
+
+        Ok(())
+    }
+
+    #[napi]
+    pub fn resume(&self) -> Result<()> {
+        if let Some(receiver) = self.0.lock().as_ref() {
+            receiver.resume().map_err(to_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rewind the playback by the seconds.
+    #[napi]
+    pub fn seek_back(&self, secs: f64) -> Result<()> {
+        if let Some(receiver) = self.0.lock().as_ref() {
+            receiver.seek_back(secs as f32).map_err(to_error)?;
+        }
+
+        Ok(())
+    }
+
+    #[napi]
+    pub fn go_live(&self) -> Result<()> {
+        if let Some(receiver) = self.0.lock().as_ref() {
+            receiver.go_live().map_err(to_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Close the receiver, the receiver is also closed when it is garbage
+    /// collected.
+    #[napi]
+    pub fn close(&self) {
+        drop(self.0.lock().take());
+    }
+}
+
+/// Creates a receiver, the options are the same as `HylaranaReceiverOptions`
+/// and the description is from the sender.
+#[napi]
+pub fn create_receiver(
+    addr: String,
+    #[napi(ts_arg_type = "any")] options: serde_json::Value,
+    #[napi(ts_arg_type = "any")] description: serde_json::Value,
+    callbacks: HylaranaStreamCallbacks,
+) -> Result<HylaranaReceiver> {
+    let (sink, observer) = callbacks.split();
+
+    Ok(HylaranaReceiver(Mutex::new(Some(
+        hylarana::create_receiver(
+            addr.parse().map_err(to_error)?,
+            &serde_json::from_value(options).map_err(to_error)?,
+            &serde_json::from_value(description).map_err(to_error)?,
+            sink,
+            observer,
+        )
+        .map_err(to_error)?,
+    ))))
+}
+
+/// LAN service discovery, the devices in the local network are reported to
+/// the callbacks.
+#[napi]
+pub struct DiscoveryService(Mutex<Option<hylarana::DiscoveryService>>);
+
+#[napi]
+impl DiscoveryService {
+    #[napi(constructor)]
+    pub fn new(bind: String, callbacks: DiscoveryCallbacks) -> Result<Self> {
+        let bind: SocketAddr = bind.parse().map_err(to_error)?;
+
+        Ok(Self(Mutex::new(Some(
+            hylarana::get_runtime_handle()
+                .block_on(hylarana::DiscoveryService::new(
+                    bind,
+                    DiscoveryServiceObserver(callbacks),
+                ))
+                .map_err(to_error)?,
+        ))))
+    }
+
+    /// Publish the metadata of this device to the other devices.
+    #[napi]
+    pub fn set_metadata(&self, metadata: Either<Buffer, String>) -> Result<()> {
+        let metadata = match metadata {
+            Either::A(it) => it.to_vec(),
+            Either::B(it) => it.into_bytes(),
+        };
+
+        let service = self.0.lock();
+        let service = service
+            .as_ref()
+            .ok_or_else(|| to_error("discovery is closed"))?;
+
+        hylarana::get_runtime_handle().block_on(service.set_metadata(metadata));
+        Ok(())
+    }
+
+    #[napi]
+    pub fn close(&self) {
+        drop(self.0.lock().take());
+    }
+}
@@ -0,0 +1,210 @@
+use std::slice::from_raw_parts;
+
+use hylarana::{
+    AudioFrame, CloseReason, MediaStreamObserver, MediaStreamSink, Source, VideoFormat,
+    VideoSubFormat,
+};
+
+use napi::{
+    bindgen_prelude::Buffer,
+    threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
+
+use napi_derive::napi;
+
+/// A copy of the video frame, only the frames that can be accessed by the
+/// software are passed to javascript.
+#[napi(object)]
+pub struct JsVideoFrame {
+    /// 0: BGRA, 1: RGBA, 2: NV12, 3: I420.
+    pub format: i32,
+    pub width: u32,
+    pub height: u32,
+    /// The planes of the frame, the number of the planes depends on the format.
+    pub planes: Vec<Buffer>,
+    pub linesize: Vec<u32>,
+}
+
+#[napi(object)]
+pub struct JsAudioFrame {
+    pub sample_rate: u32,
+    /// The number of samples per channel.
+    pub frames: u32,
+    pub channels: u32,
+    /// Interleaved samples of signed 16-bit.
+    pub data: Buffer,
+}
+
+/// Why the sender or the receiver is closed, the kind is the name of the
+/// variant of `CloseReason`.
+#[napi(object)]
+pub struct JsCloseReason {
+    pub kind: String,
+    pub message: String,
+}
+
+/// The callbacks of the sender or the receiver, all callbacks are optional.
+///
+/// The frame callbacks are called asynchronously on the javascript thread, the
+/// frames are copied, so only set them if you need the raw frames.
+#[napi(object, object_to_js = false)]
+pub struct HylaranaStreamCallbacks {
+    #[napi(ts_type = "(frame: JsVideoFrame) => void")]
+    pub video: Option<ThreadsafeFunction<JsVideoFrame, ErrorStrategy::Fatal>>,
+    #[napi(ts_type = "(frame: JsAudioFrame) => void")]
+    pub audio: Option<ThreadsafeFunction<JsAudioFrame, ErrorStrategy::Fatal>>,
+    #[napi(ts_type = "(reason: JsCloseReason) => void")]
+    pub close: Option<ThreadsafeFunction<JsCloseReason, ErrorStrategy::Fatal>>,
+    #[napi(ts_type = "(source: any) => void")]
+    pub audio_device_changed: Option<ThreadsafeFunction<serde_json::Value, ErrorStrategy::Fatal>>,
+    #[napi(ts_type = "() => void")]
+    pub signal_lost: Option<ThreadsafeFunction<(), ErrorStrategy::Fatal>>,
+    #[napi(ts_type = "() => void")]
+    pub signal_restored: Option<ThreadsafeFunction<(), ErrorStrategy::Fatal>>,
+}
+
+fn call<T: 'static>(func: &Option<ThreadsafeFunction<T, ErrorStrategy::Fatal>>, value: T) {
+    if let Some(func) = func {
+        func.call(value, ThreadsafeFunctionCallMode::NonBlocking);
+    }
+}
+
+pub struct StreamSink {
+    pub video: Option<ThreadsafeFunction<JsVideoFrame, ErrorStrategy::Fatal>>,
+    pub audio: Option<ThreadsafeFunction<JsAudioFrame, ErrorStrategy::Fatal>>,
+}
+
+impl MediaStreamSink for StreamSink {
+    fn video(&self, frame: &hylarana::VideoFrame) -> bool {
+        if self.video.is_none() {
+            return true;
+        }
+
+        if frame.sub_format != VideoSubFormat::SW {
+            log::warn!(
+                "the video frame cannot be passed to javascript, sub_format={:?}",
+                frame.sub_format
+            );
+
+            return true;
+        }
+
+        let height = frame.height as usize;
+        let planes = match frame.format {
+            VideoFormat::BGRA | VideoFormat::RGBA => vec![height],
+            VideoFormat::NV12 => vec![height, height / 2],
+            VideoFormat::I420 => vec![height, height / 2, height / 2],
+        };
+
+        call(
+            &self.video,
+            JsVideoFrame {
+                format: frame.format as i32,
+                width: frame.width,
+                height: frame.height,
+                linesize: frame.linesize[..planes.len()].to_vec(),
+                planes: planes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, rows)| {
+                        unsafe {
+                            from_raw_parts(
+                                frame.data[i] as *const u8,
+                                frame.linesize[i] as usize * rows,
+                            )
+                        }
+                        .to_vec()
+                        .into()
+                    })
+                    .collect(),
+            },
+        );
+
+        true
+    }
+
+    fn audio(&self, frame: &AudioFrame) -> bool {
+        if self.audio.is_none() {
+            return true;
+        }
+
+        let data = unsafe {
+            from_raw_parts(
+                frame.data as *const u8,
+                frame.frames as usize * frame.channels as usize * 2,
+            )
+        };
+
+        call(
+            &self.audio,
+            JsAudioFrame {
+                sample_rate: frame.sample_rate,
+                frames: frame.frames,
+                channels: frame.channels as u32,
+                data: data.to_vec().into(),
+            },
+        );
+
+        true
+    }
+}
+
+pub struct StreamObserver {
+    pub close: Option<ThreadsafeFunction<JsCloseReason, ErrorStrategy::Fatal>>,
+    pub audio_device_changed: Option<ThreadsafeFunction<serde_json::Value, ErrorStrategy::Fatal>>,
+    pub signal_lost: Option<ThreadsafeFunction<(), ErrorStrategy::Fatal>>,
+    pub signal_restored: Option<ThreadsafeFunction<(), ErrorStrategy::Fatal>>,
+}
+
+impl MediaStreamObserver for StreamObserver {
+    fn close(&self, reason: CloseReason) {
+        call(
+            &self.close,
+            JsCloseReason {
+                kind: match reason {
+                    CloseReason::RemoteClosed => "RemoteClosed",
+                    CloseReason::NetworkTimeout => "NetworkTimeout",
+                    CloseReason::Closed => "Closed",
+                    CloseReason::CaptureStopped => "CaptureStopped",
+                    CloseReason::SourceLost => "SourceLost",
+                    CloseReason::TransportError(_) => "TransportError",
+                    CloseReason::EncoderError(_) => "EncoderError",
+                    CloseReason::DecoderError(_) => "DecoderError",
+                }
+                .to_string(),
+                message: reason.to_string(),
+            },
+        );
+    }
+
+    fn audio_device_changed(&self, source: &Source) {
+        if let Ok(source) = serde_json::to_value(source) {
+            call(&self.audio_device_changed, source);
+        }
+    }
+
+    fn on_signal_lost(&self) {
+        call(&self.signal_lost, ());
+    }
+
+    fn on_signal_restored(&self) {
+        call(&self.signal_restored, ());
+    }
+}
+
+impl HylaranaStreamCallbacks {
+    pub fn split(self) -> (StreamSink, StreamObserver) {
+        (
+            StreamSink {
+                video: self.video,
+                audio: self.audio,
+            },
+            StreamObserver {
+                close: self.close,
+                audio_device_changed: self.audio_device_changed,
+                signal_lost: self.signal_lost,
+                signal_restored: self.signal_restored,
+            },
+        )
+    }
+}
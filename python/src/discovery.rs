@@ -0,0 +1,109 @@
+use std::net::{IpAddr, SocketAddr};
+
+use hylarana::{DiscoveryObserver, get_runtime_handle};
+use parking_lot::Mutex;
+use pyo3::{exceptions::PyRuntimeError, prelude::*, types::PyBytes};
+
+struct DiscoveryCallbacks {
+    online: Option<PyObject>,
+    offline: Option<PyObject>,
+    metadata: Option<PyObject>,
+}
+
+impl DiscoveryObserver for DiscoveryCallbacks {
+    async fn online(&self, local_id: &str, id: &str, ip: IpAddr) {
+        log::info!("devices manager device online, id={}, ip={}", id, ip);
+
+        if let Some(callback) = &self.online {
+            Python::with_gil(|py| {
+                if let Err(e) = callback.call1(py, (local_id, id, ip.to_string())) {
+                    e.print(py);
+                }
+            });
+        }
+    }
+
+    async fn offline(&self, local_id: &str, id: &str, ip: IpAddr) {
+        log::info!("devices manager device offline, id={}, ip={}", id, ip);
+
+        if let Some(callback) = &self.offline {
+            Python::with_gil(|py| {
+                if let Err(e) = callback.call1(py, (local_id, id, ip.to_string())) {
+                    e.print(py);
+                }
+            });
+        }
+    }
+
+    async fn on_metadata(&self, local_id: &str, id: &str, ip: IpAddr, metadata: Vec<u8>) {
+        log::info!(
+            "devices manager device on metadata, id={}, ip={} metadata={:?}",
+            id,
+            ip,
+            std::str::from_utf8(&metadata)
+        );
+
+        if let Some(callback) = &self.metadata {
+            Python::with_gil(|py| {
+                let metadata = PyBytes::new(py, &metadata);
+                if let Err(e) = callback.call1(py, (local_id, id, ip.to_string(), metadata)) {
+                    e.print(py);
+                }
+            });
+        }
+    }
+}
+
+/// LAN service discovery, the callbacks are called with
+/// `(local_id, id, ip)`, and the metadata callback also with the metadata
+/// bytes.
+#[pyclass(module = "hylarana")]
+pub struct DiscoveryService(Mutex<Option<hylarana::DiscoveryService>>);
+
+#[pymethods]
+impl DiscoveryService {
+    #[new]
+    #[pyo3(signature = (bind, on_online=None, on_offline=None, on_metadata=None))]
+    fn new(
+        py: Python<'_>,
+        bind: &str,
+        on_online: Option<PyObject>,
+        on_offline: Option<PyObject>,
+        on_metadata: Option<PyObject>,
+    ) -> PyResult<Self> {
+        let bind: SocketAddr = bind
+            .parse()
+            .map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
+
+        let callbacks = DiscoveryCallbacks {
+            online: on_online,
+            offline: on_offline,
+            metadata: on_metadata,
+        };
+
+        let service = py
+            .allow_threads(|| {
+                get_runtime_handle().block_on(hylarana::DiscoveryService::new(bind, callbacks))
+            })
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+
+        Ok(Self(Mutex::new(Some(service))))
+    }
+
+    /// Publish the metadata of this device to the other devices.
+    fn set_metadata(&self, py: Python<'_>, metadata: Vec<u8>) -> PyResult<()> {
+        py.allow_threads(|| {
+            let service = self.0.lock();
+            let service = service
+                .as_ref()
+                .ok_or_else(|| PyRuntimeError::new_err("discovery is closed"))?;
+
+            get_runtime_handle().block_on(service.set_metadata(metadata));
+            Ok(())
+        })
+    }
+
+    fn close(&self) {
+        drop(self.0.lock().take());
+    }
+}
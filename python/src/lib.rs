@@ -0,0 +1,101 @@
+mod discovery;
+mod receiver;
+
+use common::logger;
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyRuntimeError},
+    prelude::*,
+};
+
+use pythonize::pythonize;
+
+use self::{discovery::DiscoveryService, receiver::Receiver};
+
+create_exception!(hylarana, HylaranaError, PyException);
+create_exception!(hylarana, TransportError, HylaranaError);
+create_exception!(hylarana, CodecError, HylaranaError);
+create_exception!(hylarana, CaptureError, HylaranaError);
+create_exception!(hylarana, RenderError, HylaranaError);
+
+/// Raise the errors of the sdk as the exception of the category, all of them
+/// are subclasses of `HylaranaError`.
+pub(crate) fn to_py_err(e: hylarana::HylaranaError) -> PyErr {
+    let message = e.to_string();
+
+    match e {
+        hylarana::HylaranaError::TransportError(_) => TransportError::new_err(message),
+        hylarana::HylaranaError::CodecError(_) => CodecError::new_err(message),
+        hylarana::HylaranaError::CaptureError(_) => CaptureError::new_err(message),
+        hylarana::HylaranaError::RenderError(_) => RenderError::new_err(message),
+        _ => HylaranaError::new_err(message),
+    }
+}
+
+/// Initialize the logger, the level is one of "error", "warn", "info", "debug"
+/// and "trace", the logs are also written to the directory if the path is
+/// passed.
+#[pyfunction]
+#[pyo3(signature = (level="info", path=None))]
+fn init_logger(level: &str, path: Option<&str>) -> PyResult<()> {
+    let level = level
+        .parse()
+        .map_err(|_| PyRuntimeError::new_err(format!("invalid log level: {}", level)))?;
+
+    logger::init_logger(level, path).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    logger::enable_panic_logger();
+
+    Ok(())
+}
+
+/// Initialize the environment, this must be called once before any other
+/// function except the logger.
+#[pyfunction]
+fn startup() -> PyResult<()> {
+    hylarana::startup().map_err(to_py_err)
+}
+
+/// Clean up the environment, no other function can be called after this.
+#[pyfunction]
+fn shutdown() -> PyResult<()> {
+    hylarana::shutdown().map_err(to_py_err)
+}
+
+/// Get the capture sources, the kind is one of "camera", "screen" and "audio".
+#[pyfunction]
+fn get_sources<'py>(py: Python<'py>, kind: &str) -> PyResult<Bound<'py, PyAny>> {
+    let kind = match kind {
+        "camera" => hylarana::SourceType::Camera,
+        "screen" => hylarana::SourceType::Screen,
+        "audio" => hylarana::SourceType::Audio,
+        _ => {
+            return Err(PyRuntimeError::new_err(format!(
+                "invalid source type: {}",
+                kind
+            )));
+        }
+    };
+
+    let sources =
+        hylarana::Capture::get_sources(kind).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+
+    Ok(pythonize(py, &sources)?)
+}
+
+#[pymodule]
+#[pyo3(name = "hylarana")]
+fn hylarana_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(init_logger, m)?)?;
+    m.add_function(wrap_pyfunction!(startup, m)?)?;
+    m.add_function(wrap_pyfunction!(shutdown, m)?)?;
+    m.add_function(wrap_pyfunction!(get_sources, m)?)?;
+    m.add_class::<Receiver>()?;
+    m.add_class::<DiscoveryService>()?;
+    m.add("HylaranaError", m.py().get_type::<HylaranaError>())?;
+    m.add("TransportError", m.py().get_type::<TransportError>())?;
+    m.add("CodecError", m.py().get_type::<CodecError>())?;
+    m.add("CaptureError", m.py().get_type::<CaptureError>())?;
+    m.add("RenderError", m.py().get_type::<RenderError>())?;
+
+    Ok(())
+}
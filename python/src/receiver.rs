@@ -0,0 +1,350 @@
+use std::{
+    collections::VecDeque,
+    slice::from_raw_parts,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use hylarana::{
+    AudioFrame, CloseReason, HylaranaReceiverOptions, MediaStreamDescription, MediaStreamObserver,
+    MediaStreamSink, VideoFormat, VideoFrame, VideoOutput, VideoSubFormat,
+};
+
+use numpy::{IntoPyArray, ndarray::Array};
+use parking_lot::{Condvar, Mutex};
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+use pythonize::depythonize;
+
+use super::to_py_err;
+
+/// Up to one second of audio is buffered, the oldest frames are dropped if
+/// python does not read them in time.
+const MAX_AUDIO_FRAMES: usize = 100;
+
+/// A video frame copied out of the receiver, the rows are tightly packed.
+///
+/// The YUV frames of an odd size are padded to the next even size by
+/// repeating the last row and column of the luma, the chroma planes already
+/// cover the padded size.
+struct PackedVideoFrame {
+    format: VideoFormat,
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl PackedVideoFrame {
+    fn new(frame: &VideoFrame) -> Option<Self> {
+        if frame.sub_format != VideoSubFormat::SW {
+            return None;
+        }
+
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let (width, height, planes) = match frame.format {
+            VideoFormat::BGRA | VideoFormat::RGBA => (
+                width,
+                height,
+                vec![(0, width * 4, height, width * 4, height)],
+            ),
+            VideoFormat::NV12 | VideoFormat::I420 => {
+                let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+                let (padded_width, padded_height) = (chroma_width * 2, chroma_height * 2);
+
+                // (plane, bytes per row, rows, padded bytes per row, padded rows)
+                let mut planes = vec![(0, width, height, padded_width, padded_height)];
+                if frame.format == VideoFormat::NV12 {
+                    planes.push((1, padded_width, chroma_height, padded_width, chroma_height));
+                } else {
+                    for i in [1, 2] {
+                        planes.push((i, chroma_width, chroma_height, chroma_width, chroma_height));
+                    }
+                }
+
+                (padded_width, padded_height, planes)
+            }
+        };
+
+        let mut data = Vec::with_capacity(planes.iter().map(|it| it.3 * it.4).sum());
+        for (i, row_size, rows, padded_row_size, padded_rows) in planes {
+            let linesize = frame.linesize[i] as usize;
+            let plane = unsafe { from_raw_parts(frame.data[i] as *const u8, linesize * rows) };
+
+            for row in 0..padded_rows {
+                let row = &plane[row.min(rows - 1) * linesize..][..row_size];
+
+                data.extend_from_slice(row);
+                data.resize(data.len() + padded_row_size - row_size, row[row_size - 1]);
+            }
+        }
+
+        Some(Self {
+            format: frame.format,
+            width,
+            height,
+            data,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Frames {
+    video: Option<PackedVideoFrame>,
+    /// (sample rate, channels, interleaved samples)
+    audio: VecDeque<(u32, usize, Vec<i16>)>,
+}
+
+#[derive(Default)]
+struct ReceiverContext {
+    frames: Mutex<Frames>,
+    readable: Condvar,
+    closed: AtomicBool,
+}
+
+impl ReceiverContext {
+    fn wait<T>(&self, timeout: Option<f64>, take: impl Fn(&mut Frames) -> Option<T>) -> Option<T> {
+        let mut frames = self.frames.lock();
+
+        loop {
+            if let Some(it) = take(&mut frames) {
+                return Some(it);
+            }
+
+            if self.closed.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            if let Some(timeout) = timeout {
+                if self
+                    .readable
+                    .wait_for(&mut frames, Duration::from_secs_f64(timeout))
+                    .timed_out()
+                {
+                    return take(&mut frames);
+                }
+            } else {
+                self.readable.wait(&mut frames);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ReceiverSinker(Arc<ReceiverContext>);
+
+impl MediaStreamSink for ReceiverSinker {
+    fn video(&self, frame: &VideoFrame) -> bool {
+        if let Some(frame) = PackedVideoFrame::new(frame) {
+            self.0.frames.lock().video.replace(frame);
+            self.0.readable.notify_all();
+        } else {
+            log::warn!(
+                "the video frame cannot be passed to python, sub_format={:?}",
+                frame.sub_format
+            );
+        }
+
+        true
+    }
+
+    fn audio(&self, frame: &AudioFrame) -> bool {
+        let samples =
+            unsafe { from_raw_parts(frame.data, frame.frames as usize * frame.channels as usize) }
+                .to_vec();
+
+        {
+            let mut frames = self.0.frames.lock();
+            if frames.audio.len() >= MAX_AUDIO_FRAMES {
+                frames.audio.pop_front();
+            }
+
+            frames
+                .audio
+                .push_back((frame.sample_rate, frame.channels as usize, samples));
+        }
+
+        self.0.readable.notify_all();
+        true
+    }
+}
+
+impl MediaStreamObserver for ReceiverSinker {
+    fn close(&self, _: CloseReason) {
+        self.0.closed.store(true, Ordering::Relaxed);
+        self.0.readable.notify_all();
+    }
+}
+
+/// The receiver of the screen casting, the frames are read as numpy arrays.
+///
+/// The video frames are `(height, width, 4)` arrays for BGRA and RGBA, and
+/// `(height * 3 / 2, width)` arrays for NV12 and I420, which can be converted
+/// with `cv2.cvtColor` and `COLOR_YUV2BGR_NV12` or `COLOR_YUV2BGR_I420`. The
+/// YUV frames of an odd size are padded to an even size. Only the latest video
+/// frame is kept.
+///
+/// The frames of the gpu cannot be passed to python, so the `Native` video
+/// output is replaced with the `Software` output.
+#[pyclass(module = "hylarana")]
+pub struct Receiver {
+    context: Arc<ReceiverContext>,
+    receiver: Mutex<Option<hylarana::HylaranaReceiver>>,
+}
+
+#[pymethods]
+impl Receiver {
+    /// The options are the same as `HylaranaReceiverOptions`, and the
+    /// description is from the sender.
+    #[new]
+    fn new(
+        addr: &str,
+        options: &Bound<'_, PyAny>,
+        description: &Bound<'_, PyAny>,
+    ) -> PyResult<Self> {
+        let addr = addr
+            .parse()
+            .map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
+
+        let mut options: HylaranaReceiverOptions = depythonize(options)?;
+        if options.video_output == VideoOutput::Native {
+            options.video_output = VideoOutput::Software;
+        }
+        let description: MediaStreamDescription = depythonize(description)?;
+
+        let context = Arc::new(ReceiverContext::default());
+        let sinker = ReceiverSinker(context.clone());
+
+        let receiver =
+            hylarana::create_receiver(addr, &options, &description, sinker.clone(), sinker)
+                .map_err(to_py_err)?;
+
+        Ok(Self {
+            context,
+            receiver: Mutex::new(Some(receiver)),
+        })
+    }
+
+    /// Read the latest video frame, blocks until a frame is received or the
+    /// timeout in seconds expires, returns `None` on timeout or when closed.
+    #[pyo3(signature = (timeout=None))]
+    fn read_video(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<PyObject>> {
+        let context = self.context.clone();
+        let Some(frame) = py.allow_threads(|| context.wait(timeout, |it| it.video.take())) else {
+            return Ok(None);
+        };
+
+        let shape = match frame.format {
+            VideoFormat::BGRA | VideoFormat::RGBA => vec![frame.height, frame.width, 4],
+            VideoFormat::NV12 | VideoFormat::I420 => vec![frame.height * 3 / 2, frame.width],
+        };
+
+        let array = Array::from_shape_vec(shape, frame.data)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+
+        Ok(Some(array.into_pyarray(py).into_any().unbind()))
+    }
+
+    /// Read the next audio frame as `(sample_rate, samples)`, the samples are
+    /// an `(frames, channels)` int16 array, returns `None` on timeout or when
+    /// closed.
+    #[pyo3(signature = (timeout=None))]
+    fn read_audio(
+        &self,
+        py: Python<'_>,
+        timeout: Option<f64>,
+    ) -> PyResult<Option<(u32, PyObject)>> {
+        let context = self.context.clone();
+        let Some((sample_rate, channels, samples)) =
+            py.allow_threads(|| context.wait(timeout, |it| it.audio.pop_front()))
+        else {
+            return Ok(None);
+        };
+
+        let channels = channels.max(1);
+        let array = Array::from_shape_vec((samples.len() / channels, channels), samples)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+
+        Ok(Some((
+            sample_rate,
+            array.into_pyarray(py).into_any().unbind(),
+        )))
+    }
+
+    /// Set the volume of the played audio, 1.0 is the original volume.
+    fn set_volume(&self, volume: f32) {
+        if let Some(receiver) = self.receiver.lock().as_ref() {
+            receiver.set_volume(volume);
+        }
+    }
+
+    fn set_muted(&self, muted: bool) {
+        if let Some(receiver) = self.receiver.lock().as_ref() {
+            receiver.set_muted(muted);
+        }
+    }
+
+    /// Switch the receiver to the stream of another sender, the current stream
+    /// is kept if this fails.
+    fn switch_stream(&self, addr: &str, description: &Bound<'_, PyAny>) -> PyResult<()> {
+        let addr = addr
+            .parse()
+            .map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
+
+        let description: MediaStreamDescription = depythonize(description)?;
+        if let Some(receiver) = self.receiver.lock().as_mut() {
+            receiver
+                .switch_stream(addr, &description)
+                .map_err(to_py_err)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pause the playback, the time shift of the receiver must be enabled.
+    fn pause(&self) -> PyResult<()> {
+        if let Some(receiver) = self.receiver.lock().as_ref() {
+            receiver.pause().map_err(to_py_err)?;
+        }
+
+        Ok(())
+    }
+
+    fn resume(&self) -> PyResult<()> {
+        if let Some(receiver) = self.receiver.lock().as_ref() {
+            receiver.resume().map_err(to_py_err)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rewind the playback by the seconds.
+    fn seek_back(&self, secs: f32) -> PyResult<()> {
+        if let Some(receiver) = self.receiver.lock().as_ref() {
+            receiver.seek_back(secs).map_err(to_py_err)?;
+        }
+
+        Ok(())
+    }
+
+    fn go_live(&self) -> PyResult<()> {
+        if let Some(receiver) = self.receiver.lock().as_ref() {
+            receiver.go_live().map_err(to_py_err)?;
+        }
+
+        Ok(())
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.context.closed.load(Ordering::Relaxed)
+    }
+
+    fn close(&self) {
+        drop(self.receiver.lock().take());
+
+        self.context.closed.store(true, Ordering::Relaxed);
+        self.context.readable.notify_all();
+    }
+}
@@ -1,35 +1,42 @@
-mod texture;
+pub(crate) mod texture;
 
 use std::{borrow::Cow, sync::Arc};
 
 use self::texture::{bgra::Bgra, i420::I420, nv12::Nv12, rgba::Rgba};
-use crate::{Vertex, transform::TransformError};
+use crate::{ScalingQuality, Vertex, transform::TransformError};
 
 #[cfg(target_os = "windows")]
 use crate::transform::direct3d::Transformer;
 
-#[cfg(target_os = "macos")]
+#[cfg(target_vendor = "apple")]
 use crate::transform::metal::Transformer;
 
+#[cfg(target_os = "linux")]
+use crate::transform::vulkan::Transformer;
+
 use common::{
     Size,
     frame::{VideoFormat, VideoSubFormat},
+    runtime,
 };
 
 use smallvec::SmallVec;
 use thiserror::Error;
 
-#[cfg(target_os = "macos")]
+#[cfg(target_vendor = "apple")]
 use common::macos::CVPixelBufferRef;
 
+#[cfg(target_os = "linux")]
+use common::linux::DmaBufDescriptor;
+
 #[cfg(target_os = "windows")]
 use common::win32::{Direct3DDevice, windows::Win32::Graphics::Direct3D11::ID3D11Texture2D};
 
 use wgpu::{
     AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
     BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState,
-    ColorTargetState, ColorWrites, CommandEncoder, Device, Extent3d, FilterMode, FragmentState,
-    ImageCopyTexture, ImageDataLayout, IndexFormat, MultisampleState, Origin3d,
+    ColorTargetState, ColorWrites, CommandEncoder, Device, ErrorFilter, Extent3d, FilterMode,
+    FragmentState, ImageCopyTexture, ImageDataLayout, IndexFormat, MultisampleState, Origin3d,
     PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, Queue,
     RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
     ShaderModuleDescriptor, ShaderSource, ShaderStages, Texture as WGPUTexture, TextureAspect,
@@ -41,14 +48,18 @@ use wgpu::{
 pub enum BackBufferError {
     #[error(transparent)]
     TransformError(#[from] TransformError),
+    #[error("invalid video filter shader: {0}")]
+    InvalidFilter(String),
 }
 
 #[derive(Debug)]
 pub enum Texture2DRaw {
     #[cfg(target_os = "windows")]
     ID3D11Texture2D(ID3D11Texture2D, u32),
-    #[cfg(target_os = "macos")]
+    #[cfg(target_vendor = "apple")]
     CVPixelBufferRef(CVPixelBufferRef),
+    #[cfg(target_os = "linux")]
+    DmaBuf(DmaBufDescriptor),
 }
 
 #[derive(Debug)]
@@ -71,10 +82,96 @@ pub enum Texture<'a> {
     I420(Texture2DBuffer<'a>),
 }
 
-trait Texture2DSample {
+/// The fragment shaders sample the planes with this function, so that the
+/// filtering does not depend on the format.
+fn sampling_shader(quality: ScalingQuality) -> &'static str {
+    match quality {
+        ScalingQuality::Nearest | ScalingQuality::Bilinear | ScalingQuality::Integer => {
+            r#"
+    fn sample_texture(plane: texture_2d<f32>, plane_sampler: sampler, coords: vec2<f32>) -> vec4<f32> {
+        return textureSample(plane, plane_sampler, coords);
+    }"#
+        }
+        // The cubic B-spline of the 4x4 texels around the coordinate, the
+        // weights of each pair of texels are merged into one bilinear sample.
+        ScalingQuality::Bicubic => {
+            r#"
+    fn sample_texture(plane: texture_2d<f32>, plane_sampler: sampler, coords: vec2<f32>) -> vec4<f32> {
+        let size = vec2<f32>(textureDimensions(plane));
+        let texel = 1.0 / size;
+        let position = coords * size - 0.5;
+        let f = fract(position);
+        let origin = (position - f + 0.5) * texel;
+
+        let f2 = f * f;
+        let f3 = f2 * f;
+        let w0 = (1.0 - 3.0 * f + 3.0 * f2 - f3) / 6.0;
+        let w1 = (4.0 - 6.0 * f2 + 3.0 * f3) / 6.0;
+        let w2 = (1.0 + 3.0 * f + 3.0 * f2 - 3.0 * f3) / 6.0;
+        let w3 = f3 / 6.0;
+
+        let g0 = w0 + w1;
+        let g1 = w2 + w3;
+        let h0 = (w1 / g0 - 1.0) * texel;
+        let h1 = (w3 / g1 + 1.0) * texel;
+
+        let c00 = textureSample(plane, plane_sampler, origin + vec2<f32>(h0.x, h0.y));
+        let c10 = textureSample(plane, plane_sampler, origin + vec2<f32>(h1.x, h0.y));
+        let c01 = textureSample(plane, plane_sampler, origin + vec2<f32>(h0.x, h1.y));
+        let c11 = textureSample(plane, plane_sampler, origin + vec2<f32>(h1.x, h1.y));
+
+        return g0.y * (g0.x * c00 + g1.x * c10) + g1.y * (g0.x * c01 + g1.x * c11);
+    }"#
+        }
+    }
+}
+
+/// The entry point of the fragment shader, the filters are wrapped in
+/// functions and applied to the sampled color in order.
+fn filters_shader(filters: &[String]) -> String {
+    let mut shader = String::new();
+    for (i, filter) in filters.iter().enumerate() {
+        shader.push_str(&format!(
+            "fn filter_{}(color: vec4<f32>, coords: vec2<f32>) -> vec4<f32> {{\n{}\n}}\n",
+            i, filter
+        ));
+    }
+
+    shader.push_str(
+        "@fragment fn main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {\n",
+    );
+
+    shader.push_str("var color = sample_color(coords);\n");
+    for i in 0..filters.len() {
+        shader.push_str(&format!("color = filter_{}(color, coords);\n", i));
+    }
+
+    shader.push_str("return color;\n}");
+    shader
+}
+
+pub(crate) trait Texture2DSample {
     const VIEWS_COUNT: usize;
 
-    fn fragment_shader() -> ShaderModuleDescriptor<'static>;
+    /// The shader samples the planes with `sample_texture` and defines
+    /// `sample_color`, which returns the RGBA color of the video.
+    const FRAGMENT_SHADER: &'static str;
+
+    fn fragment_shader(
+        quality: ScalingQuality,
+        filters: &[String],
+    ) -> ShaderModuleDescriptor<'static> {
+        ShaderModuleDescriptor {
+            label: None,
+            source: ShaderSource::Wgsl(Cow::Owned(format!(
+                "{}\n{}\n{}",
+                sampling_shader(quality),
+                Self::FRAGMENT_SHADER,
+                filters_shader(filters)
+            ))),
+        }
+    }
+
     fn create_texture_descriptor(
         size: Size,
         sub_format: VideoSubFormat,
@@ -236,6 +333,11 @@ pub struct BackBufferOptions {
     pub format: VideoFormat,
     pub sub_format: VideoSubFormat,
     pub size: Size,
+    /// Format of the texture the back buffer is drawn to.
+    pub target: TextureFormat,
+    pub quality: ScalingQuality,
+    /// The shaders of the video filters, see [crate::VideoFilter].
+    pub filters: Vec<String>,
 }
 
 pub struct BackBuffer {
@@ -245,7 +347,6 @@ pub struct BackBuffer {
     layout: BindGroupLayout,
     pipeline: RenderPipeline,
     sample: Texture2DSourceSample,
-    #[cfg(not(target_os = "linux"))]
     transformer: Option<Transformer>,
 }
 
@@ -257,11 +358,13 @@ impl BackBuffer {
             format,
             sub_format,
             size,
+            target,
+            quality,
+            filters,
             #[cfg(target_os = "windows")]
             direct3d,
         }: BackBufferOptions,
     ) -> Result<Self, BackBufferError> {
-        #[cfg(not(target_os = "linux"))]
         let transformer = {
             if sub_format != VideoSubFormat::SW {
                 #[cfg(target_os = "windows")]
@@ -269,7 +372,7 @@ impl BackBuffer {
                     Some(Transformer::new(direct3d, &device, size, format)?)
                 }
 
-                #[cfg(target_os = "macos")]
+                #[cfg(not(target_os = "windows"))]
                 {
                     Some(Transformer::new(device.clone(), size, format)?)
                 }
@@ -278,13 +381,19 @@ impl BackBuffer {
             }
         };
 
+        // The bicubic shader relies on the bilinear filtering of the sampler.
+        let filter = match quality {
+            ScalingQuality::Nearest | ScalingQuality::Integer => FilterMode::Nearest,
+            ScalingQuality::Bilinear | ScalingQuality::Bicubic => FilterMode::Linear,
+        };
+
         let sampler = device.create_sampler(&SamplerDescriptor {
             address_mode_u: AddressMode::ClampToEdge,
             address_mode_v: AddressMode::ClampToEdge,
             address_mode_w: AddressMode::ClampToEdge,
             mipmap_filter: FilterMode::Nearest,
-            mag_filter: FilterMode::Nearest,
-            min_filter: FilterMode::Nearest,
+            mag_filter: filter,
+            min_filter: filter,
             ..Default::default()
         });
 
@@ -302,6 +411,10 @@ impl BackBuffer {
             Texture2DSourceSample::I420(it) => it.bind_group_layout(&device),
         };
 
+        // The filters may come from the user, so an invalid shader is returned as
+        // an error instead of reaching the error handler of the device.
+        device.push_error_scope(ErrorFilter::Validation);
+
         let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
             label: None,
             layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
@@ -321,16 +434,16 @@ impl BackBuffer {
             fragment: Some(FragmentState {
                 entry_point: Some("main"),
                 module: &device.create_shader_module(match &sample {
-                    Texture2DSourceSample::Rgba(_) => Rgba::fragment_shader(),
-                    Texture2DSourceSample::Bgra(_) => Bgra::fragment_shader(),
-                    Texture2DSourceSample::Nv12(_) => Nv12::fragment_shader(),
-                    Texture2DSourceSample::I420(_) => I420::fragment_shader(),
+                    Texture2DSourceSample::Rgba(_) => Rgba::fragment_shader(quality, &filters),
+                    Texture2DSourceSample::Bgra(_) => Bgra::fragment_shader(quality, &filters),
+                    Texture2DSourceSample::Nv12(_) => Nv12::fragment_shader(quality, &filters),
+                    Texture2DSourceSample::I420(_) => I420::fragment_shader(quality, &filters),
                 }),
                 compilation_options: PipelineCompilationOptions::default(),
                 targets: &[Some(ColorTargetState {
                     blend: Some(BlendState::REPLACE),
                     write_mask: ColorWrites::ALL,
-                    format: TextureFormat::Bgra8Unorm,
+                    format: target,
                 })],
             }),
             primitive: PrimitiveState {
@@ -344,8 +457,11 @@ impl BackBuffer {
             cache: None,
         });
 
+        if let Some(e) = runtime::block_on(device.pop_error_scope()) {
+            return Err(BackBufferError::InvalidFilter(e.to_string()));
+        }
+
         Ok(Self {
-            #[cfg(not(target_os = "linux"))]
             transformer,
             device: device,
             queue: queue,
@@ -356,6 +472,10 @@ impl BackBuffer {
         })
     }
 
+    pub fn pipeline(&self) -> &RenderPipeline {
+        &self.pipeline
+    }
+
     /// If it is a hardware texture, it will directly create view for the
     /// current texture, if it is a software texture, it will write the data to
     /// the internal texture first, and then create the view for the internal
@@ -393,24 +513,21 @@ impl BackBuffer {
             _ => (),
         }
 
-        #[cfg(target_os = "linux")]
-        let texture = None;
-
-        #[cfg(not(target_os = "linux"))]
         let texture = match &texture {
             Texture::Rgba(texture) | Texture::Bgra(texture) | Texture::Nv12(texture) => {
                 if let Some(transformer) = &mut self.transformer {
                     match texture {
-                        #[cfg(not(target_os = "linux"))]
                         Texture2DResource::Texture(texture) => match texture {
                             #[cfg(target_os = "windows")]
                             Texture2DRaw::ID3D11Texture2D(it, index) => {
                                 Some(transformer.transform(it, *index)?)
                             }
-                            #[cfg(target_os = "macos")]
+                            #[cfg(target_vendor = "apple")]
                             Texture2DRaw::CVPixelBufferRef(it) => {
                                 Some(transformer.transform(encoder, *it)?)
                             }
+                            #[cfg(target_os = "linux")]
+                            Texture2DRaw::DmaBuf(it) => Some(transformer.transform(it)?),
                         },
                         Texture2DResource::Buffer(_) => None,
                         #[allow(unreachable_patterns)]
@@ -3,19 +3,15 @@ use super::Texture2DSample;
 pub mod bgra {
     use super::Texture2DSample;
 
-    use std::borrow::Cow;
-
     use common::{Size, frame::VideoSubFormat};
-    use wgpu::{
-        Device, ShaderModuleDescriptor, ShaderSource, Texture, TextureAspect, TextureFormat,
-    };
+    use wgpu::{Device, Texture, TextureAspect, TextureFormat};
 
     const FRAGMENT_SHADER: &str = r#"
     @group(0) @binding(0) var texture_: texture_2d<f32>;
     @group(0) @binding(1) var sampler_: sampler;
 
-    @fragment fn main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
-        return textureSample(texture_, sampler_, coords);
+    fn sample_color(coords: vec2<f32>) -> vec4<f32> {
+        return sample_texture(texture_, sampler_, coords);
     }"#;
 
     pub struct Bgra(Option<Texture>);
@@ -29,12 +25,7 @@ pub mod bgra {
     impl Texture2DSample for Bgra {
         const VIEWS_COUNT: usize = 1;
 
-        fn fragment_shader() -> ShaderModuleDescriptor<'static> {
-            ShaderModuleDescriptor {
-                label: None,
-                source: ShaderSource::Wgsl(Cow::Borrowed(FRAGMENT_SHADER)),
-            }
-        }
+        const FRAGMENT_SHADER: &'static str = FRAGMENT_SHADER;
 
         fn create_texture_descriptor(
             size: Size,
@@ -80,12 +71,8 @@ pub mod bgra {
 pub mod i420 {
     use super::Texture2DSample;
 
-    use std::borrow::Cow;
-
     use common::{Size, frame::VideoSubFormat};
-    use wgpu::{
-        Device, ShaderModuleDescriptor, ShaderSource, Texture, TextureAspect, TextureFormat,
-    };
+    use wgpu::{Device, Texture, TextureAspect, TextureFormat};
 
     const FRAGMENT_SHADER: &str = r#"
     @group(0) @binding(0) var y_texture: texture_2d<f32>;
@@ -93,10 +80,10 @@ pub mod i420 {
     @group(0) @binding(2) var v_texture: texture_2d<f32>;
     @group(0) @binding(3) var sampler_: sampler;
 
-    @fragment fn main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
-        let y = textureSample(y_texture, sampler_, coords).r;
-        let u = textureSample(u_texture, sampler_, coords).r - 0.5;
-        let v = textureSample(v_texture, sampler_, coords).r - 0.5;
+    fn sample_color(coords: vec2<f32>) -> vec4<f32> {
+        let y = sample_texture(y_texture, sampler_, coords).r;
+        let u = sample_texture(u_texture, sampler_, coords).r - 0.5;
+        let v = sample_texture(v_texture, sampler_, coords).r - 0.5;
 
         let r = y + 1.5748 * v;
         let g = y - 0.187324 * u - 0.468124 * v;
@@ -140,12 +127,7 @@ pub mod i420 {
     impl Texture2DSample for I420 {
         const VIEWS_COUNT: usize = 3;
 
-        fn fragment_shader() -> ShaderModuleDescriptor<'static> {
-            ShaderModuleDescriptor {
-                label: None,
-                source: ShaderSource::Wgsl(Cow::Borrowed(FRAGMENT_SHADER)),
-            }
-        }
+        const FRAGMENT_SHADER: &'static str = FRAGMENT_SHADER;
 
         fn create_texture_descriptor(
             size: Size,
@@ -221,22 +203,18 @@ pub mod i420 {
 pub mod nv12 {
     use super::Texture2DSample;
 
-    use std::borrow::Cow;
-
     use common::{Size, frame::VideoSubFormat};
-    use wgpu::{
-        Device, ShaderModuleDescriptor, ShaderSource, Texture, TextureAspect, TextureFormat,
-    };
+    use wgpu::{Device, Texture, TextureAspect, TextureFormat};
 
     const FRAGMENT_SHADER: &str = r#"
     @group(0) @binding(0) var y_texture: texture_2d<f32>;
     @group(0) @binding(1) var uv_texture: texture_2d<f32>;
     @group(0) @binding(2) var sampler_: sampler;
 
-    @fragment fn main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
-        let y = textureSample(y_texture, sampler_, coords).r;
-        let u = textureSample(uv_texture, sampler_, coords).r - 0.5;
-        let v = textureSample(uv_texture, sampler_, coords).g - 0.5;
+    fn sample_color(coords: vec2<f32>) -> vec4<f32> {
+        let y = sample_texture(y_texture, sampler_, coords).r;
+        let u = sample_texture(uv_texture, sampler_, coords).r - 0.5;
+        let v = sample_texture(uv_texture, sampler_, coords).g - 0.5;
 
         let r = y + 1.5748 * v;
         let g = y - 0.187324 * u - 0.468124 * v;
@@ -273,29 +251,26 @@ pub mod nv12 {
     impl Nv12 {
         pub(crate) fn new(device: &Device, size: Size, sub_format: VideoSubFormat) -> Self {
             let mut textures = Self::create(device, size, sub_format);
-            Self(if sub_format == VideoSubFormat::D3D11 {
-                None
-            } else {
-                Some((textures.next().unwrap(), textures.next().unwrap()))
-            })
+            Self(
+                if matches!(sub_format, VideoSubFormat::D3D11 | VideoSubFormat::DmaBuf) {
+                    None
+                } else {
+                    Some((textures.next().unwrap(), textures.next().unwrap()))
+                },
+            )
         }
     }
 
     impl Texture2DSample for Nv12 {
         const VIEWS_COUNT: usize = 2;
 
-        fn fragment_shader() -> ShaderModuleDescriptor<'static> {
-            ShaderModuleDescriptor {
-                label: None,
-                source: ShaderSource::Wgsl(Cow::Borrowed(FRAGMENT_SHADER)),
-            }
-        }
+        const FRAGMENT_SHADER: &'static str = FRAGMENT_SHADER;
 
         fn create_texture_descriptor(
             size: Size,
             sub_format: VideoSubFormat,
         ) -> impl IntoIterator<Item = (Size, TextureFormat)> {
-            if sub_format == VideoSubFormat::D3D11 {
+            if matches!(sub_format, VideoSubFormat::D3D11 | VideoSubFormat::DmaBuf) {
                 Vec::new()
             } else {
                 vec![
@@ -356,19 +331,15 @@ pub mod nv12 {
 pub mod rgba {
     use super::Texture2DSample;
 
-    use std::borrow::Cow;
-
     use common::{Size, frame::VideoSubFormat};
-    use wgpu::{
-        Device, ShaderModuleDescriptor, ShaderSource, Texture, TextureAspect, TextureFormat,
-    };
+    use wgpu::{Device, Texture, TextureAspect, TextureFormat};
 
     const FRAGMENT_SHADER: &str = r#"
     @group(0) @binding(0) var texture_: texture_2d<f32>;
     @group(0) @binding(1) var sampler_: sampler;
 
-    @fragment fn main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
-        return textureSample(texture_, sampler_, coords);
+    fn sample_color(coords: vec2<f32>) -> vec4<f32> {
+        return sample_texture(texture_, sampler_, coords);
     }"#;
 
     /// RGBA stands for red green blue alpha. While it is sometimes described as
@@ -398,12 +369,7 @@ pub mod rgba {
     impl Texture2DSample for Rgba {
         const VIEWS_COUNT: usize = 1;
 
-        fn fragment_shader() -> ShaderModuleDescriptor<'static> {
-            ShaderModuleDescriptor {
-                label: None,
-                source: ShaderSource::Wgsl(Cow::Borrowed(FRAGMENT_SHADER)),
-            }
-        }
+        const FRAGMENT_SHADER: &'static str = FRAGMENT_SHADER;
 
         fn create_texture_descriptor(
             size: Size,
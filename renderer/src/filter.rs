@@ -0,0 +1,45 @@
+use std::fmt::Debug;
+
+/// A filter of the video, applied by the renderer on the gpu after the video is
+/// sampled and before it is drawn, the filters are applied in order.
+///
+/// A filter is the body of a WGSL function with the parameters
+/// `color: vec4<f32>`, which is the color of the pixel after the previous
+/// filters, and `coords: vec2<f32>`, which is the texture coordinate of the
+/// pixel, and returns the new `vec4<f32>` color. `sample_color(coords)` samples
+/// the unfiltered video at another coordinate, such as for a sharpen filter.
+pub trait VideoFilter: Debug + Send + Sync {
+    fn shader(&self) -> String;
+}
+
+/// Format the value as a WGSL float literal.
+fn float(value: f32) -> String {
+    format!("{:.6}", if value.is_finite() { value } else { 0.0 })
+}
+
+/// Shifts the color, -1.0 is black, 0.0 keeps the video and 1.0 is white.
+#[derive(Debug, Clone, Copy)]
+pub struct Brightness(pub f32);
+
+impl VideoFilter for Brightness {
+    fn shader(&self) -> String {
+        format!(
+            "return vec4<f32>(clamp(color.rgb + {}, vec3<f32>(0.0), vec3<f32>(1.0)), color.a);",
+            float(self.0.clamp(-1.0, 1.0))
+        )
+    }
+}
+
+/// Scales the color around the middle gray, 0.0 is gray, 1.0 keeps the video
+/// and the larger values increase the contrast.
+#[derive(Debug, Clone, Copy)]
+pub struct Contrast(pub f32);
+
+impl VideoFilter for Contrast {
+    fn shader(&self) -> String {
+        format!(
+            "return vec4<f32>(clamp((color.rgb - 0.5) * {} + 0.5, vec3<f32>(0.0), vec3<f32>(1.0)), color.a);",
+            float(self.0.max(0.0))
+        )
+    }
+}
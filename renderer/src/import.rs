@@ -0,0 +1,396 @@
+use std::sync::Arc;
+
+use crate::{GraphicsError, RendererSourceOptions, Texture2DRaw};
+
+#[cfg(target_os = "windows")]
+use crate::transform::direct3d::{SharedFence, Transformer};
+
+#[cfg(target_vendor = "apple")]
+use crate::{
+    ScalingQuality, Vertex,
+    backbuffer::{Texture2DSample, texture::nv12::Nv12},
+    transform::metal::{PlaneMapper, Transformer},
+};
+
+use common::frame::{VideoFormat, VideoSubFormat};
+use wgpu::{Device, Queue};
+
+#[cfg(target_vendor = "apple")]
+use std::borrow::Cow;
+
+#[cfg(target_vendor = "apple")]
+use common::{Size, macos::CVPixelBufferRef};
+
+#[cfg(target_vendor = "apple")]
+use wgpu::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, Color,
+    ColorTargetState, ColorWrites, CommandEncoderDescriptor, Extent3d, FilterMode, FragmentState,
+    IndexFormat, LoadOp, MultisampleState, Operations, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, StoreOp,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+/// Importer configuration, the device and the queue belong to the caller.
+#[derive(Debug)]
+pub struct TextureImporterOptions {
+    #[cfg(target_os = "windows")]
+    pub direct3d: common::win32::Direct3DDevice,
+    /// The hardware textures are imported with the native graphics api, so
+    /// the device must use DX12 on windows and Metal on macos.
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    pub source: RendererSourceOptions,
+}
+
+/// Imports the hardware textures of the decoder into a texture of the
+/// caller's device without rendering them, so that the video can be
+/// composited by another engine. There is no importer on Linux, the dma-buf
+/// import needs a device created by the renderer.
+///
+/// The texture of an import can be sampled by the work that the caller
+/// submits to the queue after the import and before the next import. On
+/// windows the copy is made by the d3d11 device and is fenced against the
+/// queue, and the textures are used in turn, a texture is written again only
+/// after the queue has finished the work that was submitted before the
+/// following import. On macos the copy is submitted to the queue itself, and
+/// the NV12 pixel buffers are converted to a `Rgba8Unorm` texture, since wgpu
+/// has no NV12 textures on metal.
+pub struct TextureImporter {
+    #[cfg(target_os = "windows")]
+    ring: Vec<Transformer>,
+    /// The value of the `released` fence after which the queue no longer reads
+    /// the texture of the ring.
+    #[cfg(target_os = "windows")]
+    released_at: Vec<u64>,
+    /// Signaled by the d3d11 device after a copy.
+    #[cfg(target_os = "windows")]
+    copied: SharedFence,
+    /// Signaled by the queue at every import after the work that reads the
+    /// previous texture.
+    #[cfg(target_os = "windows")]
+    released: SharedFence,
+    #[cfg(target_os = "windows")]
+    previous: Option<usize>,
+    #[cfg(target_vendor = "apple")]
+    transformer: PixelBufferTransformer,
+    #[cfg(target_vendor = "apple")]
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+}
+
+impl TextureImporter {
+    /// The number of the textures that are used in turn on windows.
+    #[cfg(target_os = "windows")]
+    const RING_SIZE: usize = 3;
+
+    pub fn new(
+        TextureImporterOptions {
+            #[cfg(target_os = "windows")]
+            direct3d,
+            device,
+            queue,
+            source,
+        }: TextureImporterOptions,
+    ) -> Result<Self, GraphicsError> {
+        tracing::info!("create texture importer, options={:?}", source);
+
+        if !Self::is_supported(source.sub_format, source.format) {
+            return Err(GraphicsError::NotSupportedImport(
+                source.format,
+                source.sub_format,
+            ));
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            let mut ring = Vec::with_capacity(Self::RING_SIZE);
+            for _ in 0..Self::RING_SIZE {
+                ring.push(Transformer::new(
+                    direct3d.clone(),
+                    &device,
+                    source.size,
+                    source.format,
+                )?);
+            }
+
+            Ok(Self {
+                released_at: vec![0; Self::RING_SIZE],
+                copied: SharedFence::new(&direct3d, &device)?,
+                released: SharedFence::new(&direct3d, &device)?,
+                previous: None,
+                ring,
+                queue,
+            })
+        }
+
+        #[cfg(target_vendor = "apple")]
+        {
+            Ok(Self {
+                transformer: if source.format == VideoFormat::NV12 {
+                    PixelBufferTransformer::Nv12(Nv12Converter::new(device.clone(), source.size)?)
+                } else {
+                    PixelBufferTransformer::Packed(Transformer::new(
+                        device.clone(),
+                        source.size,
+                        source.format,
+                    )?)
+                },
+                device,
+                queue,
+            })
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    fn is_supported(sub_format: VideoSubFormat, format: VideoFormat) -> bool {
+        sub_format == VideoSubFormat::D3D11
+            && matches!(
+                format,
+                VideoFormat::NV12 | VideoFormat::BGRA | VideoFormat::RGBA
+            )
+    }
+
+    #[cfg(target_vendor = "apple")]
+    fn is_supported(sub_format: VideoSubFormat, format: VideoFormat) -> bool {
+        sub_format == VideoSubFormat::CvPixelBufferRef
+            && matches!(
+                format,
+                VideoFormat::NV12 | VideoFormat::BGRA | VideoFormat::RGBA
+            )
+    }
+
+    /// Copy the hardware texture into a texture of the importer, the texture
+    /// can only be sampled by the caller.
+    pub fn import(&mut self, texture: Texture2DRaw) -> Result<&wgpu::Texture, GraphicsError> {
+        match texture {
+            #[cfg(target_os = "windows")]
+            Texture2DRaw::ID3D11Texture2D(it, index) => {
+                // The caller has submitted all of the work that reads the previous
+                // texture.
+                if let Some(previous) = self.previous {
+                    self.released_at[previous] = self.released.signal_dx12(&self.queue)?;
+                }
+
+                let current = self
+                    .previous
+                    .map(|it| (it + 1) % self.ring.len())
+                    .unwrap_or(0);
+                self.released.wait_d3d11(self.released_at[current])?;
+
+                let texture = self.ring[current].transform(&it, index)?;
+
+                // The work that the caller submits after this waits for the copy.
+                let copied = self.copied.signal_d3d11()?;
+                self.copied.wait_dx12(&self.queue, copied)?;
+
+                self.previous = Some(current);
+                Ok(texture)
+            }
+            #[cfg(target_vendor = "apple")]
+            Texture2DRaw::CVPixelBufferRef(it) => match &mut self.transformer {
+                PixelBufferTransformer::Packed(transformer) => {
+                    let mut encoder = self
+                        .device
+                        .create_command_encoder(&CommandEncoderDescriptor { label: None });
+
+                    let texture = transformer.transform(&mut encoder, it)?;
+                    self.queue.submit(Some(encoder.finish()));
+
+                    Ok(texture)
+                }
+                PixelBufferTransformer::Nv12(converter) => {
+                    Ok(converter.convert(&self.device, &self.queue, it)?)
+                }
+            },
+        }
+    }
+}
+
+#[cfg(target_vendor = "apple")]
+enum PixelBufferTransformer {
+    Packed(Transformer),
+    Nv12(Nv12Converter),
+}
+
+/// Converts the NV12 pixel buffers to a `Rgba8Unorm` texture with the shader
+/// of the renderer, the planes are mapped without copying them.
+#[cfg(target_vendor = "apple")]
+struct Nv12Converter {
+    mapper: PlaneMapper,
+    sampler: Sampler,
+    layout: BindGroupLayout,
+    pipeline: RenderPipeline,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    texture: wgpu::Texture,
+    view: TextureView,
+}
+
+#[cfg(target_vendor = "apple")]
+impl Nv12Converter {
+    fn new(device: Arc<Device>, size: Size) -> Result<Self, GraphicsError> {
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            count: None,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+        };
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            })),
+            vertex: VertexState {
+                entry_point: Some("main"),
+                module: &device.create_shader_module(ShaderModuleDescriptor {
+                    label: None,
+                    source: ShaderSource::Wgsl(Cow::Borrowed(Vertex::VERTEX_SHADER)),
+                }),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(FragmentState {
+                entry_point: Some("main"),
+                module: &device
+                    .create_shader_module(Nv12::fragment_shader(ScalingQuality::Bilinear, &[])),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                    format: TextureFormat::Rgba8Unorm,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: Some(IndexFormat::Uint16),
+                ..Default::default()
+            },
+            multisample: MultisampleState::default(),
+            depth_stencil: None,
+            multiview: None,
+            cache: None,
+        });
+
+        // The chroma plane has half the size of the texture.
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mipmap_filter: FilterMode::Nearest,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: None,
+            format: TextureFormat::Rgba8Unorm,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+            size: Extent3d {
+                depth_or_array_layers: 1,
+                width: size.width,
+                height: size.height,
+            },
+        });
+
+        let (vertex_buffer, index_buffer) = Vertex::create_buffers(&device);
+
+        Ok(Self {
+            view: texture.create_view(&TextureViewDescriptor::default()),
+            mapper: PlaneMapper::new(device)?,
+            vertex_buffer,
+            index_buffer,
+            texture,
+            sampler,
+            layout,
+            pipeline,
+        })
+    }
+
+    fn convert(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        buffer: CVPixelBufferRef,
+    ) -> Result<&wgpu::Texture, GraphicsError> {
+        let (y, uv) = self.mapper.map(buffer)?;
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(
+                        &y.create_view(&TextureViewDescriptor::default()),
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(
+                        &uv.create_view(&TextureViewDescriptor::default()),
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &self.view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, Some(&bind_group), &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
+            render_pass.draw_indexed(0..Vertex::INDICES.len() as u32, 0, 0..1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+        self.mapper.flush();
+
+        Ok(&self.texture)
+    }
+}
@@ -1,33 +1,50 @@
 mod backbuffer;
+mod filter;
+#[cfg(any(target_os = "windows", target_vendor = "apple"))]
+mod import;
+mod multi;
+mod overlay;
+mod surface;
 mod transform;
 mod vertex;
 
-use std::sync::Arc;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+    mpsc::Receiver,
+};
 
 use self::vertex::Vertex;
 
-pub use self::backbuffer::{
-    BackBufferError, Texture, Texture2DBuffer, Texture2DRaw, Texture2DResource,
+pub use self::{
+    backbuffer::{BackBufferError, Texture, Texture2DBuffer, Texture2DRaw, Texture2DResource},
+    filter::{Brightness, Contrast, VideoFilter},
+    multi::{MultiRenderer, MultiRendererLayout, MultiRendererOptions},
+    overlay::{OverlayLayer, OverlayPosition},
+    surface::RendererBackend,
 };
 
+#[cfg(any(target_os = "windows", target_vendor = "apple"))]
+pub use self::import::{TextureImporter, TextureImporterOptions};
+
 use common::{
     Size,
+    events::Broadcaster,
     frame::{VideoFormat, VideoSubFormat},
-    runtime::get_runtime_handle,
 };
 
 use backbuffer::{BackBuffer, BackBufferOptions};
+use overlay::Overlay;
+use surface::SurfaceContext;
 use thiserror::Error;
 use wgpu::{
-    Backends, Buffer, BufferUsages, Color, CommandEncoderDescriptor, CompositeAlphaMode, Device,
-    DeviceDescriptor, IndexFormat, Instance, InstanceDescriptor, LoadOp, MemoryHints, Operations,
-    PowerPreference, PresentMode, Queue, RenderPassColorAttachment, RenderPassDescriptor,
-    RequestAdapterOptions, StoreOp, Surface, SurfaceConfiguration, TextureFormat, TextureUsages,
-    TextureViewDescriptor,
-    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, Buffer, Color, CommandEncoder, CommandEncoderDescriptor, Device, DeviceLostReason,
+    IndexFormat, LoadOp, Operations, Queue, RenderPassColorAttachment, RenderPassDescriptor,
+    StoreOp, Surface, SurfaceConfiguration, SurfaceError, SurfaceTexture, TextureFormat,
+    TextureUsages, TextureView, TextureViewDescriptor,
 };
 
-pub use wgpu::{SurfaceTarget, rwh as raw_window_handle};
+pub use wgpu::{self, PresentMode, SurfaceTarget, rwh as raw_window_handle};
 
 #[derive(Debug, Error)]
 pub enum GraphicsError {
@@ -43,15 +60,82 @@ pub enum GraphicsError {
     CreateSurfaceError(#[from] wgpu::CreateSurfaceError),
     #[error(transparent)]
     BackBufferError(#[from] BackBufferError),
+    #[error(transparent)]
+    TransformError(#[from] transform::TransformError),
+    #[error("the target texture is not a render attachment or has a different format")]
+    InvalidTargetTexture,
+    #[error("dma-buf textures cannot be imported into this device")]
+    #[cfg(target_os = "linux")]
+    NotSupportedDmaBufTarget,
+    #[error("hardware textures cannot be imported into the {0:?} backend")]
+    NotSupportedBackend(RendererBackend),
+    #[error("the renderer does not render to a window")]
+    NotSurfaceTarget,
+    #[error("the texture cannot be imported, format={0:?}, sub_format={1:?}")]
+    NotSupportedImport(VideoFormat, VideoSubFormat),
+}
+
+/// How the frames are presented to the window, the values that are not set
+/// use the defaults of the platform.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RendererPresentOptions {
+    /// `Immediate` or `Mailbox` turn the vsync off for the lowest latency,
+    /// `Fifo` waits for the vsync and never tears. If the mode is not supported
+    /// by the surface, the default of the platform is used, and `Fifo` if that
+    /// is not supported either.
+    pub mode: Option<PresentMode>,
+    /// The number of the frames that can be queued for the presentation, 1 is
+    /// the lowest latency, the default is 2.
+    pub max_frame_latency: Option<u32>,
+}
+
+/// How the video is sampled when the window and the video have different
+/// sizes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScalingQuality {
+    /// The closest pixel, sharp but blocky.
+    Nearest,
+    #[default]
+    Bilinear,
+    /// Cubic B-spline filtering in the shader, smoother than bilinear when
+    /// the video is enlarged a lot, at the cost of four samples per plane.
+    Bicubic,
+    /// Enlarge the video by the largest whole factor that fits the window and
+    /// sample the closest pixel, so every pixel of the video is a square of
+    /// pixels of the window, such as for pixel art or text. A video larger
+    /// than the window is fitted like the others. This only changes the
+    /// placement of [ScalingMode::Fit].
+    Integer,
+}
+
+/// How the video is placed in the window when they have different aspect
+/// ratios.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScalingMode {
+    /// The whole video is shown, with black bars on the sides that are not
+    /// covered.
+    #[default]
+    Fit,
+    /// The video covers the whole window, the parts of the video outside the
+    /// window are cropped.
+    Fill,
+    /// The video covers the whole window and ignores its aspect ratio.
+    Stretch,
+    /// The video is not scaled and is centered in the window, the parts of the
+    /// video outside the window are cropped.
+    Center,
 }
 
 #[derive(Debug)]
 pub struct RendererSurfaceOptions<T> {
     pub window: T,
     pub size: Size,
+    pub present: RendererPresentOptions,
+    /// The native backend of the platform is used if it is not set.
+    pub backend: Option<RendererBackend>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct RendererSourceOptions {
     pub size: Size,
     pub format: VideoFormat,
@@ -66,131 +150,469 @@ pub struct RendererOptions<T> {
     pub source: RendererSourceOptions,
 }
 
+/// Renderer configuration for rendering into a texture instead of a window,
+/// so that the video can be composited by another engine or ui framework.
+#[derive(Debug)]
+pub struct RendererTextureOptions {
+    #[cfg(target_os = "windows")]
+    pub direct3d: common::win32::Direct3DDevice,
+    /// The device and the queue of the caller, the texture must belong to this
+    /// device. The hardware textures are imported with the native graphics api,
+    /// so the device must use DX12 on windows, Vulkan on linux and Metal on
+    /// macos.
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    /// The texture needs the `RENDER_ATTACHMENT` usage.
+    pub texture: wgpu::Texture,
+    pub source: RendererSourceOptions,
+}
+
+enum RenderTarget<'a> {
+    Surface {
+        surface: Surface<'a>,
+        config: SurfaceConfiguration,
+        context: SurfaceContext,
+    },
+    Texture(wgpu::Texture),
+}
+
+impl RenderTarget<'_> {
+    fn size(&self) -> Size {
+        match self {
+            Self::Surface { config, .. } => Size {
+                width: config.width,
+                height: config.height,
+            },
+            Self::Texture(texture) => Size {
+                width: texture.width(),
+                height: texture.height(),
+            },
+        }
+    }
+
+    fn format(&self) -> TextureFormat {
+        match self {
+            Self::Surface { config, .. } => config.format,
+            Self::Texture(texture) => texture.format(),
+        }
+    }
+}
+
+/// The recovery of a window renderer from the losses of the gpu, see
+/// [Renderer::events].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RendererEvent {
+    /// The surface was lost or outdated and was configured again.
+    SurfaceLost,
+    /// The device was lost, for example by a driver reset or a resume from
+    /// sleep, the frames are dropped until the device is created again.
+    DeviceLost,
+    /// The device was created again and the rendering continues.
+    DeviceRecovered,
+    /// The device could not be created again, the renderer stops and the
+    /// submit returns the error.
+    RecoveryFailed,
+}
+
 /// Window Renderer.
 ///
 /// Supports rendering RGBA or NV12 hardware or software textures to system
-/// native windows.
+/// native windows, or to a texture provided by the caller.
 ///
 /// Note that the renderer uses a hardware implementation by default, i.e. it
 /// uses the underlying GPU device, and the use of software devices is not
 /// currently supported.
 pub struct Renderer<'a> {
-    config: SurfaceConfiguration,
-    surface: Surface<'a>,
+    #[cfg(target_os = "windows")]
+    direct3d: common::win32::Direct3DDevice,
+    source: RendererSourceOptions,
+    target: RenderTarget<'a>,
     device: Arc<Device>,
     queue: Arc<Queue>,
     vertex_buffer: Buffer,
     index_buffer: Buffer,
     back_buffer: BackBuffer,
+    overlay: Overlay,
     viewport: Viewport,
+    quality: ScalingQuality,
+    mode: ScalingMode,
+    filters: Vec<String>,
+    last_frame: Option<BindGroup>,
+    events: Broadcaster<RendererEvent>,
+    device_lost: Arc<AtomicBool>,
+    recovery_attempts: u32,
 }
 
 impl<'a> Renderer<'a> {
+    /// The number of the consecutive failed attempts to create the device again
+    /// before the renderer gives up, one attempt is made for each submitted
+    /// frame.
+    const MAX_RECOVERY_ATTEMPTS: u32 = 10;
+
     pub fn new<T: Into<SurfaceTarget<'a>>>(
         RendererOptions {
             #[cfg(target_os = "windows")]
             direct3d,
-            surface: RendererSurfaceOptions { window, size },
+            surface:
+                RendererSurfaceOptions {
+                    window,
+                    size,
+                    present,
+                    backend,
+                },
             source,
         }: RendererOptions<T>,
     ) -> Result<Self, GraphicsError> {
-        let viewport = Viewport::new(source.size, size);
+        tracing::info!("create renderer, options={:?}", source);
 
-        log::info!("create renderer, options={:?}", source);
+        let (context, surface) = SurfaceContext::new(
+            window,
+            backend,
+            present,
+            source.sub_format,
+            #[cfg(target_os = "windows")]
+            direct3d.clone(),
+        )?;
 
-        let instance = Instance::new(InstanceDescriptor {
-            backends: if cfg!(target_os = "windows") {
-                Backends::DX12
-            } else if cfg!(target_os = "linux") {
-                Backends::VULKAN
-            } else {
-                Backends::METAL
-            },
-            ..Default::default()
-        });
-
-        let surface = instance.create_surface(window)?;
-        let adapter = get_runtime_handle()
-            .block_on(instance.request_adapter(&RequestAdapterOptions {
-                power_preference: PowerPreference::LowPower,
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-                ..Default::default()
-            }))
-            .ok_or_else(|| GraphicsError::NotFoundAdapter)?;
-
-        let (device, queue) = get_runtime_handle().block_on(adapter.request_device(
-            &DeviceDescriptor {
-                label: None,
-                memory_hints: MemoryHints::MemoryUsage,
-                required_features: adapter.features(),
-                required_limits: adapter.limits(),
+        let (device, queue) = context.create_device()?;
+        let config = context.configure(&surface, &device, size)?;
+
+        let this = Self::create(
+            RenderTarget::Surface {
+                surface,
+                config,
+                context,
             },
-            None,
-        ))?;
-
-        let device = Arc::new(device);
-        let queue = Arc::new(queue);
-
-        // Configure surface as BGRA, BGRA this format compatibility is the best, in
-        // order to unnecessary trouble, directly fixed to BGRA is the best.
-        let mut config = surface
-            .get_default_config(&adapter, size.width, size.height)
-            .ok_or_else(|| GraphicsError::NotFoundSurfaceDefaultConfig)?;
-
-        config.present_mode = if cfg!(target_os = "windows") {
-            PresentMode::Mailbox
-        } else if cfg!(target_os = "linux") {
-            PresentMode::Fifo
-        } else {
-            PresentMode::Immediate
-        };
+            device,
+            queue,
+            source,
+            #[cfg(target_os = "windows")]
+            direct3d,
+        )?;
+
+        // The device of a texture target belongs to the caller, so only the device
+        // created here is watched.
+        this.watch_device_lost();
 
-        config.format = TextureFormat::Bgra8Unorm;
-        config.alpha_mode = CompositeAlphaMode::Opaque;
-        config.usage = TextureUsages::RENDER_ATTACHMENT;
-        surface.configure(&device, &config);
+        Ok(this)
+    }
+
+    /// Create a renderer that renders into the texture of the caller instead
+    /// of a window, the texture is written by [Renderer::submit] and can be
+    /// sampled by the caller after that.
+    pub fn from_texture(
+        RendererTextureOptions {
+            #[cfg(target_os = "windows")]
+            direct3d,
+            device,
+            queue,
+            texture,
+            source,
+        }: RendererTextureOptions,
+    ) -> Result<Self, GraphicsError> {
+        tracing::info!("create texture renderer, options={:?}", source);
 
-        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(Vertex::VERTICES),
-            usage: BufferUsages::VERTEX,
-        });
+        if !texture.usage().contains(TextureUsages::RENDER_ATTACHMENT) {
+            return Err(GraphicsError::InvalidTargetTexture);
+        }
 
-        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(Vertex::INDICES),
-            usage: BufferUsages::INDEX,
-        });
+        // The dma-buf import needs vulkan device extensions, which only the device
+        // created by the renderer has.
+        #[cfg(target_os = "linux")]
+        if source.sub_format == VideoSubFormat::DmaBuf {
+            return Err(GraphicsError::NotSupportedDmaBufTarget);
+        }
 
+        Self::create(
+            RenderTarget::Texture(texture),
+            device,
+            queue,
+            source,
+            #[cfg(target_os = "windows")]
+            direct3d,
+        )
+    }
+
+    fn create(
+        target: RenderTarget<'a>,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        source: RendererSourceOptions,
+        #[cfg(target_os = "windows")] direct3d: common::win32::Direct3DDevice,
+    ) -> Result<Self, GraphicsError> {
+        let (vertex_buffer, index_buffer) = Vertex::create_buffers(&device);
         let back_buffer = BackBuffer::new(BackBufferOptions {
             device: device.clone(),
             queue: queue.clone(),
             size: source.size,
             format: source.format,
             sub_format: source.sub_format,
+            target: target.format(),
+            quality: ScalingQuality::default(),
+            filters: Vec::new(),
             #[cfg(target_os = "windows")]
-            direct3d,
+            direct3d: direct3d.clone(),
         })?;
 
         Ok(Self {
-            viewport,
+            overlay: Overlay::new(&device, target.format()),
+            viewport: Viewport::new(source.size, target.size()),
+            quality: ScalingQuality::default(),
+            mode: ScalingMode::default(),
+            filters: Vec::new(),
+            #[cfg(target_os = "windows")]
+            direct3d,
             back_buffer,
             vertex_buffer,
             index_buffer,
-            surface,
+            source,
+            target,
             device,
             queue,
-            config,
+            last_frame: None,
+            events: Broadcaster::default(),
+            device_lost: Arc::new(AtomicBool::new(false)),
+            recovery_attempts: 0,
         })
     }
 
+    /// Subscribe to the recovery events of a window renderer, a texture
+    /// renderer has no events.
+    pub fn events(&mut self) -> Receiver<RendererEvent> {
+        self.events.subscribe()
+    }
+
+    fn watch_device_lost(&self) {
+        let device_lost = self.device_lost.clone();
+        self.device
+            .set_device_lost_callback(move |reason, message| {
+                // The callback is also called when the device is dropped.
+                if reason == DeviceLostReason::Unknown {
+                    tracing::error!("renderer device lost, message={}", message);
+
+                    device_lost.store(true, Ordering::Relaxed);
+                }
+            });
+    }
+
+    fn replace_device(&mut self, device: Arc<Device>, queue: Arc<Queue>) {
+        self.device = device;
+        self.queue = queue;
+        self.watch_device_lost();
+    }
+
+    /// Move the renderer to another window, for example when the window handle
+    /// is replaced after the view is re-parented. If the new window is on
+    /// another adapter, the device is created again on that adapter, and the
+    /// overlay layers are uploaded again.
+    pub fn recreate_surface<T: Into<SurfaceTarget<'a>>>(
+        &mut self,
+        window: T,
+        size: Size,
+    ) -> Result<(), GraphicsError> {
+        let RenderTarget::Surface {
+            surface,
+            config,
+            context,
+        } = &mut self.target
+        else {
+            return Err(GraphicsError::NotSurfaceTarget);
+        };
+
+        let (new_surface, adapter_changed) = context.create_surface(window)?;
+        if adapter_changed {
+            tracing::info!("renderer surface is on another adapter, recreate the device");
+
+            let (device, queue) = context.create_device()?;
+            self.replace_device(device, queue);
+        }
+
+        *config = context.configure(&new_surface, &self.device, size)?;
+        *surface = new_surface;
+
+        if adapter_changed {
+            self.recreate_resources()?;
+        }
+
+        self.update_viewport(size);
+        self.overlay.resize(&self.queue, size);
+
+        tracing::info!("renderer surface recreated, size={:?}", size);
+
+        Ok(())
+    }
+
+    /// Create the buffers, the back buffer and the overlay again on the current
+    /// device.
+    fn recreate_resources(&mut self) -> Result<(), GraphicsError> {
+        let layers = self.overlay.layers();
+        let dim = self.overlay.dim();
+
+        (self.vertex_buffer, self.index_buffer) = Vertex::create_buffers(&self.device);
+        self.recreate_back_buffer()?;
+
+        self.overlay = Overlay::new(&self.device, self.target.format());
+        self.overlay
+            .set(&self.device, &self.queue, &layers, self.target.size());
+        self.overlay.set_dim(&self.device, &self.queue, dim);
+        self.update_viewport(self.target.size());
+
+        Ok(())
+    }
+
+    /// Create the back buffer again with the current options.
+    fn recreate_back_buffer(&mut self) -> Result<(), GraphicsError> {
+        // The last frame belongs to the previous back buffer.
+        self.last_frame = None;
+        self.back_buffer = BackBuffer::new(BackBufferOptions {
+            device: self.device.clone(),
+            queue: self.queue.clone(),
+            size: self.source.size,
+            format: self.source.format,
+            sub_format: self.source.sub_format,
+            target: self.target.format(),
+            quality: self.quality,
+            filters: self.filters.clone(),
+            #[cfg(target_os = "windows")]
+            direct3d: self.direct3d.clone(),
+        })?;
+
+        Ok(())
+    }
+
+    /// Place the video in the surface, the cropped part of the video is written
+    /// to the vertex buffer.
+    fn update_viewport(&mut self, surface: Size) {
+        self.viewport = Viewport::scaled(self.source.size, surface, self.quality, self.mode);
+        self.queue.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&Vertex::cropped(self.viewport.crop)[..]),
+        );
+    }
+
+    /// Resize the window surface, a texture target is replaced with
+    /// [Renderer::set_texture] instead.
     pub fn resize(&mut self, size: Size) {
-        self.viewport.resize(size);
+        if let RenderTarget::Surface {
+            surface, config, ..
+        } = &mut self.target
+        {
+            config.width = size.width;
+            config.height = size.height;
+            surface.configure(&self.device, config);
+        }
+
+        self.update_viewport(size);
+        self.overlay.resize(&self.queue, size);
+    }
+
+    /// Replace the texture of a renderer created with
+    /// [Renderer::from_texture], for example when the caller resizes the view,
+    /// the new texture must have the same format as the previous one.
+    pub fn set_texture(&mut self, texture: wgpu::Texture) -> Result<(), GraphicsError> {
+        match &self.target {
+            RenderTarget::Texture(it)
+                if it.format() == texture.format()
+                    && texture.usage().contains(TextureUsages::RENDER_ATTACHMENT) => {}
+            _ => return Err(GraphicsError::InvalidTargetTexture),
+        }
+
+        let size = Size {
+            width: texture.width(),
+            height: texture.height(),
+        };
+
+        self.target = RenderTarget::Texture(texture);
+        self.update_viewport(size);
+        self.overlay.resize(&self.queue, size);
 
-        self.config.width = size.width;
-        self.config.height = size.height;
-        self.surface.configure(&self.device, &self.config);
+        Ok(())
+    }
+
+    /// Render the frames of another source, such as when the stream is
+    /// switched to another sender, the back buffer is created again for the
+    /// size and the format of the source. The previous source is kept if the
+    /// back buffer cannot be created.
+    pub fn set_source(&mut self, source: RendererSourceOptions) -> Result<(), GraphicsError> {
+        tracing::info!("renderer set source={:?}", source);
+
+        let previous = std::mem::replace(&mut self.source, source);
+        if let Err(e) = self.recreate_back_buffer() {
+            self.source = previous;
+            self.recreate_back_buffer()?;
+
+            return Err(e);
+        }
+
+        self.update_viewport(self.target.size());
+
+        Ok(())
+    }
+
+    /// Change how the video is sampled, the back buffer is created again, so
+    /// this takes effect with the next submitted frame.
+    pub fn set_scaling_quality(&mut self, quality: ScalingQuality) -> Result<(), GraphicsError> {
+        if self.quality == quality {
+            return Ok(());
+        }
+
+        tracing::info!("renderer set scaling quality={:?}", quality);
+
+        self.quality = quality;
+        self.recreate_back_buffer()?;
+        self.update_viewport(self.target.size());
+
+        Ok(())
+    }
+
+    /// Replace the filters of the video, an empty list removes all filters.
+    /// The back buffer is created again, so this takes effect with the next
+    /// submitted frame. The previous filters are kept if a filter is invalid.
+    pub fn set_filters(&mut self, filters: &[Box<dyn VideoFilter>]) -> Result<(), GraphicsError> {
+        tracing::info!("renderer set filters={:?}", filters);
+
+        let previous = std::mem::replace(
+            &mut self.filters,
+            filters.iter().map(|it| it.shader()).collect(),
+        );
+
+        if let Err(e) = self.recreate_back_buffer() {
+            self.filters = previous;
+            self.recreate_back_buffer()?;
+
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Change how the video is placed in the surface, this takes effect with
+    /// the next drawn frame.
+    pub fn set_scaling_mode(&mut self, mode: ScalingMode) {
+        if self.mode == mode {
+            return;
+        }
+
+        tracing::info!("renderer set scaling mode={:?}", mode);
+
+        self.mode = mode;
+        self.update_viewport(self.target.size());
+    }
+
+    /// Draw the layers on top of the video, this replaces the previous layers,
+    /// and an empty list removes all layers. The layers are drawn with the next
+    /// submitted texture.
+    pub fn set_overlay(&mut self, layers: &[OverlayLayer]) {
+        self.overlay
+            .set(&self.device, &self.queue, layers, self.target.size());
+    }
+
+    /// Darken the video and the surface below the overlay layers, 0.0 disables
+    /// the dimming and 1.0 is completely black. Like the layers, the dimming is
+    /// drawn with the next submitted or redrawn frame.
+    pub fn set_dim(&mut self, dim: f32) {
+        self.overlay.set_dim(&self.device, &self.queue, dim);
     }
 
     // Submit the texture to the renderer, it should be noted that the renderer will
@@ -198,16 +620,63 @@ impl<'a> Renderer<'a> {
     // render queue and wait for the queue to automatically schedule the rendering
     // to the surface.
     pub fn submit(&mut self, texture: Texture) -> Result<(), GraphicsError> {
+        let _span = tracing::trace_span!("renderer_submit").entered();
+
+        // The frame is dropped, its texture may belong to the lost device.
+        if self.device_lost.load(Ordering::Relaxed) {
+            return self.recover_device();
+        }
+
+        let Some((output, view)) = self.acquire()? else {
+            return Ok(());
+        };
+
         let mut encoder = self
             .device
             .create_command_encoder(&CommandEncoderDescriptor { label: None });
 
-        let (pipeline, bind_group) = self.back_buffer.get_view(&mut encoder, texture)?;
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&TextureViewDescriptor::default());
+        let (_, bind_group) = self.back_buffer.get_view(&mut encoder, texture)?;
+        self.draw(encoder, output, view, &bind_group);
+        self.last_frame = Some(bind_group);
+
+        Ok(())
+    }
+
+    /// Present the last submitted frame again, for example to keep the window
+    /// alive at the target frame rate while the source stalls, or to show a
+    /// changed overlay without a new frame. Nothing is drawn if no frame has
+    /// been submitted yet.
+    pub fn redraw(&mut self) -> Result<(), GraphicsError> {
+        if self.device_lost.load(Ordering::Relaxed) {
+            return self.recover_device();
+        }
+
+        if self.last_frame.is_none() {
+            return Ok(());
+        }
+
+        let Some((output, view)) = self.acquire()? else {
+            return Ok(());
+        };
 
+        let encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+
+        if let Some(bind_group) = &self.last_frame {
+            self.draw(encoder, output, view, bind_group);
+        }
+
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        mut encoder: CommandEncoder,
+        output: Option<SurfaceTexture>,
+        view: TextureView,
+        bind_group: &BindGroup,
+    ) {
         {
             let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
                 color_attachments: &[Some(RenderPassColorAttachment {
@@ -230,17 +699,136 @@ impl<'a> Renderer<'a> {
                 1.0,
             );
 
-            render_pass.set_pipeline(pipeline);
-            render_pass.set_bind_group(0, Some(&bind_group), &[]);
+            render_pass.set_pipeline(self.back_buffer.pipeline());
+            render_pass.set_bind_group(0, Some(bind_group), &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
             render_pass.draw_indexed(0..Vertex::INDICES.len() as u32, 0, 0..1);
+
+            // The overlay is positioned on the whole surface, not only on the video.
+            let size = self.target.size();
+            render_pass.set_viewport(0.0, 0.0, size.width as f32, size.height as f32, 0.0, 1.0);
+
+            self.overlay.draw(&mut render_pass);
         }
 
         self.queue.submit(Some(encoder.finish()));
-        output.present();
 
-        Ok(())
+        if let Some(output) = output {
+            output.present();
+        }
+    }
+
+    /// Get the texture to draw into, `None` if the frame is skipped.
+    fn acquire(&mut self) -> Result<Option<(Option<SurfaceTexture>, TextureView)>, GraphicsError> {
+        let (surface, config) = match &self.target {
+            RenderTarget::Surface {
+                surface, config, ..
+            } => (surface, config),
+            RenderTarget::Texture(texture) => {
+                return Ok(Some((
+                    None,
+                    texture.create_view(&TextureViewDescriptor::default()),
+                )));
+            }
+        };
+
+        let output = match surface.get_current_texture() {
+            Ok(it) => it,
+            Err(SurfaceError::Timeout) => {
+                tracing::warn!("renderer surface timeout, skip the frame");
+
+                return Ok(None);
+            }
+            Err(SurfaceError::Outdated | SurfaceError::Lost) => {
+                tracing::warn!("renderer surface is lost, configure the surface again");
+
+                surface.configure(&self.device, config);
+                self.events.emit(RendererEvent::SurfaceLost);
+
+                match surface.get_current_texture() {
+                    Ok(it) => it,
+                    // The surface cannot be configured again on this device, so the device
+                    // is created again with the next frame.
+                    Err(e) => {
+                        tracing::warn!("renderer surface is still lost, err={:?}", e);
+
+                        self.device_lost.store(true, Ordering::Relaxed);
+                        return Ok(None);
+                    }
+                }
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let view = output
+            .texture
+            .create_view(&TextureViewDescriptor::default());
+
+        Ok(Some((Some(output), view)))
+    }
+
+    /// Create the device again on the adapter of the surface, the attempts are
+    /// bounded by [Renderer::MAX_RECOVERY_ATTEMPTS].
+    fn recover_device(&mut self) -> Result<(), GraphicsError> {
+        let RenderTarget::Surface {
+            surface,
+            config,
+            context,
+        } = &mut self.target
+        else {
+            return Err(GraphicsError::NotSurfaceTarget);
+        };
+
+        if self.recovery_attempts == 0 {
+            self.events.emit(RendererEvent::DeviceLost);
+        }
+
+        let size = Size {
+            width: config.width,
+            height: config.height,
+        };
+
+        let result = context
+            .reset_adapter(surface)
+            .and_then(|_| context.create_device())
+            .and_then(|(device, queue)| {
+                *config = context.configure(surface, &device, size)?;
+
+                Ok((device, queue))
+            });
+
+        match result.and_then(|(device, queue)| {
+            self.replace_device(device, queue);
+            self.recreate_resources()
+        }) {
+            Ok(_) => {
+                tracing::info!("renderer device recovered");
+
+                self.recovery_attempts = 0;
+                self.device_lost.store(false, Ordering::Relaxed);
+                self.events.emit(RendererEvent::DeviceRecovered);
+
+                Ok(())
+            }
+            Err(e) => {
+                self.recovery_attempts += 1;
+
+                tracing::warn!(
+                    "renderer device recovery failed, attempt={}, err={:?}",
+                    self.recovery_attempts,
+                    e
+                );
+
+                if self.recovery_attempts >= Self::MAX_RECOVERY_ATTEMPTS {
+                    self.events.emit(RendererEvent::RecoveryFailed);
+
+                    Err(e)
+                } else {
+                    Ok(())
+                }
+            }
+        }
     }
 }
 
@@ -251,33 +839,46 @@ struct Viewport {
     width: f32,
     height: f32,
     texture: Size,
+    /// The part of the texture that is drawn, left, top, right and bottom in
+    /// texture coordinates.
+    crop: [f32; 4],
 }
 
 impl Viewport {
+    const UNCROPPED: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
     fn new(texture: Size, surface: Size) -> Self {
-        let (texture_width, texture_height, surface_width, surface_height) = (
-            texture.width as f32,
-            texture.height as f32,
+        Self::fit(
+            texture,
+            0.0,
+            0.0,
             surface.width as f32,
             surface.height as f32,
-        );
+        )
+    }
+
+    /// Fit the texture into the area with the aspect ratio of the texture, the
+    /// texture is centered in the area.
+    fn fit(texture: Size, x: f32, y: f32, area_width: f32, area_height: f32) -> Self {
+        let (texture_width, texture_height) = (texture.width as f32, texture.height as f32);
 
         let texture_ratio = texture_width / texture_height;
-        let surface_ratio = surface_width / surface_height;
+        let area_ratio = area_width / area_height;
 
-        let (width, height, x, y) = if texture_ratio > surface_ratio {
-            let width = surface_width;
-            let height = surface_width / texture_ratio;
-            let y = (surface_height - height) / 2.0;
-            (width, height, 0.0, y)
+        let (width, height, x, y) = if texture_ratio > area_ratio {
+            let width = area_width;
+            let height = area_width / texture_ratio;
+            let y = y + (area_height - height) / 2.0;
+            (width, height, x, y)
         } else {
-            let height = surface_height;
-            let width = surface_height * texture_ratio;
-            let x = (surface_width - width) / 2.0;
-            (width, height, x, 0.0)
+            let height = area_height;
+            let width = area_height * texture_ratio;
+            let x = x + (area_width - width) / 2.0;
+            (width, height, x, y)
         };
 
         Self {
+            crop: Self::UNCROPPED,
             texture,
             x,
             y,
@@ -286,7 +887,72 @@ impl Viewport {
         }
     }
 
-    fn resize(&mut self, surface: Size) {
-        *self = Self::new(self.texture, surface);
+    /// The texture is enlarged by the largest whole factor that fits the
+    /// surface, and centered in the surface.
+    fn integer(texture: Size, surface: Size) -> Self {
+        let factor =
+            (surface.width / texture.width.max(1)).min(surface.height / texture.height.max(1));
+        if factor == 0 {
+            return Self::new(texture, surface);
+        }
+
+        let (width, height) = (texture.width * factor, texture.height * factor);
+
+        Self {
+            x: ((surface.width - width) / 2) as f32,
+            y: ((surface.height - height) / 2) as f32,
+            width: width as f32,
+            height: height as f32,
+            crop: Self::UNCROPPED,
+            texture,
+        }
+    }
+
+    /// The texture is scaled to the surface, the part of the texture that is
+    /// scaled beyond the surface is cropped, and the rest is centered in the
+    /// surface.
+    fn cropped(texture: Size, surface: Size, scale_x: f32, scale_y: f32) -> Self {
+        let (texture_width, texture_height) =
+            (texture.width.max(1) as f32, texture.height.max(1) as f32);
+        let (surface_width, surface_height) = (surface.width as f32, surface.height as f32);
+
+        let width = (texture_width * scale_x).min(surface_width);
+        let height = (texture_height * scale_y).min(surface_height);
+
+        // The visible part of the texture, as a fraction of the texture.
+        let visible_x = width / scale_x / texture_width;
+        let visible_y = height / scale_y / texture_height;
+
+        Self {
+            x: (surface_width - width) / 2.0,
+            y: (surface_height - height) / 2.0,
+            crop: [
+                (1.0 - visible_x) / 2.0,
+                (1.0 - visible_y) / 2.0,
+                (1.0 + visible_x) / 2.0,
+                (1.0 + visible_y) / 2.0,
+            ],
+            width,
+            height,
+            texture,
+        }
+    }
+
+    fn scaled(texture: Size, surface: Size, quality: ScalingQuality, mode: ScalingMode) -> Self {
+        let scale_x = surface.width as f32 / texture.width.max(1) as f32;
+        let scale_y = surface.height as f32 / texture.height.max(1) as f32;
+
+        match mode {
+            ScalingMode::Fit if quality == ScalingQuality::Integer => {
+                Self::integer(texture, surface)
+            }
+            ScalingMode::Fit => Self::new(texture, surface),
+            ScalingMode::Fill => {
+                let scale = scale_x.max(scale_y);
+                Self::cropped(texture, surface, scale, scale)
+            }
+            ScalingMode::Stretch => Self::cropped(texture, surface, scale_x, scale_y),
+            ScalingMode::Center => Self::cropped(texture, surface, 1.0, 1.0),
+        }
     }
 }
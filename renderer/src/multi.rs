@@ -0,0 +1,339 @@
+use std::sync::Arc;
+
+use crate::{
+    GraphicsError, RendererSourceOptions, RendererSurfaceOptions, ScalingQuality, Texture, Vertex,
+    Viewport,
+    backbuffer::{BackBuffer, BackBufferOptions},
+    surface::SurfaceContext,
+};
+
+use common::{Size, frame::VideoSubFormat};
+use wgpu::{
+    BindGroup, Buffer, Color, CommandEncoderDescriptor, Device, IndexFormat, LoadOp, Operations,
+    Queue, RenderPassColorAttachment, RenderPassDescriptor, StoreOp, Surface, SurfaceConfiguration,
+    SurfaceTarget, TextureViewDescriptor,
+};
+
+/// How the views are arranged on the surface, the views are ordered by the
+/// time they are added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiRendererLayout {
+    /// The views are arranged in a grid with as many columns as rows, or one
+    /// more column.
+    Grid,
+    /// The first view fills the surface, the other views are small windows
+    /// stacked up from the bottom right corner.
+    PictureInPicture,
+}
+
+#[derive(Debug)]
+pub struct MultiRendererOptions<T> {
+    #[cfg(target_os = "windows")]
+    pub direct3d: common::win32::Direct3DDevice,
+    pub surface: RendererSurfaceOptions<T>,
+    pub layout: MultiRendererLayout,
+    /// The subformat of the frames of the views, importing dma-buf needs a
+    /// different device, so all the views must agree on it.
+    pub sub_format: VideoSubFormat,
+}
+
+struct View {
+    id: u32,
+    back_buffer: BackBuffer,
+    bind_group: Option<BindGroup>,
+    viewport: Viewport,
+}
+
+/// Renders the videos of several receivers into one window.
+///
+/// Each view has its own back buffer, but all the views share one device and
+/// one surface, so there is only one swapchain no matter how many views there
+/// are. The whole surface is drawn again when any view submits a texture, the
+/// other views keep their last texture.
+pub struct MultiRenderer<'a> {
+    #[cfg(target_os = "windows")]
+    direct3d: common::win32::Direct3DDevice,
+    config: SurfaceConfiguration,
+    surface: Surface<'a>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    layout: MultiRendererLayout,
+    #[cfg(target_os = "linux")]
+    sub_format: VideoSubFormat,
+    views: Vec<View>,
+    index: u32,
+}
+
+impl<'a> MultiRenderer<'a> {
+    /// Size of the small windows of the picture in picture layout, relative
+    /// to the surface.
+    const PIP_SCALE: f32 = 0.25;
+
+    /// Distance between the small windows and the edges of the surface.
+    const PIP_MARGIN: f32 = 16.0;
+
+    pub fn new<T: Into<SurfaceTarget<'a>>>(
+        MultiRendererOptions {
+            #[cfg(target_os = "windows")]
+            direct3d,
+            surface:
+                RendererSurfaceOptions {
+                    window,
+                    size,
+                    present,
+                    backend,
+                },
+            layout,
+            sub_format,
+        }: MultiRendererOptions<T>,
+    ) -> Result<Self, GraphicsError> {
+        tracing::info!("create multi renderer, layout={:?}", layout);
+
+        let (context, surface) = SurfaceContext::new(
+            window,
+            backend,
+            present,
+            sub_format,
+            #[cfg(target_os = "windows")]
+            direct3d.clone(),
+        )?;
+
+        let (device, queue) = context.create_device()?;
+        let config = context.configure(&surface, &device, size)?;
+        let (vertex_buffer, index_buffer) = Vertex::create_buffers(&device);
+
+        Ok(Self {
+            #[cfg(target_os = "windows")]
+            direct3d,
+            views: Vec::new(),
+            index: 0,
+            vertex_buffer,
+            index_buffer,
+            #[cfg(target_os = "linux")]
+            sub_format,
+            layout,
+            surface,
+            device,
+            queue,
+            config,
+        })
+    }
+
+    /// Add a view for the frames of a receiver, returns the id of the view,
+    /// which is passed to [MultiRenderer::submit].
+    pub fn add_view(&mut self, source: RendererSourceOptions) -> Result<u32, GraphicsError> {
+        #[cfg(target_os = "linux")]
+        if source.sub_format == VideoSubFormat::DmaBuf && self.sub_format != VideoSubFormat::DmaBuf
+        {
+            return Err(GraphicsError::NotSupportedDmaBufTarget);
+        }
+
+        let back_buffer = self.back_buffer(source)?;
+
+        self.index += 1;
+        self.views.push(View {
+            viewport: Viewport::new(source.size, self.size()),
+            bind_group: None,
+            id: self.index,
+            back_buffer,
+        });
+
+        self.layout_views();
+
+        tracing::info!("multi renderer add view, id={}", self.index);
+
+        Ok(self.index)
+    }
+
+    /// Render the frames of another source in the view, such as when the
+    /// receiver of the view switches to another sender. The view is black
+    /// until the next texture is submitted.
+    pub fn set_view_source(
+        &mut self,
+        id: u32,
+        source: RendererSourceOptions,
+    ) -> Result<(), GraphicsError> {
+        #[cfg(target_os = "linux")]
+        if source.sub_format == VideoSubFormat::DmaBuf && self.sub_format != VideoSubFormat::DmaBuf
+        {
+            return Err(GraphicsError::NotSupportedDmaBufTarget);
+        }
+
+        let back_buffer = self.back_buffer(source)?;
+        let size = self.size();
+
+        if let Some(view) = self.views.iter_mut().find(|it| it.id == id) {
+            view.back_buffer = back_buffer;
+            view.bind_group = None;
+            view.viewport = Viewport::new(source.size, size);
+        }
+
+        self.layout_views();
+
+        tracing::info!(
+            "multi renderer set view source, id={}, source={:?}",
+            id,
+            source
+        );
+
+        Ok(())
+    }
+
+    pub fn remove_view(&mut self, id: u32) {
+        self.views.retain(|it| it.id != id);
+        self.layout_views();
+
+        tracing::info!("multi renderer remove view, id={}", id);
+    }
+
+    pub fn set_layout(&mut self, layout: MultiRendererLayout) {
+        self.layout = layout;
+        self.layout_views();
+    }
+
+    pub fn resize(&mut self, size: Size) {
+        self.config.width = size.width;
+        self.config.height = size.height;
+        self.surface.configure(&self.device, &self.config);
+        self.layout_views();
+    }
+
+    /// Submit the texture of a view and draw all the views to the surface.
+    pub fn submit(&mut self, id: u32, texture: Texture) -> Result<(), GraphicsError> {
+        let _span = tracing::trace_span!("renderer_submit", id).entered();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+
+        if let Some(view) = self.views.iter_mut().find(|it| it.id == id) {
+            let (_, bind_group) = view.back_buffer.get_view(&mut encoder, texture)?;
+            view.bind_group = Some(bind_group);
+        } else {
+            tracing::warn!("multi renderer submit to a missing view, id={}", id);
+
+            return Ok(());
+        }
+
+        let output = self.surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&TextureViewDescriptor::default());
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
+
+            // Views that have not received a texture yet stay black.
+            for view in &self.views {
+                if let Some(bind_group) = &view.bind_group {
+                    render_pass.set_viewport(
+                        view.viewport.x,
+                        view.viewport.y,
+                        view.viewport.width,
+                        view.viewport.height,
+                        0.0,
+                        1.0,
+                    );
+
+                    render_pass.set_pipeline(view.back_buffer.pipeline());
+                    render_pass.set_bind_group(0, Some(bind_group), &[]);
+                    render_pass.draw_indexed(0..Vertex::INDICES.len() as u32, 0, 0..1);
+                }
+            }
+        }
+
+        self.queue.submit(Some(encoder.finish()));
+        output.present();
+
+        Ok(())
+    }
+
+    fn size(&self) -> Size {
+        Size {
+            width: self.config.width,
+            height: self.config.height,
+        }
+    }
+
+    fn back_buffer(&self, source: RendererSourceOptions) -> Result<BackBuffer, GraphicsError> {
+        Ok(BackBuffer::new(BackBufferOptions {
+            device: self.device.clone(),
+            queue: self.queue.clone(),
+            size: source.size,
+            format: source.format,
+            sub_format: source.sub_format,
+            target: self.config.format,
+            quality: ScalingQuality::default(),
+            filters: Vec::new(),
+            #[cfg(target_os = "windows")]
+            direct3d: self.direct3d.clone(),
+        })?)
+    }
+
+    fn layout_views(&mut self) {
+        let (width, height) = (self.config.width as f32, self.config.height as f32);
+
+        match self.layout {
+            MultiRendererLayout::Grid => {
+                let count = self.views.len().max(1);
+                let columns = (count as f32).sqrt().ceil() as usize;
+                let rows = count.div_ceil(columns);
+
+                let (cell_width, cell_height) = (width / columns as f32, height / rows as f32);
+                for (index, view) in self.views.iter_mut().enumerate() {
+                    view.viewport = Viewport::fit(
+                        view.viewport.texture,
+                        (index % columns) as f32 * cell_width,
+                        (index / columns) as f32 * cell_height,
+                        cell_width,
+                        cell_height,
+                    );
+                }
+            }
+            MultiRendererLayout::PictureInPicture => {
+                let (small_width, small_height) =
+                    (width * Self::PIP_SCALE, height * Self::PIP_SCALE);
+
+                // The small windows are stacked up from the bottom right corner, and wrap
+                // to the next column on the left when the column is full. The columns that
+                // do not fit are kept at the left edge.
+                let rows = (((height - Self::PIP_MARGIN) / (small_height + Self::PIP_MARGIN))
+                    as usize)
+                    .max(1);
+
+                for (index, view) in self.views.iter_mut().enumerate() {
+                    view.viewport = if index == 0 {
+                        Viewport::fit(view.viewport.texture, 0.0, 0.0, width, height)
+                    } else {
+                        let (column, row) = ((index - 1) / rows, (index - 1) % rows);
+
+                        Viewport::fit(
+                            view.viewport.texture,
+                            (width - (small_width + Self::PIP_MARGIN) * (column + 1) as f32)
+                                .max(0.0),
+                            (height - (small_height + Self::PIP_MARGIN) * (row + 1) as f32)
+                                .max(0.0),
+                            small_width,
+                            small_height,
+                        )
+                    };
+                }
+            }
+        }
+    }
+}
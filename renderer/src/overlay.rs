@@ -0,0 +1,352 @@
+use std::borrow::Cow;
+
+use crate::Vertex;
+
+use common::Size;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState,
+    Buffer, BufferUsages, ColorTargetState, ColorWrites, Device, Extent3d, FilterMode,
+    FragmentState, ImageCopyTexture, ImageDataLayout, IndexFormat, MultisampleState, Origin3d,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, Queue,
+    RenderPass, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureViewDescriptor, TextureViewDimension, VertexState,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+
+const FRAGMENT_SHADER: &str = r#"
+@group(0) @binding(0) var texture_: texture_2d<f32>;
+@group(0) @binding(1) var sampler_: sampler;
+
+@fragment fn main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
+    return textureSample(texture_, sampler_, coords);
+}"#;
+
+/// Where the overlay is placed on the surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+/// RGBA image with straight alpha drawn on top of the video, such as
+/// connection statistics or a volume indicator.
+///
+/// The overlay is drawn in the pixels of the surface, it is not scaled with the
+/// video.
+#[derive(Debug, Clone)]
+pub struct OverlayLayer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub position: OverlayPosition,
+    /// Distance to the edges of the surface in pixels, ignored in the center.
+    pub margin: u32,
+}
+
+struct OverlayResource {
+    layer: OverlayLayer,
+    bind_group: BindGroup,
+    vertex_buffer: Buffer,
+}
+
+impl OverlayResource {
+    fn vertices(layer: &OverlayLayer, surface: Size) -> [Vertex; 4] {
+        let (width, height) = (layer.width as f32, layer.height as f32);
+        let (surface_width, surface_height) =
+            (surface.width.max(1) as f32, surface.height.max(1) as f32);
+
+        let margin = layer.margin as f32;
+        let x = match layer.position {
+            OverlayPosition::TopLeft | OverlayPosition::BottomLeft => margin,
+            OverlayPosition::TopRight | OverlayPosition::BottomRight => {
+                surface_width - width - margin
+            }
+            OverlayPosition::Center => (surface_width - width) / 2.0,
+        };
+
+        let y = match layer.position {
+            OverlayPosition::TopLeft | OverlayPosition::TopRight => margin,
+            OverlayPosition::BottomLeft | OverlayPosition::BottomRight => {
+                surface_height - height - margin
+            }
+            OverlayPosition::Center => (surface_height - height) / 2.0,
+        };
+
+        // From surface pixels to normalized device coordinates, the y axis of the
+        // device coordinates points up.
+        let left = x / surface_width * 2.0 - 1.0;
+        let right = (x + width) / surface_width * 2.0 - 1.0;
+        let top = 1.0 - y / surface_height * 2.0;
+        let bottom = 1.0 - (y + height) / surface_height * 2.0;
+
+        [
+            Vertex::new([left, bottom], [0.0, 0.0]),
+            Vertex::new([right, bottom], [1.0, 0.0]),
+            Vertex::new([left, top], [0.0, 1.0]),
+            Vertex::new([right, top], [1.0, 1.0]),
+        ]
+    }
+}
+
+/// Draws the overlay layers on top of the video, the layers are blended with
+/// their alpha in order.
+pub(crate) struct Overlay {
+    sampler: Sampler,
+    layout: BindGroupLayout,
+    pipeline: RenderPipeline,
+    index_buffer: Buffer,
+    quad_buffer: Buffer,
+    dim: Option<(f32, BindGroup)>,
+    layers: Vec<OverlayResource>,
+}
+
+impl Overlay {
+    pub fn new(device: &Device, target: TextureFormat) -> Self {
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mipmap_filter: FilterMode::Nearest,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    count: None,
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                },
+                BindGroupLayoutEntry {
+                    count: None,
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                },
+            ],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            })),
+            vertex: VertexState {
+                entry_point: Some("main"),
+                module: &device.create_shader_module(ShaderModuleDescriptor {
+                    label: None,
+                    source: ShaderSource::Wgsl(Cow::Borrowed(Vertex::VERTEX_SHADER)),
+                }),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(FragmentState {
+                entry_point: Some("main"),
+                module: &device.create_shader_module(ShaderModuleDescriptor {
+                    label: None,
+                    source: ShaderSource::Wgsl(Cow::Borrowed(FRAGMENT_SHADER)),
+                }),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                    format: target,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: Some(IndexFormat::Uint16),
+                ..Default::default()
+            },
+            multisample: MultisampleState::default(),
+            depth_stencil: None,
+            multiview: None,
+            cache: None,
+        });
+
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(Vertex::INDICES),
+            usage: BufferUsages::INDEX,
+        });
+
+        let quad_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(Vertex::VERTICES),
+            usage: BufferUsages::VERTEX,
+        });
+
+        Self {
+            layers: Vec::new(),
+            dim: None,
+            index_buffer,
+            quad_buffer,
+            pipeline,
+            sampler,
+            layout,
+        }
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> BindGroup {
+        let size = Extent3d {
+            depth_or_array_layers: 1,
+            width,
+            height,
+        };
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: None,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            format: TextureFormat::Rgba8Unorm,
+            view_formats: &[],
+            size,
+        });
+
+        queue.write_texture(
+            ImageCopyTexture {
+                aspect: TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+            },
+            pixels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+
+    pub fn dim(&self) -> f32 {
+        self.dim.as_ref().map(|(it, _)| *it).unwrap_or(0.0)
+    }
+
+    /// Darken the whole surface below the layers, 0.0 disables the dimming and
+    /// 1.0 is completely black.
+    pub fn set_dim(&mut self, device: &Device, queue: &Queue, dim: f32) {
+        let dim = dim.clamp(0.0, 1.0);
+        self.dim = if dim > 0.0 {
+            let pixel = [0, 0, 0, (dim * 255.0).round() as u8];
+
+            Some((dim, self.create_bind_group(device, queue, 1, 1, &pixel)))
+        } else {
+            None
+        };
+    }
+
+    pub fn layers(&self) -> Vec<OverlayLayer> {
+        self.layers.iter().map(|it| it.layer.clone()).collect()
+    }
+
+    /// Replace all layers, the textures of the layers are uploaded here.
+    pub fn set(&mut self, device: &Device, queue: &Queue, layers: &[OverlayLayer], surface: Size) {
+        self.layers.clear();
+
+        for layer in layers {
+            if layer.width == 0
+                || layer.height == 0
+                || layer.pixels.len() != layer.width as usize * layer.height as usize * 4
+            {
+                tracing::warn!(
+                    "renderer ignore invalid overlay layer, size={}x{}",
+                    layer.width,
+                    layer.height
+                );
+
+                continue;
+            }
+
+            let bind_group =
+                self.create_bind_group(device, queue, layer.width, layer.height, &layer.pixels);
+
+            let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&OverlayResource::vertices(layer, surface)[..]),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            });
+
+            self.layers.push(OverlayResource {
+                layer: layer.clone(),
+                vertex_buffer,
+                bind_group,
+            });
+        }
+    }
+
+    /// The layers keep their size in pixels, so the positions are calculated
+    /// again when the surface is resized.
+    pub fn resize(&self, queue: &Queue, surface: Size) {
+        for it in &self.layers {
+            queue.write_buffer(
+                &it.vertex_buffer,
+                0,
+                bytemuck::cast_slice(&OverlayResource::vertices(&it.layer, surface)[..]),
+            );
+        }
+    }
+
+    pub fn draw(&self, render_pass: &mut RenderPass) {
+        if self.layers.is_empty() && self.dim.is_none() {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
+
+        if let Some((_, bind_group)) = &self.dim {
+            render_pass.set_bind_group(0, Some(bind_group), &[]);
+            render_pass.set_vertex_buffer(0, self.quad_buffer.slice(..));
+            render_pass.draw_indexed(0..Vertex::INDICES.len() as u32, 0, 0..1);
+        }
+
+        for it in &self.layers {
+            render_pass.set_bind_group(0, Some(&it.bind_group), &[]);
+            render_pass.set_vertex_buffer(0, it.vertex_buffer.slice(..));
+            render_pass.draw_indexed(0..Vertex::INDICES.len() as u32, 0, 0..1);
+        }
+    }
+}
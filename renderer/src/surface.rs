@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use crate::{GraphicsError, RendererPresentOptions};
+
+#[cfg(target_os = "linux")]
+use crate::transform;
+
+#[cfg(target_os = "windows")]
+use wgpu::hal::api::Dx12;
+
+use common::{Size, frame::VideoSubFormat, runtime};
+use wgpu::{
+    Adapter, Backends, CompositeAlphaMode, Device, DeviceDescriptor, Instance, InstanceDescriptor,
+    MemoryHints, PowerPreference, PresentMode, Queue, RequestAdapterOptions, Surface,
+    SurfaceConfiguration, SurfaceTarget, TextureFormat, TextureUsages,
+};
+
+/// The graphics api used by the renderer.
+///
+/// The hardware textures of the decoders are imported with the native api of
+/// the platform, so the other backends only accept software frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RendererBackend {
+    Dx12,
+    Vulkan,
+    Metal,
+    /// OpenGL, or OpenGL ES on the platforms without desktop OpenGL.
+    Gl,
+}
+
+impl RendererBackend {
+    /// DX12 on windows, Vulkan on linux and Metal on macos.
+    pub fn native() -> Self {
+        if cfg!(target_os = "windows") {
+            Self::Dx12
+        } else if cfg!(target_os = "linux") {
+            Self::Vulkan
+        } else {
+            Self::Metal
+        }
+    }
+
+    fn backends(self) -> Backends {
+        match self {
+            Self::Dx12 => Backends::DX12,
+            Self::Vulkan => Backends::VULKAN,
+            Self::Metal => Backends::METAL,
+            Self::Gl => Backends::GL,
+        }
+    }
+}
+
+/// The instance and the adapter of a window, they are kept so that the surface
+/// can be created again for another window, and the device can be created
+/// again on another adapter.
+pub(crate) struct SurfaceContext {
+    instance: Instance,
+    adapter: Adapter,
+    backend: RendererBackend,
+    present: RendererPresentOptions,
+    #[cfg(target_os = "linux")]
+    sub_format: VideoSubFormat,
+    #[cfg(target_os = "windows")]
+    direct3d: common::win32::Direct3DDevice,
+}
+
+impl SurfaceContext {
+    /// Create the window surface and select the adapter for it.
+    pub fn new<'a, T: Into<SurfaceTarget<'a>>>(
+        window: T,
+        backend: Option<RendererBackend>,
+        present: RendererPresentOptions,
+        sub_format: VideoSubFormat,
+        #[cfg(target_os = "windows")] direct3d: common::win32::Direct3DDevice,
+    ) -> Result<(Self, Surface<'a>), GraphicsError> {
+        let backend = backend.unwrap_or_else(RendererBackend::native);
+        if backend != RendererBackend::native() && sub_format != VideoSubFormat::SW {
+            return Err(GraphicsError::NotSupportedBackend(backend));
+        }
+
+        let instance = Instance::new(InstanceDescriptor {
+            backends: backend.backends(),
+            ..Default::default()
+        });
+
+        let surface = instance.create_surface(window)?;
+        let adapter = Self::request_adapter(
+            &instance,
+            &surface,
+            backend,
+            #[cfg(target_os = "windows")]
+            &direct3d,
+        )?;
+
+        Ok((
+            Self {
+                instance,
+                adapter,
+                backend,
+                present,
+                #[cfg(target_os = "linux")]
+                sub_format,
+                #[cfg(target_os = "windows")]
+                direct3d,
+            },
+            surface,
+        ))
+    }
+
+    fn request_adapter(
+        instance: &Instance,
+        surface: &Surface,
+        backend: RendererBackend,
+        #[cfg(target_os = "windows")] direct3d: &common::win32::Direct3DDevice,
+    ) -> Result<Adapter, GraphicsError> {
+        // The textures of the capture and the codecs are shared with the renderer, so
+        // the renderer uses the adapter of the direct3d device, otherwise the textures
+        // are copied between the adapters on the systems with more than one gpu.
+        #[cfg(target_os = "windows")]
+        let adapter = match direct3d.adapter() {
+            Ok(it) => instance
+                .enumerate_adapters(backend.backends())
+                .into_iter()
+                .find(|adapter| {
+                    // The luid tells two identical gpus apart, it is only known with dx12,
+                    // which is also the only backend that imports the textures.
+                    let is_same = match Self::adapter_luid(adapter) {
+                        Some(luid) => luid == it.luid,
+                        None => {
+                            let info = adapter.get_info();
+
+                            info.vendor == it.vendor_id && info.device == it.device_id
+                        }
+                    };
+
+                    is_same && adapter.is_surface_supported(surface)
+                }),
+            Err(e) => {
+                tracing::warn!(
+                    "failed to get the adapter of the direct3d device, err={:?}",
+                    e
+                );
+
+                None
+            }
+        };
+
+        #[cfg(not(target_os = "windows"))]
+        let adapter = None;
+
+        let adapter = match adapter {
+            Some(it) => it,
+            None => runtime::block_on(instance.request_adapter(&RequestAdapterOptions {
+                power_preference: PowerPreference::LowPower,
+                force_fallback_adapter: false,
+                compatible_surface: Some(surface),
+                ..Default::default()
+            }))
+            .ok_or_else(|| GraphicsError::NotFoundAdapter)?,
+        };
+
+        tracing::info!(
+            "renderer use adapter, backend={:?}, info={:?}",
+            backend,
+            adapter.get_info()
+        );
+
+        Ok(adapter)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn adapter_luid(adapter: &Adapter) -> Option<i64> {
+        unsafe {
+            adapter.as_hal::<Dx12, _, _>(|adapter| {
+                let desc = adapter?.raw_adapter().GetDesc1().ok()?;
+
+                Some(common::win32::luid_to_i64(desc.AdapterLuid))
+            })
+        }
+    }
+
+    /// Create the surface of another window, returns true if the window is on
+    /// another adapter, in which case the device must be created again with
+    /// [SurfaceContext::create_device].
+    pub fn create_surface<'a, T: Into<SurfaceTarget<'a>>>(
+        &mut self,
+        window: T,
+    ) -> Result<(Surface<'a>, bool), GraphicsError> {
+        let surface = self.instance.create_surface(window)?;
+        if self.adapter.is_surface_supported(&surface) {
+            return Ok((surface, false));
+        }
+
+        self.reset_adapter(&surface)?;
+        Ok((surface, true))
+    }
+
+    /// Select the adapter for the surface again, the adapter of a lost device
+    /// may not be usable anymore.
+    pub fn reset_adapter(&mut self, surface: &Surface) -> Result<(), GraphicsError> {
+        self.adapter = Self::request_adapter(
+            &self.instance,
+            surface,
+            self.backend,
+            #[cfg(target_os = "windows")]
+            &self.direct3d,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn create_device(&self) -> Result<(Arc<Device>, Arc<Queue>), GraphicsError> {
+        let descriptor = DeviceDescriptor {
+            label: None,
+            memory_hints: MemoryHints::MemoryUsage,
+            required_features: self.adapter.features(),
+            required_limits: self.adapter.limits(),
+        };
+
+        // Importing dma-buf requires vulkan device extensions that wgpu does not
+        // enable, so the device is created by the transformer in this case.
+        #[cfg(target_os = "linux")]
+        let (device, queue) = if self.sub_format == VideoSubFormat::DmaBuf {
+            transform::vulkan::create_device(&self.adapter, &descriptor)?
+        } else {
+            runtime::block_on(self.adapter.request_device(&descriptor, None))?
+        };
+
+        #[cfg(not(target_os = "linux"))]
+        let (device, queue) = runtime::block_on(self.adapter.request_device(&descriptor, None))?;
+
+        Ok((Arc::new(device), Arc::new(queue)))
+    }
+
+    /// Configure the surface as BGRA.
+    pub fn configure(
+        &self,
+        surface: &Surface,
+        device: &Device,
+        size: Size,
+    ) -> Result<SurfaceConfiguration, GraphicsError> {
+        // Configure surface as BGRA, BGRA this format compatibility is the best, in
+        // order to unnecessary trouble, directly fixed to BGRA is the best.
+        let mut config = surface
+            .get_default_config(&self.adapter, size.width, size.height)
+            .ok_or_else(|| GraphicsError::NotFoundSurfaceDefaultConfig)?;
+
+        let default_present_mode = if cfg!(target_os = "windows") {
+            PresentMode::Mailbox
+        } else if cfg!(target_os = "linux") {
+            PresentMode::Fifo
+        } else {
+            PresentMode::Immediate
+        };
+
+        // Fifo is the only mode that all surfaces support.
+        let present_modes = surface.get_capabilities(&self.adapter).present_modes;
+        config.present_mode = [self.present.mode, Some(default_present_mode)]
+            .into_iter()
+            .flatten()
+            .find(|it| present_modes.contains(it))
+            .unwrap_or(PresentMode::Fifo);
+
+        if self
+            .present
+            .mode
+            .is_some_and(|it| it != config.present_mode)
+        {
+            tracing::warn!(
+                "present mode is not supported by the surface, mode={:?}, fallback={:?}",
+                self.present.mode,
+                config.present_mode
+            );
+        }
+
+        if let Some(max_frame_latency) = self.present.max_frame_latency {
+            config.desired_maximum_frame_latency = max_frame_latency.max(1);
+        }
+
+        config.format = TextureFormat::Bgra8Unorm;
+        config.alpha_mode = CompositeAlphaMode::Opaque;
+        config.usage = TextureUsages::RENDER_ATTACHMENT;
+        surface.configure(device, &config);
+
+        Ok(config)
+    }
+}
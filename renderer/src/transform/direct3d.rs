@@ -5,22 +5,29 @@ use common::{
     frame::VideoFormat,
     win32::{
         Direct3DDevice, EasyTexture,
-        windows::Win32::Graphics::{
-            Direct3D11::{
-                D3D11_RESOURCE_MISC_SHARED, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
-                ID3D11Texture2D,
-            },
-            Direct3D12::ID3D12Resource,
-            Dxgi::Common::{
-                DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_NV12, DXGI_FORMAT_R8G8B8A8_UNORM,
+        windows::{
+            Win32::{
+                Foundation::{CloseHandle, GENERIC_ALL},
+                Graphics::{
+                    Direct3D11::{
+                        D3D11_FENCE_FLAG_SHARED, D3D11_RESOURCE_MISC_SHARED, D3D11_TEXTURE2D_DESC,
+                        D3D11_USAGE_DEFAULT, ID3D11Device5, ID3D11DeviceContext4, ID3D11Fence,
+                        ID3D11Texture2D,
+                    },
+                    Direct3D12::{ID3D12CommandQueue, ID3D12Fence, ID3D12Resource},
+                    Dxgi::Common::{
+                        DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_NV12, DXGI_FORMAT_R8G8B8A8_UNORM,
+                    },
+                },
             },
+            core::{Interface, PCWSTR},
         },
     },
 };
 
 use wgpu::{
-    Device, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-    hal::api::Dx12,
+    Device, Extent3d, Queue, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, hal::api::Dx12,
 };
 
 pub struct Transformer {
@@ -163,3 +170,111 @@ impl Transformer {
         Ok(&self.texture)
     }
 }
+
+/// A fence that is shared by the d3d11 device and the dx12 queue of wgpu, so
+/// that the two devices wait for each other on the gpu. Every signal
+/// increases the value of the fence.
+pub struct SharedFence {
+    context: ID3D11DeviceContext4,
+    d3d11: ID3D11Fence,
+    d3d12: ID3D12Fence,
+    value: u64,
+}
+
+unsafe impl Sync for SharedFence {}
+unsafe impl Send for SharedFence {}
+
+impl SharedFence {
+    pub fn new(direct3d: &Direct3DDevice, device: &Device) -> Result<Self, TransformError> {
+        let context = direct3d.context.cast::<ID3D11DeviceContext4>()?;
+        let d3d11 = unsafe {
+            direct3d
+                .device
+                .cast::<ID3D11Device5>()?
+                .CreateFence::<ID3D11Fence>(0, D3D11_FENCE_FLAG_SHARED)?
+        };
+
+        // The dx12 device opens the fence with a shared handle, the handle is not
+        // needed after that.
+        let handle = unsafe { d3d11.CreateSharedHandle(None, GENERIC_ALL.0, PCWSTR::null())? };
+        let d3d12 = unsafe {
+            device.as_hal::<Dx12, _, _>(|hdevice| {
+                let mut fence = None::<ID3D12Fence>;
+
+                hdevice
+                    .ok_or_else(|| TransformError::NotFoundDxBackend)?
+                    .raw_device()
+                    .OpenSharedHandle(handle, &mut fence)
+                    .map(|_| fence.unwrap())
+                    .map_err(|e| TransformError::WindowsError(e))
+            })
+        };
+
+        unsafe {
+            let _ = CloseHandle(handle);
+        }
+
+        Ok(Self {
+            d3d12: d3d12.ok_or_else(|| TransformError::NotFoundDxBackend)??,
+            value: 0,
+            context,
+            d3d11,
+        })
+    }
+
+    /// Signals the fence after the work that is recorded on the d3d11 context,
+    /// the context is flushed so that a wait for the value does not depend on
+    /// the next flush.
+    pub fn signal_d3d11(&mut self) -> Result<u64, TransformError> {
+        self.value += 1;
+
+        unsafe {
+            self.context.Signal(&self.d3d11, self.value)?;
+            self.context.Flush();
+        }
+
+        Ok(self.value)
+    }
+
+    /// Signals the fence after the work that is submitted to the queue.
+    pub fn signal_dx12(&mut self, queue: &Queue) -> Result<u64, TransformError> {
+        self.value += 1;
+
+        let value = self.value;
+        Self::with_queue(queue, |queue| unsafe { queue.Signal(&self.d3d12, value) })?;
+
+        Ok(value)
+    }
+
+    /// The work that is recorded on the d3d11 context after this waits for
+    /// the value.
+    pub fn wait_d3d11(&self, value: u64) -> Result<(), TransformError> {
+        unsafe {
+            self.context.Wait(&self.d3d11, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// The work that is submitted to the queue after this waits for the
+    /// value.
+    pub fn wait_dx12(&self, queue: &Queue, value: u64) -> Result<(), TransformError> {
+        Self::with_queue(queue, |queue| unsafe { queue.Wait(&self.d3d12, value) })
+    }
+
+    fn with_queue<F>(queue: &Queue, func: F) -> Result<(), TransformError>
+    where
+        F: FnOnce(&ID3D12CommandQueue) -> common::win32::windows::core::Result<()>,
+    {
+        unsafe {
+            queue.as_hal::<Dx12, _, _>(|queue| {
+                Ok(func(
+                    queue
+                        .ok_or_else(|| TransformError::NotFoundDxBackend)?
+                        .as_raw(),
+                )?)
+            })
+        }
+        .ok_or_else(|| TransformError::NotFoundDxBackend)?
+    }
+}
@@ -3,7 +3,10 @@ use std::sync::Arc;
 use common::{
     Size,
     frame::VideoFormat,
-    macos::{CVPixelBufferRef, MTLTextureType, MetalTextureCache, PixelBuffer},
+    macos::{
+        CVPixelBufferRef, MTLTextureType, MetalTextureCache, PixelBuffer, Texture as MetalTexture,
+        get_pixel_buffer_plane_size,
+    },
 };
 
 use wgpu::{
@@ -14,6 +17,60 @@ use wgpu::{
 
 use super::TransformError;
 
+/// Creates a metal texture cache for converting pixel buffers to metal
+/// textures.
+fn create_texture_cache(device: &Device) -> Result<MetalTextureCache, TransformError> {
+    // Get the wgpu underlying metal device.
+    let mut raw_device = None;
+    unsafe {
+        device.as_hal::<Metal, _, _>(|device| {
+            if let Some(device) = device {
+                raw_device = Some(device.raw_device().lock().clone());
+            }
+        });
+    }
+
+    let raw_device = raw_device.ok_or_else(|| TransformError::NotFoundMetalBackend)?;
+    Ok(MetalTextureCache::new(raw_device)?)
+}
+
+/// Wraps a texture of the texture cache as a wgpu texture, the texture is
+/// only valid while the pixel buffer is alive.
+fn create_texture_from_metal(
+    device: &Device,
+    texture: MetalTexture,
+    format: TextureFormat,
+    size: Extent3d,
+    usage: TextureUsages,
+) -> Texture {
+    unsafe {
+        device.create_texture_from_hal::<Metal>(
+            <Metal as Api>::Device::texture_from_raw(
+                texture,
+                format,
+                MTLTextureType::D2,
+                1,
+                1,
+                CopyExtent {
+                    width: size.width,
+                    height: size.height,
+                    depth: 1,
+                },
+            ),
+            &TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            },
+        )
+    }
+}
+
 pub struct Transformer {
     cache: MetalTextureCache,
     device: Arc<Device>,
@@ -29,21 +86,7 @@ impl Transformer {
         size: Size,
         format: VideoFormat,
     ) -> Result<Self, TransformError> {
-        // Get the wgpu underlying metal device.
-        let mut raw_device = None;
-        unsafe {
-            device.as_hal::<Metal, _, _>(|device| {
-                if let Some(device) = device {
-                    raw_device = Some(device.raw_device().lock().clone());
-                }
-            });
-        }
-
-        // Creates a metal texture buffer for converting pixel buffers to metal
-        // textures.
-        let raw_device = raw_device.ok_or_else(|| TransformError::NotFoundMetalBackend)?;
-        let cache = MetalTextureCache::new(raw_device)?;
-
+        let cache = create_texture_cache(&device)?;
         let texture = device.create_texture(&TextureDescriptor {
             label: None,
             format: match format {
@@ -79,32 +122,13 @@ impl Transformer {
 
         encoder.copy_texture_to_texture(
             ImageCopyTexture {
-                texture: &unsafe {
-                    self.device.create_texture_from_hal::<Metal>(
-                        <Metal as Api>::Device::texture_from_raw(
-                            self.cache.map(PixelBuffer::from(buffer))?.get_texture()?,
-                            self.texture.format(),
-                            MTLTextureType::D2,
-                            1,
-                            1,
-                            CopyExtent {
-                                width: size.width,
-                                height: size.height,
-                                depth: 1,
-                            },
-                        ),
-                        &TextureDescriptor {
-                            label: None,
-                            size,
-                            mip_level_count: 1,
-                            sample_count: 1,
-                            dimension: TextureDimension::D2,
-                            format: self.texture.format(),
-                            usage: TextureUsages::COPY_SRC,
-                            view_formats: &[],
-                        },
-                    )
-                },
+                texture: &create_texture_from_metal(
+                    &self.device,
+                    self.cache.map(PixelBuffer::from(buffer))?.get_texture()?,
+                    self.texture.format(),
+                    size,
+                    TextureUsages::COPY_SRC,
+                ),
                 mip_level: 0,
                 origin: Origin3d::default(),
                 aspect: TextureAspect::All,
@@ -122,3 +146,59 @@ impl Transformer {
         Ok(&self.texture)
     }
 }
+
+/// Maps the planes of the NV12 pixel buffers without copying them, the luma
+/// plane is a `R8Unorm` texture and the chroma plane is a `Rg8Unorm` texture,
+/// wgpu has no NV12 textures on metal.
+pub struct PlaneMapper {
+    cache: MetalTextureCache,
+    device: Arc<Device>,
+}
+
+unsafe impl Send for PlaneMapper {}
+unsafe impl Sync for PlaneMapper {}
+
+impl PlaneMapper {
+    pub fn new(device: Arc<Device>) -> Result<Self, TransformError> {
+        Ok(Self {
+            cache: create_texture_cache(&device)?,
+            device,
+        })
+    }
+
+    /// The textures only have the `TEXTURE_BINDING` usage, and must not be
+    /// used after the pixel buffer is released.
+    pub fn map(&self, buffer: CVPixelBufferRef) -> Result<(Texture, Texture), TransformError> {
+        Ok((
+            self.map_plane(buffer, 0, TextureFormat::R8Unorm)?,
+            self.map_plane(buffer, 1, TextureFormat::Rg8Unorm)?,
+        ))
+    }
+
+    fn map_plane(
+        &self,
+        buffer: CVPixelBufferRef,
+        plane: usize,
+        format: TextureFormat,
+    ) -> Result<Texture, TransformError> {
+        let Size { width, height } = get_pixel_buffer_plane_size(buffer, plane);
+
+        Ok(create_texture_from_metal(
+            &self.device,
+            self.cache
+                .map_plane(PixelBuffer::from(buffer), plane)?
+                .get_texture()?,
+            format,
+            Extent3d {
+                depth_or_array_layers: 1,
+                width,
+                height,
+            },
+            TextureUsages::TEXTURE_BINDING,
+        ))
+    }
+
+    pub fn flush(&self) {
+        self.cache.flush();
+    }
+}
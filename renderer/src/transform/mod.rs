@@ -1,9 +1,12 @@
 #[cfg(target_os = "windows")]
 pub mod direct3d;
 
-#[cfg(target_os = "macos")]
+#[cfg(target_vendor = "apple")]
 pub mod metal;
 
+#[cfg(target_os = "linux")]
+pub mod vulkan;
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -11,9 +14,17 @@ pub enum TransformError {
     #[cfg(target_os = "windows")]
     #[error(transparent)]
     WindowsError(#[from] common::win32::windows::core::Error),
-    #[cfg(target_os = "macos")]
+    #[cfg(target_vendor = "apple")]
     #[error(transparent)]
     MacosError(#[from] common::macos::Error),
+    #[cfg(target_os = "linux")]
+    #[error(transparent)]
+    VulkanError(#[from] ash::vk::Result),
+    #[cfg(target_os = "linux")]
+    #[error(transparent)]
+    HalDeviceError(#[from] wgpu::hal::DeviceError),
+    #[error(transparent)]
+    RequestDeviceError(#[from] wgpu::RequestDeviceError),
     #[error("not found wgpu dx12 device")]
     NotFoundDxBackend,
     #[error("dx11 shared handle is invalid")]
@@ -28,4 +39,12 @@ pub enum TransformError {
     CreateCVTextureCacheError,
     #[error("failed to create cv metal texture")]
     CreateCVMetalTextureError,
+    #[error("not found wgpu vulkan device")]
+    NotFoundVulkanBackend,
+    #[error("vulkan device does not support dma-buf import")]
+    NotSupportedDmaBufImport,
+    #[error("dma-buf format is not supported, fourcc={0:#x}")]
+    InvalidDmaBufFormat(u32),
+    #[error("dma-buf file descriptor is invalid")]
+    InvalidDmaBufFd,
 }
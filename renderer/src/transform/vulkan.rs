@@ -0,0 +1,358 @@
+use std::{ffi::CStr, sync::Arc};
+
+use super::TransformError;
+
+use ash::vk;
+use common::{
+    Size,
+    frame::VideoFormat,
+    linux::{
+        DRM_FORMAT_ABGR8888, DRM_FORMAT_ARGB8888, DRM_FORMAT_MOD_INVALID, DRM_FORMAT_NV12,
+        DRM_FORMAT_XBGR8888, DRM_FORMAT_XRGB8888, DmaBufDescriptor,
+    },
+};
+
+use smallvec::SmallVec;
+use wgpu::{
+    Adapter, Device, DeviceDescriptor, Extent3d, Queue, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages,
+    hal::{self, Api, api::Vulkan},
+};
+
+/// Device extensions required to import a dma-buf object as a vulkan image.
+const DMA_BUF_EXTENSIONS: [&CStr; 3] = [
+    ash::khr::external_memory_fd::NAME,
+    ash::ext::external_memory_dma_buf::NAME,
+    ash::ext::image_drm_format_modifier::NAME,
+];
+
+/// Dependencies of the dma-buf extensions, these have been promoted to the core
+/// in later vulkan versions, but they are still enabled if the device exposes
+/// them.
+const DMA_BUF_DEPENDENCIES: [&CStr; 5] = [
+    ash::khr::external_memory::NAME,
+    ash::khr::image_format_list::NAME,
+    ash::khr::sampler_ycbcr_conversion::NAME,
+    ash::khr::bind_memory2::NAME,
+    ash::khr::get_memory_requirements2::NAME,
+];
+
+/// Create a wgpu device with the dma-buf import extensions enabled.
+///
+/// wgpu does not enable the external memory extensions on its own, so the
+/// vulkan device is created manually here and then handed over to wgpu.
+pub fn create_device(
+    adapter: &Adapter,
+    descriptor: &DeviceDescriptor,
+) -> Result<(Device, Queue), TransformError> {
+    let device = unsafe {
+        adapter.as_hal::<Vulkan, _, _>(|adapter| {
+            let adapter = adapter.ok_or_else(|| TransformError::NotFoundVulkanBackend)?;
+            let capabilities = adapter.physical_device_capabilities();
+
+            let mut extensions = adapter.required_device_extensions(descriptor.required_features);
+            for extension in DMA_BUF_EXTENSIONS {
+                if !capabilities.supports_extension(extension) {
+                    return Err(TransformError::NotSupportedDmaBufImport);
+                }
+            }
+
+            for extension in DMA_BUF_EXTENSIONS.into_iter().chain(DMA_BUF_DEPENDENCIES) {
+                if capabilities.supports_extension(extension) && !extensions.contains(&extension) {
+                    extensions.push(extension);
+                }
+            }
+
+            let mut features =
+                adapter.physical_device_features(&extensions, descriptor.required_features);
+
+            // wgpu always uses the first queue of the first queue family.
+            let priorities = [1.0];
+            let queues = [vk::DeviceQueueCreateInfo::default()
+                .queue_family_index(0)
+                .queue_priorities(&priorities)];
+
+            let extension_names = extensions.iter().map(|it| it.as_ptr()).collect::<Vec<_>>();
+
+            let raw_device = adapter.shared_instance().raw_instance().create_device(
+                adapter.raw_physical_device(),
+                &features.add_to_device_create(
+                    vk::DeviceCreateInfo::default()
+                        .queue_create_infos(&queues)
+                        .enabled_extension_names(&extension_names),
+                ),
+                None,
+            )?;
+
+            Ok(adapter.device_from_raw(
+                raw_device,
+                None,
+                &extensions,
+                descriptor.required_features,
+                &descriptor.memory_hints,
+                0,
+                0,
+            )?)
+        })?
+    };
+
+    Ok(unsafe { adapter.create_device_from_hal(device, descriptor, None)? })
+}
+
+/// Imports the dma-buf objects exported by the hardware decoder into wgpu
+/// textures, the frames are never copied back to the system memory.
+pub struct Transformer {
+    device: Arc<Device>,
+    format: TextureFormat,
+    size: Size,
+    texture: Option<Texture>,
+}
+
+unsafe impl Send for Transformer {}
+unsafe impl Sync for Transformer {}
+
+impl Transformer {
+    pub fn new(
+        device: Arc<Device>,
+        size: Size,
+        format: VideoFormat,
+    ) -> Result<Self, TransformError> {
+        let mut is_vulkan = false;
+        unsafe {
+            device.as_hal::<Vulkan, _, _>(|device| {
+                is_vulkan = device.is_some();
+            });
+        }
+
+        if !is_vulkan {
+            return Err(TransformError::NotFoundVulkanBackend);
+        }
+
+        Ok(Self {
+            format: match format {
+                VideoFormat::NV12 => TextureFormat::NV12,
+                VideoFormat::BGRA => TextureFormat::Bgra8Unorm,
+                VideoFormat::RGBA => TextureFormat::Rgba8Unorm,
+                _ => unimplemented!("not supports format={:?}", format),
+            },
+            texture: None,
+            device,
+            size,
+        })
+    }
+
+    pub fn transform(&mut self, descriptor: &DmaBufDescriptor) -> Result<&Texture, TransformError> {
+        let (vk_format, flags) = match (self.format, descriptor.format) {
+            (TextureFormat::NV12, DRM_FORMAT_NV12) => (
+                vk::Format::G8_B8R8_2PLANE_420_UNORM,
+                // Each plane is sampled through a view with a different format.
+                vk::ImageCreateFlags::MUTABLE_FORMAT,
+            ),
+            (TextureFormat::Bgra8Unorm, DRM_FORMAT_ARGB8888 | DRM_FORMAT_XRGB8888) => {
+                (vk::Format::B8G8R8A8_UNORM, vk::ImageCreateFlags::empty())
+            }
+            (TextureFormat::Rgba8Unorm, DRM_FORMAT_ABGR8888 | DRM_FORMAT_XBGR8888) => {
+                (vk::Format::R8G8B8A8_UNORM, vk::ImageCreateFlags::empty())
+            }
+            _ => return Err(TransformError::InvalidDmaBufFormat(descriptor.format)),
+        };
+
+        // The layout of an object without an explicit modifier is private to the
+        // driver that exported it, it cannot be described to vulkan.
+        if descriptor.modifier == DRM_FORMAT_MOD_INVALID || descriptor.planes().is_empty() {
+            return Err(TransformError::InvalidDmaBufFormat(descriptor.format));
+        }
+
+        let size = Extent3d {
+            width: self.size.width,
+            height: self.size.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = unsafe {
+            self.device
+                .as_hal::<Vulkan, _, _>(|device| {
+                    let device = device.ok_or_else(|| TransformError::NotFoundVulkanBackend)?;
+                    let raw_device = device.raw_device().clone();
+                    let (image, memory) =
+                        import_dma_buf(device, descriptor, size, vk_format, flags)?;
+
+                    // The image and the memory are released by the callback when wgpu
+                    // destroys the texture, which happens after the gpu is done with it.
+                    Ok::<_, TransformError>(<Vulkan as Api>::Device::texture_from_raw(
+                        image,
+                        &hal::TextureDescriptor {
+                            label: None,
+                            size,
+                            mip_level_count: 1,
+                            sample_count: 1,
+                            dimension: TextureDimension::D2,
+                            format: self.format,
+                            usage: hal::TextureUses::RESOURCE,
+                            memory_flags: hal::MemoryFlags::empty(),
+                            view_formats: Vec::new(),
+                        },
+                        Some(Box::new(move || {
+                            raw_device.destroy_image(image, None);
+                            raw_device.free_memory(memory, None);
+                        })),
+                    ))
+                })
+                .ok_or_else(|| TransformError::NotFoundVulkanBackend)??
+        };
+
+        Ok(self.texture.insert(unsafe {
+            self.device.create_texture_from_hal::<Vulkan>(
+                texture,
+                &TextureDescriptor {
+                    label: None,
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: self.format,
+                    usage: TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+            )
+        }))
+    }
+}
+
+/// Create a vulkan image on top of the dma-buf object, the image uses the
+/// layout described by the exporter through the drm format modifier.
+unsafe fn import_dma_buf(
+    device: &<Vulkan as Api>::Device,
+    descriptor: &DmaBufDescriptor,
+    size: Extent3d,
+    format: vk::Format,
+    flags: vk::ImageCreateFlags,
+) -> Result<(vk::Image, vk::DeviceMemory), TransformError> {
+    let raw_device = device.raw_device();
+
+    let layouts = descriptor
+        .planes()
+        .iter()
+        .map(|it| vk::SubresourceLayout {
+            offset: it.offset as u64,
+            size: 0,
+            row_pitch: it.pitch as u64,
+            array_pitch: 0,
+            depth_pitch: 0,
+        })
+        .collect::<SmallVec<[_; 4]>>();
+
+    let mut modifier_info = vk::ImageDrmFormatModifierExplicitCreateInfoEXT::default()
+        .drm_format_modifier(descriptor.modifier)
+        .plane_layouts(&layouts);
+
+    let mut external_info = vk::ExternalMemoryImageCreateInfo::default()
+        .handle_types(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT);
+
+    let image = unsafe {
+        raw_device.create_image(
+            &vk::ImageCreateInfo::default()
+                .flags(flags)
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(format)
+                .extent(vk::Extent3D {
+                    width: size.width,
+                    height: size.height,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT)
+                .usage(vk::ImageUsageFlags::SAMPLED)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .push_next(&mut external_info)
+                .push_next(&mut modifier_info),
+            None,
+        )?
+    };
+
+    match unsafe { import_memory(device, descriptor, image) } {
+        Ok(memory) => Ok((image, memory)),
+        Err(e) => {
+            unsafe {
+                raw_device.destroy_image(image, None);
+            }
+
+            Err(e)
+        }
+    }
+}
+
+unsafe fn import_memory(
+    device: &<Vulkan as Api>::Device,
+    descriptor: &DmaBufDescriptor,
+    image: vk::Image,
+) -> Result<vk::DeviceMemory, TransformError> {
+    let raw_device = device.raw_device();
+    let external_memory_fd = ash::khr::external_memory_fd::Device::new(
+        device.shared_instance().raw_instance(),
+        raw_device,
+    );
+
+    let requirements = unsafe { raw_device.get_image_memory_requirements(image) };
+
+    let mut properties = vk::MemoryFdPropertiesKHR::default();
+    unsafe {
+        external_memory_fd.get_memory_fd_properties(
+            vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
+            descriptor.fd,
+            &mut properties,
+        )?;
+    }
+
+    let memory_type_bits = requirements.memory_type_bits & properties.memory_type_bits;
+    if memory_type_bits == 0 {
+        return Err(TransformError::NotSupportedDmaBufImport);
+    }
+
+    // A successful import transfers the ownership of the file descriptor to the
+    // vulkan driver, and the descriptor is only borrowed, so a duplicate is
+    // imported instead.
+    let fd = unsafe { libc::dup(descriptor.fd) };
+    if fd < 0 {
+        return Err(TransformError::InvalidDmaBufFd);
+    }
+
+    let mut import_info = vk::ImportMemoryFdInfoKHR::default()
+        .handle_type(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT)
+        .fd(fd);
+
+    let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::default().image(image);
+
+    let memory = match unsafe {
+        raw_device.allocate_memory(
+            &vk::MemoryAllocateInfo::default()
+                .allocation_size(requirements.size)
+                .memory_type_index(memory_type_bits.trailing_zeros())
+                .push_next(&mut import_info)
+                .push_next(&mut dedicated_info),
+            None,
+        )
+    } {
+        Ok(it) => it,
+        Err(e) => {
+            unsafe {
+                libc::close(fd);
+            }
+
+            return Err(e.into());
+        }
+    };
+
+    if let Err(e) = unsafe { raw_device.bind_image_memory(image, memory, 0) } {
+        unsafe {
+            raw_device.free_memory(memory, None);
+        }
+
+        return Err(e.into());
+    }
+
+    Ok(memory)
+}
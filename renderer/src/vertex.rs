@@ -1,5 +1,9 @@
 use bytemuck::{Pod, Zeroable};
-use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode};
+use wgpu::{
+    Buffer, BufferAddress, BufferUsages, Device, VertexAttribute, VertexBufferLayout, VertexFormat,
+    VertexStepMode,
+    util::{BufferInitDescriptor, DeviceExt},
+};
 
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
@@ -38,6 +42,34 @@ impl Vertex {
         }
     }
 
+    /// The quad that shows only a part of the texture, the part is left, top,
+    /// right and bottom in texture coordinates, the origin is the top left.
+    pub fn cropped([left, top, right, bottom]: [f32; 4]) -> [Self; 4] {
+        [
+            Vertex::new([-1.0, -1.0], [left, 1.0 - bottom]),
+            Vertex::new([1.0, -1.0], [right, 1.0 - bottom]),
+            Vertex::new([-1.0, 1.0], [left, 1.0 - top]),
+            Vertex::new([1.0, 1.0], [right, 1.0 - top]),
+        ]
+    }
+
+    /// Create the vertex buffer and the index buffer of the quad.
+    pub fn create_buffers(device: &Device) -> (Buffer, Buffer) {
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(Self::VERTICES),
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        });
+
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(Self::INDICES),
+            usage: BufferUsages::INDEX,
+        });
+
+        (vertex_buffer, index_buffer)
+    }
+
     pub fn desc<'a>() -> VertexBufferLayout<'a> {
         VertexBufferLayout {
             array_stride: std::mem::size_of::<Self>() as BufferAddress,
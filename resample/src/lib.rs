@@ -1,14 +1,20 @@
 use std::{
     marker::PhantomData,
-    ptr::null_mut,
+    mem::zeroed,
+    ptr::{null, null_mut},
     sync::{
         Arc,
         atomic::{AtomicBool, Ordering},
-        mpsc::{Sender, channel},
+        mpsc::{Receiver, Sender, channel},
     },
     thread,
 };
 
+use common::{
+    Size,
+    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    strings::PSTR,
+};
 use ffmpeg::*;
 use thiserror::Error;
 
@@ -37,22 +43,56 @@ pub struct AudioSampleDescription {
 }
 
 impl AudioSampleDescription {
+    /// The default layout of ffmpeg for the channel count, such as 5.1 for 6
+    /// channels and 7.1 for 8 channels. When the output has less channels than
+    /// the input, the input is downmixed.
     fn channel_layout(&self) -> AVChannelLayout {
-        AVChannelLayout {
-            order: AVChannelOrder::AV_CHANNEL_ORDER_NATIVE,
-            nb_channels: self.channels as i32,
-            u: AVChannelLayout__bindgen_ty_1 {
-                mask: match self.channels {
-                    1 => AV_CH_LAYOUT_MONO,
-                    2 => AV_CH_LAYOUT_STEREO,
-                    _ => unimplemented!("unsupports audio channels={}", self.channels),
-                },
-            },
-            opaque: null_mut(),
+        unsafe {
+            let mut layout = zeroed::<AVChannelLayout>();
+            av_channel_layout_default(&mut layout, self.channels.max(1) as i32);
+
+            layout
         }
     }
 }
 
+/// The resampling engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioResamplerEngine {
+    /// The builtin engine of swresample.
+    Swr,
+    /// [SoX Resampler](https://sourceforge.net/projects/soxr/), better quality
+    /// at the same speed, requires ffmpeg to be built with libsoxr.
+    Soxr,
+}
+
+impl ToString for AudioResamplerEngine {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Swr => "swr",
+            Self::Soxr => "soxr",
+        }
+        .to_string()
+    }
+}
+
+/// Quality options of the resampler, the defaults of swresample are used for
+/// the options that are not set.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AudioResamplerQuality {
+    pub engine: Option<AudioResamplerEngine>,
+    /// The length of the filter, a longer filter has a better quality and is
+    /// slower, only used by the swr engine.
+    pub filter_size: Option<u32>,
+    /// Log2 of the number of the filter phases, in the range 0 to 30, more
+    /// phases are more accurate for the ratios between the sample rates, only
+    /// used by the swr engine.
+    pub phase_shift: Option<u32>,
+    /// The cutoff frequency relative to the nyquist frequency, in the range 0.0
+    /// to 1.0.
+    pub cutoff: Option<f64>,
+}
+
 pub trait AudioResamplerOutput<T>: Send {
     fn output(&mut self, buffer: &[T], frames: u32) -> bool;
 }
@@ -65,15 +105,28 @@ pub enum AudioResamplerError {
     CreateSwresampleError,
     #[error("queue is closed")]
     QueueClosed,
+    #[error("failed to resample the audio")]
+    ResampleError,
+    #[error("failed to set the compensation")]
+    SetCompensationError,
 }
 
 /// Audio resampler, quickly resample input to a single channel count and
 /// different sampling rates.
 ///
-/// Note that due to the fast sampling, the quality may be reduced.
+/// The resampling runs on a separate thread, the input is copied into a
+/// buffer taken from a pool, and the buffer is returned to the pool after it
+/// is resampled, so no allocation happens once the pool is warmed up. Use
+/// `SyncAudioResampler` if the caller already runs on a dedicated audio
+/// thread.
+///
+/// Note that due to the fast sampling, the quality may be reduced, see
+/// [AudioResamplerQuality].
 pub struct AudioResampler<I, O> {
     _p: PhantomData<O>,
     tx: Sender<Vec<I>>,
+    compensation: Sender<(i32, i32)>,
+    pool: Receiver<Vec<I>>,
     status: Arc<AtomicBool>,
 }
 
@@ -85,28 +138,47 @@ where
     pub fn new<T: AudioResamplerOutput<O> + 'static>(
         input: AudioSampleDescription,
         output: AudioSampleDescription,
+        sink: T,
+    ) -> Result<Self, AudioResamplerError> {
+        Self::with_quality(input, output, AudioResamplerQuality::default(), sink)
+    }
+
+    pub fn with_quality<T: AudioResamplerOutput<O> + 'static>(
+        input: AudioSampleDescription,
+        output: AudioSampleDescription,
+        quality: AudioResamplerQuality,
         mut sink: T,
     ) -> Result<Self, AudioResamplerError> {
         let (tx, rx) = channel::<Vec<I>>();
+        let (pool_tx, pool) = channel::<Vec<I>>();
+        let (compensation, compensation_rx) = channel::<(i32, i32)>();
 
         let status = Arc::new(AtomicBool::new(true));
-        let mut swresample = Swresample::new(&input, &output)
-            .ok_or_else(|| AudioResamplerError::CreateSwresampleError)?;
+        let mut resampler = SyncAudioResampler::<I, O>::with_quality(input, output, quality)?;
 
         let status_ = status.clone();
         thread::spawn(move || {
-            let mut output: Vec<O> =
-                vec![O::default(); output.sample_rate as usize * output.channels as usize];
+            let mut output: Vec<O> = Vec::new();
 
             while let Ok(buffer) = rx.recv() {
-                let frames = buffer.len() / input.channels as usize;
-                if swresample.convert(&buffer, &mut output, frames as i32) {
-                    if !sink.output(&output, frames as u32) {
+                // Only the latest compensation matters.
+                if let Some((delta, distance)) = compensation_rx.try_iter().last() {
+                    if resampler.set_compensation(delta, distance).is_err() {
                         break;
                     }
-                } else {
-                    break;
                 }
+
+                match resampler.resample_into(&buffer, &mut output) {
+                    Ok(frames) => {
+                        if !sink.output(&output, frames) {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+
+                // The resampler may be dropped, the buffer is simply freed then.
+                let _ = pool_tx.send(buffer);
             }
 
             status_.store(false, Ordering::Relaxed);
@@ -114,30 +186,138 @@ where
 
         Ok(Self {
             _p: PhantomData::default(),
+            compensation,
             status,
+            pool,
             tx,
         })
     }
 
+    /// See [SyncAudioResampler::set_compensation], the compensation is applied
+    /// before the next resampled buffer.
+    pub fn set_compensation(
+        &self,
+        sample_delta: i32,
+        compensation_distance: i32,
+    ) -> Result<(), AudioResamplerError> {
+        if !self.status.load(Ordering::Relaxed) {
+            return Err(AudioResamplerError::QueueClosed);
+        }
+
+        self.compensation
+            .send((sample_delta, compensation_distance))
+            .map_err(|_| AudioResamplerError::SendBufferError)
+    }
+
     pub fn resample<'a>(&'a mut self, buffer: &'a [I]) -> Result<(), AudioResamplerError> {
         if !self.status.load(Ordering::Relaxed) {
             return Err(AudioResamplerError::QueueClosed);
         }
 
+        let mut pooled = self.pool.try_recv().unwrap_or_default();
+        pooled.clear();
+        pooled.extend_from_slice(buffer);
+
         self.tx
-            .send(buffer.to_vec())
+            .send(pooled)
             .map_err(|_| AudioResamplerError::SendBufferError)?;
         Ok(())
     }
 }
 
+/// The synchronous version of `AudioResampler`, the input is resampled on the
+/// calling thread without any copy or thread hop, which is suitable for the
+/// callers that already run on a dedicated audio thread.
+pub struct SyncAudioResampler<I, O> {
+    _p: PhantomData<fn(&[I]) -> O>,
+    swresample: Swresample,
+    input: AudioSampleDescription,
+    output: AudioSampleDescription,
+}
+
+impl<I, O> SyncAudioResampler<I, O>
+where
+    I: Copy,
+    O: Copy + Default,
+{
+    pub fn new(
+        input: AudioSampleDescription,
+        output: AudioSampleDescription,
+    ) -> Result<Self, AudioResamplerError> {
+        Self::with_quality(input, output, AudioResamplerQuality::default())
+    }
+
+    pub fn with_quality(
+        input: AudioSampleDescription,
+        output: AudioSampleDescription,
+        quality: AudioResamplerQuality,
+    ) -> Result<Self, AudioResamplerError> {
+        Ok(Self {
+            swresample: Swresample::new(&input, &output, &quality)
+                .ok_or_else(|| AudioResamplerError::CreateSwresampleError)?,
+            _p: PhantomData::default(),
+            output,
+            input,
+        })
+    }
+
+    /// Resample the interleaved input into the output buffer, the output is
+    /// resized to hold exactly the resampled samples, returns the number of
+    /// the resampled frames.
+    ///
+    /// The output buffer only grows, so reusing the same buffer does not
+    /// allocate after the first calls.
+    pub fn resample_into(
+        &mut self,
+        buffer: &[I],
+        output: &mut Vec<O>,
+    ) -> Result<u32, AudioResamplerError> {
+        let frames = buffer.len() / self.input.channels as usize;
+        let capacity = self.swresample.get_out_samples(frames as i32);
+
+        output.resize(capacity * self.output.channels as usize, O::default());
+
+        let frames = self
+            .swresample
+            .convert(buffer, frames as i32, output, capacity as i32)
+            .ok_or_else(|| AudioResamplerError::ResampleError)?;
+
+        output.truncate(frames as usize * self.output.channels as usize);
+        Ok(frames)
+    }
+
+    /// Stretch or squeeze the output by `sample_delta` frames over the next
+    /// `compensation_distance` output frames, for example to slew the audio
+    /// to the video clock without an audible jump. A positive delta adds
+    /// frames, which slows the audio down, and a zero delta stops the
+    /// compensation.
+    pub fn set_compensation(
+        &mut self,
+        sample_delta: i32,
+        compensation_distance: i32,
+    ) -> Result<(), AudioResamplerError> {
+        if self
+            .swresample
+            .set_compensation(sample_delta, compensation_distance)
+        {
+            Ok(())
+        } else {
+            Err(AudioResamplerError::SetCompensationError)
+        }
+    }
+}
+
 struct Swresample(*mut SwrContext);
 
 unsafe impl Send for Swresample {}
 unsafe impl Sync for Swresample {}
 
 impl Swresample {
-    fn new(input: &AudioSampleDescription, output: &AudioSampleDescription) -> Option<Self> {
+    fn new(
+        input: &AudioSampleDescription,
+        output: &AudioSampleDescription,
+        quality: &AudioResamplerQuality,
+    ) -> Option<Self> {
         let mut ctx = null_mut();
         if unsafe {
             swr_alloc_set_opts2(
@@ -147,7 +327,7 @@ impl Swresample {
                 output.sample_rate as i32,
                 &input.channel_layout(),
                 input.sample_bits.into(),
-                output.sample_rate as i32,
+                input.sample_rate as i32,
                 0,
                 null_mut(),
             )
@@ -156,22 +336,81 @@ impl Swresample {
             return None;
         }
 
-        if unsafe { swr_init(ctx) } != 0 {
+        // The context is freed by the drop if the initialization fails.
+        let this = Self(ctx);
+        if let Some(it) = quality.engine {
+            this.set_str_option("resampler", &it.to_string());
+        }
+
+        if let Some(it) = quality.filter_size {
+            this.set_option("filter_size", it as i64);
+        }
+
+        if let Some(it) = quality.phase_shift {
+            this.set_option("phase_shift", it as i64);
+        }
+
+        if let Some(it) = quality.cutoff {
+            unsafe {
+                av_opt_set_double(this.0 as _, PSTR::from("cutoff").as_ptr(), it, 0);
+            }
+        }
+
+        if unsafe { swr_init(this.0) } != 0 {
             return None;
         }
 
-        Some(Self(ctx))
+        Some(this)
     }
 
-    fn convert<I, O>(&mut self, input: &[I], output: &mut [O], frames: i32) -> bool {
+    fn set_option(&self, key: &str, value: i64) {
         unsafe {
+            av_opt_set_int(self.0 as _, PSTR::from(key).as_ptr(), value, 0);
+        }
+    }
+
+    fn set_str_option(&self, key: &str, value: &str) {
+        unsafe {
+            av_opt_set(
+                self.0 as _,
+                PSTR::from(key).as_ptr(),
+                PSTR::from(value).as_ptr(),
+                0,
+            );
+        }
+    }
+
+    fn set_compensation(&mut self, sample_delta: i32, compensation_distance: i32) -> bool {
+        unsafe { swr_set_compensation(self.0, sample_delta, compensation_distance) >= 0 }
+    }
+
+    /// The upper bound of the number of the output frames for the number of
+    /// the input frames, including the frames buffered in the context.
+    fn get_out_samples(&self, frames: i32) -> usize {
+        unsafe { swr_get_out_samples(self.0, frames) }.max(0) as usize
+    }
+
+    fn convert<I, O>(
+        &mut self,
+        input: &[I],
+        input_frames: i32,
+        output: &mut [O],
+        output_frames: i32,
+    ) -> Option<u32> {
+        let frames = unsafe {
             swr_convert(
                 self.0,
                 [output.as_mut_ptr() as _].as_ptr(),
-                frames,
+                output_frames,
                 [input.as_ptr() as _].as_ptr(),
-                frames,
-            ) >= 0
+                input_frames,
+            )
+        };
+
+        if frames >= 0 {
+            Some(frames as u32)
+        } else {
+            None
         }
     }
 }
@@ -184,6 +423,146 @@ impl Drop for Swresample {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum VideoScalerError {
+    #[error("unsupported video format")]
+    UnsupportedFormat,
+    #[error("failed to create swscale")]
+    CreateSwscaleError,
+    #[error("the frame does not match the input of the scaler")]
+    InvalidInputFrame,
+    #[error("failed to scale video frame")]
+    ScaleError,
+}
+
+/// Scales the video frames in the system memory, such as the lower
+/// renditions of a simulcast video, the output is fixed to NV12.
+pub struct VideoScaler {
+    context: *mut SwsContext,
+    format: VideoFormat,
+    input: Size,
+    #[allow(unused)]
+    buffer: Vec<u8>,
+    frame: VideoFrame,
+}
+
+unsafe impl Send for VideoScaler {}
+unsafe impl Sync for VideoScaler {}
+
+impl VideoScaler {
+    pub fn new(format: VideoFormat, input: Size, output: Size) -> Result<Self, VideoScalerError> {
+        let context = unsafe {
+            sws_getContext(
+                input.width as i32,
+                input.height as i32,
+                match format {
+                    VideoFormat::BGRA => AVPixelFormat::AV_PIX_FMT_BGRA,
+                    VideoFormat::RGBA => AVPixelFormat::AV_PIX_FMT_RGBA,
+                    VideoFormat::NV12 => AVPixelFormat::AV_PIX_FMT_NV12,
+                    VideoFormat::I420 => AVPixelFormat::AV_PIX_FMT_YUV420P,
+                },
+                output.width as i32,
+                output.height as i32,
+                AVPixelFormat::AV_PIX_FMT_NV12,
+                SWS_BILINEAR as i32,
+                null_mut(),
+                null_mut(),
+                null(),
+            )
+        };
+
+        if context.is_null() {
+            return Err(VideoScalerError::CreateSwscaleError);
+        }
+
+        let luma = output.width as usize * output.height as usize;
+        let mut buffer = vec![0u8; luma + luma / 2];
+
+        Ok(Self {
+            frame: VideoFrame {
+                format: VideoFormat::NV12,
+                sub_format: VideoSubFormat::SW,
+                width: output.width,
+                height: output.height,
+                data: [
+                    buffer.as_mut_ptr() as *const _,
+                    buffer[luma..].as_mut_ptr() as *const _,
+                    null(),
+                ],
+                linesize: [output.width, output.width, 0],
+                timestamp: 0,
+            },
+            context,
+            format,
+            input,
+            buffer,
+        })
+    }
+
+    /// Scales a frame in the system memory with the format and the size of
+    /// the input, the output keeps the timestamp of the frame.
+    pub fn process(&mut self, frame: &VideoFrame) -> Result<&VideoFrame, VideoScalerError> {
+        if frame.sub_format != VideoSubFormat::SW
+            || frame.format != self.format
+            || frame.width != self.input.width
+            || frame.height != self.input.height
+        {
+            return Err(VideoScalerError::InvalidInputFrame);
+        }
+
+        let mut data = [null(); 4];
+        let mut linesize = [0i32; 4];
+        for i in 0..3 {
+            data[i] = frame.data[i] as *const u8;
+            linesize[i] = frame.linesize[i] as i32;
+        }
+
+        if unsafe {
+            sws_scale(
+                self.context,
+                data.as_ptr(),
+                linesize.as_ptr(),
+                0,
+                self.input.height as i32,
+                [
+                    self.frame.data[0] as *mut u8,
+                    self.frame.data[1] as *mut u8,
+                    null_mut(),
+                    null_mut(),
+                ]
+                .as_ptr(),
+                [
+                    self.frame.linesize[0] as i32,
+                    self.frame.linesize[1] as i32,
+                    0,
+                    0,
+                ]
+                .as_ptr(),
+            )
+        } <= 0
+        {
+            return Err(VideoScalerError::ScaleError);
+        }
+
+        self.frame.timestamp = frame.timestamp;
+
+        Ok(&self.frame)
+    }
+
+    /// The output of the last processed frame.
+    pub fn frame(&self) -> &VideoFrame {
+        &self.frame
+    }
+}
+
+impl Drop for VideoScaler {
+    fn drop(&mut self) {
+        unsafe {
+            sws_freeContext(self.context);
+        }
+    }
+}
+
 #[cfg(target_os = "windows")]
 pub mod win32 {
     use std::mem::ManuallyDrop;
@@ -199,22 +578,25 @@ pub mod win32 {
                     Graphics::{
                         Direct3D11::{
                             D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE,
-                            D3D11_CPU_ACCESS_READ, D3D11_CPU_ACCESS_WRITE, D3D11_MAP_READ,
-                            D3D11_MAP_WRITE_DISCARD, D3D11_MAPPED_SUBRESOURCE,
-                            D3D11_RESOURCE_MISC_SHARED, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
-                            D3D11_USAGE_DYNAMIC, D3D11_USAGE_STAGING,
+                            D3D11_CPU_ACCESS_READ, D3D11_CPU_ACCESS_WRITE, D3D11_MAP_WRITE_DISCARD,
+                            D3D11_MAPPED_SUBRESOURCE, D3D11_RENDER_TARGET_VIEW_DESC,
+                            D3D11_RESOURCE_MISC_SHARED, D3D11_RTV_DIMENSION_TEXTURE2D,
+                            D3D11_RTV_DIMENSION_TEXTURE2DARRAY, D3D11_TEXTURE2D_DESC,
+                            D3D11_USAGE_DEFAULT, D3D11_USAGE_DYNAMIC, D3D11_USAGE_STAGING,
                             D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
                             D3D11_VIDEO_PROCESSOR_COLOR_SPACE, D3D11_VIDEO_PROCESSOR_CONTENT_DESC,
                             D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC,
                             D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC, D3D11_VIDEO_PROCESSOR_STREAM,
                             D3D11_VIDEO_USAGE_PLAYBACK_NORMAL, D3D11_VPIV_DIMENSION_TEXTURE2D,
-                            D3D11_VPOV_DIMENSION_TEXTURE2D, ID3D11Device, ID3D11DeviceContext,
+                            D3D11_VPOV_DIMENSION_TEXTURE2D, D3D11_VPOV_DIMENSION_TEXTURE2DARRAY,
+                            ID3D11Device, ID3D11DeviceContext, ID3D11DeviceContext1,
                             ID3D11Texture2D, ID3D11VideoContext, ID3D11VideoDevice,
                             ID3D11VideoProcessor, ID3D11VideoProcessorEnumerator,
                             ID3D11VideoProcessorInputView, ID3D11VideoProcessorOutputView,
                         },
                         Dxgi::Common::{
                             DXGI_FORMAT, DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_NV12,
+                            DXGI_FORMAT_R8_UNORM, DXGI_FORMAT_R8G8_UNORM,
                             DXGI_FORMAT_R8G8B8A8_UNORM,
                         },
                     },
@@ -224,6 +606,8 @@ pub mod win32 {
         },
     };
 
+    pub use common::win32::TextureBuffer;
+
     #[derive(Clone)]
     pub enum Resource {
         Default(VideoFormat, Size),
@@ -659,49 +1043,332 @@ pub mod win32 {
         }
     }
 
-    pub struct TextureBuffer<'a> {
-        d3d_context: &'a ID3D11DeviceContext,
-        texture: &'a ID3D11Texture2D,
-        resource: D3D11_MAPPED_SUBRESOURCE,
+    /// How [VideoMask] covers the regions.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum VideoMaskStyle {
+        /// The regions are filled with black.
+        Blank,
+        /// The regions are scaled down by the factor and back up, which
+        /// leaves only a coarse picture of them.
+        Blur(u32),
     }
 
-    unsafe impl Send for TextureBuffer<'_> {}
-    unsafe impl Sync for TextureBuffer<'_> {}
+    /// The scaled down copy of the frames for the blur, one pixel of it
+    /// covers a block of the frame.
+    struct BlurTarget {
+        key: (u32, u32, u32),
+        video_processor: ID3D11VideoProcessor,
+        input_view: ID3D11VideoProcessorInputView,
+        output_view: ID3D11VideoProcessorOutputView,
+        video_enumerator: ID3D11VideoProcessorEnumerator,
+    }
 
-    impl<'a> TextureBuffer<'a> {
-        pub fn new(
-            d3d_context: &'a ID3D11DeviceContext,
-            texture: &'a ID3D11Texture2D,
-        ) -> Result<Self, Error> {
-            let mut resource = D3D11_MAPPED_SUBRESOURCE::default();
-            unsafe {
-                d3d_context.Map(texture, 0, D3D11_MAP_READ, 0, Some(&mut resource))?;
-            }
+    /// Covers the regions of the NV12 textures in place, the work stays on
+    /// the gpu and the rest of the texture is not touched. The textures must
+    /// be bindable as render targets.
+    pub struct VideoMask {
+        d3d_device: ID3D11Device,
+        d3d_context: ID3D11DeviceContext1,
+        video_device: ID3D11VideoDevice,
+        video_context: ID3D11VideoContext,
+        blur: Option<BlurTarget>,
+    }
 
+    unsafe impl Send for VideoMask {}
+    unsafe impl Sync for VideoMask {}
+
+    impl VideoMask {
+        pub fn new(direct3d: Direct3DDevice) -> Result<Self, Error> {
             Ok(Self {
-                d3d_context,
-                resource,
-                texture,
+                video_device: direct3d.device.cast()?,
+                video_context: direct3d.context.cast()?,
+                d3d_context: direct3d.context.cast()?,
+                d3d_device: direct3d.device,
+                blur: None,
             })
         }
 
-        /// Represents a pointer to texture data. Internally, the texture is
-        /// copied to the CPU first, and then the internal data is
-        /// mapped.
-        pub fn buffer(&self) -> *const u8 {
-            self.resource.pData as *const _
+        /// The rects are in pixels of the texture and must be aligned to even
+        /// pixels, the index is the slice of a texture array.
+        pub fn mask(
+            &mut self,
+            texture: &ID3D11Texture2D,
+            index: u32,
+            rects: &[RECT],
+            style: VideoMaskStyle,
+        ) -> Result<(), Error> {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe {
+                texture.GetDesc(&mut desc);
+            }
+
+            match style {
+                VideoMaskStyle::Blank => self.clear(texture, &desc, index, rects),
+                VideoMaskStyle::Blur(block) => self.blur(texture, &desc, index, rects, block),
+            }
+        }
+
+        /// The planes are cleared through views of their own format, black is
+        /// 16 in the luma and 128 in both chroma channels, and the chroma
+        /// plane has half of the size.
+        fn clear(
+            &self,
+            texture: &ID3D11Texture2D,
+            desc: &D3D11_TEXTURE2D_DESC,
+            index: u32,
+            rects: &[RECT],
+        ) -> Result<(), Error> {
+            let chroma_rects = rects
+                .iter()
+                .map(|it| RECT {
+                    left: it.left / 2,
+                    top: it.top / 2,
+                    right: it.right / 2,
+                    bottom: it.bottom / 2,
+                })
+                .collect::<Vec<_>>();
+
+            for (format, color, rects) in [
+                (DXGI_FORMAT_R8_UNORM, [16.0 / 255.0, 0.0, 0.0, 0.0], rects),
+                (
+                    DXGI_FORMAT_R8G8_UNORM,
+                    [128.0 / 255.0, 128.0 / 255.0, 0.0, 0.0],
+                    &chroma_rects[..],
+                ),
+            ] {
+                let view = unsafe {
+                    let mut view_desc = D3D11_RENDER_TARGET_VIEW_DESC::default();
+                    view_desc.Format = format;
+
+                    if desc.ArraySize > 1 {
+                        view_desc.ViewDimension = D3D11_RTV_DIMENSION_TEXTURE2DARRAY;
+                        view_desc.Anonymous.Texture2DArray.FirstArraySlice = index;
+                        view_desc.Anonymous.Texture2DArray.ArraySize = 1;
+                    } else {
+                        view_desc.ViewDimension = D3D11_RTV_DIMENSION_TEXTURE2D;
+                    }
+
+                    let mut view = None;
+                    self.d3d_device.CreateRenderTargetView(
+                        texture,
+                        Some(&view_desc),
+                        Some(&mut view),
+                    )?;
+
+                    view.unwrap()
+                };
+
+                unsafe {
+                    self.d3d_context.ClearView(&view, &color, Some(rects));
+                }
+            }
+
+            Ok(())
         }
 
-        /// The stride of the texture data
-        pub fn stride(&self) -> u32 {
-            self.resource.RowPitch
+        /// Each rect is scaled down into the blur target and scaled back up
+        /// into the rect, the filtering of the video processor smooths the
+        /// blocks.
+        fn blur(
+            &mut self,
+            texture: &ID3D11Texture2D,
+            desc: &D3D11_TEXTURE2D_DESC,
+            index: u32,
+            rects: &[RECT],
+            block: u32,
+        ) -> Result<(), Error> {
+            let block = block.max(1);
+            let key = (desc.Width, desc.Height, block);
+            if self.blur.as_ref().map(|it| it.key) != Some(key) {
+                self.blur = Some(self.create_blur_target(key)?);
+            }
+
+            let Some(target) = &self.blur else {
+                return Ok(());
+            };
+
+            let (frame_input, frame_output) = unsafe {
+                let mut input_desc = D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC::default();
+                input_desc.ViewDimension = D3D11_VPIV_DIMENSION_TEXTURE2D;
+                input_desc.Anonymous.Texture2D.ArraySlice = index;
+
+                let mut input = None;
+                self.video_device.CreateVideoProcessorInputView(
+                    texture,
+                    &target.video_enumerator,
+                    &input_desc,
+                    Some(&mut input),
+                )?;
+
+                let mut output_desc = D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC::default();
+                if desc.ArraySize > 1 {
+                    output_desc.ViewDimension = D3D11_VPOV_DIMENSION_TEXTURE2DARRAY;
+                    output_desc.Anonymous.Texture2DArray.FirstArraySlice = index;
+                    output_desc.Anonymous.Texture2DArray.ArraySize = 1;
+                } else {
+                    output_desc.ViewDimension = D3D11_VPOV_DIMENSION_TEXTURE2D;
+                }
+
+                let mut output = None;
+                self.video_device.CreateVideoProcessorOutputView(
+                    texture,
+                    &target.video_enumerator,
+                    &output_desc,
+                    Some(&mut output),
+                )?;
+
+                (input.unwrap(), output.unwrap())
+            };
+
+            for rect in rects {
+                let small = RECT {
+                    left: 0,
+                    top: 0,
+                    right: ((rect.right - rect.left) as u32).div_ceil(block).max(1) as i32,
+                    bottom: ((rect.bottom - rect.top) as u32).div_ceil(block).max(1) as i32,
+                };
+
+                self.blt(target, &frame_input, &target.output_view, rect, &small)?;
+                self.blt(target, &target.input_view, &frame_output, &small, rect)?;
+            }
+
+            Ok(())
         }
-    }
 
-    impl Drop for TextureBuffer<'_> {
-        fn drop(&mut self) {
+        /// The target is large enough for the rects that cover the whole
+        /// frame.
+        fn create_blur_target(&self, key: (u32, u32, u32)) -> Result<BlurTarget, Error> {
+            let (width, height, block) = key;
+            let size = (
+                (width.div_ceil(block) + 1) & !1,
+                (height.div_ceil(block) + 1) & !1,
+            );
+
+            let texture = unsafe {
+                let mut desc = D3D11_TEXTURE2D_DESC::default();
+                desc.Width = size.0;
+                desc.Height = size.1;
+                desc.MipLevels = 1;
+                desc.ArraySize = 1;
+                desc.SampleDesc.Count = 1;
+                desc.SampleDesc.Quality = 0;
+                desc.Usage = D3D11_USAGE_DEFAULT;
+                desc.BindFlags = D3D11_BIND_RENDER_TARGET.0 as u32;
+                desc.CPUAccessFlags = 0;
+                desc.MiscFlags = 0;
+                desc.Format = DXGI_FORMAT_NV12;
+
+                let mut texture = None;
+                self.d3d_device
+                    .CreateTexture2D(&desc, None, Some(&mut texture))?;
+                texture.unwrap()
+            };
+
+            let (video_enumerator, video_processor) = unsafe {
+                let mut desc = D3D11_VIDEO_PROCESSOR_CONTENT_DESC::default();
+                desc.InputFrameFormat = D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE;
+                desc.InputWidth = width;
+                desc.InputHeight = height;
+                desc.OutputWidth = size.0;
+                desc.OutputHeight = size.1;
+                desc.Usage = D3D11_VIDEO_USAGE_PLAYBACK_NORMAL;
+
+                let enumerator = self.video_device.CreateVideoProcessorEnumerator(&desc)?;
+                let processor = self.video_device.CreateVideoProcessor(&enumerator, 0)?;
+                (enumerator, processor)
+            };
+
+            let input_view = unsafe {
+                let mut desc = D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC::default();
+                desc.ViewDimension = D3D11_VPIV_DIMENSION_TEXTURE2D;
+
+                let mut view = None;
+                self.video_device.CreateVideoProcessorInputView(
+                    &texture,
+                    &video_enumerator,
+                    &desc,
+                    Some(&mut view),
+                )?;
+
+                view.unwrap()
+            };
+
+            let output_view = unsafe {
+                let mut desc = D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC::default();
+                desc.ViewDimension = D3D11_VPOV_DIMENSION_TEXTURE2D;
+
+                let mut view = None;
+                self.video_device.CreateVideoProcessorOutputView(
+                    &texture,
+                    &video_enumerator,
+                    &desc,
+                    Some(&mut view),
+                )?;
+
+                view.unwrap()
+            };
+
+            unsafe {
+                let color_space = D3D11_VIDEO_PROCESSOR_COLOR_SPACE::default();
+                self.video_context.VideoProcessorSetStreamColorSpace(
+                    &video_processor,
+                    0,
+                    &color_space,
+                );
+                self.video_context
+                    .VideoProcessorSetOutputColorSpace(&video_processor, &color_space);
+            }
+
+            Ok(BlurTarget {
+                key,
+                video_processor,
+                video_enumerator,
+                input_view,
+                output_view,
+            })
+        }
+
+        /// Only the destination rect of the output is written, the output
+        /// target rect keeps the processor from filling the rest of the
+        /// output with the background color.
+        fn blt(
+            &self,
+            target: &BlurTarget,
+            input: &ID3D11VideoProcessorInputView,
+            output: &ID3D11VideoProcessorOutputView,
+            source_rect: &RECT,
+            dest_rect: &RECT,
+        ) -> Result<(), Error> {
             unsafe {
-                self.d3d_context.Unmap(self.texture, 0);
+                let processor = &target.video_processor;
+                self.video_context.VideoProcessorSetStreamSourceRect(
+                    processor,
+                    0,
+                    true,
+                    Some(source_rect),
+                );
+                self.video_context.VideoProcessorSetStreamDestRect(
+                    processor,
+                    0,
+                    true,
+                    Some(dest_rect),
+                );
+                self.video_context.VideoProcessorSetOutputTargetRect(
+                    processor,
+                    true,
+                    Some(dest_rect),
+                );
+
+                let mut streams = [D3D11_VIDEO_PROCESSOR_STREAM::default()];
+                streams[0].Enable = true.into();
+                streams[0].pInputSurface = ManuallyDrop::new(Some(input.clone()));
+
+                let result = self
+                    .video_context
+                    .VideoProcessorBlt(processor, output, 0, &streams);
+
+                ManuallyDrop::drop(&mut streams[0].pInputSurface);
+                result
             }
         }
     }
@@ -732,3 +1399,118 @@ pub mod win32 {
         }
     }
 }
+
+#[cfg(target_os = "linux")]
+pub mod linux {
+    use std::ptr::{null, null_mut};
+
+    use common::{Size, frame::VideoFormat};
+    use ffmpeg::*;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum VideoResamplerError {
+        #[error("unsupported input video format")]
+        UnsupportedFormat,
+        #[error("failed to create swscale")]
+        CreateSwscaleError,
+        #[error("input buffer is too small")]
+        InvalidInputBuffer,
+        #[error("failed to scale video frame")]
+        ScaleError,
+    }
+
+    /// Used to convert packed RGB video frames in system memory, including
+    /// color space conversion and scaling, the output is fixed to NV12.
+    pub struct VideoResampler {
+        context: *mut SwsContext,
+        input: Size,
+        output: Size,
+        buffer: Vec<u8>,
+    }
+
+    unsafe impl Send for VideoResampler {}
+    unsafe impl Sync for VideoResampler {}
+
+    impl VideoResampler {
+        pub fn new(
+            format: VideoFormat,
+            input: Size,
+            output: Size,
+        ) -> Result<Self, VideoResamplerError> {
+            let format = match format {
+                VideoFormat::BGRA => AVPixelFormat::AV_PIX_FMT_BGRA,
+                VideoFormat::RGBA => AVPixelFormat::AV_PIX_FMT_RGBA,
+                _ => return Err(VideoResamplerError::UnsupportedFormat),
+            };
+
+            let context = unsafe {
+                sws_getContext(
+                    input.width as i32,
+                    input.height as i32,
+                    format,
+                    output.width as i32,
+                    output.height as i32,
+                    AVPixelFormat::AV_PIX_FMT_NV12,
+                    SWS_FAST_BILINEAR as i32,
+                    null_mut(),
+                    null_mut(),
+                    null(),
+                )
+            };
+
+            if context.is_null() {
+                return Err(VideoResamplerError::CreateSwscaleError);
+            }
+
+            let luma = output.width as usize * output.height as usize;
+            Ok(Self {
+                buffer: vec![0u8; luma + luma / 2],
+                context,
+                input,
+                output,
+            })
+        }
+
+        /// Convert a frame, returns the Y plane and the interleaved UV plane of
+        /// the output, the line size of both planes is the output width.
+        pub fn process(
+            &mut self,
+            buffer: &[u8],
+            linesize: u32,
+        ) -> Result<[&[u8]; 2], VideoResamplerError> {
+            if buffer.len() < linesize as usize * self.input.height as usize {
+                return Err(VideoResamplerError::InvalidInputBuffer);
+            }
+
+            let (y, uv) = self
+                .buffer
+                .split_at_mut(self.output.width as usize * self.output.height as usize);
+
+            if unsafe {
+                sws_scale(
+                    self.context,
+                    [buffer.as_ptr(), null(), null(), null()].as_ptr(),
+                    [linesize as i32, 0, 0, 0].as_ptr(),
+                    0,
+                    self.input.height as i32,
+                    [y.as_mut_ptr(), uv.as_mut_ptr(), null_mut(), null_mut()].as_ptr(),
+                    [self.output.width as i32, self.output.width as i32, 0, 0].as_ptr(),
+                )
+            } <= 0
+            {
+                return Err(VideoResamplerError::ScaleError);
+            }
+
+            Ok([y, uv])
+        }
+    }
+
+    impl Drop for VideoResampler {
+        fn drop(&mut self) {
+            unsafe {
+                sws_freeContext(self.context);
+            }
+        }
+    }
+}
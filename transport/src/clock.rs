@@ -0,0 +1,264 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use parking_lot::Mutex;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Buffer, BufferType, StreamType};
+
+/// One exchange of the clock sync, the times are in microseconds of
+/// `common::clock`, t0 and t3 are read on the receiver, t1 and t2 on the
+/// sender.
+#[derive(Debug, Clone, Copy)]
+struct ClockSample {
+    /// The receiver sent the request.
+    t0: u64,
+    /// The sender received the request.
+    t1: u64,
+    /// The sender sent the response.
+    t2: u64,
+    /// The receiver received the response.
+    t3: u64,
+}
+
+impl ClockSample {
+    fn rtt(&self) -> u64 {
+        self.t3
+            .saturating_sub(self.t0)
+            .saturating_sub(self.t2.saturating_sub(self.t1))
+    }
+
+    /// The clock of the sender minus the clock of the receiver, assuming that
+    /// both directions take the same time.
+    fn offset(&self) -> i64 {
+        ((self.t1 as i64 - self.t0 as i64) + (self.t2 as i64 - self.t3 as i64)) / 2
+    }
+}
+
+/// How the clock of the sender relates to the clock of the receiver, see
+/// `TransportReceiver::clock`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct ClockEstimate {
+    /// The clock of the sender minus the clock of the receiver in
+    /// microseconds, at the time of the measurement.
+    pub offset: i64,
+    /// The round trip time of the exchange that the offset comes from in
+    /// microseconds, the offset is off by at most half of it. Both directions
+    /// include the latency of the transport.
+    pub rtt: u64,
+    /// How much faster the clock of the sender runs than the clock of the
+    /// receiver, in parts per million.
+    pub drift: f64,
+    /// The time of the measurement on the clock of the receiver.
+    pub measured_at: u64,
+}
+
+impl ClockEstimate {
+    /// The offset at a time of the receiver, the drift is applied from the
+    /// time of the measurement.
+    fn offset_at(&self, local: u64) -> i64 {
+        let elapsed = local as i64 - self.measured_at as i64;
+
+        self.offset + (elapsed as f64 * self.drift / 1_000_000.0) as i64
+    }
+
+    /// Converts a timestamp of the sender to the clock of the receiver.
+    pub fn to_local(&self, remote: u64) -> u64 {
+        // The drift is too slow for the error of the approximate local time to
+        // matter when the offset at that time is found.
+        let approximate = (remote as i64 - self.offset).max(0) as u64;
+
+        (remote as i64 - self.offset_at(approximate)).max(0) as u64
+    }
+
+    /// Converts a timestamp of the receiver to the clock of the sender.
+    pub fn to_remote(&self, local: u64) -> u64 {
+        (local as i64 + self.offset_at(local)).max(0) as u64
+    }
+}
+
+/// The clock filter of the receiver, the offset is taken from the exchange
+/// with the lowest round trip time of the recent ones, which is the least
+/// affected by the queuing, and the drift is the slope of the offsets over
+/// time.
+#[derive(Default)]
+struct ClockFilter {
+    samples: VecDeque<ClockSample>,
+    estimate: Option<ClockEstimate>,
+}
+
+impl ClockFilter {
+    /// The number of the recent exchanges that are kept.
+    const WINDOW: usize = 16;
+
+    /// The drift is only estimated over at least this span, the offsets of
+    /// a shorter span are dominated by the jitter.
+    const DRIFT_SPAN: u64 = 10_000_000;
+
+    fn push(&mut self, sample: ClockSample) {
+        if self.samples.len() == Self::WINDOW {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(sample);
+
+        let Some(best) = self.samples.iter().min_by_key(|it| it.rtt()).copied() else {
+            return;
+        };
+
+        self.estimate = Some(ClockEstimate {
+            offset: best.offset(),
+            rtt: best.rtt(),
+            drift: self.drift(best.rtt()),
+            measured_at: best.t3,
+        });
+    }
+
+    /// The least squares slope of the offsets, only the exchanges that were
+    /// about as fast as the best one are used.
+    fn drift(&self, min_rtt: u64) -> f64 {
+        let samples = self
+            .samples
+            .iter()
+            .filter(|it| it.rtt() <= min_rtt * 3 / 2 + 500)
+            .map(|it| (it.t3 as f64, it.offset() as f64))
+            .collect::<Vec<_>>();
+
+        let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+            return 0.0;
+        };
+
+        if samples.len() < 4 || (last.0 - first.0) < Self::DRIFT_SPAN as f64 {
+            return 0.0;
+        }
+
+        let count = samples.len() as f64;
+        let mean_x = samples.iter().map(|it| it.0).sum::<f64>() / count;
+        let mean_y = samples.iter().map(|it| it.1).sum::<f64>() / count;
+
+        let (mut covariance, mut variance) = (0.0, 0.0);
+        for (x, y) in &samples {
+            covariance += (x - mean_x) * (y - mean_y);
+            variance += (x - mean_x) * (x - mean_x);
+        }
+
+        if variance == 0.0 {
+            0.0
+        } else {
+            covariance / variance * 1_000_000.0
+        }
+    }
+}
+
+/// The clock sync of a receiver, it is shared between the transport, which
+/// exchanges the times with the sender over the connection, and the users of
+/// the timestamps of the sender.
+#[derive(Clone, Default)]
+pub struct ClockSync(Arc<Mutex<ClockFilter>>);
+
+impl ClockSync {
+    /// The first exchanges are sent quickly, so that the clock is synced
+    /// shortly after the connection.
+    const FAST_INTERVAL: Duration = Duration::from_millis(250);
+    const FAST_EXCHANGES: u32 = 8;
+
+    /// The exchanges after that only follow the drift.
+    const INTERVAL: Duration = Duration::from_secs(2);
+
+    /// The clock of a sender in the same process, it is the same clock.
+    pub(crate) fn local() -> Self {
+        let sync = Self::default();
+        sync.0.lock().estimate = Some(ClockEstimate::default());
+        sync
+    }
+
+    /// The interval before the next exchange, after the count exchanges.
+    pub(crate) fn interval(count: u32) -> Duration {
+        if count < Self::FAST_EXCHANGES {
+            Self::FAST_INTERVAL
+        } else {
+            Self::INTERVAL
+        }
+    }
+
+    /// The current estimate, `None` before the first exchange is completed.
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        self.0.lock().estimate
+    }
+
+    /// Converts a timestamp of the sender to the clock of the receiver, the
+    /// timestamp is returned as it is before the clock is synced.
+    pub fn to_local(&self, remote: u64) -> u64 {
+        self.estimate()
+            .map(|it| it.to_local(remote))
+            .unwrap_or(remote)
+    }
+
+    /// The exchanges of a previous connection may be with another sender.
+    pub(crate) fn reset(&self) {
+        let mut filter = self.0.lock();
+        filter.samples.clear();
+        filter.estimate = None;
+    }
+
+    /// Takes the response of the sender, arrived is when the response was
+    /// received.
+    pub(crate) fn response(&self, buffer: &Buffer<Bytes>, arrived: u64) {
+        let mut data = buffer.data.clone();
+        if data.len() < 16 {
+            return;
+        }
+
+        let sample = ClockSample {
+            t0: buffer.timestamp,
+            t1: data.get_u64(),
+            t2: data.get_u64(),
+            t3: arrived,
+        };
+
+        // A response that is older than the request is not from this connection.
+        if sample.t3 < sample.t0 {
+            return;
+        }
+
+        tracing::debug!(
+            "transport clock sync, offset={}, rtt={}",
+            sample.offset(),
+            sample.rtt()
+        );
+
+        self.0.lock().push(sample);
+    }
+}
+
+/// The request of the receiver, it only carries the time it is sent.
+pub(crate) fn request() -> Bytes {
+    Buffer {
+        data: Buffer::<BytesMut>::with_capacity(0),
+        stream: StreamType::Video,
+        ty: BufferType::Clock,
+        rendition: 0,
+        timestamp: common::clock::now(),
+    }
+    .encode(0)
+}
+
+/// The response of the sender to a request, received is when the request
+/// was received, the time of the request is returned as the timestamp.
+pub(crate) fn respond(request: &Buffer<Bytes>, received: u64) -> Bytes {
+    let mut data = Buffer::<BytesMut>::with_capacity(0);
+    data.put_u64(received);
+    data.put_u64(common::clock::now());
+
+    Buffer {
+        stream: StreamType::Video,
+        ty: BufferType::Clock,
+        rendition: 0,
+        timestamp: request.timestamp,
+        data,
+    }
+    .encode(0)
+}
@@ -7,11 +7,12 @@ use arc_swap::ArcSwapOption;
 use bytes::{Bytes, BytesMut};
 use smallvec::SmallVec;
 
-use crate::{Buffer, BufferType, StreamType};
+use crate::{Buffer, BufferType, MAX_RENDITIONS, StreamType};
 
+/// Each rendition of the video has its own configuration.
 #[derive(Default)]
 struct Configs {
-    video: ArcSwapOption<BytesMut>,
+    video: [ArcSwapOption<BytesMut>; MAX_RENDITIONS as usize],
     audio: ArcSwapOption<BytesMut>,
 }
 
@@ -23,7 +24,10 @@ struct Configs {
 #[derive(Default)]
 pub struct StreamProducer {
     audio_count: AtomicU8,
-    sequence: AtomicU32,
+    audio_sequence: AtomicU32,
+    /// Each rendition of the video has its own sequence, so that a receiver
+    /// of one rendition finds the lost packets of that rendition.
+    sequences: [AtomicU32; MAX_RENDITIONS as usize],
     configs: Configs,
 }
 
@@ -33,34 +37,39 @@ impl StreamProducer {
     // h264 decoding any p-frames and i-frames requires sps and pps
     // frames, so the configuration frames are saved here, although it
     // should be noted that the configuration frames will only be
-    // generated once.
+    // generated once. The rendition of the buffer must be below
+    // `MAX_RENDITIONS`.
     pub fn filter(&self, buffer: Buffer<BytesMut>) -> SmallVec<[Bytes; 2]> {
         let mut pkts: SmallVec<[Bytes; 2]> = SmallVec::with_capacity(5);
 
         match buffer.stream {
             StreamType::Video => {
+                let rendition = buffer.rendition;
+                let config = &self.configs.video[rendition as usize];
+                let sequence = &self.sequences[rendition as usize];
+
                 if buffer.ty == BufferType::Config {
-                    self.configs
-                        .video
-                        .store(Some(Arc::new(buffer.data.clone())));
+                    config.store(Some(Arc::new(buffer.data.clone())));
                 }
 
-                // Add SPS and PPS units in front of each keyframe (only use android)
+                // Add SPS and PPS units in front of each keyframe (only use android), the
+                // receivers also switch the renditions there.
                 if buffer.ty == BufferType::KeyFrame {
-                    if let Some(cfg) = self.configs.video.load().as_ref() {
+                    if let Some(cfg) = config.load().as_ref() {
                         pkts.push(
                             Buffer {
                                 data: cfg.as_ref().clone(),
                                 stream: StreamType::Video,
                                 ty: BufferType::Config,
                                 timestamp: buffer.timestamp,
+                                rendition,
                             }
-                            .encode(self.sequence.fetch_add(1, Ordering::Relaxed)),
+                            .encode(sequence.fetch_add(1, Ordering::Relaxed)),
                         );
                     }
                 }
 
-                pkts.push(buffer.encode(self.sequence.fetch_add(1, Ordering::Relaxed)));
+                pkts.push(buffer.encode(sequence.fetch_add(1, Ordering::Relaxed)));
             }
             StreamType::Audio => {
                 if buffer.ty == BufferType::Config {
@@ -79,6 +88,7 @@ impl StreamProducer {
                                 data: cfg.as_ref().clone(),
                                 stream: StreamType::Audio,
                                 ty: BufferType::Config,
+                                rendition: 0,
                                 timestamp: buffer.timestamp,
                             }
                             .encode(0),
@@ -86,7 +96,9 @@ impl StreamProducer {
                     }
                 }
 
-                pkts.push(buffer.encode(0));
+                // The audio sequence is independent of the video, the receiver uses it to
+                // find the lost audio packets and conceal them.
+                pkts.push(buffer.encode(self.audio_sequence.fetch_add(1, Ordering::Relaxed)));
             }
         }
 
@@ -110,6 +122,13 @@ impl PacketFilter {
     }
 
     fn filter(&mut self, ty: BufferType) -> bool {
+        // The track notices carry no media, they are passed through even before the
+        // decoder is initialized, so that a receiver joining a disabled track still
+        // knows why there is nothing to play.
+        if ty == BufferType::TrackDisabled || ty == BufferType::TrackEnabled {
+            return true;
+        }
+
         // First check whether the decoder has been initialized. Here, it is judged
         // whether the configuration information has consumer. If the configuration
         // information has consumer, the decoder initialization is marked as completed.
@@ -159,6 +178,13 @@ impl PacketFilter {
 /// guarantee no packet loss.
 pub struct StreamConsumer {
     last_sequence: Option<u32>,
+    last_audio_sequence: Option<u32>,
+    audio_lost: u32,
+    video_lost: u32,
+    /// The rendition of the video that is passed on, and the rendition that
+    /// it is switched to at the next configuration of that rendition.
+    rendition: u8,
+    target: u8,
     video: PacketFilter,
     audio: PacketFilter,
 }
@@ -169,6 +195,11 @@ impl Default for StreamConsumer {
             video: PacketFilter::new(StreamType::Video),
             audio: PacketFilter::new(StreamType::Audio),
             last_sequence: None,
+            last_audio_sequence: None,
+            audio_lost: 0,
+            video_lost: 0,
+            rendition: 0,
+            target: 0,
         }
     }
 }
@@ -181,19 +212,58 @@ impl StreamConsumer {
         // Decode the data packet to get sequence number and buffer information
         let (sequence, buffer) = Buffer::<Bytes>::decode(bytes).ok()?;
 
+        // The end of the stream and the clock sync are passed through regardless of
+        // the state of the streams.
+        if buffer.ty == BufferType::EndOfStream || buffer.ty == BufferType::Clock {
+            return Some(buffer);
+        }
+
         match buffer.stream {
             StreamType::Video => {
-                // If there is a previous sequence number, perform packet loss detection
-                if let Some(last) = self.last_sequence.replace(sequence) {
-                    // Check if sequence numbers are consecutive, if not, packet loss is detected
-                    if sequence != last.wrapping_add(1) {
-                        // Mark video stream as unreadable and wait for next keyframe
-                        self.video.pkt_loss();
-
-                        log::warn!("packet loss occurs at the transport layer");
+                let is_media = matches!(
+                    buffer.ty,
+                    BufferType::Partial | BufferType::KeyFrame | BufferType::Config
+                );
 
+                // The configuration is in front of every key frame, the video is switched to
+                // the subscribed rendition there, the media of the other renditions is
+                // dropped. The new rendition starts over like a new stream.
+                if is_media && buffer.rendition != self.rendition {
+                    if buffer.rendition != self.target || buffer.ty != BufferType::Config {
                         return None;
                     }
+
+                    tracing::info!(
+                        "transport receiver switch rendition, from={}, to={}",
+                        self.rendition,
+                        self.target
+                    );
+
+                    self.rendition = self.target;
+                    self.last_sequence = None;
+                    self.video = PacketFilter::new(StreamType::Video);
+                }
+
+                // If there is a previous sequence number, perform packet loss detection, the
+                // track notices of the other renditions have sequences of their own.
+                if buffer.rendition == self.rendition {
+                    if let Some(last) = self.last_sequence.replace(sequence) {
+                        // Check if sequence numbers are consecutive, if not, packet loss is
+                        // detected
+                        if sequence != last.wrapping_add(1) {
+                            let lost = sequence.wrapping_sub(last).wrapping_sub(1);
+                            if lost < u32::MAX / 2 {
+                                self.video_lost += lost;
+                            }
+
+                            // Mark video stream as unreadable and wait for next keyframe
+                            self.video.pkt_loss();
+
+                            tracing::warn!("packet loss occurs at the transport layer");
+
+                            return None;
+                        }
+                    }
                 }
 
                 // Filter packets based on their type
@@ -202,6 +272,24 @@ impl StreamConsumer {
                 }
             }
             StreamType::Audio => {
+                // The configuration packets do not carry a sequence number.
+                if buffer.ty != BufferType::Config {
+                    if let Some(last) = self.last_audio_sequence.replace(sequence) {
+                        let lost = sequence.wrapping_sub(last).wrapping_sub(1);
+
+                        // A very large gap means that the packet is older than the previous
+                        // one, this is not a loss.
+                        if lost > 0 && lost < u32::MAX / 2 {
+                            self.audio_lost += lost;
+
+                            tracing::warn!(
+                                "audio packet loss occurs at the transport layer, lost={}",
+                                lost
+                            );
+                        }
+                    }
+                }
+
                 // Audio stream only needs type-based filtering
                 if self.audio.filter(buffer.ty) {
                     return Some(buffer);
@@ -211,4 +299,90 @@ impl StreamConsumer {
 
         None
     }
+
+    /// Takes the number of audio packets lost since the last call, the audio
+    /// stream is not interrupted by the loss, the decoder conceals the missing
+    /// packets instead.
+    pub fn take_audio_lost(&mut self) -> u32 {
+        std::mem::take(&mut self.audio_lost)
+    }
+
+    /// Takes the number of video packets lost since the last call, the video
+    /// waits for the next key frame after a loss.
+    pub fn take_video_lost(&mut self) -> u32 {
+        std::mem::take(&mut self.video_lost)
+    }
+
+    /// Subscribes to another rendition of the video, the current rendition
+    /// is passed on until the next key frame of the new one.
+    pub fn set_rendition(&mut self, rendition: u8) {
+        self.target = rendition;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn produce(producer: &StreamProducer, ty: BufferType, rendition: u8) -> Vec<Bytes> {
+        producer
+            .filter(Buffer {
+                data: Buffer::<()>::copy_from_slice(&[rendition]),
+                stream: StreamType::Video,
+                timestamp: 0,
+                rendition,
+                ty,
+            })
+            .into_vec()
+    }
+
+    /// The renditions of the packets that the consumer passes on.
+    fn consume(consumer: &mut StreamConsumer, packets: Vec<Bytes>) -> Vec<(BufferType, u8)> {
+        packets
+            .into_iter()
+            .filter_map(|it| consumer.filter(it))
+            .map(|it| (it.ty, it.rendition))
+            .collect()
+    }
+
+    #[test]
+    fn switches_the_rendition_at_its_configuration() {
+        let producer = StreamProducer::default();
+        let mut consumer = StreamConsumer::default();
+
+        for rendition in [0, 1] {
+            produce(&producer, BufferType::Config, rendition);
+        }
+
+        // The key frames carry the configuration in front of them.
+        assert_eq!(
+            consume(&mut consumer, produce(&producer, BufferType::KeyFrame, 0)),
+            [(BufferType::Config, 0), (BufferType::KeyFrame, 0)]
+        );
+
+        assert!(consume(&mut consumer, produce(&producer, BufferType::KeyFrame, 1)).is_empty());
+
+        consumer.set_rendition(1);
+
+        // The current rendition is passed on until the new one starts.
+        assert_eq!(
+            consume(&mut consumer, produce(&producer, BufferType::Partial, 0)),
+            [(BufferType::Partial, 0)]
+        );
+
+        assert!(consume(&mut consumer, produce(&producer, BufferType::Partial, 1)).is_empty());
+
+        assert_eq!(
+            consume(&mut consumer, produce(&producer, BufferType::KeyFrame, 1)),
+            [(BufferType::Config, 1), (BufferType::KeyFrame, 1)]
+        );
+
+        assert!(consume(&mut consumer, produce(&producer, BufferType::Partial, 0)).is_empty());
+        assert_eq!(
+            consume(&mut consumer, produce(&producer, BufferType::Partial, 1)),
+            [(BufferType::Partial, 1)]
+        );
+
+        assert_eq!(consumer.take_video_lost(), 0);
+    }
 }
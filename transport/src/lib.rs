@@ -1,7 +1,19 @@
+mod clock;
 mod filter;
+mod loopback;
+mod options;
+mod pacer;
+mod pool;
+mod probe;
 mod protocol;
+mod simulcast;
+mod stream_id;
+mod viewers;
 
-use std::io::{Error, ErrorKind, Result};
+use std::{
+    io::{Error, ErrorKind, Result},
+    time::Duration,
+};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
@@ -9,8 +21,15 @@ use bytes::{Buf, BufMut, Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
 
 pub use self::{
+    clock::{ClockEstimate, ClockSync},
+    options::{FecArq, FecLayout, FecOptions, TransportOptionsBuilder, TransportOptionsError},
+    pacer::PacingStats,
+    pool::{BufferPool, BufferPoolStats},
+    probe::{ProbeReport, ProbeServer, bandwidth_probe},
     receiver::{Receiver as TransportReceiver, ReceiverSink as TransportReceiverSink},
-    sender::Sender as TransportSender,
+    sender::{ConnectionStats, Sender as TransportSender},
+    simulcast::RenditionSubscription,
+    stream_id::{StreamId, StreamMode},
 };
 
 #[derive(Debug, Clone)]
@@ -28,6 +47,24 @@ pub struct TransportOptions {
     pub fec: String,
     // Flow control window size
     pub fc: u32,
+    /// The session token, the receiver sends it in the stream id of the
+    /// connection, and the sender only accepts the receivers with the same
+    /// token, `None` on the sender accepts any receiver.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub session: Option<String>,
+    /// The name of the stream in the stream id of the connection, the srt
+    /// relays route the streams by it, `None` on the sender accepts any
+    /// stream name.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub resource: Option<String>,
+    /// The passphrase of the srt encryption, both sides must use the same
+    /// passphrase, `None` does not encrypt the stream.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub passphrase: Option<String>,
+    /// How the sender and the receiver are connected, both sides must use the
+    /// same strategy.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub strategy: TransportStrategy,
 }
 
 impl Default for TransportOptions {
@@ -39,6 +76,50 @@ impl Default for TransportOptions {
             latency: 20,
             mtu: 1500,
             fc: 32,
+            session: None,
+            resource: None,
+            passphrase: None,
+            strategy: TransportStrategy::default(),
+        }
+    }
+}
+
+/// How the sender and the receiver are connected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum TransportStrategy {
+    /// The SRT protocol over UDP.
+    #[default]
+    Srt,
+    /// The sender and the receiver are connected within this process without
+    /// any socket, the receiver finds the sender by the port of the address
+    /// only. No packet is lost, so the stream is deterministic, for example
+    /// for the tests on the machines where SRT is not available. The
+    /// receiver does not reconnect.
+    Loopback,
+}
+
+/// Reconnection policy of the receiver when the connection to the sender is
+/// lost.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct ReconnectOptions {
+    /// The maximum number of attempts before the receiver is closed.
+    pub max_retries: u32,
+    /// The delay before the first attempt in milliseconds, the delay is
+    /// doubled after each failed attempt.
+    pub backoff: u64,
+}
+
+impl ReconnectOptions {
+    const MAX_BACKOFF: Duration = Duration::from_secs(10);
+}
+
+impl Default for ReconnectOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            backoff: 500,
         }
     }
 }
@@ -54,13 +135,24 @@ pub fn shutdown() {
     protocol::cleanup()
 }
 
+/// The version of the linked SRT library.
+pub fn version() -> String {
+    protocol::version()
+}
+
 /// Represents different types of data buffers in the transport layer
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BufferType {
-    Partial = 0,  // Represents a partial frame or incomplete data
-    KeyFrame = 1, // Represents a complete key frame in video streaming
-    Config = 2,   // Represents configuration data
+    Partial = 0,       // Represents a partial frame or incomplete data
+    KeyFrame = 1,      // Represents a complete key frame in video streaming
+    Config = 2,        // Represents configuration data
+    EndOfStream = 3,   // The sender ended the stream, no data follows it
+    Silence = 4,       // The audio is silent, no audio follows until the sound returns
+    TrackDisabled = 5, // The sender disabled the stream, no data follows until it is enabled
+    TrackEnabled = 6,  // The sender enabled the stream again
+    Clock = 7,         // The exchange of the clock sync, it is never passed to the sink
+    Heartbeat = 8,     // The receiver is still connected, only the receiver sends it
 }
 
 impl TryFrom<u8> for BufferType {
@@ -71,6 +163,12 @@ impl TryFrom<u8> for BufferType {
             0 => Self::Partial,
             1 => Self::KeyFrame,
             2 => Self::Config,
+            3 => Self::EndOfStream,
+            4 => Self::Silence,
+            5 => Self::TrackDisabled,
+            6 => Self::TrackEnabled,
+            7 => Self::Clock,
+            8 => Self::Heartbeat,
             _ => {
                 return Err(Error::new(
                     ErrorKind::InvalidInput,
@@ -106,24 +204,52 @@ impl TryFrom<u8> for StreamType {
     }
 }
 
+/// Why the receiver is closed, the reasons of the transport are a subset of
+/// the reasons of the sdk and are named the same.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum CloseReason {
+    /// The sender ended the stream intentionally.
+    RemoteClosed = 0,
+    /// The connection to the sender was lost, such as the network is
+    /// disconnected, and the reconnection failed if it is enabled.
+    NetworkTimeout = 1,
+    /// The receiver is closed locally, by the sink or by dropping it.
+    Closed = 2,
+}
+
+/// The number of the renditions of the simulcast video, including the main
+/// video, which is rendition 0.
+pub const MAX_RENDITIONS: u8 = 3;
+
+/// The version of the packet format, it changes with the header of the
+/// buffers. The receiver sends it in the stream id and the sender only
+/// accepts the receivers of the same version, the senders publish it in
+/// their descriptions.
+pub const PROTOCOL_VERSION: u8 = 2;
+
 /// Generic buffer structure for handling different types of data
 #[derive(Debug, Clone)]
 pub struct Buffer<T> {
     pub stream: StreamType, // Type of stream (video/audio)
     pub ty: BufferType,     // Type of buffer (keyframe/config/etc)
+    pub rendition: u8,      // Rendition of the simulcast video, 0 for everything else
     pub timestamp: u64,     // Timestamp for synchronization
     pub data: T,            // The actual data payload
 }
 
 impl<T> Buffer<T> {
-    /// Size of the header in bytes for each buffer
-    const HEAD_SIZE: usize = 14;
+    /// Size of the header in bytes for each buffer, the [PROTOCOL_VERSION]
+    /// changes with it.
+    const HEAD_SIZE: usize = 15;
 
     /// Creates a BytesMut and copies from src to a buffer. The created buffer
     /// contains the initial message header required for message encoding, which
-    /// is an optimization to reduce data copying in the process.
+    /// is an optimization to reduce data copying in the process. The buffer is
+    /// taken from the global buffer pool.
     pub fn copy_from_slice(src: &[u8]) -> BytesMut {
-        let mut bytes = BytesMut::with_capacity(src.len() + Self::HEAD_SIZE);
+        let mut bytes = BufferPool::global().get(src.len() + Self::HEAD_SIZE);
         bytes.put_bytes(0, Self::HEAD_SIZE);
         bytes.put(src);
         bytes
@@ -134,7 +260,9 @@ impl<T> Buffer<T> {
     /// for message encoding, which is an optimization to reduce data
     /// copying in the process.
     pub fn with_capacity(size: usize) -> BytesMut {
-        BytesMut::zeroed(size + Self::HEAD_SIZE)
+        let mut bytes = BufferPool::global().get(size + Self::HEAD_SIZE);
+        bytes.resize(size + Self::HEAD_SIZE, 0);
+        bytes
     }
 }
 
@@ -153,6 +281,7 @@ impl Buffer<BytesMut> {
         self.data.put_u32(sequence);
         self.data.put_u8(self.stream as u8);
         self.data.put_u8(self.ty as u8);
+        self.data.put_u8(self.rendition);
         self.data.put_u64(self.timestamp);
 
         // Restore the original data
@@ -173,6 +302,7 @@ impl Buffer<Bytes> {
             Buffer {
                 stream: StreamType::try_from(bytes.get_u8())?,
                 ty: BufferType::try_from(bytes.get_u8())?,
+                rendition: bytes.get_u8(),
                 timestamp: bytes.get_u64(),
                 data: bytes,
             },
@@ -181,14 +311,30 @@ impl Buffer<Bytes> {
 }
 
 mod receiver {
-    use std::{io::Error, net::SocketAddr, sync::Arc, thread};
+    use std::{
+        io::{Error, ErrorKind},
+        net::SocketAddr,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        thread,
+        time::Duration,
+    };
 
+    use arc_swap::ArcSwap;
     use bytes::Bytes;
 
     use super::{
-        Buffer, TransportOptions,
+        Buffer, BufferType, CloseReason, PROTOCOL_VERSION, ReconnectOptions, StreamType,
+        TransportOptions, TransportStrategy,
+        clock::{self, ClockSync},
         filter::StreamConsumer,
-        protocol::{FragmentDecoder, SrtOptions, SrtSocket},
+        loopback,
+        protocol::{FragmentDecoder, FragmentEncoder, SrtSocket},
+        simulcast::RenditionSubscription,
+        stream_id::{StreamId, StreamMode},
+        viewers,
     };
 
     /// Trait for handling received data
@@ -196,13 +342,40 @@ mod receiver {
         /// Process received buffer data
         /// Returns false if processing should stop
         fn sink(&mut self, buffer: Buffer<Bytes>) -> bool;
+        /// Called before the next packet of the stream when packets of the
+        /// stream have been lost, count is the number of lost packets.
+        /// Returns false if processing should stop
+        #[allow(unused_variables)]
+        fn lost(&mut self, stream: StreamType, count: u32) -> bool {
+            true
+        }
+        /// Called before each attempt to reconnect after the connection is
+        /// lost, attempt starts from 1.
+        /// Returns false if the reconnection should stop
+        #[allow(unused_variables)]
+        fn reconnecting(&mut self, attempt: u32) -> bool {
+            true
+        }
+        /// Called when the connection is re-established
+        fn reconnected(&mut self) {}
         /// Cleanup when receiver is closed
-        fn close(&mut self);
+        fn close(&mut self, reason: CloseReason);
+    }
+
+    /// Passes the packet to the sink in the span of the received packet, the
+    /// decoding and the rendering of the packet are the children of the span.
+    pub(crate) fn recv<S: ReceiverSink>(sinker: &mut S, buffer: Buffer<Bytes>) -> bool {
+        tracing::trace_span!("recv", stream = ?buffer.stream, timestamp = buffer.timestamp)
+            .in_scope(|| sinker.sink(buffer))
     }
 
     /// Handles receiving data over SRT protocol
     pub struct Receiver {
-        socket: Arc<SrtSocket>,
+        /// There is no socket with the loopback strategy.
+        socket: Option<Arc<ArcSwap<SrtSocket>>>,
+        closed: Arc<AtomicBool>,
+        clock: ClockSync,
+        rendition: RenditionSubscription,
     }
 
     impl Receiver {
@@ -211,233 +384,965 @@ mod receiver {
         pub fn new<S: ReceiverSink + 'static>(
             addr: SocketAddr,
             options: TransportOptions,
+            sinker: S,
+        ) -> Result<Self, Error> {
+            Self::with_reconnect(addr, options, None, sinker)
+        }
+
+        /// Creates a new receiver that reconnects to the sender when the
+        /// connection is lost, the sink is kept across the reconnections and
+        /// is only closed when all attempts have failed.
+        pub fn with_reconnect<S: ReceiverSink + 'static>(
+            addr: SocketAddr,
+            options: TransportOptions,
+            reconnect: Option<ReconnectOptions>,
             mut sinker: S,
         ) -> Result<Self, Error> {
-            log::info!("transport create receiver, addr={}", addr);
+            tracing::info!("transport create receiver, addr={}", addr);
+
+            options
+                .validate()
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+            let rendition = RenditionSubscription::default();
+            if options.strategy == TransportStrategy::Loopback {
+                return Ok(Self {
+                    closed: loopback::subscribe(addr, options, rendition.clone(), sinker)?,
+                    clock: ClockSync::local(),
+                    socket: None,
+                    rendition,
+                });
+            }
 
             // Create SRT connection with optimized settings
-            let socket = Arc::new(SrtSocket::connect(addr, {
-                let mut opt = SrtOptions::default();
-                opt.max_bandwidth = options.max_bandwidth;
-                opt.timeout = options.timeout;
-                opt.latency = options.latency;
-                opt.fec = options.fec;
-                opt.mtu = options.mtu;
-                opt.fc = options.fc;
+            let srt_options = {
+                let mut opt = options.srt_options();
+                opt.stream_id = Some(
+                    StreamId {
+                        resource: options.resource,
+                        mode: Some(StreamMode::Request),
+                        session: options.session,
+                        version: Some(PROTOCOL_VERSION),
+                    }
+                    .to_string(),
+                );
 
                 opt
-            })?);
+            };
+
+            let socket = Arc::new(ArcSwap::from_pointee(SrtSocket::connect(
+                addr,
+                srt_options.clone(),
+            )?));
+
+            let closed = Arc::new(AtomicBool::new(false));
+            let clock = ClockSync::default();
+            let mtu = srt_options.mtu as usize;
+
+            // The heartbeats and the requests of the clock sync are sent on a thread of
+            // their own, the responses come back with the media on the receiver thread.
+            let socket_ = socket.clone();
+            let closed_ = closed.clone();
+            let clock_ = clock.clone();
+            let rendition_ = rendition.clone();
+            thread::Builder::new()
+                .name("HylaranaTransportControlThread".to_string())
+                .spawn(move || {
+                    let mut encoder = FragmentEncoder::new(mtu);
+                    let mut count = 0;
+
+                    // The id is kept across the reconnections, the sender counts the
+                    // receiver once.
+                    let id = viewers::viewer_id();
+                    let mut rendition = rendition_.get();
+
+                    while !closed_.load(Ordering::Relaxed) {
+                        // The clock is reset on a reconnection, it is synced quickly again.
+                        if clock_.estimate().is_none() {
+                            count = 0;
+                        }
+
+                        let socket = socket_.load();
+                        for message in [viewers::heartbeat(id, rendition), clock::request()] {
+                            for packet in encoder.encode(&message) {
+                                if let Err(e) = socket.send(packet) {
+                                    tracing::debug!(
+                                        "transport failed to send control message, err={:?}",
+                                        e
+                                    );
+                                }
+                            }
+                        }
+
+                        // A new subscription is sent right away.
+                        rendition = rendition_.wait(rendition, ClockSync::interval(count));
+                        count += 1;
+                    }
+                })?;
 
             // Spawn receiver thread
             let socket_ = socket.clone();
+            let closed_ = closed.clone();
+            let clock_ = clock.clone();
+            let rendition_ = rendition.clone();
             thread::Builder::new()
                 .name("HylaranaTransportReceiverThread".to_string())
                 .spawn(move || {
                     let mut bytes = [0u8; 4096];
-                    let mut decoder = FragmentDecoder::new();
-                    let mut consumer = StreamConsumer::default();
+                    let mut reason = CloseReason::NetworkTimeout;
+
+                    'connection: loop {
+                        // The sequence numbers start over on a new connection.
+                        let mut decoder = FragmentDecoder::new();
+                        let mut consumer = StreamConsumer::default();
+                        let socket = socket_.load_full();
+
+                        // A message is decoded when the first fragment of the next message
+                        // arrives, so it was received with the previous fragment.
+                        let mut arrived = 0;
+
+                        // Main receive loop
+                        loop {
+                            match socket.read(&mut bytes) {
+                                Ok(size) => {
+                                    if size == 0 {
+                                        break;
+                                    }
 
-                    // Main receive loop
-                    loop {
-                        match socket_.read(&mut bytes) {
-                            Ok(size) => {
-                                if size == 0 {
-                                    break;
-                                }
+                                    let now = common::clock::now();
+                                    let received = std::mem::replace(&mut arrived, now);
+
+                                    // Process received data
+                                    if let Some(packet) = decoder.decode(&bytes[..size]) {
+                                        consumer.set_rendition(rendition_.get());
+                                        let buffer = consumer.filter(packet);
+
+                                        for (stream, lost) in [
+                                            (StreamType::Audio, consumer.take_audio_lost()),
+                                            (StreamType::Video, consumer.take_video_lost()),
+                                        ] {
+                                            if lost > 0 && !sinker.lost(stream, lost) {
+                                                reason = CloseReason::Closed;
+                                                break 'connection;
+                                            }
+                                        }
 
-                                // Process received data
-                                if let Some(packet) = decoder.decode(&bytes[..size]) {
-                                    if let Some(buffer) = consumer.filter(packet) {
-                                        if !sinker.sink(buffer) {
-                                            break;
+                                        if let Some(buffer) = buffer {
+                                            if buffer.ty == BufferType::Clock {
+                                                clock_.response(&buffer, received);
+                                                continue;
+                                            }
+
+                                            if buffer.ty == BufferType::EndOfStream {
+                                                tracing::info!(
+                                                    "transport receiver end of stream, addr={}",
+                                                    addr
+                                                );
+
+                                                reason = CloseReason::RemoteClosed;
+                                                break 'connection;
+                                            }
+
+                                            if !recv(&mut sinker, buffer) {
+                                                reason = CloseReason::Closed;
+                                                break 'connection;
+                                            }
                                         }
                                     }
                                 }
+                                Err(e) => {
+                                    tracing::error!("{:?}", e);
+                                    break;
+                                }
                             }
-                            Err(e) => {
-                                log::error!("{:?}", e);
-                                break;
+                        }
+
+                        if closed_.load(Ordering::Relaxed) {
+                            reason = CloseReason::Closed;
+                            break;
+                        }
+
+                        let Some(reconnect) = reconnect.as_ref() else {
+                            break;
+                        };
+
+                        let mut backoff = Duration::from_millis(reconnect.backoff);
+                        for attempt in 1..=reconnect.max_retries {
+                            if closed_.load(Ordering::Relaxed) || !sinker.reconnecting(attempt) {
+                                reason = CloseReason::Closed;
+                                break 'connection;
+                            }
+
+                            tracing::warn!(
+                                "transport receiver reconnecting, addr={}, attempt={}",
+                                addr,
+                                attempt
+                            );
+
+                            thread::sleep(backoff);
+                            backoff = (backoff * 2).min(ReconnectOptions::MAX_BACKOFF);
+
+                            match SrtSocket::connect(addr, srt_options.clone()) {
+                                Ok(socket) => {
+                                    socket_.store(Arc::new(socket));
+
+                                    // The receiver may be dropped while connecting, the new
+                                    // socket was not closed by it.
+                                    if closed_.load(Ordering::Relaxed) {
+                                        socket_.load().close();
+
+                                        reason = CloseReason::Closed;
+                                        break 'connection;
+                                    }
+
+                                    tracing::info!("transport receiver reconnected, addr={}", addr);
+
+                                    // The sender may have been restarted with another clock.
+                                    clock_.reset();
+
+                                    sinker.reconnected();
+                                    continue 'connection;
+                                }
+                                Err(e) => {
+                                    tracing::warn!(
+                                        "transport receiver reconnect failed, err={:?}",
+                                        e
+                                    );
+                                }
                             }
                         }
+
+                        break;
                     }
 
-                    log::warn!("transport receiver is closed, addr={}", addr);
+                    tracing::warn!(
+                        "transport receiver is closed, addr={}, reason={:?}",
+                        addr,
+                        reason
+                    );
 
-                    sinker.close();
+                    sinker.close(reason);
                 })?;
 
-            Ok(Self { socket })
+            Ok(Self {
+                socket: Some(socket),
+                rendition,
+                closed,
+                clock,
+            })
+        }
+
+        /// The clock sync with the sender, the timestamps of the buffers are on
+        /// the clock of the sender, see `common::clock`.
+        pub fn clock(&self) -> ClockSync {
+            self.clock.clone()
+        }
+
+        /// The rendition of the simulcast video that the receiver plays, it is
+        /// kept across the reconnections. The sender only sends the subscribed
+        /// renditions to a connection.
+        pub fn rendition(&self) -> RenditionSubscription {
+            self.rendition.clone()
         }
     }
 
     impl Drop for Receiver {
         fn drop(&mut self) {
-            log::info!("transport receiver is drop");
+            tracing::info!("transport receiver is drop");
+
+            self.closed.store(true, Ordering::Relaxed);
 
-            self.socket.close();
+            if let Some(socket) = self.socket.as_ref() {
+                socket.load().close();
+            }
         }
     }
 }
 
 mod sender {
     use std::{
+        collections::HashSet,
         io::{Error, ErrorKind, Result},
         net::SocketAddr,
         sync::{
-            Arc,
+            Arc, Weak,
             atomic::{AtomicBool, Ordering},
         },
         thread,
+        time::Duration,
     };
 
-    use arc_swap::ArcSwapOption;
-    use bytes::BytesMut;
+    use arc_swap::ArcSwap;
+    use bytes::{Bytes, BytesMut};
     use parking_lot::Mutex;
+    use smallvec::SmallVec;
 
     use super::{
-        Buffer, TransportOptions,
+        Buffer, BufferPool, BufferType, MAX_RENDITIONS, PROTOCOL_VERSION, StreamType,
+        TransportOptions, TransportStrategy, clock,
         filter::StreamProducer,
-        protocol::{FragmentEncoder, SRT_TRACEBSTATS, SrtOptions, SrtServer, SrtSocket},
+        loopback::Endpoint,
+        pacer::{PacedSender, PacingStats},
+        protocol::{FragmentEncoder, SRT_TRACEBSTATS, SrtServer, SrtSocket},
+        simulcast::{Subscriptions, is_wanted_by_any},
+        stream_id::{StreamId, StreamMode},
+        viewers::Viewers,
     };
 
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+
+    pub(crate) type ConnectedCallback = Box<dyn Fn(SocketAddr) + Send + Sync>;
+    pub(crate) type AccessControl = Box<dyn Fn(SocketAddr) -> bool + Send + Sync>;
+
+    /// The statistics of a connection to a receiver, see
+    /// [Sender::connections], the counters are the totals since the
+    /// connection was made.
+    #[derive(Debug, Clone, Copy)]
+    #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+    pub struct ConnectionStats {
+        /// The address of the receiver, or of the relay in front of it.
+        pub addr: SocketAddr,
+        /// The round trip time in milliseconds.
+        pub rtt: f64,
+        /// The rate that the socket sends at in bits per second, this includes
+        /// the fec, the retransmissions and the headers.
+        pub send_rate: f64,
+        /// The estimated capacity of the link in bits per second.
+        pub bandwidth: f64,
+        /// The packets sent, without the retransmissions.
+        pub sent: u64,
+        /// The packets reported lost by the receiver.
+        pub lost: u64,
+        /// The packets sent again after they were lost.
+        pub retransmitted: u64,
+        /// The packets dropped because they were too late to be sent.
+        pub dropped: u64,
+        /// The time since the connection was made.
+        pub uptime: Duration,
+    }
+
+    impl ConnectionStats {
+        fn new(addr: SocketAddr, stats: &SRT_TRACEBSTATS) -> Self {
+            Self {
+                rtt: stats.msRTT,
+                send_rate: stats.mbpsSendRate * 1_000_000.0,
+                bandwidth: stats.mbpsBandwidth * 1_000_000.0,
+                sent: stats.pktSentUniqueTotal.max(0) as u64,
+                lost: stats.pktSndLossTotal.max(0) as u64,
+                retransmitted: stats.pktRetransTotal.max(0) as u64,
+                dropped: stats.pktSndDropTotal.max(0) as u64,
+                uptime: Duration::from_millis(stats.msTimeStamp.max(0) as u64),
+                addr,
+            }
+        }
+    }
+
+    /// A socket accepted by the sender, the receiver or the relay in front of
+    /// the receivers.
+    struct Connection {
+        socket: Arc<SrtSocket>,
+        /// The renditions of the video that the receivers of the connection
+        /// subscribe to.
+        subscriptions: Subscriptions,
+        /// The packets are paced on a thread of their own if the bandwidth is
+        /// capped, see [TransportOptions::payload_bandwidth].
+        pacer: Option<PacedSender>,
+    }
+
+    impl Connection {
+        /// The new connection starts with everything until its receivers
+        /// subscribe.
+        fn new(socket: SrtSocket, rate: Option<u64>) -> Result<Arc<Self>> {
+            let socket = Arc::new(socket);
+
+            Ok(Arc::new(Self {
+                pacer: rate
+                    .map(|it| PacedSender::new(it, socket.clone()))
+                    .transpose()?,
+                subscriptions: Subscriptions::default(),
+                socket,
+            }))
+        }
+
+        fn send(&self, packets: &[BytesMut]) {
+            if let Some(pacer) = self.pacer.as_ref() {
+                // The pacer thread sends the packets, and closes the socket if that
+                // fails.
+                if !pacer.send(packets) {
+                    tracing::debug!("transport drop a buffer, the pacer queue is full");
+                }
+            } else if let Err(e) = self.socket.send_batch(packets) {
+                tracing::warn!("transport failed to send data with srt socket, err={:?}", e);
+
+                // The control thread of the connection ends with the socket and
+                // removes the connection.
+                self.socket.close();
+            }
+        }
+    }
+
+    /// The connections of the sender, each accepted socket is kept until it is
+    /// closed, the packets are sent to all of them.
+    #[derive(Default)]
+    struct Connections(ArcSwap<Vec<Arc<Connection>>>);
+
+    impl Connections {
+        fn load(&self) -> Arc<Vec<Arc<Connection>>> {
+            self.0.load_full()
+        }
+
+        fn add(&self, connection: Arc<Connection>) -> usize {
+            let previous = self.0.rcu(|it| {
+                let mut it = it.to_vec();
+                it.push(connection.clone());
+                it
+            });
+
+            previous.len() + 1
+        }
+
+        fn remove(&self, connection: &Arc<Connection>) -> usize {
+            let previous = self.0.rcu(|it| {
+                it.iter()
+                    .filter(|it| !Arc::ptr_eq(it, connection))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            });
+
+            previous.len().saturating_sub(1)
+        }
+
+        fn clear(&self) -> Arc<Vec<Arc<Connection>>> {
+            self.0.swap(Default::default())
+        }
+
+        fn is_wanted(&self, rendition: u8) -> bool {
+            is_wanted_by_any(self.0.load().iter().map(|it| &it.subscriptions), rendition)
+        }
+    }
+
     /// Handles sending data over SRT protocol
     pub struct Sender {
+        connected: Arc<Mutex<Option<ConnectedCallback>>>,
+        access: Arc<Mutex<Option<AccessControl>>>,
+        viewers: Arc<Viewers>,
         working: Arc<AtomicBool>,
         producer: StreamProducer,
-        encoder: Mutex<FragmentEncoder>,
-        socket: Arc<ArcSwapOption<SrtSocket>>,
-        server: Arc<SrtServer>,
+        /// Shared with the threads that answer the clock sync of the receivers.
+        encoder: Arc<Mutex<FragmentEncoder>>,
+        /// The paced rate of each connection, `None` if the bandwidth is not
+        /// capped.
+        rate: Option<u64>,
+        connections: Arc<Connections>,
+        /// There is no server with the loopback strategy, the packets are
+        /// passed to the endpoint instead.
+        server: Option<Arc<SrtServer>>,
+        endpoint: Option<Arc<Endpoint>>,
         address: SocketAddr,
     }
 
     impl Sender {
+        /// The accepted sockets waiting for the accept thread.
+        const BACKLOG: u32 = 16;
+
+        /// The wait before the publisher connects to the relay again.
+        const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
         /// Creates a new sender with specified options
         /// Initializes SRT server and spawns thread for connection handling
         pub fn new(bind: SocketAddr, options: TransportOptions) -> Result<Self> {
-            log::info!("transport create sender, bind={}", bind);
+            tracing::info!("transport create sender, bind={}", bind);
+
+            options
+                .validate()
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
 
             let working = Arc::new(AtomicBool::new(true));
-            let socket: Arc<ArcSwapOption<SrtSocket>> = Default::default();
+            let connections: Arc<Connections> = Default::default();
+            let connected: Arc<Mutex<Option<ConnectedCallback>>> = Default::default();
+            let access: Arc<Mutex<Option<AccessControl>>> = Default::default();
+            let viewers: Arc<Viewers> = Default::default();
+
+            if options.strategy == TransportStrategy::Loopback {
+                let endpoint = Endpoint::bind(
+                    bind,
+                    &options,
+                    connected.clone(),
+                    access.clone(),
+                    viewers.clone(),
+                )?;
+
+                return Ok(Self {
+                    encoder: Arc::new(Mutex::new(FragmentEncoder::new(options.mtu as usize))),
+                    producer: Default::default(),
+                    rate: None,
+                    address: endpoint.local_addr(),
+                    endpoint: Some(endpoint),
+                    server: None,
+                    connections,
+                    connected,
+                    viewers,
+                    access,
+                    working,
+                });
+            }
+
+            let session = options.session.clone();
+            let resource = options.resource.clone();
+            let rate = options.payload_bandwidth();
 
             // Initialize SRT server with optimized settings
-            let server = Arc::new(SrtServer::bind(
-                bind,
-                {
-                    let mut opt = SrtOptions::default();
-                    opt.max_bandwidth = options.max_bandwidth;
-                    opt.timeout = options.timeout;
-                    opt.latency = options.latency;
-                    opt.fec = options.fec;
-                    opt.mtu = options.mtu;
-                    opt.fc = options.fc;
-
-                    opt
-                },
-                1,
-            )?);
+            let server = Arc::new(SrtServer::bind(bind, options.srt_options(), Self::BACKLOG)?);
 
             let address = server
                 .local_addr()
                 .ok_or_else(|| Error::new(ErrorKind::AddrNotAvailable, ""))?;
 
+            let encoder = Arc::new(Mutex::new(FragmentEncoder::new(options.mtu as usize)));
+
+            // The heartbeats of a receiver may stop while its connection is still open.
+            Viewers::start_expiry(&viewers)?;
+
             // Spawn server thread for connection handling
             let working_ = working.clone();
             let server_ = server.clone();
-            let socket_ = Arc::downgrade(&socket);
+            let connections_ = Arc::downgrade(&connections);
+            let connected_ = connected.clone();
+            let access_ = access.clone();
+            let encoder_ = encoder.clone();
+            let viewers_ = viewers.clone();
             thread::Builder::new()
                 .name("HylaranaTransportSenderThread".to_string())
                 .spawn(move || {
                     while let Ok((socket, addr)) = server_.accept() {
-                        if let Some(srt_socket) = socket_.upgrade() {
-                            srt_socket.store(Some(Arc::new(socket)));
+                        // Only the subscribers of this stream are accepted, the stream id
+                        // is not checked for the keys that the sender does not set. The
+                        // receivers of another version, or of the versions before the
+                        // version was sent, can not read the packets.
+                        let stream_id =
+                            StreamId::from(socket.stream_id().unwrap_or_default().as_str());
+                        if (resource.is_some() && stream_id.resource != resource)
+                            || (session.is_some() && stream_id.session != session)
+                            || stream_id.version != Some(PROTOCOL_VERSION)
+                            || stream_id.mode == Some(StreamMode::Publish)
+                        {
+                            tracing::warn!(
+                                "transport reject a wrong stream id, addr={}, stream id={}",
+                                addr,
+                                stream_id
+                            );
+
+                            continue;
+                        }
+
+                        // The rejected socket is closed when it is dropped, the other
+                        // receivers are not affected.
+                        if !access_.lock().as_ref().map(|it| it(addr)).unwrap_or(true) {
+                            tracing::warn!("transport srt server reject a socket, addr={}", addr);
+
+                            continue;
+                        }
 
-                            log::info!("transport srt server accept a socket, addr={}", addr);
-                        } else {
+                        let Some(connections) = connections_.upgrade() else {
                             break;
+                        };
+
+                        let connection = match Connection::new(socket, rate) {
+                            Ok(it) => it,
+                            Err(e) => {
+                                tracing::warn!(
+                                    "transport failed to start the pacer thread, err={:?}",
+                                    e
+                                );
+
+                                continue;
+                            }
+                        };
+
+                        let count = connections.add(connection.clone());
+
+                        tracing::info!(
+                            "transport srt server accept a socket, addr={}, connections={}",
+                            addr,
+                            count
+                        );
+
+                        if let Err(e) = thread::Builder::new()
+                            .name("HylaranaTransportControlThread".to_string())
+                            .spawn({
+                                let connection = connection.clone();
+                                let connections = Arc::downgrade(&connections);
+                                let encoder = encoder_.clone();
+                                let viewers = viewers_.clone();
+
+                                move || control(connection, connections, encoder, viewers)
+                            })
+                        {
+                            tracing::warn!(
+                                "transport failed to start the control thread, err={:?}",
+                                e
+                            );
+
+                            connections.remove(&connection);
+
+                            continue;
+                        }
+
+                        if let Some(callback) = connected_.lock().as_ref() {
+                            callback(addr);
                         }
                     }
 
-                    log::info!("transport srt server is closed, addr={}", address);
+                    tracing::info!("transport srt server is closed, addr={}", address);
 
                     working_.store(false, Ordering::Relaxed);
                 })?;
 
             Ok(Self {
-                encoder: Mutex::new(FragmentEncoder::new(options.mtu as usize)),
                 producer: Default::default(),
+                server: Some(server),
+                endpoint: None,
+                connections,
+                connected,
+                viewers,
+                encoder,
+                access,
                 address,
                 working,
-                socket,
-                server,
+                rate,
             })
         }
 
-        /// Calculates and returns the packet loss rate
+        /// Creates a sender that publishes the stream to a srt relay, such as
+        /// srt-live-transmit or SLS, instead of listening for the receivers,
+        /// the receivers request the stream from the relay by the resource of
+        /// the options, which is required. The connection to the relay is made
+        /// again until the sender is dropped, and the connected callback is
+        /// called with the address of the relay each time.
+        ///
+        /// The relay does not pass the heartbeats and the clock sync of the
+        /// receivers back, so the viewers are not counted, all renditions are
+        /// sent, and the receivers behind the relay can not be in a sync
+        /// group.
+        pub fn publish(relay: SocketAddr, options: TransportOptions) -> Result<Self> {
+            tracing::info!("transport create publisher, relay={}", relay);
+
+            options
+                .validate()
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+            if options.strategy != TransportStrategy::Srt {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "only the srt strategy publishes to a relay",
+                ));
+            }
+
+            let srt_options = {
+                let mut opt = options.srt_options();
+                opt.stream_id = Some(
+                    StreamId {
+                        resource: Some(options.resource.clone().ok_or_else(|| {
+                            Error::new(
+                                ErrorKind::InvalidInput,
+                                "the resource is required to publish to a relay",
+                            )
+                        })?),
+                        mode: Some(StreamMode::Publish),
+                        session: options.session.clone(),
+                        version: Some(PROTOCOL_VERSION),
+                    }
+                    .to_string(),
+                );
+
+                opt
+            };
+
+            let working = Arc::new(AtomicBool::new(true));
+            let connections: Arc<Connections> = Default::default();
+            let connected: Arc<Mutex<Option<ConnectedCallback>>> = Default::default();
+            let viewers: Arc<Viewers> = Default::default();
+            let encoder = Arc::new(Mutex::new(FragmentEncoder::new(options.mtu as usize)));
+            let rate = options.payload_bandwidth();
+
+            let working_ = working.clone();
+            let connections_ = Arc::downgrade(&connections);
+            let connected_ = connected.clone();
+            let encoder_ = encoder.clone();
+            let viewers_ = viewers.clone();
+            thread::Builder::new()
+                .name("HylaranaTransportPublishThread".to_string())
+                .spawn(move || {
+                    loop {
+                        match SrtSocket::connect(relay, srt_options.clone())
+                            .and_then(|it| Connection::new(it, rate))
+                        {
+                            Ok(connection) => {
+                                let Some(connections) = connections_.upgrade() else {
+                                    break;
+                                };
+
+                                connections.add(connection.clone());
+
+                                tracing::info!("transport publish to the relay, addr={}", relay);
+
+                                if let Some(callback) = connected_.lock().as_ref() {
+                                    callback(relay);
+                                }
+
+                                // The connection is removed once the socket is closed.
+                                control(
+                                    connection,
+                                    Arc::downgrade(&connections),
+                                    encoder_.clone(),
+                                    viewers_.clone(),
+                                );
+                            }
+                            Err(e) => {
+                                tracing::warn!(
+                                    "transport failed to connect to the relay, addr={}, err={:?}",
+                                    relay,
+                                    e
+                                );
+                            }
+                        }
+
+                        if connections_.strong_count() == 0 {
+                            break;
+                        }
+
+                        thread::sleep(Self::RECONNECT_INTERVAL);
+                    }
+
+                    tracing::info!("transport publisher is closed, relay={}", relay);
+
+                    working_.store(false, Ordering::Relaxed);
+                })?;
+
+            Ok(Self {
+                producer: Default::default(),
+                access: Default::default(),
+                server: None,
+                endpoint: None,
+                address: relay,
+                connections,
+                connected,
+                viewers,
+                encoder,
+                working,
+                rate,
+            })
+        }
+
+        /// Sets the callback called with the address of the receiver when a
+        /// receiver is connected, this replaces the previous callback.
+        pub fn set_connected_callback<F>(&self, callback: F)
+        where
+            F: Fn(SocketAddr) + Send + Sync + 'static,
+        {
+            self.connected.lock().replace(Box::new(callback));
+        }
+
+        /// Sets the callback that decides whether a receiver may connect, it is
+        /// called with the address of the receiver before the receiver is
+        /// accepted, this replaces the previous callback. All receivers are
+        /// accepted if there is no callback.
+        pub fn set_access_control<F>(&self, callback: F)
+        where
+            F: Fn(SocketAddr) -> bool + Send + Sync + 'static,
+        {
+            self.access.lock().replace(Box::new(callback));
+        }
+
+        /// Sets the callback called with the number of the receivers when a
+        /// receiver joins or leaves, this replaces the previous callback.
+        pub fn set_viewers_callback<F>(&self, callback: F)
+        where
+            F: Fn(u32) + Send + Sync + 'static,
+        {
+            self.viewers.set_callback(Box::new(callback));
+        }
+
+        /// Returns the number of the receivers of all connections, a relay
+        /// may carry several receivers on one connection.
+        pub fn get_viewers(&self) -> u32 {
+            self.viewers.count()
+        }
+
+        /// Tells the connected receivers that the stream has ended, so that
+        /// they do not treat the following disconnection as a network failure.
+        /// This is also done when the sender is dropped.
+        pub fn end_of_stream(&self) {
+            if let Err(e) = self.send(Buffer {
+                data: Buffer::<BytesMut>::with_capacity(0),
+                stream: StreamType::Video,
+                ty: BufferType::EndOfStream,
+                rendition: 0,
+                timestamp: 0,
+            }) {
+                tracing::warn!("transport failed to send end of stream, err={:?}", e);
+            }
+        }
+
+        /// Calculates and returns the packet loss rate of all connections
         /// Returns a value between 0.0 and 1.0
         pub fn get_pkt_lose_rate(&self) -> f64 {
-            if let Some(socket) = self.socket.load().as_ref() {
-                if let Ok(SRT_TRACEBSTATS {
-                    pktSndDrop,
-                    pktSentUnique,
-                    ..
-                }) = socket.get_stats()
-                {
-                    log::info!(
-                        "transport pkt send drop={}, send count={}",
-                        pktSndDrop,
-                        pktSentUnique
-                    );
+            let (dropped, sent) = self
+                .connections
+                .load()
+                .iter()
+                .filter_map(|it| it.socket.get_stats().ok())
+                .fold((0, 0), |(dropped, sent), it| {
+                    (dropped + it.pktSndDrop, sent + it.pktSentUnique)
+                });
+
+            if sent == 0 {
+                return 0.0;
+            }
 
-                    return (pktSndDrop as f64 / pktSentUnique as f64 * 10.0).floor() / 10.0;
-                }
+            tracing::info!("transport pkt send drop={}, send count={}", dropped, sent);
+
+            (dropped as f64 / sent as f64 * 10.0).floor() / 10.0
+        }
+
+        /// Returns the highest round trip time of the connected receivers in
+        /// milliseconds, `None` if there is no receiver.
+        pub fn get_rtt(&self) -> Option<f64> {
+            self.connections
+                .load()
+                .iter()
+                .filter_map(|it| it.socket.get_stats().ok())
+                .map(|it| it.msRTT)
+                .reduce(f64::max)
+        }
+
+        /// Returns the highest rate that a socket sends at in bits per second,
+        /// this includes the fec, the retransmissions and the headers, the cap
+        /// applies to each socket, see [Sender::connections] for each of
+        /// them. `None` if there is no receiver.
+        pub fn get_send_rate(&self) -> Option<f64> {
+            self.connections
+                .load()
+                .iter()
+                .filter_map(|it| it.socket.get_stats().ok())
+                .map(|it| it.mbpsSendRate * 1_000_000.0)
+                .reduce(f64::max)
+        }
+
+        /// Returns the statistics of each connected receiver, none with the
+        /// loopback strategy. The interval counters of the sockets are not
+        /// reset by this.
+        pub fn connections(&self) -> Vec<ConnectionStats> {
+            self.connections
+                .load()
+                .iter()
+                .filter_map(|it| {
+                    Some(ConnectionStats::new(
+                        it.socket.peer_addr()?,
+                        &it.socket.get_total_stats().ok()?,
+                    ))
+                })
+                .collect()
+        }
+
+        /// Whether the rendition of the video is sent to anyone, the renditions
+        /// that no receiver plays need not be encoded. The loopback receivers
+        /// pick the rendition themselves, so everything is wanted.
+        pub fn is_rendition_wanted(&self, rendition: u8) -> bool {
+            if self.endpoint.is_some() {
+                return true;
             }
 
-            0.0
+            self.connections.is_wanted(rendition)
+        }
+
+        /// Returns the counters of the pacing, `None` if the bandwidth is not
+        /// capped.
+        pub fn get_pacing_stats(&self) -> Option<PacingStats> {
+            let rate = self.rate?;
+
+            Some(
+                self.connections
+                    .load()
+                    .iter()
+                    .filter_map(|it| it.pacer.as_ref())
+                    .map(|it| it.stats())
+                    .fold(
+                        PacingStats {
+                            rate,
+                            ..Default::default()
+                        },
+                        |mut stats, it| {
+                            stats.bytes += it.bytes;
+                            stats.waited += it.waited;
+                            stats.dropped += it.dropped;
+                            stats
+                        },
+                    ),
+            )
         }
 
-        /// Sends data through the SRT connection
+        /// Sends data through the SRT connections
         /// Handles data fragmentation and error recovery
         pub fn send(&self, buffer: Buffer<BytesMut>) -> Result<()> {
+            let _span = tracing::trace_span!(
+                "send",
+                stream = ?buffer.stream,
+                ty = ?buffer.ty,
+                timestamp = buffer.timestamp
+            )
+            .entered();
+
             if !self.working.load(Ordering::Relaxed) {
                 return Err(Error::new(ErrorKind::NetworkDown, "srt server is closed"));
             }
 
+            if buffer.rendition >= MAX_RENDITIONS {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid rendition: {}", buffer.rendition),
+                ));
+            }
+
             if buffer.data.is_empty() {
                 return Ok(());
             }
 
-            let mut is_close = false;
-            {
-                let socket = self.socket.load();
-                let mut encoder = self.encoder.lock();
-
-                // Process and send each filtered buffer
-                for buffer in self.producer.filter(buffer) {
-                    if let Some(socket) = socket.as_ref() {
-                        for chunk in encoder.encode(&buffer) {
-                            if let Err(e) = socket.send(chunk) {
-                                log::warn!(
-                                    "transport failed to send data with srt current socket, err={:?}",
-                                    e
-                                );
-
-                                is_close = true;
-                                break;
-                            }
-                        }
-                    } else {
-                        break;
+            // Each connection takes the renditions of its receivers, the loopback
+            // receivers pick the rendition themselves.
+            let connections = self.connections.load();
+            let targets = connections
+                .iter()
+                .filter(|it| {
+                    it.subscriptions
+                        .is_sent(buffer.stream, buffer.ty, buffer.rendition)
+                })
+                .collect::<SmallVec<[_; 4]>>();
+
+            let mut encoder = self.encoder.lock();
+
+            // Process and send each filtered buffer, the buffers are returned to the
+            // pool once they are sent. The packets are encoded once for all
+            // connections.
+            for buffer in self.producer.filter(buffer) {
+                if let Some(endpoint) = self.endpoint.as_ref() {
+                    endpoint.send(&buffer);
+                } else if !targets.is_empty() {
+                    let packets = encoder.encode(&buffer);
+                    for connection in &targets {
+                        connection.send(packets);
                     }
                 }
-            }
 
-            if is_close {
-                self.socket.store(None);
+                BufferPool::global().recycle(buffer);
             }
 
             Ok(())
         }
 
+        /// The address the sender is bound to, or the address of the relay if
+        /// the sender publishes to a relay.
         pub fn local_addr(&self) -> SocketAddr {
             self.address
         }
@@ -445,9 +1350,90 @@ mod sender {
 
     impl Drop for Sender {
         fn drop(&mut self) {
-            log::info!("transport sender is drop");
+            tracing::info!("transport sender is drop");
+
+            self.end_of_stream();
+
+            if let Some(server) = self.server.as_ref() {
+                server.close();
+            }
+
+            // The sockets are also held by their control threads.
+            for connection in self.connections.clear().iter() {
+                connection.socket.close();
+            }
+        }
+    }
+
+    /// Answers the clock sync and takes the heartbeats of the receivers until
+    /// the socket is closed, the receivers heard on the socket leave with it,
+    /// and the connection is removed.
+    fn control(
+        connection: Arc<Connection>,
+        connections: Weak<Connections>,
+        encoder: Arc<Mutex<FragmentEncoder>>,
+        viewers: Arc<Viewers>,
+    ) {
+        let Connection {
+            socket,
+            subscriptions,
+            ..
+        } = connection.as_ref();
+
+        let mut bytes = [0u8; 4096];
+        let mut heard = HashSet::new();
+
+        while let Ok(size) = socket.read(&mut bytes) {
+            if size == 0 {
+                break;
+            }
+
+            let received = common::clock::now();
+
+            // The messages of the receiver fit in one fragment.
+            if size <= FragmentEncoder::HEAD_SIZE {
+                continue;
+            }
+
+            let Ok((_, message)) = Buffer::<Bytes>::decode(Bytes::copy_from_slice(
+                &bytes[FragmentEncoder::HEAD_SIZE..size],
+            )) else {
+                continue;
+            };
+
+            match message.ty {
+                BufferType::Heartbeat => {
+                    if let Some(id) = viewers.heartbeat(&message) {
+                        subscriptions.heartbeat(id, message.rendition);
+                        heard.insert(id);
+                    }
+                }
+                BufferType::Clock => {
+                    // The response must not be sent between the fragments of a media
+                    // message.
+                    let mut encoder = encoder.lock();
+                    if let Err(e) =
+                        socket.send_batch(encoder.encode(&clock::respond(&message, received)))
+                    {
+                        tracing::warn!("transport failed to answer the clock sync, err={:?}", e);
+
+                        break;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        viewers.leave(heard);
+
+        if let Some(connections) = connections.upgrade() {
+            let count = connections.remove(&connection);
 
-            self.server.close();
+            tracing::info!(
+                "transport srt connection is closed, addr={:?}, connections={}",
+                socket.peer_addr(),
+                count
+            );
         }
     }
 }
@@ -0,0 +1,237 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    net::{Ipv4Addr, SocketAddr},
+    sync::{
+        Arc, LazyLock, Weak,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::{
+    BufferType, CloseReason, TransportOptions,
+    filter::StreamConsumer,
+    receiver::{ReceiverSink, recv},
+    sender::{AccessControl, ConnectedCallback},
+    simulcast::RenditionSubscription,
+    viewers::{Viewers, viewer_id},
+};
+
+/// The loopback senders of this process by their port.
+static ENDPOINTS: LazyLock<Mutex<HashMap<u16, Weak<Endpoint>>>> = LazyLock::new(Default::default);
+
+static PORTS: AtomicU32 = AtomicU32::new(0);
+
+/// Takes a port from the dynamic range, for the senders bound to port 0 and
+/// as the address of the receivers.
+fn next_port() -> u16 {
+    49152 + (PORTS.fetch_add(1, Ordering::Relaxed) % 16384) as u16
+}
+
+/// The loopback sender, the encoded packets are passed to the receivers
+/// through channels instead of sockets.
+pub(crate) struct Endpoint {
+    port: u16,
+    session: Option<String>,
+    resource: Option<String>,
+    connected: Arc<Mutex<Option<ConnectedCallback>>>,
+    access: Arc<Mutex<Option<AccessControl>>>,
+    viewers: Arc<Viewers>,
+    subscribers: Mutex<Vec<mpsc::Sender<Bytes>>>,
+}
+
+impl Endpoint {
+    pub(crate) fn bind(
+        bind: SocketAddr,
+        options: &TransportOptions,
+        connected: Arc<Mutex<Option<ConnectedCallback>>>,
+        access: Arc<Mutex<Option<AccessControl>>>,
+        viewers: Arc<Viewers>,
+    ) -> Result<Arc<Self>> {
+        let mut endpoints = ENDPOINTS.lock();
+        endpoints.retain(|_, it| it.strong_count() > 0);
+
+        let port = if bind.port() == 0 {
+            loop {
+                let port = next_port();
+                if !endpoints.contains_key(&port) {
+                    break port;
+                }
+            }
+        } else if endpoints.contains_key(&bind.port()) {
+            return Err(Error::new(
+                ErrorKind::AddrInUse,
+                "the port is used by another loopback sender",
+            ));
+        } else {
+            bind.port()
+        };
+
+        let endpoint = Arc::new(Self {
+            session: options.session.clone(),
+            resource: options.resource.clone(),
+            subscribers: Default::default(),
+            connected,
+            viewers,
+            access,
+            port,
+        });
+
+        endpoints.insert(port, Arc::downgrade(&endpoint));
+
+        tracing::info!("transport loopback sender is bound, port={}", port);
+
+        Ok(endpoint)
+    }
+
+    /// The address of a loopback sender is always the loopback address.
+    pub(crate) fn local_addr(&self) -> SocketAddr {
+        SocketAddr::new(Ipv4Addr::LOCALHOST.into(), self.port)
+    }
+
+    /// Passes the packet to all receivers, a receiver is removed once it is
+    /// closed. All renditions of the video are passed, each receiver picks
+    /// its rendition.
+    pub(crate) fn send(&self, packet: &Bytes) {
+        self.subscribers.lock().retain(|tx| {
+            if tx.send(packet.clone()).is_err() {
+                tracing::info!("transport loopback receiver is gone, port={}", self.port);
+
+                return false;
+            }
+
+            true
+        });
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        let mut endpoints = ENDPOINTS.lock();
+
+        // The port may already be taken by a new sender.
+        if endpoints
+            .get(&self.port)
+            .map(|it| it.strong_count() == 0)
+            .unwrap_or(false)
+        {
+            endpoints.remove(&self.port);
+        }
+    }
+}
+
+/// Connects a receiver to the loopback sender on the port of the address, it
+/// receives the packets next to the other receivers of the sender like a new
+/// SRT connection does. The packets are passed to the sink on a thread of the receiver, the
+/// returned flag closes the receiver.
+pub(crate) fn subscribe<S: ReceiverSink + 'static>(
+    addr: SocketAddr,
+    options: TransportOptions,
+    rendition: RenditionSubscription,
+    mut sinker: S,
+) -> Result<Arc<AtomicBool>> {
+    let endpoint = ENDPOINTS
+        .lock()
+        .get(&addr.port())
+        .and_then(Weak::upgrade)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::ConnectionRefused,
+                "there is no loopback sender on the port",
+            )
+        })?;
+
+    // The same checks as the stream id of a SRT connection.
+    if (endpoint.resource.is_some() && options.resource != endpoint.resource)
+        || (endpoint.session.is_some() && options.session != endpoint.session)
+    {
+        tracing::warn!("transport loopback reject a wrong stream id, addr={}", addr);
+
+        return Err(Error::new(ErrorKind::ConnectionRefused, "wrong stream id"));
+    }
+
+    let peer = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), next_port());
+    if !endpoint
+        .access
+        .lock()
+        .as_ref()
+        .map(|it| it(peer))
+        .unwrap_or(true)
+    {
+        tracing::warn!("transport loopback sender reject a receiver, addr={}", peer);
+
+        return Err(Error::new(
+            ErrorKind::ConnectionRefused,
+            "the receiver is rejected by the sender",
+        ));
+    }
+
+    let (tx, rx) = mpsc::channel::<Bytes>();
+    let closed = Arc::new(AtomicBool::new(false));
+
+    // The receiver is counted while its thread runs, it sends no heartbeats.
+    let id = viewer_id();
+    let viewers = endpoint.viewers.clone();
+    viewers.join(id);
+
+    let closed_ = closed.clone();
+    thread::Builder::new()
+        .name("HylaranaTransportLoopbackThread".to_string())
+        .spawn(move || {
+            // The packets are filtered as they are on a SRT connection, so the receiver
+            // still starts from the configuration and the key frame.
+            let mut consumer = StreamConsumer::default();
+            let mut reason = CloseReason::NetworkTimeout;
+
+            while !closed_.load(Ordering::Relaxed) {
+                let packet = match rx.recv_timeout(Duration::from_millis(100)) {
+                    Ok(packet) => packet,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+
+                consumer.set_rendition(rendition.get());
+                if let Some(buffer) = consumer.filter(packet) {
+                    if buffer.ty == BufferType::EndOfStream {
+                        reason = CloseReason::RemoteClosed;
+                        break;
+                    }
+
+                    if !recv(&mut sinker, buffer) {
+                        reason = CloseReason::Closed;
+                        break;
+                    }
+                }
+            }
+
+            if closed_.load(Ordering::Relaxed) {
+                reason = CloseReason::Closed;
+            }
+
+            tracing::warn!(
+                "transport loopback receiver is closed, addr={}, reason={:?}",
+                addr,
+                reason
+            );
+
+            viewers.leave([id]);
+            sinker.close(reason);
+        })
+        .inspect_err(|_| endpoint.viewers.leave([id]))?;
+
+    endpoint.subscribers.lock().push(tx);
+
+    tracing::info!("transport loopback sender accept a receiver, addr={}", peer);
+
+    if let Some(callback) = endpoint.connected.lock().as_ref() {
+        callback(peer);
+    }
+
+    Ok(closed)
+}
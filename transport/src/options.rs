@@ -0,0 +1,427 @@
+use std::fmt;
+
+use thiserror::Error;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{TransportOptions, TransportStrategy, protocol::SrtOptions};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TransportOptionsError {
+    #[error(
+        "mtu must be in {min}..={max}, mtu={0}",
+        min = TransportOptions::MIN_MTU,
+        max = TransportOptions::MAX_MTU
+    )]
+    InvalidMtu(u32),
+    #[error("latency must be at most {max} ms, latency={0}", max = TransportOptions::MAX_LATENCY)]
+    InvalidLatency(u32),
+    #[error("timeout must be greater than the latency, timeout={timeout}, latency={latency}")]
+    InvalidTimeout { timeout: u32, latency: u32 },
+    #[error("flow control window must be at least {min}, fc={0}", min = TransportOptions::MIN_FC)]
+    InvalidFlowControl(u32),
+    #[error(
+        "max bandwidth must be at least {min} bytes per second or -1 for unlimited, max_bandwidth={0}",
+        min = TransportOptions::MIN_BANDWIDTH
+    )]
+    InvalidMaxBandwidth(i64),
+    #[error("fec rows and cols must be at least 1, rows={rows}, cols={cols}")]
+    InvalidFecMatrix { rows: u16, cols: u16 },
+    #[error("invalid fec configuration: {0}")]
+    InvalidFec(String),
+    #[error(
+        "session must be 1..={max} alphanumeric characters or '-', session={0}",
+        max = TransportOptions::MAX_SESSION
+    )]
+    InvalidSession(String),
+    #[error(
+        "resource must be 1..={max} characters without ',' or '=', resource={0}",
+        max = TransportOptions::MAX_RESOURCE
+    )]
+    InvalidResource(String),
+    #[error(
+        "passphrase must be {min}..={max} characters",
+        min = TransportOptions::MIN_PASSPHRASE,
+        max = TransportOptions::MAX_PASSPHRASE
+    )]
+    InvalidPassphrase,
+}
+
+/// How the fec groups are arranged, see the packet filter documentation of
+/// srt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum FecLayout {
+    Even,
+    Staircase,
+}
+
+/// When the retransmission is used together with the fec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum FecArq {
+    /// Retransmit the lost packets at the same time as the fec recovers them.
+    Always,
+    /// Only retransmit the packets that the fec failed to recover.
+    OnRequest,
+    /// Rely on the fec only.
+    Never,
+}
+
+/// Forward Error Correction configuration, this is formatted as the packet
+/// filter string of srt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct FecOptions {
+    pub layout: FecLayout,
+    pub rows: u16,
+    pub cols: u16,
+    pub arq: FecArq,
+}
+
+impl Default for FecOptions {
+    fn default() -> Self {
+        Self {
+            layout: FecLayout::Staircase,
+            arq: FecArq::OnRequest,
+            rows: 2,
+            cols: 10,
+        }
+    }
+}
+
+impl fmt::Display for FecOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fec,layout:{},rows:{},cols:{},arq:{}",
+            match self.layout {
+                FecLayout::Even => "even",
+                FecLayout::Staircase => "staircase",
+            },
+            self.rows,
+            self.cols,
+            match self.arq {
+                FecArq::Always => "always",
+                FecArq::OnRequest => "onreq",
+                FecArq::Never => "never",
+            }
+        )
+    }
+}
+
+impl TryFrom<&str> for FecOptions {
+    type Error = TransportOptionsError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let invalid = || TransportOptionsError::InvalidFec(value.to_string());
+
+        let mut items = value.split(',');
+        if items.next() != Some("fec") {
+            return Err(invalid());
+        }
+
+        let mut options = Self::default();
+        for item in items {
+            let (key, value) = item.split_once(':').ok_or_else(invalid)?;
+
+            match key {
+                "layout" => {
+                    options.layout = match value {
+                        "even" => FecLayout::Even,
+                        "staircase" => FecLayout::Staircase,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "arq" => {
+                    options.arq = match value {
+                        "always" => FecArq::Always,
+                        "onreq" => FecArq::OnRequest,
+                        "never" => FecArq::Never,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "rows" => options.rows = value.parse().map_err(|_| invalid())?,
+                "cols" => options.cols = value.parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+
+        options.validate()?;
+        Ok(options)
+    }
+}
+
+impl FecOptions {
+    pub fn validate(&self) -> Result<(), TransportOptionsError> {
+        if self.rows == 0 || self.cols == 0 {
+            return Err(TransportOptionsError::InvalidFecMatrix {
+                rows: self.rows,
+                cols: self.cols,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The share of the packets that the fec adds to the stream, the row
+    /// groups add one packet for the columns, and the column groups one for
+    /// the rows, such as 0.6 for 2 rows and 10 columns.
+    pub fn overhead(&self) -> f64 {
+        let mut overhead = 1.0 / self.cols.max(1) as f64;
+        if self.rows > 1 {
+            overhead += 1.0 / self.rows as f64;
+        }
+
+        overhead
+    }
+}
+
+impl TransportOptions {
+    /// The fragment header and the srt header must fit in a packet, and srt
+    /// does not send packets larger than an ethernet frame.
+    pub const MIN_MTU: u32 = 576;
+    pub const MAX_MTU: u32 = 1500;
+
+    pub const MAX_LATENCY: u32 = 10000;
+
+    /// The smallest flow control window that srt accepts.
+    pub const MIN_FC: u32 = 32;
+
+    /// The session is carried in the stream id of srt, which is limited to
+    /// 512 bytes together with the other keys.
+    pub const MAX_SESSION: usize = 64;
+    pub const MAX_RESOURCE: usize = 256;
+
+    /// The limits of the passphrase of srt.
+    pub const MIN_PASSPHRASE: usize = 10;
+    pub const MAX_PASSPHRASE: usize = 79;
+
+    /// The smallest cap of the bandwidth in bytes per second, so that the
+    /// media left after the fec and the headers is paced at a sane rate.
+    pub const MIN_BANDWIDTH: i64 = 16_000;
+
+    /// The share of the bandwidth taken by the headers of srt, udp and ip.
+    const HEADER_OVERHEAD: f64 = 0.05;
+
+    /// The bytes per second left for the media when the bandwidth is capped,
+    /// the fec and the headers of the packets are taken off the max
+    /// bandwidth, `None` if the bandwidth is not capped.
+    pub fn payload_bandwidth(&self) -> Option<u64> {
+        if self.max_bandwidth <= 0 {
+            return None;
+        }
+
+        let fec = FecOptions::try_from(self.fec.as_str())
+            .map(|it| it.overhead())
+            .unwrap_or(0.0);
+
+        Some((self.max_bandwidth as f64 / (1.0 + fec + Self::HEADER_OVERHEAD)) as u64)
+    }
+
+    /// The options of the srt sockets of the sender, the receiver and the
+    /// probe, without the stream id.
+    pub(crate) fn srt_options(&self) -> SrtOptions {
+        let mut opt = SrtOptions::default();
+        opt.max_bandwidth = self.max_bandwidth;
+        opt.timeout = self.timeout;
+        opt.latency = self.latency;
+        opt.fec = self.fec.clone();
+        opt.mtu = self.mtu;
+        opt.fc = self.fc;
+        opt.passphrase = self.passphrase.clone();
+
+        opt
+    }
+
+    /// Check the options before they are passed to srt, which otherwise only
+    /// fails with an opaque error when the socket is created.
+    pub fn validate(&self) -> Result<(), TransportOptionsError> {
+        if !(Self::MIN_MTU..=Self::MAX_MTU).contains(&self.mtu) {
+            return Err(TransportOptionsError::InvalidMtu(self.mtu));
+        }
+
+        if self.latency > Self::MAX_LATENCY {
+            return Err(TransportOptionsError::InvalidLatency(self.latency));
+        }
+
+        if self.timeout <= self.latency {
+            return Err(TransportOptionsError::InvalidTimeout {
+                timeout: self.timeout,
+                latency: self.latency,
+            });
+        }
+
+        if self.fc < Self::MIN_FC {
+            return Err(TransportOptionsError::InvalidFlowControl(self.fc));
+        }
+
+        if self.max_bandwidth != -1 && self.max_bandwidth < Self::MIN_BANDWIDTH {
+            return Err(TransportOptionsError::InvalidMaxBandwidth(
+                self.max_bandwidth,
+            ));
+        }
+
+        // An empty string disables the fec.
+        if !self.fec.is_empty() {
+            FecOptions::try_from(self.fec.as_str())?;
+        }
+
+        if let Some(session) = &self.session {
+            if session.is_empty()
+                || session.len() > Self::MAX_SESSION
+                || !session
+                    .chars()
+                    .all(|it| it.is_ascii_alphanumeric() || it == '-')
+            {
+                return Err(TransportOptionsError::InvalidSession(session.clone()));
+            }
+        }
+
+        if let Some(resource) = &self.resource {
+            if resource.is_empty()
+                || resource.len() > Self::MAX_RESOURCE
+                || resource.contains([',', '='])
+            {
+                return Err(TransportOptionsError::InvalidResource(resource.clone()));
+            }
+        }
+
+        // The passphrase is not printed in the error.
+        if let Some(passphrase) = &self.passphrase {
+            if !(Self::MIN_PASSPHRASE..=Self::MAX_PASSPHRASE).contains(&passphrase.len()) {
+                return Err(TransportOptionsError::InvalidPassphrase);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Build the transport options with typed values, the values are checked when
+/// the options are built.
+#[derive(Debug, Clone, Default)]
+pub struct TransportOptionsBuilder(TransportOptions);
+
+impl TransportOptionsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum Transmission Unit size
+    pub fn mtu(mut self, mtu: u32) -> Self {
+        self.0.mtu = mtu;
+        self
+    }
+
+    /// Maximum bandwidth in bytes per second, `None` is unlimited.
+    pub fn max_bandwidth(mut self, max_bandwidth: Option<u64>) -> Self {
+        self.0.max_bandwidth = max_bandwidth.map(|it| it as i64).unwrap_or(-1);
+        self
+    }
+
+    /// Latency in milliseconds
+    pub fn latency(mut self, latency: u32) -> Self {
+        self.0.latency = latency;
+        self
+    }
+
+    /// Connection timeout in milliseconds
+    pub fn timeout(mut self, timeout: u32) -> Self {
+        self.0.timeout = timeout;
+        self
+    }
+
+    /// Forward Error Correction configuration, `None` disables the fec.
+    pub fn fec(mut self, fec: Option<FecOptions>) -> Self {
+        self.0.fec = fec.map(|it| it.to_string()).unwrap_or_default();
+        self
+    }
+
+    /// Flow control window size
+    pub fn fc(mut self, fc: u32) -> Self {
+        self.0.fc = fc;
+        self
+    }
+
+    /// The session token, `None` on the sender accepts any receiver.
+    pub fn session(mut self, session: Option<String>) -> Self {
+        self.0.session = session;
+        self
+    }
+
+    /// The name of the stream for the srt relays, `None` on the sender
+    /// accepts any stream name.
+    pub fn resource(mut self, resource: Option<String>) -> Self {
+        self.0.resource = resource;
+        self
+    }
+
+    /// The passphrase of the srt encryption, `None` does not encrypt the
+    /// stream.
+    pub fn passphrase(mut self, passphrase: Option<String>) -> Self {
+        self.0.passphrase = passphrase;
+        self
+    }
+
+    /// How the sender and the receiver are connected, see
+    /// [TransportStrategy].
+    pub fn strategy(mut self, strategy: TransportStrategy) -> Self {
+        self.0.strategy = strategy;
+        self
+    }
+
+    pub fn build(self) -> Result<TransportOptions, TransportOptionsError> {
+        self.0.validate()?;
+
+        Ok(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_too_small_bandwidth() {
+        let options = |max_bandwidth| TransportOptions {
+            max_bandwidth,
+            ..Default::default()
+        };
+
+        for max_bandwidth in [0, 1, 7, TransportOptions::MIN_BANDWIDTH - 1, -2] {
+            assert_eq!(
+                options(max_bandwidth).validate(),
+                Err(TransportOptionsError::InvalidMaxBandwidth(max_bandwidth))
+            );
+        }
+
+        assert!(options(-1).validate().is_ok());
+        assert!(options(TransportOptions::MIN_BANDWIDTH).validate().is_ok());
+        assert!(
+            options(TransportOptions::MIN_BANDWIDTH)
+                .payload_bandwidth()
+                .unwrap()
+                > 0
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_passphrase() {
+        let options = |passphrase: &str| TransportOptions {
+            passphrase: Some(passphrase.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            options("short").validate(),
+            Err(TransportOptionsError::InvalidPassphrase)
+        );
+        assert_eq!(
+            options(&"a".repeat(80)).validate(),
+            Err(TransportOptionsError::InvalidPassphrase)
+        );
+        assert!(options("0123456789").validate().is_ok());
+    }
+}
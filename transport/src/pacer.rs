@@ -0,0 +1,211 @@
+use std::{
+    io::Result,
+    sync::{
+        Arc,
+        mpsc::{Receiver, SyncSender, TrySendError, sync_channel},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::BytesMut;
+use parking_lot::Mutex;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::SrtSocket;
+
+/// The counters of the pacing of a sender, see `TransportSender::get_pacing_stats`,
+/// the counters are summed over the current connections.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct PacingStats {
+    /// The paced rate in bytes per second, the media after the fec and the
+    /// headers of the packets.
+    pub rate: u64,
+    /// The bytes that went through the pacer.
+    pub bytes: u64,
+    /// The total time the packets were held back to stay under the cap, it
+    /// grows quickly if the encoder produces more than the cap allows.
+    pub waited: Duration,
+    /// The buffers dropped because the queue in front of the pacer was full.
+    pub dropped: u64,
+}
+
+/// A token bucket in front of the socket, so that the bursts of the key frames
+/// are spread out instead of exceeding the cap, the bucket holds only a short
+/// burst.
+struct Pacer {
+    rate: f64,
+    burst: f64,
+    /// The bytes that may be sent right away, it is negative while the sender
+    /// owes the bucket.
+    tokens: f64,
+    updated: Instant,
+    stats: PacingStats,
+}
+
+impl Pacer {
+    /// The bucket holds the bytes of this duration at the rate.
+    const BURST: Duration = Duration::from_millis(20);
+
+    fn new(rate: u64) -> Self {
+        let burst = rate as f64 * Self::BURST.as_secs_f64();
+
+        Self {
+            rate: rate as f64,
+            tokens: burst,
+            updated: Instant::now(),
+            stats: PacingStats {
+                rate,
+                ..Default::default()
+            },
+            burst,
+        }
+    }
+
+    /// Takes the bytes of a packet from the bucket, and returns how long to
+    /// wait until the bucket is no longer in debt.
+    fn take(&mut self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.updated).as_secs_f64() * self.rate)
+            .min(self.burst);
+        self.updated = now;
+
+        self.tokens -= bytes as f64;
+        self.stats.bytes += bytes as u64;
+
+        if self.tokens < 0.0 {
+            let delay = Duration::from_secs_f64(-self.tokens / self.rate);
+            self.stats.waited += delay;
+
+            delay
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Sends the packets of a connection through the pacer on a thread of its
+/// own, so that the waits of the pacer do not hold back the thread that
+/// encodes the media, each connection is paced on its own because the cap
+/// applies to each link.
+///
+/// The queue holds a few frames, so it absorbs the bursts of the key frames.
+/// If the media stays over the cap the queue fills up, and the new buffers
+/// are dropped instead of blocking the sender.
+pub(crate) struct PacedSender {
+    queue: SyncSender<Vec<BytesMut>>,
+    pacer: Arc<Mutex<Pacer>>,
+}
+
+impl PacedSender {
+    /// The number of buffers waiting for the pacer.
+    const QUEUE_SIZE: usize = 64;
+
+    /// The thread runs until the pacer is dropped, the socket is closed if
+    /// sending to it fails, which ends the connection.
+    pub(crate) fn new(rate: u64, socket: Arc<SrtSocket>) -> Result<Self> {
+        let pacer = Arc::new(Mutex::new(Pacer::new(rate)));
+        let (queue, rx) = sync_channel(Self::QUEUE_SIZE);
+
+        let pacer_ = pacer.clone();
+        thread::Builder::new()
+            .name("HylaranaTransportPacerThread".to_string())
+            .spawn(move || Self::run(rx, pacer_, socket))?;
+
+        Ok(Self { queue, pacer })
+    }
+
+    /// Queues the packets of a buffer, returns false if the buffer is dropped.
+    pub(crate) fn send(&self, packets: &[BytesMut]) -> bool {
+        match self.queue.try_send(packets.to_vec()) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                self.pacer.lock().stats.dropped += 1;
+
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    pub(crate) fn stats(&self) -> PacingStats {
+        self.pacer.lock().stats
+    }
+
+    fn run(rx: Receiver<Vec<BytesMut>>, pacer: Arc<Mutex<Pacer>>, socket: Arc<SrtSocket>) {
+        for packets in rx {
+            // The packets are sent one by one, so that a key frame does not burst
+            // over the cap.
+            for packet in packets {
+                let delay = pacer.lock().take(packet.len());
+                if !delay.is_zero() {
+                    thread::sleep(delay);
+                }
+
+                if let Err(e) = socket.send_batch(std::slice::from_ref(&packet)) {
+                    tracing::warn!("transport failed to send data with srt socket, err={:?}", e);
+
+                    socket.close();
+
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_the_burst_right_away() {
+        let mut pacer = Pacer::new(100_000);
+
+        // The bucket holds 20ms at the rate.
+        assert_eq!(pacer.take(1000), Duration::ZERO);
+        assert_eq!(pacer.take(1000), Duration::ZERO);
+        assert_eq!(pacer.stats.bytes, 2000);
+        assert_eq!(pacer.stats.waited, Duration::ZERO);
+    }
+
+    #[test]
+    fn waits_for_the_debt() {
+        let mut pacer = Pacer::new(100_000);
+
+        // 2000 bytes in the bucket, 10000 bytes over it, 100ms at the rate.
+        let delay = pacer.take(12_000);
+        assert!(delay > Duration::from_millis(90), "{:?}", delay);
+        assert!(delay <= Duration::from_millis(100), "{:?}", delay);
+        assert_eq!(pacer.stats.waited, delay);
+
+        // The debt adds up.
+        assert!(pacer.take(10_000) > delay);
+    }
+
+    #[test]
+    fn refills_up_to_the_burst() {
+        let mut pacer = Pacer::new(100_000);
+        pacer.take(2000);
+
+        // The bucket is never filled beyond the burst, however long it waited.
+        pacer.updated -= Duration::from_secs(10);
+        assert_eq!(pacer.take(2000), Duration::ZERO);
+        assert!(pacer.take(1000) > Duration::ZERO);
+    }
+
+    #[test]
+    fn paces_the_smallest_bandwidth() {
+        let options = crate::TransportOptions {
+            max_bandwidth: crate::TransportOptions::MIN_BANDWIDTH,
+            ..Default::default()
+        };
+
+        let mut pacer = Pacer::new(options.payload_bandwidth().unwrap());
+        let delay = pacer.take(1500 * 100);
+        assert!(delay > Duration::ZERO && delay < Duration::from_secs(60));
+    }
+}
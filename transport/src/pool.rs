@@ -0,0 +1,107 @@
+use std::sync::{
+    LazyLock,
+    atomic::{AtomicU64, Ordering},
+};
+
+use bytes::{Bytes, BytesMut};
+use parking_lot::Mutex;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+static GLOBAL: LazyLock<BufferPool> = LazyLock::new(BufferPool::default);
+
+/// The counters of the buffer pool.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct BufferPoolStats {
+    /// The number of the requests served by a pooled buffer.
+    pub hits: u64,
+    /// The number of the requests that allocated a new buffer.
+    pub misses: u64,
+    /// The number of the buffers currently in the pool.
+    pub pooled: usize,
+}
+
+impl BufferPoolStats {
+    /// The share of the requests served by a pooled buffer, between 0.0 and
+    /// 1.0.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// A freelist of `BytesMut`, the packets of the encoders are copied into the
+/// pooled buffers, and the buffers are returned after they are sent, so that
+/// a buffer is not allocated for every packet.
+#[derive(Default)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<BytesMut>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BufferPool {
+    /// The upper limit of the pooled buffers, the buffers beyond this are
+    /// freed, key frames of high resolutions are large, so the pool is kept
+    /// small.
+    const MAX_BUFFERS: usize = 32;
+
+    /// The pool shared by the encoders and the transport.
+    pub fn global() -> &'static Self {
+        &GLOBAL
+    }
+
+    /// Take an empty buffer that can hold at least the size without
+    /// allocation.
+    pub fn get(&self, size: usize) -> BytesMut {
+        let pooled = {
+            let mut buffers = self.buffers.lock();
+            buffers
+                .iter()
+                .position(|it| it.capacity() >= size)
+                .map(|index| buffers.swap_remove(index))
+        };
+
+        if let Some(buffer) = pooled {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+
+            buffer
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+
+            BytesMut::with_capacity(size)
+        }
+    }
+
+    /// Return a buffer to the pool.
+    pub fn put(&self, mut buffer: BytesMut) {
+        buffer.clear();
+
+        let mut buffers = self.buffers.lock();
+        if buffers.len() < Self::MAX_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+
+    /// Return a frozen buffer to the pool, it is only reclaimed if there are
+    /// no other references to it.
+    pub fn recycle(&self, buffer: Bytes) {
+        if let Ok(buffer) = buffer.try_into_mut() {
+            self.put(buffer);
+        }
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            pooled: self.buffers.lock().len(),
+        }
+    }
+}
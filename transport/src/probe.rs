@@ -0,0 +1,267 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, BufMut, BytesMut};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{
+    TransportOptions,
+    protocol::{SrtServer, SrtSocket},
+};
+
+/// How long the burst of the probe lasts.
+const PROBE_DURATION: Duration = Duration::from_secs(2);
+
+/// How often the round trip time is sampled during the burst.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The largest payload of a single packet of srt in live mode.
+const PACKET_SIZE: usize = 1316;
+
+/// The end marker is sent more than once because it may be dropped like any
+/// other packet in live mode.
+const END_MARKERS: usize = 3;
+
+/// The share of the measured throughput that is recommended for the encoder,
+/// the rest is left for the forward error correction and the fluctuation of
+/// the network.
+const HEADROOM: f64 = 0.7;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PacketKind {
+    Data = 0,
+    End = 1,
+    Report = 2,
+}
+
+impl TryFrom<u8> for PacketKind {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => Self::Data,
+            1 => Self::End,
+            2 => Self::Report,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid probe packet kind: {}", value),
+                ));
+            }
+        })
+    }
+}
+
+/// The result of a bandwidth probe.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct ProbeReport {
+    /// The throughput that reached the peer in bits per second.
+    pub throughput: u64,
+    /// The average round trip time in milliseconds.
+    pub rtt: f64,
+    /// The average variation of the round trip time in milliseconds.
+    pub jitter: f64,
+    /// The share of the packets that did not reach the peer, between 0.0 and
+    /// 1.0.
+    pub loss: f64,
+}
+
+impl ProbeReport {
+    /// The bit rate that is recommended for the video encoder in bits per
+    /// second.
+    pub fn recommended_bit_rate(&self) -> u64 {
+        (self.throughput as f64 * (1.0 - self.loss) * HEADROOM) as u64
+    }
+}
+
+/// The peer of `bandwidth_probe`, it counts the packets of the burst and
+/// replies with what it has received, run it on the device that is going to
+/// be the sender.
+pub struct ProbeServer {
+    server: Arc<SrtServer>,
+    address: SocketAddr,
+}
+
+impl ProbeServer {
+    pub fn bind(bind: SocketAddr) -> Result<Self> {
+        tracing::info!("transport create probe server, bind={}", bind);
+
+        let server = Arc::new(SrtServer::bind(
+            bind,
+            TransportOptions::default().srt_options(),
+            1,
+        )?);
+
+        let address = server
+            .local_addr()
+            .ok_or_else(|| Error::new(ErrorKind::AddrNotAvailable, ""))?;
+
+        let server_ = server.clone();
+        thread::Builder::new()
+            .name("HylaranaTransportProbeThread".to_string())
+            .spawn(move || {
+                while let Ok((socket, addr)) = server_.accept() {
+                    tracing::info!("transport probe server accept a socket, addr={}", addr);
+
+                    if let Err(e) = Self::reply(&socket) {
+                        tracing::warn!("transport probe failed, addr={}, err={:?}", addr, e);
+                    }
+                }
+
+                tracing::info!("transport probe server is closed, addr={}", address);
+            })?;
+
+        Ok(Self { server, address })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    fn reply(socket: &SrtSocket) -> Result<()> {
+        let mut buffer = [0u8; 2048];
+        let mut report: Option<BytesMut> = None;
+        let mut start: Option<Instant> = None;
+        let mut received = 0u64;
+        let mut packets = 0u32;
+
+        // The socket is read until the client closes it, so that the report is
+        // not lost by closing the socket right after sending it.
+        loop {
+            let size = socket.read(&mut buffer)?;
+            if size == 0 {
+                break;
+            }
+
+            match PacketKind::try_from(buffer[0])? {
+                PacketKind::Data => {
+                    start.get_or_insert_with(Instant::now);
+                    received += size as u64;
+                    packets += 1;
+                }
+                PacketKind::End => {
+                    let report = report.get_or_insert_with(|| {
+                        let elapsed = start.map(|it| it.elapsed()).unwrap_or_default();
+
+                        let mut bytes = BytesMut::with_capacity(21);
+                        bytes.put_u8(PacketKind::Report as u8);
+                        bytes.put_u64(received);
+                        bytes.put_u64(elapsed.as_micros() as u64);
+                        bytes.put_u32(packets);
+                        bytes
+                    });
+
+                    socket.send(report)?;
+                }
+                PacketKind::Report => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ProbeServer {
+    fn drop(&mut self) {
+        tracing::info!("transport probe server is drop");
+
+        self.server.close();
+    }
+}
+
+/// Sends a short burst to the `ProbeServer` at the address, and reports the
+/// throughput, the round trip time and the jitter of the network between the
+/// two devices, which can be used to choose the bit rate of the encoder before
+/// the sender is created. This blocks for about two seconds.
+pub fn bandwidth_probe(addr: SocketAddr) -> Result<ProbeReport> {
+    tracing::info!("transport bandwidth probe, addr={}", addr);
+
+    let socket = SrtSocket::connect(addr, TransportOptions::default().srt_options())?;
+
+    let mut payload = [0u8; PACKET_SIZE];
+    payload[0] = PacketKind::Data as u8;
+
+    let mut rtts =
+        Vec::with_capacity((PROBE_DURATION.as_millis() / SAMPLE_INTERVAL.as_millis()) as usize + 1);
+
+    let mut sent = 0u32;
+    let start = Instant::now();
+    let mut next_sample = start;
+    while start.elapsed() < PROBE_DURATION {
+        // The sending is not blocking, an error means that the send buffer is
+        // full, so wait for the buffer to be drained.
+        if socket.send(&payload).is_ok() {
+            sent += 1;
+        } else {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        if Instant::now() >= next_sample {
+            if let Ok(stats) = socket.get_stats() {
+                rtts.push(stats.msRTT);
+            }
+
+            next_sample += SAMPLE_INTERVAL;
+        }
+    }
+
+    for _ in 0..END_MARKERS {
+        socket.send(&[PacketKind::End as u8])?;
+    }
+
+    let mut buffer = [0u8; 2048];
+    let (received, elapsed, packets) = loop {
+        let size = socket.read(&mut buffer)?;
+        if size == 0 {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "the probe server closed the connection",
+            ));
+        }
+
+        let mut bytes = &buffer[..size];
+        if PacketKind::try_from(bytes.get_u8())? == PacketKind::Report && bytes.len() >= 20 {
+            break (bytes.get_u64(), bytes.get_u64(), bytes.get_u32());
+        }
+    };
+
+    let report = ProbeReport {
+        throughput: if elapsed > 0 {
+            (received as f64 * 8.0 / (elapsed as f64 / 1_000_000.0)) as u64
+        } else {
+            0
+        },
+        rtt: if rtts.is_empty() {
+            0.0
+        } else {
+            rtts.iter().sum::<f64>() / rtts.len() as f64
+        },
+        jitter: if rtts.len() > 1 {
+            rtts.windows(2).map(|it| (it[1] - it[0]).abs()).sum::<f64>() / (rtts.len() - 1) as f64
+        } else {
+            0.0
+        },
+        loss: if sent > 0 {
+            (1.0 - packets as f64 / sent as f64).clamp(0.0, 1.0)
+        } else {
+            0.0
+        },
+    };
+
+    tracing::info!(
+        "transport bandwidth probe done, addr={}, report={:?}",
+        addr,
+        report
+    );
+
+    Ok(report)
+}
@@ -11,8 +11,8 @@ use std::{
 };
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use common::strings::PSTR;
-use log::{Level, log};
+use common::{logger, strings::PSTR};
+use log::{Level, LevelFilter, log};
 use os_socketaddr::OsSocketAddr;
 
 pub use self::sys::SRT_TRACEBSTATS;
@@ -88,9 +88,23 @@ unsafe extern "C" fn loghandler(
     }
 }
 
+// SRT drops the logs above its own level before they reach the handler, so
+// the level follows the level of the `srt` target of the logger.
+fn set_log_level(level: LevelFilter) {
+    let level = match level {
+        LevelFilter::Off => SRT_LOG_LEVEL::LOG_CRIT,
+        LevelFilter::Error => SRT_LOG_LEVEL::LOG_ERR,
+        LevelFilter::Warn => SRT_LOG_LEVEL::LOG_WARNING,
+        LevelFilter::Info => SRT_LOG_LEVEL::LOG_INFO,
+        LevelFilter::Debug | LevelFilter::Trace => SRT_LOG_LEVEL::LOG_DEBUG,
+    };
+
+    unsafe { sys::srt_setloglevel(level as c_int) }
+}
+
 // Initialize SRT library and set up logging
 pub fn startup() -> bool {
-    unsafe { sys::srt_setloglevel(SRT_LOG_LEVEL::LOG_INFO as c_int) }
+    logger::watch_target_level("srt", set_log_level);
     unsafe { sys::srt_setloghandler(null_mut(), Some(loghandler)) }
     unsafe { sys::srt_startup() != -1 }
 }
@@ -102,15 +116,28 @@ pub fn cleanup() {
     }
 }
 
+// Get the version of the SRT library as major.minor.patch
+pub fn version() -> String {
+    let version = unsafe { sys::srt_getversion() };
+    format!(
+        "{}.{}.{}",
+        (version >> 16) & 0xff,
+        (version >> 8) & 0xff,
+        version & 0xff
+    )
+}
+
 // Configuration options for SRT connections
 #[derive(Debug, Clone)]
 pub struct SrtOptions {
-    pub max_bandwidth: i64, // Maximum bandwidth in bytes per second
-    pub latency: u32,       // Latency in milliseconds
-    pub timeout: u32,       // Connection timeout in milliseconds
-    pub fec: String,        // Forward Error Correction configuration
-    pub mtu: u32,           // Maximum Transmission Unit size
-    pub fc: u32,            // Flow control window size
+    pub max_bandwidth: i64,         // Maximum bandwidth in bytes per second
+    pub latency: u32,               // Latency in milliseconds
+    pub timeout: u32,               // Connection timeout in milliseconds
+    pub fec: String,                // Forward Error Correction configuration
+    pub mtu: u32,                   // Maximum Transmission Unit size
+    pub fc: u32,                    // Flow control window size
+    pub stream_id: Option<String>,  // Stream id sent by the caller
+    pub passphrase: Option<String>, // Passphrase of the encryption
 }
 
 impl SrtOptions {
@@ -150,8 +177,16 @@ impl SrtOptions {
         // Set peer idle timeout
         set_sock_opt(fd, sys::SRT_SOCKOPT::SRTO_PEERIDLETIMEO, &self.timeout)?;
 
-        // Set packet filter (FEC configuration)
-        set_sock_opt_str(fd, sys::SRT_SOCKOPT::SRTO_PACKETFILTER, &self.fec)?;
+        // Set packet filter (FEC configuration), an empty string disables it
+        if !self.fec.is_empty() {
+            set_sock_opt_str(fd, sys::SRT_SOCKOPT::SRTO_PACKETFILTER, &self.fec)?;
+        }
+
+        // Enable the encryption, the accepted sockets inherit it from the listener
+        // and srt rejects the peers with another passphrase
+        if let Some(passphrase) = &self.passphrase {
+            set_sock_opt_str(fd, sys::SRT_SOCKOPT::SRTO_PASSPHRASE, passphrase)?;
+        }
 
         Ok(())
     }
@@ -166,6 +201,8 @@ impl Default for SrtOptions {
             latency: 60,
             mtu: 1500,
             fc: 25600,
+            stream_id: None,
+            passphrase: None,
         }
     }
 }
@@ -216,7 +253,7 @@ pub struct FragmentEncoder {
 }
 
 impl FragmentEncoder {
-    const HEAD_SIZE: usize = 8; // Size of packet header (sequence + size)
+    pub(crate) const HEAD_SIZE: usize = 8; // Size of packet header (sequence + size)
 
     // Create a new fragment encoder with specified MTU
     pub fn new(mtu: usize) -> Self {
@@ -322,16 +359,60 @@ impl SrtSocket {
         Self { fd }
     }
 
-    // Get connection statistics including bandwidth, latency, and packet loss
+    // Get connection statistics including bandwidth, latency, and packet loss,
+    // the interval counters are reset
     pub fn get_stats(&self) -> Result<sys::SRT_TRACEBSTATS, Error> {
+        self.bstats(true)
+    }
+
+    // Get connection statistics without resetting the interval counters, for the
+    // readers of the total counters
+    pub fn get_total_stats(&self) -> Result<sys::SRT_TRACEBSTATS, Error> {
+        self.bstats(false)
+    }
+
+    fn bstats(&self, clear: bool) -> Result<sys::SRT_TRACEBSTATS, Error> {
         let mut stats = MaybeUninit::<sys::SRT_TRACEBSTATS>::uninit();
-        if unsafe { sys::srt_bstats(self.fd, stats.as_mut_ptr(), true as i32) } != 0 {
+        if unsafe { sys::srt_bstats(self.fd, stats.as_mut_ptr(), clear as i32) } != 0 {
             return Err(current_error());
         }
 
         Ok(unsafe { stats.assume_init() })
     }
 
+    // Get the address of the peer, `None` if the socket is not connected
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        let mut addr = OsSocketAddr::new();
+        let mut addrlen = addr.capacity() as c_int;
+        if unsafe { sys::srt_getpeername(self.fd, addr.as_mut_ptr() as *mut _, &mut addrlen) } != 0
+        {
+            return None;
+        }
+
+        addr.into()
+    }
+
+    // Get the stream id sent by the caller, only for the accepted sockets
+    pub fn stream_id(&self) -> Option<String> {
+        let mut buffer = [0u8; 512];
+        let mut size = buffer.len() as c_int;
+        if unsafe {
+            sys::srt_getsockflag(
+                self.fd,
+                sys::SRT_SOCKOPT::SRTO_STREAMID,
+                buffer.as_mut_ptr() as *mut _,
+                &mut size,
+            )
+        } != 0
+        {
+            return None;
+        }
+
+        String::from_utf8(buffer[..size as usize].to_vec())
+            .ok()
+            .filter(|it| !it.is_empty())
+    }
+
     // Establishes a new SRT connection in live mode
     pub fn connect(addr: SocketAddr, opt: SrtOptions) -> Result<Self, Error> {
         let fd = unsafe { sys::srt_create_socket() };
@@ -341,6 +422,12 @@ impl SrtSocket {
             opt.apply_socket(fd)?;
         }
 
+        // Only the caller sends the stream id, the listener reads it from the
+        // accepted socket.
+        if let Some(stream_id) = &opt.stream_id {
+            set_sock_opt_str(fd, sys::SRT_SOCKOPT::SRTO_STREAMID, stream_id)?;
+        }
+
         let addr: OsSocketAddr = addr.into();
         if unsafe { sys::srt_connect(fd, addr.as_ptr() as *const _, addr.len() as c_int) } == -1 {
             return Err(current_error());
@@ -382,6 +469,33 @@ impl SrtSocket {
         Ok(())
     }
 
+    // Send all fragments of a message in one go, the fragments share the same
+    // source time, so that the receiver delivers them together instead of
+    // spreading them over the latency window
+    pub fn send_batch(&self, buffers: &[BytesMut]) -> Result<(), Error> {
+        let mut ctrl = MaybeUninit::<sys::SRT_MSGCTRL>::uninit();
+        unsafe { sys::srt_msgctrl_init(ctrl.as_mut_ptr()) };
+
+        let mut ctrl = unsafe { ctrl.assume_init() };
+        ctrl.srctime = unsafe { sys::srt_time_now() };
+
+        for buffer in buffers.iter().filter(|it| !it.is_empty()) {
+            if unsafe {
+                sys::srt_sendmsg2(
+                    self.fd,
+                    buffer.as_ptr() as *const _,
+                    buffer.len() as c_int,
+                    &mut ctrl,
+                )
+            } != buffer.len() as i32
+            {
+                return Err(current_error());
+            }
+        }
+
+        Ok(())
+    }
+
     // Close the socket and release resources
     pub fn close(&self) {
         unsafe { sys::srt_close(self.fd) };
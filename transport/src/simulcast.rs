@@ -0,0 +1,232 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::{BufferType, MAX_RENDITIONS, StreamType, viewers::Viewers};
+
+/// The rendition of the simulcast video that a receiver plays, 0 is the main
+/// video and the higher ids are the lower renditions, see
+/// [crate::TransportReceiver::rendition]. It is shared between the receiver
+/// and its users, the receiver keeps playing the previous rendition until the
+/// next key frame of the new one.
+#[derive(Clone, Default)]
+pub struct RenditionSubscription(Arc<(Mutex<u8>, Condvar)>);
+
+impl RenditionSubscription {
+    pub fn get(&self) -> u8 {
+        *self.0.0.lock()
+    }
+
+    /// The ids beyond the renditions of the transport are clamped, the
+    /// sender is told right away.
+    pub fn set(&self, rendition: u8) {
+        let rendition = rendition.min(MAX_RENDITIONS - 1);
+
+        let mut current = self.0.0.lock();
+        if *current != rendition {
+            *current = rendition;
+
+            self.0.1.notify_all();
+        }
+    }
+
+    /// Waits until the rendition is changed from the given one or the time is
+    /// out, returns the current rendition.
+    pub(crate) fn wait(&self, rendition: u8, timeout: Duration) -> u8 {
+        let mut current = self.0.0.lock();
+        if *current == rendition {
+            self.0.1.wait_for(&mut current, timeout);
+        }
+
+        *current
+    }
+}
+
+/// The renditions that the receivers of a connection subscribe to, they tell
+/// them with their heartbeats. Each connection of the sender has its own
+/// subscriptions, so the receivers on different networks play different
+/// renditions at the same time.
+pub(crate) struct Subscriptions {
+    receivers: Mutex<HashMap<u64, (u8, Instant)>>,
+    /// The bits of the renditions.
+    requested: AtomicU32,
+    active: AtomicU32,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self {
+            receivers: Default::default(),
+            requested: AtomicU32::new(0),
+            // The main video is played until the subscribed rendition starts.
+            active: AtomicU32::new(1),
+        }
+    }
+}
+
+impl Subscriptions {
+    pub(crate) fn heartbeat(&self, id: u64, rendition: u8) {
+        let mut receivers = self.receivers.lock();
+        receivers.insert(id, (rendition, Instant::now()));
+        receivers.retain(|_, (_, seen)| seen.elapsed() < Viewers::TIMEOUT);
+
+        let requested = receivers
+            .values()
+            .fold(0, |bits, (rendition, _)| bits | 1 << rendition);
+
+        if self.requested.swap(requested, Ordering::Relaxed) != requested {
+            tracing::info!(
+                "transport sender renditions changed, renditions={:#b}",
+                requested
+            );
+        }
+    }
+
+    /// Whether the packet is sent on the connection, only the media of the
+    /// video is filtered. A newly subscribed rendition is sent from its next
+    /// key frame, and the renditions that are no longer subscribed are sent
+    /// until then, so that the receivers switch without a gap.
+    pub(crate) fn is_sent(&self, stream: StreamType, ty: BufferType, rendition: u8) -> bool {
+        if stream != StreamType::Video
+            || !matches!(
+                ty,
+                BufferType::Partial | BufferType::KeyFrame | BufferType::Config
+            )
+        {
+            return true;
+        }
+
+        // Nothing is known before the first heartbeat, everything is sent.
+        let requested = self.requested.load(Ordering::Relaxed);
+        if requested == 0 {
+            return true;
+        }
+
+        let bit = 1 << rendition;
+        let mut active = self.active.load(Ordering::Relaxed);
+        if requested & bit != 0 && ty != BufferType::Partial {
+            active |= bit;
+        }
+
+        if active & requested == requested {
+            active = requested;
+        }
+
+        self.active.store(active, Ordering::Relaxed);
+
+        active & bit != 0
+    }
+
+    /// Whether the rendition is subscribed or still sent until the subscribed
+    /// one starts, everything is wanted before the first heartbeat.
+    pub(crate) fn is_wanted(&self, rendition: u8) -> bool {
+        let requested = self.requested.load(Ordering::Relaxed);
+
+        requested == 0 || (requested | self.active.load(Ordering::Relaxed)) & 1 << rendition != 0
+    }
+}
+
+/// Whether any connection wants the rendition, a rendition is encoded while
+/// any receiver plays it, see [Subscriptions::is_wanted].
+pub(crate) fn is_wanted_by_any<'a>(
+    subscriptions: impl IntoIterator<Item = &'a Subscriptions>,
+    rendition: u8,
+) -> bool {
+    subscriptions.into_iter().any(|it| it.is_wanted(rendition))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_everything_before_the_first_heartbeat() {
+        let subscriptions = Subscriptions::default();
+
+        for rendition in 0..MAX_RENDITIONS {
+            assert!(subscriptions.is_wanted(rendition));
+            assert!(subscriptions.is_sent(StreamType::Video, BufferType::Partial, rendition));
+        }
+    }
+
+    #[test]
+    fn switches_to_the_subscribed_rendition_at_its_key_frame() {
+        let subscriptions = Subscriptions::default();
+        subscriptions.heartbeat(1, 2);
+
+        // The main video is sent until the key frame of the subscribed rendition.
+        assert!(subscriptions.is_sent(StreamType::Video, BufferType::Partial, 0));
+        assert!(!subscriptions.is_sent(StreamType::Video, BufferType::Partial, 2));
+        assert!(!subscriptions.is_sent(StreamType::Video, BufferType::Partial, 1));
+        assert!(subscriptions.is_wanted(0));
+        assert!(!subscriptions.is_wanted(1));
+
+        assert!(subscriptions.is_sent(StreamType::Video, BufferType::KeyFrame, 2));
+        assert!(subscriptions.is_sent(StreamType::Video, BufferType::Partial, 2));
+        assert!(!subscriptions.is_sent(StreamType::Video, BufferType::Partial, 0));
+        assert!(!subscriptions.is_wanted(0));
+        assert!(subscriptions.is_wanted(2));
+    }
+
+    #[test]
+    fn switches_each_connection_on_its_own() {
+        let (first, second) = (Subscriptions::default(), Subscriptions::default());
+        first.heartbeat(1, 2);
+        second.heartbeat(2, 1);
+
+        assert!(first.is_sent(StreamType::Video, BufferType::KeyFrame, 2));
+        assert!(second.is_sent(StreamType::Video, BufferType::KeyFrame, 1));
+
+        assert!(first.is_sent(StreamType::Video, BufferType::Partial, 2));
+        assert!(!first.is_sent(StreamType::Video, BufferType::Partial, 1));
+        assert!(second.is_sent(StreamType::Video, BufferType::Partial, 1));
+        assert!(!second.is_sent(StreamType::Video, BufferType::Partial, 2));
+    }
+
+    #[test]
+    fn wants_the_union_of_the_connections() {
+        let (first, second) = (Subscriptions::default(), Subscriptions::default());
+        first.heartbeat(1, 2);
+        second.heartbeat(2, 1);
+
+        first.is_sent(StreamType::Video, BufferType::KeyFrame, 2);
+        second.is_sent(StreamType::Video, BufferType::KeyFrame, 1);
+
+        assert!(is_wanted_by_any([&first, &second], 1));
+        assert!(is_wanted_by_any([&first, &second], 2));
+        assert!(!is_wanted_by_any([&first, &second], 0));
+        assert!(!is_wanted_by_any([], 1));
+
+        // A new connection wants everything until its receivers subscribe.
+        let third = Subscriptions::default();
+        assert!(is_wanted_by_any([&first, &second, &third], 0));
+    }
+
+    #[test]
+    fn does_not_filter_the_audio_and_the_notices() {
+        let subscriptions = Subscriptions::default();
+        subscriptions.heartbeat(1, 1);
+
+        assert!(subscriptions.is_sent(StreamType::Audio, BufferType::Partial, 0));
+        assert!(subscriptions.is_sent(StreamType::Video, BufferType::TrackDisabled, 0));
+    }
+
+    #[test]
+    fn clamps_the_subscription() {
+        let subscription = RenditionSubscription::default();
+        subscription.set(u8::MAX);
+
+        assert_eq!(subscription.get(), MAX_RENDITIONS - 1);
+        assert_eq!(
+            subscription.wait(0, Duration::from_millis(1)),
+            MAX_RENDITIONS - 1
+        );
+    }
+}
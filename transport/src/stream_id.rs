@@ -0,0 +1,126 @@
+use std::fmt;
+
+/// The role of the caller, the srt relays use it to tell the publishers from
+/// the subscribers of a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamMode {
+    /// The caller receives the stream, the key is `m=request`.
+    Request,
+    /// The caller sends the stream to a relay, the key is `m=publish`, see
+    /// `TransportSender::publish`.
+    Publish,
+}
+
+/// The stream id of the srt connection, it is formatted in the access control
+/// syntax of srt, `#!::key=value,...`, so that the standard srt tools and
+/// relays, such as srt-live-transmit and SLS, can route the stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamId {
+    /// The name of the stream, the key is `r`.
+    pub resource: Option<String>,
+    /// The role of the caller, the key is `m`.
+    pub mode: Option<StreamMode>,
+    /// The session token of the sender, the key is `s`.
+    pub session: Option<String>,
+    /// The packet format of the caller, see [crate::PROTOCOL_VERSION], the
+    /// key is `v`.
+    pub version: Option<u8>,
+}
+
+impl StreamId {
+    const PREFIX: &str = "#!::";
+}
+
+/// The stream ids that are not in the access control syntax are the names of
+/// the streams, which is how srt treats them as well.
+impl From<&str> for StreamId {
+    fn from(value: &str) -> Self {
+        let mut id = Self::default();
+
+        let Some(items) = value.strip_prefix(Self::PREFIX) else {
+            if !value.is_empty() {
+                id.resource = Some(value.to_string());
+            }
+
+            return id;
+        };
+
+        for (key, value) in items.split(',').filter_map(|it| it.split_once('=')) {
+            match key {
+                "r" => id.resource = Some(value.to_string()),
+                "s" => id.session = Some(value.to_string()),
+                "v" => id.version = value.parse().ok(),
+                "m" => {
+                    id.mode = match value {
+                        "request" => Some(StreamMode::Request),
+                        "publish" => Some(StreamMode::Publish),
+                        _ => None,
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        id
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut items = Vec::with_capacity(4);
+
+        if let Some(resource) = &self.resource {
+            items.push(format!("r={}", resource));
+        }
+
+        if let Some(mode) = self.mode {
+            items.push(format!(
+                "m={}",
+                match mode {
+                    StreamMode::Request => "request",
+                    StreamMode::Publish => "publish",
+                }
+            ));
+        }
+
+        if let Some(session) = &self.session {
+            items.push(format!("s={}", session));
+        }
+
+        if let Some(version) = self.version {
+            items.push(format!("v={}", version));
+        }
+
+        write!(f, "{}{}", Self::PREFIX, items.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_the_publisher_for_the_relays() {
+        let id = StreamId {
+            resource: Some("live/screen".to_string()),
+            mode: Some(StreamMode::Publish),
+            session: Some("token".to_string()),
+            version: Some(2),
+        };
+
+        assert_eq!(id.to_string(), "#!::r=live/screen,m=publish,s=token,v=2");
+        assert_eq!(StreamId::from(id.to_string().as_str()), id);
+    }
+
+    #[test]
+    fn parses_the_plain_stream_name() {
+        let id = StreamId::from("live/screen");
+
+        assert_eq!(id.resource.as_deref(), Some("live/screen"));
+        assert_eq!(id.mode, None);
+        assert_eq!(
+            StreamId::from("#!::m=request").mode,
+            Some(StreamMode::Request)
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    collections::{HashMap, hash_map::RandomState},
+    hash::{BuildHasher, Hasher},
+    io,
+    sync::{Arc, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use parking_lot::Mutex;
+
+use crate::{Buffer, BufferType, StreamType};
+
+pub(crate) type ViewersCallback = Box<dyn Fn(u32) + Send + Sync>;
+
+/// A random id of a receiver, it tells the receivers of a sender apart, such
+/// as a receiver that replaced another one on the connection.
+pub(crate) fn viewer_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(common::clock::now());
+    hasher.finish()
+}
+
+/// The heartbeat of a receiver, it carries the id of the receiver, and the
+/// rendition of the video that the receiver subscribes to in the header.
+pub(crate) fn heartbeat(id: u64, rendition: u8) -> Bytes {
+    let mut data = Buffer::<BytesMut>::with_capacity(0);
+    data.put_u64(id);
+
+    Buffer {
+        stream: StreamType::Video,
+        ty: BufferType::Heartbeat,
+        timestamp: common::clock::now(),
+        rendition,
+        data,
+    }
+    .encode(0)
+}
+
+/// The receivers of a sender, a receiver is counted from its first heartbeat
+/// until its connection is closed or its heartbeats stop, the receivers behind
+/// a relay share its connection and are counted each. The loopback endpoint
+/// counts all receivers of the process.
+#[derive(Default)]
+pub(crate) struct Viewers {
+    /// The time of the last heartbeat, `None` for the receivers in this
+    /// process, which do not send heartbeats.
+    seen: Mutex<HashMap<u64, Option<Instant>>>,
+    callback: Mutex<Option<ViewersCallback>>,
+}
+
+impl Viewers {
+    /// The receivers send a heartbeat at least every 2 seconds.
+    pub(crate) const TIMEOUT: Duration = Duration::from_secs(6);
+
+    pub(crate) fn set_callback(&self, callback: ViewersCallback) {
+        self.callback.lock().replace(callback);
+    }
+
+    pub(crate) fn count(&self) -> u32 {
+        self.seen.lock().len() as u32
+    }
+
+    /// Takes the heartbeat of a receiver, returns the id of the receiver.
+    pub(crate) fn heartbeat(&self, buffer: &Buffer<Bytes>) -> Option<u64> {
+        let mut data = buffer.data.clone();
+        if data.len() < 8 {
+            return None;
+        }
+
+        let id = data.get_u64();
+        self.update(|seen| seen.insert(id, Some(Instant::now())).is_none());
+
+        Some(id)
+    }
+
+    /// Counts a receiver until it leaves, without the heartbeats.
+    pub(crate) fn join(&self, id: u64) {
+        self.update(|seen| seen.insert(id, None).is_none());
+    }
+
+    pub(crate) fn leave(&self, ids: impl IntoIterator<Item = u64>) {
+        self.update(|seen| {
+            ids.into_iter()
+                .fold(false, |changed, id| seen.remove(&id).is_some() || changed)
+        });
+    }
+
+    /// Expires the receivers on a thread of its own until the viewers are
+    /// dropped, so that they also expire while nothing is sent.
+    pub(crate) fn start_expiry(this: &Arc<Self>) -> io::Result<()> {
+        let viewers = Arc::downgrade(this);
+
+        thread::Builder::new()
+            .name("HylaranaTransportViewersThread".to_string())
+            .spawn(move || Self::run_expiry(viewers))?;
+
+        Ok(())
+    }
+
+    fn run_expiry(viewers: Weak<Self>) {
+        loop {
+            thread::sleep(Self::TIMEOUT / 3);
+
+            match viewers.upgrade() {
+                Some(it) => it.expire(),
+                None => break,
+            }
+        }
+    }
+
+    /// Removes the receivers whose heartbeats stopped while their connection
+    /// is still open.
+    fn expire(&self) {
+        self.update(|seen| {
+            let count = seen.len();
+            seen.retain(|_, it| it.map(|it| it.elapsed() < Self::TIMEOUT).unwrap_or(true));
+            seen.len() != count
+        });
+    }
+
+    /// The callback is called with the new count if the receivers changed, the
+    /// lock is held so that the counts are passed in order.
+    fn update<F>(&self, func: F)
+    where
+        F: FnOnce(&mut HashMap<u64, Option<Instant>>) -> bool,
+    {
+        let mut seen = self.seen.lock();
+        if !func(&mut seen) {
+            return;
+        }
+
+        tracing::info!("transport sender viewers changed, count={}", seen.len());
+
+        if let Some(callback) = self.callback.lock().as_ref() {
+            callback(seen.len() as u32);
+        }
+    }
+}